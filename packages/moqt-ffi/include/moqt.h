@@ -0,0 +1,239 @@
+#ifndef MOQT_H
+#define MOQT_H
+
+/* Generated by cbindgen from packages/moqt-ffi, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Result of a call.
+ */
+typedef enum MoqtStatus {
+  MOQT_STATUS_OK = 0,
+  /**
+   * Nothing to return, e.g. no control bytes to send.
+   */
+  MOQT_STATUS_EMPTY = 1,
+  /**
+   * A pointer was null or a string was not UTF-8.
+   */
+  MOQT_STATUS_INVALID_ARGUMENT = 2,
+  /**
+   * The peer violated the protocol; the session should be closed.
+   */
+  MOQT_STATUS_PROTOCOL_VIOLATION = 3,
+  /**
+   * Any other failure, see [`moqt_last_error`].
+   */
+  MOQT_STATUS_ERROR = 4,
+} MoqtStatus;
+
+/**
+ * Publisher of a track, created with [`moqt_publisher_new`].
+ */
+typedef struct MoqtPublisher MoqtPublisher;
+
+/**
+ * A client session, created with [`moqt_session_new`].
+ */
+typedef struct MoqtSession MoqtSession;
+
+/**
+ * An object received or published. `payload` is only valid during the
+ * callback it is passed to.
+ */
+typedef struct MoqtObject {
+  uint64_t track_alias;
+  uint64_t group_id;
+  uint64_t subgroup_id;
+  uint64_t object_id;
+  uint8_t publisher_priority;
+  /**
+   * Object Status, 0 for a normal object.
+   */
+  uint64_t status;
+  const uint8_t *payload;
+  size_t payload_len;
+} MoqtObject;
+
+/**
+ * Called with each object of a subscription.
+ */
+typedef void (*MoqtObjectCallback)(void *user_data, const struct MoqtObject *object);
+
+/**
+ * Called with each object published, and the bytes to write for it on
+ * the subgroup stream of its group and subgroup. The first object of a
+ * subgroup comes with the SUBGROUP_HEADER opening a new stream.
+ */
+typedef void (*MoqtStreamCallback)(void *user_data,
+                                   const struct MoqtObject *object,
+                                   const uint8_t *data,
+                                   size_t len);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Message of the last failed call on this thread, valid until the next
+ * call failing on this thread. Null if no call failed yet.
+ */
+const char *moqt_last_error(void);
+
+/**
+ * Number of handles not yet freed, to check an application for leaks.
+ */
+size_t moqt_live_handles(void);
+
+/**
+ * Create a client session. Its CLIENT_SETUP is the first data returned by
+ * [`moqt_session_poll_control`]. Returns null on failure.
+ */
+struct MoqtSession *moqt_session_new(void);
+
+/**
+ * Free a session and its subscriptions.
+ *
+ * # Safety
+ *
+ * `session` must be null or returned by [`moqt_session_new`] and not freed
+ * yet.
+ */
+void moqt_session_free(struct MoqtSession *session);
+
+/**
+ * Copy up to `capacity` bytes to write to the control stream into `buf`,
+ * setting `len` to their number. Returns `MOQT_STATUS_EMPTY` if there are
+ * none.
+ *
+ * # Safety
+ *
+ * `session` must be a live session, `buf` must point to `capacity`
+ * writable bytes and `len` to a writable `size_t`.
+ */
+enum MoqtStatus moqt_session_poll_control(struct MoqtSession *session,
+                                          uint8_t *buf,
+                                          size_t capacity,
+                                          size_t *len);
+
+/**
+ * Process `len` bytes read from the control stream. Messages may be split
+ * across calls.
+ *
+ * # Safety
+ *
+ * `session` must be a live session and `data` must point to `len`
+ * readable bytes.
+ */
+enum MoqtStatus moqt_session_handle_control(struct MoqtSession *session,
+                                            const uint8_t *data,
+                                            size_t len);
+
+/**
+ * Process a whole subgroup stream received from the peer, passing its
+ * objects to the callbacks of the subscriptions.
+ *
+ * # Safety
+ *
+ * `session` must be a live session and `data` must point to `len`
+ * readable bytes.
+ */
+enum MoqtStatus moqt_session_handle_stream(struct MoqtSession *session,
+                                           const uint8_t *data,
+                                           size_t len);
+
+/**
+ * Subscribe to `track_name` in `track_namespace`, passing its objects to
+ * `callback` with `user_data`. Sets `request_id` to the Request ID of the
+ * SUBSCRIBE.
+ *
+ * # Safety
+ *
+ * `session` must be a live session, `track_name` a NUL-terminated string
+ * and `request_id` must point to a writable `uint64_t`. `user_data` is
+ * passed to `callback` as is.
+ */
+enum MoqtStatus moqt_subscribe(struct MoqtSession *session,
+                               uint64_t track_namespace,
+                               const char *track_name,
+                               MoqtObjectCallback callback,
+                               void *user_data,
+                               uint64_t *request_id);
+
+/**
+ * End the subscription `request_id` with UNSUBSCRIBE. Its callback is not
+ * called anymore, even from within a callback for objects already
+ * received.
+ *
+ * # Safety
+ *
+ * `session` must be a live session.
+ */
+enum MoqtStatus moqt_unsubscribe(struct MoqtSession *session, uint64_t request_id);
+
+/**
+ * Announce the namespace of the `fields_len` strings `fields`. Sets
+ * `request_id` to the Request ID of the ANNOUNCE.
+ *
+ * # Safety
+ *
+ * `session` must be a live session, `fields` must point to `fields_len`
+ * NUL-terminated strings and `request_id` to a writable `uint64_t`.
+ */
+enum MoqtStatus moqt_announce(struct MoqtSession *session,
+                              const char *const *fields,
+                              size_t fields_len,
+                              uint64_t *request_id);
+
+/**
+ * Response to the ANNOUNCE `request_id`: `MOQT_STATUS_OK` once accepted,
+ * `MOQT_STATUS_EMPTY` while there is none, and `MOQT_STATUS_ERROR` once
+ * rejected or cancelled, with the reason in [`moqt_last_error`]. The
+ * namespace may then be announced again.
+ *
+ * # Safety
+ *
+ * `session` must be a live session.
+ */
+enum MoqtStatus moqt_announce_status(struct MoqtSession *session, uint64_t request_id);
+
+/**
+ * Create the publisher of the track aliased `track_alias`.
+ */
+struct MoqtPublisher *moqt_publisher_new(uint64_t track_alias);
+
+/**
+ * # Safety
+ *
+ * `publisher` must be null or returned by [`moqt_publisher_new`] and not
+ * freed yet.
+ */
+void moqt_publisher_free(struct MoqtPublisher *publisher);
+
+/**
+ * Publish a frame: a keyframe starts a new group, frames before the first
+ * keyframe are dropped. Calls `callback` with each resulting object and
+ * the bytes to send for it.
+ *
+ * # Safety
+ *
+ * `publisher` must be a live publisher and `data` must point to `len`
+ * readable bytes. `user_data` is passed to `callback` as is.
+ */
+enum MoqtStatus moqt_publisher_push_frame(struct MoqtPublisher *publisher,
+                                          bool keyframe,
+                                          const uint8_t *data,
+                                          size_t len,
+                                          MoqtStreamCallback callback,
+                                          void *user_data);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* MOQT_H */
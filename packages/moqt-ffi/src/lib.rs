@@ -0,0 +1,708 @@
+//! C ABI of the MoQT client, for native applications embedding it.
+//!
+//! The application owns the connection: it writes the bytes returned by
+//! [`moqt_session_poll_control`] to the control stream, passes the bytes it
+//! reads from the control stream to [`moqt_session_handle_control`] and the
+//! subgroup streams it receives to [`moqt_session_handle_stream`]. Objects
+//! of a subscription are then passed to the callback given to
+//! [`moqt_subscribe`]. A [`MoqtPublisher`] turns frames into the bytes of
+//! the subgroup streams to send.
+//!
+//! Callbacks run on the calling thread before the call passing them data
+//! returns. They may call back into the session, e.g. to unsubscribe, but
+//! must not free it.
+//!
+//! Every handle returned must be released with its `_free` function. The
+//! header `include/moqt.h` is generated with cbindgen, see `cbindgen.toml`.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::ptr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bytes::{Bytes, BytesMut};
+use tokio::sync::mpsc;
+use tokio_util::codec::{Decoder, Encoder};
+
+use moqt_transport::{
+    blocking::{BlockingSession, BlockingSubscription},
+    codec::{ControlMessageCodec, DRAFT_12, WireVersion},
+    data::{SubgroupHeader, SubgroupId},
+    error::Error,
+    error::RequestErrorCode,
+    message::{ClientSetup, ControlMessage, SubscribeError},
+    request::{AnnounceRequest, SubscribeRequest},
+    session::Session,
+    subgroup::SubgroupReader,
+    track::{Object, TrackPublisher},
+    transport::AppTransport,
+};
+
+/// Result of a call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoqtStatus {
+    Ok = 0,
+    /// Nothing to return, e.g. no control bytes to send.
+    Empty = 1,
+    /// A pointer was null or a string was not UTF-8.
+    InvalidArgument = 2,
+    /// The peer violated the protocol; the session should be closed.
+    ProtocolViolation = 3,
+    /// Any other failure, see [`moqt_last_error`].
+    Error = 4,
+}
+
+/// An object received or published. `payload` is only valid during the
+/// callback it is passed to.
+#[repr(C)]
+pub struct MoqtObject {
+    pub track_alias: u64,
+    pub group_id: u64,
+    pub subgroup_id: u64,
+    pub object_id: u64,
+    pub publisher_priority: u8,
+    /// Object Status, 0 for a normal object.
+    pub status: u64,
+    pub payload: *const u8,
+    pub payload_len: usize,
+}
+
+impl MoqtObject {
+    fn new(object: &Object) -> Self {
+        let metadata = &object.metadata;
+        Self {
+            track_alias: metadata.track_alias,
+            group_id: metadata.group_id,
+            subgroup_id: metadata.subgroup_id,
+            object_id: metadata.object_id,
+            publisher_priority: metadata.publisher_priority,
+            status: object.status.code(),
+            payload: object.payload.as_ptr(),
+            payload_len: object.payload.len(),
+        }
+    }
+}
+
+/// Called with each object of a subscription.
+pub type MoqtObjectCallback =
+    Option<unsafe extern "C" fn(user_data: *mut c_void, object: *const MoqtObject)>;
+
+/// Called with each object published, and the bytes to write for it on
+/// the subgroup stream of its group and subgroup. The first object of a
+/// subgroup comes with the SUBGROUP_HEADER opening a new stream.
+pub type MoqtStreamCallback = Option<
+    unsafe extern "C" fn(
+        user_data: *mut c_void,
+        object: *const MoqtObject,
+        data: *const u8,
+        len: usize,
+    ),
+>;
+
+static LIVE_HANDLES: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn into_handle<T>(value: T) -> *mut T {
+    LIVE_HANDLES.fetch_add(1, Ordering::Relaxed);
+    Box::into_raw(Box::new(value))
+}
+
+/// # Safety
+///
+/// `handle` must be null or returned by [`into_handle`] and not freed yet.
+unsafe fn free_handle<T>(handle: *mut T) {
+    if !handle.is_null() {
+        LIVE_HANDLES.fetch_sub(1, Ordering::Relaxed);
+        drop(unsafe { Box::from_raw(handle) });
+    }
+}
+
+fn fail(e: Error) -> MoqtStatus {
+    let status = match e {
+        Error::ProtocolViolation { .. }
+        | Error::Codec(_)
+        | Error::VarIntRange
+        | Error::UnknownMessageType => MoqtStatus::ProtocolViolation,
+        _ => MoqtStatus::Error,
+    };
+    set_last_error(e.to_string());
+    status
+}
+
+fn invalid(reason: &str) -> MoqtStatus {
+    set_last_error(reason.to_string());
+    MoqtStatus::InvalidArgument
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// # Safety
+///
+/// `s` must be null or a NUL-terminated string.
+unsafe fn str_arg<'a>(s: *const c_char) -> Result<&'a str, MoqtStatus> {
+    if s.is_null() {
+        return Err(invalid("null string"));
+    }
+    unsafe { CStr::from_ptr(s) }
+        .to_str()
+        .map_err(|_| invalid("string is not UTF-8"))
+}
+
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, or be anything if `len` is 0.
+unsafe fn bytes_arg<'a>(data: *const u8, len: usize) -> Result<&'a [u8], MoqtStatus> {
+    match (data.is_null(), len) {
+        (_, 0) => Ok(&[]),
+        (true, _) => Err(invalid("null buffer")),
+        (false, _) => Ok(unsafe { std::slice::from_raw_parts(data, len) }),
+    }
+}
+
+macro_rules! arg {
+    ($e:expr) => {
+        match $e {
+            Ok(value) => value,
+            Err(status) => return status,
+        }
+    };
+}
+
+struct Subscription {
+    subscription: BlockingSubscription,
+    callback: MoqtObjectCallback,
+    user_data: *mut c_void,
+}
+
+/// An object taken from the queue of a subscription, whose callback has
+/// not been called yet.
+struct Ready {
+    request_id: u64,
+    callback: unsafe extern "C" fn(user_data: *mut c_void, object: *const MoqtObject),
+    user_data: *mut c_void,
+    object: Object,
+}
+
+/// Response to an ANNOUNCE: `None` while pending, then whether it was
+/// accepted or the reason it was rejected or cancelled.
+struct Announced {
+    namespace: Vec<String>,
+    outcome: Option<Result<(), String>>,
+}
+
+/// A client session, created with [`moqt_session_new`].
+pub struct MoqtSession {
+    session: BlockingSession<AppTransport>,
+    control: mpsc::Receiver<ControlMessage>,
+    codec: ControlMessageCodec,
+    setup: ClientSetup,
+    /// Encoded control messages not yet polled.
+    outgoing: BytesMut,
+    /// Control stream bytes not yet decoded.
+    incoming: BytesMut,
+    subscriptions: HashMap<u64, Subscription>,
+    /// Objects to pass to the callbacks once the call receiving them is
+    /// done with the session.
+    ready: VecDeque<Ready>,
+    announces: HashMap<u64, Announced>,
+}
+
+impl MoqtSession {
+    fn new() -> Result<Self, Error> {
+        let (session, control) = Session::new(Arc::new(AppTransport));
+        let session = BlockingSession::new(session)?;
+        let setup = ClientSetup::new([DRAFT_12]);
+        let mut codec = ControlMessageCodec::new();
+        let mut outgoing = BytesMut::new();
+        codec.encode(ControlMessage::ClientSetup(setup.clone()), &mut outgoing)?;
+        Ok(Self {
+            session,
+            control,
+            codec,
+            setup,
+            outgoing,
+            incoming: BytesMut::new(),
+            subscriptions: HashMap::new(),
+            ready: VecDeque::new(),
+            announces: HashMap::new(),
+        })
+    }
+
+    fn handle_control(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.incoming.extend_from_slice(data);
+        while let Some(mut msg) = self.codec.decode(&mut self.incoming)? {
+            let session = self.session.session();
+            session.check_incoming(&mut msg)?;
+            match msg {
+                ControlMessage::ServerSetup(server) => {
+                    server.validate(&self.setup)?;
+                    self.codec
+                        .set_version(WireVersion::negotiated(server.selected_version)?);
+                    let max = server.max_request_id()?;
+                    if max > 0 {
+                        session.track_manager.handle_max_request_id(max)?;
+                    }
+                    session.activate();
+                }
+                ControlMessage::MaxRequestId(max) => {
+                    session
+                        .track_manager
+                        .handle_max_request_id(max.request_id)?;
+                }
+                ControlMessage::SubscribeOk(ok) => {
+                    session.track_manager.handle_subscribe_ok(&ok)?;
+                    // Objects held until the alias was known were released.
+                    self.collect();
+                }
+                ControlMessage::SubscribeError(e) => {
+                    self.subscriptions.remove(&e.request_id);
+                }
+                ControlMessage::SubscribeDone(done) => {
+                    self.collect();
+                    self.subscriptions.remove(&done.request_id);
+                }
+                // Nothing is published through the session, so no track
+                // can be subscribed to.
+                ControlMessage::Subscribe(subscribe) => {
+                    self.session
+                        .send_control(ControlMessage::SubscribeError(SubscribeError {
+                            request_id: subscribe.request_id,
+                            error_code: RequestErrorCode::TrackDoesNotExist.code(),
+                            error_reason: "not publishing".into(),
+                        }))?;
+                }
+                ControlMessage::AnnounceOk(ok) => {
+                    if let Some(a) = self.announces.get_mut(&ok.request_id) {
+                        a.outcome = Some(Ok(()));
+                    }
+                }
+                ControlMessage::AnnounceError(e) => {
+                    session.handle_announce_error(&e);
+                    if let Some(a) = self.announces.get_mut(&e.request_id) {
+                        a.outcome = Some(Err(e.error_reason));
+                    }
+                }
+                ControlMessage::AnnounceCancel(cancel) => {
+                    session.handle_announce_cancel(&cancel);
+                    for a in self.announces.values_mut() {
+                        if a.namespace == cancel.track_namespace {
+                            a.outcome = Some(Err(cancel.error_reason.clone()));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Deliver the objects of a subgroup stream one at a time, taking each
+    /// from the subscriptions' queues before the next, so that no queue
+    /// overflows however many objects the stream holds.
+    fn handle_stream(&mut self, data: &[u8]) -> Result<(), Error> {
+        let mut reader = SubgroupReader::new(data);
+        loop {
+            let tracks = &self.session.session().track_manager;
+            let delivered = self.session.block_on(reader.deliver_next(tracks));
+            self.collect();
+            if !delivered? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Take the objects queued on the subscriptions, to be passed to their
+    /// callbacks by [`run_callbacks`].
+    fn collect(&mut self) {
+        for (&request_id, s) in &mut self.subscriptions {
+            while let Some(object) = s.subscription.try_recv() {
+                let (Ok(object), Some(callback)) = (object, s.callback) else {
+                    continue;
+                };
+                self.ready.push_back(Ready {
+                    request_id,
+                    callback,
+                    user_data: s.user_data,
+                    object,
+                });
+            }
+        }
+    }
+
+    fn poll_control(&mut self) -> Result<(), Error> {
+        while let Ok(msg) = self.control.try_recv() {
+            self.codec.encode(msg, &mut self.outgoing)?;
+        }
+        Ok(())
+    }
+}
+
+/// Pass the objects collected to their callbacks. No reference to the
+/// session is held while a callback runs, so it may call back into it.
+///
+/// # Safety
+///
+/// `session` must be a live session.
+unsafe fn run_callbacks(session: *mut MoqtSession) {
+    loop {
+        let Some(ready) = (unsafe { &mut *session }).ready.pop_front() else {
+            return;
+        };
+        let object = MoqtObject::new(&ready.object);
+        unsafe { (ready.callback)(ready.user_data, &object) };
+    }
+}
+
+/// Message of the last failed call on this thread, valid until the next
+/// call failing on this thread. Null if no call failed yet.
+#[unsafe(no_mangle)]
+pub extern "C" fn moqt_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Number of handles not yet freed, to check an application for leaks.
+#[unsafe(no_mangle)]
+pub extern "C" fn moqt_live_handles() -> usize {
+    LIVE_HANDLES.load(Ordering::Relaxed)
+}
+
+/// Create a client session. Its CLIENT_SETUP is the first data returned by
+/// [`moqt_session_poll_control`]. Returns null on failure.
+#[unsafe(no_mangle)]
+pub extern "C" fn moqt_session_new() -> *mut MoqtSession {
+    match MoqtSession::new() {
+        Ok(session) => into_handle(session),
+        Err(e) => {
+            fail(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Free a session and its subscriptions.
+///
+/// # Safety
+///
+/// `session` must be null or returned by [`moqt_session_new`] and not freed
+/// yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn moqt_session_free(session: *mut MoqtSession) {
+    if let Some(s) = unsafe { session.as_ref() } {
+        s.session.shutdown();
+    }
+    unsafe { free_handle(session) }
+}
+
+/// Copy up to `capacity` bytes to write to the control stream into `buf`,
+/// setting `len` to their number. Returns `MOQT_STATUS_EMPTY` if there are
+/// none.
+///
+/// # Safety
+///
+/// `session` must be a live session, `buf` must point to `capacity`
+/// writable bytes and `len` to a writable `size_t`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn moqt_session_poll_control(
+    session: *mut MoqtSession,
+    buf: *mut u8,
+    capacity: usize,
+    len: *mut usize,
+) -> MoqtStatus {
+    let (Some(session), Some(len)) = (unsafe { session.as_mut() }, unsafe { len.as_mut() }) else {
+        return invalid("null argument");
+    };
+    if let Err(e) = session.poll_control() {
+        return fail(e);
+    }
+    if session.outgoing.is_empty() {
+        *len = 0;
+        return MoqtStatus::Empty;
+    }
+    if buf.is_null() {
+        return invalid("null buffer");
+    }
+    let n = capacity.min(session.outgoing.len());
+    let data = session.outgoing.split_to(n);
+    unsafe { ptr::copy_nonoverlapping(data.as_ptr(), buf, n) };
+    *len = n;
+    MoqtStatus::Ok
+}
+
+/// Process `len` bytes read from the control stream. Messages may be split
+/// across calls.
+///
+/// # Safety
+///
+/// `session` must be a live session and `data` must point to `len`
+/// readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn moqt_session_handle_control(
+    session: *mut MoqtSession,
+    data: *const u8,
+    len: usize,
+) -> MoqtStatus {
+    let Some(s) = (unsafe { session.as_mut() }) else {
+        return invalid("null session");
+    };
+    let data = arg!(unsafe { bytes_arg(data, len) });
+    let handled = s.handle_control(data);
+    unsafe { run_callbacks(session) };
+    match handled {
+        Ok(()) => MoqtStatus::Ok,
+        Err(e) => fail(e),
+    }
+}
+
+/// Process a whole subgroup stream received from the peer, passing its
+/// objects to the callbacks of the subscriptions.
+///
+/// # Safety
+///
+/// `session` must be a live session and `data` must point to `len`
+/// readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn moqt_session_handle_stream(
+    session: *mut MoqtSession,
+    data: *const u8,
+    len: usize,
+) -> MoqtStatus {
+    let Some(s) = (unsafe { session.as_mut() }) else {
+        return invalid("null session");
+    };
+    let data = arg!(unsafe { bytes_arg(data, len) });
+    let handled = s.handle_stream(data);
+    unsafe { run_callbacks(session) };
+    match handled {
+        Ok(()) => MoqtStatus::Ok,
+        Err(e) => fail(e),
+    }
+}
+
+/// Subscribe to `track_name` in `track_namespace`, passing its objects to
+/// `callback` with `user_data`. Sets `request_id` to the Request ID of the
+/// SUBSCRIBE.
+///
+/// # Safety
+///
+/// `session` must be a live session, `track_name` a NUL-terminated string
+/// and `request_id` must point to a writable `uint64_t`. `user_data` is
+/// passed to `callback` as is.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn moqt_subscribe(
+    session: *mut MoqtSession,
+    track_namespace: u64,
+    track_name: *const c_char,
+    callback: MoqtObjectCallback,
+    user_data: *mut c_void,
+    request_id: *mut u64,
+) -> MoqtStatus {
+    let (Some(session), Some(request_id)) =
+        (unsafe { session.as_mut() }, unsafe { request_id.as_mut() })
+    else {
+        return invalid("null argument");
+    };
+    let track_name = arg!(unsafe { str_arg(track_name) });
+    let request = SubscribeRequest::new(track_namespace, track_name);
+    match session.session.subscribe(request) {
+        Ok(subscription) => {
+            *request_id = subscription.request_id();
+            session.subscriptions.insert(
+                *request_id,
+                Subscription {
+                    subscription,
+                    callback,
+                    user_data,
+                },
+            );
+            MoqtStatus::Ok
+        }
+        Err(e) => fail(e),
+    }
+}
+
+/// End the subscription `request_id` with UNSUBSCRIBE. Its callback is not
+/// called anymore, even from within a callback for objects already
+/// received.
+///
+/// # Safety
+///
+/// `session` must be a live session.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn moqt_unsubscribe(
+    session: *mut MoqtSession,
+    request_id: u64,
+) -> MoqtStatus {
+    let Some(session) = (unsafe { session.as_mut() }) else {
+        return invalid("null session");
+    };
+    let Some(s) = session.subscriptions.remove(&request_id) else {
+        return invalid("unknown subscription");
+    };
+    session.ready.retain(|r| r.request_id != request_id);
+    match s.subscription.unsubscribe() {
+        Ok(()) => MoqtStatus::Ok,
+        Err(e) => fail(e),
+    }
+}
+
+/// Announce the namespace of the `fields_len` strings `fields`. Sets
+/// `request_id` to the Request ID of the ANNOUNCE.
+///
+/// # Safety
+///
+/// `session` must be a live session, `fields` must point to `fields_len`
+/// NUL-terminated strings and `request_id` to a writable `uint64_t`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn moqt_announce(
+    session: *mut MoqtSession,
+    fields: *const *const c_char,
+    fields_len: usize,
+    request_id: *mut u64,
+) -> MoqtStatus {
+    let (Some(session), Some(request_id)) =
+        (unsafe { session.as_mut() }, unsafe { request_id.as_mut() })
+    else {
+        return invalid("null argument");
+    };
+    if fields.is_null() && fields_len > 0 {
+        return invalid("null namespace");
+    }
+    let mut namespace = Vec::with_capacity(fields_len);
+    for i in 0..fields_len {
+        let field = arg!(unsafe { str_arg(*fields.add(i)) });
+        namespace.push(field.to_string());
+    }
+    match session
+        .session
+        .announce(AnnounceRequest::new(namespace.clone()))
+    {
+        Ok(id) => {
+            *request_id = id;
+            session.announces.insert(
+                id,
+                Announced {
+                    namespace,
+                    outcome: None,
+                },
+            );
+            MoqtStatus::Ok
+        }
+        Err(e) => fail(e),
+    }
+}
+
+/// Response to the ANNOUNCE `request_id`: `MOQT_STATUS_OK` once accepted,
+/// `MOQT_STATUS_EMPTY` while there is none, and `MOQT_STATUS_ERROR` once
+/// rejected or cancelled, with the reason in [`moqt_last_error`]. The
+/// namespace may then be announced again.
+///
+/// # Safety
+///
+/// `session` must be a live session.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn moqt_announce_status(
+    session: *mut MoqtSession,
+    request_id: u64,
+) -> MoqtStatus {
+    let Some(session) = (unsafe { session.as_mut() }) else {
+        return invalid("null session");
+    };
+    let Some(announced) = session.announces.get(&request_id) else {
+        return invalid("unknown announce");
+    };
+    match &announced.outcome {
+        None => MoqtStatus::Empty,
+        Some(Ok(())) => MoqtStatus::Ok,
+        Some(Err(reason)) => {
+            set_last_error(reason.clone());
+            MoqtStatus::Error
+        }
+    }
+}
+
+/// Publisher of a track, created with [`moqt_publisher_new`].
+pub struct MoqtPublisher {
+    publisher: TrackPublisher,
+    /// Group and subgroup of the stream written last.
+    stream: Option<(u64, u64)>,
+}
+
+/// Create the publisher of the track aliased `track_alias`.
+#[unsafe(no_mangle)]
+pub extern "C" fn moqt_publisher_new(track_alias: u64) -> *mut MoqtPublisher {
+    into_handle(MoqtPublisher {
+        publisher: TrackPublisher::new(track_alias),
+        stream: None,
+    })
+}
+
+/// # Safety
+///
+/// `publisher` must be null or returned by [`moqt_publisher_new`] and not
+/// freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn moqt_publisher_free(publisher: *mut MoqtPublisher) {
+    unsafe { free_handle(publisher) }
+}
+
+/// Publish a frame: a keyframe starts a new group, frames before the first
+/// keyframe are dropped. Calls `callback` with each resulting object and
+/// the bytes to send for it.
+///
+/// # Safety
+///
+/// `publisher` must be a live publisher and `data` must point to `len`
+/// readable bytes. `user_data` is passed to `callback` as is.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn moqt_publisher_push_frame(
+    publisher: *mut MoqtPublisher,
+    keyframe: bool,
+    data: *const u8,
+    len: usize,
+    callback: MoqtStreamCallback,
+    user_data: *mut c_void,
+) -> MoqtStatus {
+    let Some(publisher) = (unsafe { publisher.as_mut() }) else {
+        return invalid("null publisher");
+    };
+    let data = arg!(unsafe { bytes_arg(data, len) });
+    let objects = publisher
+        .publisher
+        .push_frame(keyframe, Bytes::copy_from_slice(data));
+    for object in objects {
+        let metadata = &object.metadata;
+        let stream = (metadata.group_id, metadata.subgroup_id);
+        let mut buf = BytesMut::new();
+        if publisher.stream != Some(stream) {
+            publisher.stream = Some(stream);
+            let header = SubgroupHeader {
+                track_alias: metadata.track_alias,
+                group_id: metadata.group_id,
+                subgroup_id: SubgroupId::Explicit(metadata.subgroup_id),
+                publisher_priority: metadata.publisher_priority,
+                extensions_present: false,
+                end_of_group: false,
+            };
+            if let Err(e) = header.encode(&mut buf) {
+                return fail(e);
+            }
+        }
+        if let Err(e) = object.to_subgroup_object().encode(&mut buf, false) {
+            return fail(e);
+        }
+        if let Some(callback) = callback {
+            let moqt_object = MoqtObject::new(&object);
+            unsafe { callback(user_data, &moqt_object, buf.as_ptr(), buf.len()) };
+        }
+    }
+    MoqtStatus::Ok
+}
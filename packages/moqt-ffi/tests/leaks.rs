@@ -0,0 +1,308 @@
+//! Drives the C ABI as a native application would and checks that every
+//! handle and every allocation is released. Kept to a single test, as the
+//! handle count and the allocator are process wide.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::ffi::{CStr, c_void};
+use std::ptr;
+use std::sync::atomic::{AtomicIsize, Ordering};
+
+use bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+
+use moqt_ffi::*;
+use moqt_transport::{
+    codec::{ControlMessageCodec, DRAFT_12},
+    message::{AnnounceError, ControlMessage, ServerSetup, SubscribeOk},
+};
+
+/// Counts the bytes allocated and not freed yet.
+struct Counting;
+
+static ALLOCATED: AtomicIsize = AtomicIsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size() as isize, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size() as isize, Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+unsafe extern "C" fn collect_payload(user_data: *mut c_void, object: *const MoqtObject) {
+    let payloads = unsafe { &mut *(user_data as *mut Vec<Vec<u8>>) };
+    let object = unsafe { &*object };
+    let payload = unsafe { std::slice::from_raw_parts(object.payload, object.payload_len) };
+    payloads.push(payload.to_vec());
+}
+
+/// Unsubscribes from within the callback of its first object.
+struct Unsubscriber {
+    session: *mut MoqtSession,
+    request_id: u64,
+    objects: usize,
+}
+
+unsafe extern "C" fn unsubscribe_on_first(user_data: *mut c_void, _object: *const MoqtObject) {
+    let unsubscriber = unsafe { &mut *(user_data as *mut Unsubscriber) };
+    unsubscriber.objects += 1;
+    let status = unsafe { moqt_unsubscribe(unsubscriber.session, unsubscriber.request_id) };
+    assert_eq!(status, MoqtStatus::Ok);
+}
+
+unsafe extern "C" fn collect_stream(
+    user_data: *mut c_void,
+    object: *const MoqtObject,
+    data: *const u8,
+    len: usize,
+) {
+    let streams = unsafe { &mut *(user_data as *mut Vec<(u64, Vec<u8>)>) };
+    let group_id = unsafe { (*object).group_id };
+    let data = unsafe { std::slice::from_raw_parts(data, len) };
+    match streams.last_mut() {
+        Some((group, stream)) if *group == group_id => stream.extend_from_slice(data),
+        _ => streams.push((group_id, data.to_vec())),
+    }
+}
+
+/// Control messages the session wants sent.
+fn poll_control(session: *mut MoqtSession, codec: &mut ControlMessageCodec) -> Vec<ControlMessage> {
+    let mut buf = BytesMut::new();
+    let mut chunk = [0u8; 7];
+    let mut len = 0;
+    while unsafe { moqt_session_poll_control(session, chunk.as_mut_ptr(), chunk.len(), &mut len) }
+        == MoqtStatus::Ok
+    {
+        buf.extend_from_slice(&chunk[..len]);
+    }
+    let mut messages = Vec::new();
+    while let Some(msg) = codec.decode(&mut buf).unwrap() {
+        messages.push(msg);
+    }
+    assert!(buf.is_empty());
+    messages
+}
+
+fn handle_control(session: *mut MoqtSession, codec: &mut ControlMessageCodec, msg: ControlMessage) {
+    let mut buf = BytesMut::new();
+    codec.encode(msg, &mut buf).unwrap();
+    let status = unsafe { moqt_session_handle_control(session, buf.as_ptr(), buf.len()) };
+    assert_eq!(status, MoqtStatus::Ok);
+}
+
+fn exchange_objects() {
+    let mut codec = ControlMessageCodec::new();
+    let session = moqt_session_new();
+    assert!(!session.is_null());
+    assert!(matches!(
+        poll_control(session, &mut codec)[..],
+        [ControlMessage::ClientSetup(_)]
+    ));
+    handle_control(
+        session,
+        &mut codec,
+        ControlMessage::ServerSetup(ServerSetup::accept(DRAFT_12).with_max_request_id(10)),
+    );
+
+    let mut payloads: Vec<Vec<u8>> = Vec::new();
+    let mut request_id = 0;
+    let status = unsafe {
+        moqt_subscribe(
+            session,
+            0,
+            c"video".as_ptr(),
+            Some(collect_payload),
+            &mut payloads as *mut _ as *mut c_void,
+            &mut request_id,
+        )
+    };
+    assert_eq!(status, MoqtStatus::Ok);
+    let [ControlMessage::Subscribe(subscribe)] = &poll_control(session, &mut codec)[..] else {
+        panic!("expected SUBSCRIBE");
+    };
+    assert_eq!(subscribe.request_id, request_id);
+    handle_control(
+        session,
+        &mut codec,
+        ControlMessage::SubscribeOk(SubscribeOk {
+            request_id,
+            track_alias: 7,
+            expires: 0,
+            group_order: 1,
+            content_exists: false,
+            largest_location: None,
+            parameters: Vec::new(),
+        }),
+    );
+
+    let publisher = moqt_publisher_new(7);
+    let mut streams: Vec<(u64, Vec<u8>)> = Vec::new();
+    let mut frames = vec![(true, &b"key"[..])];
+    // More objects in one stream than a subscription queues.
+    frames.extend([(false, &b"delta"[..]); 40]);
+    frames.push((true, b"key2"));
+    for (keyframe, frame) in frames {
+        let status = unsafe {
+            moqt_publisher_push_frame(
+                publisher,
+                keyframe,
+                frame.as_ptr(),
+                frame.len(),
+                Some(collect_stream),
+                &mut streams as *mut _ as *mut c_void,
+            )
+        };
+        assert_eq!(status, MoqtStatus::Ok);
+    }
+    assert_eq!(streams.len(), 2);
+    for (_, stream) in &streams {
+        let status = unsafe { moqt_session_handle_stream(session, stream.as_ptr(), stream.len()) };
+        assert_eq!(status, MoqtStatus::Ok);
+    }
+    // The End of Group status object of group 0 has no payload.
+    let mut expected = vec![&b"key"[..]];
+    expected.extend([&b"delta"[..]; 40]);
+    expected.extend([&b""[..], b"key2"]);
+    assert_eq!(payloads, expected);
+
+    assert_eq!(
+        unsafe { moqt_unsubscribe(session, request_id) },
+        MoqtStatus::Ok
+    );
+    assert_eq!(
+        unsafe { moqt_unsubscribe(session, request_id) },
+        MoqtStatus::InvalidArgument
+    );
+    assert!(!moqt_last_error().is_null());
+
+    // A callback ending its own subscription is not called again.
+    let mut unsubscriber = Unsubscriber {
+        session,
+        request_id: 0,
+        objects: 0,
+    };
+    let status = unsafe {
+        moqt_subscribe(
+            session,
+            0,
+            c"video".as_ptr(),
+            Some(unsubscribe_on_first),
+            &mut unsubscriber as *mut _ as *mut c_void,
+            &mut unsubscriber.request_id,
+        )
+    };
+    assert_eq!(status, MoqtStatus::Ok);
+    handle_control(
+        session,
+        &mut codec,
+        ControlMessage::SubscribeOk(SubscribeOk {
+            request_id: unsubscriber.request_id,
+            track_alias: 8,
+            expires: 0,
+            group_order: 1,
+            content_exists: false,
+            largest_location: None,
+            parameters: Vec::new(),
+        }),
+    );
+    let publisher_8 = moqt_publisher_new(8);
+    let mut streams: Vec<(u64, Vec<u8>)> = Vec::new();
+    for keyframe in [true, false, false] {
+        let status = unsafe {
+            moqt_publisher_push_frame(
+                publisher_8,
+                keyframe,
+                b"frame".as_ptr(),
+                5,
+                Some(collect_stream),
+                &mut streams as *mut _ as *mut c_void,
+            )
+        };
+        assert_eq!(status, MoqtStatus::Ok);
+    }
+    let status =
+        unsafe { moqt_session_handle_stream(session, streams[0].1.as_ptr(), streams[0].1.len()) };
+    assert_eq!(status, MoqtStatus::Ok);
+    assert_eq!(unsubscriber.objects, 1);
+
+    // ANNOUNCE_ERROR is reported.
+    let field = c"example.com".as_ptr();
+    let mut announce_id = 0;
+    let status = unsafe { moqt_announce(session, &field, 1, &mut announce_id) };
+    assert_eq!(status, MoqtStatus::Ok);
+    assert_eq!(
+        unsafe { moqt_announce_status(session, announce_id) },
+        MoqtStatus::Empty
+    );
+    handle_control(
+        session,
+        &mut codec,
+        ControlMessage::AnnounceError(AnnounceError {
+            request_id: announce_id,
+            error_code: 0x1,
+            error_reason: "unauthorized".into(),
+        }),
+    );
+    assert_eq!(
+        unsafe { moqt_announce_status(session, announce_id) },
+        MoqtStatus::Error
+    );
+    let error = unsafe { CStr::from_ptr(moqt_last_error()) };
+    assert_eq!(error.to_str().unwrap(), "unauthorized");
+    // The namespace may be announced again.
+    let status = unsafe { moqt_announce(session, &field, 1, &mut announce_id) };
+    assert_eq!(status, MoqtStatus::Ok);
+
+    unsafe {
+        moqt_publisher_free(publisher);
+        moqt_publisher_free(publisher_8);
+        moqt_session_free(session);
+    }
+}
+
+#[test]
+fn handles_are_released() {
+    exchange_objects();
+    let allocated = ALLOCATED.load(Ordering::Relaxed);
+    for _ in 0..20 {
+        exchange_objects();
+        assert_eq!(moqt_live_handles(), 0);
+    }
+    // Only the last error message of the thread may differ in size.
+    let grown = ALLOCATED.load(Ordering::Relaxed) - allocated;
+    assert!(grown.abs() < 64, "{grown} bytes not freed");
+
+    // Sessions freed with live subscriptions and unread control messages.
+    for _ in 0..20 {
+        let session = moqt_session_new();
+        let mut request_id = 0;
+        let status = unsafe {
+            moqt_subscribe(
+                session,
+                0,
+                c"video".as_ptr(),
+                None,
+                ptr::null_mut(),
+                &mut request_id,
+            )
+        };
+        // No SERVER_SETUP yet: no Request ID may be used.
+        assert_eq!(status, MoqtStatus::Error);
+        let error = unsafe { CStr::from_ptr(moqt_last_error()) };
+        assert_eq!(error.to_str().unwrap(), "too many requests");
+        unsafe { moqt_session_free(session) };
+    }
+    assert_eq!(moqt_live_handles(), 0);
+
+    unsafe {
+        moqt_session_free(ptr::null_mut());
+        moqt_publisher_free(ptr::null_mut());
+    }
+}
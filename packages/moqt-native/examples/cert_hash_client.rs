@@ -0,0 +1,154 @@
+//! Connect to a relay authenticated by certificate hash, as dev MoQ relays
+//! with short-lived self-signed certificates expect.
+//!
+//! Run against a relay by passing its address and the SHA-256 hash of its
+//! certificate:
+//!
+//! ```text
+//! cargo run -p moqt-native --example cert_hash_client -- 127.0.0.1:4443 9f:86:d0:...
+//! ```
+//!
+//! Without arguments, a server with a freshly generated certificate is
+//! started in-process and connected to.
+
+use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use bytes::BytesMut;
+use moqt_native::cert_hash::{self, ALPN, CertificateHash};
+use moqt_transport::{
+    codec::{ControlMessageCodec, DRAFT_12, WireVersion},
+    message::{ClientSetup, ControlMessage, ServerSetup},
+};
+use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_util::codec::{Decoder, Encoder};
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+fn main() -> Result<(), BoxError> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    rt.block_on(async {
+        let mut args = std::env::args().skip(1);
+        let (addr, hash) = match (args.next(), args.next()) {
+            (Some(addr), Some(hash)) => (addr.parse()?, hash.parse()?),
+            _ => serve_locally()?,
+        };
+        connect(addr, hash).await
+    })
+}
+
+/// Open a MOQT session to `addr`, accepting its certificate by `hash`, and
+/// exchange the setup messages.
+async fn connect(addr: SocketAddr, hash: CertificateHash) -> Result<(), BoxError> {
+    let tls = cert_hash::client_config([hash]);
+    let quic = QuicClientConfig::try_from(tls)?;
+    let mut endpoint = quinn::Endpoint::client("0.0.0.0:0".parse()?)?;
+    endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(quic)));
+
+    // The server name is not checked against the certificate.
+    let connection = endpoint.connect(addr, "localhost")?.await?;
+    println!("connected to {addr} with certificate {hash}");
+
+    let (mut send, mut recv) = connection.open_bi().await?;
+    let mut codec = ControlMessageCodec::new();
+    let setup = ClientSetup::new([DRAFT_12]);
+    let mut buf = BytesMut::new();
+    codec.encode(ControlMessage::ClientSetup(setup.clone()), &mut buf)?;
+    send.write_all(&buf).await?;
+
+    let Some(ControlMessage::ServerSetup(server)) = read_message(&mut codec, &mut recv).await?
+    else {
+        return Err("expected SERVER_SETUP".into());
+    };
+    server.validate(&setup)?;
+    codec.set_version(WireVersion::negotiated(server.selected_version)?);
+    println!(
+        "session established: version {:#x}, max request id {}",
+        server.selected_version,
+        server.max_request_id()?
+    );
+
+    connection.close(0u32.into(), b"done");
+    endpoint.wait_idle().await;
+    Ok(())
+}
+
+/// Start a server answering CLIENT_SETUP with a self-signed certificate
+/// valid for a week. Returns its address and certificate hash.
+fn serve_locally() -> Result<(SocketAddr, CertificateHash), BoxError> {
+    let now = time::OffsetDateTime::now_utc();
+    let mut params = rcgen::CertificateParams::new(vec!["localhost".into()])?;
+    params.not_before = now - time::Duration::hours(1);
+    params.not_after = now + time::Duration::days(7);
+    let key = rcgen::KeyPair::generate()?;
+    let cert = params.self_signed(&key)?;
+    let hash = CertificateHash::of(cert.der());
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut tls = rustls::ServerConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![CertificateDer::from(cert.der().to_vec())],
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der())),
+        )?;
+    tls.alpn_protocols = vec![ALPN.to_vec()];
+    let quic = QuicServerConfig::try_from(tls)?;
+    let endpoint = quinn::Endpoint::server(
+        quinn::ServerConfig::with_crypto(Arc::new(quic)),
+        "127.0.0.1:0".parse()?,
+    )?;
+    let addr = endpoint.local_addr()?;
+
+    tokio::spawn(async move {
+        while let Some(incoming) = endpoint.accept().await {
+            tokio::spawn(async move {
+                if let Err(e) = answer_setup(incoming).await {
+                    eprintln!("server: {e}");
+                }
+            });
+        }
+    });
+    Ok((addr, hash))
+}
+
+async fn answer_setup(incoming: quinn::Incoming) -> Result<(), BoxError> {
+    let connection = incoming.await?;
+    let (mut send, mut recv) = connection.accept_bi().await?;
+    let mut codec = ControlMessageCodec::new();
+    let Some(ControlMessage::ClientSetup(setup)) = read_message(&mut codec, &mut recv).await?
+    else {
+        return Err("expected CLIENT_SETUP".into());
+    };
+    if !setup.supported_versions.contains(&DRAFT_12) {
+        return Err("no supported version offered".into());
+    }
+    let mut buf = BytesMut::new();
+    let server = ServerSetup::accept(DRAFT_12).with_max_request_id(100);
+    codec.encode(ControlMessage::ServerSetup(server), &mut buf)?;
+    send.write_all(&buf).await?;
+    connection.closed().await;
+    Ok(())
+}
+
+/// Read from `stream` until a control message is decoded, or `None` if the
+/// stream ends first.
+async fn read_message(
+    codec: &mut ControlMessageCodec,
+    stream: &mut (impl AsyncRead + Unpin),
+) -> Result<Option<ControlMessage>, BoxError> {
+    let mut buf = BytesMut::new();
+    loop {
+        if let Some(msg) = codec.decode(&mut buf)? {
+            return Ok(Some(msg));
+        }
+        if stream.read_buf(&mut buf).await? == 0 {
+            return Ok(None);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Run the interoperability scenario against an external endpoint over raw
+//! QUIC, authenticating it by certificate hash:
+//!
+//! ```text
+//! MOQT_INTEROP_URL=moqt://127.0.0.1:4443/interop \
+//! MOQT_INTEROP_CERT_HASH=9f:86:d0:... \
+//! cargo run -p moqt-native --example interop_client
+//! ```
+//!
+//! The path of the URL is sent as the PATH parameter of CLIENT_SETUP. The
+//! pass/fail matrix is printed, and the process fails if any step did.
+
+use std::error::Error;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use moqt_native::cert_hash::{self, CertificateHash};
+use moqt_transport::{
+    interop::{Dialer, INTEROP_URL_VAR, Scenario, endpoint_from_env},
+    transport::{BiStream, BoxError, Capabilities, Transport, UniStream},
+};
+use quinn::crypto::rustls::QuicClientConfig;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Environment variable holding the SHA-256 hash of the endpoint's
+/// certificate.
+const CERT_HASH_VAR: &str = "MOQT_INTEROP_CERT_HASH";
+
+fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let url = endpoint_from_env().ok_or(format!("{INTEROP_URL_VAR} is not set"))?;
+    let hash: CertificateHash = std::env::var(CERT_HASH_VAR)
+        .map_err(|_| format!("{CERT_HASH_VAR} is not set"))?
+        .parse()?;
+    let (_, path) = split_url(&url)?;
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let report = rt.block_on(async {
+        let mut scenario = Scenario::default();
+        if !path.is_empty() {
+            scenario = scenario.with_path(path);
+        }
+        scenario.run_against(&QuicDialer { hash }, &url).await
+    });
+    println!("{report}");
+    if !report.passed() {
+        return Err(format!("{url} failed the scenario").into());
+    }
+    Ok(())
+}
+
+/// Authority and path of a `moqt://` URL.
+fn split_url(url: &str) -> Result<(&str, &str), BoxError> {
+    let rest = url
+        .strip_prefix("moqt://")
+        .ok_or_else(|| format!("{url}: not a moqt:// URL"))?;
+    Ok(match rest.find(['/', '?']) {
+        Some(at) => rest.split_at(at),
+        None => (rest, ""),
+    })
+}
+
+/// Dials endpoints over raw QUIC, accepting the certificate of `hash`.
+struct QuicDialer {
+    hash: CertificateHash,
+}
+
+#[async_trait]
+impl Dialer for QuicDialer {
+    type Transport = QuicTransport;
+
+    async fn dial(&self, url: &str) -> Result<QuicTransport, BoxError> {
+        let (authority, _) = split_url(url)?;
+        let addr = tokio::net::lookup_host(authority)
+            .await?
+            .next()
+            .ok_or_else(|| format!("{authority}: no address"))?;
+        let quic = QuicClientConfig::try_from(cert_hash::client_config([self.hash]))?;
+        let mut endpoint = quinn::Endpoint::client("0.0.0.0:0".parse()?)?;
+        endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(quic)));
+        // The server name is not checked against the certificate.
+        let connection = endpoint.connect(addr, "localhost")?.await?;
+        Ok(QuicTransport {
+            _endpoint: endpoint,
+            connection,
+        })
+    }
+}
+
+/// A MOQT transport over a quinn connection.
+struct QuicTransport {
+    _endpoint: quinn::Endpoint,
+    connection: quinn::Connection,
+}
+
+/// One direction of a unidirectional QUIC stream.
+enum QuicUni {
+    Send(quinn::SendStream),
+    Recv(quinn::RecvStream),
+}
+
+fn wrong_direction() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Unsupported, "wrong stream direction")
+}
+
+impl AsyncRead for QuicUni {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            QuicUni::Recv(recv) => Pin::new(recv).poll_read(cx, buf),
+            QuicUni::Send(_) => Poll::Ready(Err(wrong_direction())),
+        }
+    }
+}
+
+impl AsyncWrite for QuicUni {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            QuicUni::Send(send) => AsyncWrite::poll_write(Pin::new(send), cx, buf),
+            QuicUni::Recv(_) => Poll::Ready(Err(wrong_direction())),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            QuicUni::Send(send) => Pin::new(send).poll_flush(cx),
+            QuicUni::Recv(_) => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            QuicUni::Send(send) => Pin::new(send).poll_shutdown(cx),
+            QuicUni::Recv(_) => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+impl UniStream for QuicUni {
+    fn reset(&mut self, code: u64) {
+        let code = quinn::VarInt::from_u64(code).unwrap_or(quinn::VarInt::MAX);
+        match self {
+            QuicUni::Send(send) => {
+                let _ = send.reset(code);
+            }
+            QuicUni::Recv(recv) => {
+                let _ = recv.stop(code);
+            }
+        }
+    }
+}
+
+struct QuicBi(quinn::SendStream, quinn::RecvStream);
+
+impl BiStream for QuicBi {
+    type Reader = quinn::RecvStream;
+    type Writer = quinn::SendStream;
+
+    fn split(self) -> (quinn::RecvStream, quinn::SendStream) {
+        (self.1, self.0)
+    }
+}
+
+#[async_trait]
+impl Transport for QuicTransport {
+    type Uni = QuicUni;
+    type Bi = QuicBi;
+
+    async fn open_uni_stream(&mut self) -> Result<QuicUni, BoxError> {
+        Ok(QuicUni::Send(self.connection.open_uni().await?))
+    }
+
+    async fn accept_uni_stream(&mut self) -> Result<QuicUni, BoxError> {
+        Ok(QuicUni::Recv(self.connection.accept_uni().await?))
+    }
+
+    async fn open_bi_stream(&mut self) -> Result<QuicBi, BoxError> {
+        let (send, recv) = self.connection.open_bi().await?;
+        Ok(QuicBi(send, recv))
+    }
+
+    async fn accept_bi_stream(&mut self) -> Result<QuicBi, BoxError> {
+        let (send, recv) = self.connection.accept_bi().await?;
+        Ok(QuicBi(send, recv))
+    }
+
+    async fn send_datagram(&mut self, data: Bytes) -> Result<(), BoxError> {
+        Ok(self.connection.send_datagram(data)?)
+    }
+
+    async fn recv_datagram(&mut self) -> Option<Bytes> {
+        self.connection.read_datagram().await.ok()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        let max_datagram_size = self.connection.max_datagram_size();
+        Capabilities {
+            datagrams: max_datagram_size.is_some(),
+            max_datagram_size,
+            ..Capabilities::default()
+        }
+    }
+
+    fn close(&self, code: u64, reason: &str) {
+        let code = quinn::VarInt::from_u64(code).unwrap_or(quinn::VarInt::MAX);
+        self.connection.close(code, reason.as_bytes());
+    }
+}
@@ -0,0 +1,324 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ring::digest::{SHA256, digest};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{CertificateError, ClientConfig, DigitallySignedStruct, OtherError, SignatureScheme};
+
+/// ALPN identifying MOQT on a raw QUIC connection.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-native-quic
+pub const ALPN: &[u8] = b"moq-00";
+
+/// Longest validity period of a certificate accepted by its hash, as
+/// WebTransport requires for `serverCertificateHashes`.
+///
+/// https://www.w3.org/TR/webtransport/#verify-a-certificate-hash
+pub const MAX_VALIDITY: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+
+/// Why a certificate or certificate hash was refused.
+#[derive(Debug, thiserror::Error)]
+pub enum CertHashError {
+    #[error("invalid certificate hash {0:?}: expected 32 hex-encoded bytes")]
+    InvalidHash(String),
+
+    #[error("certificate hash {0} is not among the expected hashes")]
+    UnknownHash(CertificateHash),
+
+    #[error("malformed certificate: {0}")]
+    MalformedCertificate(String),
+
+    #[error("certificate valid for {days} days, more than the 14 allowed")]
+    ValidityTooLong { days: u64 },
+}
+
+/// SHA-256 hash of a DER-encoded certificate, by which a server with a
+/// self-signed certificate is authenticated instead of a chain to a trusted
+/// root.
+///
+/// Displayed as hex, and parsed as hex with `:` between every two bytes or
+/// none, e.g. the output of `openssl x509 -noout -fingerprint -sha256`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CertificateHash([u8; 32]);
+
+impl CertificateHash {
+    /// Hash of the DER-encoded certificate `der`.
+    pub fn of(der: &[u8]) -> Self {
+        let mut hash = [0; 32];
+        hash.copy_from_slice(digest(&SHA256, der).as_ref());
+        Self(hash)
+    }
+
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl fmt::Display for CertificateHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for CertificateHash {
+    type Err = CertHashError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || CertHashError::InvalidHash(s.into());
+        let pairs: Vec<&[u8]> = if s.contains(':') {
+            s.as_bytes().split(|b| *b == b':').collect()
+        } else {
+            s.as_bytes().chunks(2).collect()
+        };
+        let valid = |pair: &&[u8]| pair.len() == 2 && pair.iter().all(u8::is_ascii_hexdigit);
+        if pairs.len() != 32 || !pairs.iter().all(valid) {
+            return Err(invalid());
+        }
+        let mut hash = [0; 32];
+        for (byte, pair) in hash.iter_mut().zip(pairs) {
+            *byte = pair.iter().fold(0, |acc, d| {
+                acc << 4 | char::from(*d).to_digit(16).unwrap() as u8
+            });
+        }
+        Ok(Self(hash))
+    }
+}
+
+/// Server certificate verifier accepting certificates by their hash, as
+/// WebTransport's `serverCertificateHashes` does.
+///
+/// A certificate is accepted when its SHA-256 hash is one of the expected
+/// hashes, it is currently valid, and its validity period does not exceed
+/// [`MAX_VALIDITY`]. Neither its issuer nor the server name are checked,
+/// so dev relays can use short-lived self-signed certificates. Handshake
+/// signatures are verified as usual.
+#[derive(Debug)]
+pub struct CertHashVerifier {
+    hashes: Vec<CertificateHash>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl CertHashVerifier {
+    /// Verifier accepting the certificates hashing to one of `hashes`,
+    /// verifying signatures with the ring crypto provider.
+    pub fn new(hashes: impl IntoIterator<Item = CertificateHash>) -> Self {
+        Self::with_provider(hashes, Arc::new(rustls::crypto::ring::default_provider()))
+    }
+
+    /// Like [`new`](Self::new), verifying signatures with `provider`.
+    pub fn with_provider(
+        hashes: impl IntoIterator<Item = CertificateHash>,
+        provider: Arc<CryptoProvider>,
+    ) -> Self {
+        Self {
+            hashes: hashes.into_iter().collect(),
+            provider,
+        }
+    }
+
+    /// Check `der` against the expected hashes and its validity at `now`.
+    pub fn verify(&self, der: &[u8], now: UnixTime) -> Result<(), rustls::Error> {
+        let refuse = |e: CertHashError| {
+            rustls::Error::InvalidCertificate(CertificateError::Other(OtherError(Arc::new(e))))
+        };
+        let hash = CertificateHash::of(der);
+        if !self.hashes.contains(&hash) {
+            return Err(refuse(CertHashError::UnknownHash(hash)));
+        }
+
+        let (_, cert) = x509_parser::parse_x509_certificate(der)
+            .map_err(|e| refuse(CertHashError::MalformedCertificate(e.to_string())))?;
+        let validity = cert.validity();
+        let not_before = validity.not_before.timestamp();
+        let not_after = validity.not_after.timestamp();
+        let now = now.as_secs() as i64;
+        if now < not_before {
+            return Err(rustls::Error::InvalidCertificate(
+                CertificateError::NotValidYet,
+            ));
+        }
+        if now > not_after {
+            return Err(rustls::Error::InvalidCertificate(CertificateError::Expired));
+        }
+        let period = not_after.saturating_sub(not_before) as u64;
+        if period > MAX_VALIDITY.as_secs() {
+            let days = period.div_ceil(24 * 60 * 60);
+            return Err(refuse(CertHashError::ValidityTooLong { days }));
+        }
+        Ok(())
+    }
+}
+
+impl ServerCertVerifier for CertHashVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.verify(end_entity, now)?;
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// TLS 1.3 client configuration authenticating the server by certificate
+/// hash with [`CertHashVerifier`] and offering the MOQT [`ALPN`], ready to
+/// be turned into a QUIC client configuration.
+///
+/// ```ignore
+/// let hash: CertificateHash = "9f:86:d0:...".parse()?;
+/// let tls = cert_hash::client_config([hash]);
+/// let quic = quinn::crypto::rustls::QuicClientConfig::try_from(tls)?;
+/// endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(quic)));
+/// ```
+pub fn client_config(hashes: impl IntoIterator<Item = CertificateHash>) -> ClientConfig {
+    let verifier = CertHashVerifier::new(hashes);
+    let mut config = ClientConfig::builder_with_provider(verifier.provider.clone())
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .expect("the ring provider supports TLS 1.3")
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    config.alpn_protocols = vec![ALPN.to_vec()];
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{CertificateParams, KeyPair};
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    /// Self-signed certificate valid from `from` to `to` seconds around now.
+    fn certificate(from: i64, to: i64) -> (Vec<u8>, UnixTime) {
+        let now = time::OffsetDateTime::now_utc();
+        let mut params = CertificateParams::new(vec!["localhost".into()]).unwrap();
+        params.not_before = now + time::Duration::seconds(from);
+        params.not_after = now + time::Duration::seconds(to);
+        let key = KeyPair::generate().unwrap();
+        let der = params.self_signed(&key).unwrap().der().to_vec();
+        (der, UnixTime::now())
+    }
+
+    #[test]
+    fn hash_roundtrips_through_hex() {
+        let hash = CertificateHash::of(b"certificate");
+        let hex = hash.to_string();
+        assert_eq!(hex.len(), 64);
+        assert_eq!(hex.parse::<CertificateHash>().unwrap(), hash);
+
+        let colons: Vec<_> = hash.as_bytes().iter().map(|b| format!("{b:02X}")).collect();
+        assert_eq!(colons.join(":").parse::<CertificateHash>().unwrap(), hash);
+        assert!(matches!(
+            "abcd".parse::<CertificateHash>(),
+            Err(CertHashError::InvalidHash(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_hex() {
+        let hex = CertificateHash::of(b"certificate").to_string();
+        let malformed = [
+            // Signs accepted by `u8::from_str_radix`.
+            format!("+f+f{}", &hex[4..]),
+            // Colons that do not separate two-digit bytes.
+            format!("::{hex}"),
+            format!("{}:", &hex),
+            format!("{}:{}", &hex[..1], &hex[1..]),
+            format!("{}:{}", &hex[..2], &hex[2..]),
+            format!("{}g", &hex[..63]),
+            format!("{}é", &hex[..62]),
+        ];
+        for s in malformed {
+            assert!(
+                matches!(
+                    s.parse::<CertificateHash>(),
+                    Err(CertHashError::InvalidHash(_))
+                ),
+                "{s:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn accepts_only_expected_short_lived_certificates() {
+        let (der, now) = certificate(-DAY, 10 * DAY);
+        let verifier = CertHashVerifier::new([CertificateHash::of(&der)]);
+        verifier.verify(&der, now).unwrap();
+
+        let other = CertHashVerifier::new([CertificateHash::of(b"other")]);
+        assert!(matches!(
+            other.verify(&der, now),
+            Err(rustls::Error::InvalidCertificate(CertificateError::Other(
+                _
+            )))
+        ));
+
+        let (long, now) = certificate(-DAY, 30 * DAY);
+        let verifier = CertHashVerifier::new([CertificateHash::of(&long)]);
+        match verifier.verify(&long, now) {
+            Err(rustls::Error::InvalidCertificate(CertificateError::Other(e))) => {
+                assert!(matches!(
+                    e.0.downcast_ref(),
+                    Some(CertHashError::ValidityTooLong { days: 31 })
+                ))
+            }
+            r => panic!("unexpected result: {r:?}"),
+        }
+
+        let (expired, now) = certificate(-10 * DAY, -DAY);
+        let verifier = CertHashVerifier::new([CertificateHash::of(&expired)]);
+        assert_eq!(
+            verifier.verify(&expired, now),
+            Err(rustls::Error::InvalidCertificate(CertificateError::Expired))
+        );
+    }
+}
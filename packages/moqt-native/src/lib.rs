@@ -1 +1 @@
-
+pub mod cert_hash;
@@ -0,0 +1,671 @@
+//! Python bindings of the MoQT client, for test automation and research
+//! scripts.
+//!
+//! As with the C ABI, the script owns the connection, e.g. a WebTransport
+//! session of `aioquic`: it writes [`Session::control_bytes`] to the
+//! control stream, and passes what it reads to
+//! [`Session::receive_control`] and [`Session::receive_stream`]. Requests
+//! are asyncio awaitables, and subscriptions and fetches are async
+//! iterators of objects:
+//!
+//! ```python
+//! session = moqt.Session()
+//! # ... exchange control bytes until set up
+//! await session.connected()
+//! subscription = await session.subscribe(0, "video")
+//! async for obj in subscription:
+//!     print(obj.group_id, obj.object_id, len(obj.payload))
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use bytes::{Bytes, BytesMut};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyStopAsyncIteration};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use pyo3_async_runtimes::tokio::future_into_py;
+use tokio::sync::{mpsc, oneshot};
+use tokio_util::codec::{Decoder, Encoder};
+
+use moqt_transport::{
+    codec::{ControlMessageCodec, DRAFT_12, WireVersion},
+    data::{SubgroupHeader, SubgroupId},
+    error::Error,
+    incoming::IncomingStream,
+    message::{ClientSetup, ControlMessage, Fetch as FetchMessage, FetchOk},
+    model::Location,
+    request::{AnnounceRequest, SubscribeRequest},
+    scheduler::DEFAULT_SUBSCRIBER_PRIORITY,
+    session::{Session as TransportSession, State},
+    subscription::SubscriptionHandle,
+    track::{Object as TransportObject, TrackPublisher},
+    transport::AppTransport,
+};
+
+create_exception!(moqt, MoqtError, PyException, "A MoQT operation failed.");
+
+fn to_py(e: Error) -> PyErr {
+    MoqtError::new_err(e.to_string())
+}
+
+/// An object received or published.
+#[pyclass(frozen, module = "moqt")]
+pub struct Object {
+    /// `None` for a fetched object of a track not subscribed to, as FETCH
+    /// streams do not carry a Track Alias.
+    #[pyo3(get)]
+    track_alias: Option<u64>,
+    #[pyo3(get)]
+    group_id: u64,
+    #[pyo3(get)]
+    subgroup_id: u64,
+    #[pyo3(get)]
+    object_id: u64,
+    #[pyo3(get)]
+    publisher_priority: u8,
+    /// Object Status, 0 for a normal object.
+    #[pyo3(get)]
+    status: u64,
+    payload: Bytes,
+}
+
+#[pymethods]
+impl Object {
+    #[getter]
+    fn payload<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.payload)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Object(group_id={}, object_id={}, status={}, payload=<{} bytes>)",
+            self.group_id,
+            self.object_id,
+            self.status,
+            self.payload.len()
+        )
+    }
+}
+
+impl From<TransportObject> for Object {
+    fn from(object: TransportObject) -> Self {
+        let metadata = object.metadata;
+        Self {
+            track_alias: Some(metadata.track_alias),
+            group_id: metadata.group_id,
+            subgroup_id: metadata.subgroup_id,
+            object_id: metadata.object_id,
+            publisher_priority: metadata.publisher_priority,
+            status: object.status.code(),
+            payload: object.payload,
+        }
+    }
+}
+
+/// Control stream state of a [`Session`].
+struct Control {
+    queue: mpsc::Receiver<ControlMessage>,
+    codec: ControlMessageCodec,
+    setup: ClientSetup,
+    /// Encoded control messages not yet written.
+    outgoing: BytesMut,
+    /// Control stream bytes not yet decoded.
+    incoming: BytesMut,
+}
+
+/// A FETCH whose data stream did not end yet.
+struct PendingFetch {
+    track_name: String,
+    /// Taken by the FETCH_OK or FETCH_ERROR.
+    response: Option<oneshot::Sender<Result<FetchOk, Error>>>,
+    objects: mpsc::UnboundedSender<Object>,
+}
+
+struct Connection {
+    session: Arc<TransportSession<AppTransport>>,
+    control: Mutex<Control>,
+    fetches: Mutex<HashMap<u64, PendingFetch>>,
+}
+
+impl Connection {
+    fn receive_control(&self, data: &[u8]) -> Result<(), Error> {
+        let mut control = self.control.lock().unwrap();
+        let control = &mut *control;
+        control.incoming.extend_from_slice(data);
+        while let Some(mut msg) = control.codec.decode(&mut control.incoming)? {
+            self.session.check_incoming(&mut msg)?;
+            let tracks = &self.session.track_manager;
+            match msg {
+                ControlMessage::ServerSetup(server) => {
+                    server.validate(&control.setup)?;
+                    control
+                        .codec
+                        .set_version(WireVersion::negotiated(server.selected_version)?);
+                    let max = server.max_request_id()?;
+                    if max > 0 {
+                        tracks.handle_max_request_id(max)?;
+                    }
+                    self.session.activate();
+                }
+                ControlMessage::MaxRequestId(max) => {
+                    tracks.handle_max_request_id(max.request_id)?
+                }
+                ControlMessage::SubscribeOk(ok) => tracks.handle_subscribe_ok(&ok)?,
+                ControlMessage::SubscribeError(e) => {
+                    tracks.end_subscription(e.request_id, Some(Error::from(&e)));
+                }
+                ControlMessage::SubscribeDone(done) => {
+                    tracks.end_subscription(done.request_id, None);
+                }
+                ControlMessage::FetchOk(ok) => {
+                    let mut fetches = self.fetches.lock().unwrap();
+                    if let Some(response) = fetches
+                        .get_mut(&ok.request_id)
+                        .and_then(|f| f.response.take())
+                    {
+                        let _ = response.send(Ok(ok));
+                    }
+                }
+                ControlMessage::FetchError(e) => {
+                    let fetch = self.fetches.lock().unwrap().remove(&e.request_id);
+                    if let Some(response) = fetch.and_then(|f| f.response) {
+                        let _ = response.send(Err(Error::from(&e)));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    async fn receive_stream(&self, data: Vec<u8>) -> Result<(), Error> {
+        let max_buffer_size = data.len().max(1);
+        match IncomingStream::accept(&data[..], max_buffer_size).await? {
+            // Waits for the script to take objects from full subscriptions.
+            IncomingStream::Subgroup(reader) => {
+                reader
+                    .with_max_object_payload_size(self.session.max_object_payload_size())
+                    .deliver_async(&self.session.track_manager)
+                    .await?;
+            }
+            IncomingStream::Fetch(reader) => {
+                let mut reader =
+                    reader.with_max_object_payload_size(self.session.max_object_payload_size());
+                let request_id = reader.request_id().await?;
+                let Some(fetch) = self.fetches.lock().unwrap().remove(&request_id) else {
+                    return Err(Error::ProtocolViolation {
+                        reason: format!("FETCH stream of unknown request {request_id}"),
+                        context: None,
+                    });
+                };
+                let track_alias = self.session.track_manager.alias_of(&fetch.track_name);
+                while let Some(object) = reader.next().await? {
+                    let object = TransportObject::from_fetch(0, object)?;
+                    let _ = fetch.objects.send(Object {
+                        track_alias,
+                        ..Object::from(object)
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A client session whose connection is carried by the script.
+#[pyclass(frozen, module = "moqt")]
+pub struct Session {
+    connection: Arc<Connection>,
+}
+
+#[pymethods]
+impl Session {
+    /// Create a session. Its CLIENT_SETUP is the first of the
+    /// [`control_bytes`](Self::control_bytes).
+    #[new]
+    fn new() -> PyResult<Self> {
+        let (session, queue) = TransportSession::new(Arc::new(AppTransport));
+        let setup = ClientSetup::new([DRAFT_12]);
+        let mut codec = ControlMessageCodec::new();
+        let mut outgoing = BytesMut::new();
+        codec
+            .encode(ControlMessage::ClientSetup(setup.clone()), &mut outgoing)
+            .map_err(to_py)?;
+        Ok(Self {
+            connection: Arc::new(Connection {
+                session: Arc::new(session),
+                control: Mutex::new(Control {
+                    queue,
+                    codec,
+                    setup,
+                    outgoing,
+                    incoming: BytesMut::new(),
+                }),
+                fetches: Mutex::new(HashMap::new()),
+            }),
+        })
+    }
+
+    /// Bytes to write to the control stream.
+    fn control_bytes<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let mut control = self.connection.control.lock().unwrap();
+        let control = &mut *control;
+        while let Ok(msg) = control.queue.try_recv() {
+            control
+                .codec
+                .encode(msg, &mut control.outgoing)
+                .map_err(to_py)?;
+        }
+        let bytes = control.outgoing.split();
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    /// Process bytes read from the control stream. Messages may be split
+    /// across calls.
+    fn receive_control(&self, data: &[u8]) -> PyResult<()> {
+        self.connection.receive_control(data).map_err(to_py)
+    }
+
+    /// Process a whole data stream received from the peer, a subgroup
+    /// stream of a subscription or the stream of a FETCH.
+    fn receive_stream<'py>(&self, py: Python<'py>, data: Vec<u8>) -> PyResult<Bound<'py, PyAny>> {
+        let connection = self.connection.clone();
+        future_into_py(py, async move {
+            connection.receive_stream(data).await.map_err(to_py)
+        })
+    }
+
+    /// Wait for the SERVER_SETUP.
+    fn connected<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let mut state = self.connection.session.state_changes();
+        future_into_py(py, async move {
+            state
+                .wait_for(|s| *s != State::Initializing)
+                .await
+                .map_err(|_| MoqtError::new_err("session closed"))?;
+            Ok(())
+        })
+    }
+
+    /// Subscribe to `track_name` in `track_namespace`.
+    fn subscribe<'py>(
+        &self,
+        py: Python<'py>,
+        track_namespace: u64,
+        track_name: String,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let connection = self.connection.clone();
+        future_into_py(py, async move {
+            let request = SubscribeRequest::new(track_namespace, track_name);
+            let handle = connection.session.subscribe(request).await.map_err(to_py)?;
+            Ok(Subscription {
+                request_id: handle.request_id(),
+                handle: Arc::new(tokio::sync::Mutex::new(Some(handle))),
+            })
+        })
+    }
+
+    /// Announce `track_namespace`. Returns the Request ID of the ANNOUNCE.
+    fn announce<'py>(
+        &self,
+        py: Python<'py>,
+        track_namespace: Vec<String>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let connection = self.connection.clone();
+        future_into_py(py, async move {
+            connection
+                .session
+                .announce(AnnounceRequest::new(track_namespace))
+                .await
+                .map_err(to_py)
+        })
+    }
+
+    /// Fetch the objects of `track_name` in `track_namespace` from `start`
+    /// to `end`, each a `(group, object)` pair. Completes once the FETCH_OK
+    /// arrives, raising `MoqtError` on FETCH_ERROR.
+    fn fetch<'py>(
+        &self,
+        py: Python<'py>,
+        track_namespace: u64,
+        track_name: String,
+        start: (u64, u64),
+        end: (u64, u64),
+    ) -> PyResult<Bound<'py, PyAny>> {
+        // Registered before returning, so that a FETCH_OK passed to
+        // `receive_control` right after the call is matched to it.
+        let connection = self.connection.clone();
+        let request_id = connection
+            .session
+            .track_manager
+            .new_request_id()
+            .map_err(to_py)?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (response, ok) = oneshot::channel();
+        connection.fetches.lock().unwrap().insert(
+            request_id,
+            PendingFetch {
+                track_name: track_name.clone(),
+                response: Some(response),
+                objects: tx,
+            },
+        );
+        future_into_py(py, async move {
+            let session = &connection.session;
+            let fetch = FetchMessage {
+                request_id,
+                subscriber_priority: DEFAULT_SUBSCRIBER_PRIORITY,
+                group_order: 0x0,
+                fetch_type: 0x1,
+                track_namespace: Some(track_namespace),
+                track_name: Some(track_name),
+                start_location: Some(Location::new(start.0, start.1)),
+                end_location: Some(Location::new(end.0, end.1)),
+                joining_request_id: None,
+                joining_start: None,
+                parameters: Vec::new(),
+            };
+            if let Err(e) = session.send_control(ControlMessage::Fetch(fetch)).await {
+                connection.fetches.lock().unwrap().remove(&request_id);
+                return Err(to_py(e));
+            }
+            let ok = ok
+                .await
+                .map_err(|_| MoqtError::new_err("session closed"))?
+                .map_err(to_py)?;
+            Ok(FetchObjects {
+                request_id,
+                end_of_track: ok.end_of_track,
+                end_location: (ok.end_location.group, ok.end_location.object),
+                objects: Arc::new(tokio::sync::Mutex::new(rx)),
+            })
+        })
+    }
+
+    /// Stop the session's background tasks.
+    fn close<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let connection = self.connection.clone();
+        future_into_py(py, async move {
+            connection.session.shutdown().await;
+            Ok(())
+        })
+    }
+}
+
+/// A subscription, iterating over its objects with `async for`.
+#[pyclass(frozen, module = "moqt")]
+pub struct Subscription {
+    #[pyo3(get)]
+    request_id: u64,
+    handle: Arc<tokio::sync::Mutex<Option<SubscriptionHandle>>>,
+}
+
+#[pymethods]
+impl Subscription {
+    fn __aiter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    fn __anext__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let handle = self.handle.clone();
+        future_into_py(py, async move {
+            let mut handle = handle.lock().await;
+            let next = match handle.as_mut() {
+                Some(handle) => handle.recv().await,
+                None => None,
+            };
+            match next {
+                Some(Ok(object)) => Ok(Object::from(object)),
+                Some(Err(e)) => Err(to_py(e)),
+                None => Err(PyStopAsyncIteration::new_err(())),
+            }
+        })
+    }
+
+    /// End the subscription with UNSUBSCRIBE.
+    fn unsubscribe<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let handle = self.handle.clone();
+        future_into_py(py, async move {
+            match handle.lock().await.take() {
+                Some(handle) => handle.unsubscribe().await.map_err(to_py),
+                None => Ok(()),
+            }
+        })
+    }
+}
+
+/// The objects of a FETCH, iterated with `async for` until its data
+/// stream ends.
+#[pyclass(frozen, module = "moqt")]
+pub struct FetchObjects {
+    #[pyo3(get)]
+    request_id: u64,
+    /// Whether the fetched range includes the end of the track.
+    #[pyo3(get)]
+    end_of_track: bool,
+    /// Location of the last object fetched, as a `(group, object)` pair.
+    #[pyo3(get)]
+    end_location: (u64, u64),
+    objects: Arc<tokio::sync::Mutex<mpsc::UnboundedReceiver<Object>>>,
+}
+
+#[pymethods]
+impl FetchObjects {
+    fn __aiter__(slf: Py<Self>) -> Py<Self> {
+        slf
+    }
+
+    fn __anext__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let objects = self.objects.clone();
+        future_into_py(py, async move {
+            match objects.lock().await.recv().await {
+                Some(object) => Ok(object),
+                None => Err(PyStopAsyncIteration::new_err(())),
+            }
+        })
+    }
+}
+
+/// Publisher of a track, turning frames into the data streams to send.
+#[pyclass(frozen, module = "moqt")]
+pub struct Publisher {
+    state: Mutex<(TrackPublisher, Option<(u64, u64)>)>,
+}
+
+#[pymethods]
+impl Publisher {
+    #[new]
+    fn new(track_alias: u64) -> Self {
+        Self {
+            state: Mutex::new((TrackPublisher::new(track_alias), None)),
+        }
+    }
+
+    /// Publish a frame: a keyframe starts a new group, frames before the
+    /// first keyframe are dropped. Returns each resulting object with the
+    /// bytes to write for it on the subgroup stream of its group and
+    /// subgroup. The first object of a subgroup comes with the
+    /// SUBGROUP_HEADER opening a new stream.
+    fn push_frame<'py>(
+        &self,
+        py: Python<'py>,
+        keyframe: bool,
+        payload: &[u8],
+    ) -> PyResult<Vec<(Object, Bound<'py, PyBytes>)>> {
+        let mut state = self.state.lock().unwrap();
+        let (publisher, stream) = &mut *state;
+        let mut out = Vec::new();
+        for object in publisher.push_frame(keyframe, Bytes::copy_from_slice(payload)) {
+            let metadata = &object.metadata;
+            let mut buf = BytesMut::new();
+            if *stream != Some((metadata.group_id, metadata.subgroup_id)) {
+                *stream = Some((metadata.group_id, metadata.subgroup_id));
+                SubgroupHeader {
+                    track_alias: metadata.track_alias,
+                    group_id: metadata.group_id,
+                    subgroup_id: SubgroupId::Explicit(metadata.subgroup_id),
+                    publisher_priority: metadata.publisher_priority,
+                    extensions_present: false,
+                    end_of_group: false,
+                }
+                .encode(&mut buf)
+                .map_err(to_py)?;
+            }
+            object
+                .to_subgroup_object()
+                .encode(&mut buf, false)
+                .map_err(to_py)?;
+            out.push((Object::from(object), PyBytes::new(py, &buf)));
+        }
+        Ok(out)
+    }
+}
+
+#[pymodule]
+fn moqt(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("MoqtError", m.py().get_type::<MoqtError>())?;
+    m.add_class::<Session>()?;
+    m.add_class::<Subscription>()?;
+    m.add_class::<FetchObjects>()?;
+    m.add_class::<Object>()?;
+    m.add_class::<Publisher>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use pyo3::types::PyDict;
+
+    use super::*;
+    use moqt_transport::data::FetchHeader;
+    use moqt_transport::message::{ServerSetup, SubscribeDone, SubscribeError, SubscribeOk};
+
+    fn encode(msg: ControlMessage) -> Vec<u8> {
+        let mut codec = ControlMessageCodec::new();
+        codec.set_version(WireVersion::negotiated(DRAFT_12).unwrap());
+        let mut buf = BytesMut::new();
+        codec.encode(msg, &mut buf).unwrap();
+        buf.to_vec()
+    }
+
+    const SCRIPT: &str = r#"
+import asyncio
+
+async def main():
+    session = moqt.Session()
+    assert len(session.control_bytes()) > 0
+    session.receive_control(server_setup)
+    await session.connected()
+
+    subscription = await session.subscribe(0, "video")
+    assert subscription.request_id == 0
+    assert len(session.control_bytes()) > 0
+    session.receive_control(subscribe_ok)
+
+    # More objects in one stream than a subscription queues: the stream
+    # waits for the script to take them.
+    publisher = moqt.Publisher(7)
+    frames = [(True, b"key")] + [(False, b"delta")] * 40
+    stream = b"".join(
+        data for keyframe, frame in frames for _, data in publisher.push_frame(keyframe, frame)
+    )
+    received = asyncio.ensure_future(session.receive_stream(stream))
+    payloads = [(await subscription.__anext__()).payload for _ in range(len(frames))]
+    assert payloads == [frame for _, frame in frames], payloads
+    await received
+
+    # SUBSCRIBE_DONE ends the iteration.
+    session.receive_control(subscribe_done)
+    async for _ in subscription:
+        raise AssertionError("object after SUBSCRIBE_DONE")
+
+    # SUBSCRIBE_ERROR is raised.
+    rejected = await session.subscribe(0, "audio")
+    session.receive_control(subscribe_error)
+    try:
+        async for _ in rejected:
+            raise AssertionError("object of a rejected subscription")
+        raise AssertionError("SUBSCRIBE_ERROR not raised")
+    except moqt.MoqtError:
+        pass
+
+    # FETCH completes with its FETCH_OK, and its objects carry the alias
+    # of the track subscribed to.
+    fetching = session.fetch(0, "video", (0, 0), (0, 1))
+    session.receive_control(fetch_ok)
+    fetch = await fetching
+    assert fetch.request_id == 2 and fetch.end_location == (0, 1)
+    await session.receive_stream(fetch_stream)
+    objects = [obj async for obj in fetch]
+    assert [(o.track_alias, o.object_id) for o in objects] == [(7, 0), (7, 1)]
+
+    await subscription.unsubscribe()
+    await session.close()
+
+asyncio.run(main())
+"#;
+
+    #[test]
+    fn asyncio_subscription_receives_published_objects() {
+        pyo3::append_to_inittab!(moqt);
+        pyo3::prepare_freethreaded_python();
+        let server_setup = encode(ControlMessage::ServerSetup(
+            ServerSetup::accept(DRAFT_12).with_max_request_id(10),
+        ));
+        let subscribe_ok = encode(ControlMessage::SubscribeOk(SubscribeOk {
+            request_id: 0,
+            track_alias: 7,
+            expires: 0,
+            group_order: 1,
+            content_exists: false,
+            largest_location: None,
+            parameters: Vec::new(),
+        }));
+        let subscribe_done = encode(ControlMessage::SubscribeDone(SubscribeDone {
+            request_id: 0,
+            status_code: 0x2,
+            stream_count: 1,
+            reason: String::new(),
+        }));
+        let subscribe_error = encode(ControlMessage::SubscribeError(SubscribeError {
+            request_id: 1,
+            error_code: 0x4,
+            error_reason: "no such track".into(),
+        }));
+        let fetch_ok = encode(ControlMessage::FetchOk(FetchOk {
+            request_id: 2,
+            group_order: 1,
+            end_of_track: false,
+            end_location: Location::new(0, 1),
+            parameters: Vec::new(),
+        }));
+        let mut fetch_stream = BytesMut::new();
+        FetchHeader { request_id: 2 }
+            .encode(&mut fetch_stream)
+            .unwrap();
+        let mut publisher = TrackPublisher::new(7);
+        for object in publisher.push_frame(true, Bytes::from_static(b"key")) {
+            object.to_fetch_object().encode(&mut fetch_stream).unwrap();
+        }
+        for object in publisher.push_frame(false, Bytes::from_static(b"delta")) {
+            object.to_fetch_object().encode(&mut fetch_stream).unwrap();
+        }
+        Python::with_gil(|py| {
+            let globals = PyDict::new(py);
+            globals.set_item("moqt", py.import("moqt")?)?;
+            globals.set_item("server_setup", PyBytes::new(py, &server_setup))?;
+            globals.set_item("subscribe_ok", PyBytes::new(py, &subscribe_ok))?;
+            globals.set_item("subscribe_done", PyBytes::new(py, &subscribe_done))?;
+            globals.set_item("subscribe_error", PyBytes::new(py, &subscribe_error))?;
+            globals.set_item("fetch_ok", PyBytes::new(py, &fetch_ok))?;
+            globals.set_item("fetch_stream", PyBytes::new(py, &fetch_stream))?;
+            let script = CString::new(SCRIPT).unwrap();
+            py.run(&script, Some(&globals), None)
+        })
+        .unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use bytes::Bytes;
+
+use moqt_transport::{
+    auth::{AuthRequest, Authorizer, Token},
+    codec::is_namespace_prefix,
+};
+
+/// What a request asks to do with a namespace.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Action {
+    Announce,
+    Publish,
+    Subscribe,
+}
+
+impl Action {
+    fn of(request: &AuthRequest<'_>) -> Self {
+        match request {
+            AuthRequest::Announce(_) => Action::Announce,
+            AuthRequest::Publish(_) => Action::Publish,
+            AuthRequest::Subscribe(_) => Action::Subscribe,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Decision {
+    Allow,
+    Deny,
+}
+
+/// Allows or denies an action on every namespace starting with a prefix,
+/// optionally only for requests presenting a given token.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AclRule {
+    pub action: Action,
+    pub prefix: Vec<String>,
+    pub token: Option<Bytes>,
+    pub decision: Decision,
+}
+
+impl AclRule {
+    pub fn allow(action: Action, prefix: Vec<String>) -> Self {
+        Self {
+            action,
+            prefix,
+            token: None,
+            decision: Decision::Allow,
+        }
+    }
+
+    pub fn deny(action: Action, prefix: Vec<String>) -> Self {
+        Self {
+            decision: Decision::Deny,
+            ..Self::allow(action, prefix)
+        }
+    }
+
+    /// Only apply the rule to requests carrying a token with this value.
+    pub fn with_token(mut self, token: impl Into<Bytes>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    fn matches(&self, action: Action, namespace: &[String], tokens: &[Token]) -> bool {
+        self.action == action
+            && is_namespace_prefix(&self.prefix, namespace)
+            && self
+                .token
+                .as_ref()
+                .is_none_or(|t| tokens.iter().any(|token| token.value == *t))
+    }
+}
+
+/// Rules evaluated in order, the first matching rule deciding. Requests no
+/// rule matches get the default decision, which allows everything unless
+/// configured otherwise.
+///
+/// SUBSCRIBE and PUBLISH name their namespace by a numeric ID, matched as
+/// the tuple registered for it with [`with_namespace`](Self::with_namespace).
+/// Requests for an unregistered ID are denied whatever the default.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AclConfig {
+    pub rules: Vec<AclRule>,
+    pub default: Decision,
+    pub namespaces: HashMap<u64, Vec<String>>,
+}
+
+impl Default for AclConfig {
+    fn default() -> Self {
+        Self {
+            rules: Vec::new(),
+            default: Decision::Allow,
+            namespaces: HashMap::new(),
+        }
+    }
+}
+
+impl AclConfig {
+    pub fn with_rule(mut self, rule: AclRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn with_default(mut self, default: Decision) -> Self {
+        self.default = default;
+        self
+    }
+
+    /// Register the namespace tuple a numeric namespace ID stands for.
+    pub fn with_namespace(mut self, id: u64, namespace: Vec<String>) -> Self {
+        self.namespaces.insert(id, namespace);
+        self
+    }
+
+    /// The namespace tuple `request` is about, `None` if it names an
+    /// unregistered namespace ID.
+    pub fn resolve<'a>(&'a self, request: &'a AuthRequest<'_>) -> Option<&'a [String]> {
+        match request.namespace_id() {
+            Some(id) => self.namespaces.get(&id).map(Vec::as_slice),
+            None => request.namespace(),
+        }
+    }
+
+    /// Decide on `request`, denying it if its namespace cannot be resolved.
+    pub fn decide_request(&self, request: &AuthRequest<'_>, tokens: &[Token]) -> Decision {
+        match self.resolve(request) {
+            Some(namespace) => self.decide(Action::of(request), namespace, tokens),
+            None => Decision::Deny,
+        }
+    }
+
+    pub fn decide(&self, action: Action, namespace: &[String], tokens: &[Token]) -> Decision {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(action, namespace, tokens))
+            .map_or(self.default, |rule| rule.decision)
+    }
+}
+
+/// Namespace access control of a relay, installed on each session with
+/// [`Session::set_authorizer`](moqt_transport::session::Session::set_authorizer).
+///
+/// Clones share the configuration, so a [`reload`](Self::reload) applies
+/// to every session from its next request on.
+#[derive(Clone, Default)]
+pub struct NamespaceAcl {
+    config: Arc<RwLock<Arc<AclConfig>>>,
+}
+
+impl NamespaceAcl {
+    pub fn new(config: AclConfig) -> Self {
+        Self {
+            config: Arc::new(RwLock::new(Arc::new(config))),
+        }
+    }
+
+    /// Replace the configuration. Requests already authorized are not
+    /// revisited.
+    pub fn reload(&self, config: AclConfig) {
+        *self.config.write().unwrap() = Arc::new(config);
+    }
+
+    pub fn config(&self) -> Arc<AclConfig> {
+        self.config.read().unwrap().clone()
+    }
+}
+
+impl Authorizer for NamespaceAcl {
+    fn authorize(&self, request: &AuthRequest<'_>, tokens: &[Token]) -> bool {
+        self.config().decide_request(request, tokens) == Decision::Allow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use moqt_transport::{
+        message::{Announce, Subscribe},
+        model::Filter,
+    };
+
+    use super::*;
+
+    fn ns(fields: &[&str]) -> Vec<String> {
+        fields.iter().map(|f| f.to_string()).collect()
+    }
+
+    fn token(value: &'static [u8]) -> Token {
+        Token {
+            token_type: 0,
+            value: Bytes::from_static(value),
+        }
+    }
+
+    fn announce(namespace: &[&str]) -> Announce {
+        Announce {
+            request_id: 0,
+            track_namespace: ns(namespace),
+            parameters: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_decides() {
+        let config = AclConfig::default()
+            .with_default(Decision::Deny)
+            .with_rule(AclRule::deny(Action::Announce, ns(&["live", "private"])))
+            .with_rule(AclRule::allow(Action::Announce, ns(&["live"])).with_token(&b"alice"[..]))
+            .with_rule(AclRule::allow(Action::Subscribe, ns(&["live"])));
+
+        let alice = [token(b"alice")];
+        assert_eq!(
+            config.decide(Action::Announce, &ns(&["live", "cam"]), &alice),
+            Decision::Allow
+        );
+        assert_eq!(
+            config.decide(Action::Announce, &ns(&["live", "cam"]), &[]),
+            Decision::Deny
+        );
+        assert_eq!(
+            config.decide(Action::Announce, &ns(&["live", "private", "x"]), &alice),
+            Decision::Deny
+        );
+        assert_eq!(
+            config.decide(Action::Subscribe, &ns(&["live", "cam"]), &[]),
+            Decision::Allow
+        );
+        assert_eq!(
+            config.decide(Action::Subscribe, &ns(&["vod"]), &[]),
+            Decision::Deny
+        );
+    }
+
+    #[test]
+    fn reload_applies_to_clones() {
+        let acl = NamespaceAcl::default();
+        let installed = acl.clone();
+        let request = announce(&["live", "cam"]);
+        assert!(installed.authorize(&AuthRequest::Announce(&request), &[]));
+
+        acl.reload(AclConfig::default().with_rule(AclRule::deny(Action::Announce, ns(&["live"]))));
+        assert!(!installed.authorize(&AuthRequest::Announce(&request), &[]));
+        assert!(installed.authorize(&AuthRequest::Announce(&announce(&["vod"])), &[]));
+    }
+
+    #[test]
+    fn subscribe_matches_registered_namespace_tuple() {
+        let subscribe = Subscribe::new(0, 7, "video", Filter::LargestObject);
+        let acl = NamespaceAcl::new(
+            AclConfig::default()
+                .with_namespace(7, ns(&["live", "sports", "football"]))
+                .with_namespace(8, ns(&["vod"]))
+                .with_rule(AclRule::deny(Action::Subscribe, ns(&["live", "sports"]))),
+        );
+        assert!(!acl.authorize(&AuthRequest::Subscribe(&subscribe), &[]));
+        let mut vod = subscribe.clone();
+        vod.track_namespace = 8;
+        assert!(acl.authorize(&AuthRequest::Subscribe(&vod), &[]));
+
+        // The decimal form of the ID no longer matches a rule.
+        let acl = NamespaceAcl::new(
+            AclConfig::default()
+                .with_default(Decision::Deny)
+                .with_rule(AclRule::allow(Action::Subscribe, ns(&["7"]))),
+        );
+        assert!(!acl.authorize(&AuthRequest::Subscribe(&subscribe), &[]));
+    }
+
+    #[test]
+    fn unresolved_namespace_is_denied() {
+        let subscribe = Subscribe::new(0, 9, "video", Filter::LargestObject);
+        let acl = NamespaceAcl::default();
+        assert_eq!(acl.config().default, Decision::Allow);
+        assert!(!acl.authorize(&AuthRequest::Subscribe(&subscribe), &[]));
+        assert!(acl.authorize(&AuthRequest::Announce(&announce(&["live"])), &[]));
+    }
+}
@@ -0,0 +1,219 @@
+use std::sync::Arc;
+
+use tokio::time::Instant;
+
+use moqt_transport::track::FullTrackName;
+
+use crate::{
+    acl::AclConfig,
+    cache::CacheOccupancy,
+    relay::{RelayState, SessionId},
+};
+
+#[cfg(feature = "admin-http")]
+pub mod http;
+
+/// A connected session as seen by the admin interface.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SessionInfo {
+    pub id: SessionId,
+    pub remote: String,
+    pub subscriptions: Vec<FullTrackName>,
+    pub namespaces: Vec<Vec<String>>,
+}
+
+/// A namespace announced to the relay and the session announcing it.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct NamespaceInfo {
+    pub namespace: Vec<String>,
+    pub session: SessionId,
+}
+
+/// Objects and bytes forwarded for a track since it was first seen, and
+/// duplicate objects dropped.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TrackThroughput {
+    pub track: FullTrackName,
+    pub objects: u64,
+    pub bytes: u64,
+    pub duplicates: u64,
+    pub bytes_per_second: f64,
+}
+
+/// Live introspection and control of a [`Relay`](crate::Relay).
+#[derive(Clone)]
+pub struct Admin {
+    state: Arc<RelayState>,
+}
+
+impl Admin {
+    pub(crate) fn new(state: Arc<RelayState>) -> Self {
+        Self { state }
+    }
+
+    /// Connected sessions ordered by id.
+    pub fn sessions(&self) -> Vec<SessionInfo> {
+        let sessions = self.state.sessions.lock().unwrap();
+        sessions
+            .iter()
+            .map(|(&id, entry)| SessionInfo {
+                id,
+                remote: entry.remote.clone(),
+                subscriptions: entry.subscriptions.clone(),
+                namespaces: entry.namespaces.clone(),
+            })
+            .collect()
+    }
+
+    /// Every active subscription as (session, track) pairs.
+    pub fn subscriptions(&self) -> Vec<(SessionId, FullTrackName)> {
+        let sessions = self.state.sessions.lock().unwrap();
+        sessions
+            .iter()
+            .flat_map(|(&id, e)| e.subscriptions.iter().map(move |t| (id, t.clone())))
+            .collect()
+    }
+
+    /// Announced namespaces ordered by namespace.
+    pub fn namespaces(&self) -> Vec<NamespaceInfo> {
+        let sessions = self.state.sessions.lock().unwrap();
+        let mut namespaces: Vec<_> = sessions
+            .iter()
+            .flat_map(|(&session, e)| {
+                e.namespaces.iter().map(move |n| NamespaceInfo {
+                    namespace: n.clone(),
+                    session,
+                })
+            })
+            .collect();
+        namespaces.sort_by(|a, b| a.namespace.cmp(&b.namespace));
+        namespaces
+    }
+
+    pub fn cache(&self) -> Vec<CacheOccupancy> {
+        self.state.cache.occupancy()
+    }
+
+    /// Per-track throughput ordered by track name.
+    pub fn throughput(&self) -> Vec<TrackThroughput> {
+        let now = Instant::now();
+        let stats = self.state.stats.lock().unwrap();
+        let mut throughput: Vec<_> = stats
+            .iter()
+            .map(|(track, s)| {
+                let elapsed = now.duration_since(s.since).as_secs_f64();
+                TrackThroughput {
+                    track: track.clone(),
+                    objects: s.objects,
+                    bytes: s.bytes,
+                    duplicates: s.duplicates,
+                    bytes_per_second: if elapsed > 0.0 {
+                        s.bytes as f64 / elapsed
+                    } else {
+                        0.0
+                    },
+                }
+            })
+            .collect();
+        throughput.sort_by(|a, b| a.track.cmp(&b.track));
+        throughput
+    }
+
+    /// Ask a session to disconnect. Returns `false` for unknown sessions.
+    pub fn kick_session(&self, id: SessionId) -> bool {
+        match self.state.sessions.lock().unwrap().get(&id) {
+            Some(entry) => {
+                entry.kick.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drop the cached objects of a track. Returns `false` if nothing was
+    /// cached for it.
+    pub fn purge_track(&self, track: &FullTrackName) -> bool {
+        self.state.cache.purge(track)
+    }
+
+    /// Replace the namespace access control rules of the relay.
+    pub fn reload_acl(&self, config: AclConfig) {
+        self.state.acl.reload(config);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Relay;
+    use moqt_transport::{mock::ObjectBuilder, track::Object};
+    use std::time::Duration;
+
+    fn object(object_id: u64, len: usize) -> Object {
+        ObjectBuilder::new(0, object_id)
+            .with_payload(vec![0; len])
+            .build()
+    }
+
+    #[test]
+    fn lists_sessions_and_namespaces() {
+        let relay = Relay::new();
+        let admin = relay.admin();
+        let a = relay.register_session("10.0.0.1:4443");
+        let b = relay.register_session("10.0.0.2:4443");
+        a.add_subscription("video".into());
+        b.add_namespace(vec!["example.com".into(), "live".into()]);
+
+        let sessions = admin.sessions();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].remote, "10.0.0.1:4443");
+        assert_eq!(admin.subscriptions(), vec![(a.id(), "video".to_string())]);
+        assert_eq!(admin.namespaces()[0].session, b.id());
+
+        drop(a);
+        assert_eq!(admin.sessions().len(), 1);
+        assert!(admin.subscriptions().is_empty());
+    }
+
+    #[test]
+    fn kick_signals_session() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let relay = Relay::new();
+            let session = relay.register_session("peer");
+            assert!(relay.admin().kick_session(session.id()));
+            session.kicked().await;
+            assert!(session.is_kicked());
+            assert!(!relay.admin().kick_session(session.id() + 1));
+        });
+    }
+
+    #[test]
+    fn throughput_and_purge() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .start_paused(true)
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let relay = Relay::new();
+            let track = "video".to_string();
+            relay.record_object(&track, object(0, 1000));
+            tokio::time::advance(Duration::from_secs(2)).await;
+            assert!(relay.record_object(&track, object(1, 1000)));
+            assert!(!relay.record_object(&track, object(1, 1000)));
+
+            let admin = relay.admin();
+            let throughput = admin.throughput();
+            assert_eq!(throughput[0].objects, 2);
+            assert_eq!(throughput[0].bytes, 2000);
+            assert_eq!(throughput[0].duplicates, 1);
+            assert!((throughput[0].bytes_per_second - 1000.0).abs() < 1.0);
+            assert_eq!(admin.cache()[0].bytes, 2000);
+
+            assert!(admin.purge_track(&track));
+            assert!(admin.cache().is_empty());
+        });
+    }
+}
@@ -0,0 +1,405 @@
+//! Minimal HTTP/1.1 front end for the [`Admin`] interface.
+//!
+//! | Method   | Path                  | Action                      |
+//! |----------|-----------------------|-----------------------------|
+//! | `GET`    | `/sessions`           | list sessions               |
+//! | `GET`    | `/namespaces`         | list announced namespaces   |
+//! | `GET`    | `/cache`              | cache occupancy per track   |
+//! | `GET`    | `/throughput`         | throughput per track        |
+//! | `POST`   | `/sessions/{id}/kick` | kick a session              |
+//! | `DELETE` | `/cache/{track}`      | purge a cached track        |
+//!
+//! Responses are JSON. Every connection serves a single request. The track
+//! of `DELETE /cache/{track}` is percent-decoded and may contain `/`.
+//!
+//! Kicking and purging require a bearer token, see
+//! [`AdminServer::with_token`]; without one only the read-only endpoints
+//! are served.
+
+use std::fmt::Write as _;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+
+use super::Admin;
+
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+/// HTTP server for an [`Admin`] interface.
+///
+/// ```ignore
+/// let listener = TcpListener::bind("127.0.0.1:8080").await?;
+/// AdminServer::new(relay.admin())
+///     .with_token(std::env::var("ADMIN_TOKEN")?)
+///     .serve(listener)
+///     .await?;
+/// ```
+pub struct AdminServer {
+    admin: Admin,
+    token: Option<String>,
+    timeout: Duration,
+    max_connections: usize,
+}
+
+impl AdminServer {
+    /// Server without a token, giving each connection 5 seconds and
+    /// handling up to 64 connections at once.
+    pub fn new(admin: Admin) -> Self {
+        Self {
+            admin,
+            token: None,
+            timeout: Duration::from_secs(5),
+            max_connections: 64,
+        }
+    }
+
+    /// Require every request to carry `Authorization: Bearer {token}`,
+    /// enabling the kick and purge endpoints. Requests without it get
+    /// `401 Unauthorized`.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// How long a connection may take to send its request and receive the
+    /// response before it is dropped.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Connections handled at once. Further connections wait in the
+    /// listener's backlog.
+    pub fn with_max_connections(mut self, max: usize) -> Self {
+        self.max_connections = max.clamp(1, Semaphore::MAX_PERMITS);
+        self
+    }
+
+    /// Serve on `listener` until accepting fails.
+    pub async fn serve(self, listener: TcpListener) -> std::io::Result<()> {
+        let connections = Arc::new(Semaphore::new(self.max_connections));
+        let server = Arc::new(self);
+        loop {
+            let permit = connections.clone().acquire_owned().await;
+            let (stream, _) = listener.accept().await?;
+            let server = server.clone();
+            tokio::spawn(async move {
+                let _permit = permit;
+                let _ = tokio::time::timeout(server.timeout, server.handle(stream)).await;
+            });
+        }
+    }
+
+    async fn handle(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        let Some(head) = read_head(&mut stream).await? else {
+            return respond(&mut stream, "400 Bad Request", "").await;
+        };
+        let head = String::from_utf8_lossy(&head);
+        let mut lines = head.lines();
+        let mut request_line = lines.next().unwrap_or("").split(' ');
+        let method = request_line.next().unwrap_or("");
+        let path = request_line.next().unwrap_or("");
+        let bearer = lines.find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            let value = value.trim();
+            if !name.trim().eq_ignore_ascii_case("authorization") {
+                return None;
+            }
+            let (scheme, token) = value.split_once(' ')?;
+            scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+        });
+
+        let authorized = match (&self.token, bearer) {
+            (Some(expected), Some(token)) => {
+                constant_time_eq(expected.as_bytes(), token.as_bytes())
+            }
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        if !authorized {
+            return respond(&mut stream, "401 Unauthorized", "").await;
+        }
+        if self.token.is_none() && method != "GET" {
+            return respond(&mut stream, "403 Forbidden", "").await;
+        }
+        let (status, body) = route(&self.admin, method, path);
+        respond(&mut stream, status, &body).await
+    }
+}
+
+/// Serve the read-only endpoints of `admin` on `listener` with the
+/// [`AdminServer`] defaults until accepting fails.
+pub async fn serve(admin: Admin, listener: TcpListener) -> std::io::Result<()> {
+    AdminServer::new(admin).serve(listener).await
+}
+
+/// Read the request line and headers, `None` if they are cut short or too
+/// long.
+async fn read_head(stream: &mut TcpStream) -> std::io::Result<Option<Vec<u8>>> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 || head.len() + n > MAX_REQUEST_HEAD {
+            return Ok(None);
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    Ok(Some(head))
+}
+
+/// Compare without returning early, so a token is not guessed from the
+/// response time.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Decode the `%XX` escapes of a path segment, `None` if one is invalid or
+/// the result is not UTF-8.
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut input = s.bytes();
+    while let Some(b) = input.next() {
+        if b == b'%' {
+            let hex = [input.next()?, input.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(b);
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn route(admin: &Admin, method: &str, path: &str) -> (&'static str, String) {
+    const OK: &str = "200 OK";
+    const NO_CONTENT: &str = "204 No Content";
+    const NOT_FOUND: &str = "404 Not Found";
+
+    const BAD_REQUEST: &str = "400 Bad Request";
+
+    if let ("DELETE", Some(track)) = (method, path.strip_prefix("/cache/")) {
+        return match percent_decode(track) {
+            Some(track) if admin.purge_track(&track) => (NO_CONTENT, String::new()),
+            Some(_) => (NOT_FOUND, String::new()),
+            None => (BAD_REQUEST, String::new()),
+        };
+    }
+    let segments: Vec<_> = path.trim_matches('/').split('/').collect();
+    match (method, segments.as_slice()) {
+        ("GET", ["sessions"]) => (OK, sessions_json(admin)),
+        ("GET", ["namespaces"]) => (OK, namespaces_json(admin)),
+        ("GET", ["cache"]) => (OK, cache_json(admin)),
+        ("GET", ["throughput"]) => (OK, throughput_json(admin)),
+        ("POST", ["sessions", id, "kick"]) => match id.parse() {
+            Ok(id) if admin.kick_session(id) => (NO_CONTENT, String::new()),
+            _ => (NOT_FOUND, String::new()),
+        },
+        _ => (NOT_FOUND, String::new()),
+    }
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, body: &str) -> std::io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn array<T>(items: &[T], f: impl Fn(&T) -> String) -> String {
+    let items: Vec<_> = items.iter().map(f).collect();
+    format!("[{}]", items.join(","))
+}
+
+fn namespace(ns: &[String]) -> String {
+    array(ns, |field| string(field))
+}
+
+fn sessions_json(admin: &Admin) -> String {
+    array(&admin.sessions(), |s| {
+        format!(
+            r#"{{"id":{},"remote":{},"subscriptions":{},"namespaces":{}}}"#,
+            s.id,
+            string(&s.remote),
+            array(&s.subscriptions, |t| string(t)),
+            array(&s.namespaces, |n| namespace(n)),
+        )
+    })
+}
+
+fn namespaces_json(admin: &Admin) -> String {
+    array(&admin.namespaces(), |n| {
+        format!(
+            r#"{{"namespace":{},"session":{}}}"#,
+            namespace(&n.namespace),
+            n.session
+        )
+    })
+}
+
+fn cache_json(admin: &Admin) -> String {
+    array(&admin.cache(), |c| {
+        format!(
+            r#"{{"track":{},"objects":{},"bytes":{}}}"#,
+            string(&c.track),
+            c.objects,
+            c.bytes
+        )
+    })
+}
+
+fn throughput_json(admin: &Admin) -> String {
+    array(&admin.throughput(), |t| {
+        format!(
+            r#"{{"track":{},"objects":{},"bytes":{},"duplicates":{},"bytes_per_second":{:.1}}}"#,
+            string(&t.track),
+            t.objects,
+            t.bytes,
+            t.duplicates,
+            t.bytes_per_second
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use moqt_transport::track::TrackPublisher;
+
+    use super::*;
+    use crate::Relay;
+
+    async fn request(addr: std::net::SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[test]
+    fn serves_sessions_and_kicks() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let relay = Relay::new();
+            let session = relay.register_session("peer \"1\"");
+            session.add_namespace(vec!["example.com".into()]);
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server = AdminServer::new(relay.admin()).with_token("secret");
+            tokio::spawn(server.serve(listener));
+
+            let response = request(addr, "GET /sessions HTTP/1.1\r\n\r\n").await;
+            assert!(response.starts_with("HTTP/1.1 401"));
+            let response = request(
+                addr,
+                "GET /sessions HTTP/1.1\r\nAuthorization: Bearer secret\r\n\r\n",
+            )
+            .await;
+            assert!(response.starts_with("HTTP/1.1 200 OK"));
+            assert!(response.ends_with(&format!(
+                r#"[{{"id":{},"remote":"peer \"1\"","subscriptions":[],"namespaces":[["example.com"]]}}]"#,
+                session.id()
+            )));
+
+            let kick = format!(
+                "POST /sessions/{}/kick HTTP/1.1\r\nAuthorization: Bearer wrong\r\n\r\n",
+                session.id()
+            );
+            assert!(request(addr, &kick).await.starts_with("HTTP/1.1 401"));
+            assert!(!session.is_kicked());
+            let kick = format!(
+                "POST /sessions/{}/kick HTTP/1.1\r\nauthorization: bearer secret\r\n\r\n",
+                session.id()
+            );
+            assert!(request(addr, &kick).await.starts_with("HTTP/1.1 204"));
+            assert!(session.is_kicked());
+
+            let purge = "DELETE /cache/video HTTP/1.1\r\nAuthorization: Bearer secret\r\n\r\n";
+            assert!(request(addr, purge).await.starts_with("HTTP/1.1 404"));
+        });
+    }
+
+    #[test]
+    fn purges_track_names_with_slashes() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let relay = Relay::new();
+            let track = "live/cam 1".to_string();
+            let frame = bytes::Bytes::from_static(b"frame");
+            for object in TrackPublisher::new(1).push_frame(true, frame) {
+                relay.record_object(&track, object);
+            }
+
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(
+                AdminServer::new(relay.admin())
+                    .with_token("t")
+                    .serve(listener),
+            );
+
+            let auth = "Authorization: Bearer t\r\n\r\n";
+            let bad = request(addr, &format!("DELETE /cache/live%2 HTTP/1.1\r\n{auth}")).await;
+            assert!(bad.starts_with("HTTP/1.1 400"));
+            let purge = format!("DELETE /cache/live%2Fcam%201 HTTP/1.1\r\n{auth}");
+            assert!(request(addr, &purge).await.starts_with("HTTP/1.1 204"));
+            assert!(relay.cache().occupancy().is_empty());
+        });
+    }
+
+    #[test]
+    fn mutations_need_a_token_and_slow_peers_are_dropped() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let relay = Relay::new();
+            let session = relay.register_session("peer");
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server = AdminServer::new(relay.admin())
+                .with_timeout(Duration::from_millis(50))
+                .with_max_connections(1);
+            tokio::spawn(server.serve(listener));
+
+            // A peer sending nothing holds the only connection slot until
+            // it times out.
+            let mut idle = TcpStream::connect(addr).await.unwrap();
+            let kick = format!("POST /sessions/{}/kick HTTP/1.1\r\n\r\n", session.id());
+            assert!(request(addr, &kick).await.starts_with("HTTP/1.1 403"));
+            assert!(!session.is_kicked());
+            let mut rest = Vec::new();
+            assert_eq!(idle.read_to_end(&mut rest).await.unwrap(), 0);
+        });
+    }
+}
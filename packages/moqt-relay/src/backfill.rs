@@ -0,0 +1,258 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+
+use moqt_transport::{
+    error::Error,
+    model::Location,
+    track::{FullTrackName, Object, ObjectStream},
+};
+
+use crate::Relay;
+
+/// Capacity of the stream returned by [`Backfill::fetch`].
+const FETCH_QUEUE_CAPACITY: usize = 64;
+
+/// Where the relay fetches objects it does not have cached.
+#[async_trait]
+pub trait Upstream: Send + Sync {
+    /// Issue an upstream FETCH for `start..=end` of the track.
+    async fn fetch(
+        &self,
+        track: &FullTrackName,
+        start: Location,
+        end: Location,
+    ) -> Result<ObjectStream, Error>;
+
+    /// Largest Location of the track upstream, e.g. from TRACK_STATUS, or
+    /// `None` if it is not known.
+    async fn largest(&self, _track: &FullTrackName) -> Result<Option<Location>, Error> {
+        Ok(None)
+    }
+}
+
+/// Serves downstream FETCHes from the relay cache, fetching the sub-ranges
+/// the cache does not hold from upstream.
+pub struct Backfill {
+    relay: Relay,
+    upstream: Arc<dyn Upstream>,
+}
+
+fn key(object: &Object) -> Location {
+    object.metadata.location()
+}
+
+impl Backfill {
+    pub fn new(relay: Relay, upstream: Arc<dyn Upstream>) -> Self {
+        Self { relay, upstream }
+    }
+
+    /// Objects of `start..=end` in ascending order. Cached objects are
+    /// interleaved with upstream ones; objects received from upstream are
+    /// cached as they pass through and a sub-range is marked complete once
+    /// its upstream FETCH finished.
+    pub fn fetch(&self, track: FullTrackName, start: Location, end: Location) -> ObjectStream {
+        let (tx, stream) = ObjectStream::channel(FETCH_QUEUE_CAPACITY);
+        let relay = self.relay.clone();
+        let upstream = self.upstream.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve(&relay, upstream.as_ref(), &track, start, end, &tx).await {
+                let _ = tx.send(Err(e)).await;
+            }
+        });
+        stream
+    }
+}
+
+type Sender = mpsc::Sender<Result<Object, Error>>;
+
+async fn send(tx: &Sender, object: Object) -> Result<(), Error> {
+    tx.send(Ok(object)).await.map_err(|_| Error::SessionClosed)
+}
+
+/// Send cached objects located before `limit`.
+async fn send_cached_before(
+    tx: &Sender,
+    cached: &mut VecDeque<Object>,
+    limit: &Location,
+) -> Result<(), Error> {
+    while cached.front().is_some_and(|o| key(o).is_before(limit)) {
+        send(tx, cached.pop_front().unwrap()).await?;
+    }
+    Ok(())
+}
+
+async fn serve(
+    relay: &Relay,
+    upstream: &dyn Upstream,
+    track: &FullTrackName,
+    start: Location,
+    end: Location,
+    tx: &Sender,
+) -> Result<(), Error> {
+    let cache = relay.cache();
+    let mut cached: VecDeque<_> = cache.range(track, &start, &end).into();
+
+    for (gap_start, gap_end) in cache.missing(track, &start, &end) {
+        send_cached_before(tx, &mut cached, &gap_start).await?;
+
+        let mut objects = upstream
+            .fetch(track, gap_start.clone(), gap_end.clone())
+            .await?;
+        while let Some(object) = objects.recv().await {
+            let object = object?;
+            let key = key(&object);
+            if !key.is_within(&gap_start, &gap_end) {
+                continue;
+            }
+            send_cached_before(tx, &mut cached, &key).await?;
+            if cached.front().is_some_and(|o| self::key(o) == key) {
+                cached.pop_front();
+            }
+            cache.insert(track, object.clone());
+            send(tx, object).await?;
+        }
+        cache.mark_complete(track, &gap_start, &gap_end);
+    }
+
+    while let Some(object) = cached.pop_front() {
+        send(tx, object).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use moqt_transport::{
+        message::{ControlMessage, FetchOk},
+        mock::ObjectBuilder,
+        publish::max_cache_duration_parameter,
+    };
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    fn object(group_id: u64, object_id: u64) -> Object {
+        ObjectBuilder::new(group_id, object_id)
+            .with_payload(Bytes::from_static(b"data"))
+            .build()
+    }
+
+    fn loc(group: u64, object: u64) -> Location {
+        Location { group, object }
+    }
+
+    /// Upstream holding groups 0..4 with objects 0..3 each.
+    #[derive(Default)]
+    struct MockUpstream {
+        fetches: Mutex<Vec<(Location, Location)>>,
+    }
+
+    #[async_trait]
+    impl Upstream for MockUpstream {
+        async fn fetch(
+            &self,
+            _track: &FullTrackName,
+            start: Location,
+            end: Location,
+        ) -> Result<ObjectStream, Error> {
+            self.fetches
+                .lock()
+                .unwrap()
+                .push((start.clone(), end.clone()));
+            let (tx, stream) = ObjectStream::channel(64);
+            for g in 0..4 {
+                for o in 0..3 {
+                    if Location::new(g, o).is_within(&start, &end) {
+                        tx.try_send(Ok(object(g, o))).unwrap();
+                    }
+                }
+            }
+            Ok(stream)
+        }
+    }
+
+    async fn collect(mut stream: ObjectStream) -> Vec<(u64, u64)> {
+        let mut out = Vec::new();
+        while let Some(object) = stream.recv().await {
+            let loc = key(&object.unwrap());
+            out.push((loc.group, loc.object));
+        }
+        out
+    }
+
+    #[test]
+    fn cache_miss_is_backfilled_in_order() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let relay = Relay::new();
+            let track = "video".to_string();
+            // Group 1 is fully cached, group 2 only partially from live data.
+            for o in 0..3 {
+                relay.cache().insert(&track, object(1, o));
+            }
+            relay
+                .cache()
+                .mark_complete(&track, &loc(1, 0), &loc(1, u64::MAX));
+            relay.cache().insert(&track, object(2, 1));
+
+            let upstream = Arc::new(MockUpstream::default());
+            let backfill = Backfill::new(relay.clone(), upstream.clone());
+
+            let objects = collect(backfill.fetch(track.clone(), loc(0, 0), loc(2, 2))).await;
+            let expected: Vec<_> = (0..3).flat_map(|g| (0..3).map(move |o| (g, o))).collect();
+            assert_eq!(objects, expected);
+            assert_eq!(
+                *upstream.fetches.lock().unwrap(),
+                vec![(loc(0, 0), loc(0, u64::MAX)), (loc(2, 0), loc(2, 2))]
+            );
+
+            // The range is now served from cache alone.
+            let objects = collect(backfill.fetch(track.clone(), loc(0, 1), loc(2, 0))).await;
+            assert_eq!(objects.first(), Some(&(0, 1)));
+            assert_eq!(objects.last(), Some(&(2, 0)));
+            assert_eq!(upstream.fetches.lock().unwrap().len(), 2);
+            assert_eq!(relay.cache().occupancy()[0].objects, 9);
+        });
+    }
+
+    #[test]
+    fn stale_objects_are_fetched_upstream() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let relay = Relay::new();
+            let track = "video".to_string();
+            for o in 0..3 {
+                relay.cache().insert(&track, object(1, o));
+            }
+            relay
+                .cache()
+                .mark_complete(&track, &loc(1, 0), &loc(1, u64::MAX));
+            // Upstream allows no caching at all.
+            let ok = ControlMessage::FetchOk(FetchOk {
+                request_id: 0,
+                group_order: 1,
+                end_of_track: false,
+                end_location: loc(1, 2),
+                parameters: vec![max_cache_duration_parameter(Duration::ZERO).unwrap()],
+            });
+            relay.record_response(&track, &ok).unwrap();
+
+            let upstream = Arc::new(MockUpstream::default());
+            let backfill = Backfill::new(relay.clone(), upstream.clone());
+            let objects = collect(backfill.fetch(track.clone(), loc(1, 0), loc(1, 2))).await;
+            assert_eq!(objects, [(1, 0), (1, 1), (1, 2)]);
+            assert_eq!(
+                *upstream.fetches.lock().unwrap(),
+                vec![(loc(1, 0), loc(1, 2))]
+            );
+        });
+    }
+}
@@ -0,0 +1,634 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use moqt_transport::{
+    model::Location,
+    retention::RetentionPolicy,
+    track::{FullTrackName, Object, ObjectKey},
+};
+
+#[derive(Default)]
+struct CachedTrack {
+    /// Keyed by [`ObjectKey::untracked`], as the track's objects may come
+    /// from several upstreams under different aliases.
+    objects: BTreeMap<ObjectKey, Object>,
+    /// When each cached object arrived, oldest first, recorded only while
+    /// `max_age` is set.
+    arrivals: VecDeque<(Instant, ObjectKey)>,
+    bytes: usize,
+    /// Sorted, disjoint inclusive ranges known to be fully cached.
+    complete: Vec<(Location, Location)>,
+    /// MAX_CACHE_DURATION announced upstream.
+    max_age: Option<Duration>,
+    /// Retention policy set for the track, see
+    /// [`TrackCache::set_retention_policy`].
+    retention: Option<RetentionPolicy>,
+    /// When an object was last added to each cached group, recorded only
+    /// while `retention` is set.
+    updated: BTreeMap<u64, Instant>,
+}
+
+impl CachedTrack {
+    /// Evict the objects cached for longer than the track's
+    /// MAX_CACHE_DURATION at `now`. The ranges they belonged to are no
+    /// longer complete, so they are fetched upstream again rather than
+    /// served stale. The groups the retention policy no longer allows are
+    /// evicted as well.
+    /// Returns the payload bytes freed.
+    fn expire(&mut self, now: Instant) -> usize {
+        let mut freed = 0;
+        if let Some(max_age) = self.max_age {
+            while let Some(&(at, key)) = self.arrivals.front() {
+                if now.saturating_duration_since(at) < max_age {
+                    break;
+                }
+                self.arrivals.pop_front();
+                freed += self.remove(&key);
+            }
+        }
+        freed + self.retain(now)
+    }
+
+    /// Evict the oldest groups the retention policy no longer allows at
+    /// `now`. Returns the payload bytes freed.
+    fn retain(&mut self, now: Instant) -> usize {
+        let Some(policy) = self.retention else {
+            return 0;
+        };
+        let mut freed = 0;
+        while let Some((&group, &updated)) = self.updated.first_key_value() {
+            let expired = match policy {
+                RetentionPolicy::None => true,
+                RetentionPolicy::Groups(n) => self.updated.len() > n,
+                RetentionPolicy::Age(age) => now.saturating_duration_since(updated) > age,
+            };
+            if !expired {
+                break;
+            }
+            freed += self.remove_group(group);
+        }
+        freed
+    }
+
+    /// Remove every object of `group`. Returns the payload bytes freed.
+    fn remove_group(&mut self, group: u64) -> usize {
+        let keys: Vec<_> = self
+            .objects
+            .range(group_keys(group))
+            .map(|(key, _)| *key)
+            .collect();
+        self.arrivals.retain(|(_, key)| key.group != group);
+        self.updated.remove(&group);
+        keys.iter().map(|key| self.remove(key)).sum()
+    }
+
+    /// Remove the object under `key`, which no longer completes its range.
+    /// Returns the payload bytes freed.
+    fn remove(&mut self, key: &ObjectKey) -> usize {
+        let loc = key.location();
+        self.complete.retain(|(s, e)| !loc.is_within(s, e));
+        let Some(object) = self.objects.remove(key) else {
+            return 0;
+        };
+        if self.objects.range(group_keys(key.group)).next().is_none() {
+            self.updated.remove(&key.group);
+        }
+        self.bytes -= object.payload.len();
+        object.payload.len()
+    }
+}
+
+/// Keys of every object of `group`.
+fn group_keys(group: u64) -> std::ops::RangeInclusive<ObjectKey> {
+    ObjectKey::range(0, &Location::new(group, 0), &Location::new(group, u64::MAX))
+}
+
+/// Cached tracks and their total payload bytes.
+#[derive(Default)]
+struct Tracks {
+    by_name: HashMap<FullTrackName, CachedTrack>,
+    bytes: usize,
+}
+
+impl Tracks {
+    /// The cached track, with its expired objects evicted at `now`.
+    fn get_mut(&mut self, track: &FullTrackName, now: Instant) -> Option<&mut CachedTrack> {
+        let cached = self.by_name.get_mut(track)?;
+        self.bytes -= cached.expire(now);
+        Some(cached)
+    }
+
+    fn entry(&mut self, track: &FullTrackName, now: Instant) -> &mut CachedTrack {
+        let cached = self.by_name.entry(track.clone()).or_default();
+        self.bytes -= cached.expire(now);
+        cached
+    }
+
+    fn expire(&mut self, now: Instant) {
+        let freed: usize = self.by_name.values_mut().map(|t| t.expire(now)).sum();
+        self.bytes -= freed;
+    }
+
+    /// Evict the oldest objects of the largest tracks until at most
+    /// `max_bytes` are cached.
+    fn evict(&mut self, max_bytes: usize) {
+        while self.bytes > max_bytes {
+            let Some(cached) = self.by_name.values_mut().max_by_key(|t| t.bytes) else {
+                return;
+            };
+            let Some(key) = cached.objects.keys().next().copied() else {
+                return;
+            };
+            if let Some(pos) = cached.arrivals.iter().position(|(_, k)| *k == key) {
+                cached.arrivals.remove(pos);
+            }
+            self.bytes -= cached.remove(&key);
+        }
+    }
+}
+
+/// Location immediately following `loc`. Ranges spanning whole groups end
+/// at object `u64::MAX`, which is followed by the next group.
+fn successor(loc: &Location) -> Location {
+    match loc.object {
+        u64::MAX => loc.next_group(),
+        _ => loc.next_object(),
+    }
+}
+
+/// Location immediately preceding `loc`, which must not be (0, 0).
+fn predecessor(loc: &Location) -> Location {
+    match loc.object.checked_sub(1) {
+        Some(object) => Location::new(loc.group, object),
+        None => Location::new(loc.group - 1, u64::MAX),
+    }
+}
+
+/// Occupancy of one cached track.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CacheOccupancy {
+    pub track: FullTrackName,
+    pub objects: usize,
+    pub bytes: usize,
+}
+
+/// Payload bytes a [`TrackCache`] holds by default, 256 MiB.
+pub const DEFAULT_MAX_BYTES: usize = 256 << 20;
+
+/// Objects the relay keeps per track to serve FETCH and late joiners.
+///
+/// The cache holds at most [`max_bytes`](Self::max_bytes) of payload. Once
+/// full, the oldest objects of the largest tracks are evicted first, and
+/// the ranges they belonged to are fetched upstream again.
+pub struct TrackCache {
+    tracks: Mutex<Tracks>,
+    max_bytes: AtomicUsize,
+}
+
+impl Default for TrackCache {
+    fn default() -> Self {
+        Self {
+            tracks: Mutex::default(),
+            max_bytes: AtomicUsize::new(DEFAULT_MAX_BYTES),
+        }
+    }
+}
+
+fn key(object: &Object) -> ObjectKey {
+    object.metadata.key().untracked()
+}
+
+impl TrackCache {
+    /// Limit the payload bytes cached across every track, evicting objects
+    /// if more are cached already.
+    pub fn set_max_bytes(&self, max_bytes: usize) {
+        self.max_bytes.store(max_bytes, Ordering::Relaxed);
+        self.tracks.lock().unwrap().evict(max_bytes);
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Cache an object. Returns `false` if an object with the same key is
+    /// already cached, in which case the cache is left unchanged.
+    pub fn insert(&self, track: &FullTrackName, object: Object) -> bool {
+        let mut tracks = self.tracks.lock().unwrap();
+        let now = Instant::now();
+        let cached = tracks.entry(track, now);
+        let key = key(&object);
+        if cached.objects.contains_key(&key) {
+            return false;
+        }
+        let len = object.payload.len();
+        cached.bytes += len;
+        cached.objects.insert(key, object);
+        if cached.max_age.is_some() {
+            cached.arrivals.push_back((now, key));
+        }
+        if cached.retention.is_some() {
+            cached.updated.insert(key.group, now);
+        }
+        let freed = cached.retain(now);
+        tracks.bytes += len;
+        tracks.bytes -= freed;
+        tracks.evict(self.max_bytes());
+        true
+    }
+
+    /// Keep only what `policy` allows of the track's groups, like a
+    /// publisher's [`RetentionBuffer`](moqt_transport::retention::RetentionBuffer),
+    /// `None` lifting the policy. Applies to the groups already cached as
+    /// well; an idle time is counted for those from now. Objects the policy
+    /// does not retain are no longer recognized as duplicates by
+    /// [`insert`](Self::insert).
+    pub fn set_retention_policy(&self, track: &FullTrackName, policy: Option<RetentionPolicy>) {
+        let mut tracks = self.tracks.lock().unwrap();
+        let now = Instant::now();
+        let cached = tracks.entry(track, now);
+        match policy {
+            Some(_) if cached.retention.is_none() => {
+                cached.updated = cached.objects.keys().map(|key| (key.group, now)).collect();
+            }
+            Some(_) => {}
+            None => cached.updated.clear(),
+        }
+        cached.retention = policy;
+        tracks.expire(now);
+    }
+
+    /// Limit how long objects of the track are cached, as announced by the
+    /// MAX_CACHE_DURATION of a SUBSCRIBE_OK, PUBLISH or FETCH_OK from
+    /// upstream, `None` lifting the limit. Applies to the objects already
+    /// cached as well; those cached while there was no limit are aged from
+    /// now. See [`Relay::record_response`](crate::Relay::record_response).
+    pub fn set_max_cache_duration(&self, track: &FullTrackName, max: Option<Duration>) {
+        let mut tracks = self.tracks.lock().unwrap();
+        let now = Instant::now();
+        let cached = tracks.entry(track, now);
+        match max {
+            Some(_) if cached.max_age.is_none() => {
+                cached.arrivals = cached.objects.keys().map(|key| (now, *key)).collect();
+            }
+            Some(_) => {}
+            None => cached.arrivals.clear(),
+        }
+        cached.max_age = max;
+        tracks.expire(now);
+    }
+
+    pub fn get(&self, track: &FullTrackName, loc: &Location) -> Option<Object> {
+        let mut tracks = self.tracks.lock().unwrap();
+        let cached = tracks.get_mut(track, Instant::now())?;
+        let (_, object) = cached.objects.range(ObjectKey::range(0, loc, loc)).next()?;
+        Some(object.clone())
+    }
+
+    /// Cached objects from `start` up to and including `end`, in order.
+    pub fn range(&self, track: &FullTrackName, start: &Location, end: &Location) -> Vec<Object> {
+        let mut tracks = self.tracks.lock().unwrap();
+        match tracks.get_mut(track, Instant::now()) {
+            Some(cached) => cached
+                .objects
+                .range(ObjectKey::range(0, start, end))
+                .map(|(_, o)| o.clone())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Record that every object from `start` to `end` inclusive is cached,
+    /// e.g. after an upstream FETCH of that range completed.
+    pub fn mark_complete(&self, track: &FullTrackName, start: &Location, end: &Location) {
+        let mut tracks = self.tracks.lock().unwrap();
+        let cached = tracks.entry(track, Instant::now());
+        let (mut start, mut end) = (start.clone(), end.clone());
+
+        // Merge with every range that overlaps or is adjacent.
+        cached.complete.retain(|(s, e)| {
+            if successor(e) < start || successor(&end) < *s {
+                return true;
+            }
+            start = start.clone().min(s.clone());
+            end = end.clone().max(e.clone());
+            false
+        });
+        let pos = cached.complete.partition_point(|(s, _)| *s < start);
+        cached.complete.insert(pos, (start, end));
+    }
+
+    /// Sub-ranges of `start..=end` not known to be fully cached, in order.
+    pub fn missing(
+        &self,
+        track: &FullTrackName,
+        start: &Location,
+        end: &Location,
+    ) -> Vec<(Location, Location)> {
+        let mut tracks = self.tracks.lock().unwrap();
+        let complete = match tracks.get_mut(track, Instant::now()) {
+            Some(cached) => cached.complete.as_slice(),
+            None => &[],
+        };
+        let mut next = start.clone();
+        let mut missing = Vec::new();
+
+        for (s, e) in complete {
+            if next > *end {
+                break;
+            }
+            if *e < next {
+                continue;
+            }
+            if *s > next {
+                let gap_end = if s > end { end.clone() } else { predecessor(s) };
+                missing.push((next, gap_end));
+            }
+            next = successor(e);
+        }
+        if next <= *end {
+            missing.push((next, end.clone()));
+        }
+        missing
+    }
+
+    /// Drop every cached object of the track. Returns `false` if nothing
+    /// was cached for it.
+    pub fn purge(&self, track: &FullTrackName) -> bool {
+        let mut tracks = self.tracks.lock().unwrap();
+        let Some(cached) = tracks.by_name.remove(track) else {
+            return false;
+        };
+        tracks.bytes -= cached.bytes;
+        true
+    }
+
+    /// Per-track occupancy, sorted by track name.
+    pub fn occupancy(&self) -> Vec<CacheOccupancy> {
+        let mut tracks = self.tracks.lock().unwrap();
+        tracks.expire(Instant::now());
+        let mut occupancy: Vec<_> = tracks
+            .by_name
+            .iter()
+            .map(|(track, cached)| CacheOccupancy {
+                track: track.clone(),
+                objects: cached.objects.len(),
+                bytes: cached.bytes,
+            })
+            .collect();
+        occupancy.sort_by(|a, b| a.track.cmp(&b.track));
+        occupancy
+    }
+
+    /// Total payload bytes cached.
+    pub fn bytes(&self) -> usize {
+        let mut tracks = self.tracks.lock().unwrap();
+        tracks.expire(Instant::now());
+        tracks.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use moqt_transport::mock::ObjectBuilder;
+
+    fn object(group_id: u64, object_id: u64) -> Object {
+        ObjectBuilder::new(group_id, object_id)
+            .with_payload(Bytes::from_static(b"data"))
+            .build()
+    }
+
+    #[test]
+    fn range_is_ordered_and_inclusive() {
+        let cache = TrackCache::default();
+        let track = "video".to_string();
+        for (g, o) in [(1, 1), (0, 0), (1, 0), (2, 0)] {
+            assert!(cache.insert(&track, object(g, o)));
+        }
+        assert!(!cache.insert(&track, object(1, 0)));
+
+        let objects = cache.range(
+            &track,
+            &Location {
+                group: 0,
+                object: 0,
+            },
+            &Location {
+                group: 1,
+                object: 1,
+            },
+        );
+        let locations: Vec<_> = objects.iter().map(|o| o.metadata.location()).collect();
+        assert_eq!(
+            locations,
+            vec![
+                Location::new(0, 0),
+                Location::new(1, 0),
+                Location::new(1, 1)
+            ]
+        );
+        assert_eq!(cache.bytes(), 16);
+    }
+
+    fn loc(group: u64, object: u64) -> Location {
+        Location { group, object }
+    }
+
+    #[test]
+    fn missing_ranges_skip_complete_ones() {
+        let cache = TrackCache::default();
+        let track = "video".to_string();
+        assert_eq!(
+            cache.missing(&track, &loc(0, 0), &loc(3, 0)),
+            vec![(loc(0, 0), loc(3, 0))]
+        );
+
+        cache.mark_complete(&track, &loc(1, 0), &loc(1, 9));
+        cache.mark_complete(&track, &loc(2, 5), &loc(2, 9));
+        assert_eq!(
+            cache.missing(&track, &loc(0, 0), &loc(3, 0)),
+            vec![
+                (loc(0, 0), loc(0, u64::MAX)),
+                (loc(1, 10), loc(2, 4)),
+                (loc(2, 10), loc(3, 0)),
+            ]
+        );
+        assert!(cache.missing(&track, &loc(1, 2), &loc(1, 5)).is_empty());
+
+        // Adjacent ranges merge.
+        cache.mark_complete(&track, &loc(1, 10), &loc(2, 4));
+        assert_eq!(
+            cache.missing(&track, &loc(1, 0), &loc(2, 9)),
+            Vec::<(Location, Location)>::new()
+        );
+    }
+
+    #[test]
+    fn purge_releases_occupancy() {
+        let cache = TrackCache::default();
+        let track = "audio".to_string();
+        cache.insert(&track, object(0, 0));
+        assert_eq!(
+            cache.occupancy(),
+            vec![CacheOccupancy {
+                track: track.clone(),
+                objects: 1,
+                bytes: 4,
+            }]
+        );
+        assert!(cache.purge(&track));
+        assert!(!cache.purge(&track));
+        assert!(cache.occupancy().is_empty());
+    }
+
+    #[test]
+    fn duplicates_are_detected_across_aliases() {
+        let cache = TrackCache::default();
+        let track = "video".to_string();
+        assert!(cache.insert(&track, object(0, 0)));
+        let mut failover = object(0, 0);
+        failover.metadata.track_alias = 5;
+        assert!(!cache.insert(&track, failover));
+        assert_eq!(
+            cache.get(&track, &loc(0, 0)).unwrap().metadata.track_alias,
+            0
+        );
+    }
+
+    #[test]
+    fn oldest_objects_of_largest_track_are_evicted() {
+        let cache = TrackCache::default();
+        cache.set_max_bytes(16);
+        let (video, audio) = ("video".to_string(), "audio".to_string());
+        for o in 0..3 {
+            cache.insert(&video, object(0, o));
+        }
+        cache.insert(&audio, object(0, 0));
+        cache.mark_complete(&video, &loc(0, 0), &loc(0, 2));
+        assert_eq!(cache.bytes(), 16);
+
+        cache.insert(&audio, object(0, 1));
+        assert_eq!(cache.bytes(), 16);
+        assert!(cache.get(&video, &loc(0, 0)).is_none());
+        assert_eq!(cache.range(&video, &loc(0, 0), &loc(0, 2)).len(), 2);
+        assert_eq!(
+            cache.missing(&video, &loc(0, 0), &loc(0, 2)),
+            vec![(loc(0, 0), loc(0, 2))]
+        );
+
+        cache.set_max_bytes(4);
+        assert_eq!(cache.bytes(), 4);
+        cache.purge(&video);
+        cache.purge(&audio);
+        assert_eq!(cache.bytes(), 0);
+    }
+
+    #[test]
+    fn objects_expire_after_max_cache_duration() {
+        let cache = TrackCache::default();
+        let track = "video".to_string();
+        cache.set_max_cache_duration(&track, Some(Duration::from_secs(60)));
+        cache.insert(&track, object(0, 0));
+        cache.insert(&track, object(0, 1));
+        cache.mark_complete(&track, &loc(0, 0), &loc(0, 1));
+        assert_eq!(cache.range(&track, &loc(0, 0), &loc(0, 1)).len(), 2);
+        assert!(cache.missing(&track, &loc(0, 0), &loc(0, 1)).is_empty());
+
+        let later = Instant::now() + Duration::from_secs(60);
+        cache.tracks.lock().unwrap().expire(later);
+        assert!(cache.range(&track, &loc(0, 0), &loc(0, 1)).is_empty());
+        assert_eq!(
+            cache.missing(&track, &loc(0, 0), &loc(0, 1)),
+            vec![(loc(0, 0), loc(0, 1))]
+        );
+        assert_eq!(cache.bytes(), 0);
+
+        // Expired objects are cached again when fetched anew.
+        assert!(cache.insert(&track, object(0, 0)));
+    }
+
+    #[test]
+    fn retention_policy_keeps_last_groups() {
+        let cache = TrackCache::default();
+        let track = "video".to_string();
+        for group in 0..3 {
+            cache.insert(&track, object(group, 0));
+            cache.insert(&track, object(group, 1));
+        }
+        cache.set_retention_policy(&track, Some(RetentionPolicy::Groups(2)));
+        assert!(cache.get(&track, &loc(0, 1)).is_none());
+        assert_eq!(cache.bytes(), 16);
+
+        cache.insert(&track, object(3, 0));
+        assert!(cache.get(&track, &loc(1, 0)).is_none());
+        assert_eq!(cache.range(&track, &loc(0, 0), &loc(3, 0)).len(), 3);
+
+        // Evicting a whole group by size frees its slot.
+        cache.set_max_bytes(4);
+        cache.set_max_bytes(DEFAULT_MAX_BYTES);
+        cache.insert(&track, object(4, 0));
+        assert_eq!(cache.range(&track, &loc(0, 0), &loc(4, 0)).len(), 2);
+
+        cache.set_retention_policy(&track, Some(RetentionPolicy::None));
+        assert_eq!(cache.bytes(), 0);
+        cache.set_retention_policy(&track, None);
+        cache.insert(&track, object(5, 0));
+        assert_eq!(cache.bytes(), 4);
+    }
+
+    #[test]
+    fn retention_policy_drops_idle_groups() {
+        let cache = TrackCache::default();
+        let track = "video".to_string();
+        cache.set_retention_policy(&track, Some(RetentionPolicy::Age(Duration::from_secs(10))));
+        cache.insert(&track, object(0, 0));
+        cache.insert(&track, object(1, 0));
+
+        let later = Instant::now() + Duration::from_secs(11);
+        cache
+            .tracks
+            .lock()
+            .unwrap()
+            .by_name
+            .get_mut(&track)
+            .unwrap()
+            .updated
+            .insert(1, later);
+        cache.tracks.lock().unwrap().expire(later);
+        assert!(cache.get(&track, &loc(0, 0)).is_none());
+        assert!(cache.get(&track, &loc(1, 0)).is_some());
+        assert_eq!(cache.bytes(), 4);
+    }
+
+    #[test]
+    fn arrivals_are_recorded_only_while_limited() {
+        let cache = TrackCache::default();
+        let track = "video".to_string();
+        cache.insert(&track, object(0, 0));
+        cache.insert(&track, object(0, 1));
+        let arrivals =
+            |cache: &TrackCache| cache.tracks.lock().unwrap().by_name[&track].arrivals.len();
+        assert_eq!(arrivals(&cache), 0);
+
+        // Objects cached without a limit are aged from when it is set.
+        cache.set_max_cache_duration(&track, Some(Duration::from_secs(60)));
+        assert_eq!(arrivals(&cache), 2);
+        assert_eq!(cache.range(&track, &loc(0, 0), &loc(0, 1)).len(), 2);
+        let later = Instant::now() + Duration::from_secs(60);
+        cache.tracks.lock().unwrap().expire(later);
+        assert_eq!(cache.bytes(), 0);
+
+        cache.insert(&track, object(0, 2));
+        cache.set_max_cache_duration(&track, None);
+        assert_eq!(arrivals(&cache), 0);
+        cache
+            .tracks
+            .lock()
+            .unwrap()
+            .expire(later + Duration::from_secs(60));
+        assert!(cache.get(&track, &loc(0, 2)).is_some());
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::{HashMap, VecDeque};
+
+use moqt_transport::scheduler::{Priority, Scheduler};
+
+use crate::relay::SessionId;
+
+/// How a relay shares downstream capacity between the subscribers of a
+/// track.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FairnessPolicy {
+    /// The draft's scheduling applied across every subscriber: objects of
+    /// the subscriber requesting the highest priority always go first,
+    /// even if the others starve.
+    #[default]
+    StrictPriority,
+    /// Subscribers take turns, each sending up to its weight in objects per
+    /// turn.
+    WeightedRoundRobin,
+    /// Subscribers take turns, each sending up to `quantum` bytes times its
+    /// weight per turn. Unused budget carries over to the next turn while
+    /// the subscriber has objects queued, so large objects are sent
+    /// eventually.
+    ByteBudget { quantum: usize },
+}
+
+struct SessionQueue<T> {
+    queue: Scheduler<(T, usize)>,
+    weight: usize,
+    /// Objects or bytes left to send in the current turn.
+    credit: usize,
+    in_turn: bool,
+}
+
+impl<T> Default for SessionQueue<T> {
+    fn default() -> Self {
+        Self {
+            queue: Scheduler::default(),
+            weight: 1,
+            credit: 0,
+            in_turn: false,
+        }
+    }
+}
+
+/// Objects queued for the downstream sessions of a relay, released in the
+/// order given by a [`FairnessPolicy`].
+///
+/// Within one session objects always follow the draft's scheduling
+/// algorithm; the policy only decides which session sends next.
+pub struct Fanout<T> {
+    policy: FairnessPolicy,
+    strict: Scheduler<(SessionId, T)>,
+    sessions: HashMap<SessionId, SessionQueue<T>>,
+    /// Sessions with queued objects, the current turn first.
+    turns: VecDeque<SessionId>,
+}
+
+impl<T> Default for Fanout<T> {
+    fn default() -> Self {
+        Self::new(FairnessPolicy::default())
+    }
+}
+
+impl<T> Fanout<T> {
+    pub fn new(policy: FairnessPolicy) -> Self {
+        Self {
+            policy,
+            strict: Scheduler::default(),
+            sessions: HashMap::new(),
+            turns: VecDeque::new(),
+        }
+    }
+
+    pub fn policy(&self) -> FairnessPolicy {
+        self.policy
+    }
+
+    /// Set the share of a session under the round-robin policies. Sessions
+    /// default to weight 1; a weight of 0 is treated as 1.
+    pub fn set_weight(&mut self, session: SessionId, weight: usize) {
+        self.sessions.entry(session).or_default().weight = weight.max(1);
+    }
+
+    /// Queue an object of `size` bytes for `session`.
+    pub fn push(&mut self, session: SessionId, priority: Priority, item: T, size: usize) {
+        if self.policy == FairnessPolicy::StrictPriority {
+            self.strict.push(priority, (session, item));
+            return;
+        }
+        let queue = self.sessions.entry(session).or_default();
+        if queue.queue.is_empty() && !self.turns.contains(&session) {
+            self.turns.push_back(session);
+        }
+        queue.queue.push(priority, (item, size));
+    }
+
+    /// Remove the object to send next, with the session to send it to.
+    pub fn pop(&mut self) -> Option<(SessionId, T)> {
+        let quantum = match self.policy {
+            FairnessPolicy::StrictPriority => return self.strict.pop(),
+            FairnessPolicy::WeightedRoundRobin => None,
+            FairnessPolicy::ByteBudget { quantum } => Some(quantum.max(1)),
+        };
+        loop {
+            let session = *self.turns.front()?;
+            let queue = self.sessions.get_mut(&session)?;
+            let Some(&(_, size)) = queue.queue.peek() else {
+                queue.credit = 0;
+                queue.in_turn = false;
+                self.turns.pop_front();
+                continue;
+            };
+            if !queue.in_turn {
+                queue.in_turn = true;
+                queue.credit = match quantum {
+                    None => queue.weight,
+                    Some(quantum) => queue
+                        .credit
+                        .saturating_add(quantum.saturating_mul(queue.weight)),
+                };
+            }
+            let cost = if quantum.is_some() { size } else { 1 };
+            if cost > queue.credit {
+                queue.in_turn = false;
+                self.turns.rotate_left(1);
+                continue;
+            }
+            queue.credit -= cost;
+            let (item, _) = queue.queue.pop().expect("peeked above");
+            if quantum.is_none() && queue.credit == 0 {
+                queue.in_turn = false;
+                self.turns.rotate_left(1);
+            }
+            return Some((session, item));
+        }
+    }
+
+    /// Drop everything queued for a session, e.g. once it unsubscribed.
+    pub fn remove_session(&mut self, session: SessionId) {
+        self.strict.retain(|(s, _)| *s != session);
+        self.sessions.remove(&session);
+        self.turns.retain(|s| *s != session);
+    }
+
+    pub fn len(&self) -> usize {
+        self.strict.len() + self.sessions.values().map(|s| s.queue.len()).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn priority(subscriber_priority: u8) -> Priority {
+        Priority {
+            subscriber_priority,
+            publisher_priority: 128,
+            group_order: 1,
+            group_id: 0,
+            subgroup_id: 0,
+        }
+    }
+
+    /// Session 1 requests the highest priority, session 2 a lower one.
+    fn fill(fanout: &mut Fanout<u64>, objects: u64, size: usize) {
+        for object in 0..objects {
+            fanout.push(1, priority(0), object, size);
+            fanout.push(2, priority(200), object, size);
+        }
+    }
+
+    fn drain(fanout: &mut Fanout<u64>, n: usize) -> Vec<SessionId> {
+        (0..n).map(|_| fanout.pop().unwrap().0).collect()
+    }
+
+    #[test]
+    fn strict_priority_serves_highest_first() {
+        let mut fanout = Fanout::new(FairnessPolicy::StrictPriority);
+        fill(&mut fanout, 3, 100);
+        assert_eq!(drain(&mut fanout, 6), [1, 1, 1, 2, 2, 2]);
+    }
+
+    #[test]
+    fn weighted_round_robin_shares_objects() {
+        let mut fanout = Fanout::new(FairnessPolicy::WeightedRoundRobin);
+        fanout.set_weight(2, 2);
+        fill(&mut fanout, 4, 100);
+        assert_eq!(drain(&mut fanout, 6), [1, 2, 2, 1, 2, 2]);
+        assert_eq!(drain(&mut fanout, 2), [1, 1]);
+        assert!(fanout.pop().is_none());
+    }
+
+    #[test]
+    fn byte_budget_shares_bytes() {
+        let mut fanout = Fanout::new(FairnessPolicy::ByteBudget { quantum: 1000 });
+        // Session 1 sends large objects, session 2 small ones.
+        for object in 0..2 {
+            fanout.push(1, priority(0), object, 1000);
+        }
+        for object in 0..8 {
+            fanout.push(2, priority(200), object, 250);
+        }
+        assert_eq!(drain(&mut fanout, 10), [1, 2, 2, 2, 2, 1, 2, 2, 2, 2]);
+
+        // A large object waits until enough budget accumulated.
+        fanout.push(1, priority(0), 0, 2500);
+        fanout.push(2, priority(200), 0, 1000);
+        fanout.push(2, priority(200), 1, 1000);
+        assert_eq!(drain(&mut fanout, 3), [2, 2, 1]);
+    }
+
+    #[test]
+    fn budget_saturates_instead_of_overflowing() {
+        let mut fanout = Fanout::new(FairnessPolicy::ByteBudget {
+            quantum: usize::MAX,
+        });
+        fanout.set_weight(1, 2);
+        fanout.push(1, priority(0), 0, 100);
+        fanout.push(1, priority(0), 1, 100);
+        assert_eq!(drain(&mut fanout, 2), [1, 1]);
+    }
+
+    #[test]
+    fn removed_session_is_skipped() {
+        for policy in [
+            FairnessPolicy::StrictPriority,
+            FairnessPolicy::WeightedRoundRobin,
+        ] {
+            let mut fanout = Fanout::new(policy);
+            fill(&mut fanout, 2, 100);
+            fanout.remove_session(1);
+            assert_eq!(fanout.len(), 2);
+            assert_eq!(drain(&mut fanout, 2), [2, 2]);
+            assert!(fanout.is_empty());
+        }
+    }
+}
@@ -1 +1,12 @@
+pub mod acl;
+pub mod admin;
+pub mod backfill;
+pub mod cache;
+pub mod fanout;
+pub mod migration;
+pub mod prewarm;
+pub mod relay;
+pub mod rewrite;
+pub mod upstream;
 
+pub use relay::{Relay, SessionHandle, SessionId};
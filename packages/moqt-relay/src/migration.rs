@@ -0,0 +1,518 @@
+use std::future::{Future, poll_fn};
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::sync::{oneshot, watch};
+
+use moqt_transport::{
+    error::Error,
+    message::Goaway,
+    model::{Filter, Location},
+    track::{FullTrackName, ObjectStream},
+};
+
+use crate::{Relay, SessionHandle};
+
+/// Capacity of each stream returned in a [`Migration`].
+const MIGRATED_QUEUE_CAPACITY: usize = 1024;
+
+/// Default of [`Relay::with_drain_timeout`].
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A connected upstream session the relay subscribes through.
+#[async_trait]
+pub trait UpstreamLink: Send + Sync {
+    /// SUBSCRIBE to `track` with `filter`.
+    async fn subscribe(&self, track: &FullTrackName, filter: Filter)
+    -> Result<ObjectStream, Error>;
+}
+
+/// Opens upstream sessions, e.g. to the New Session URI of a GOAWAY.
+#[async_trait]
+pub trait Connector: Send + Sync {
+    async fn connect(&self, uri: &str) -> Result<Arc<dyn UpstreamLink>, Error>;
+}
+
+/// A track the relay receives from an upstream and forwards downstream.
+pub struct ForwardedTrack {
+    pub track: FullTrackName,
+    pub objects: ObjectStream,
+}
+
+/// An upstream migration in progress, see [`Relay::migrate_upstream`].
+pub struct Migration {
+    /// The new upstream session.
+    pub upstream: Arc<dyn UpstreamLink>,
+    /// Registration of the new upstream session with the relay.
+    pub session: SessionHandle,
+    /// The migrated tracks, in the order given. Each stream carries the
+    /// objects of the old upstream up to the switch group and those of the
+    /// new upstream from there on.
+    pub tracks: Vec<ForwardedTrack>,
+    done: oneshot::Receiver<()>,
+}
+
+impl Migration {
+    /// Wait until every track switched to the new upstream and the old
+    /// upstream session was kicked.
+    pub async fn completed(&mut self) {
+        let _ = (&mut self.done).await;
+    }
+}
+
+impl Relay {
+    /// Migrate the upstream session `old` after it sent `goaway`: to its
+    /// New Session URI, or back to the URI `old` was registered with if
+    /// the GOAWAY carries none. See [`migrate_upstream`](Self::migrate_upstream).
+    pub async fn handle_goaway(
+        &self,
+        old: &SessionHandle,
+        goaway: &Goaway,
+        connector: &dyn Connector,
+        tracks: Vec<ForwardedTrack>,
+    ) -> Result<Migration, Error> {
+        let uri = match &goaway.new_session_uri {
+            Some(uri) => uri.clone(),
+            None => self
+                .admin()
+                .sessions()
+                .into_iter()
+                .find(|s| s.id == old.id())
+                .map(|s| s.remote)
+                .ok_or(Error::SessionClosed)?,
+        };
+        self.migrate_upstream(old, &uri, connector, tracks).await
+    }
+
+    /// Move the subscriptions the relay holds on the upstream session
+    /// `old` over to a new session at `uri`, typically after `old` sent
+    /// GOAWAY.
+    ///
+    /// The new session is connected and registered with the namespaces
+    /// and subscriptions of the old one, then each track is subscribed
+    /// again from the next group. Objects keep being forwarded from the
+    /// old upstream until its subscription reaches the first group
+    /// received from the new one, or ends, so every track switches at a
+    /// group boundary without gaps or duplicates. An old upstream that does
+    /// not get there within the [drain timeout](Self::with_drain_timeout)
+    /// of that group arriving is abandoned, and the objects it had yet to
+    /// deliver before the boundary are lost. Only once every track
+    /// switched is the old session kicked, for its session task to close
+    /// the connection and drop its handle.
+    ///
+    /// `uri` must be allowed by the relay's
+    /// [`GoawayUriPolicy`](moqt_transport::goaway::GoawayUriPolicy),
+    /// otherwise [`Error::InvalidUri`] is returned without connecting. If
+    /// connecting or any subscription fails, the old upstream is left
+    /// untouched.
+    pub async fn migrate_upstream(
+        &self,
+        old: &SessionHandle,
+        uri: &str,
+        connector: &dyn Connector,
+        tracks: Vec<ForwardedTrack>,
+    ) -> Result<Migration, Error> {
+        self.goaway_uri_policy.validate(uri)?;
+        let upstream = connector.connect(uri).await?;
+        let mut resubscribed = Vec::with_capacity(tracks.len());
+        for track in &tracks {
+            resubscribed.push(
+                upstream
+                    .subscribe(&track.track, Filter::next_group())
+                    .await?,
+            );
+        }
+
+        let session = self.register_session(uri);
+        let admin = self.admin();
+        if let Some(info) = admin.sessions().into_iter().find(|s| s.id == old.id()) {
+            for namespace in info.namespaces {
+                session.add_namespace(namespace);
+            }
+            for track in info.subscriptions {
+                session.add_subscription(track);
+            }
+        }
+
+        let mut switched = Vec::with_capacity(tracks.len());
+        let tracks = tracks
+            .into_iter()
+            .zip(resubscribed)
+            .map(|(forwarded, new)| {
+                let (tx, rx) = oneshot::channel();
+                switched.push(rx);
+                ForwardedTrack {
+                    track: forwarded.track,
+                    objects: switch_over(forwarded.objects, new, self.drain_timeout, tx),
+                }
+            })
+            .collect();
+
+        let (done_tx, done) = oneshot::channel();
+        let old = old.id();
+        tokio::spawn(async move {
+            for rx in switched {
+                let _ = rx.await;
+            }
+            admin.kick_session(old);
+            let _ = done_tx.send(());
+        });
+
+        Ok(Migration {
+            upstream,
+            session,
+            tracks,
+            done,
+        })
+    }
+}
+
+/// Forward `old` until it reaches the first group of `new`, or
+/// `drain_timeout` after that group arrived, then `new`. `switched` is
+/// signaled once `old` is no longer read.
+fn switch_over(
+    mut old: ObjectStream,
+    mut new: ObjectStream,
+    drain_timeout: Duration,
+    switched: oneshot::Sender<()>,
+) -> ObjectStream {
+    let (tx, stream) = ObjectStream::channel(MIGRATED_QUEUE_CAPACITY);
+    let (boundary_tx, boundary) = watch::channel(None);
+
+    let old_tx = tx.clone();
+    let mut known = boundary.clone();
+    let draining = tokio::spawn(async move {
+        let deadline = async move {
+            if known.wait_for(Option::is_some).await.is_err() {
+                std::future::pending::<()>().await;
+            }
+            tokio::time::sleep(drain_timeout).await;
+        };
+        let mut deadline = pin!(deadline);
+        let mut last: Option<Location> = None;
+        loop {
+            let mut next = pin!(old.recv());
+            let next = poll_fn(|cx| {
+                if deadline.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(None);
+                }
+                next.as_mut().poll(cx).map(Some)
+            })
+            .await;
+            let Some(Some(Ok(object))) = next else {
+                break;
+            };
+            let switch_group = *boundary.borrow();
+            if switch_group.is_some_and(|g| object.metadata.group_id >= g) {
+                break;
+            }
+            last = Some(object.metadata.location());
+            if old_tx.send(Ok(object)).await.is_err() {
+                break;
+            }
+        }
+        let _ = switched.send(());
+        last
+    });
+
+    tokio::spawn(async move {
+        let first = new.recv().await;
+        if let Some(Ok(object)) = &first {
+            let _ = boundary_tx.send(Some(object.metadata.group_id));
+        }
+        let last = draining.await.ok().flatten();
+
+        let mut next = first;
+        while let Some(result) = next {
+            let forward = match &result {
+                Ok(object) => last
+                    .as_ref()
+                    .is_none_or(|l| l.is_before(&object.metadata.location())),
+                Err(_) => true,
+            };
+            if forward && tx.send(result).await.is_err() {
+                return;
+            }
+            next = new.recv().await;
+        }
+    });
+
+    stream
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use bytes::Bytes;
+    use moqt_transport::{mock::ObjectBuilder, track::Object};
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    type Sender = mpsc::Sender<Result<Object, Error>>;
+
+    fn object(group_id: u64, object_id: u64) -> Object {
+        ObjectBuilder::new(group_id, object_id)
+            .with_payload(Bytes::from_static(b"data"))
+            .build()
+    }
+
+    /// Upstream handing out the streams queued for its subscriptions.
+    #[derive(Default)]
+    struct MockLink {
+        streams: Mutex<Vec<ObjectStream>>,
+        filters: Mutex<Vec<Filter>>,
+        dialed: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl UpstreamLink for MockLink {
+        async fn subscribe(
+            &self,
+            _track: &FullTrackName,
+            filter: Filter,
+        ) -> Result<ObjectStream, Error> {
+            self.filters.lock().unwrap().push(filter);
+            self.streams
+                .lock()
+                .unwrap()
+                .pop()
+                .ok_or(Error::SessionClosed)
+        }
+    }
+
+    struct MockConnector(Arc<MockLink>);
+
+    #[async_trait]
+    impl Connector for MockConnector {
+        async fn connect(&self, uri: &str) -> Result<Arc<dyn UpstreamLink>, Error> {
+            self.0.dialed.lock().unwrap().push(uri.into());
+            Ok(self.0.clone())
+        }
+    }
+
+    /// Connector failing the test if it is used.
+    struct UnreachableConnector;
+
+    #[async_trait]
+    impl Connector for UnreachableConnector {
+        async fn connect(&self, uri: &str) -> Result<Arc<dyn UpstreamLink>, Error> {
+            panic!("dialed {uri}");
+        }
+    }
+
+    async fn send(tx: &Sender, group: u64, object_id: u64) {
+        tx.send(Ok(object(group, object_id))).await.unwrap();
+    }
+
+    async fn recv(stream: &mut ObjectStream) -> (u64, u64) {
+        let loc = stream.recv().await.unwrap().unwrap().metadata.location();
+        (loc.group, loc.object)
+    }
+
+    #[test]
+    fn tracks_switch_at_group_boundary() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let relay = Relay::new();
+            let old = relay.register_session("origin-a");
+            old.add_namespace(vec!["live".into()]);
+            old.add_subscription("video".into());
+
+            let (old_tx, old_stream) = ObjectStream::channel(16);
+            let (new_tx, new_stream) = ObjectStream::channel(16);
+            let link = Arc::new(MockLink::default());
+            link.streams.lock().unwrap().push(new_stream);
+
+            send(&old_tx, 0, 0).await;
+            let mut migration = relay
+                .migrate_upstream(
+                    &old,
+                    "https://origin-b.example/moq",
+                    &MockConnector(link.clone()),
+                    vec![ForwardedTrack {
+                        track: "video".into(),
+                        objects: old_stream,
+                    }],
+                )
+                .await
+                .unwrap();
+            assert_eq!(*link.filters.lock().unwrap(), [Filter::NextGroupStart]);
+            assert_eq!(relay.select_upstream(&["live".to_string()]), Some(old.id()));
+            let objects = &mut migration.tracks[0].objects;
+
+            send(&new_tx, 1, 0).await;
+            send(&new_tx, 1, 1).await;
+            send(&old_tx, 0, 1).await;
+            assert_eq!(recv(objects).await, (0, 0));
+            assert_eq!(recv(objects).await, (0, 1));
+
+            // The old upstream reaches the first group of the new one.
+            send(&old_tx, 1, 0).await;
+            assert_eq!(recv(objects).await, (1, 0));
+            assert_eq!(recv(objects).await, (1, 1));
+            migration.completed().await;
+            assert!(old_tx.is_closed());
+            assert!(old.is_kicked());
+            drop(old);
+
+            let sessions = relay.admin().sessions();
+            assert_eq!(sessions.len(), 1);
+            assert_eq!(sessions[0].id, migration.session.id());
+            assert_eq!(sessions[0].subscriptions, ["video".to_string()]);
+            assert_eq!(
+                relay.select_upstream(&["live".to_string()]),
+                Some(migration.session.id())
+            );
+        });
+    }
+
+    #[test]
+    fn stalled_old_upstream_is_abandoned_after_drain_timeout() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .start_paused(true)
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let relay = Relay::new().with_drain_timeout(Duration::from_secs(1));
+            let old = relay.register_session("origin-a");
+            let (old_tx, old_stream) = ObjectStream::channel(16);
+            let (new_tx, new_stream) = ObjectStream::channel(16);
+            let link = Arc::new(MockLink::default());
+            link.streams.lock().unwrap().push(new_stream);
+
+            send(&old_tx, 0, 0).await;
+            let mut migration = relay
+                .migrate_upstream(
+                    &old,
+                    "https://origin-b.example/moq",
+                    &MockConnector(link),
+                    vec![ForwardedTrack {
+                        track: "video".into(),
+                        objects: old_stream,
+                    }],
+                )
+                .await
+                .unwrap();
+            let objects = &mut migration.tracks[0].objects;
+            assert_eq!(recv(objects).await, (0, 0));
+
+            // The old upstream never gets to group 1.
+            let start = tokio::time::Instant::now();
+            send(&new_tx, 1, 0).await;
+            assert_eq!(recv(objects).await, (1, 0));
+            assert_eq!(start.elapsed(), Duration::from_secs(1));
+            migration.completed().await;
+            assert!(old_tx.is_closed());
+            assert!(old.is_kicked());
+        });
+    }
+
+    #[test]
+    fn goaway_migrates_to_new_session_uri() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let relay = Relay::new();
+            for (uri, dialed) in [
+                (
+                    Some("https://origin-b.example/moq"),
+                    "https://origin-b.example/moq",
+                ),
+                (None, "https://origin-a.example/moq"),
+            ] {
+                let old = relay.register_session("https://origin-a.example/moq");
+                let (_old_tx, old_stream) = ObjectStream::channel(16);
+                let (_new_tx, new_stream) = ObjectStream::channel(16);
+                let link = Arc::new(MockLink::default());
+                link.streams.lock().unwrap().push(new_stream);
+                let goaway = Goaway {
+                    new_session_uri: uri.map(String::from),
+                };
+                let migration = relay
+                    .handle_goaway(
+                        &old,
+                        &goaway,
+                        &MockConnector(link.clone()),
+                        vec![ForwardedTrack {
+                            track: "video".into(),
+                            objects: old_stream,
+                        }],
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(*link.dialed.lock().unwrap(), [dialed.to_string()]);
+                let sessions = relay.admin().sessions();
+                let session = sessions
+                    .iter()
+                    .find(|s| s.id == migration.session.id())
+                    .unwrap();
+                assert_eq!(session.remote, dialed);
+            }
+        });
+    }
+
+    #[test]
+    fn failed_resubscribe_keeps_old_upstream() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let relay = Relay::new();
+            let old = relay.register_session("origin-a");
+            let (_old_tx, old_stream) = ObjectStream::channel(16);
+            let link = Arc::new(MockLink::default());
+            let result = relay
+                .migrate_upstream(
+                    &old,
+                    "https://origin-b.example/moq",
+                    &MockConnector(link),
+                    vec![ForwardedTrack {
+                        track: "video".into(),
+                        objects: old_stream,
+                    }],
+                )
+                .await;
+            assert!(matches!(result, Err(Error::SessionClosed)));
+            assert!(!old.is_kicked());
+            let sessions = relay.admin().sessions();
+            assert_eq!(sessions.len(), 1);
+            assert_eq!(sessions[0].id, old.id());
+        });
+    }
+
+    #[test]
+    fn disallowed_uri_is_not_dialed() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let relay = Relay::new();
+            let old = relay.register_session("origin-a");
+            let (_old_tx, old_stream) = ObjectStream::channel(16);
+            let result = relay
+                .migrate_upstream(
+                    &old,
+                    "file:///etc/passwd",
+                    &UnreachableConnector,
+                    vec![ForwardedTrack {
+                        track: "video".into(),
+                        objects: old_stream,
+                    }],
+                )
+                .await;
+            assert!(matches!(result, Err(Error::InvalidUri { .. })));
+            assert!(!old.is_kicked());
+        });
+    }
+}
@@ -0,0 +1,222 @@
+use std::sync::Arc;
+
+use moqt_transport::{
+    codec::is_namespace_prefix,
+    error::Error,
+    model::Location,
+    track::{FullTrackName, full_track_name},
+};
+
+use crate::{
+    Relay,
+    backfill::{Backfill, Upstream},
+};
+
+/// Tracks to fetch into the cache when a namespace starting with `prefix`
+/// is announced upstream.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PrewarmRule {
+    pub prefix: Vec<String>,
+    /// Names of the tracks within the announced namespace.
+    pub tracks: Vec<String>,
+    /// Number of most recent groups to fetch, including the group of the
+    /// Largest Location.
+    pub groups: u64,
+}
+
+impl PrewarmRule {
+    pub fn new(prefix: Vec<String>, groups: u64) -> Self {
+        Self {
+            prefix,
+            tracks: Vec::new(),
+            groups,
+        }
+    }
+
+    pub fn with_track(mut self, name: impl Into<String>) -> Self {
+        self.tracks.push(name.into());
+        self
+    }
+}
+
+/// Prewarming rules of a relay. Nothing is prewarmed unless configured.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct PrewarmConfig {
+    pub rules: Vec<PrewarmRule>,
+}
+
+impl PrewarmConfig {
+    pub fn with_rule(mut self, rule: PrewarmRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+}
+
+/// Fetches the latest groups of configured tracks into the relay cache as
+/// soon as their namespace is announced upstream, so the first subscribers
+/// are backfilled from the cache.
+pub struct Prewarmer {
+    backfill: Backfill,
+    upstream: Arc<dyn Upstream>,
+    config: PrewarmConfig,
+}
+
+impl Prewarmer {
+    pub fn new(relay: Relay, upstream: Arc<dyn Upstream>, config: PrewarmConfig) -> Self {
+        Self {
+            backfill: Backfill::new(relay, upstream.clone()),
+            upstream,
+            config,
+        }
+    }
+
+    /// Tracks prewarmed when `namespace` is announced, with the number of
+    /// groups to fetch.
+    pub fn tracks(&self, namespace: &[String]) -> Vec<(FullTrackName, u64)> {
+        self.config
+            .rules
+            .iter()
+            .filter(|rule| rule.groups > 0 && is_namespace_prefix(&rule.prefix, namespace))
+            .flat_map(|rule| {
+                let tracks = rule.tracks.iter();
+                tracks.map(|name| (full_track_name(namespace, name), rule.groups))
+            })
+            .collect()
+    }
+
+    /// Prewarm the tracks configured for an announced namespace. Tracks
+    /// whose Largest Location is unknown upstream are skipped, and ranges
+    /// already cached are not fetched again. Returns the number of objects
+    /// of the prewarmed ranges now cached.
+    ///
+    /// Spawned by [`SessionHandle::add_namespace`](crate::SessionHandle::add_namespace)
+    /// on relays configured with [`Relay::with_prewarm`].
+    pub async fn on_announce(&self, namespace: &[String]) -> Result<u64, Error> {
+        let mut objects = 0;
+        for (track, groups) in self.tracks(namespace) {
+            let Some(largest) = self.upstream.largest(&track).await? else {
+                continue;
+            };
+            let start = Location::new(largest.group.saturating_sub(groups - 1), 0);
+            let mut stream = self.backfill.fetch(track, start, largest);
+            while let Some(object) = stream.recv().await {
+                object?;
+                objects += 1;
+            }
+        }
+        Ok(objects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use moqt_transport::{mock::ObjectBuilder, track::ObjectStream};
+
+    use super::*;
+
+    fn ns(fields: &[&str]) -> Vec<String> {
+        fields.iter().map(|f| f.to_string()).collect()
+    }
+
+    /// Upstream whose "live/cam/video" track has groups 0..=5 with objects
+    /// 0..3.
+    #[derive(Default)]
+    struct MockUpstream {
+        fetches: Mutex<Vec<(FullTrackName, Location, Location)>>,
+    }
+
+    #[async_trait]
+    impl Upstream for MockUpstream {
+        async fn fetch(
+            &self,
+            track: &FullTrackName,
+            start: Location,
+            end: Location,
+        ) -> Result<ObjectStream, Error> {
+            self.fetches
+                .lock()
+                .unwrap()
+                .push((track.clone(), start.clone(), end.clone()));
+            let (tx, stream) = ObjectStream::channel(64);
+            for (group_id, object_id) in (0..6).flat_map(|g| (0..3).map(move |o| (g, o))) {
+                if Location::new(group_id, object_id).is_within(&start, &end) {
+                    let object = ObjectBuilder::new(group_id, object_id)
+                        .with_payload(Bytes::from_static(b"data"))
+                        .build();
+                    tx.try_send(Ok(object)).unwrap();
+                }
+            }
+            Ok(stream)
+        }
+
+        async fn largest(&self, track: &FullTrackName) -> Result<Option<Location>, Error> {
+            Ok((track == "live/cam/video").then(|| Location::new(5, 2)))
+        }
+    }
+
+    #[test]
+    fn announce_prewarms_latest_groups() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let relay = Relay::new();
+            let upstream = Arc::new(MockUpstream::default());
+            let config = PrewarmConfig::default().with_rule(
+                PrewarmRule::new(ns(&["live"]), 2)
+                    .with_track("video")
+                    .with_track("audio"),
+            );
+            let prewarmer = Prewarmer::new(relay.clone(), upstream.clone(), config);
+
+            assert_eq!(prewarmer.on_announce(&ns(&["vod"])).await.unwrap(), 0);
+            assert_eq!(
+                prewarmer.on_announce(&ns(&["live", "cam"])).await.unwrap(),
+                6
+            );
+            let video = "live/cam/video".to_string();
+            assert_eq!(
+                *upstream.fetches.lock().unwrap(),
+                vec![(video.clone(), Location::new(4, 0), Location::new(5, 2))]
+            );
+            assert!(relay.cache().get(&video, &Location::new(4, 0)).is_some());
+            assert!(relay.cache().get(&video, &Location::new(3, 2)).is_none());
+
+            // Served from the cache when announced again.
+            assert_eq!(
+                prewarmer.on_announce(&ns(&["live", "cam"])).await.unwrap(),
+                6
+            );
+            assert_eq!(upstream.fetches.lock().unwrap().len(), 1);
+        });
+    }
+
+    #[test]
+    fn announced_namespace_is_prewarmed() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let upstream = Arc::new(MockUpstream::default());
+            let config = PrewarmConfig::default()
+                .with_rule(PrewarmRule::new(ns(&["live"]), 1).with_track("video"));
+            let relay = Relay::new().with_prewarm(upstream.clone(), config);
+            let session = relay.register_session("publisher");
+
+            session.add_namespace(ns(&["live", "cam"]));
+            let video = "live/cam/video".to_string();
+            for _ in 0..100 {
+                if relay.cache().get(&video, &Location::new(5, 2)).is_some() {
+                    break;
+                }
+                tokio::task::yield_now().await;
+            }
+            assert!(relay.cache().get(&video, &Location::new(5, 0)).is_some());
+            assert!(relay.cache().get(&video, &Location::new(4, 2)).is_none());
+        });
+    }
+}
@@ -0,0 +1,585 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tokio::time::Instant;
+use tokio_util::sync::{CancellationToken, WaitForCancellationFuture};
+
+use moqt_transport::{
+    bandwidth::{BandwidthLimits, Rate, Release, Shaper},
+    codec::is_namespace_prefix,
+    error::Error,
+    goaway::GoawayUriPolicy,
+    message::ControlMessage,
+    publish::max_cache_duration,
+    scheduler::Priority,
+    track::{FullTrackName, Object},
+};
+
+use crate::{
+    acl::NamespaceAcl,
+    admin::Admin,
+    backfill::Upstream,
+    cache::TrackCache,
+    fanout::{FairnessPolicy, Fanout},
+    migration::DEFAULT_DRAIN_TIMEOUT,
+    prewarm::{PrewarmConfig, Prewarmer},
+    upstream::{Candidate, LongestPrefix, UpstreamSelector},
+};
+
+/// Identifies a session connected to the relay.
+pub type SessionId = u64;
+
+pub(crate) struct SessionEntry {
+    pub(crate) remote: String,
+    pub(crate) subscriptions: Vec<FullTrackName>,
+    pub(crate) namespaces: Vec<Vec<String>>,
+    pub(crate) rtt: Option<Duration>,
+    pub(crate) kick: CancellationToken,
+    /// Subscriptions the relay forwards recorded objects to.
+    pub(crate) forwards: Vec<Forward>,
+    /// Objects forwarded to the session and not yet sent.
+    pub(crate) outbound: Shaper<Object>,
+}
+
+pub(crate) struct Forward {
+    pub(crate) request_id: u64,
+    pub(crate) track: FullTrackName,
+    pub(crate) subscriber_priority: u8,
+}
+
+impl Forward {
+    fn priority(&self, object: &Object) -> Priority {
+        Priority::for_object(self.subscriber_priority, 0x1, &object.metadata)
+    }
+}
+
+pub(crate) struct TrackStats {
+    pub(crate) objects: u64,
+    pub(crate) bytes: u64,
+    pub(crate) duplicates: u64,
+    pub(crate) since: Instant,
+}
+
+#[derive(Default)]
+pub(crate) struct RelayState {
+    pub(crate) next_session: Mutex<SessionId>,
+    pub(crate) sessions: Mutex<BTreeMap<SessionId, SessionEntry>>,
+    pub(crate) stats: Mutex<HashMap<FullTrackName, TrackStats>>,
+    pub(crate) cache: TrackCache,
+    pub(crate) acl: NamespaceAcl,
+    /// Objects forwarded to sessions, handed to their shapers in the order
+    /// of the relay's fairness policy.
+    pub(crate) fanout: Mutex<Fanout<(u64, Object)>>,
+    /// Notified when an object is forwarded to a session.
+    pub(crate) forwarded: Notify,
+}
+
+/// What [`Relay::next_object`] released.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Dispatch {
+    /// Send `object` to `session` on subscription `request_id` now.
+    Send {
+        session: SessionId,
+        request_id: u64,
+        object: Object,
+    },
+    /// `count` objects of group `group_id` forwarded to `session` on
+    /// subscription `request_id` were dropped over its bandwidth cap.
+    Dropped {
+        session: SessionId,
+        request_id: u64,
+        group_id: u64,
+        count: usize,
+    },
+    /// No session may be sent anything before the given instant.
+    Wait(Instant),
+    /// Nothing is waiting to be sent.
+    Idle,
+}
+
+/// Shared state of a relay: the connected sessions, what they subscribed
+/// to and announced, and the object cache.
+#[derive(Clone)]
+pub struct Relay {
+    state: Arc<RelayState>,
+    bandwidth: BandwidthLimits,
+    selector: Arc<dyn UpstreamSelector>,
+    prewarmer: Option<Arc<Prewarmer>>,
+    pub(crate) goaway_uri_policy: GoawayUriPolicy,
+    pub(crate) drain_timeout: Duration,
+}
+
+impl Default for Relay {
+    fn default() -> Self {
+        Self {
+            state: Arc::default(),
+            bandwidth: BandwidthLimits::default(),
+            selector: Arc::new(LongestPrefix),
+            prewarmer: None,
+            goaway_uri_policy: GoawayUriPolicy::default(),
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+        }
+    }
+}
+
+impl Relay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Share downstream capacity between the subscribers of a track
+    /// according to `policy` instead of strict priority, deciding which
+    /// session [`next_object`](Self::next_object) serves next.
+    pub fn with_fairness(self, policy: FairnessPolicy) -> Self {
+        *self.state.fanout.lock().unwrap() = Fanout::new(policy);
+        self
+    }
+
+    /// Cap the object bytes released by [`next_object`](Self::next_object)
+    /// for each downstream session and each of its subscriptions. Uncapped
+    /// by default.
+    pub fn with_bandwidth_limits(mut self, limits: BandwidthLimits) -> Self {
+        self.bandwidth = limits;
+        self
+    }
+
+    /// Payload bytes cached across every track, 256 MiB by default. The
+    /// oldest objects of the largest tracks are evicted beyond it.
+    pub fn with_cache_max_bytes(self, max_bytes: usize) -> Self {
+        self.state.cache.set_max_bytes(max_bytes);
+        self
+    }
+
+    /// Pick upstreams with `selector` instead of [`LongestPrefix`].
+    /// Fetch the latest groups of the tracks configured in `config` from
+    /// `upstream` into the cache whenever a session announces a matching
+    /// namespace, see [`SessionHandle::add_namespace`].
+    pub fn with_prewarm(mut self, upstream: Arc<dyn Upstream>, config: PrewarmConfig) -> Self {
+        self.prewarmer = Some(Arc::new(Prewarmer::new(self.clone(), upstream, config)));
+        self
+    }
+
+    pub fn with_upstream_selector(mut self, selector: impl UpstreamSelector + 'static) -> Self {
+        self.selector = Arc::new(selector);
+        self
+    }
+
+    /// New Session URIs the relay migrates upstreams to, see
+    /// [`migrate_upstream`](Self::migrate_upstream). `https` and `moqt`
+    /// URIs by default.
+    pub fn with_goaway_uri_policy(mut self, policy: GoawayUriPolicy) -> Self {
+        self.goaway_uri_policy = policy;
+        self
+    }
+
+    /// How long a migrated track keeps waiting for the old upstream to
+    /// reach the first group of the new one, see
+    /// [`migrate_upstream`](Self::migrate_upstream). 5 seconds by default.
+    pub fn with_drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    /// Session to forward requests for `namespace` to, chosen by the
+    /// upstream selector among the sessions that announced a prefix of it.
+    pub fn select_upstream(&self, namespace: &[String]) -> Option<SessionId> {
+        let candidates: Vec<_> = self
+            .state
+            .sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(id, entry)| {
+                let announced = entry
+                    .namespaces
+                    .iter()
+                    .filter(|n| is_namespace_prefix(n, namespace))
+                    .max_by_key(|n| n.len())?;
+                Some(Candidate {
+                    id: *id,
+                    remote: entry.remote.clone(),
+                    namespace: announced.clone(),
+                    rtt: entry.rtt,
+                })
+            })
+            .collect();
+        self.selector.select(namespace, &candidates)
+    }
+
+    /// Register a newly accepted session. The session is unregistered when
+    /// the returned handle is dropped.
+    pub fn register_session(&self, remote: impl Into<String>) -> SessionHandle {
+        let id = {
+            let mut next = self.state.next_session.lock().unwrap();
+            *next += 1;
+            *next
+        };
+        let kick = CancellationToken::new();
+        self.state.sessions.lock().unwrap().insert(
+            id,
+            SessionEntry {
+                remote: remote.into(),
+                subscriptions: Vec::new(),
+                namespaces: Vec::new(),
+                rtt: None,
+                kick: kick.clone(),
+                forwards: Vec::new(),
+                outbound: Shaper::new(self.bandwidth, Instant::now()),
+            },
+        );
+        SessionHandle {
+            id,
+            kick,
+            state: self.state.clone(),
+            prewarmer: self.prewarmer.clone(),
+        }
+    }
+
+    /// Record an object arriving from upstream and forward it to the
+    /// sessions forwarding the track, see [`next_object`](Self::next_object).
+    /// Returns `false` if the same object of the track was already
+    /// received, e.g. from another upstream during failover; the duplicate
+    /// is neither cached, counted towards throughput nor forwarded. The
+    /// cache is bounded, see [`with_cache_max_bytes`](Self::with_cache_max_bytes).
+    pub fn record_object(&self, track: &FullTrackName, object: Object) -> bool {
+        let bytes = object.payload.len() as u64;
+        let fresh = self.state.cache.insert(track, object.clone());
+        if fresh {
+            self.forward(track, object);
+        }
+
+        let mut stats = self.state.stats.lock().unwrap();
+        let stats = stats.entry(track.clone()).or_insert_with(|| TrackStats {
+            objects: 0,
+            bytes: 0,
+            duplicates: 0,
+            since: Instant::now(),
+        });
+        if fresh {
+            stats.objects += 1;
+            stats.bytes += bytes;
+        } else {
+            stats.duplicates += 1;
+        }
+        fresh
+    }
+
+    /// Record a SUBSCRIBE_OK, PUBLISH or FETCH_OK of the track received
+    /// from upstream, limiting how long its objects are cached to the
+    /// MAX_CACHE_DURATION it carries, or lifting the limit if it has none.
+    /// Other messages are ignored.
+    pub fn record_response(
+        &self,
+        track: &FullTrackName,
+        msg: &ControlMessage,
+    ) -> Result<(), Error> {
+        let parameters = match msg {
+            ControlMessage::SubscribeOk(m) => &m.parameters,
+            ControlMessage::Publish(m) => &m.parameters,
+            ControlMessage::FetchOk(m) => &m.parameters,
+            _ => return Ok(()),
+        };
+        let max = max_cache_duration(parameters)?;
+        self.state.cache.set_max_cache_duration(track, max);
+        Ok(())
+    }
+
+    fn forward(&self, track: &FullTrackName, object: Object) {
+        let sessions = self.state.sessions.lock().unwrap();
+        let mut fanout = self.state.fanout.lock().unwrap();
+        let mut forwarded = false;
+        for (&session, entry) in sessions.iter() {
+            for forward in entry.forwards.iter().filter(|f| &f.track == track) {
+                let size = object.payload.len();
+                let item = (forward.request_id, object.clone());
+                fanout.push(session, forward.priority(&object), item, size);
+                forwarded = true;
+            }
+        }
+        if forwarded {
+            self.state.forwarded.notify_one();
+        }
+    }
+
+    /// Release the next object forwarded to a session, or tell why none may
+    /// be sent. Objects are taken in the order of the relay's fairness
+    /// policy, then held by their session's shaper until its bandwidth
+    /// limits let them go.
+    pub fn next_object(&self, now: Instant) -> Dispatch {
+        let mut sessions = self.state.sessions.lock().unwrap();
+        let mut fanout = self.state.fanout.lock().unwrap();
+        loop {
+            let mut wait: Option<Instant> = None;
+            for (&session, entry) in sessions.iter_mut() {
+                match entry.outbound.pop(now) {
+                    Release::Send { request_id, item } => {
+                        return Dispatch::Send {
+                            session,
+                            request_id,
+                            object: item,
+                        };
+                    }
+                    Release::Dropped {
+                        request_id,
+                        group_id,
+                        count,
+                    } => {
+                        return Dispatch::Dropped {
+                            session,
+                            request_id,
+                            group_id,
+                            count,
+                        };
+                    }
+                    Release::Wait(at) => wait = Some(wait.map_or(at, |w| w.min(at))),
+                    Release::Idle => {}
+                }
+            }
+            // Nothing may be sent right away: hand the next object to its
+            // session, unless the session or its subscription went away.
+            let Some((session, (request_id, object))) = fanout.pop() else {
+                return wait.map_or(Dispatch::Idle, Dispatch::Wait);
+            };
+            let Some(entry) = sessions.get_mut(&session) else {
+                continue;
+            };
+            if let Some(forward) = entry.forwards.iter().find(|f| f.request_id == request_id) {
+                let priority = forward.priority(&object);
+                let size = object.payload.len();
+                entry.outbound.push(request_id, priority, object, size, now);
+            }
+        }
+    }
+
+    /// Resolves once an object was forwarded to a session since the last
+    /// time it resolved, for the task sending them to call
+    /// [`next_object`](Self::next_object) again.
+    pub async fn forwarded(&self) {
+        self.state.forwarded.notified().await
+    }
+
+    pub fn cache(&self) -> &TrackCache {
+        &self.state.cache
+    }
+
+    /// Namespace access control, to be installed as the authorizer of
+    /// every accepted session. Allows everything until configured.
+    pub fn acl(&self) -> NamespaceAcl {
+        self.state.acl.clone()
+    }
+
+    /// Administrative view of the relay.
+    pub fn admin(&self) -> Admin {
+        Admin::new(self.state.clone())
+    }
+}
+
+/// A session's registration with the relay.
+pub struct SessionHandle {
+    id: SessionId,
+    kick: CancellationToken,
+    state: Arc<RelayState>,
+    prewarmer: Option<Arc<Prewarmer>>,
+}
+
+impl SessionHandle {
+    pub fn id(&self) -> SessionId {
+        self.id
+    }
+
+    /// Resolves once an administrator kicked the session. The session
+    /// task is expected to close the connection.
+    pub fn kicked(&self) -> WaitForCancellationFuture<'_> {
+        self.kick.cancelled()
+    }
+
+    pub fn is_kicked(&self) -> bool {
+        self.kick.is_cancelled()
+    }
+
+    fn with_entry(&self, f: impl FnOnce(&mut SessionEntry)) {
+        if let Some(entry) = self.state.sessions.lock().unwrap().get_mut(&self.id) {
+            f(entry);
+        }
+    }
+
+    pub fn add_subscription(&self, track: FullTrackName) {
+        self.with_entry(|e| e.subscriptions.push(track));
+    }
+
+    pub fn remove_subscription(&self, track: &FullTrackName) {
+        self.with_entry(|e| e.subscriptions.retain(|t| t != track));
+    }
+
+    /// Forward the objects recorded for `track` from now on to this session
+    /// as subscription `request_id`, sent in the order `subscriber_priority`
+    /// gives them, see [`Relay::next_object`].
+    pub fn forward(&self, request_id: u64, track: FullTrackName, subscriber_priority: u8) {
+        self.with_entry(|e| {
+            e.forwards.push(Forward {
+                request_id,
+                track,
+                subscriber_priority,
+            })
+        });
+    }
+
+    /// Stop forwarding to subscription `request_id`, dropping the objects
+    /// still queued for it.
+    pub fn stop_forwarding(&self, request_id: u64) {
+        self.with_entry(|e| {
+            e.forwards.retain(|f| f.request_id != request_id);
+            e.outbound.remove_subscription(request_id);
+        });
+    }
+
+    /// Share of the session under the relay's round-robin fairness
+    /// policies, 1 by default.
+    pub fn set_fanout_weight(&self, weight: usize) {
+        self.state
+            .fanout
+            .lock()
+            .unwrap()
+            .set_weight(self.id, weight);
+    }
+
+    /// Cap subscription `request_id` at `rate` instead of the relay's
+    /// default subscription rate, or lift its cap with `None`.
+    pub fn set_subscription_rate(&self, request_id: u64, rate: Option<Rate>) {
+        self.with_entry(|e| {
+            e.outbound
+                .set_subscription_rate(request_id, rate, Instant::now())
+        });
+    }
+
+    /// Record a namespace announced by the session. On relays configured
+    /// with [`Relay::with_prewarm`] the matching tracks are prewarmed in a
+    /// spawned task, so the ANNOUNCE is answered without waiting for it.
+    pub fn add_namespace(&self, namespace: Vec<String>) {
+        if let Some(prewarmer) = self.prewarmer.clone() {
+            let namespace = namespace.clone();
+            tokio::spawn(async move {
+                // Subscribers fall back to upstream if prewarming failed.
+                let _ = prewarmer.on_announce(&namespace).await;
+            });
+        }
+        self.with_entry(|e| e.namespaces.push(namespace));
+    }
+
+    pub fn remove_namespace(&self, namespace: &[String]) {
+        self.with_entry(|e| e.namespaces.retain(|n| n != namespace));
+    }
+
+    /// Report the session's current round trip time, used by latency based
+    /// upstream selection.
+    pub fn set_rtt(&self, rtt: Duration) {
+        self.with_entry(|e| e.rtt = Some(rtt));
+    }
+}
+
+impl Drop for SessionHandle {
+    fn drop(&mut self) {
+        self.state.sessions.lock().unwrap().remove(&self.id);
+        self.state.fanout.lock().unwrap().remove_session(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use moqt_transport::mock::ObjectBuilder;
+
+    use super::*;
+
+    fn object(object_id: u64, size: usize) -> Object {
+        ObjectBuilder::new(0, object_id)
+            .with_payload(vec![0; size])
+            .build()
+    }
+
+    #[test]
+    fn recorded_objects_are_forwarded_within_session_rate() {
+        let relay = Relay::new()
+            .with_bandwidth_limits(BandwidthLimits::default().with_session_rate(Rate::new(1000)));
+        let session = relay.register_session("peer");
+        let other = relay.register_session("other");
+        session.forward(7, "video".into(), 128);
+        other.forward(3, "audio".into(), 128);
+
+        let track = "video".to_string();
+        assert!(relay.record_object(&track, object(0, 800)));
+        assert!(relay.record_object(&track, object(1, 800)));
+        assert!(!relay.record_object(&track, object(1, 800)));
+
+        let now = Instant::now();
+        match relay.next_object(now) {
+            Dispatch::Send {
+                session: id,
+                request_id: 7,
+                object,
+            } => {
+                assert_eq!(id, session.id());
+                assert_eq!(object.metadata.object_id, 0);
+            }
+            d => panic!("unexpected dispatch: {d:?}"),
+        }
+        let Dispatch::Wait(at) = relay.next_object(now) else {
+            panic!("second object is over the session rate");
+        };
+        assert!(at > now);
+        assert!(matches!(
+            relay.next_object(at),
+            Dispatch::Send { object, .. } if object.metadata.object_id == 1
+        ));
+        assert_eq!(relay.next_object(at), Dispatch::Idle);
+    }
+
+    #[test]
+    fn stopped_forwards_drop_queued_objects() {
+        let relay = Relay::new();
+        let session = relay.register_session("peer");
+        session.forward(7, "video".into(), 128);
+        relay.record_object(&"video".to_string(), object(0, 10));
+
+        session.stop_forwarding(7);
+        assert_eq!(relay.next_object(Instant::now()), Dispatch::Idle);
+        relay.record_object(&"video".to_string(), object(1, 10));
+        assert_eq!(relay.next_object(Instant::now()), Dispatch::Idle);
+    }
+
+    fn sessions_served(relay: &Relay, n: usize) -> Vec<SessionId> {
+        (0..n)
+            .map(|_| match relay.next_object(Instant::now()) {
+                Dispatch::Send { session, .. } => session,
+                d => panic!("unexpected dispatch: {d:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn forwarded_objects_follow_fairness_policy() {
+        for (policy, alternate) in [
+            (FairnessPolicy::StrictPriority, false),
+            (FairnessPolicy::WeightedRoundRobin, true),
+        ] {
+            let relay = Relay::new().with_fairness(policy);
+            let high = relay.register_session("high");
+            let low = relay.register_session("low");
+            high.forward(1, "video".into(), 0);
+            low.forward(1, "video".into(), 255);
+            for object_id in 0..2 {
+                relay.record_object(&"video".to_string(), object(object_id, 10));
+            }
+
+            let (h, l) = (high.id(), low.id());
+            let expected = if alternate {
+                [h, l, h, l]
+            } else {
+                [h, h, l, l]
+            };
+            assert_eq!(sessions_served(&relay, 4), expected, "{policy:?}");
+            assert_eq!(relay.next_object(Instant::now()), Dispatch::Idle);
+        }
+    }
+}
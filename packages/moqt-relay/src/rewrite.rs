@@ -0,0 +1,712 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use moqt_transport::{
+    codec::VarInt,
+    data::{StreamType, SubgroupHeader},
+    error::Error,
+    message::ControlMessage,
+};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// The session a forwarded control message was received on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    /// A session of the relay's subscribers.
+    Downstream,
+    /// A session to a publisher or to the next relay.
+    Upstream,
+}
+
+impl Side {
+    pub fn other(self) -> Self {
+        match self {
+            Side::Downstream => Side::Upstream,
+            Side::Upstream => Side::Downstream,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Request IDs and Track Aliases the relay allocates on one session.
+/// Clones share the sequences, so every [`RequestTranslator`] involving the
+/// session should be given a clone of the same `SessionIds`.
+#[derive(Debug, Clone)]
+pub struct SessionIds {
+    next_request_id: Arc<AtomicU64>,
+    next_track_alias: Arc<AtomicU64>,
+}
+
+impl SessionIds {
+    /// Sequences of the relay acting as client of the session, whose
+    /// Request IDs are even, or as its server, whose Request IDs are odd.
+    pub fn new(is_client: bool) -> Self {
+        Self {
+            next_request_id: Arc::new(AtomicU64::new(if is_client { 0 } else { 1 })),
+            next_track_alias: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn next_request_id(&self) -> u64 {
+        self.next_request_id.fetch_add(2, Ordering::Relaxed)
+    }
+
+    pub fn next_track_alias(&self) -> u64 {
+        self.next_track_alias.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+/// The beginning of a data stream with its Track Alias or Request ID
+/// rewritten by [`RequestTranslator::patch_stream_header`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchedHeader {
+    /// What to send in place of the first `replaced` bytes of the stream.
+    pub header: Bytes,
+    pub replaced: usize,
+}
+
+/// Result of [`RequestTranslator::patch_stream_header`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderPatch {
+    Patched(PatchedHeader),
+    /// The track or request is not known (yet), e.g. objects arriving
+    /// before their SUBSCRIBE_OK, which the caller may buffer or drop.
+    Unknown,
+    /// The bytes received so far end within the header; call again once
+    /// more of the stream arrived.
+    Incomplete,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Discovery {
+    Announce,
+    SubscribeAnnounces,
+}
+
+/// A namespace request, by the side that opened it.
+type NamespaceKey = (Discovery, Side, Vec<String>);
+
+struct Request {
+    /// Request ID on the downstream and on the upstream session.
+    ids: [u64; 2],
+    /// Track Alias on the downstream and on the upstream session, once
+    /// the publisher chose one.
+    aliases: Option<[u64; 2]>,
+    namespace: Option<NamespaceKey>,
+}
+
+/// Rewrites the Request IDs and Track Aliases of the control messages a
+/// relay forwards between a downstream and an upstream session.
+///
+/// Each session has its own Request ID and Track Alias spaces, so a
+/// request opened on one session is forwarded with an ID the relay
+/// allocates on the other, and the Track Alias chosen by the publisher is
+/// replaced by one the relay allocates likewise. Responses and follow-up
+/// messages are mapped back in either direction, and so are the headers
+/// of data streams and datagrams of the tracks being forwarded.
+///
+/// A request is forgotten once it ends: with an error response,
+/// SUBSCRIBE_DONE, TRACK_STATUS, UNANNOUNCE, ANNOUNCE_CANCEL or
+/// UNSUBSCRIBE_ANNOUNCES. A FETCH ends with its data stream, which is
+/// reported with [`complete`](Self::complete).
+pub struct RequestTranslator {
+    ids: [SessionIds; 2],
+    /// Forwarded requests by downstream Request ID.
+    requests: HashMap<u64, Request>,
+    /// Downstream Request ID by upstream Request ID.
+    upstream: HashMap<u64, u64>,
+    /// Downstream Request ID by Track Alias, per side.
+    aliases: [HashMap<u64, u64>; 2],
+    namespaces: HashMap<NamespaceKey, u64>,
+}
+
+impl RequestTranslator {
+    /// Translator between the sessions whose relay allocated IDs are
+    /// `downstream` and `upstream`.
+    pub fn new(downstream: SessionIds, upstream: SessionIds) -> Self {
+        Self {
+            ids: [downstream, upstream],
+            requests: HashMap::new(),
+            upstream: HashMap::new(),
+            aliases: [HashMap::new(), HashMap::new()],
+            namespaces: HashMap::new(),
+        }
+    }
+
+    /// Rewrite `msg`, received on the `from` side, to be sent on the other.
+    ///
+    /// Returns `None` for UNSUBSCRIBE, SUBSCRIBE_UPDATE and FETCH_CANCEL of
+    /// a request that already ended, e.g. crossing its SUBSCRIBE_DONE, as
+    /// there is nothing left to forward them to. Other messages referring
+    /// to an unknown request, and requests reusing a Request ID, are
+    /// protocol violations. Messages not referring to a request are
+    /// returned unchanged.
+    pub fn translate(
+        &mut self,
+        from: Side,
+        mut msg: ControlMessage,
+    ) -> Result<Option<ControlMessage>, Error> {
+        let to = from.other();
+        let ended = match &msg {
+            ControlMessage::Unannounce(m) => {
+                Some((Discovery::Announce, from, m.track_namespace.clone()))
+            }
+            ControlMessage::AnnounceCancel(m) => {
+                Some((Discovery::Announce, to, m.track_namespace.clone()))
+            }
+            ControlMessage::UnsubscribeAnnounces(m) => Some((
+                Discovery::SubscribeAnnounces,
+                from,
+                m.track_namespace_prefix.clone(),
+            )),
+            _ => None,
+        };
+        if let Some(namespace) = ended {
+            if let Some(key) = self.namespaces.get(&namespace) {
+                self.remove(*key);
+            }
+            return Ok(Some(msg));
+        }
+        let Some(request_id) = msg.request_id() else {
+            return Ok(Some(msg));
+        };
+
+        if let ControlMessage::Fetch(fetch) = &mut msg
+            && let Some(joining) = &mut fetch.joining_request_id
+        {
+            *joining = self
+                .request_id(from, *joining)
+                .ok_or_else(|| unknown_request(*joining))?;
+        }
+        let key = if msg.message_type().is_some_and(|t| t.is_request()) {
+            self.open(from, request_id, &msg)?
+        } else {
+            match self.key(from, request_id) {
+                Some(key) => key,
+                None if matches!(
+                    msg,
+                    ControlMessage::Unsubscribe(_)
+                        | ControlMessage::SubscribeUpdate(_)
+                        | ControlMessage::FetchCancel(_)
+                ) =>
+                {
+                    return Ok(None);
+                }
+                None => return Err(unknown_request(request_id)),
+            }
+        };
+        *msg.request_id_mut().expect("message has a Request ID") =
+            self.requests[&key].ids[to.index()];
+
+        let track_alias = match &mut msg {
+            ControlMessage::Publish(m) => Some(&mut m.track_alias),
+            ControlMessage::SubscribeOk(m) => Some(&mut m.track_alias),
+            _ => None,
+        };
+        if let Some(track_alias) = track_alias {
+            match self.assign_alias(key, from, *track_alias) {
+                Ok(alias) => *track_alias = alias,
+                Err(e) => {
+                    self.remove(key);
+                    return Err(e);
+                }
+            }
+        }
+
+        if matches!(
+            msg,
+            ControlMessage::SubscribeError(_)
+                | ControlMessage::SubscribeDone(_)
+                | ControlMessage::PublishError(_)
+                | ControlMessage::FetchError(_)
+                | ControlMessage::TrackStatus(_)
+                | ControlMessage::AnnounceError(_)
+                | ControlMessage::SubscribeAnnouncesError(_)
+        ) {
+            self.remove(key);
+        }
+        Ok(Some(msg))
+    }
+
+    /// The Request ID on the other side of the request `request_id` on the
+    /// `from` side, e.g. to rewrite the FETCH_HEADER of a forwarded fetch.
+    pub fn request_id(&self, from: Side, request_id: u64) -> Option<u64> {
+        let key = self.key(from, request_id)?;
+        Some(self.requests[&key].ids[from.other().index()])
+    }
+
+    /// The Track Alias on the other side of the track aliased `track_alias`
+    /// on the `from` side, to forward its objects.
+    pub fn track_alias(&self, from: Side, track_alias: u64) -> Option<u64> {
+        let key = self.aliases[from.index()].get(&track_alias)?;
+        Some(self.requests[key].aliases?[from.other().index()])
+    }
+
+    /// Rewrite the Track Alias of the SUBGROUP_HEADER, or the Request ID of
+    /// the FETCH_HEADER, at the beginning of a data stream received on the
+    /// `from` side.
+    ///
+    /// Only the Type and the rewritten field are re-encoded; the rest of the
+    /// stream, from `replaced` on, is forwarded as is. `data` may be just
+    /// the first bytes received; as QUIC delivers a stream in arbitrary
+    /// pieces, a header split between them is reported as
+    /// [`HeaderPatch::Incomplete`].
+    pub fn patch_stream_header(&self, from: Side, data: &[u8]) -> Result<HeaderPatch, Error> {
+        let Some((stream_type, id, replaced)) = leading_varints(data)? else {
+            return Ok(HeaderPatch::Incomplete);
+        };
+        let id = if SubgroupHeader::is_subgroup_type(stream_type) {
+            self.track_alias(from, id)
+        } else if stream_type == StreamType::FetchHeader as u64 {
+            self.request_id(from, id)
+        } else {
+            return Err(Error::ProtocolViolation {
+                reason: format!("unknown stream type {stream_type:#x}"),
+                context: None,
+            });
+        };
+        let Some(id) = id else {
+            return Ok(HeaderPatch::Unknown);
+        };
+        let mut header = BytesMut::with_capacity(16);
+        VarInt.encode(stream_type, &mut header)?;
+        VarInt.encode(id, &mut header)?;
+        Ok(HeaderPatch::Patched(PatchedHeader {
+            header: header.freeze(),
+            replaced,
+        }))
+    }
+
+    /// Rewrite the Track Alias of an OBJECT_DATAGRAM or
+    /// OBJECT_DATAGRAM_STATUS received on the `from` side, copying the rest
+    /// of the datagram unparsed. Returns `None` for an unknown track.
+    pub fn patch_datagram(&self, from: Side, datagram: &[u8]) -> Result<Option<Bytes>, Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        let (datagram_type, track_alias, replaced) = leading_varints(datagram)?
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "datagram header"))?;
+        if datagram_type > 0x05 {
+            return Err(Error::ProtocolViolation {
+                reason: format!("unknown datagram type {datagram_type:#x}"),
+                context: None,
+            });
+        }
+        let Some(track_alias) = self.track_alias(from, track_alias) else {
+            return Ok(None);
+        };
+        let mut patched = BytesMut::with_capacity(datagram.len() + 8);
+        VarInt.encode(datagram_type, &mut patched)?;
+        VarInt.encode(track_alias, &mut patched)?;
+        patched.put_slice(&datagram[replaced..]);
+        Ok(Some(patched.freeze()))
+    }
+
+    /// Forget the request `request_id` on the `from` side, e.g. a FETCH
+    /// whose data stream ended. Returns whether it was known.
+    pub fn complete(&mut self, from: Side, request_id: u64) -> bool {
+        match self.key(from, request_id) {
+            Some(key) => {
+                self.remove(key);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Number of requests being forwarded.
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    fn key(&self, from: Side, request_id: u64) -> Option<u64> {
+        match from {
+            Side::Downstream => self
+                .requests
+                .contains_key(&request_id)
+                .then_some(request_id),
+            Side::Upstream => self.upstream.get(&request_id).copied(),
+        }
+    }
+
+    fn open(&mut self, from: Side, request_id: u64, msg: &ControlMessage) -> Result<u64, Error> {
+        if self.key(from, request_id).is_some() {
+            return Err(Error::ProtocolViolation {
+                reason: format!("Request ID {request_id} already in use"),
+                context: None,
+            });
+        }
+        let mut ids = [request_id; 2];
+        ids[from.other().index()] = self.ids[from.other().index()].next_request_id();
+        let [key, upstream] = ids;
+
+        let namespace = match msg {
+            ControlMessage::Announce(m) => {
+                Some((Discovery::Announce, from, m.track_namespace.clone()))
+            }
+            ControlMessage::SubscribeAnnounces(m) => Some((
+                Discovery::SubscribeAnnounces,
+                from,
+                m.track_namespace_prefix.clone(),
+            )),
+            _ => None,
+        };
+        if let Some(namespace) = &namespace {
+            self.namespaces.insert(namespace.clone(), key);
+        }
+        self.requests.insert(
+            key,
+            Request {
+                ids,
+                aliases: None,
+                namespace,
+            },
+        );
+        self.upstream.insert(upstream, key);
+        Ok(key)
+    }
+
+    /// Map the alias the publisher on the `from` side chose to one
+    /// allocated on the other side.
+    fn assign_alias(&mut self, key: u64, from: Side, track_alias: u64) -> Result<u64, Error> {
+        if self.aliases[from.index()].contains_key(&track_alias) {
+            return Err(Error::DuplicateTrackAlias(track_alias));
+        }
+        let to = from.other();
+        let mut aliases = [track_alias; 2];
+        aliases[to.index()] = self.ids[to.index()].next_track_alias();
+        let request = self.requests.get_mut(&key).expect("request is open");
+        if let Some(old) = request.aliases.replace(aliases) {
+            for side in [Side::Downstream, Side::Upstream] {
+                self.aliases[side.index()].remove(&old[side.index()]);
+            }
+        }
+        for side in [Side::Downstream, Side::Upstream] {
+            self.aliases[side.index()].insert(aliases[side.index()], key);
+        }
+        Ok(aliases[to.index()])
+    }
+
+    fn remove(&mut self, key: u64) {
+        let Some(request) = self.requests.remove(&key) else {
+            return;
+        };
+        self.upstream.remove(&request.ids[Side::Upstream.index()]);
+        if let Some(aliases) = request.aliases {
+            for side in [Side::Downstream, Side::Upstream] {
+                self.aliases[side.index()].remove(&aliases[side.index()]);
+            }
+        }
+        if let Some(namespace) = request.namespace {
+            self.namespaces.remove(&namespace);
+        }
+    }
+}
+
+/// Decode the Type and the following varint of a data stream or datagram,
+/// with the number of bytes they take, or `None` if `data` ends before.
+fn leading_varints(data: &[u8]) -> Result<Option<(u64, u64, usize)>, Error> {
+    let mut buf = BytesMut::from(&data[..data.len().min(16)]);
+    let len = buf.len();
+    let Some(ty) = VarInt.decode(&mut buf)? else {
+        return Ok(None);
+    };
+    let Some(id) = VarInt.decode(&mut buf)? else {
+        return Ok(None);
+    };
+    Ok(Some((ty, id, len - buf.len())))
+}
+
+fn unknown_request(request_id: u64) -> Error {
+    Error::ProtocolViolation {
+        reason: format!("unknown Request ID {request_id}"),
+        context: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use moqt_transport::{
+        data::{FetchHeader, ObjectDatagram, SubgroupId},
+        message::{
+            AnnounceOk, Fetch, FetchOk, Publish, PublishError, SubscribeDone, SubscribeOk,
+            Unannounce, Unsubscribe,
+        },
+        model::Location,
+        request::{AnnounceRequest, SubscribeRequest},
+    };
+
+    use super::*;
+
+    /// Translator of a relay that is the server of the downstream session
+    /// and the client of the upstream one.
+    fn translator() -> RequestTranslator {
+        RequestTranslator::new(SessionIds::new(false), SessionIds::new(true))
+    }
+
+    fn subscribe_ok(request_id: u64, track_alias: u64) -> ControlMessage {
+        ControlMessage::SubscribeOk(SubscribeOk {
+            request_id,
+            track_alias,
+            expires: 0,
+            group_order: 1,
+            content_exists: false,
+            largest_location: None,
+            parameters: Vec::new(),
+        })
+    }
+
+    fn forward(
+        translator: &mut RequestTranslator,
+        from: Side,
+        msg: ControlMessage,
+    ) -> ControlMessage {
+        translator.translate(from, msg).unwrap().unwrap()
+    }
+
+    #[test]
+    fn subscription_is_mapped_both_ways_and_forgotten() {
+        let mut t = translator();
+        let subscribe = SubscribeRequest::new(0, "video").into_subscribe(8).unwrap();
+        let up = forward(
+            &mut t,
+            Side::Downstream,
+            ControlMessage::Subscribe(subscribe),
+        );
+        assert_eq!(up.request_id(), Some(0));
+        assert_eq!(t.request_id(Side::Upstream, 0), Some(8));
+
+        let ControlMessage::SubscribeOk(ok) = forward(&mut t, Side::Upstream, subscribe_ok(0, 42))
+        else {
+            panic!("expected SUBSCRIBE_OK");
+        };
+        assert_eq!((ok.request_id, ok.track_alias), (8, 0));
+        assert_eq!(t.track_alias(Side::Upstream, 42), Some(0));
+        assert_eq!(t.track_alias(Side::Downstream, 0), Some(42));
+
+        let unsubscribe = ControlMessage::Unsubscribe(Unsubscribe { request_id: 8 });
+        let up = forward(&mut t, Side::Downstream, unsubscribe.clone());
+        assert_eq!(up.request_id(), Some(0));
+        let done = ControlMessage::SubscribeDone(SubscribeDone {
+            request_id: 0,
+            status_code: 0,
+            stream_count: 0,
+            reason: String::new(),
+        });
+        assert_eq!(forward(&mut t, Side::Upstream, done).request_id(), Some(8));
+        assert!(t.is_empty());
+        assert_eq!(t.track_alias(Side::Upstream, 42), None);
+
+        // Late messages of the ended subscription.
+        assert!(
+            t.translate(Side::Downstream, unsubscribe)
+                .unwrap()
+                .is_none()
+        );
+        assert!(t.translate(Side::Upstream, subscribe_ok(0, 42)).is_err());
+    }
+
+    #[test]
+    fn requests_from_upstream_get_downstream_ids() {
+        let mut t = translator();
+        let publish = ControlMessage::Publish(Publish {
+            request_id: 1,
+            track_namespace: 0,
+            track_name: "audio".into(),
+            track_alias: 7,
+            group_order: 1,
+            content_exists: 0,
+            largest: None,
+            forward: 1,
+            parameters: Vec::new(),
+        });
+        let ControlMessage::Publish(down) = forward(&mut t, Side::Upstream, publish.clone()) else {
+            panic!("expected PUBLISH");
+        };
+        assert_eq!((down.request_id, down.track_alias), (1, 0));
+        assert!(matches!(
+            t.translate(Side::Upstream, publish),
+            Err(Error::ProtocolViolation { .. })
+        ));
+
+        let error = ControlMessage::PublishError(PublishError {
+            request_id: 1,
+            error_code: 0,
+            error_reason: String::new(),
+        });
+        assert_eq!(
+            forward(&mut t, Side::Downstream, error).request_id(),
+            Some(1)
+        );
+        assert!(t.is_empty());
+    }
+
+    #[test]
+    fn announce_ends_with_unannounce() {
+        let mut t = translator();
+        let ns = vec!["live".to_string()];
+        let announce = AnnounceRequest::new(ns.clone()).into_announce(2).unwrap();
+        forward(&mut t, Side::Downstream, ControlMessage::Announce(announce));
+        let ok = ControlMessage::AnnounceOk(AnnounceOk { request_id: 0 });
+        assert_eq!(forward(&mut t, Side::Upstream, ok).request_id(), Some(2));
+        assert_eq!(t.len(), 1);
+
+        let unannounce = ControlMessage::Unannounce(Unannounce {
+            track_namespace: ns,
+        });
+        forward(&mut t, Side::Downstream, unannounce);
+        assert!(t.is_empty());
+    }
+
+    #[test]
+    fn joining_fetch_refers_to_translated_subscription() {
+        let mut t = translator();
+        let subscribe = SubscribeRequest::new(0, "video").into_subscribe(2).unwrap();
+        forward(
+            &mut t,
+            Side::Downstream,
+            ControlMessage::Subscribe(subscribe),
+        );
+        let fetch = |joining_request_id| {
+            ControlMessage::Fetch(Fetch {
+                request_id: 4,
+                subscriber_priority: 128,
+                group_order: 0,
+                fetch_type: 0x2,
+                track_namespace: None,
+                track_name: None,
+                start_location: None,
+                end_location: None,
+                joining_request_id: Some(joining_request_id),
+                joining_start: Some(0),
+                parameters: Vec::new(),
+            })
+        };
+        assert!(t.translate(Side::Downstream, fetch(6)).is_err());
+        assert_eq!(t.len(), 1);
+
+        let ControlMessage::Fetch(up) = forward(&mut t, Side::Downstream, fetch(2)) else {
+            panic!("expected FETCH");
+        };
+        assert_eq!((up.request_id, up.joining_request_id), (2, Some(0)));
+        let ok = ControlMessage::FetchOk(FetchOk {
+            request_id: 2,
+            group_order: 1,
+            end_of_track: false,
+            end_location: Location::new(0, 0),
+            parameters: Vec::new(),
+        });
+        forward(&mut t, Side::Upstream, ok);
+        assert!(t.complete(Side::Upstream, 2));
+        assert_eq!(t.request_id(Side::Downstream, 4), None);
+        assert_eq!(t.len(), 1);
+    }
+
+    #[test]
+    fn data_headers_are_patched_in_place() {
+        let mut t = translator();
+        let subscribe = SubscribeRequest::new(0, "video").into_subscribe(8).unwrap();
+        forward(
+            &mut t,
+            Side::Downstream,
+            ControlMessage::Subscribe(subscribe),
+        );
+
+        let header = SubgroupHeader {
+            track_alias: 300,
+            group_id: 5,
+            subgroup_id: SubgroupId::Explicit(1),
+            publisher_priority: 7,
+            extensions_present: false,
+            end_of_group: true,
+        };
+        let mut stream = BytesMut::new();
+        header.encode(&mut stream).unwrap();
+        stream.put_slice(b"objects");
+        assert_eq!(
+            t.patch_stream_header(Side::Upstream, &stream).unwrap(),
+            HeaderPatch::Unknown
+        );
+
+        forward(&mut t, Side::Upstream, subscribe_ok(0, 300));
+        let HeaderPatch::Patched(patched) = t.patch_stream_header(Side::Upstream, &stream).unwrap()
+        else {
+            panic!("expected a patched header");
+        };
+        let mut downstream = BytesMut::from(&patched.header[..]);
+        downstream.put_slice(&stream[patched.replaced..]);
+        let decoded = SubgroupHeader::decode(&mut downstream).unwrap();
+        assert_eq!(
+            decoded,
+            SubgroupHeader {
+                track_alias: 0,
+                ..header
+            }
+        );
+        assert_eq!(&downstream[..], b"objects");
+
+        let datagram = ObjectDatagram {
+            track_alias: 300,
+            group_id: 5,
+            object_id: 2,
+            publisher_priority: 7,
+            end_of_group: false,
+            extension_headers: Bytes::new(),
+            object_status: None,
+            payload: Bytes::from_static(b"frame"),
+        };
+        let mut buf = BytesMut::new();
+        datagram.encode(&mut buf).unwrap();
+        let patched = t.patch_datagram(Side::Upstream, &buf).unwrap().unwrap();
+        let decoded = ObjectDatagram::decode(&mut BytesMut::from(&patched[..])).unwrap();
+        assert_eq!(
+            decoded,
+            ObjectDatagram {
+                track_alias: 0,
+                ..datagram
+            }
+        );
+        assert!(t.patch_datagram(Side::Upstream, &[0x06, 0x00]).is_err());
+    }
+
+    #[test]
+    fn fetch_header_gets_downstream_request_id() {
+        let mut t = translator();
+        let subscribe = SubscribeRequest::new(0, "video").into_subscribe(9).unwrap();
+        forward(
+            &mut t,
+            Side::Downstream,
+            ControlMessage::Subscribe(subscribe),
+        );
+
+        let mut stream = BytesMut::new();
+        FetchHeader { request_id: 0 }.encode(&mut stream).unwrap();
+        let HeaderPatch::Patched(patched) = t.patch_stream_header(Side::Upstream, &stream).unwrap()
+        else {
+            panic!("expected a patched header");
+        };
+        assert_eq!(patched.replaced, stream.len());
+        let decoded = FetchHeader::decode(&mut BytesMut::from(&patched.header[..])).unwrap();
+        assert_eq!(decoded.request_id, 9);
+
+        // A header split across reads needs more bytes, not an error.
+        for split in [&[][..], &[0x05], &[0x05, 0x40]] {
+            assert_eq!(
+                t.patch_stream_header(Side::Upstream, split).unwrap(),
+                HeaderPatch::Incomplete
+            );
+        }
+        assert!(
+            t.patch_stream_header(Side::Upstream, &[0x3f, 0x00])
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::SessionId;
+
+/// A connected session that announced a prefix of the namespace an
+/// upstream is selected for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub id: SessionId,
+    /// Remote address the session was registered with.
+    pub remote: String,
+    /// The announced namespace, a prefix of the requested one.
+    pub namespace: Vec<String>,
+    /// Round trip time last reported for the session.
+    pub rtt: Option<Duration>,
+}
+
+/// Picks the upstream session the relay forwards requests for a namespace
+/// to, so deployments with several origins can route without forking the
+/// relay.
+pub trait UpstreamSelector: Send + Sync {
+    /// The candidate to use for `namespace`, or `None` to treat the
+    /// namespace as unavailable. `candidates` are ordered by session ID.
+    fn select(&self, namespace: &[String], candidates: &[Candidate]) -> Option<SessionId>;
+}
+
+/// Picks the candidate that announced the longest prefix of the namespace,
+/// the oldest session among equals. The default selector.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LongestPrefix;
+
+impl UpstreamSelector for LongestPrefix {
+    fn select(&self, _namespace: &[String], candidates: &[Candidate]) -> Option<SessionId> {
+        candidates
+            .iter()
+            .rev()
+            .max_by_key(|c| c.namespace.len())
+            .map(|c| c.id)
+    }
+}
+
+/// Routes namespaces to configured remotes by prefix, the longest
+/// configured prefix deciding. Namespaces without a route, or whose remote
+/// is not connected, are left to the fallback selector.
+pub struct StaticUpstreams {
+    routes: HashMap<Vec<String>, String>,
+    fallback: Box<dyn UpstreamSelector>,
+}
+
+impl Default for StaticUpstreams {
+    fn default() -> Self {
+        Self {
+            routes: HashMap::new(),
+            fallback: Box::new(LongestPrefix),
+        }
+    }
+}
+
+impl StaticUpstreams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Route namespaces starting with `prefix` to the session connected
+    /// from `remote`.
+    pub fn with_route(mut self, prefix: Vec<String>, remote: impl Into<String>) -> Self {
+        self.routes.insert(prefix, remote.into());
+        self
+    }
+
+    pub fn with_fallback(mut self, fallback: impl UpstreamSelector + 'static) -> Self {
+        self.fallback = Box::new(fallback);
+        self
+    }
+}
+
+impl UpstreamSelector for StaticUpstreams {
+    fn select(&self, namespace: &[String], candidates: &[Candidate]) -> Option<SessionId> {
+        let route = (0..=namespace.len())
+            .rev()
+            .find_map(|len| self.routes.get(&namespace[..len]));
+        route
+            .and_then(|remote| candidates.iter().find(|c| c.remote == *remote))
+            .map(|c| c.id)
+            .or_else(|| self.fallback.select(namespace, candidates))
+    }
+}
+
+/// Spreads namespaces over the candidates by rendezvous hashing of the
+/// namespace and each candidate's remote address. A namespace keeps its
+/// upstream as long as that upstream stays connected, and relays with the
+/// same upstreams agree on the choice.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ConsistentHash;
+
+impl ConsistentHash {
+    fn weight(namespace: &[String], remote: &str) -> u64 {
+        // FNV-1a, stable across processes unlike the std hasher.
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut write = |bytes: &[u8]| {
+            for b in bytes {
+                hash ^= u64::from(*b);
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        for field in namespace {
+            write(field.as_bytes());
+            write(&[0]);
+        }
+        write(remote.as_bytes());
+        hash
+    }
+}
+
+impl UpstreamSelector for ConsistentHash {
+    fn select(&self, namespace: &[String], candidates: &[Candidate]) -> Option<SessionId> {
+        candidates
+            .iter()
+            .max_by_key(|c| (Self::weight(namespace, &c.remote), std::cmp::Reverse(c.id)))
+            .map(|c| c.id)
+    }
+}
+
+/// Picks the candidate with the lowest round trip time. Candidates whose
+/// round trip time is unknown are only picked when no other is known.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LowestLatency;
+
+impl UpstreamSelector for LowestLatency {
+    fn select(&self, _namespace: &[String], candidates: &[Candidate]) -> Option<SessionId> {
+        candidates
+            .iter()
+            .min_by_key(|c| (c.rtt.is_none(), c.rtt))
+            .map(|c| c.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ns(fields: &[&str]) -> Vec<String> {
+        fields.iter().map(|f| f.to_string()).collect()
+    }
+
+    fn candidate(
+        id: SessionId,
+        remote: &str,
+        namespace: &[&str],
+        rtt_ms: Option<u64>,
+    ) -> Candidate {
+        Candidate {
+            id,
+            remote: remote.into(),
+            namespace: ns(namespace),
+            rtt: rtt_ms.map(Duration::from_millis),
+        }
+    }
+
+    #[test]
+    fn longest_prefix_then_oldest() {
+        let candidates = [
+            candidate(1, "a", &["live"], None),
+            candidate(2, "b", &["live", "sports"], None),
+            candidate(3, "c", &["live", "sports"], None),
+        ];
+        let namespace = ns(&["live", "sports", "match"]);
+        assert_eq!(LongestPrefix.select(&namespace, &candidates), Some(2));
+        assert_eq!(LongestPrefix.select(&namespace, &[]), None);
+    }
+
+    #[test]
+    fn static_routes_by_longest_prefix() {
+        let selector = StaticUpstreams::new()
+            .with_route(ns(&["live"]), "origin-a")
+            .with_route(ns(&["live", "news"]), "origin-b");
+        let candidates = [
+            candidate(1, "origin-a", &["live"], None),
+            candidate(2, "origin-b", &["live"], None),
+        ];
+        assert_eq!(
+            selector.select(&ns(&["live", "news", "1"]), &candidates),
+            Some(2)
+        );
+        assert_eq!(
+            selector.select(&ns(&["live", "sports"]), &candidates),
+            Some(1)
+        );
+
+        // The routed origin is not connected.
+        assert_eq!(
+            selector.select(&ns(&["live", "news"]), &candidates[..1]),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn consistent_hash_is_stable() {
+        let candidates: Vec<_> = (1..=3)
+            .map(|id| candidate(id, &format!("origin-{id}"), &[], None))
+            .collect();
+        let namespaces: Vec<_> = (0..32).map(|i| ns(&["live", &i.to_string()])).collect();
+        let chosen: Vec<_> = namespaces
+            .iter()
+            .map(|n| ConsistentHash.select(n, &candidates).unwrap())
+            .collect();
+        assert!((1..=3).all(|id| chosen.contains(&id)));
+
+        // Losing an upstream only moves the namespaces it served.
+        for (namespace, before) in namespaces.iter().zip(&chosen) {
+            let after = ConsistentHash.select(namespace, &candidates[..2]).unwrap();
+            if *before != 3 {
+                assert_eq!(after, *before);
+            }
+        }
+    }
+
+    #[test]
+    fn lowest_latency_prefers_known_rtt() {
+        let candidates = [
+            candidate(1, "a", &[], None),
+            candidate(2, "b", &[], Some(40)),
+            candidate(3, "c", &[], Some(15)),
+        ];
+        assert_eq!(LowestLatency.select(&[], &candidates), Some(3));
+        assert_eq!(LowestLatency.select(&[], &candidates[..1]), Some(1));
+    }
+
+    #[test]
+    fn relay_selects_among_announcing_sessions() {
+        use crate::Relay;
+
+        let relay = Relay::new().with_upstream_selector(LowestLatency);
+        let near = relay.register_session("near");
+        let far = relay.register_session("far");
+        let other = relay.register_session("other");
+        near.add_namespace(ns(&["live"]));
+        far.add_namespace(ns(&["live", "news"]));
+        other.add_namespace(ns(&["vod"]));
+        near.set_rtt(Duration::from_millis(10));
+        far.set_rtt(Duration::from_millis(80));
+
+        assert_eq!(
+            relay.select_upstream(&ns(&["live", "news"])),
+            Some(near.id())
+        );
+        drop(near);
+        assert_eq!(
+            relay.select_upstream(&ns(&["live", "news"])),
+            Some(far.id())
+        );
+        assert_eq!(relay.select_upstream(&ns(&["live", "sports"])), None);
+    }
+}
@@ -0,0 +1,64 @@
+//! Per-object track alias resolution throughput.
+//!
+//! Run with `cargo bench -p moqt-transport --bench alias_resolution`.
+//! Reports the objects per second resolved and delivered against the
+//! target of 100k, without failing: wall-clock rates depend on the machine.
+
+use std::time::Instant;
+
+use moqt_transport::{message::SubscribeOk, mock::ObjectBuilder, track::TrackManager};
+
+const OBJECTS: u64 = 100_000;
+const TARGET_PER_SEC: f64 = 100_000.0;
+
+fn main() {
+    let manager = TrackManager::default();
+    manager.handle_max_request_id(1).unwrap();
+    let (request_id, mut stream) = manager.subscribe_track("live/video".to_string()).unwrap();
+    manager
+        .handle_subscribe_ok(&SubscribeOk {
+            request_id,
+            track_alias: 1,
+            expires: 0,
+            group_order: 1,
+            content_exists: false,
+            largest_location: None,
+            parameters: Vec::new(),
+        })
+        .unwrap();
+
+    let start = Instant::now();
+    for _ in 0..OBJECTS {
+        assert!(manager.resolve_alias(1).is_some());
+    }
+    report("resolve_alias", start);
+
+    // The subscriber keeps up, so every object takes the path of a queued
+    // object rather than that of a full queue.
+    let object = ObjectBuilder::new(0, 0)
+        .with_track_alias(1)
+        .with_publisher_priority(128)
+        .with_payload(&[0u8; 1200][..])
+        .build();
+    let start = Instant::now();
+    for object_id in 0..OBJECTS {
+        let mut object = object.clone();
+        object.metadata.object_id = object_id;
+        assert_eq!(manager.deliver(object), 1);
+        assert!(stream.try_recv().is_some_and(|o| o.is_ok()));
+    }
+    report("deliver", start);
+}
+
+fn report(name: &str, start: Instant) {
+    let elapsed = start.elapsed().as_secs_f64();
+    let rate = OBJECTS as f64 / elapsed;
+    let verdict = if rate >= TARGET_PER_SEC {
+        "meets"
+    } else {
+        "below"
+    };
+    println!(
+        "{name}: {OBJECTS} objects in {elapsed:.3}s ({rate:.0} objects/s, {verdict} the {TARGET_PER_SEC} target)"
+    );
+}
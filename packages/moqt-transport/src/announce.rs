@@ -0,0 +1,641 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use tokio::sync::mpsc;
+
+use crate::{
+    auth::{AuthToken, Token},
+    codec::{is_namespace_prefix, namespace_eq},
+    error::{Error, RequestErrorCode},
+    message::{
+        Announce, AnnounceError, SubscribeAnnounces, SubscribeAnnouncesError, Unannounce,
+        UnsubscribeAnnounces,
+    },
+    request::AnnounceRequest,
+};
+
+/// SUBSCRIBE_ANNOUNCES_ERROR code for a prefix overlapping an active
+/// namespace subscription.
+pub const NAMESPACE_PREFIX_OVERLAP: u64 = 0x5;
+
+/// Change to the set of announced namespaces matching a namespace
+/// subscription, to be sent to the subscriber as ANNOUNCE or UNANNOUNCE.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum AnnounceEvent {
+    Announced(Vec<String>),
+    Unannounced(Vec<String>),
+}
+
+impl AnnounceEvent {
+    pub fn namespace(&self) -> &[String] {
+        match self {
+            AnnounceEvent::Announced(ns) | AnnounceEvent::Unannounced(ns) => ns,
+        }
+    }
+}
+
+impl From<&Announce> for AnnounceEvent {
+    fn from(msg: &Announce) -> Self {
+        AnnounceEvent::Announced(msg.track_namespace.clone())
+    }
+}
+
+impl From<&Unannounce> for AnnounceEvent {
+    fn from(msg: &Unannounce) -> Self {
+        AnnounceEvent::Unannounced(msg.track_namespace.clone())
+    }
+}
+
+/// Notifications for one namespace subscription. Ends once the subscriber
+/// sends UNSUBSCRIBE_ANNOUNCES for its prefix.
+pub struct AnnounceNotifications {
+    pub request_id: u64,
+    rx: mpsc::UnboundedReceiver<AnnounceEvent>,
+}
+
+impl AnnounceNotifications {
+    pub async fn recv(&mut self) -> Option<AnnounceEvent> {
+        self.rx.recv().await
+    }
+
+    pub fn try_recv(&mut self) -> Option<AnnounceEvent> {
+        self.rx.try_recv().ok()
+    }
+}
+
+struct NamespaceSubscription {
+    prefix: Vec<String>,
+    tx: mpsc::UnboundedSender<AnnounceEvent>,
+}
+
+/// Publisher side state of the namespace subscriptions a peer created with
+/// SUBSCRIBE_ANNOUNCES.
+#[derive(Default)]
+pub struct AnnounceSubscriptions {
+    subscriptions: Mutex<Vec<NamespaceSubscription>>,
+}
+
+impl AnnounceSubscriptions {
+    /// Accept a namespace subscription. A prefix that is a prefix of,
+    /// suffix of, or equal to an active one is rejected with Namespace
+    /// Prefix Overlap.
+    pub fn handle_subscribe_announces(
+        &self,
+        msg: &SubscribeAnnounces,
+    ) -> Result<AnnounceNotifications, SubscribeAnnouncesError> {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.retain(|s| !s.tx.is_closed());
+        let prefix = &msg.track_namespace_prefix;
+        if subscriptions.iter().any(|s| {
+            is_namespace_prefix(&s.prefix, prefix) || is_namespace_prefix(prefix, &s.prefix)
+        }) {
+            return Err(SubscribeAnnouncesError {
+                request_id: msg.request_id,
+                error_code: NAMESPACE_PREFIX_OVERLAP,
+                error_reason: "namespace prefix overlap".into(),
+            });
+        }
+        let (tx, rx) = mpsc::unbounded_channel();
+        subscriptions.push(NamespaceSubscription {
+            prefix: prefix.clone(),
+            tx,
+        });
+        Ok(AnnounceNotifications {
+            request_id: msg.request_id,
+            rx,
+        })
+    }
+
+    /// Tear down the namespace subscription for the prefix, ending its
+    /// notification stream.
+    pub fn handle_unsubscribe_announces(&self, msg: &UnsubscribeAnnounces) -> Result<(), Error> {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let before = subscriptions.len();
+        subscriptions.retain(|s| s.prefix != msg.track_namespace_prefix);
+        if subscriptions.len() == before {
+            return Err(Error::ProtocolViolation {
+                reason: "UNSUBSCRIBE_ANNOUNCES for unknown prefix".into(),
+                context: None,
+            });
+        }
+        Ok(())
+    }
+
+    /// Forward an announcement change to every subscription whose prefix
+    /// matches the namespace. Returns the number of subscriptions notified.
+    pub fn notify(&self, event: AnnounceEvent) -> usize {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let mut notified = 0;
+        subscriptions.retain(|s| {
+            if !is_namespace_prefix(&s.prefix, event.namespace()) {
+                return !s.tx.is_closed();
+            }
+            let sent = s.tx.send(event.clone()).is_ok();
+            notified += sent as usize;
+            sent
+        });
+        notified
+    }
+
+    /// Number of active namespace subscriptions.
+    pub fn len(&self) -> usize {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.retain(|s| !s.tx.is_closed());
+        subscriptions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// How an incoming ANNOUNCE for a namespace the peer already announced, or
+/// is announcing, is treated.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateAnnouncePolicy {
+    /// Close the session with PROTOCOL_VIOLATION.
+    #[default]
+    Reject,
+    /// Answer it like the first ANNOUNCE, without registering the
+    /// namespace again.
+    Idempotent,
+}
+
+/// Limits on the namespaces a peer may announce on one session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnnounceLimits {
+    max_namespaces: usize,
+    max_pending: usize,
+    duplicates: DuplicateAnnouncePolicy,
+}
+
+impl Default for AnnounceLimits {
+    /// 1024 namespaces, of which 64 may await a response, and duplicate
+    /// ANNOUNCEs rejected.
+    fn default() -> Self {
+        Self {
+            max_namespaces: 1024,
+            max_pending: 64,
+            duplicates: DuplicateAnnouncePolicy::default(),
+        }
+    }
+}
+
+impl AnnounceLimits {
+    /// Namespaces announced or awaiting a response at the same time.
+    pub fn with_max_namespaces(mut self, max_namespaces: usize) -> Self {
+        self.max_namespaces = max_namespaces;
+        self
+    }
+
+    /// ANNOUNCEs awaiting ANNOUNCE_OK or ANNOUNCE_ERROR.
+    pub fn with_max_pending(mut self, max_pending: usize) -> Self {
+        self.max_pending = max_pending;
+        self
+    }
+
+    pub fn with_duplicate_policy(mut self, duplicates: DuplicateAnnouncePolicy) -> Self {
+        self.duplicates = duplicates;
+        self
+    }
+}
+
+#[derive(Default)]
+struct PeerAnnouncesInner {
+    pending: Vec<(u64, Vec<String>)>,
+    announced: Vec<Vec<String>>,
+}
+
+impl PeerAnnouncesInner {
+    /// Whether `namespace` was announced or is awaiting a response.
+    fn is_announced(&self, namespace: &[String]) -> bool {
+        self.announced.iter().any(|n| namespace_eq(n, namespace))
+            || self.pending.iter().any(|(_, n)| namespace_eq(n, namespace))
+    }
+}
+
+/// Namespaces the peer announced, bounded by [`AnnounceLimits`] so a peer
+/// cannot exhaust the namespace registry of a relay.
+#[derive(Default)]
+pub struct PeerAnnounces {
+    limits: AnnounceLimits,
+    inner: Mutex<PeerAnnouncesInner>,
+}
+
+impl PeerAnnounces {
+    pub fn new(limits: AnnounceLimits) -> Self {
+        Self {
+            limits,
+            inner: Mutex::default(),
+        }
+    }
+
+    /// Check an incoming ANNOUNCE against the namespaces the peer announced
+    /// or is announcing. A duplicate is an error closing the session unless
+    /// the [`DuplicateAnnouncePolicy`] treats it as idempotent.
+    pub fn check_duplicate(&self, msg: &Announce) -> Result<(), Error> {
+        if self.limits.duplicates == DuplicateAnnouncePolicy::Idempotent {
+            return Ok(());
+        }
+        let inner = self.inner.lock().unwrap();
+        if inner.is_announced(&msg.track_namespace) {
+            return Err(Error::DuplicateAnnounce {
+                namespace: msg.track_namespace.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Record an incoming ANNOUNCE as pending until
+    /// [`accept`](Self::accept) or [`reject`](Self::reject). Beyond the
+    /// limits the ANNOUNCE_ERROR to send is returned instead. A duplicate
+    /// let through by [`check_duplicate`](Self::check_duplicate) does not
+    /// count against the limits.
+    pub fn handle_announce(&self, msg: &Announce) -> Result<(), AnnounceError> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.is_announced(&msg.track_namespace) {
+            inner
+                .pending
+                .push((msg.request_id, msg.track_namespace.clone()));
+            return Ok(());
+        }
+        let pending = inner.pending.len();
+        if pending >= self.limits.max_pending
+            || pending + inner.announced.len() >= self.limits.max_namespaces
+        {
+            return Err(AnnounceError {
+                request_id: msg.request_id,
+                error_code: RequestErrorCode::AnnounceLimitExceeded.code(),
+                error_reason: "limit exceeded".into(),
+            });
+        }
+        inner
+            .pending
+            .push((msg.request_id, msg.track_namespace.clone()));
+        Ok(())
+    }
+
+    /// Record that ANNOUNCE_OK was sent. Returns the announced namespace,
+    /// or `None` if the request was not pending.
+    pub fn accept(&self, request_id: u64) -> Option<Vec<String>> {
+        let mut inner = self.inner.lock().unwrap();
+        let index = inner.pending.iter().position(|(id, _)| *id == request_id)?;
+        let (_, namespace) = inner.pending.remove(index);
+        if !inner.announced.iter().any(|n| namespace_eq(n, &namespace)) {
+            inner.announced.push(namespace.clone());
+        }
+        Some(namespace)
+    }
+
+    /// Record that ANNOUNCE_ERROR was sent.
+    pub fn reject(&self, request_id: u64) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let len = inner.pending.len();
+        inner.pending.retain(|(id, _)| *id != request_id);
+        inner.pending.len() != len
+    }
+
+    /// Process UNANNOUNCE, freeing the namespace's share of the limit.
+    pub fn handle_unannounce(&self, msg: &Unannounce) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let len = inner.announced.len();
+        inner.announced.retain(|n| *n != msg.track_namespace);
+        inner.announced.len() != len
+    }
+
+    /// Namespaces accepted and not yet unannounced.
+    pub fn announced(&self) -> Vec<Vec<String>> {
+        self.inner.lock().unwrap().announced.clone()
+    }
+
+    /// Number of ANNOUNCEs awaiting a response.
+    pub fn pending(&self) -> usize {
+        self.inner.lock().unwrap().pending.len()
+    }
+}
+
+/// Namespaces this endpoint announced and prefixes it subscribed to with
+/// SUBSCRIBE_ANNOUNCES, kept so that discovery can be re-established on a
+/// new session after GOAWAY.
+#[derive(Default)]
+pub struct DiscoveryState {
+    inner: Mutex<DiscoveryInner>,
+}
+
+#[derive(Default)]
+struct DiscoveryInner {
+    /// Active namespaces and the tokens they were announced with.
+    announced: Vec<(Vec<String>, Vec<AuthToken>)>,
+    /// Namespace of each ANNOUNCE sent, by Request ID.
+    requests: HashMap<u64, Vec<String>>,
+    prefixes: Vec<Vec<String>>,
+    /// Tokens registered by the ANNOUNCEs sent, by alias.
+    aliases: HashMap<u64, Token>,
+}
+
+impl DiscoveryState {
+    /// Record the namespace of `request` as announced with its tokens.
+    /// Returns `false` if it already was.
+    pub(crate) fn add_announced(&self, request: &AnnounceRequest) -> bool {
+        let namespace = request.track_namespace();
+        let mut inner = self.inner.lock().unwrap();
+        if inner
+            .announced
+            .iter()
+            .any(|(n, _)| namespace_eq(n, namespace))
+        {
+            return false;
+        }
+        for token in request.auth_tokens() {
+            match token {
+                AuthToken::Register { alias, token } => {
+                    inner.aliases.insert(*alias, token.clone());
+                }
+                AuthToken::Delete { alias } => {
+                    inner.aliases.remove(alias);
+                }
+                _ => {}
+            }
+        }
+        let tokens = request.auth_tokens().to_vec();
+        inner.announced.push((namespace.to_vec(), tokens));
+        true
+    }
+
+    /// Record the Request ID of the ANNOUNCE sent for `namespace`, so that
+    /// an ANNOUNCE_ERROR for it can be matched.
+    pub(crate) fn announce_sent(&self, request_id: u64, namespace: &[String]) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.requests.insert(request_id, namespace.to_vec());
+    }
+
+    pub(crate) fn remove_announced(&self, namespace: &[String]) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        inner.requests.retain(|_, n| !namespace_eq(n, namespace));
+        let len = inner.announced.len();
+        inner.announced.retain(|(n, _)| !namespace_eq(n, namespace));
+        inner.announced.len() != len
+    }
+
+    /// Remove the namespace announced with `request_id`. Returns it if it
+    /// was still announced.
+    pub(crate) fn remove_request(&self, request_id: u64) -> Option<Vec<String>> {
+        let namespace = self.inner.lock().unwrap().requests.remove(&request_id)?;
+        self.remove_announced(&namespace).then_some(namespace)
+    }
+
+    pub(crate) fn add_prefix(&self, prefix: Vec<String>) {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.prefixes.contains(&prefix) {
+            inner.prefixes.push(prefix);
+        }
+    }
+
+    pub(crate) fn remove_prefix(&self, prefix: &[String]) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let len = inner.prefixes.len();
+        inner.prefixes.retain(|p| p != prefix);
+        inner.prefixes.len() != len
+    }
+
+    /// Active namespaces in the order they were announced.
+    pub fn announced(&self) -> Vec<Vec<String>> {
+        let inner = self.inner.lock().unwrap();
+        inner.announced.iter().map(|(n, _)| n.clone()).collect()
+    }
+
+    /// ANNOUNCEs of the active namespaces for another session. Token
+    /// aliases are scoped to a session, so each token is sent by value:
+    /// one used by an alias this state did not see registered is dropped.
+    pub(crate) fn announce_requests(&self) -> Vec<AnnounceRequest> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .announced
+            .iter()
+            .map(|(namespace, tokens)| {
+                tokens
+                    .iter()
+                    .filter_map(|token| match token {
+                        AuthToken::Register { token, .. } | AuthToken::UseValue { token } => {
+                            Some(token.clone())
+                        }
+                        AuthToken::UseAlias { alias } => inner.aliases.get(alias).cloned(),
+                        AuthToken::Delete { .. } => None,
+                    })
+                    .fold(AnnounceRequest::new(namespace.clone()), |request, token| {
+                        request.with_auth_token(AuthToken::UseValue { token })
+                    })
+            })
+            .collect()
+    }
+
+    /// Active namespace subscription prefixes in the order they were sent.
+    pub fn prefixes(&self) -> Vec<Vec<String>> {
+        self.inner.lock().unwrap().prefixes.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ns(parts: &[&str]) -> Vec<String> {
+        parts.iter().map(|p| p.to_string()).collect()
+    }
+
+    fn subscribe(request_id: u64, prefix: &[&str]) -> SubscribeAnnounces {
+        SubscribeAnnounces {
+            request_id,
+            track_namespace_prefix: ns(prefix),
+            parameters: vec![],
+        }
+    }
+
+    #[test]
+    fn notifications_follow_prefix() {
+        let subs = AnnounceSubscriptions::default();
+        let mut meeting = subs
+            .handle_subscribe_announces(&subscribe(0, &["example.com", "meeting=123"]))
+            .unwrap();
+
+        let participant = ns(&["example.com", "meeting=123", "participant=100"]);
+        assert_eq!(
+            subs.notify(AnnounceEvent::Announced(participant.clone())),
+            1
+        );
+        assert_eq!(
+            subs.notify(AnnounceEvent::Announced(ns(&["example.com", "other"]))),
+            0
+        );
+        assert_eq!(
+            meeting.try_recv(),
+            Some(AnnounceEvent::Announced(participant))
+        );
+        assert_eq!(meeting.try_recv(), None);
+    }
+
+    #[test]
+    fn overlapping_prefix_is_rejected() {
+        let subs = AnnounceSubscriptions::default();
+        let _a = subs
+            .handle_subscribe_announces(&subscribe(0, &["example.com", "meeting=123"]))
+            .unwrap();
+
+        for (request_id, prefix) in [
+            (2, &["example.com"][..]),
+            (4, &["example.com", "meeting=123"][..]),
+            (6, &["example.com", "meeting=123", "participant=1"][..]),
+        ] {
+            let err = subs
+                .handle_subscribe_announces(&subscribe(request_id, prefix))
+                .err()
+                .unwrap();
+            assert_eq!(err.request_id, request_id);
+            assert_eq!(err.error_code, NAMESPACE_PREFIX_OVERLAP);
+        }
+
+        assert!(
+            subs.handle_subscribe_announces(&subscribe(8, &["example.com", "meeting=456"]))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn unsubscribe_ends_notification_stream() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let subs = AnnounceSubscriptions::default();
+            let mut notifications = subs
+                .handle_subscribe_announces(&subscribe(0, &["example.com"]))
+                .unwrap();
+
+            subs.handle_unsubscribe_announces(&UnsubscribeAnnounces {
+                track_namespace_prefix: ns(&["example.com"]),
+            })
+            .unwrap();
+            assert_eq!(notifications.recv().await, None);
+            assert!(subs.is_empty());
+
+            assert!(
+                subs.handle_unsubscribe_announces(&UnsubscribeAnnounces {
+                    track_namespace_prefix: ns(&["example.com"]),
+                })
+                .is_err()
+            );
+
+            // The prefix can be subscribed again.
+            assert!(
+                subs.handle_subscribe_announces(&subscribe(2, &["example.com"]))
+                    .is_ok()
+            );
+        });
+    }
+
+    fn announce(request_id: u64, namespace: &[&str]) -> Announce {
+        Announce {
+            request_id,
+            track_namespace: ns(namespace),
+            parameters: vec![],
+        }
+    }
+
+    #[test]
+    fn peer_announces_are_limited() {
+        let announces = PeerAnnounces::new(
+            AnnounceLimits::default()
+                .with_max_namespaces(3)
+                .with_max_pending(2),
+        );
+        announces.handle_announce(&announce(0, &["a"])).unwrap();
+        announces.handle_announce(&announce(2, &["b"])).unwrap();
+        let err = announces.handle_announce(&announce(4, &["c"])).unwrap_err();
+        assert_eq!(err.request_id, 4);
+        assert_eq!(
+            err.error_code,
+            RequestErrorCode::AnnounceLimitExceeded.code()
+        );
+        assert_eq!(err.error_reason, "limit exceeded");
+
+        assert_eq!(announces.accept(0), Some(ns(&["a"])));
+        assert_eq!(announces.accept(2), Some(ns(&["b"])));
+        announces.handle_announce(&announce(6, &["c"])).unwrap();
+        assert!(announces.handle_announce(&announce(8, &["d"])).is_err());
+
+        // Rejected and unannounced namespaces free their share.
+        assert!(announces.reject(6));
+        assert!(announces.handle_unannounce(&Unannounce {
+            track_namespace: ns(&["a"]),
+        }));
+        announces.handle_announce(&announce(10, &["d"])).unwrap();
+        announces.handle_announce(&announce(12, &["e"])).unwrap();
+        assert_eq!(announces.pending(), 2);
+        assert_eq!(announces.announced(), vec![ns(&["b"])]);
+    }
+
+    #[test]
+    fn duplicate_peer_announce() {
+        let strict = PeerAnnounces::default();
+        strict.check_duplicate(&announce(0, &["a"])).unwrap();
+        strict.handle_announce(&announce(0, &["a"])).unwrap();
+        // Pending and accepted namespaces are both active.
+        assert!(matches!(
+            strict.check_duplicate(&announce(2, &["a"])),
+            Err(Error::DuplicateAnnounce { .. })
+        ));
+        strict.accept(0);
+        assert!(strict.check_duplicate(&announce(2, &["a"])).is_err());
+        strict.check_duplicate(&announce(2, &["A"])).unwrap();
+
+        let idempotent = PeerAnnounces::new(
+            AnnounceLimits::default()
+                .with_max_namespaces(1)
+                .with_duplicate_policy(DuplicateAnnouncePolicy::Idempotent),
+        );
+        idempotent.handle_announce(&announce(0, &["a"])).unwrap();
+        assert_eq!(idempotent.accept(0), Some(ns(&["a"])));
+        idempotent.check_duplicate(&announce(2, &["a"])).unwrap();
+        idempotent.handle_announce(&announce(2, &["a"])).unwrap();
+        assert_eq!(idempotent.accept(2), Some(ns(&["a"])));
+        assert_eq!(idempotent.announced(), vec![ns(&["a"])]);
+    }
+
+    #[test]
+    fn wire_announce_matches_prefix_subscription() {
+        use bytes::BytesMut;
+
+        let subs = AnnounceSubscriptions::default();
+        let mut notifications = subs
+            .handle_subscribe_announces(&subscribe(0, &["example.com", "meeting=123"]))
+            .unwrap();
+
+        let mut buf = BytesMut::new();
+        Announce {
+            request_id: 1,
+            track_namespace: ns(&["example.com", "meeting=123", "participant=100"]),
+            parameters: vec![],
+        }
+        .encode(&mut buf)
+        .unwrap();
+        Unannounce {
+            track_namespace: ns(&["example.com", "meeting=123", "participant=100"]),
+        }
+        .encode(&mut buf)
+        .unwrap();
+
+        let announce = Announce::decode(&mut buf).unwrap();
+        let unannounce = Unannounce::decode(&mut buf).unwrap();
+        assert_eq!(subs.notify((&announce).into()), 1);
+        assert_eq!(subs.notify((&unannounce).into()), 1);
+
+        assert_eq!(
+            notifications.try_recv(),
+            Some(AnnounceEvent::Announced(announce.track_namespace.clone()))
+        );
+        assert_eq!(
+            notifications.try_recv(),
+            Some(AnnounceEvent::Unannounced(announce.track_namespace))
+        );
+    }
+}
@@ -0,0 +1,657 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{
+    error::{Error, RequestErrorCode, TerminationCode},
+    message::{Announce, Publish, Subscribe},
+    model::Parameter,
+};
+
+/// AUTHORIZATION TOKEN version specific parameter.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-authorization-token
+pub const AUTHORIZATION_TOKEN: u64 = 0x03;
+
+/// MAX_AUTH_TOKEN_CACHE_SIZE setup parameter.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-max_auth_token_cache_size
+pub const MAX_AUTH_TOKEN_CACHE_SIZE: u64 = 0x04;
+
+/// A token type and value, as carried by an AUTHORIZATION TOKEN parameter
+/// or registered under an alias.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Token {
+    pub token_type: u64,
+    pub value: Bytes,
+}
+
+impl Token {
+    /// Size the token occupies in the receiver's cache: 16 bytes plus the
+    /// length of the token value.
+    pub fn cache_size(&self) -> u64 {
+        16 + self.value.len() as u64
+    }
+}
+
+/// The Token structure of an AUTHORIZATION TOKEN parameter.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum AuthToken {
+    /// Retire a previously registered alias.
+    Delete { alias: u64 },
+    /// Register `token` under `alias` and use it for this message.
+    Register { alias: u64, token: Token },
+    /// Use the token previously registered under `alias`.
+    UseAlias { alias: u64 },
+    /// Use `token` for this message only.
+    UseValue { token: Token },
+}
+
+impl AuthToken {
+    pub fn encode(&self, buf: &mut BytesMut) -> Result<(), Error> {
+        let mut vi = crate::codec::VarInt;
+
+        match self {
+            AuthToken::Delete { alias } => {
+                vi.encode(0x0, buf)?;
+                vi.encode(*alias, buf)?;
+            }
+            AuthToken::Register { alias, token } => {
+                vi.encode(0x1, buf)?;
+                vi.encode(*alias, buf)?;
+                vi.encode(token.token_type, buf)?;
+                buf.put_slice(&token.value);
+            }
+            AuthToken::UseAlias { alias } => {
+                vi.encode(0x2, buf)?;
+                vi.encode(*alias, buf)?;
+            }
+            AuthToken::UseValue { token } => {
+                vi.encode(0x3, buf)?;
+                vi.encode(token.token_type, buf)?;
+                buf.put_slice(&token.value);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decode a Token structure. The token value extends to the end of
+    /// `buf`, which must hold exactly one parameter value.
+    pub fn decode(buf: &mut BytesMut) -> Result<Self, Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        let mut vi = crate::codec::VarInt;
+
+        let alias_type = vi
+            .decode(buf)?
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "alias type"))?;
+        let mut varint = |what: &'static str| -> Result<u64, Error> {
+            Ok(vi
+                .decode(buf)?
+                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, what))?)
+        };
+
+        let token = match alias_type {
+            0x0 => AuthToken::Delete {
+                alias: varint("token alias")?,
+            },
+            0x1 => {
+                let alias = varint("token alias")?;
+                let token_type = varint("token type")?;
+                AuthToken::Register {
+                    alias,
+                    token: Token {
+                        token_type,
+                        value: buf.split().freeze(),
+                    },
+                }
+            }
+            0x2 => AuthToken::UseAlias {
+                alias: varint("token alias")?,
+            },
+            0x3 => {
+                let token_type = varint("token type")?;
+                AuthToken::UseValue {
+                    token: Token {
+                        token_type,
+                        value: buf.split().freeze(),
+                    },
+                }
+            }
+            _ => return Err(IoError::new(ErrorKind::InvalidData, "invalid alias type").into()),
+        };
+
+        if matches!(token, AuthToken::Delete { .. } | AuthToken::UseAlias { .. }) && !buf.is_empty()
+        {
+            return Err(IoError::new(ErrorKind::InvalidData, "trailing token bytes").into());
+        }
+        Ok(token)
+    }
+
+    pub fn into_parameter(self) -> Result<Parameter, Error> {
+        let mut buf = BytesMut::new();
+        self.encode(&mut buf)?;
+        Ok(Parameter::bytes(AUTHORIZATION_TOKEN, buf.to_vec())?)
+    }
+}
+
+/// Authorization failures. [`AuthError::is_session_error`] tells whether
+/// the session must be closed or only the request rejected.
+#[derive(Debug, PartialEq, Eq, Clone, thiserror::Error)]
+pub enum AuthError {
+    #[error("malformed token structure")]
+    KeyValueFormatting,
+
+    #[error("auth token cache overflow")]
+    CacheOverflow,
+
+    #[error("duplicate auth token alias {0}")]
+    DuplicateAlias(u64),
+
+    #[error("unknown auth token alias {0}")]
+    UnknownAlias(u64),
+
+    /// A well-formed token its [`Authorizer`] found invalid for its Token
+    /// Type.
+    #[error("malformed auth token")]
+    MalformedToken,
+
+    #[error("unauthorized")]
+    Unauthorized,
+}
+
+impl AuthError {
+    pub fn is_session_error(&self) -> bool {
+        matches!(
+            self,
+            AuthError::KeyValueFormatting | AuthError::CacheOverflow | AuthError::DuplicateAlias(_)
+        )
+    }
+
+    /// Session termination code for session errors, or the request error
+    /// code carried in SUBSCRIBE_ERROR, ANNOUNCE_ERROR and friends.
+    ///
+    /// An unknown alias rejects only the request, with the Unknown Auth
+    /// Token Alias code of the termination code table, the only code the
+    /// draft assigns to it.
+    pub fn code(&self) -> u64 {
+        let error = Error::Auth(self.clone());
+        if self.is_session_error() {
+            TerminationCode::from(&error).code()
+        } else {
+            RequestErrorCode::from(&error).code()
+        }
+    }
+}
+
+/// Sender side token aliases. The first request using a token registers
+/// it under a fresh alias, later requests refer to it by alias only.
+///
+/// Registrations are limited by the peer's MAX_AUTH_TOKEN_CACHE_SIZE;
+/// tokens that do not fit are sent by value instead.
+#[derive(Default)]
+pub struct TokenAliases {
+    state: Mutex<AliasState>,
+}
+
+#[derive(Default)]
+struct AliasState {
+    next_alias: u64,
+    peer_cache_size: u64,
+    used: u64,
+    registered: HashMap<Token, u64>,
+}
+
+impl TokenAliases {
+    /// Set the peer's MAX_AUTH_TOKEN_CACHE_SIZE. Defaults to 0, which
+    /// prohibits aliases.
+    pub fn set_peer_cache_size(&self, size: u64) {
+        self.state.lock().unwrap().peer_cache_size = size;
+    }
+
+    /// Token structure to attach to the next request using `token`.
+    pub fn token(&self, token: Token) -> AuthToken {
+        let mut state = self.state.lock().unwrap();
+        if let Some(&alias) = state.registered.get(&token) {
+            return AuthToken::UseAlias { alias };
+        }
+        let size = token.cache_size();
+        if state.used + size > state.peer_cache_size {
+            return AuthToken::UseValue { token };
+        }
+        let alias = state.next_alias;
+        state.next_alias += 1;
+        state.used += size;
+        state.registered.insert(token.clone(), alias);
+        AuthToken::Register { alias, token }
+    }
+
+    /// Token structure retiring `token`, if it was registered.
+    pub fn retire(&self, token: &Token) -> Option<AuthToken> {
+        let mut state = self.state.lock().unwrap();
+        let alias = state.registered.remove(token)?;
+        state.used -= token.cache_size();
+        Some(AuthToken::Delete { alias })
+    }
+
+    /// Forget registrations the peer did not accept during setup because
+    /// they exceeded its MAX_AUTH_TOKEN_CACHE_SIZE.
+    pub fn purge_over_budget(&self) {
+        let mut state = self.state.lock().unwrap();
+        let AliasState {
+            peer_cache_size,
+            used,
+            registered,
+            ..
+        } = &mut *state;
+        let mut by_alias: Vec<_> = registered.drain().collect();
+        by_alias.sort_by_key(|(_, alias)| *alias);
+        *used = 0;
+        for (token, alias) in by_alias {
+            let size = token.cache_size();
+            if *used + size <= *peer_cache_size {
+                *used += size;
+                registered.insert(token, alias);
+            }
+        }
+    }
+}
+
+/// Receiver side registry of the tokens the peer registered, bounded by
+/// the MAX_AUTH_TOKEN_CACHE_SIZE this endpoint advertised.
+#[derive(Default)]
+pub struct TokenCache {
+    max_size: u64,
+    size: u64,
+    tokens: HashMap<u64, Token>,
+}
+
+impl TokenCache {
+    pub fn new(max_size: u64) -> Self {
+        Self {
+            max_size,
+            ..Default::default()
+        }
+    }
+
+    /// Process the AUTHORIZATION TOKEN parameters of a message and return
+    /// the tokens it carries.
+    ///
+    /// Registrations and deletions are applied even when the message is
+    /// rejected for a request level reason, so that pipelined requests
+    /// referring to a registered alias keep working.
+    pub fn resolve(&mut self, parameters: &[Parameter]) -> Result<Vec<Token>, AuthError> {
+        self.resolve_inner(parameters, false)
+    }
+
+    /// Like [`TokenCache::resolve`] for the parameters of CLIENT_SETUP,
+    /// where registrations exceeding the cache size are used by value
+    /// instead of closing the session.
+    pub fn resolve_setup(&mut self, parameters: &[Parameter]) -> Result<Vec<Token>, AuthError> {
+        self.resolve_inner(parameters, true)
+    }
+
+    fn resolve_inner(
+        &mut self,
+        parameters: &[Parameter],
+        setup: bool,
+    ) -> Result<Vec<Token>, AuthError> {
+        let mut tokens = Vec::new();
+        let mut rejected = None;
+
+        for p in parameters
+            .iter()
+            .filter(|p| p.parameter_type == AUTHORIZATION_TOKEN)
+        {
+            let value = p.as_bytes().map_err(|_| AuthError::KeyValueFormatting)?;
+            let mut buf = BytesMut::from(value);
+            let token = AuthToken::decode(&mut buf).map_err(|_| AuthError::KeyValueFormatting)?;
+            match token {
+                AuthToken::Delete { alias } => match self.tokens.remove(&alias) {
+                    Some(token) => self.size -= token.cache_size(),
+                    None => {
+                        rejected.get_or_insert(AuthError::UnknownAlias(alias));
+                    }
+                },
+                AuthToken::Register { alias, token } => {
+                    if self.tokens.contains_key(&alias) {
+                        return Err(AuthError::DuplicateAlias(alias));
+                    }
+                    let size = self.size + token.cache_size();
+                    if size > self.max_size {
+                        if !setup {
+                            return Err(AuthError::CacheOverflow);
+                        }
+                    } else {
+                        self.size = size;
+                        self.tokens.insert(alias, token.clone());
+                    }
+                    tokens.push(token);
+                }
+                AuthToken::UseAlias { alias } => match self.tokens.get(&alias) {
+                    Some(token) => tokens.push(token.clone()),
+                    None => {
+                        rejected.get_or_insert(AuthError::UnknownAlias(alias));
+                    }
+                },
+                AuthToken::UseValue { token } => tokens.push(token),
+            }
+        }
+
+        match rejected {
+            Some(e) => Err(e),
+            None => Ok(tokens),
+        }
+    }
+
+    /// Number of registered aliases.
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Bytes of the cache budget in use.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// Read MAX_AUTH_TOKEN_CACHE_SIZE from setup parameters, defaulting to 0.
+pub fn max_auth_token_cache_size(parameters: &[Parameter]) -> Result<u64, Error> {
+    parameters
+        .iter()
+        .find(|p| p.parameter_type == MAX_AUTH_TOKEN_CACHE_SIZE)
+        .map_or(Ok(0), |p| Ok(p.as_varint()?))
+}
+
+/// A request subject to authorization.
+#[derive(Debug, Clone, Copy)]
+pub enum AuthRequest<'a> {
+    Subscribe(&'a Subscribe),
+    Publish(&'a Publish),
+    Announce(&'a Announce),
+}
+
+impl AuthRequest<'_> {
+    pub fn parameters(&self) -> &[Parameter] {
+        match self {
+            AuthRequest::Subscribe(msg) => &msg.parameters,
+            AuthRequest::Publish(msg) => &msg.parameters,
+            AuthRequest::Announce(msg) => &msg.parameters,
+        }
+    }
+
+    /// The track namespace tuple the request is about, if it carries one.
+    /// SUBSCRIBE and PUBLISH identify their namespace by a numeric ID in
+    /// this implementation, see [`namespace_id`](Self::namespace_id), which
+    /// the authorizer has to resolve itself.
+    pub fn namespace(&self) -> Option<&[String]> {
+        match self {
+            AuthRequest::Subscribe(_) | AuthRequest::Publish(_) => None,
+            AuthRequest::Announce(msg) => Some(&msg.track_namespace),
+        }
+    }
+
+    /// The numeric namespace ID of a SUBSCRIBE or PUBLISH.
+    pub fn namespace_id(&self) -> Option<u64> {
+        match self {
+            AuthRequest::Subscribe(msg) => Some(msg.track_namespace),
+            AuthRequest::Publish(msg) => Some(msg.track_namespace),
+            AuthRequest::Announce(_) => None,
+        }
+    }
+}
+
+/// An owned [`AuthRequest`], for authorizing a request on another task.
+#[derive(Debug, Clone)]
+pub enum OwnedAuthRequest {
+    Subscribe(Subscribe),
+    Publish(Publish),
+    Announce(Announce),
+}
+
+impl OwnedAuthRequest {
+    pub fn as_request(&self) -> AuthRequest<'_> {
+        match self {
+            OwnedAuthRequest::Subscribe(msg) => AuthRequest::Subscribe(msg),
+            OwnedAuthRequest::Publish(msg) => AuthRequest::Publish(msg),
+            OwnedAuthRequest::Announce(msg) => AuthRequest::Announce(msg),
+        }
+    }
+}
+
+impl From<AuthRequest<'_>> for OwnedAuthRequest {
+    fn from(request: AuthRequest<'_>) -> Self {
+        match request {
+            AuthRequest::Subscribe(msg) => OwnedAuthRequest::Subscribe(msg.clone()),
+            AuthRequest::Publish(msg) => OwnedAuthRequest::Publish(msg.clone()),
+            AuthRequest::Announce(msg) => OwnedAuthRequest::Announce(msg.clone()),
+        }
+    }
+}
+
+/// Decides whether a request is allowed given the tokens it carries.
+pub trait Authorizer: Send + Sync {
+    fn authorize(&self, request: &AuthRequest<'_>, tokens: &[Token]) -> bool;
+
+    /// Whether `token` is valid for its Token Type. Requests carrying a
+    /// token failing this check are rejected with Malformed Auth Token
+    /// before [`authorize`](Self::authorize) is called. Every token is
+    /// valid by default.
+    fn check_token(&self, _token: &Token) -> bool {
+        true
+    }
+}
+
+impl<F> Authorizer for F
+where
+    F: Fn(&AuthRequest<'_>, &[Token]) -> bool + Send + Sync,
+{
+    fn authorize(&self, request: &AuthRequest<'_>, tokens: &[Token]) -> bool {
+        self(request, tokens)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(value: &'static [u8]) -> Token {
+        Token {
+            token_type: 0,
+            value: Bytes::from_static(value),
+        }
+    }
+
+    #[test]
+    fn encode_decode_roundtrip() {
+        for msg in [
+            AuthToken::Delete { alias: 3 },
+            AuthToken::Register {
+                alias: 1,
+                token: token(b"secret"),
+            },
+            AuthToken::UseAlias { alias: 1 },
+            AuthToken::UseValue {
+                token: token(b"once"),
+            },
+        ] {
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf).unwrap();
+            assert_eq!(AuthToken::decode(&mut buf).unwrap(), msg);
+        }
+    }
+
+    #[test]
+    fn decode_rejects_unknown_alias_type() {
+        let mut buf = BytesMut::from(&[0x04, 0x00][..]);
+        assert!(AuthToken::decode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn aliases_are_reused_across_requests() {
+        let aliases = TokenAliases::default();
+        aliases.set_peer_cache_size(1024);
+        let first = aliases.token(token(b"secret"));
+        let second = aliases.token(token(b"secret"));
+        let other = aliases.token(token(b"other"));
+
+        assert_eq!(
+            first,
+            AuthToken::Register {
+                alias: 0,
+                token: token(b"secret")
+            }
+        );
+        assert_eq!(second, AuthToken::UseAlias { alias: 0 });
+        assert!(matches!(other, AuthToken::Register { alias: 1, .. }));
+
+        let mut cache = TokenCache::new(1024);
+        for msg in [first, second, other] {
+            let params = [msg.into_parameter().unwrap()];
+            assert_eq!(cache.resolve(&params).unwrap().len(), 1);
+        }
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn unknown_alias_rejects_request_but_keeps_registrations() {
+        let mut cache = TokenCache::new(1024);
+        let params = [
+            AuthToken::UseAlias { alias: 7 }.into_parameter().unwrap(),
+            AuthToken::Register {
+                alias: 1,
+                token: token(b"secret"),
+            }
+            .into_parameter()
+            .unwrap(),
+        ];
+        let err = cache.resolve(&params).unwrap_err();
+        assert_eq!(err, AuthError::UnknownAlias(7));
+        assert!(!err.is_session_error());
+        assert_eq!(err.code(), 0x17);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn duplicate_registration_is_session_error() {
+        let mut cache = TokenCache::new(1024);
+        let register = AuthToken::Register {
+            alias: 1,
+            token: token(b"secret"),
+        }
+        .into_parameter()
+        .unwrap();
+        cache.resolve(std::slice::from_ref(&register)).unwrap();
+
+        let err = cache.resolve(&[register]).unwrap_err();
+        assert_eq!(err, AuthError::DuplicateAlias(1));
+        assert!(err.is_session_error());
+    }
+
+    #[test]
+    fn registration_budget_boundary() {
+        // Two tokens of 16 + 6 bytes fit exactly.
+        let mut cache = TokenCache::new(44);
+        for alias in 0..2 {
+            let register = AuthToken::Register {
+                alias,
+                token: token(b"secret"),
+            };
+            cache
+                .resolve(&[register.into_parameter().unwrap()])
+                .unwrap();
+        }
+        assert_eq!(cache.size(), 44);
+
+        let overflow = AuthToken::Register {
+            alias: 2,
+            token: token(b"x"),
+        }
+        .into_parameter()
+        .unwrap();
+        let err = cache.resolve(std::slice::from_ref(&overflow)).unwrap_err();
+        assert_eq!(err, AuthError::CacheOverflow);
+        assert_eq!(err.code(), 0x13);
+
+        // Deleting frees the token's share of the budget.
+        let delete = AuthToken::Delete { alias: 0 }.into_parameter().unwrap();
+        cache.resolve(&[delete]).unwrap();
+        assert_eq!(cache.size(), 22);
+        cache.resolve(&[overflow]).unwrap();
+        assert_eq!(cache.size(), 39);
+    }
+
+    #[test]
+    fn zero_budget_prohibits_aliases() {
+        let aliases = TokenAliases::default();
+        assert!(matches!(
+            aliases.token(token(b"secret")),
+            AuthToken::UseValue { .. }
+        ));
+
+        let mut cache = TokenCache::default();
+        let register = AuthToken::Register {
+            alias: 0,
+            token: token(b"secret"),
+        }
+        .into_parameter()
+        .unwrap();
+        assert_eq!(
+            cache.resolve(std::slice::from_ref(&register)),
+            Err(AuthError::CacheOverflow)
+        );
+
+        // In CLIENT_SETUP the token is used by value instead.
+        assert_eq!(cache.resolve_setup(&[register]).unwrap().len(), 1);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn sender_tracks_peer_budget() {
+        let aliases = TokenAliases::default();
+        aliases.set_peer_cache_size(22);
+        assert!(matches!(
+            aliases.token(token(b"secret")),
+            AuthToken::Register { alias: 0, .. }
+        ));
+        assert!(matches!(
+            aliases.token(token(b"other")),
+            AuthToken::UseValue { .. }
+        ));
+
+        assert_eq!(
+            aliases.retire(&token(b"secret")),
+            Some(AuthToken::Delete { alias: 0 })
+        );
+        assert!(matches!(
+            aliases.token(token(b"other")),
+            AuthToken::Register { alias: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn purge_drops_registrations_over_budget() {
+        let aliases = TokenAliases::default();
+        aliases.set_peer_cache_size(1024);
+        aliases.token(token(b"first"));
+        aliases.token(token(b"second"));
+
+        aliases.set_peer_cache_size(21);
+        aliases.purge_over_budget();
+        assert_eq!(
+            aliases.token(token(b"first")),
+            AuthToken::UseAlias { alias: 0 }
+        );
+        assert!(matches!(
+            aliases.token(token(b"second")),
+            AuthToken::UseValue { .. }
+        ));
+    }
+}
@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::scheduler::{Priority, Scheduler};
+
+/// A byte rate with the burst allowed above it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate {
+    bytes_per_second: u64,
+    burst: u64,
+}
+
+impl Rate {
+    /// `bytes_per_second`, with a burst of one second worth of bytes. A
+    /// rate of 0 is treated as 1.
+    pub fn new(bytes_per_second: u64) -> Self {
+        let bytes_per_second = bytes_per_second.max(1);
+        Self {
+            bytes_per_second,
+            burst: bytes_per_second,
+        }
+    }
+
+    /// Bytes that may be sent at once after an idle period.
+    pub fn with_burst(mut self, burst: u64) -> Self {
+        self.burst = burst;
+        self
+    }
+
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bytes_per_second
+    }
+
+    pub fn burst(&self) -> u64 {
+        self.burst
+    }
+}
+
+/// What happens to the objects of a subscription sending faster than its
+/// cap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverLimit {
+    /// Keep them queued until the subscription may send again.
+    #[default]
+    Delay,
+    /// Drop the rest of the group of the first object over the limit, so
+    /// that the subscriber skips ahead to the next group instead of falling
+    /// behind.
+    DropGroup,
+}
+
+/// Caps on the object bytes sent on a session, see [`Shaper`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BandwidthLimits {
+    session: Option<Rate>,
+    subscription: Option<Rate>,
+    over_limit: OverLimit,
+}
+
+impl BandwidthLimits {
+    /// Cap on the bytes of every subscription of the session together.
+    pub fn with_session_rate(mut self, rate: Rate) -> Self {
+        self.session = Some(rate);
+        self
+    }
+
+    /// Cap on the bytes of each subscription, unless overridden with
+    /// [`Shaper::set_subscription_rate`].
+    pub fn with_subscription_rate(mut self, rate: Rate) -> Self {
+        self.subscription = Some(rate);
+        self
+    }
+
+    pub fn with_over_limit(mut self, over_limit: OverLimit) -> Self {
+        self.over_limit = over_limit;
+        self
+    }
+
+    pub fn session_rate(&self) -> Option<Rate> {
+        self.session
+    }
+
+    pub fn subscription_rate(&self) -> Option<Rate> {
+        self.subscription
+    }
+
+    pub fn over_limit(&self) -> OverLimit {
+        self.over_limit
+    }
+}
+
+/// Token bucket filled at a [`Rate`], holding at most its burst.
+///
+/// An object may be sent once the bucket holds its size, or is full for
+/// objects larger than the burst; the bucket then goes into debt by the
+/// rest, so that large objects are delayed rather than blocked.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    rate: Rate,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket.
+    pub fn new(rate: Rate, now: Instant) -> Self {
+        Self {
+            rate,
+            tokens: rate.burst as f64,
+            updated: now,
+        }
+    }
+
+    pub fn rate(&self) -> Rate {
+        self.rate
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * self.rate.bytes_per_second as f64).min(self.rate.burst as f64);
+        self.updated = self.updated.max(now);
+    }
+
+    /// When `size` bytes may be sent, `now` if they may be sent right away.
+    pub fn ready_at(&mut self, size: usize, now: Instant) -> Instant {
+        self.refill(now);
+        let needed = (size as f64).min(self.rate.burst as f64);
+        if self.tokens >= needed {
+            return now;
+        }
+        let missing = needed - self.tokens;
+        now + Duration::from_secs_f64(missing / self.rate.bytes_per_second as f64)
+    }
+
+    /// Take `size` bytes, whether or not they were available.
+    pub fn take(&mut self, size: usize, now: Instant) {
+        self.refill(now);
+        self.tokens -= size as f64;
+    }
+}
+
+/// What [`Shaper::pop`] released.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Release<T> {
+    /// Send `item` of subscription `request_id` now.
+    Send { request_id: u64, item: T },
+    /// `count` queued objects of group `group_id` of subscription
+    /// `request_id` were dropped, and later ones of the group will be.
+    Dropped {
+        request_id: u64,
+        group_id: u64,
+        count: usize,
+    },
+    /// Nothing may be sent before the given instant.
+    Wait(Instant),
+    /// Nothing is queued.
+    Idle,
+}
+
+struct Queued<T> {
+    priority: Priority,
+    item: T,
+    size: usize,
+}
+
+struct SubscriptionQueue<T> {
+    queue: Scheduler<Queued<T>>,
+    bucket: Option<TokenBucket>,
+    /// Group whose objects are dropped.
+    dropping: Option<u64>,
+}
+
+/// Objects queued for a session, released in the draft's priority order
+/// within the session's and subscriptions' [`BandwidthLimits`].
+///
+/// The most urgent object whose subscription is within its cap goes
+/// first. If the session is over its cap, it is held until the session
+/// may send it; objects of lower priority wait behind it, as releasing
+/// them would take capacity it needs. Subscriptions over their cap are
+/// delayed or lose the rest of the group, according to [`OverLimit`].
+pub struct Shaper<T> {
+    limits: BandwidthLimits,
+    session: Option<TokenBucket>,
+    subscriptions: HashMap<u64, SubscriptionQueue<T>>,
+}
+
+impl<T> Shaper<T> {
+    pub fn new(limits: BandwidthLimits, now: Instant) -> Self {
+        Self {
+            limits,
+            session: limits.session.map(|rate| TokenBucket::new(rate, now)),
+            subscriptions: HashMap::new(),
+        }
+    }
+
+    pub fn limits(&self) -> &BandwidthLimits {
+        &self.limits
+    }
+
+    /// Cap subscription `request_id` at `rate` instead of the default
+    /// subscription rate, or lift its cap with `None`.
+    pub fn set_subscription_rate(&mut self, request_id: u64, rate: Option<Rate>, now: Instant) {
+        self.queue(request_id, now).bucket = rate.map(|rate| TokenBucket::new(rate, now));
+    }
+
+    /// Queue `item` of `size` bytes for subscription `request_id`. Returns
+    /// `false` if it was dropped instead, being part of a dropped group.
+    pub fn push(
+        &mut self,
+        request_id: u64,
+        priority: Priority,
+        item: T,
+        size: usize,
+        now: Instant,
+    ) -> bool {
+        let queue = self.queue(request_id, now);
+        if queue.dropping == Some(priority.group_id) {
+            return false;
+        }
+        queue.queue.push(
+            priority,
+            Queued {
+                priority,
+                item,
+                size,
+            },
+        );
+        true
+    }
+
+    /// Release the next object to send, or tell why none may be sent.
+    pub fn pop(&mut self, now: Instant) -> Release<T> {
+        let mut heads: Vec<_> = self
+            .subscriptions
+            .iter()
+            .filter_map(|(id, s)| Some((s.queue.peek()?.priority.key(), *id)))
+            .collect();
+        if heads.is_empty() {
+            return Release::Idle;
+        }
+        heads.sort_unstable();
+
+        let mut wait: Option<Instant> = None;
+        for (_, request_id) in heads {
+            let subscription = self
+                .subscriptions
+                .get_mut(&request_id)
+                .expect("head of a queued subscription");
+            let head = subscription.queue.peek().expect("queue is not empty");
+            let (size, group_id) = (head.size, head.priority.group_id);
+            if let Some(bucket) = &mut subscription.bucket {
+                let ready = bucket.ready_at(size, now);
+                if ready > now {
+                    if self.limits.over_limit == OverLimit::DropGroup {
+                        let before = subscription.queue.len();
+                        subscription
+                            .queue
+                            .retain(|q| q.priority.group_id != group_id);
+                        subscription.dropping = Some(group_id);
+                        return Release::Dropped {
+                            request_id,
+                            group_id,
+                            count: before - subscription.queue.len(),
+                        };
+                    }
+                    wait = Some(wait.map_or(ready, |w| w.min(ready)));
+                    continue;
+                }
+            }
+            if let Some(session) = &mut self.session {
+                let ready = session.ready_at(size, now);
+                if ready > now {
+                    return Release::Wait(ready);
+                }
+                session.take(size, now);
+            }
+            if let Some(bucket) = &mut subscription.bucket {
+                bucket.take(size, now);
+            }
+            let queued = subscription.queue.pop().expect("peeked above");
+            return Release::Send {
+                request_id,
+                item: queued.item,
+            };
+        }
+        Release::Wait(wait.expect("every subscription is over its cap"))
+    }
+
+    /// Drop everything queued for subscription `request_id` and forget it.
+    pub fn remove_subscription(&mut self, request_id: u64) {
+        self.subscriptions.remove(&request_id);
+    }
+
+    pub fn len(&self) -> usize {
+        self.subscriptions.values().map(|s| s.queue.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn queue(&mut self, request_id: u64, now: Instant) -> &mut SubscriptionQueue<T> {
+        let rate = self.limits.subscription;
+        self.subscriptions
+            .entry(request_id)
+            .or_insert_with(|| SubscriptionQueue {
+                queue: Scheduler::default(),
+                bucket: rate.map(|rate| TokenBucket::new(rate, now)),
+                dropping: None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn priority(subscriber_priority: u8, group_id: u64) -> Priority {
+        Priority {
+            subscriber_priority,
+            publisher_priority: 128,
+            group_order: 1,
+            group_id,
+            subgroup_id: 0,
+        }
+    }
+
+    fn sent<T>(release: Release<T>) -> (u64, T) {
+        match release {
+            Release::Send { request_id, item } => (request_id, item),
+            _ => panic!("nothing sent"),
+        }
+    }
+
+    #[test]
+    fn bucket_refills_at_rate() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(Rate::new(1000).with_burst(500), now);
+        assert_eq!(bucket.ready_at(500, now), now);
+        bucket.take(500, now);
+        assert_eq!(bucket.ready_at(250, now), now + Duration::from_millis(250));
+        // Larger than the burst: waits for a full bucket, then goes into
+        // debt.
+        let later = now + Duration::from_secs(1);
+        assert_eq!(bucket.ready_at(2000, later), later);
+        bucket.take(2000, later);
+        assert_eq!(
+            bucket.ready_at(1, later),
+            later + Duration::from_millis(1501)
+        );
+    }
+
+    #[test]
+    fn zero_rate_is_one_byte_per_second() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(Rate::new(0), now);
+        assert_eq!(bucket.ready_at(1, now), now);
+        bucket.take(1, now);
+        assert_eq!(bucket.ready_at(1, now), now + Duration::from_secs(1));
+    }
+
+    #[test]
+    fn subscription_cap_delays_and_lets_others_through() {
+        let now = Instant::now();
+        let limits = BandwidthLimits::default().with_subscription_rate(Rate::new(1000));
+        let mut shaper = Shaper::new(limits, now);
+        shaper.set_subscription_rate(2, None, now);
+        for object in 0..2 {
+            shaper.push(1, priority(0, 0), object, 1000, now);
+        }
+        shaper.push(2, priority(200, 0), 10, 1000, now);
+
+        assert_eq!(sent(shaper.pop(now)), (1, 0));
+        // Subscription 1 is over its cap, the less urgent one goes.
+        assert_eq!(sent(shaper.pop(now)), (2, 10));
+        assert_eq!(shaper.pop(now), Release::Wait(now + Duration::from_secs(1)));
+        let later = now + Duration::from_secs(1);
+        assert_eq!(sent(shaper.pop(later)), (1, 1));
+        assert_eq!(shaper.pop(later), Release::Idle);
+    }
+
+    #[test]
+    fn session_cap_holds_back_lower_priorities() {
+        let now = Instant::now();
+        let limits = BandwidthLimits::default().with_session_rate(Rate::new(1000).with_burst(1500));
+        let mut shaper = Shaper::new(limits, now);
+        shaper.push(1, priority(0, 0), "urgent", 1000, now);
+        shaper.push(1, priority(0, 0), "urgent", 1000, now);
+        shaper.push(2, priority(200, 0), "small", 100, now);
+
+        assert_eq!(sent(shaper.pop(now)), (1, "urgent"));
+        assert_eq!(
+            shaper.pop(now),
+            Release::Wait(now + Duration::from_millis(500))
+        );
+        let later = now + Duration::from_millis(500);
+        assert_eq!(sent(shaper.pop(later)), (1, "urgent"));
+        assert!(matches!(shaper.pop(later), Release::Wait(_)));
+        assert_eq!(shaper.len(), 1);
+    }
+
+    #[test]
+    fn over_limit_groups_are_dropped() {
+        let now = Instant::now();
+        let limits = BandwidthLimits::default()
+            .with_subscription_rate(Rate::new(1000))
+            .with_over_limit(OverLimit::DropGroup);
+        let mut shaper = Shaper::new(limits, now);
+        for object in 0..3 {
+            assert!(shaper.push(1, priority(0, 7), object, 600, now));
+        }
+        assert_eq!(sent(shaper.pop(now)), (1, 0));
+        assert_eq!(
+            shaper.pop(now),
+            Release::Dropped {
+                request_id: 1,
+                group_id: 7,
+                count: 2
+            }
+        );
+        assert!(!shaper.push(1, priority(0, 7), 3, 600, now));
+
+        let later = now + Duration::from_secs(1);
+        assert!(shaper.push(1, priority(0, 8), 0, 600, later));
+        assert_eq!(sent(shaper.pop(later)), (1, 0));
+        shaper.remove_subscription(1);
+        assert!(shaper.is_empty());
+    }
+}
@@ -0,0 +1,270 @@
+//! Synchronous wrappers of a [`Session`] for applications that do not
+//! use async, such as command line tools and test scripts.
+//!
+//! The session still needs its control stream and data streams driven, by
+//! tasks spawned with [`BlockingSession::spawn`]. They run while the
+//! application blocks on the session, e.g. iterating over a subscription:
+//!
+//! ```ignore
+//! // `transport` is a handle to the same connection as `connection`.
+//! let (session, outgoing) = Session::new(Arc::new(transport));
+//! let session = BlockingSession::new(session)?;
+//! let (reader, writer) = session.block_on(connection.open_bi_stream())?.split();
+//!
+//! // Write the outgoing control messages, starting with CLIENT_SETUP.
+//! session.spawn_control_writer(writer, outgoing);
+//! session.send_control(ControlMessage::ClientSetup(
+//!     ClientSetup::new([DRAFT_12]).with_max_request_id(16),
+//! ))?;
+//!
+//! // Read the incoming ones.
+//! let control = session.session().clone();
+//! session.spawn(async move {
+//!     let mut incoming = FramedRead::new(reader, control.control_codec());
+//!     while let Some(Ok(msg)) = incoming.next().await {
+//!         match msg {
+//!             ControlMessage::ServerSetup(setup) => {
+//!                 let max = setup.max_request_id().unwrap_or(0);
+//!                 let _ = control.track_manager.handle_max_request_id(max);
+//!                 control.activate();
+//!             }
+//!             ControlMessage::MaxRequestId(msg) => {
+//!                 let _ = control.track_manager.handle_max_request_id(msg.request_id);
+//!             }
+//!             ControlMessage::SubscribeOk(ok) => {
+//!                 let _ = control.track_manager.handle_subscribe_ok(&ok);
+//!             }
+//!             _ => {}
+//!         }
+//!     }
+//! });
+//!
+//! // Read the objects of the subscriptions from the data streams.
+//! let data = session.session().clone();
+//! let (fetch_streams, _) = mpsc::channel(1);
+//! session.spawn(async move {
+//!     let _ = data.accept_data_streams(&mut connection, fetch_streams).await;
+//! });
+//!
+//! for object in session.subscribe(SubscribeRequest::new(0, "video"))? {
+//!     println!("{:?}", object?.metadata.location());
+//! }
+//! ```
+
+use std::future::Future;
+use std::sync::Arc;
+
+use tokio::io::AsyncWrite;
+use tokio::runtime::{Builder, Runtime};
+use tokio::sync::mpsc;
+
+use crate::{
+    error::Error,
+    message::ControlMessage,
+    request::{AnnounceRequest, SubscribeRequest},
+    session::Session,
+    subscription::SubscriptionHandle,
+    track::Object,
+    transport::Transport,
+};
+
+/// A [`Session`] driven by a runtime of its own.
+///
+/// The runtime runs on the calling thread, only while a method of the
+/// session or of one of its subscriptions blocks: background tasks of the
+/// session, such as those reading data streams, make progress during those
+/// calls only. Must not be used from within an async context.
+pub struct BlockingSession<T: Transport> {
+    runtime: Arc<Runtime>,
+    session: Arc<Session<T>>,
+}
+
+impl<T: Transport> BlockingSession<T> {
+    pub fn new(session: Session<T>) -> Result<Self, Error> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        Ok(Self {
+            runtime: Arc::new(runtime),
+            session: Arc::new(session),
+        })
+    }
+
+    /// The wrapped session, e.g. to hand to tasks started with
+    /// [`block_on`](Self::block_on).
+    pub fn session(&self) -> &Arc<Session<T>> {
+        &self.session
+    }
+
+    /// Spawn a background task of the session on its runtime, e.g. to
+    /// drive the control stream. Like the session's other tasks, it makes
+    /// progress only while a blocking method runs. Returns `false` if the
+    /// session has already been shut down.
+    pub fn spawn<F>(&self, task: F) -> bool
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let _runtime = self.runtime.enter();
+        self.session.spawn(task)
+    }
+
+    /// [`Session::spawn_control_writer`] on the session's runtime.
+    pub fn spawn_control_writer<W>(
+        &self,
+        writer: W,
+        outgoing: mpsc::Receiver<ControlMessage>,
+    ) -> bool
+    where
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let _runtime = self.runtime.enter();
+        self.session.spawn_control_writer(writer, outgoing)
+    }
+
+    /// Run `future` to completion on the session's runtime.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// Blocking [`Session::subscribe`]. The returned subscription iterates
+    /// over the objects received.
+    pub fn subscribe(&self, request: SubscribeRequest) -> Result<BlockingSubscription, Error> {
+        let handle = self.block_on(self.session.subscribe(request))?;
+        Ok(BlockingSubscription {
+            runtime: self.runtime.clone(),
+            handle,
+        })
+    }
+
+    /// Blocking [`Session::announce`].
+    pub fn announce(&self, request: AnnounceRequest) -> Result<u64, Error> {
+        self.block_on(self.session.announce(request))
+    }
+
+    /// Blocking [`Session::unannounce`].
+    pub fn unannounce(&self, namespace: &[String]) -> Result<(), Error> {
+        self.block_on(self.session.unannounce(namespace))
+    }
+
+    /// Blocking [`Session::send_control`].
+    pub fn send_control(&self, msg: ControlMessage) -> Result<(), Error> {
+        self.block_on(self.session.send_control(msg))
+    }
+
+    /// Blocking [`Session::shutdown`].
+    pub fn shutdown(&self) {
+        self.block_on(self.session.shutdown())
+    }
+}
+
+/// A subscription of a [`BlockingSession`], iterating over the objects
+/// received until the subscription ends.
+pub struct BlockingSubscription {
+    runtime: Arc<Runtime>,
+    handle: SubscriptionHandle,
+}
+
+impl BlockingSubscription {
+    pub fn request_id(&self) -> u64 {
+        self.handle.request_id()
+    }
+
+    /// The wrapped handle, e.g. to check
+    /// [`SubscriptionHandle::is_established`] before updating it.
+    pub fn handle(&self) -> &SubscriptionHandle {
+        &self.handle
+    }
+
+    /// Blocking [`SubscriptionHandle::recv`].
+    pub fn recv(&mut self) -> Option<Result<Object, Error>> {
+        self.runtime.block_on(self.handle.recv())
+    }
+
+    /// The next object if one is queued, without blocking.
+    pub fn try_recv(&mut self) -> Option<Result<Object, Error>> {
+        self.handle.try_recv()
+    }
+
+    /// Blocking [`SubscriptionHandle::unsubscribe`].
+    pub fn unsubscribe(self) -> Result<(), Error> {
+        self.runtime.block_on(self.handle.unsubscribe())
+    }
+}
+
+impl Iterator for BlockingSubscription {
+    type Item = Result<Object, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::{message::SubscribeOk, mock::MockTransport, track::TrackPublisher};
+
+    #[test]
+    fn subscription_iterates_over_objects() {
+        let (transport, _peer) = MockTransport::pair();
+        let (session, mut control) = Session::new(Arc::new(transport));
+        session.track_manager.handle_max_request_id(1).unwrap();
+        let session = BlockingSession::new(session).unwrap();
+
+        let mut subscription = session
+            .subscribe(SubscribeRequest::new(0, "video"))
+            .unwrap();
+        assert!(matches!(
+            control.try_recv(),
+            Ok(ControlMessage::Subscribe(s)) if s.request_id == subscription.request_id()
+        ));
+        let tracks = &session.session().track_manager;
+        tracks
+            .handle_subscribe_ok(&SubscribeOk {
+                request_id: subscription.request_id(),
+                track_alias: 1,
+                expires: 0,
+                group_order: 1,
+                content_exists: false,
+                largest_location: None,
+                parameters: Vec::new(),
+            })
+            .unwrap();
+
+        let mut publisher = TrackPublisher::new(1);
+        for frame in [&b"key"[..], b"delta"] {
+            for object in publisher.push_frame(frame == b"key", Bytes::from_static(frame)) {
+                tracks.deliver(object);
+            }
+        }
+        let payloads: Vec<_> = subscription
+            .by_ref()
+            .take(2)
+            .map(|o| o.unwrap().payload)
+            .collect();
+        assert_eq!(payloads, [&b"key"[..], b"delta"]);
+
+        subscription.unsubscribe().unwrap();
+        assert!(matches!(
+            control.try_recv(),
+            Ok(ControlMessage::Unsubscribe(_))
+        ));
+        session.shutdown();
+    }
+
+    #[test]
+    fn spawned_tasks_run_while_blocking() {
+        let (transport, _peer) = MockTransport::pair();
+        let (session, _control) = Session::new(Arc::new(transport));
+        let session = BlockingSession::new(session).unwrap();
+
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        assert!(session.spawn(async move {
+            let _ = tx.send(());
+        }));
+        assert!(session.block_on(rx).is_ok());
+
+        session.shutdown();
+        assert!(!session.spawn(async {}));
+    }
+}
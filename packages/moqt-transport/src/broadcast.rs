@@ -0,0 +1,351 @@
+use std::sync::Mutex;
+
+use tokio::sync::mpsc;
+use tokio::time::{Instant, timeout_at};
+
+use crate::{
+    error::Error,
+    track::{Object, ObjectStream, TrackPublisher},
+};
+
+/// Identifies one session a [`Broadcast`] is attached to.
+pub type AttachmentId = u64;
+
+/// Per-session delivery counters of a [`Broadcast`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AttachmentStats {
+    /// Objects queued for the session.
+    pub sent: u64,
+    /// Objects the publisher had to wait for room in the session's queue
+    /// for.
+    pub waited: u64,
+    /// Objects dropped because the session's queue had no room for them
+    /// within the negotiated delivery timeout.
+    pub expired: u64,
+}
+
+struct Attachment {
+    id: AttachmentId,
+    publisher: TrackPublisher,
+    tx: mpsc::Sender<Result<Object, Error>>,
+    stats: AttachmentStats,
+}
+
+struct BroadcastState {
+    next_id: AttachmentId,
+    attachments: Vec<Attachment>,
+}
+
+/// A track pushed to several sessions at once, e.g. an origin publishing
+/// the same track to multiple relays.
+///
+/// Each session is attached with its own [`TrackPublisher`], carrying the
+/// track alias and delivery parameters negotiated on that session, and its
+/// own bounded queue. A slow session only fills its own queue: the others
+/// are handed each object as soon as it is published, while the publisher
+/// waits for room in the slow session's queue before publishing the next.
+/// Full queues are waited for in the order of the priority negotiated on
+/// each session, and for at most the negotiated delivery timeout, after
+/// which the object is dropped for that session.
+pub struct Broadcast {
+    capacity: usize,
+    state: Mutex<BroadcastState>,
+}
+
+impl Broadcast {
+    /// Create a broadcast queuing at most `capacity` objects per session,
+    /// at least one.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            state: Mutex::new(BroadcastState {
+                next_id: 0,
+                attachments: Vec::new(),
+            }),
+        }
+    }
+
+    /// Attach a session. Objects delivered through the returned stream
+    /// carry the session's track alias.
+    pub fn attach(&self, publisher: TrackPublisher) -> (AttachmentId, ObjectStream) {
+        let (tx, rx) = mpsc::channel(self.capacity);
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.attachments.push(Attachment {
+            id,
+            publisher,
+            tx,
+            stats: AttachmentStats::default(),
+        });
+        (id, ObjectStream { rx })
+    }
+
+    /// Detach a session, ending its object stream.
+    pub fn detach(&self, id: AttachmentId) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let before = state.attachments.len();
+        state.attachments.retain(|a| a.id != id);
+        state.attachments.len() != before
+    }
+
+    /// Queue an object on every attached session that should receive it,
+    /// waiting for room in the queues that are full, most urgent session
+    /// first. Sessions whose stream has been dropped are detached.
+    pub async fn publish(&self, object: &Object) {
+        let now = Instant::now();
+        let loc = object.metadata.location();
+        let mut full = Vec::new();
+        {
+            let mut state = self.state.lock().unwrap();
+            state.attachments.retain_mut(|a| {
+                if !a.publisher.should_send(&loc) {
+                    return !a.tx.is_closed();
+                }
+                let mut object = object.clone();
+                object.metadata.track_alias = a.publisher.alias();
+                let priority = a.publisher.priority(&object.metadata);
+                match a.tx.try_send(Ok(object)) {
+                    Ok(()) => a.stats.sent += 1,
+                    Err(mpsc::error::TrySendError::Full(object)) => {
+                        let deadline = a.publisher.delivery_timeout().map(|t| now + t);
+                        full.push((priority, deadline, a.id, a.tx.clone(), object));
+                    }
+                    Err(mpsc::error::TrySendError::Closed(_)) => return false,
+                }
+                true
+            });
+        }
+        full.sort_by_key(|(priority, ..)| priority.key());
+        for (_, deadline, id, tx, object) in full {
+            let sent = match deadline {
+                Some(deadline) => timeout_at(deadline, tx.send(object)).await.ok(),
+                None => Some(tx.send(object).await),
+            };
+            let mut state = self.state.lock().unwrap();
+            let Some(sent) = sent else {
+                if let Some(a) = state.attachments.iter_mut().find(|a| a.id == id) {
+                    a.stats.expired += 1;
+                }
+                continue;
+            };
+            if sent.is_err() {
+                state.attachments.retain(|a| a.id != id);
+            } else if let Some(a) = state.attachments.iter_mut().find(|a| a.id == id) {
+                a.stats.sent += 1;
+                a.stats.waited += 1;
+            }
+        }
+    }
+
+    pub fn stats(&self, id: AttachmentId) -> Option<AttachmentStats> {
+        let state = self.state.lock().unwrap();
+        state
+            .attachments
+            .iter()
+            .find(|a| a.id == id)
+            .map(|a| a.stats)
+    }
+
+    pub fn attachment_count(&self) -> usize {
+        self.state.lock().unwrap().attachments.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::ObjectBuilder;
+    use crate::model::Filter;
+    use crate::publish::DeliveryParams;
+    use crate::scheduler::GROUP_ORDER_ASCENDING;
+    use bytes::Bytes;
+    use std::time::Duration;
+
+    fn object(object_id: u64) -> Object {
+        ObjectBuilder::new(0, object_id)
+            .with_payload(Bytes::from_static(b"x"))
+            .build()
+    }
+
+    fn run(test: impl std::future::Future<Output = ()>) {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(test);
+    }
+
+    #[test]
+    fn each_session_gets_its_alias() {
+        run(async {
+            let broadcast = Broadcast::new(8);
+            let (_, mut a) = broadcast.attach(TrackPublisher::new(1));
+            let (_, mut b) = broadcast.attach(TrackPublisher::new(2));
+
+            broadcast.publish(&object(0)).await;
+
+            assert_eq!(a.rx.try_recv().unwrap().unwrap().metadata.track_alias, 1);
+            assert_eq!(b.rx.try_recv().unwrap().unwrap().metadata.track_alias, 2);
+        });
+    }
+
+    #[test]
+    fn slow_session_holds_back_only_the_publisher() {
+        use std::future::Future;
+        use std::task::Poll;
+
+        run(async {
+            let broadcast = Broadcast::new(0);
+            let (slow_id, mut slow) = broadcast.attach(TrackPublisher::new(1));
+            let (fast_id, mut fast) = broadcast.attach(TrackPublisher::new(2));
+
+            broadcast.publish(&object(0)).await;
+            fast.rx.try_recv().unwrap().unwrap();
+
+            // The slow session's queue is full: the fast one gets the
+            // object at once, the publisher waits until the slow one reads.
+            let next = object(1);
+            let mut publish = std::pin::pin!(broadcast.publish(&next));
+            std::future::poll_fn(|cx| {
+                assert!(publish.as_mut().poll(cx).is_pending());
+                Poll::Ready(())
+            })
+            .await;
+            assert_eq!(fast.rx.try_recv().unwrap().unwrap().metadata.object_id, 1);
+            assert_eq!(slow.rx.try_recv().unwrap().unwrap().metadata.object_id, 0);
+            publish.await;
+            assert_eq!(slow.rx.try_recv().unwrap().unwrap().metadata.object_id, 1);
+
+            assert_eq!(
+                broadcast.stats(slow_id),
+                Some(AttachmentStats {
+                    sent: 2,
+                    waited: 1,
+                    expired: 0
+                })
+            );
+            assert_eq!(
+                broadcast.stats(fast_id),
+                Some(AttachmentStats {
+                    sent: 2,
+                    waited: 0,
+                    expired: 0
+                })
+            );
+        });
+    }
+
+    fn negotiated(subscriber_priority: u8, delivery_timeout: Option<Duration>) -> TrackPublisher {
+        let mut publisher = TrackPublisher::new(1);
+        publisher.set_delivery(DeliveryParams {
+            forward: true,
+            subscriber_priority,
+            group_order: GROUP_ORDER_ASCENDING,
+            filter: Filter::LargestObject,
+            largest: None,
+            delivery_timeout,
+        });
+        publisher
+    }
+
+    #[test]
+    fn full_queues_are_waited_for_by_negotiated_priority() {
+        use std::future::Future;
+        use std::task::Poll;
+
+        run(async {
+            let broadcast = Broadcast::new(1);
+            let (_, mut relaxed) = broadcast.attach(negotiated(200, None));
+            let (_, mut urgent) = broadcast.attach(negotiated(10, None));
+            broadcast.publish(&object(0)).await;
+
+            // The urgent session is waited for first, even though it was
+            // attached last.
+            let next = object(1);
+            let mut publish = std::pin::pin!(broadcast.publish(&next));
+            std::future::poll_fn(|cx| {
+                assert!(publish.as_mut().poll(cx).is_pending());
+                Poll::Ready(())
+            })
+            .await;
+            urgent.try_recv().unwrap().unwrap();
+            std::future::poll_fn(|cx| {
+                assert!(publish.as_mut().poll(cx).is_pending());
+                Poll::Ready(())
+            })
+            .await;
+            assert_eq!(urgent.try_recv().unwrap().unwrap().metadata.object_id, 1);
+
+            relaxed.try_recv().unwrap().unwrap();
+            publish.await;
+            assert_eq!(relaxed.try_recv().unwrap().unwrap().metadata.object_id, 1);
+        });
+    }
+
+    #[test]
+    fn object_waiting_past_delivery_timeout_is_dropped() {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .start_paused(true)
+            .build()
+            .unwrap()
+            .block_on(async {
+                let broadcast = Broadcast::new(1);
+                let (id, mut stream) =
+                    broadcast.attach(negotiated(128, Some(Duration::from_millis(100))));
+                broadcast.publish(&object(0)).await;
+
+                // Nobody reads the session: the publisher gives up on it
+                // after the delivery timeout instead of waiting forever.
+                broadcast.publish(&object(1)).await;
+                assert_eq!(
+                    broadcast.stats(id),
+                    Some(AttachmentStats {
+                        sent: 1,
+                        waited: 0,
+                        expired: 1
+                    })
+                );
+                assert_eq!(stream.try_recv().unwrap().unwrap().metadata.object_id, 0);
+                assert!(stream.try_recv().is_none());
+            });
+    }
+
+    #[test]
+    fn dropped_stream_detaches_session() {
+        run(async {
+            let broadcast = Broadcast::new(2);
+            let (_, stream) = broadcast.attach(TrackPublisher::new(1));
+            let (id, _other) = broadcast.attach(TrackPublisher::new(2));
+            drop(stream);
+
+            broadcast.publish(&object(0)).await;
+            assert_eq!(broadcast.attachment_count(), 1);
+            assert!(broadcast.detach(id));
+            assert_eq!(broadcast.attachment_count(), 0);
+        });
+    }
+
+    #[test]
+    fn session_dropped_while_waiting_is_detached() {
+        use std::future::Future;
+        use std::task::Poll;
+
+        run(async {
+            let broadcast = Broadcast::new(1);
+            let (_, stream) = broadcast.attach(TrackPublisher::new(1));
+            broadcast.publish(&object(0)).await;
+
+            let next = object(1);
+            let mut publish = std::pin::pin!(broadcast.publish(&next));
+            std::future::poll_fn(|cx| {
+                assert!(publish.as_mut().poll(cx).is_pending());
+                Poll::Ready(())
+            })
+            .await;
+            drop(stream);
+            publish.await;
+            assert_eq!(broadcast.attachment_count(), 0);
+        });
+    }
+}
@@ -1,17 +1,21 @@
 mod length;
+mod limits;
 mod message;
+mod parameters;
 mod varint;
+mod version;
 
 pub use length::*;
+pub use limits::*;
 pub use message::*;
-pub use varint::*;
+pub use parameters::*;
+pub use varint::VarInt;
+pub use version::*;
 
-use bytes::BytesMut;
-
-pub trait Encode {
-    fn encode(&self, buf: &mut BytesMut) -> Result<(), crate::error::Error>;
-}
-
-pub trait Decode: Sized {
-    fn decode(buf: &mut BytesMut) -> Result<Self, crate::error::Error>;
-}
+pub use moqt_wire::codec::{
+    BytesField, Decode, DecodeCtx, Encode, MAX_FULL_TRACK_NAME_LENGTH, MAX_NAMESPACE_FIELDS,
+    MAX_PARAMETER_VALUE_LENGTH, MAX_PARAMETERS, MAX_REASON_PHRASE_LENGTH, MAX_URI_LENGTH,
+    MAX_VERSIONS, StringField, bounded_vec, decode_namespace, decode_namespace_prefix,
+    encode_namespace, encode_namespace_prefix, is_namespace_prefix, namespace_eq,
+    validate_full_track_name, validate_namespace,
+};
@@ -1,4 +1,4 @@
-use bytes::BytesMut;
+use bytes::{Buf, BytesMut};
 use tokio_util::codec::{Decoder, Encoder};
 
 use crate::codec::{Decode, Encode, VarInt};
@@ -7,6 +7,12 @@ pub struct WithLengthCodec<T> {
     _marker: std::marker::PhantomData<T>,
 }
 
+impl Default for WithLengthCodec<()> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl WithLengthCodec<()> {
     pub fn new() -> Self {
         WithLengthCodec {
@@ -35,20 +41,50 @@ impl<T: Decode> Decoder for WithLengthCodec<T> {
     type Error = crate::error::Error;
 
     fn decode(&mut self, src: &mut bytes::BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        if src.len() < 1 {
+        if src.is_empty() {
             return Ok(None);
         }
 
-        if let Some(len) = VarInt.decode(src)? {
-            let len = len as usize;
-            if src.len() < len {
-                // TODO: handle this case properly
-                todo!()
+        // Nothing is consumed until the whole item has arrived.
+        let Some((len, len_len)) = VarInt.peek(src) else {
+            return Ok(None);
+        };
+        let len = len as usize;
+        if src.len() - len_len < len {
+            return Ok(None);
+        }
+        src.advance(len_len);
+        let item = T::decode(&mut src.split_to(len))?;
+        Ok(Some(item))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::MaxRequestId;
+
+    #[test]
+    fn partial_item_is_left_in_buffer() {
+        let mut encoded = BytesMut::new();
+        WithLengthCodec::new()
+            .encode(MaxRequestId { request_id: 300 }, &mut encoded)
+            .unwrap();
+
+        let mut codec = WithLengthCodec::<MaxRequestId> {
+            _marker: std::marker::PhantomData,
+        };
+        let mut buf = BytesMut::new();
+        for (i, byte) in encoded.iter().enumerate() {
+            buf.extend_from_slice(&[*byte]);
+            let decoded = codec.decode(&mut buf).unwrap();
+            if i + 1 < encoded.len() {
+                assert!(decoded.is_none());
+                assert_eq!(buf.len(), i + 1);
+            } else {
+                assert_eq!(decoded.unwrap().request_id, 300);
+                assert!(buf.is_empty());
             }
-            let item = T::decode(&mut src.split_to(len))?;
-            Ok(Some(item))
-        } else {
-            Ok(None)
         }
     }
 }
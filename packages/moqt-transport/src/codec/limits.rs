@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+
+use crate::message::ControlMessageType;
+
+/// Default largest control message payload accepted by the decoder: the
+/// most the 16-bit length of a draft-12 control message can state. A
+/// request may carry a full track name of up to 4096 bytes on top of its
+/// authorization tokens, so anything smaller rejects valid messages.
+pub const DEFAULT_MAX_CONTROL_MESSAGE_SIZE: usize = u16::MAX as usize;
+
+/// Largest payload of messages that carry nothing but a few varints.
+const SMALL_MESSAGE_SIZE: usize = 16;
+
+/// Messages whose payload is at most a couple of varints.
+const SMALL_MESSAGES: &[u64] = &[
+    ControlMessageType::MaxRequestId as u64,
+    ControlMessageType::RequestsBlocked as u64,
+    ControlMessageType::Unsubscribe as u64,
+    ControlMessageType::FetchCancel as u64,
+    ControlMessageType::AnnounceOk as u64,
+    ControlMessageType::SubscribeAnnouncesOk as u64,
+];
+
+/// Maximum payload size of each control message type, checked by
+/// [`ControlMessageCodec`](crate::codec::ControlMessageCodec) as soon as
+/// the length is read and before the payload is buffered.
+///
+/// Messages that only carry a few varints are limited to 16 bytes, every
+/// other type to [`DEFAULT_MAX_CONTROL_MESSAGE_SIZE`] unless overridden.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageSizeLimits {
+    default: usize,
+    limits: HashMap<u64, usize>,
+}
+
+impl Default for MessageSizeLimits {
+    fn default() -> Self {
+        Self {
+            default: DEFAULT_MAX_CONTROL_MESSAGE_SIZE,
+            limits: SMALL_MESSAGES
+                .iter()
+                .map(|t| (*t, SMALL_MESSAGE_SIZE))
+                .collect(),
+        }
+    }
+}
+
+impl MessageSizeLimits {
+    /// Limit of message types without an explicit limit, including unknown
+    /// types surfaced by the codec.
+    pub fn with_default(mut self, max: usize) -> Self {
+        self.default = max;
+        self
+    }
+
+    pub fn with_limit(mut self, message_type: ControlMessageType, max: usize) -> Self {
+        self.limits.insert(message_type as u64, max);
+        self
+    }
+
+    /// Maximum payload size of messages of type `message_type`.
+    pub fn limit(&self, message_type: u64) -> usize {
+        self.limits
+            .get(&message_type)
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_take_precedence() {
+        let limits = MessageSizeLimits::default()
+            .with_default(1024)
+            .with_limit(ControlMessageType::Subscribe, 256);
+        assert_eq!(limits.limit(ControlMessageType::Subscribe as u64), 256);
+        assert_eq!(limits.limit(ControlMessageType::Fetch as u64), 1024);
+        assert_eq!(limits.limit(ControlMessageType::MaxRequestId as u64), 16);
+        assert_eq!(limits.limit(0x3e), 1024);
+    }
+}
@@ -1,9 +1,12 @@
-use bytes::{BufMut, BytesMut};
+use bytes::{Buf, BufMut, BytesMut};
 use tokio_util::codec::{Decoder, Encoder};
 
 use crate::{
-    codec::{Decode, VarInt, WithLengthCodec},
-    error::Error,
+    codec::{
+        Decode, DecodeCtx, MessageSizeLimits, VarInt, WireVersion, WithLengthCodec,
+        check_duplicate_parameters, parameters::message_parameters,
+    },
+    error::{Error, Offending},
     message::{
         Announce, AnnounceCancel, AnnounceError, AnnounceOk, ClientSetup, ControlMessage,
         ControlMessageType, Fetch, FetchCancel, FetchError, FetchOk, Goaway, MaxRequestId, Publish,
@@ -12,327 +15,465 @@ use crate::{
         SubscribeUpdate, TrackStatus, TrackStatusRequest, Unannounce, Unsubscribe,
         UnsubscribeAnnounces,
     },
+    observe::{Observer, Observers},
 };
 
-pub struct ControlMessageCodec;
+/// How the codec reacts to a control message whose type it does not know.
+///
+/// The draft requires an endpoint to close the session with
+/// PROTOCOL_VIOLATION on an unknown message type, so [`Reject`] is the
+/// default. [`Surface`] consumes the declared length and yields
+/// [`ControlMessage::Unknown`] instead, keeping the stream in sync so the
+/// application can log the message and decide itself.
+///
+/// [`Reject`]: UnknownMessagePolicy::Reject
+/// [`Surface`]: UnknownMessagePolicy::Surface
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownMessagePolicy {
+    #[default]
+    Reject,
+    Surface,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ControlMessageCodec {
+    unknown_message_policy: UnknownMessagePolicy,
+    size_limits: MessageSizeLimits,
+    version: WireVersion,
+    observers: Observers,
+}
+
+impl ControlMessageCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_unknown_message_policy(mut self, policy: UnknownMessagePolicy) -> Self {
+        self.unknown_message_policy = policy;
+        self
+    }
+
+    pub fn with_size_limits(mut self, limits: MessageSizeLimits) -> Self {
+        self.size_limits = limits;
+        self
+    }
+
+    /// Speak the wire format of `version`, draft-12 by default. The
+    /// decoder and encoder of a control stream should share one version.
+    pub fn with_version(mut self, version: WireVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Switch to the wire format negotiated through the setup messages,
+    /// which are encoded the same way in every version.
+    pub fn set_version(&mut self, version: WireVersion) {
+        self.version = version;
+    }
+
+    pub fn version(&self) -> &WireVersion {
+        &self.version
+    }
+
+    /// Notify `observers` of every message encoded or decoded.
+    pub fn with_observers(mut self, observers: Observers) -> Self {
+        self.observers = observers;
+        self
+    }
+}
 
 impl Encoder<ControlMessage> for ControlMessageCodec {
     type Error = Error;
 
     fn encode(&mut self, item: ControlMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        let mut with_length = WithLengthCodec::new();
-
-        match item {
-            ControlMessage::ClientSetup(msg) => {
-                VarInt.encode(ControlMessageType::ClientSetup as u64, dst)?;
-                with_length.encode(msg, dst)?;
-            }
-            ControlMessage::ServerSetup(msg) => {
-                VarInt.encode(ControlMessageType::ServerSetup as u64, dst)?;
-                with_length.encode(msg, dst)?;
-            }
-            ControlMessage::Subscribe(msg) => {
-                VarInt.encode(ControlMessageType::Subscribe as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::SubscribeAnnounces(msg) => {
-                VarInt.encode(ControlMessageType::SubscribeAnnounces as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::SubscribeAnnouncesOk(msg) => {
-                VarInt.encode(ControlMessageType::SubscribeAnnouncesOk as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::SubscribeAnnouncesError(msg) => {
-                VarInt.encode(ControlMessageType::SubscribeAnnouncesError as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::SubscribeOk(msg) => {
-                VarInt.encode(ControlMessageType::SubscribeOk as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::SubscribeError(msg) => {
-                VarInt.encode(ControlMessageType::SubscribeError as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::SubscribeUpdate(msg) => {
-                VarInt.encode(ControlMessageType::SubscribeUpdate as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::Unsubscribe(msg) => {
-                VarInt.encode(ControlMessageType::Unsubscribe as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::UnsubscribeAnnounces(msg) => {
-                VarInt.encode(ControlMessageType::UnsubscribeAnnounces as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::SubscribeDone(msg) => {
-                VarInt.encode(ControlMessageType::SubscribeDone as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::Publish(msg) => {
-                VarInt.encode(ControlMessageType::Publish as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::PublishOk(msg) => {
-                VarInt.encode(ControlMessageType::PublishOk as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::PublishError(msg) => {
-                VarInt.encode(ControlMessageType::PublishError as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::Fetch(msg) => {
-                VarInt.encode(ControlMessageType::Fetch as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::FetchOk(msg) => {
-                VarInt.encode(ControlMessageType::FetchOk as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::FetchError(msg) => {
-                VarInt.encode(ControlMessageType::FetchError as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::FetchCancel(msg) => {
-                VarInt.encode(ControlMessageType::FetchCancel as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::Goaway(msg) => {
-                VarInt.encode(ControlMessageType::Goaway as u64, dst)?;
-                with_length.encode(msg, dst)?;
-            }
-            ControlMessage::MaxRequestId(msg) => {
-                VarInt.encode(ControlMessageType::MaxRequestId as u64, dst)?;
-                with_length.encode(msg, dst)?;
-            }
-            ControlMessage::RequestsBlocked(msg) => {
-                VarInt.encode(ControlMessageType::RequestsBlocked as u64, dst)?;
-                with_length.encode(msg, dst)?;
-            }
-            ControlMessage::TrackStatus(msg) => {
-                VarInt.encode(ControlMessageType::TrackStatus as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::TrackStatusRequest(msg) => {
-                VarInt.encode(ControlMessageType::TrackStatusRequest as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::Announce(msg) => {
-                VarInt.encode(ControlMessageType::Announce as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::AnnounceOk(msg) => {
-                VarInt.encode(ControlMessageType::AnnounceOk as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::AnnounceError(msg) => {
-                VarInt.encode(ControlMessageType::AnnounceError as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::Unannounce(msg) => {
-                VarInt.encode(ControlMessageType::Unannounce as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
-            ControlMessage::AnnounceCancel(msg) => {
-                VarInt.encode(ControlMessageType::AnnounceCancel as u64, dst)?;
-                let mut buf = BytesMut::new();
-                msg.encode(&mut buf)?;
-                VarInt.encode(buf.len() as u64, dst)?;
-                dst.put(buf);
-            }
+        self.observers.on_message_sent(&item);
+        if let WireVersion::Draft12 = self.version {
+            return encode_message(item, dst);
         }
-        Ok(())
+        let mut frame = BytesMut::new();
+        encode_message(item, &mut frame)?;
+        self.version.downgrade(frame, dst)
     }
 }
 
-impl Decoder for ControlMessageCodec {
-    type Item = ControlMessage;
-    type Error = Error;
+/// Encode `item` in its draft-12 layout.
+fn encode_message(item: ControlMessage, dst: &mut BytesMut) -> Result<(), Error> {
+    let mut with_length = WithLengthCodec::new();
 
-    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let msg_type = match VarInt.decode(src)? {
-            Some(v) => v,
-            None => return Ok(None),
+    match item {
+        ControlMessage::ClientSetup(msg) => {
+            VarInt.encode(ControlMessageType::ClientSetup as u64, dst)?;
+            with_length.encode(msg, dst)?;
+        }
+        ControlMessage::ServerSetup(msg) => {
+            VarInt.encode(ControlMessageType::ServerSetup as u64, dst)?;
+            with_length.encode(msg, dst)?;
+        }
+        ControlMessage::Subscribe(msg) => {
+            VarInt.encode(ControlMessageType::Subscribe as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::SubscribeAnnounces(msg) => {
+            VarInt.encode(ControlMessageType::SubscribeAnnounces as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::SubscribeAnnouncesOk(msg) => {
+            VarInt.encode(ControlMessageType::SubscribeAnnouncesOk as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::SubscribeAnnouncesError(msg) => {
+            VarInt.encode(ControlMessageType::SubscribeAnnouncesError as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::SubscribeOk(msg) => {
+            VarInt.encode(ControlMessageType::SubscribeOk as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::SubscribeError(msg) => {
+            VarInt.encode(ControlMessageType::SubscribeError as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::SubscribeUpdate(msg) => {
+            VarInt.encode(ControlMessageType::SubscribeUpdate as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::Unsubscribe(msg) => {
+            VarInt.encode(ControlMessageType::Unsubscribe as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::UnsubscribeAnnounces(msg) => {
+            VarInt.encode(ControlMessageType::UnsubscribeAnnounces as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::SubscribeDone(msg) => {
+            VarInt.encode(ControlMessageType::SubscribeDone as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::Publish(msg) => {
+            VarInt.encode(ControlMessageType::Publish as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::PublishOk(msg) => {
+            VarInt.encode(ControlMessageType::PublishOk as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::PublishError(msg) => {
+            VarInt.encode(ControlMessageType::PublishError as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::Fetch(msg) => {
+            VarInt.encode(ControlMessageType::Fetch as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::FetchOk(msg) => {
+            VarInt.encode(ControlMessageType::FetchOk as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::FetchError(msg) => {
+            VarInt.encode(ControlMessageType::FetchError as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::FetchCancel(msg) => {
+            VarInt.encode(ControlMessageType::FetchCancel as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::Goaway(msg) => {
+            VarInt.encode(ControlMessageType::Goaway as u64, dst)?;
+            with_length.encode(msg, dst)?;
+        }
+        ControlMessage::MaxRequestId(msg) => {
+            VarInt.encode(ControlMessageType::MaxRequestId as u64, dst)?;
+            with_length.encode(msg, dst)?;
+        }
+        ControlMessage::RequestsBlocked(msg) => {
+            VarInt.encode(ControlMessageType::RequestsBlocked as u64, dst)?;
+            with_length.encode(msg, dst)?;
+        }
+        ControlMessage::TrackStatus(msg) => {
+            VarInt.encode(ControlMessageType::TrackStatus as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::TrackStatusRequest(msg) => {
+            VarInt.encode(ControlMessageType::TrackStatusRequest as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::Announce(msg) => {
+            VarInt.encode(ControlMessageType::Announce as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::AnnounceOk(msg) => {
+            VarInt.encode(ControlMessageType::AnnounceOk as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::AnnounceError(msg) => {
+            VarInt.encode(ControlMessageType::AnnounceError as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::Unannounce(msg) => {
+            VarInt.encode(ControlMessageType::Unannounce as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::AnnounceCancel(msg) => {
+            VarInt.encode(ControlMessageType::AnnounceCancel as u64, dst)?;
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf)?;
+            VarInt.encode(buf.len() as u64, dst)?;
+            dst.put(buf);
+        }
+        ControlMessage::Unknown {
+            message_type,
+            payload,
+        } => {
+            VarInt.encode(message_type, dst)?;
+            VarInt.encode(payload.len() as u64, dst)?;
+            dst.put(payload);
+        }
+    }
+    Ok(())
+}
+
+impl ControlMessageCodec {
+    fn decode_message(&mut self, src: &mut BytesMut) -> Result<Option<ControlMessage>, Error> {
+        // The header is only peeked at so that nothing is consumed until
+        // the whole message has arrived.
+        let Some((msg_type, type_len)) = VarInt.peek(src) else {
+            return Ok(None);
         };
-        let len = match VarInt.decode(src)? {
-            Some(v) => v as usize,
-            None => return Ok(None),
+        let Some((len, len_len)) = VarInt.peek(&src[type_len..]) else {
+            return Ok(None);
         };
-        if src.len() < len {
+        let len = len as usize;
+        let header_len = type_len + len_len;
+        // Checked before buffering the payload so a peer cannot make the
+        // decoder hold on to an oversized message.
+        let limit = self.size_limits.limit(msg_type);
+        if len > limit {
+            let header = src.split_to(header_len);
+            let error = Error::ProtocolViolation {
+                reason: format!(
+                    "control message {msg_type:#x} of {len} bytes exceeds limit of {limit}"
+                ),
+                context: None,
+            };
+            return Err(
+                error.with_offending("control.message_length", Offending::Bytes(header.freeze()))
+            );
+        }
+        if src.len() - header_len < len {
             return Ok(None);
         }
-        let mut payload = src.split_to(len);
-        let message = match ControlMessageType::try_from(msg_type)? {
-            ControlMessageType::ClientSetup => {
-                ControlMessage::ClientSetup(ClientSetup::decode(&mut payload)?)
-            }
-            ControlMessageType::ServerSetup => {
-                ControlMessage::ServerSetup(ServerSetup::decode(&mut payload)?)
-            }
-            ControlMessageType::Subscribe => {
-                ControlMessage::Subscribe(Subscribe::decode(&mut payload)?)
-            }
-            ControlMessageType::SubscribeAnnounces => {
-                ControlMessage::SubscribeAnnounces(SubscribeAnnounces::decode(&mut payload)?)
-            }
-            ControlMessageType::SubscribeAnnouncesOk => {
-                ControlMessage::SubscribeAnnouncesOk(SubscribeAnnouncesOk::decode(&mut payload)?)
-            }
-            ControlMessageType::SubscribeAnnouncesError => ControlMessage::SubscribeAnnouncesError(
-                SubscribeAnnouncesError::decode(&mut payload)?,
-            ),
-            ControlMessageType::SubscribeOk => {
-                ControlMessage::SubscribeOk(SubscribeOk::decode(&mut payload)?)
-            }
-            ControlMessageType::SubscribeError => {
-                ControlMessage::SubscribeError(SubscribeError::decode(&mut payload)?)
-            }
-            ControlMessageType::SubscribeUpdate => {
-                ControlMessage::SubscribeUpdate(SubscribeUpdate::decode(&mut payload)?)
-            }
-            ControlMessageType::Unsubscribe => {
-                ControlMessage::Unsubscribe(Unsubscribe::decode(&mut payload)?)
-            }
-            ControlMessageType::UnsubscribeAnnounces => {
-                ControlMessage::UnsubscribeAnnounces(UnsubscribeAnnounces::decode(&mut payload)?)
-            }
-            ControlMessageType::SubscribeDone => {
-                ControlMessage::SubscribeDone(SubscribeDone::decode(&mut payload)?)
-            }
-            ControlMessageType::Publish => ControlMessage::Publish(Publish::decode(&mut payload)?),
-            ControlMessageType::PublishOk => {
-                ControlMessage::PublishOk(PublishOk::decode(&mut payload)?)
-            }
-            ControlMessageType::PublishError => {
-                ControlMessage::PublishError(PublishError::decode(&mut payload)?)
-            }
-            ControlMessageType::Fetch => ControlMessage::Fetch(Fetch::decode(&mut payload)?),
-            ControlMessageType::FetchOk => ControlMessage::FetchOk(FetchOk::decode(&mut payload)?),
-            ControlMessageType::FetchError => {
-                ControlMessage::FetchError(FetchError::decode(&mut payload)?)
-            }
-            ControlMessageType::FetchCancel => {
-                ControlMessage::FetchCancel(FetchCancel::decode(&mut payload)?)
-            }
-            ControlMessageType::Goaway => ControlMessage::Goaway(Goaway::decode(&mut payload)?),
-            ControlMessageType::MaxRequestId => {
-                ControlMessage::MaxRequestId(MaxRequestId::decode(&mut payload)?)
-            }
-            ControlMessageType::RequestsBlocked => {
-                ControlMessage::RequestsBlocked(RequestsBlocked::decode(&mut payload)?)
-            }
-            ControlMessageType::TrackStatus => {
-                ControlMessage::TrackStatus(TrackStatus::decode(&mut payload)?)
-            }
-            ControlMessageType::TrackStatusRequest => {
-                ControlMessage::TrackStatusRequest(TrackStatusRequest::decode(&mut payload)?)
-            }
-            ControlMessageType::Announce => {
-                ControlMessage::Announce(Announce::decode(&mut payload)?)
-            }
-            ControlMessageType::AnnounceOk => {
-                ControlMessage::AnnounceOk(AnnounceOk::decode(&mut payload)?)
-            }
-            ControlMessageType::AnnounceError => {
-                ControlMessage::AnnounceError(AnnounceError::decode(&mut payload)?)
-            }
-            ControlMessageType::Unannounce => {
-                ControlMessage::Unannounce(Unannounce::decode(&mut payload)?)
-            }
-            ControlMessageType::AnnounceCancel => {
-                ControlMessage::AnnounceCancel(AnnounceCancel::decode(&mut payload)?)
-            }
+        src.advance(header_len);
+        let payload = src.split_to(len);
+        let raw = payload.clone().freeze();
+        let message_type = match ControlMessageType::try_from(msg_type) {
+            Ok(t) if self.version.has_message_type(t) => t,
+            Ok(_) if self.unknown_message_policy == UnknownMessagePolicy::Surface => {
+                return Ok(Some(ControlMessage::Unknown {
+                    message_type: msg_type,
+                    payload: payload.freeze(),
+                }));
+            }
+            Ok(_) => return Err(Error::UnknownMessageType),
+            Err(moqt_wire::error::Error::UnknownMessageType)
+                if self.unknown_message_policy == UnknownMessagePolicy::Surface =>
+            {
+                return Ok(Some(ControlMessage::Unknown {
+                    message_type: msg_type,
+                    payload: payload.freeze(),
+                }));
+            }
+            Err(e) => return Err(e.into()),
         };
-        if !payload.is_empty() {
+        let mut payload = self.version.upgrade(message_type, payload).map_err(|e| {
+            e.with_offending("control.malformed_message", Offending::Bytes(raw.clone()))
+        })?;
+        let ctx = DecodeCtx::new(message_type.name(), &payload);
+        let message = decode_message(message_type, &mut payload)
+            .map_err(|e| Error::from(ctx.wrap(&payload, e)))
+            .and_then(|message| {
+                ctx.finish(&payload)?;
+                Ok(message)
+            })
+            .map_err(|e| e.with_offending("control.malformed_message", Offending::Bytes(raw)))?;
+        if let Err(e) = check_duplicate_parameters(msg_type, message_parameters(&message)) {
             return Err(
-                std::io::Error::new(std::io::ErrorKind::InvalidData, "excess payload").into(),
+                e.with_offending("control.duplicate_parameter", Offending::Message(message))
             );
         }
         Ok(Some(message))
     }
 }
 
+impl Decoder for ControlMessageCodec {
+    type Item = ControlMessage;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let msg = self.decode_message(src)?;
+        if let Some(msg) = &msg {
+            self.observers.on_message_received(msg);
+        }
+        Ok(msg)
+    }
+}
+
+/// Decode the body of a control message of type `message_type`.
+fn decode_message(
+    message_type: ControlMessageType,
+    payload: &mut BytesMut,
+) -> Result<ControlMessage, moqt_wire::error::Error> {
+    Ok(match message_type {
+        ControlMessageType::ClientSetup => {
+            ControlMessage::ClientSetup(ClientSetup::decode(payload)?)
+        }
+        ControlMessageType::ServerSetup => {
+            ControlMessage::ServerSetup(ServerSetup::decode(payload)?)
+        }
+        ControlMessageType::Subscribe => ControlMessage::Subscribe(Subscribe::decode(payload)?),
+        ControlMessageType::SubscribeAnnounces => {
+            ControlMessage::SubscribeAnnounces(SubscribeAnnounces::decode(payload)?)
+        }
+        ControlMessageType::SubscribeAnnouncesOk => {
+            ControlMessage::SubscribeAnnouncesOk(SubscribeAnnouncesOk::decode(payload)?)
+        }
+        ControlMessageType::SubscribeAnnouncesError => {
+            ControlMessage::SubscribeAnnouncesError(SubscribeAnnouncesError::decode(payload)?)
+        }
+        ControlMessageType::SubscribeOk => {
+            ControlMessage::SubscribeOk(SubscribeOk::decode(payload)?)
+        }
+        ControlMessageType::SubscribeError => {
+            ControlMessage::SubscribeError(SubscribeError::decode(payload)?)
+        }
+        ControlMessageType::SubscribeUpdate => {
+            ControlMessage::SubscribeUpdate(SubscribeUpdate::decode(payload)?)
+        }
+        ControlMessageType::Unsubscribe => {
+            ControlMessage::Unsubscribe(Unsubscribe::decode(payload)?)
+        }
+        ControlMessageType::UnsubscribeAnnounces => {
+            ControlMessage::UnsubscribeAnnounces(UnsubscribeAnnounces::decode(payload)?)
+        }
+        ControlMessageType::SubscribeDone => {
+            ControlMessage::SubscribeDone(SubscribeDone::decode(payload)?)
+        }
+        ControlMessageType::Publish => ControlMessage::Publish(Publish::decode(payload)?),
+        ControlMessageType::PublishOk => ControlMessage::PublishOk(PublishOk::decode(payload)?),
+        ControlMessageType::PublishError => {
+            ControlMessage::PublishError(PublishError::decode(payload)?)
+        }
+        ControlMessageType::Fetch => ControlMessage::Fetch(Fetch::decode(payload)?),
+        ControlMessageType::FetchOk => ControlMessage::FetchOk(FetchOk::decode(payload)?),
+        ControlMessageType::FetchError => ControlMessage::FetchError(FetchError::decode(payload)?),
+        ControlMessageType::FetchCancel => {
+            ControlMessage::FetchCancel(FetchCancel::decode(payload)?)
+        }
+        ControlMessageType::Goaway => ControlMessage::Goaway(Goaway::decode(payload)?),
+        ControlMessageType::MaxRequestId => {
+            ControlMessage::MaxRequestId(MaxRequestId::decode(payload)?)
+        }
+        ControlMessageType::RequestsBlocked => {
+            ControlMessage::RequestsBlocked(RequestsBlocked::decode(payload)?)
+        }
+        ControlMessageType::TrackStatus => {
+            ControlMessage::TrackStatus(TrackStatus::decode(payload)?)
+        }
+        ControlMessageType::TrackStatusRequest => {
+            ControlMessage::TrackStatusRequest(TrackStatusRequest::decode(payload)?)
+        }
+        ControlMessageType::Announce => ControlMessage::Announce(Announce::decode(payload)?),
+        ControlMessageType::AnnounceOk => ControlMessage::AnnounceOk(AnnounceOk::decode(payload)?),
+        ControlMessageType::AnnounceError => {
+            ControlMessage::AnnounceError(AnnounceError::decode(payload)?)
+        }
+        ControlMessageType::Unannounce => ControlMessage::Unannounce(Unannounce::decode(payload)?),
+        ControlMessageType::AnnounceCancel => {
+            ControlMessage::AnnounceCancel(AnnounceCancel::decode(payload)?)
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
-    use super::ControlMessageCodec;
-    use crate::message::{ControlMessage, MaxRequestId, RequestsBlocked};
+    use super::{ControlMessageCodec, UnknownMessagePolicy};
+    use crate::codec::{MessageSizeLimits, VarInt};
+    use crate::error::{Error, Offending};
+    use crate::message::{ControlMessage, ControlMessageType, MaxRequestId, RequestsBlocked};
     use bytes::BytesMut;
     use tokio_util::codec::{Decoder, Encoder};
 
     #[test]
     fn codec_requests_blocked_roundtrip() {
-        let mut codec = ControlMessageCodec;
+        let mut codec = ControlMessageCodec::new();
         let msg = ControlMessage::RequestsBlocked(RequestsBlocked {
             maximum_request_id: 42,
         });
@@ -353,7 +494,7 @@ mod tests {
 
     #[test]
     fn codec_max_request_id_roundtrip() {
-        let mut codec = ControlMessageCodec;
+        let mut codec = ControlMessageCodec::new();
         let msg = ControlMessage::MaxRequestId(MaxRequestId { request_id: 5 });
 
         let mut buf = BytesMut::new();
@@ -369,4 +510,469 @@ mod tests {
         }
         assert!(buf.is_empty());
     }
+
+    #[test]
+    fn codec_rejects_unknown_type_by_default() {
+        let mut codec = ControlMessageCodec::new();
+        let mut buf = BytesMut::from(&[0x3F, 0x02, 0xAA, 0xBB][..]);
+        match codec.decode(&mut buf) {
+            Err(Error::UnknownMessageType) => {}
+            r => panic!("unexpected result: {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn malformed_message_carries_offending_bytes() {
+        let mut codec = ControlMessageCodec::new();
+        // REQUESTS_BLOCKED whose payload ends inside its only field.
+        let mut buf = BytesMut::from(&[0x1A, 0x01, 0x40][..]);
+        let error = codec.decode(&mut buf).unwrap_err();
+        let violation = error.violation().expect("violation context");
+        assert_eq!(violation.rule, "control.malformed_message");
+        assert!(matches!(&violation.offending, Offending::Bytes(b) if b[..] == [0x40]));
+    }
+
+    #[test]
+    fn codec_surfaces_unknown_type_and_stays_in_sync() {
+        let mut codec =
+            ControlMessageCodec::new().with_unknown_message_policy(UnknownMessagePolicy::Surface);
+        let mut buf = BytesMut::from(&[0x3F, 0x02, 0xAA, 0xBB, 0x15, 0x01, 0x05][..]);
+
+        match codec.decode(&mut buf).unwrap().unwrap() {
+            ControlMessage::Unknown {
+                message_type,
+                payload,
+            } => {
+                assert_eq!(message_type, 0x3F);
+                assert_eq!(payload.as_ref(), &[0xAA, 0xBB]);
+            }
+            _ => panic!("unexpected message"),
+        }
+        match codec.decode(&mut buf).unwrap().unwrap() {
+            ControlMessage::MaxRequestId(mr) => assert_eq!(mr.request_id, 5),
+            _ => panic!("unexpected message"),
+        }
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn decode_error_reports_message_and_offset() {
+        let mut buf = BytesMut::new();
+        VarInt
+            .encode(ControlMessageType::SubscribeError as u64, &mut buf)
+            .unwrap();
+        // Request ID, Error Code, then a reason of 5 bytes holding 1.
+        buf.extend_from_slice(&[0x04, 0x01, 0x02, 0x05, b'a']);
+
+        match ControlMessageCodec::new().decode(&mut buf) {
+            Err(Error::ProtocolViolation {
+                reason,
+                context: Some(v),
+            }) => {
+                assert_eq!(
+                    reason,
+                    "SUBSCRIBE_ERROR: truncated at offset 3 while reading reason"
+                );
+                assert_eq!(v.rule, "control.malformed_message");
+            }
+            r => panic!("unexpected result: {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn codec_rejects_duplicate_setup_parameter() {
+        use crate::message::ClientSetup;
+        use crate::model::Parameter;
+
+        let max_request_id = Parameter::varint(crate::codec::MAX_REQUEST_ID, 10).unwrap();
+        let msg = ControlMessage::ClientSetup(ClientSetup {
+            supported_versions: vec![0xff00000c],
+            setup_parameters: vec![max_request_id.clone(), max_request_id],
+        });
+
+        let mut codec = ControlMessageCodec::new();
+        let mut buf = BytesMut::new();
+        codec.encode(msg, &mut buf).unwrap();
+        let error = codec.decode(&mut buf).unwrap_err();
+        let violation = error.violation().expect("violation context");
+        assert_eq!(violation.rule, "control.duplicate_parameter");
+        assert!(matches!(
+            &violation.offending,
+            Offending::Message(ControlMessage::ClientSetup(_))
+        ));
+    }
+
+    #[test]
+    fn reencoding_preserves_parameters() {
+        use crate::message::ClientSetup;
+        use crate::model::Parameter;
+
+        let mut codec = ControlMessageCodec::new();
+        let reencode = |codec: &mut ControlMessageCodec, wire: &BytesMut| {
+            let msg = codec.decode(&mut wire.clone()).unwrap().unwrap();
+            let mut buf = BytesMut::new();
+            codec.encode(msg, &mut buf).unwrap();
+            buf
+        };
+
+        // An unknown parameter ahead of MAX_REQUEST_ID, whose value is a
+        // four byte varint.
+        let setup = ControlMessage::ClientSetup(ClientSetup {
+            supported_versions: vec![0xff00000c],
+            setup_parameters: vec![
+                Parameter {
+                    parameter_type: 0x3f,
+                    value: vec![0xaa],
+                },
+                Parameter {
+                    parameter_type: crate::codec::MAX_REQUEST_ID,
+                    value: vec![0x80, 0x00, 0x00, 0x0a],
+                },
+            ],
+        });
+        let mut wire = BytesMut::new();
+        codec.encode(setup, &mut wire).unwrap();
+        assert_eq!(reencode(&mut codec, &wire), wire);
+
+        // A relay rewriting the Request ID of a SUBSCRIBE leaves its
+        // parameters, known or not, as they were.
+        let mut subscribe = crate::request::SubscribeRequest::new(0, "video")
+            .into_subscribe(2)
+            .unwrap();
+        subscribe.parameters = vec![
+            Parameter::bytes(0x3f, vec![0x01, 0x02]).unwrap(),
+            Parameter::varint(crate::publish::DELIVERY_TIMEOUT, 500).unwrap(),
+            Parameter::bytes(0x3f, Vec::new()).unwrap(),
+            // An unknown varint parameter in a two byte encoding.
+            Parameter {
+                parameter_type: 0x3e,
+                value: vec![0x40, 0x07],
+            },
+        ];
+        let mut wire = BytesMut::new();
+        codec
+            .encode(ControlMessage::Subscribe(subscribe.clone()), &mut wire)
+            .unwrap();
+        assert_eq!(reencode(&mut codec, &wire), wire);
+
+        let Some(ControlMessage::Subscribe(mut decoded)) = codec.decode(&mut wire).unwrap() else {
+            panic!("expected SUBSCRIBE");
+        };
+        decoded.request_id = 4;
+        subscribe.request_id = 4;
+        assert_eq!(decoded.parameters, subscribe.parameters);
+        let (mut rewritten, mut expected) = (BytesMut::new(), BytesMut::new());
+        codec
+            .encode(ControlMessage::Subscribe(decoded), &mut rewritten)
+            .unwrap();
+        codec
+            .encode(ControlMessage::Subscribe(subscribe), &mut expected)
+            .unwrap();
+        assert_eq!(rewritten, expected);
+    }
+
+    #[test]
+    fn codec_accepts_longest_track_name_with_token() {
+        use crate::codec::MAX_FULL_TRACK_NAME_LENGTH;
+        use crate::message::Subscribe;
+        use crate::model::{Filter, Parameter};
+
+        let mut subscribe = Subscribe::new(
+            0,
+            0,
+            "v".repeat(MAX_FULL_TRACK_NAME_LENGTH),
+            Filter::LargestObject,
+        );
+        subscribe.parameters = vec![Parameter::bytes(0x03, vec![0; 8192]).unwrap()];
+        let mut codec = ControlMessageCodec::new();
+        let mut buf = BytesMut::new();
+        codec
+            .encode(ControlMessage::Subscribe(subscribe.clone()), &mut buf)
+            .unwrap();
+        assert!(buf.len() > 4096);
+        match codec.decode(&mut buf).unwrap() {
+            Some(ControlMessage::Subscribe(decoded)) => assert_eq!(decoded, subscribe),
+            m => panic!("unexpected message: {:?}", m),
+        }
+    }
+
+    #[test]
+    fn codec_rejects_oversized_message_before_payload() {
+        // SUBSCRIBE announcing a 1 MiB payload, of which nothing arrived yet.
+        let mut buf = BytesMut::new();
+        VarInt
+            .encode(ControlMessageType::Subscribe as u64, &mut buf)
+            .unwrap();
+        VarInt.encode(1 << 20, &mut buf).unwrap();
+
+        let mut codec = ControlMessageCodec::new();
+        match codec.decode(&mut buf.clone()) {
+            Err(Error::ProtocolViolation {
+                context: Some(v), ..
+            }) => assert_eq!(v.rule, "control.message_length"),
+            r => panic!("unexpected result: {:?}", r.map(|_| ())),
+        }
+
+        let limits =
+            MessageSizeLimits::default().with_limit(ControlMessageType::Subscribe, 2 << 20);
+        let mut codec = ControlMessageCodec::new().with_size_limits(limits);
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+    }
+
+    #[test]
+    fn codec_server_setup_roundtrip() {
+        use crate::message::ServerSetup;
+        use crate::model::Parameter;
+
+        let msg = ServerSetup {
+            selected_version: 1,
+            setup_parameters: vec![Parameter::varint(0x02, 5).unwrap()],
+        };
+
+        let mut codec = ControlMessageCodec::new();
+        let mut buf = BytesMut::new();
+        codec
+            .encode(ControlMessage::ServerSetup(msg.clone()), &mut buf)
+            .unwrap();
+
+        let decoded = codec.decode(&mut buf).unwrap().unwrap();
+        match decoded {
+            ControlMessage::ServerSetup(d) => assert_eq!(d, msg),
+            _ => panic!("unexpected message type"),
+        }
+        assert!(buf.is_empty());
+    }
+
+    /// One instance of each message type, with every optional field set.
+    /// The match is exhaustive so that a new type cannot be added without
+    /// a sample.
+    fn sample(message_type: ControlMessageType) -> ControlMessage {
+        use crate::message::*;
+        use crate::model::{Filter, Location, Parameter};
+
+        let params = || {
+            vec![
+                Parameter::varint(0x02, 500).unwrap(),
+                Parameter::varint(0x04, 1000).unwrap(),
+            ]
+        };
+        let namespace = || vec!["example".to_string(), "live".to_string()];
+        let location = Location::new(7, 3);
+        match message_type {
+            ControlMessageType::ClientSetup => ControlMessage::ClientSetup(ClientSetup {
+                supported_versions: vec![0xff00000b, 0xff00000c],
+                setup_parameters: vec![
+                    Parameter::bytes(0x01, "/moq").unwrap(),
+                    Parameter::varint(0x02, 100).unwrap(),
+                ],
+            }),
+            ControlMessageType::ServerSetup => ControlMessage::ServerSetup(ServerSetup {
+                selected_version: 0xff00000c,
+                setup_parameters: vec![Parameter::varint(0x02, 100).unwrap()],
+            }),
+            ControlMessageType::Goaway => ControlMessage::Goaway(Goaway {
+                new_session_uri: Some("https://relay.example/moq".into()),
+            }),
+            ControlMessageType::MaxRequestId => {
+                ControlMessage::MaxRequestId(MaxRequestId { request_id: 64 })
+            }
+            ControlMessageType::RequestsBlocked => {
+                ControlMessage::RequestsBlocked(RequestsBlocked {
+                    maximum_request_id: 64,
+                })
+            }
+            ControlMessageType::Subscribe => {
+                let filter = Filter::absolute_range(location.clone(), 9).unwrap();
+                let mut subscribe = Subscribe::new(2, 1, "video", filter);
+                subscribe.subscriber_priority = 10;
+                subscribe.group_order = 0x2;
+                subscribe.parameters = params();
+                ControlMessage::Subscribe(subscribe)
+            }
+            ControlMessageType::SubscribeOk => ControlMessage::SubscribeOk(SubscribeOk {
+                request_id: 2,
+                track_alias: 5,
+                expires: 30_000,
+                group_order: 0x1,
+                content_exists: true,
+                largest_location: Some(location.clone()),
+                parameters: params(),
+            }),
+            ControlMessageType::SubscribeError => ControlMessage::SubscribeError(SubscribeError {
+                request_id: 2,
+                error_code: 0x4,
+                error_reason: "track does not exist".into(),
+            }),
+            ControlMessageType::SubscribeUpdate => {
+                ControlMessage::SubscribeUpdate(SubscribeUpdate {
+                    request_id: 2,
+                    start_location: location.clone(),
+                    end_group: 10,
+                    subscriber_priority: 20,
+                    forward: 0,
+                    parameters: params(),
+                })
+            }
+            ControlMessageType::Unsubscribe => {
+                ControlMessage::Unsubscribe(Unsubscribe { request_id: 2 })
+            }
+            ControlMessageType::SubscribeDone => ControlMessage::SubscribeDone(SubscribeDone {
+                request_id: 2,
+                status_code: 0x2,
+                stream_count: 12,
+                reason: "track ended".into(),
+            }),
+            ControlMessageType::Publish => ControlMessage::Publish(Publish {
+                request_id: 4,
+                track_namespace: 1,
+                track_name: "audio".into(),
+                track_alias: 6,
+                group_order: 0x1,
+                content_exists: 1,
+                largest: Some(location.clone()),
+                forward: 1,
+                parameters: params(),
+            }),
+            ControlMessageType::PublishOk => ControlMessage::PublishOk(PublishOk {
+                request_id: 4,
+                forward: 1,
+                subscriber_priority: 30,
+                group_order: 0x2,
+                filter_type: 0x4,
+                start: Some(location.clone()),
+                end_group: Some(12),
+                parameters: params(),
+            }),
+            ControlMessageType::PublishError => ControlMessage::PublishError(PublishError {
+                request_id: 4,
+                error_code: 0x1,
+                error_reason: "unauthorized".into(),
+            }),
+            ControlMessageType::Fetch => ControlMessage::Fetch(Fetch {
+                request_id: 6,
+                subscriber_priority: 40,
+                group_order: 0x1,
+                fetch_type: 0x1,
+                track_namespace: Some(1),
+                track_name: Some("video".into()),
+                start_location: Some(Location::new(1, 0)),
+                end_location: Some(location.clone()),
+                joining_request_id: None,
+                joining_start: None,
+                parameters: params(),
+            }),
+            ControlMessageType::FetchOk => ControlMessage::FetchOk(FetchOk {
+                request_id: 6,
+                group_order: 0x1,
+                end_of_track: true,
+                end_location: location.clone(),
+                parameters: params(),
+            }),
+            ControlMessageType::FetchError => ControlMessage::FetchError(FetchError {
+                request_id: 6,
+                error_code: 0x3,
+                error_reason: "invalid range".into(),
+            }),
+            ControlMessageType::FetchCancel => {
+                ControlMessage::FetchCancel(FetchCancel { request_id: 6 })
+            }
+            ControlMessageType::TrackStatusRequest => {
+                ControlMessage::TrackStatusRequest(TrackStatusRequest {
+                    request_id: 8,
+                    track_namespace: 1,
+                    track_name: "video".into(),
+                    parameters: params(),
+                })
+            }
+            ControlMessageType::TrackStatus => ControlMessage::TrackStatus(TrackStatus {
+                request_id: 8,
+                status_code: 0x0,
+                largest_location: location.clone(),
+                parameters: params(),
+            }),
+            ControlMessageType::Announce => ControlMessage::Announce(Announce {
+                request_id: 10,
+                track_namespace: namespace(),
+                parameters: params(),
+            }),
+            ControlMessageType::AnnounceOk => {
+                ControlMessage::AnnounceOk(AnnounceOk { request_id: 10 })
+            }
+            ControlMessageType::AnnounceError => ControlMessage::AnnounceError(AnnounceError {
+                request_id: 10,
+                error_code: 0x2,
+                error_reason: "namespace taken".into(),
+            }),
+            ControlMessageType::Unannounce => ControlMessage::Unannounce(Unannounce {
+                track_namespace: namespace(),
+            }),
+            ControlMessageType::AnnounceCancel => ControlMessage::AnnounceCancel(AnnounceCancel {
+                track_namespace: namespace(),
+                error_code: 0x0,
+                error_reason: "shutting down".into(),
+            }),
+            ControlMessageType::SubscribeAnnounces => {
+                ControlMessage::SubscribeAnnounces(SubscribeAnnounces {
+                    request_id: 12,
+                    track_namespace_prefix: namespace(),
+                    parameters: params(),
+                })
+            }
+            ControlMessageType::SubscribeAnnouncesOk => {
+                ControlMessage::SubscribeAnnouncesOk(SubscribeAnnouncesOk { request_id: 12 })
+            }
+            ControlMessageType::SubscribeAnnouncesError => {
+                ControlMessage::SubscribeAnnouncesError(SubscribeAnnouncesError {
+                    request_id: 12,
+                    error_code: 0x4,
+                    error_reason: "prefix overlaps".into(),
+                })
+            }
+            ControlMessageType::UnsubscribeAnnounces => {
+                ControlMessage::UnsubscribeAnnounces(UnsubscribeAnnounces {
+                    track_namespace_prefix: namespace(),
+                })
+            }
+        }
+    }
+
+    #[test]
+    fn codec_roundtrips_every_message_type() {
+        let types: Vec<_> = (0..0x40)
+            .filter_map(|t| ControlMessageType::try_from(t).ok())
+            .collect();
+        assert_eq!(types.len(), 29);
+
+        let mut codec = ControlMessageCodec::new();
+        for message_type in types {
+            let msg = sample(message_type);
+            assert_eq!(msg.message_type(), Some(message_type));
+
+            let mut buf = BytesMut::new();
+            codec.encode(msg.clone(), &mut buf).unwrap();
+            let encoded = buf.clone().freeze();
+
+            // Header: the message's own type, then the exact payload length.
+            let mut header = buf.clone();
+            assert_eq!(
+                VarInt.decode(&mut header).unwrap(),
+                Some(message_type as u64),
+                "{message_type:?}: type"
+            );
+            let length = VarInt.decode(&mut header).unwrap().unwrap() as usize;
+            assert_eq!(length, header.len(), "{message_type:?}: length");
+
+            let decoded = codec
+                .decode(&mut buf)
+                .unwrap_or_else(|e| panic!("{message_type:?}: {e}"))
+                .unwrap_or_else(|| panic!("{message_type:?}: incomplete"));
+            assert!(buf.is_empty(), "{message_type:?}: trailing bytes");
+            assert_eq!(decoded.message_type(), Some(message_type));
+            assert_eq!(format!("{decoded:?}"), format!("{msg:?}"));
+
+            let mut reencoded = BytesMut::new();
+            codec.encode(decoded, &mut reencoded).unwrap();
+            assert_eq!(reencoded, encoded, "{message_type:?}: re-encoding differs");
+        }
+    }
 }
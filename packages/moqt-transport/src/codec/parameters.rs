@@ -0,0 +1,171 @@
+use crate::{
+    auth::MAX_AUTH_TOKEN_CACHE_SIZE,
+    error::Error,
+    message::{ControlMessage, ControlMessageType},
+    model::Parameter,
+    publish::DELIVERY_TIMEOUT,
+};
+
+pub use crate::message::{MAX_REQUEST_ID, PATH};
+
+/// MAX_CACHE_DURATION version specific parameter.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-max-cache-duration-paramete
+pub const MAX_CACHE_DURATION: u64 = 0x04;
+
+/// Parameter types that may appear at most once in each message type.
+///
+/// AUTHORIZATION TOKEN may be repeated wherever it is allowed, and
+/// parameters unknown to a message may always be repeated, so neither is
+/// listed. Draft-12 allows DELIVERY TIMEOUT in TRACK_STATUS, SUBSCRIBE,
+/// SUBSCRIBE_OK, SUBSCRIBE_UPDATE, PUBLISH and PUBLISH_OK, where each
+/// endpoint of a PUBLISH states its own, and MAX_CACHE_DURATION in
+/// SUBSCRIBE_OK, PUBLISH, FETCH_OK and TRACK_STATUS.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-version-specific-parameters
+const UNIQUE_PARAMETERS: &[(u64, &[u64])] = &[
+    (
+        ControlMessageType::ClientSetup as u64,
+        &[PATH, MAX_REQUEST_ID, MAX_AUTH_TOKEN_CACHE_SIZE],
+    ),
+    (
+        ControlMessageType::ServerSetup as u64,
+        &[PATH, MAX_REQUEST_ID, MAX_AUTH_TOKEN_CACHE_SIZE],
+    ),
+    (ControlMessageType::Subscribe as u64, &[DELIVERY_TIMEOUT]),
+    (
+        ControlMessageType::SubscribeOk as u64,
+        &[DELIVERY_TIMEOUT, MAX_CACHE_DURATION],
+    ),
+    (
+        ControlMessageType::SubscribeUpdate as u64,
+        &[DELIVERY_TIMEOUT],
+    ),
+    (
+        ControlMessageType::Publish as u64,
+        &[DELIVERY_TIMEOUT, MAX_CACHE_DURATION],
+    ),
+    (ControlMessageType::PublishOk as u64, &[DELIVERY_TIMEOUT]),
+    (ControlMessageType::FetchOk as u64, &[MAX_CACHE_DURATION]),
+    (
+        ControlMessageType::TrackStatus as u64,
+        &[DELIVERY_TIMEOUT, MAX_CACHE_DURATION],
+    ),
+];
+
+/// Reject parameters repeated in a message type that does not allow it.
+pub fn check_duplicate_parameters(
+    message_type: u64,
+    parameters: &[Parameter],
+) -> Result<(), Error> {
+    let Some((_, unique)) = UNIQUE_PARAMETERS.iter().find(|(t, _)| *t == message_type) else {
+        return Ok(());
+    };
+    for (i, p) in parameters.iter().enumerate() {
+        if unique.contains(&p.parameter_type)
+            && parameters[..i]
+                .iter()
+                .any(|q| q.parameter_type == p.parameter_type)
+        {
+            return Err(Error::ProtocolViolation {
+                reason: format!(
+                    "duplicate parameter {:#x} in message {:#x}",
+                    p.parameter_type, message_type
+                ),
+                context: None,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Parameters carried by a control message, if any.
+pub(crate) fn message_parameters(message: &ControlMessage) -> &[Parameter] {
+    match message {
+        ControlMessage::ClientSetup(m) => &m.setup_parameters,
+        ControlMessage::ServerSetup(m) => &m.setup_parameters,
+        ControlMessage::Subscribe(m) => &m.parameters,
+        ControlMessage::SubscribeOk(m) => &m.parameters,
+        ControlMessage::SubscribeUpdate(m) => &m.parameters,
+        ControlMessage::SubscribeAnnounces(m) => &m.parameters,
+        ControlMessage::Publish(m) => &m.parameters,
+        ControlMessage::PublishOk(m) => &m.parameters,
+        ControlMessage::Fetch(m) => &m.parameters,
+        ControlMessage::FetchOk(m) => &m.parameters,
+        ControlMessage::TrackStatusRequest(m) => &m.parameters,
+        ControlMessage::TrackStatus(m) => &m.parameters,
+        ControlMessage::Announce(m) => &m.parameters,
+        _ => &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::AUTHORIZATION_TOKEN;
+
+    fn param(parameter_type: u64) -> Parameter {
+        Parameter {
+            parameter_type,
+            value: vec![0x01],
+        }
+    }
+
+    #[test]
+    fn duplicates_rejected_per_message() {
+        let setup = ControlMessageType::ClientSetup as u64;
+        let subscribe = ControlMessageType::Subscribe as u64;
+
+        assert!(check_duplicate_parameters(setup, &[param(PATH), param(MAX_REQUEST_ID)]).is_ok());
+        assert!(matches!(
+            check_duplicate_parameters(
+                setup,
+                &[param(MAX_REQUEST_ID), param(PATH), param(MAX_REQUEST_ID)]
+            ),
+            Err(Error::ProtocolViolation { .. })
+        ));
+        assert!(
+            check_duplicate_parameters(
+                subscribe,
+                &[param(DELIVERY_TIMEOUT), param(DELIVERY_TIMEOUT)]
+            )
+            .is_err()
+        );
+
+        // Repeatable and unknown parameters.
+        assert!(
+            check_duplicate_parameters(
+                subscribe,
+                &[param(AUTHORIZATION_TOKEN), param(AUTHORIZATION_TOKEN)]
+            )
+            .is_ok()
+        );
+        assert!(check_duplicate_parameters(subscribe, &[param(0x3e), param(0x3e)]).is_ok());
+        // Both endpoints of a PUBLISH state a single DELIVERY TIMEOUT.
+        for message_type in [ControlMessageType::Publish, ControlMessageType::PublishOk] {
+            assert!(
+                check_duplicate_parameters(
+                    message_type as u64,
+                    &[param(DELIVERY_TIMEOUT), param(DELIVERY_TIMEOUT)]
+                )
+                .is_err()
+            );
+        }
+        assert!(
+            check_duplicate_parameters(
+                ControlMessageType::Publish as u64,
+                &[param(MAX_CACHE_DURATION), param(MAX_CACHE_DURATION)]
+            )
+            .is_err()
+        );
+        // DELIVERY TIMEOUT is not defined for FETCH, so repeating it is
+        // repeating an unknown parameter.
+        assert!(
+            check_duplicate_parameters(
+                ControlMessageType::Fetch as u64,
+                &[param(DELIVERY_TIMEOUT), param(DELIVERY_TIMEOUT)]
+            )
+            .is_ok()
+        );
+    }
+}
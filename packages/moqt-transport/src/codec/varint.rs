@@ -1,38 +1,33 @@
-use bytes::{BufMut, BytesMut};
+use bytes::{Buf, BytesMut};
 use tokio_util::codec::{Decoder, Encoder};
 
-/// Variable-Length Integer Encoding
+/// Variable-Length Integer Encoding as a tokio codec, delegating to
+/// [`moqt_wire::codec::VarInt`].
 ///
 /// https://datatracker.ietf.org/doc/html/rfc9000#name-variable-length-integer-enc
 pub struct VarInt;
 
+impl VarInt {
+    /// Decode a value from the start of `src` without consuming it; see
+    /// [`moqt_wire::codec::VarInt::peek`].
+    pub fn peek(&self, src: &[u8]) -> Option<(u64, usize)> {
+        moqt_wire::codec::VarInt.peek(src)
+    }
+
+    /// Decode a value from any contiguous buffer, or return `None` without
+    /// consuming anything if it holds an incomplete encoding.
+    pub fn decode_buf(&self, src: &mut impl Buf) -> Option<u64> {
+        let (value, len) = self.peek(src.chunk())?;
+        src.advance(len);
+        Some(value)
+    }
+}
+
 impl Encoder<u64> for VarInt {
     type Error = crate::error::Error;
 
     fn encode(&mut self, item: u64, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        if item < (1 << 6) {
-            dst.put_u8(item as u8);
-        } else if item < (1 << 14) {
-            dst.put_u8(0x40 | ((item >> 8) as u8));
-            dst.put_u8(item as u8);
-        } else if item < (1 << 30) {
-            dst.put_u8(0x80 | ((item >> 24) as u8));
-            dst.put_u8(((item >> 16) & 0xff) as u8);
-            dst.put_u8(((item >> 8) & 0xff) as u8);
-            dst.put_u8(item as u8);
-        } else if item < (1 << 62) {
-            dst.put_u8(0xC0 | ((item >> 56) as u8));
-            dst.put_u8(((item >> 48) & 0xff) as u8);
-            dst.put_u8(((item >> 40) & 0xff) as u8);
-            dst.put_u8(((item >> 32) & 0xff) as u8);
-            dst.put_u8(((item >> 24) & 0xff) as u8);
-            dst.put_u8(((item >> 16) & 0xff) as u8);
-            dst.put_u8(((item >> 8) & 0xff) as u8);
-            dst.put_u8(item as u8);
-        } else {
-            return Err(crate::error::Error::VarIntRange);
-        }
-        Ok(())
+        Ok(moqt_wire::codec::VarInt.encode(item, dst)?)
     }
 }
 
@@ -41,45 +36,7 @@ impl Decoder for VarInt {
     type Error = crate::error::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let first = match src.first() {
-            Some(v) => *v,
-            None => return Ok(None),
-        };
-
-        let prefix = first >> 6;
-        let len = 1usize << prefix;
-
-        if src.len() < len {
-            return Ok(None);
-        }
-
-        let value = match len {
-            1 => (first & 0x3f) as u64,
-            2 => {
-                let b1 = src[1] as u64;
-                ((first as u64 & 0x3f) << 8) | b1
-            }
-            4 => {
-                ((first as u64 & 0x3f) << 24)
-                    | ((src[1] as u64) << 16)
-                    | ((src[2] as u64) << 8)
-                    | src[3] as u64
-            }
-            8 => {
-                ((first as u64 & 0x3f) << 56)
-                    | ((src[1] as u64) << 48)
-                    | ((src[2] as u64) << 40)
-                    | ((src[3] as u64) << 32)
-                    | ((src[4] as u64) << 24)
-                    | ((src[5] as u64) << 16)
-                    | ((src[6] as u64) << 8)
-                    | src[7] as u64
-            }
-            _ => unreachable!(),
-        };
-
-        let _ = src.split_to(len);
-        Ok(Some(value))
+        Ok(moqt_wire::codec::VarInt.decode(src)?)
     }
 }
 
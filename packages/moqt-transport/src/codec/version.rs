@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use bytes::{BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{codec::VarInt, error::Error, message::ControlMessageType};
+
+/// Version number of draft-ietf-moq-transport-11.
+pub const DRAFT_11: u32 = 0xff00000b;
+
+/// Version number of draft-ietf-moq-transport-12.
+pub const DRAFT_12: u32 = 0xff00000c;
+
+/// Wire format spoken by a [`ControlMessageCodec`], selected by the version
+/// negotiated through CLIENT_SETUP and SERVER_SETUP.
+///
+/// Messages are always represented in their draft-12 form. A
+/// [`Draft11`](WireVersion::Draft11) codec translates the layouts that
+/// changed since draft-11 on the way in and out:
+///
+/// * SUBSCRIBE carries the Track Alias, chosen by the subscriber. This
+///   endpoint uses the Request ID as alias of its subscriptions.
+/// * SUBSCRIBE_OK carries no Track Alias. Incoming ones get the Request ID,
+///   outgoing ones must use the alias the peer put in its SUBSCRIBE, see
+///   [`Draft11Aliases::alias`].
+/// * PUBLISH, PUBLISH_OK and PUBLISH_ERROR do not exist. They cannot be
+///   sent, and are unknown message types when received.
+///
+/// Subgroup streams differ only in their header types, which
+/// [`SubgroupHeader::encode_for`](crate::data::SubgroupHeader::encode_for)
+/// translates, and datagrams in their types, which
+/// [`ObjectDatagram::encode_for`](crate::data::ObjectDatagram::encode_for)
+/// and [`decode_for`](crate::data::ObjectDatagram::decode_for) translate.
+/// Neither can mark the end of a group in draft-11.
+/// [`Session::write_object`](crate::session::Session::write_object),
+/// [`send_object_datagram`](crate::session::Session::send_object_datagram)
+/// and [`accept_datagrams`](crate::session::Session::accept_datagrams)
+/// follow the session's [wire version](crate::session::Session::wire_version).
+///
+/// [`ControlMessageCodec`]: crate::codec::ControlMessageCodec
+#[derive(Debug, Default, Clone)]
+pub enum WireVersion {
+    Draft11(Draft11Aliases),
+    #[default]
+    Draft12,
+}
+
+impl WireVersion {
+    /// The wire format of the version selected by SERVER_SETUP.
+    pub fn negotiated(selected_version: u32) -> Result<Self, Error> {
+        match selected_version {
+            DRAFT_11 => Ok(WireVersion::Draft11(Draft11Aliases::default())),
+            DRAFT_12 => Ok(WireVersion::Draft12),
+            v => Err(Error::ProtocolViolation {
+                reason: format!("unsupported version {v:#x}"),
+                context: None,
+            }),
+        }
+    }
+
+    pub fn version(&self) -> u32 {
+        match self {
+            WireVersion::Draft11(_) => DRAFT_11,
+            WireVersion::Draft12 => DRAFT_12,
+        }
+    }
+
+    /// Whether messages of type `message_type` exist in this version.
+    pub(crate) fn has_message_type(&self, message_type: ControlMessageType) -> bool {
+        !matches!(
+            (self, message_type),
+            (
+                WireVersion::Draft11(_),
+                ControlMessageType::Publish
+                    | ControlMessageType::PublishOk
+                    | ControlMessageType::PublishError
+            )
+        )
+    }
+
+    /// Rewrite the payload of an incoming message into its draft-12 layout.
+    pub(crate) fn upgrade(
+        &self,
+        message_type: ControlMessageType,
+        payload: BytesMut,
+    ) -> Result<BytesMut, Error> {
+        let WireVersion::Draft11(aliases) = self else {
+            return Ok(payload);
+        };
+        match message_type {
+            ControlMessageType::Subscribe => {
+                let (request_id, mut rest) = split_varint(payload, "request id")?;
+                let track_alias = decode_varint(&mut rest, "track alias")?;
+                aliases.insert(request_id, track_alias);
+                Ok(join(&[request_id], rest))
+            }
+            ControlMessageType::SubscribeOk => {
+                // This endpoint subscribes with its Request ID as alias.
+                let (request_id, rest) = split_varint(payload, "request id")?;
+                Ok(join(&[request_id, request_id], rest))
+            }
+            _ => Ok(payload),
+        }
+    }
+
+    /// Rewrite an encoded draft-12 message into this version's layout and
+    /// append it to `dst`.
+    pub(crate) fn downgrade(&self, mut frame: BytesMut, dst: &mut BytesMut) -> Result<(), Error> {
+        let WireVersion::Draft11(aliases) = self else {
+            dst.put(frame);
+            return Ok(());
+        };
+        let message_type = decode_varint(&mut frame, "message type")?;
+        let len = decode_varint(&mut frame, "message length")? as usize;
+        let payload = frame.split_to(len);
+        let payload = match ControlMessageType::try_from(message_type) {
+            Ok(ControlMessageType::Subscribe) => {
+                let (request_id, rest) = split_varint(payload, "request id")?;
+                join(&[request_id, request_id], rest)
+            }
+            Ok(ControlMessageType::SubscribeOk) => {
+                let (request_id, mut rest) = split_varint(payload, "request id")?;
+                let track_alias = decode_varint(&mut rest, "track alias")?;
+                match aliases.remove(request_id) {
+                    Some(alias) if alias != track_alias => {
+                        return Err(Error::Codec(format!(
+                            "SUBSCRIBE_OK for request {request_id} uses track alias \
+                             {track_alias}, the draft-11 subscriber chose {alias}"
+                        )));
+                    }
+                    _ => join(&[request_id], rest),
+                }
+            }
+            Ok(ControlMessageType::SubscribeError) => {
+                let mut peek = payload.clone();
+                aliases.remove(decode_varint(&mut peek, "request id")?);
+                payload
+            }
+            Ok(t) if !self.has_message_type(t) => {
+                return Err(Error::Codec(format!(
+                    "{} does not exist in draft-11",
+                    t.name()
+                )));
+            }
+            _ => payload,
+        };
+        VarInt.encode(message_type, dst)?;
+        VarInt.encode(payload.len() as u64, dst)?;
+        dst.put(payload);
+        Ok(())
+    }
+}
+
+/// Track aliases chosen by a draft-11 peer in the SUBSCRIBE messages it
+/// sent, by Request ID. Clones share the same aliases, so the decoder and
+/// encoder of a control stream should be created from one
+/// [`WireVersion`].
+#[derive(Debug, Default, Clone)]
+pub struct Draft11Aliases {
+    aliases: Arc<Mutex<HashMap<u64, u64>>>,
+}
+
+impl Draft11Aliases {
+    /// Track alias the peer chose for its subscription `request_id`, to be
+    /// used in the SUBSCRIBE_OK answering it and for the objects sent to
+    /// it.
+    pub fn alias(&self, request_id: u64) -> Option<u64> {
+        self.aliases.lock().unwrap().get(&request_id).copied()
+    }
+
+    fn insert(&self, request_id: u64, track_alias: u64) {
+        self.aliases.lock().unwrap().insert(request_id, track_alias);
+    }
+
+    fn remove(&self, request_id: u64) -> Option<u64> {
+        self.aliases.lock().unwrap().remove(&request_id)
+    }
+}
+
+fn decode_varint(buf: &mut BytesMut, field: &str) -> Result<u64, Error> {
+    VarInt.decode(buf)?.ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::UnexpectedEof, field.to_string()).into()
+    })
+}
+
+fn split_varint(mut payload: BytesMut, field: &str) -> Result<(u64, BytesMut), Error> {
+    let value = decode_varint(&mut payload, field)?;
+    Ok((value, payload))
+}
+
+/// `values` as varints followed by `rest`.
+fn join(values: &[u64], rest: BytesMut) -> BytesMut {
+    let mut buf = BytesMut::with_capacity(rest.len() + 8 * values.len());
+    for value in values {
+        VarInt
+            .encode(*value, &mut buf)
+            .expect("value was decoded as varint");
+    }
+    buf.put(rest);
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::ControlMessageCodec;
+    use crate::message::{ControlMessage, PublishError, SubscribeOk};
+    use crate::request::SubscribeRequest;
+
+    fn subscribe_ok(request_id: u64, track_alias: u64) -> ControlMessage {
+        ControlMessage::SubscribeOk(SubscribeOk {
+            request_id,
+            track_alias,
+            expires: 0,
+            group_order: 1,
+            content_exists: false,
+            largest_location: None,
+            parameters: Vec::new(),
+        })
+    }
+
+    fn encode(codec: &mut ControlMessageCodec, msg: ControlMessage) -> Result<BytesMut, Error> {
+        let mut buf = BytesMut::new();
+        codec.encode(msg, &mut buf)?;
+        Ok(buf)
+    }
+
+    #[test]
+    fn negotiated_versions() {
+        assert_eq!(
+            WireVersion::negotiated(DRAFT_11).unwrap().version(),
+            DRAFT_11
+        );
+        assert_eq!(
+            WireVersion::negotiated(DRAFT_12).unwrap().version(),
+            DRAFT_12
+        );
+        assert!(WireVersion::negotiated(0xff00000a).is_err());
+    }
+
+    #[test]
+    fn subscribe_carries_alias_in_draft11() {
+        let version = WireVersion::negotiated(DRAFT_11).unwrap();
+        let WireVersion::Draft11(aliases) = version.clone() else {
+            unreachable!()
+        };
+        let mut client =
+            ControlMessageCodec::new().with_version(WireVersion::negotiated(DRAFT_11).unwrap());
+        let mut server = ControlMessageCodec::new().with_version(version);
+        let subscribe = SubscribeRequest::new(1, "video").into_subscribe(6).unwrap();
+        let msg = ControlMessage::Subscribe(subscribe.clone());
+
+        let draft12 = encode(&mut ControlMessageCodec::new(), msg.clone()).unwrap();
+        let mut draft11 = encode(&mut client, msg).unwrap();
+        // Type, length, request ID and the alias inserted after it.
+        assert_eq!(draft11.len(), draft12.len() + 1);
+        assert_eq!(&draft11[2..4], &[6, 6]);
+        match server.decode(&mut draft11).unwrap() {
+            Some(ControlMessage::Subscribe(decoded)) => assert_eq!(decoded, subscribe),
+            other => panic!("unexpected message: {other:?}"),
+        }
+        assert_eq!(aliases.alias(6), Some(6));
+
+        // The answer must use the subscriber's alias and omits it.
+        assert!(matches!(
+            encode(&mut server, subscribe_ok(6, 1)),
+            Err(Error::Codec(_))
+        ));
+        let mut ok = encode(&mut server, subscribe_ok(6, 6)).unwrap();
+        let draft12 = encode(&mut ControlMessageCodec::new(), subscribe_ok(6, 6)).unwrap();
+        assert_eq!(ok[1] + 1, draft12[1]);
+        assert_eq!(&ok[3..], &draft12[4..]);
+        assert_eq!(aliases.alias(6), None);
+        match client.decode(&mut ok).unwrap() {
+            Some(ControlMessage::SubscribeOk(decoded)) => assert_eq!(decoded.track_alias, 6),
+            other => panic!("unexpected message: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn publish_does_not_exist_in_draft11() {
+        let mut codec =
+            ControlMessageCodec::new().with_version(WireVersion::negotiated(DRAFT_11).unwrap());
+        let msg = ControlMessage::PublishError(PublishError {
+            request_id: 0,
+            error_code: 0,
+            error_reason: "".into(),
+        });
+        assert!(matches!(
+            encode(&mut codec, msg.clone()),
+            Err(Error::Codec(_))
+        ));
+
+        let mut buf = encode(&mut ControlMessageCodec::new(), msg).unwrap();
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(Error::UnknownMessageType)
+        ));
+    }
+}
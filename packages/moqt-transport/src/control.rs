@@ -0,0 +1,377 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use bytes::BytesMut;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_util::codec::Encoder;
+
+use crate::{codec::ControlMessageCodec, error::Error, message::ControlMessage};
+
+/// Default number of encoded bytes after which a batch is written out even
+/// if more messages are queued.
+pub const DEFAULT_MAX_BATCH_BYTES: usize = 16 * 1024;
+
+/// Default capacity of a session's outgoing control message queue.
+pub const DEFAULT_CONTROL_QUEUE_CAPACITY: usize = 16;
+
+/// What sending a control message does when the session's outgoing queue
+/// is full.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait until the queue has room.
+    #[default]
+    Wait,
+    /// Fail with [`Error::ControlQueueFull`].
+    Error,
+    /// Drop non-critical messages, currently TRACK_STATUS_REQUEST and
+    /// TRACK_STATUS, and wait for room for any other message.
+    DropNonCritical,
+}
+
+/// Configuration of a session's outgoing control message queue and of the
+/// [`ControlWriter`] draining it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlQueueConfig {
+    pub(crate) capacity: usize,
+    pub(crate) overflow: OverflowPolicy,
+    pub(crate) max_batch_bytes: usize,
+    pub(crate) linger: Option<Duration>,
+}
+
+impl Default for ControlQueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_CONTROL_QUEUE_CAPACITY,
+            overflow: OverflowPolicy::Wait,
+            max_batch_bytes: DEFAULT_MAX_BATCH_BYTES,
+            linger: None,
+        }
+    }
+}
+
+impl ControlQueueConfig {
+    /// Messages queued at most, at least one.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    pub fn with_overflow_policy(mut self, overflow: OverflowPolicy) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// See [`ControlWriter::with_max_batch_bytes`].
+    pub fn with_max_batch_bytes(mut self, max: usize) -> Self {
+        self.max_batch_bytes = max;
+        self
+    }
+
+    /// See [`ControlWriter::with_linger`].
+    pub fn with_linger(mut self, linger: Duration) -> Self {
+        self.linger = Some(linger);
+        self
+    }
+}
+
+/// Occupancy of a session's outgoing control message queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ControlQueueStats {
+    pub capacity: usize,
+    /// Messages currently queued.
+    pub depth: usize,
+    /// Largest depth observed.
+    pub high_water: usize,
+    /// Messages dropped by [`OverflowPolicy::DropNonCritical`].
+    pub dropped: u64,
+    /// Messages refused by [`OverflowPolicy::Error`].
+    pub rejected: u64,
+}
+
+/// A session's outgoing control message queue, shared with the handles
+/// sending on the session's behalf so every message is subject to the
+/// same [`OverflowPolicy`].
+pub(crate) struct ControlQueue {
+    tx: mpsc::Sender<ControlMessage>,
+    config: ControlQueueConfig,
+    stats: Mutex<ControlQueueStats>,
+}
+
+impl ControlQueue {
+    pub(crate) fn new(config: ControlQueueConfig) -> (Self, mpsc::Receiver<ControlMessage>) {
+        let (tx, rx) = mpsc::channel(config.capacity);
+        let queue = Self {
+            tx,
+            stats: Mutex::new(ControlQueueStats {
+                capacity: config.capacity,
+                ..Default::default()
+            }),
+            config,
+        };
+        (queue, rx)
+    }
+
+    /// Queue a message, applying the configured [`OverflowPolicy`] when
+    /// the queue is full.
+    pub(crate) async fn send(&self, msg: ControlMessage) -> Result<(), Error> {
+        let msg = match self.tx.try_send(msg) {
+            Ok(()) => {
+                self.record_depth();
+                return Ok(());
+            }
+            Err(mpsc::error::TrySendError::Closed(msg)) => msg,
+            Err(mpsc::error::TrySendError::Full(msg)) => match self.config.overflow {
+                OverflowPolicy::Wait => msg,
+                OverflowPolicy::Error => {
+                    self.stats.lock().unwrap().rejected += 1;
+                    return Err(Error::ControlQueueFull);
+                }
+                OverflowPolicy::DropNonCritical if is_non_critical(&msg) => {
+                    self.stats.lock().unwrap().dropped += 1;
+                    return Ok(());
+                }
+                OverflowPolicy::DropNonCritical => msg,
+            },
+        };
+        self.tx
+            .send(msg)
+            .await
+            .map_err(|e| Error::Transport(Box::new(e)))?;
+        self.record_depth();
+        Ok(())
+    }
+
+    pub(crate) fn config(&self) -> &ControlQueueConfig {
+        &self.config
+    }
+
+    pub(crate) fn stats(&self) -> ControlQueueStats {
+        let mut stats = *self.stats.lock().unwrap();
+        stats.depth = self.depth();
+        stats
+    }
+
+    fn depth(&self) -> usize {
+        self.config.capacity - self.tx.capacity()
+    }
+
+    fn record_depth(&self) {
+        let depth = self.depth();
+        let mut stats = self.stats.lock().unwrap();
+        stats.high_water = stats.high_water.max(depth);
+    }
+}
+
+/// Whether a message may be dropped under
+/// [`OverflowPolicy::DropNonCritical`].
+fn is_non_critical(msg: &ControlMessage) -> bool {
+    matches!(
+        msg,
+        ControlMessage::TrackStatusRequest(_) | ControlMessage::TrackStatus(_)
+    )
+}
+
+/// Writes control messages onto the control stream, coalescing all messages
+/// queued at the same time into a single write.
+///
+/// With a `linger` duration the writer additionally waits up to that long
+/// for further messages before writing a batch, trading a little latency
+/// for fewer writes under bursty request loads.
+pub struct ControlWriter<W> {
+    writer: W,
+    codec: ControlMessageCodec,
+    buf: BytesMut,
+    max_batch_bytes: usize,
+    linger: Option<Duration>,
+}
+
+impl<W: AsyncWrite + Unpin> ControlWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            codec: ControlMessageCodec::new(),
+            buf: BytesMut::new(),
+            max_batch_bytes: DEFAULT_MAX_BATCH_BYTES,
+            linger: None,
+        }
+    }
+
+    /// Encode with `codec`, e.g. one set to the negotiated version, instead
+    /// of a default draft-12 codec.
+    pub fn with_codec(mut self, codec: ControlMessageCodec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    pub fn with_max_batch_bytes(mut self, max: usize) -> Self {
+        self.max_batch_bytes = max;
+        self
+    }
+
+    pub fn with_linger(mut self, linger: Duration) -> Self {
+        self.linger = Some(linger);
+        self
+    }
+
+    /// Encode a message into the pending batch. The batch is written out
+    /// once it reaches the configured size.
+    pub async fn push(&mut self, msg: ControlMessage) -> Result<(), Error> {
+        self.codec.encode(msg, &mut self.buf)?;
+        if self.buf.len() >= self.max_batch_bytes {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Write out the pending batch, if any.
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let batch = self.buf.split();
+        self.writer.write_all(&batch).await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    /// Number of encoded bytes waiting to be written.
+    pub fn pending(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Drain `rx` until it is closed, writing one batch per wake-up.
+    pub async fn run(mut self, mut rx: mpsc::Receiver<ControlMessage>) -> Result<W, Error> {
+        while let Some(msg) = rx.recv().await {
+            self.push(msg).await?;
+
+            let deadline = self.linger.map(|l| Instant::now() + l);
+            loop {
+                match rx.try_recv() {
+                    Ok(msg) => {
+                        self.push(msg).await?;
+                        continue;
+                    }
+                    Err(mpsc::error::TryRecvError::Disconnected) => break,
+                    Err(mpsc::error::TryRecvError::Empty) => {}
+                }
+                let Some(deadline) = deadline else { break };
+                match tokio::time::timeout_at(deadline, rx.recv()).await {
+                    Ok(Some(msg)) => self.push(msg).await?,
+                    Ok(None) | Err(_) => break,
+                }
+            }
+
+            self.flush().await?;
+        }
+        self.flush().await?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::MaxRequestId;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// Writer recording the size of every `poll_write` call.
+    #[derive(Default)]
+    struct RecordingWriter {
+        writes: Vec<usize>,
+        data: Vec<u8>,
+    }
+
+    impl AsyncWrite for RecordingWriter {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            let this = self.get_mut();
+            this.writes.push(buf.len());
+            this.data.extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn max_request_id(request_id: u64) -> ControlMessage {
+        ControlMessage::MaxRequestId(MaxRequestId { request_id })
+    }
+
+    #[test]
+    fn queued_messages_are_written_once() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (tx, rx) = mpsc::channel(16);
+            for i in 1..=5 {
+                tx.send(max_request_id(i)).await.unwrap();
+            }
+            drop(tx);
+
+            let writer = ControlWriter::new(RecordingWriter::default())
+                .run(rx)
+                .await
+                .unwrap();
+            assert_eq!(writer.writes, vec![15]);
+        });
+    }
+
+    #[test]
+    fn batch_size_limit_splits_writes() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (tx, rx) = mpsc::channel(16);
+            for i in 1..=4 {
+                tx.send(max_request_id(i)).await.unwrap();
+            }
+            drop(tx);
+
+            let writer = ControlWriter::new(RecordingWriter::default())
+                .with_max_batch_bytes(6)
+                .run(rx)
+                .await
+                .unwrap();
+            assert_eq!(writer.writes, vec![6, 6]);
+        });
+    }
+
+    #[test]
+    fn linger_collects_late_messages() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .start_paused(true)
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (tx, rx) = mpsc::channel(16);
+            let sender = tokio::spawn(async move {
+                tx.send(max_request_id(1)).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(2)).await;
+                tx.send(max_request_id(2)).await.unwrap();
+            });
+
+            let writer = ControlWriter::new(RecordingWriter::default())
+                .with_linger(Duration::from_millis(5))
+                .run(rx)
+                .await
+                .unwrap();
+            sender.await.unwrap();
+            assert_eq!(writer.writes, vec![6]);
+        });
+    }
+}
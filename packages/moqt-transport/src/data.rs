@@ -0,0 +1,45 @@
+mod fetch_header;
+mod fetch_object;
+mod object_datagram;
+mod subgroup_header;
+mod subgroup_object;
+mod vectored;
+
+pub use fetch_header::*;
+pub use fetch_object::*;
+pub use object_datagram::*;
+pub use subgroup_header::*;
+pub use subgroup_object::*;
+pub use vectored::*;
+
+use crate::error::Error;
+
+/// Data Streams and Datagrams
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-data-streams-and-datagrams
+///
+/// All unidirectional MOQT streams start with a variable-length integer
+/// indicating the type of the stream in question.
+pub enum StreamType {
+    FetchHeader = 0x05,
+}
+
+/// Error codes used when resetting a data stream.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#table-14
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamResetCode {
+    InternalError = 0x0,
+    Cancelled = 0x1,
+    DeliveryTimeout = 0x2,
+    SessionClosed = 0x3,
+}
+
+/// Fail with [`Error::ObjectTooLarge`] if an object payload of `size`
+/// bytes exceeds `max`.
+pub(crate) fn check_payload_size(size: usize, max: usize) -> Result<(), Error> {
+    if size > max {
+        return Err(Error::ObjectTooLarge { size, max });
+    }
+    Ok(())
+}
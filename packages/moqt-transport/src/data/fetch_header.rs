@@ -0,0 +1,63 @@
+use bytes::{Buf, BytesMut};
+use tokio_util::codec::Encoder;
+
+use crate::data::StreamType;
+
+/// FETCH_HEADER
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-fetch-header
+///
+/// ```text
+/// FETCH_HEADER {
+///   Type (i) = 0x5,
+///   Request ID (i),
+/// }
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FetchHeader {
+    pub request_id: u64,
+}
+
+impl FetchHeader {
+    pub fn encode(&self, buf: &mut BytesMut) -> Result<(), crate::error::Error> {
+        let mut vi = crate::codec::VarInt;
+        vi.encode(StreamType::FetchHeader as u64, buf)?;
+        vi.encode(self.request_id, buf)?;
+        Ok(())
+    }
+
+    pub fn decode(buf: &mut impl Buf) -> Result<Self, crate::error::Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        let vi = crate::codec::VarInt;
+        let stream_type = vi
+            .decode_buf(buf)
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "stream type"))?;
+        if stream_type != StreamType::FetchHeader as u64 {
+            return Err(IoError::new(ErrorKind::InvalidData, "not a fetch stream").into());
+        }
+        let request_id = vi
+            .decode_buf(buf)
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "request id"))?;
+
+        Ok(FetchHeader { request_id })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_roundtrip() {
+        let msg = FetchHeader { request_id: 12 };
+
+        let mut buf = BytesMut::new();
+        msg.encode(&mut buf).unwrap();
+        assert_eq!(buf.as_ref(), &[0x05, 0x0C]);
+
+        let decoded = FetchHeader::decode(&mut buf).unwrap();
+        assert!(buf.is_empty());
+        assert_eq!(decoded, msg);
+    }
+}
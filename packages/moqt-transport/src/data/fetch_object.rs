@@ -0,0 +1,170 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio_util::codec::Encoder;
+
+/// Object sent on a fetch stream after the FETCH_HEADER.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-fetch-header
+///
+/// ```text
+/// {
+///   Group ID (i),
+///   Subgroup ID (i),
+///   Object ID (i),
+///   Publisher Priority (8),
+///   Extension Headers Length (i),
+///   [Extension headers (...)],
+///   Object Payload Length (i),
+///   [Object Status (i)],
+///   Object Payload (..),
+/// }
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FetchObject {
+    pub group_id: u64,
+    pub subgroup_id: u64,
+    pub object_id: u64,
+    pub publisher_priority: u8,
+    pub extension_headers: Bytes,
+    /// Only present on the wire when the payload is empty.
+    pub object_status: Option<u64>,
+    pub payload: Bytes,
+}
+
+impl FetchObject {
+    pub fn encode(&self, buf: &mut BytesMut) -> Result<(), crate::error::Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        let mut vi = crate::codec::VarInt;
+
+        vi.encode(self.group_id, buf)?;
+        vi.encode(self.subgroup_id, buf)?;
+        vi.encode(self.object_id, buf)?;
+        buf.put_u8(self.publisher_priority);
+
+        vi.encode(self.extension_headers.len() as u64, buf)?;
+        buf.put_slice(&self.extension_headers);
+
+        vi.encode(self.payload.len() as u64, buf)?;
+        if self.payload.is_empty() {
+            vi.encode(self.object_status.unwrap_or(0), buf)?;
+        } else if self.object_status.is_some_and(|s| s != 0) {
+            return Err(IoError::new(ErrorKind::InvalidData, "object status with payload").into());
+        }
+        buf.put_slice(&self.payload);
+
+        Ok(())
+    }
+
+    pub fn decode(buf: &mut impl Buf) -> Result<Self, crate::error::Error> {
+        Self::decode_bounded(buf, usize::MAX)
+    }
+
+    /// Like [`decode`](Self::decode), but fails with
+    /// [`ObjectTooLarge`](crate::error::Error::ObjectTooLarge) as soon as
+    /// the payload length exceeds `max_payload_size`, before the payload
+    /// itself is received.
+    pub fn decode_bounded(
+        buf: &mut impl Buf,
+        max_payload_size: usize,
+    ) -> Result<Self, crate::error::Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        let vi = crate::codec::VarInt;
+
+        let group_id = vi
+            .decode_buf(buf)
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "group id"))?;
+        let subgroup_id = vi
+            .decode_buf(buf)
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "subgroup id"))?;
+        let object_id = vi
+            .decode_buf(buf)
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "object id"))?;
+        if !buf.has_remaining() {
+            return Err(IoError::new(ErrorKind::UnexpectedEof, "publisher priority").into());
+        }
+        let publisher_priority = buf.get_u8();
+
+        let ext_len = vi
+            .decode_buf(buf)
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "extension headers len"))?
+            as usize;
+        if buf.remaining() < ext_len {
+            return Err(IoError::new(ErrorKind::UnexpectedEof, "extension headers").into());
+        }
+        let extension_headers = buf.copy_to_bytes(ext_len);
+
+        let payload_len = vi
+            .decode_buf(buf)
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "payload len"))?
+            as usize;
+        crate::data::check_payload_size(payload_len, max_payload_size)?;
+        let object_status = if payload_len == 0 {
+            Some(
+                vi.decode_buf(buf)
+                    .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "object status"))?,
+            )
+        } else {
+            None
+        };
+        if buf.remaining() < payload_len {
+            return Err(IoError::new(ErrorKind::UnexpectedEof, "payload").into());
+        }
+        let payload = buf.copy_to_bytes(payload_len);
+
+        Ok(FetchObject {
+            group_id,
+            subgroup_id,
+            object_id,
+            publisher_priority,
+            extension_headers,
+            object_status,
+            payload,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_roundtrip_with_payload() {
+        let msg = FetchObject {
+            group_id: 1,
+            subgroup_id: 0,
+            object_id: 3,
+            publisher_priority: 2,
+            extension_headers: Bytes::new(),
+            object_status: None,
+            payload: Bytes::from_static(b"frame"),
+        };
+
+        let mut buf = BytesMut::new();
+        msg.encode(&mut buf).unwrap();
+
+        let decoded = FetchObject::decode(&mut buf).unwrap();
+        assert!(buf.is_empty());
+        assert_eq!(decoded, msg);
+    }
+
+    #[test]
+    fn encode_decode_roundtrip_status() {
+        let msg = FetchObject {
+            group_id: 1,
+            subgroup_id: 0,
+            object_id: 4,
+            publisher_priority: 2,
+            extension_headers: Bytes::new(),
+            object_status: Some(0x3),
+            payload: Bytes::new(),
+        };
+
+        let mut buf = BytesMut::new();
+        msg.encode(&mut buf).unwrap();
+
+        let decoded = FetchObject::decode(&mut buf).unwrap();
+        assert!(buf.is_empty());
+        assert_eq!(decoded, msg);
+    }
+}
@@ -0,0 +1,343 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{
+    codec::WireVersion,
+    error::{Error, RequestErrorCode},
+    transport::Transport,
+};
+
+/// OBJECT_DATAGRAM and OBJECT_DATAGRAM_STATUS
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-object-datagram
+///
+/// ```text
+/// OBJECT_DATAGRAM {
+///   Type (i) = 0x0-0x3,
+///   Track Alias (i),
+///   Group ID (i),
+///   Object ID (i),
+///   Publisher Priority (8),
+///   [Extension Headers Length (i),
+///   Extension headers (...)],
+///   Object Payload (..),
+/// }
+/// ```
+///
+/// An object with a status instead of a payload is sent as
+/// OBJECT_DATAGRAM_STATUS (Type 0x4-0x5), which ends with the Object Status
+/// in place of the payload. Extension headers are present on the wire only
+/// when there are some.
+///
+/// Draft-11 peers use the types 0x0-0x1 for objects and 0x2-0x3 for
+/// statuses, with no end-of-group bit. [`encode_for`](Self::encode_for)
+/// and [`decode_for`](Self::decode_for) a draft-11 session use those.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ObjectDatagram {
+    pub track_alias: u64,
+    pub group_id: u64,
+    pub object_id: u64,
+    pub publisher_priority: u8,
+    /// Whether this is the last object of its group. Not carried by
+    /// OBJECT_DATAGRAM_STATUS.
+    pub end_of_group: bool,
+    pub extension_headers: Bytes,
+    /// Sent as OBJECT_DATAGRAM_STATUS when set.
+    pub object_status: Option<u64>,
+    pub payload: Bytes,
+}
+
+const END_OF_GROUP: u64 = 0x02;
+const EXTENSIONS_PRESENT: u64 = 0x01;
+const STATUS: u64 = 0x04;
+const DRAFT_11_STATUS: u64 = 0x02;
+
+impl ObjectDatagram {
+    fn datagram_type(&self, version: &WireVersion) -> u64 {
+        let extensions = if self.extension_headers.is_empty() {
+            0
+        } else {
+            EXTENSIONS_PRESENT
+        };
+        match (version, self.object_status) {
+            (WireVersion::Draft11(_), Some(_)) => DRAFT_11_STATUS | extensions,
+            (WireVersion::Draft11(_), None) => extensions,
+            (WireVersion::Draft12, Some(_)) => STATUS | extensions,
+            (WireVersion::Draft12, None) if self.end_of_group => END_OF_GROUP | extensions,
+            (WireVersion::Draft12, None) => extensions,
+        }
+    }
+
+    /// Encode everything preceding the payload or status.
+    fn encode_header(&self, version: &WireVersion, buf: &mut BytesMut) -> Result<(), Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        if self.object_status.is_some() && !self.payload.is_empty() {
+            return Err(IoError::new(ErrorKind::InvalidData, "object status with payload").into());
+        }
+        let mut vi = crate::codec::VarInt;
+        vi.encode(self.datagram_type(version), buf)?;
+        vi.encode(self.track_alias, buf)?;
+        vi.encode(self.group_id, buf)?;
+        vi.encode(self.object_id, buf)?;
+        buf.put_u8(self.publisher_priority);
+        if !self.extension_headers.is_empty() {
+            vi.encode(self.extension_headers.len() as u64, buf)?;
+            buf.put_slice(&self.extension_headers);
+        }
+        Ok(())
+    }
+
+    pub fn encode(&self, buf: &mut BytesMut) -> Result<(), Error> {
+        self.encode_for(&WireVersion::Draft12, buf)
+    }
+
+    /// Encode the datagram in the layout of `version`. Draft-11 has no
+    /// end-of-group bit, so `end_of_group` is not conveyed to such peers.
+    pub fn encode_for(&self, version: &WireVersion, buf: &mut BytesMut) -> Result<(), Error> {
+        self.encode_header(version, buf)?;
+        match self.object_status {
+            Some(status) => crate::codec::VarInt.encode(status, buf)?,
+            None => buf.put_slice(&self.payload),
+        }
+        Ok(())
+    }
+
+    /// Size of the datagram once encoded, the same in every version.
+    pub fn encoded_len(&self) -> Result<usize, Error> {
+        let mut buf = BytesMut::new();
+        self.encode_header(&WireVersion::Draft12, &mut buf)?;
+        match self.object_status {
+            Some(status) => crate::codec::VarInt.encode(status, &mut buf)?,
+            None => return Ok(buf.len() + self.payload.len()),
+        }
+        Ok(buf.len())
+    }
+
+    /// Decode a whole datagram.
+    pub fn decode(buf: &mut BytesMut) -> Result<Self, Error> {
+        Self::decode_for(&WireVersion::Draft12, buf)
+    }
+
+    /// Decode a whole datagram sent in the layout of `version`.
+    pub fn decode_for(version: &WireVersion, buf: &mut BytesMut) -> Result<Self, Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        let (status, end_of_group) = match version {
+            WireVersion::Draft11(_) => (DRAFT_11_STATUS, 0),
+            WireVersion::Draft12 => (STATUS, END_OF_GROUP),
+        };
+
+        let mut vi = crate::codec::VarInt;
+        let mut field = |what: &'static str, buf: &mut BytesMut| {
+            vi.decode(buf)?
+                .ok_or_else(|| Error::from(IoError::new(ErrorKind::UnexpectedEof, what)))
+        };
+
+        let datagram_type = field("datagram type", buf)?;
+        if datagram_type > (status | EXTENSIONS_PRESENT) {
+            return Err(Error::ProtocolViolation {
+                reason: format!("unknown datagram type {datagram_type:#x}"),
+                context: None,
+            });
+        }
+        let track_alias = field("track alias", buf)?;
+        let group_id = field("group id", buf)?;
+        let object_id = field("object id", buf)?;
+        if buf.is_empty() {
+            return Err(IoError::new(ErrorKind::UnexpectedEof, "publisher priority").into());
+        }
+        let publisher_priority = buf.split_to(1)[0];
+
+        let extension_headers = if datagram_type & EXTENSIONS_PRESENT != 0 {
+            let len = field("extension headers len", buf)? as usize;
+            if len == 0 {
+                return Err(Error::ProtocolViolation {
+                    reason: "empty extension headers in datagram".into(),
+                    context: None,
+                });
+            }
+            if buf.len() < len {
+                return Err(IoError::new(ErrorKind::UnexpectedEof, "extension headers").into());
+            }
+            buf.split_to(len).freeze()
+        } else {
+            Bytes::new()
+        };
+
+        let (object_status, payload) = if datagram_type & status != 0 {
+            (Some(field("object status", buf)?), Bytes::new())
+        } else {
+            (None, buf.split().freeze())
+        };
+
+        Ok(ObjectDatagram {
+            track_alias,
+            group_id,
+            object_id,
+            publisher_priority,
+            end_of_group: datagram_type & status == 0 && datagram_type & end_of_group != 0,
+            extension_headers,
+            object_status,
+            payload,
+        })
+    }
+}
+
+/// Send `datagram` over `transport`, first checking it fits in the
+/// transport's maximum datagram size.
+///
+/// A datagram that is too large fails with
+/// [`Error::ObjectTooLargeForDatagram`] instead of being handed to the
+/// transport, which would drop it or fail opaquely. Since a track keeps
+/// the forwarding preference of its first object, falling back to a stream
+/// is left to the caller. The datagram is encoded in the layout of
+/// `version`, the [wire version](crate::session::Session::wire_version)
+/// of the session.
+pub async fn send_object_datagram<T: Transport>(
+    version: &WireVersion,
+    transport: &mut T,
+    datagram: &ObjectDatagram,
+) -> Result<(), Error> {
+    let capabilities = transport.capabilities();
+    if !capabilities.datagrams {
+        return Err(Error::RequestFailed {
+            code: RequestErrorCode::NotSupported,
+            reason: "datagrams not supported by the transport".into(),
+        });
+    }
+    let size = datagram.encoded_len()?;
+    if let Some(max) = capabilities.max_datagram_size
+        && size > max
+    {
+        return Err(Error::ObjectTooLargeForDatagram { size, max });
+    }
+    let mut buf = BytesMut::with_capacity(size);
+    datagram.encode_for(version, &mut buf)?;
+    transport
+        .send_datagram(buf.freeze())
+        .await
+        .map_err(Error::Transport)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datagram() -> ObjectDatagram {
+        ObjectDatagram {
+            track_alias: 1,
+            group_id: 2,
+            object_id: 3,
+            publisher_priority: 128,
+            end_of_group: true,
+            extension_headers: Bytes::from_static(&[0x02, 0x01]),
+            object_status: None,
+            payload: Bytes::from_static(b"frame"),
+        }
+    }
+
+    #[test]
+    fn encode_decode_roundtrip() {
+        let status = ObjectDatagram {
+            end_of_group: false,
+            extension_headers: Bytes::new(),
+            object_status: Some(0x3),
+            payload: Bytes::new(),
+            ..datagram()
+        };
+        for msg in [datagram(), status] {
+            let mut buf = BytesMut::new();
+            msg.encode(&mut buf).unwrap();
+            assert_eq!(buf.len(), msg.encoded_len().unwrap());
+            assert_eq!(ObjectDatagram::decode(&mut buf).unwrap(), msg);
+            assert!(buf.is_empty());
+        }
+
+        let mut buf = BytesMut::new();
+        datagram().encode(&mut buf).unwrap();
+        assert_eq!(&buf[..5], &[0x03, 0x01, 0x02, 0x03, 0x80]);
+    }
+
+    #[test]
+    fn draft11_types_roundtrip() {
+        let draft11 = WireVersion::negotiated(crate::codec::DRAFT_11).unwrap();
+        let status = ObjectDatagram {
+            end_of_group: false,
+            object_status: Some(0x3),
+            payload: Bytes::new(),
+            ..datagram()
+        };
+
+        let mut buf = BytesMut::new();
+        datagram().encode_for(&draft11, &mut buf).unwrap();
+        assert_eq!(buf[0], 0x01);
+        assert_eq!(
+            ObjectDatagram::decode_for(&draft11, &mut buf).unwrap(),
+            ObjectDatagram {
+                end_of_group: false,
+                ..datagram()
+            }
+        );
+
+        let mut buf = BytesMut::new();
+        status.encode_for(&draft11, &mut buf).unwrap();
+        assert_eq!(buf[0], 0x03);
+        assert_eq!(buf.len(), status.encoded_len().unwrap());
+        assert_eq!(
+            ObjectDatagram::decode_for(&draft11, &mut buf).unwrap(),
+            status
+        );
+
+        // The draft-12 status types are unknown to draft-11.
+        let mut buf = BytesMut::new();
+        status.encode(&mut buf).unwrap();
+        assert!(matches!(
+            ObjectDatagram::decode_for(&draft11, &mut buf),
+            Err(Error::ProtocolViolation { .. })
+        ));
+    }
+
+    #[test]
+    fn empty_extensions_are_violation() {
+        let mut buf = BytesMut::from(&[0x01, 0x01, 0x02, 0x03, 0x80, 0x00][..]);
+        assert!(matches!(
+            ObjectDatagram::decode(&mut buf),
+            Err(Error::ProtocolViolation { .. })
+        ));
+    }
+
+    #[test]
+    fn oversized_datagram_is_rejected() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (mut a, mut b) = crate::mock::MockTransport::pair();
+            a.set_capabilities(crate::transport::Capabilities {
+                max_datagram_size: Some(16),
+                ..Default::default()
+            });
+
+            // 8 bytes of header and extensions, 8 of payload.
+            let fits = ObjectDatagram {
+                payload: Bytes::from_static(b"01234567"),
+                ..datagram()
+            };
+            send_object_datagram(&WireVersion::Draft12, &mut a, &fits)
+                .await
+                .unwrap();
+            let mut received = BytesMut::from(&b.recv_datagram().await.unwrap()[..]);
+            assert_eq!(ObjectDatagram::decode(&mut received).unwrap(), fits);
+
+            let too_large = ObjectDatagram {
+                payload: Bytes::from_static(b"012345678"),
+                ..datagram()
+            };
+            match send_object_datagram(&WireVersion::Draft12, &mut a, &too_large).await {
+                Err(Error::ObjectTooLargeForDatagram { size: 17, max: 16 }) => {}
+                r => panic!("unexpected result: {r:?}"),
+            }
+        });
+    }
+}
@@ -0,0 +1,206 @@
+use bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::Encoder;
+
+use crate::codec::WireVersion;
+
+/// How the Subgroup ID of a subgroup stream is conveyed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SubgroupId {
+    /// Not present on the wire, the Subgroup ID is 0.
+    Zero,
+    /// Not present on the wire, the Subgroup ID is the Object ID of the
+    /// first object on the stream.
+    FirstObjectId,
+    /// Present on the wire.
+    Explicit(u64),
+}
+
+/// SUBGROUP_HEADER
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-subgroup-header
+///
+/// ```text
+/// SUBGROUP_HEADER {
+///   Type (i) = 0x10..0x1D,
+///   Track Alias (i),
+///   Group ID (i),
+///   [Subgroup ID (i),]
+///   Publisher Priority (8),
+/// }
+/// ```
+///
+/// Draft-11 peers use the types 0x08..0x0D, which have the same layout but
+/// no end-of-group bit. They are accepted on decoding, and written by
+/// [`encode_for`](Self::encode_for) a draft-11 session.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SubgroupHeader {
+    pub track_alias: u64,
+    pub group_id: u64,
+    pub subgroup_id: SubgroupId,
+    pub publisher_priority: u8,
+    /// Whether every object on the stream carries an Extension Headers
+    /// Length field.
+    pub extensions_present: bool,
+    /// Whether the last object before FIN is the last object in the group.
+    pub end_of_group: bool,
+}
+
+impl SubgroupHeader {
+    /// Stream type value for this header.
+    pub fn stream_type(&self) -> u64 {
+        let mut ty = 0x10;
+        if self.extensions_present {
+            ty |= 0x01;
+        }
+        match self.subgroup_id {
+            SubgroupId::Zero => {}
+            SubgroupId::FirstObjectId => ty |= 0x02,
+            SubgroupId::Explicit(_) => ty |= 0x04,
+        }
+        if self.end_of_group {
+            ty |= 0x08;
+        }
+        ty
+    }
+
+    /// Whether `stream_type` is one of the SUBGROUP_HEADER types, of
+    /// draft-12 or draft-11.
+    pub fn is_subgroup_type(stream_type: u64) -> bool {
+        matches!(stream_type, 0x08..=0x0D | 0x10..=0x15 | 0x18..=0x1D)
+    }
+
+    pub fn encode(&self, buf: &mut BytesMut) -> Result<(), crate::error::Error> {
+        self.encode_for(&WireVersion::Draft12, buf)
+    }
+
+    /// Encode the header in the layout of `version`. Draft-11 has no
+    /// end-of-group bit, so `end_of_group` is not conveyed to such peers.
+    pub fn encode_for(
+        &self,
+        version: &WireVersion,
+        buf: &mut BytesMut,
+    ) -> Result<(), crate::error::Error> {
+        let mut vi = crate::codec::VarInt;
+
+        let stream_type = match version {
+            WireVersion::Draft11(_) => 0x08 | (self.stream_type() & 0x07),
+            WireVersion::Draft12 => self.stream_type(),
+        };
+        vi.encode(stream_type, buf)?;
+        vi.encode(self.track_alias, buf)?;
+        vi.encode(self.group_id, buf)?;
+        if let SubgroupId::Explicit(id) = self.subgroup_id {
+            vi.encode(id, buf)?;
+        }
+        buf.put_u8(self.publisher_priority);
+
+        Ok(())
+    }
+
+    pub fn decode(buf: &mut impl Buf) -> Result<Self, crate::error::Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        let vi = crate::codec::VarInt;
+
+        let stream_type = vi
+            .decode_buf(buf)
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "stream type"))?;
+        if !Self::is_subgroup_type(stream_type) {
+            return Err(IoError::new(ErrorKind::InvalidData, "not a subgroup stream").into());
+        }
+        let track_alias = vi
+            .decode_buf(buf)
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "track alias"))?;
+        let group_id = vi
+            .decode_buf(buf)
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "group id"))?;
+        let subgroup_id = match stream_type & 0x06 {
+            0x00 => SubgroupId::Zero,
+            0x02 => SubgroupId::FirstObjectId,
+            _ => SubgroupId::Explicit(
+                vi.decode_buf(buf)
+                    .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "subgroup id"))?,
+            ),
+        };
+        if !buf.has_remaining() {
+            return Err(IoError::new(ErrorKind::UnexpectedEof, "publisher priority").into());
+        }
+        let publisher_priority = buf.get_u8();
+
+        Ok(SubgroupHeader {
+            track_alias,
+            group_id,
+            subgroup_id,
+            publisher_priority,
+            extensions_present: stream_type & 0x01 != 0,
+            end_of_group: stream_type & 0x18 == 0x18,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_roundtrip_all_types() {
+        for subgroup_id in [
+            SubgroupId::Zero,
+            SubgroupId::FirstObjectId,
+            SubgroupId::Explicit(7),
+        ] {
+            for extensions_present in [false, true] {
+                for end_of_group in [false, true] {
+                    let msg = SubgroupHeader {
+                        track_alias: 1,
+                        group_id: 2,
+                        subgroup_id,
+                        publisher_priority: 3,
+                        extensions_present,
+                        end_of_group,
+                    };
+
+                    let mut buf = BytesMut::new();
+                    msg.encode(&mut buf).unwrap();
+                    assert!(SubgroupHeader::is_subgroup_type(buf[0] as u64));
+
+                    let decoded = SubgroupHeader::decode(&mut buf).unwrap();
+                    assert!(buf.is_empty());
+                    assert_eq!(decoded, msg);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn draft11_types_roundtrip() {
+        let draft11 = WireVersion::negotiated(crate::codec::DRAFT_11).unwrap();
+        let msg = SubgroupHeader {
+            track_alias: 1,
+            group_id: 2,
+            subgroup_id: SubgroupId::Explicit(7),
+            publisher_priority: 3,
+            extensions_present: true,
+            end_of_group: true,
+        };
+        let mut buf = BytesMut::new();
+        msg.encode_for(&draft11, &mut buf).unwrap();
+        assert_eq!(buf[0], 0x0D);
+        assert!(SubgroupHeader::is_subgroup_type(0x0D));
+
+        let decoded = SubgroupHeader::decode(&mut buf).unwrap();
+        assert_eq!(
+            decoded,
+            SubgroupHeader {
+                end_of_group: false,
+                ..msg
+            }
+        );
+    }
+
+    #[test]
+    fn decode_rejects_other_stream_types() {
+        let mut buf = BytesMut::from(&[0x16, 0x01, 0x02, 0x03][..]);
+        assert!(SubgroupHeader::decode(&mut buf).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio_util::codec::Encoder;
+
+/// Object sent on a subgroup stream after the SUBGROUP_HEADER.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-subgroup-header
+///
+/// ```text
+/// {
+///   Object ID (i),
+///   [Extension Headers Length (i),
+///   Extension headers (...)],
+///   Object Payload Length (i),
+///   [Object Status (i)],
+///   Object Payload (..),
+/// }
+/// ```
+///
+/// Whether the extension fields are present is decided by the stream's
+/// SUBGROUP_HEADER type, so it is passed to `encode`/`decode`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SubgroupObject {
+    pub object_id: u64,
+    pub extension_headers: Bytes,
+    /// Only present on the wire when the payload is empty.
+    pub object_status: Option<u64>,
+    pub payload: Bytes,
+}
+
+impl SubgroupObject {
+    pub(crate) fn validate(&self, extensions_present: bool) -> Result<(), crate::error::Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        if !extensions_present && !self.extension_headers.is_empty() {
+            return Err(IoError::new(
+                ErrorKind::InvalidData,
+                "extension headers on stream without extensions",
+            )
+            .into());
+        }
+        if !self.payload.is_empty() && self.object_status.is_some_and(|s| s != 0) {
+            return Err(IoError::new(ErrorKind::InvalidData, "object status with payload").into());
+        }
+        Ok(())
+    }
+
+    /// Encode the fields preceding the extension headers.
+    pub(crate) fn encode_head(
+        &self,
+        buf: &mut BytesMut,
+        extensions_present: bool,
+    ) -> Result<(), crate::error::Error> {
+        let mut vi = crate::codec::VarInt;
+        vi.encode(self.object_id, buf)?;
+        if extensions_present {
+            vi.encode(self.extension_headers.len() as u64, buf)?;
+        }
+        Ok(())
+    }
+
+    /// Encode the fields between the extension headers and the payload.
+    pub(crate) fn encode_length(&self, buf: &mut BytesMut) -> Result<(), crate::error::Error> {
+        let mut vi = crate::codec::VarInt;
+        vi.encode(self.payload.len() as u64, buf)?;
+        if self.payload.is_empty() {
+            vi.encode(self.object_status.unwrap_or(0), buf)?;
+        }
+        Ok(())
+    }
+
+    pub fn encode(
+        &self,
+        buf: &mut BytesMut,
+        extensions_present: bool,
+    ) -> Result<(), crate::error::Error> {
+        self.validate(extensions_present)?;
+        self.encode_head(buf, extensions_present)?;
+        buf.put_slice(&self.extension_headers);
+        self.encode_length(buf)?;
+        buf.put_slice(&self.payload);
+        Ok(())
+    }
+
+    pub fn decode(
+        buf: &mut impl Buf,
+        extensions_present: bool,
+    ) -> Result<Self, crate::error::Error> {
+        Self::decode_bounded(buf, extensions_present, usize::MAX)
+    }
+
+    /// Like [`decode`](Self::decode), but fails with
+    /// [`ObjectTooLarge`](crate::error::Error::ObjectTooLarge) as soon as
+    /// the payload length exceeds `max_payload_size`, before the payload
+    /// itself is received.
+    pub fn decode_bounded(
+        buf: &mut impl Buf,
+        extensions_present: bool,
+        max_payload_size: usize,
+    ) -> Result<Self, crate::error::Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        let vi = crate::codec::VarInt;
+
+        let object_id = vi
+            .decode_buf(buf)
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "object id"))?;
+
+        let extension_headers = if extensions_present {
+            let ext_len = vi
+                .decode_buf(buf)
+                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "extension headers len"))?
+                as usize;
+            if buf.remaining() < ext_len {
+                return Err(IoError::new(ErrorKind::UnexpectedEof, "extension headers").into());
+            }
+            buf.copy_to_bytes(ext_len)
+        } else {
+            Bytes::new()
+        };
+
+        let payload_len = vi
+            .decode_buf(buf)
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "payload len"))?
+            as usize;
+        crate::data::check_payload_size(payload_len, max_payload_size)?;
+        let object_status = if payload_len == 0 {
+            Some(
+                vi.decode_buf(buf)
+                    .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "object status"))?,
+            )
+        } else {
+            None
+        };
+        if buf.remaining() < payload_len {
+            return Err(IoError::new(ErrorKind::UnexpectedEof, "payload").into());
+        }
+        let payload = buf.copy_to_bytes(payload_len);
+
+        Ok(SubgroupObject {
+            object_id,
+            extension_headers,
+            object_status,
+            payload,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_bounded_rejects_large_payload_before_it_arrives() {
+        let msg = SubgroupObject {
+            object_id: 1,
+            extension_headers: Bytes::new(),
+            object_status: None,
+            payload: Bytes::from(vec![0; 100]),
+        };
+        let mut buf = BytesMut::new();
+        msg.encode(&mut buf, false).unwrap();
+        buf.truncate(3);
+
+        assert!(matches!(
+            SubgroupObject::decode_bounded(&mut buf, false, 99),
+            Err(crate::error::Error::ObjectTooLarge { size: 100, max: 99 })
+        ));
+    }
+
+    #[test]
+    fn encode_decode_roundtrip_with_extensions() {
+        let msg = SubgroupObject {
+            object_id: 5,
+            extension_headers: Bytes::from_static(&[0x02, 0x01]),
+            object_status: None,
+            payload: Bytes::from_static(b"frame"),
+        };
+
+        let mut buf = BytesMut::new();
+        msg.encode(&mut buf, true).unwrap();
+
+        let decoded = SubgroupObject::decode(&mut buf, true).unwrap();
+        assert!(buf.is_empty());
+        assert_eq!(decoded, msg);
+    }
+
+    #[test]
+    fn encode_decode_roundtrip_status_without_extensions() {
+        let msg = SubgroupObject {
+            object_id: 6,
+            extension_headers: Bytes::new(),
+            object_status: Some(0x3),
+            payload: Bytes::new(),
+        };
+
+        let mut buf = BytesMut::new();
+        msg.encode(&mut buf, false).unwrap();
+        assert_eq!(buf.as_ref(), &[0x06, 0x00, 0x03]);
+
+        let decoded = SubgroupObject::decode(&mut buf, false).unwrap();
+        assert!(buf.is_empty());
+        assert_eq!(decoded, msg);
+    }
+
+    #[test]
+    fn extensions_rejected_when_not_present() {
+        let msg = SubgroupObject {
+            object_id: 1,
+            extension_headers: Bytes::from_static(&[0x02, 0x01]),
+            object_status: None,
+            payload: Bytes::from_static(b"x"),
+        };
+        assert!(msg.encode(&mut BytesMut::new(), false).is_err());
+    }
+}
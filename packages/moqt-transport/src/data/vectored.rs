@@ -0,0 +1,219 @@
+use std::io::IoSlice;
+
+use bytes::{Buf, BytesMut};
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    codec::WireVersion,
+    data::{SubgroupHeader, SubgroupObject},
+    error::Error,
+    transport::UniStream,
+};
+
+/// Write an object on a subgroup stream without copying its extension
+/// headers or payload into an intermediate buffer.
+///
+/// `header` is written first when given, i.e. for the first object on a
+/// newly opened stream. Streams that advertise vectored writes receive all
+/// pieces through `write_vectored`; others receive the same pieces one
+/// after another.
+pub async fn write_object_vectored<S: UniStream>(
+    stream: &mut S,
+    header: Option<&SubgroupHeader>,
+    extensions_present: bool,
+    object: &SubgroupObject,
+) -> Result<(), Error> {
+    write_object_vectored_for(
+        &WireVersion::Draft12,
+        stream,
+        header,
+        extensions_present,
+        object,
+    )
+    .await
+}
+
+/// [`write_object_vectored`], encoding `header` in the layout of `version`.
+pub(crate) async fn write_object_vectored_for<S: UniStream>(
+    version: &WireVersion,
+    stream: &mut S,
+    header: Option<&SubgroupHeader>,
+    extensions_present: bool,
+    object: &SubgroupObject,
+) -> Result<(), Error> {
+    object.validate(extensions_present)?;
+
+    let mut head = BytesMut::new();
+    if let Some(header) = header {
+        header.encode_for(version, &mut head)?;
+    }
+    object.encode_head(&mut head, extensions_present)?;
+    let mut length = BytesMut::new();
+    object.encode_length(&mut length)?;
+
+    let mut chain = head
+        .freeze()
+        .chain(object.extension_headers.clone())
+        .chain(length.freeze())
+        .chain(object.payload.clone());
+
+    if stream.supports_vectored_writes() {
+        while chain.has_remaining() {
+            let mut slices = [IoSlice::new(&[]); 4];
+            let n = chain.chunks_vectored(&mut slices);
+            let written = stream.write_vectored(&slices[..n]).await?;
+            if written == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into());
+            }
+            chain.advance(written);
+        }
+    } else {
+        stream.write_all_buf(&mut chain).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::SubgroupId;
+    use bytes::Bytes;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    /// Stream recording every write call, optionally accepting at most
+    /// `limit` bytes per call to exercise partial vectored writes.
+    #[derive(Default)]
+    struct RecordingStream {
+        vectored: bool,
+        limit: Option<usize>,
+        calls: usize,
+        data: Vec<u8>,
+    }
+
+    impl AsyncRead for RecordingStream {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl AsyncWrite for RecordingStream {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            let this = self.get_mut();
+            this.calls += 1;
+            let n = this.limit.map_or(buf.len(), |l| l.min(buf.len()));
+            this.data.extend_from_slice(&buf[..n]);
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_write_vectored(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            bufs: &[IoSlice<'_>],
+        ) -> Poll<std::io::Result<usize>> {
+            let this = self.get_mut();
+            this.calls += 1;
+            let mut budget = this.limit.unwrap_or(usize::MAX);
+            let mut n = 0;
+            for b in bufs {
+                let take = budget.min(b.len());
+                this.data.extend_from_slice(&b[..take]);
+                budget -= take;
+                n += take;
+            }
+            Poll::Ready(Ok(n))
+        }
+
+        fn is_write_vectored(&self) -> bool {
+            self.vectored
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl UniStream for RecordingStream {
+        fn reset(&mut self, _code: u64) {}
+    }
+
+    fn header() -> SubgroupHeader {
+        SubgroupHeader {
+            track_alias: 1,
+            group_id: 2,
+            subgroup_id: SubgroupId::Explicit(3),
+            publisher_priority: 4,
+            extensions_present: true,
+            end_of_group: false,
+        }
+    }
+
+    fn object() -> SubgroupObject {
+        SubgroupObject {
+            object_id: 0,
+            extension_headers: Bytes::from_static(&[0x02, 0x05]),
+            object_status: None,
+            payload: Bytes::from(vec![0xAB; 300]),
+        }
+    }
+
+    fn expected() -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        header().encode(&mut buf).unwrap();
+        object().encode(&mut buf, true).unwrap();
+        buf.to_vec()
+    }
+
+    fn write(mut stream: RecordingStream) -> RecordingStream {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            write_object_vectored(&mut stream, Some(&header()), true, &object())
+                .await
+                .unwrap();
+        });
+        stream
+    }
+
+    #[test]
+    fn vectored_stream_gets_single_write() {
+        let stream = write(RecordingStream {
+            vectored: true,
+            ..Default::default()
+        });
+        assert_eq!(stream.calls, 1);
+        assert_eq!(stream.data, expected());
+    }
+
+    #[test]
+    fn partial_vectored_writes_resume() {
+        let stream = write(RecordingStream {
+            vectored: true,
+            limit: Some(7),
+            ..Default::default()
+        });
+        assert!(stream.calls > 1);
+        assert_eq!(stream.data, expected());
+    }
+
+    #[test]
+    fn non_vectored_stream_gets_pieces() {
+        let stream = write(RecordingStream::default());
+        assert_eq!(stream.calls, 4);
+        assert_eq!(stream.data, expected());
+    }
+}
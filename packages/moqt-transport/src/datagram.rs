@@ -0,0 +1,267 @@
+use bytes::{Bytes, BytesMut};
+
+use crate::{
+    codec::WireVersion,
+    data::{ObjectDatagram, send_object_datagram},
+    error::{Error, Offending},
+    model::{ForwardingPreference, ObjectStatus},
+    track::{AliasWindow, Delivery, Object, ObjectMetadata, TrackManager},
+    transport::Transport,
+};
+
+/// How a received datagram whose Track Alias is not registered is handled.
+///
+/// The draft lets an endpoint drop such a datagram or buffer it briefly,
+/// since the SUBSCRIBE_OK establishing the alias may still be in flight.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-datagrams
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownAliasPolicy {
+    /// Drop the datagram. The default.
+    #[default]
+    Drop,
+    /// Hold the object within the bounds of the window until the alias is
+    /// registered.
+    Buffer(AliasWindow),
+    /// Fail with [`Error::UnknownTrackAlias`], to close the session.
+    Reject,
+}
+
+/// Decode a received datagram, sent in the layout of `version`, and
+/// deliver its object to the subscribers of its track through `tracks`,
+/// applying `policy` if the alias is unknown.
+///
+/// A datagram that cannot be decoded is a protocol violation.
+pub fn receive_datagram(
+    version: &WireVersion,
+    tracks: &TrackManager,
+    policy: UnknownAliasPolicy,
+    datagram: Bytes,
+) -> Result<Delivery, Error> {
+    let raw = datagram.clone();
+    let datagram = ObjectDatagram::decode_for(version, &mut BytesMut::from(datagram))
+        .and_then(Object::from_datagram)
+        .map_err(|e| {
+            let error = match e {
+                Error::Io(e) => Error::ProtocolViolation {
+                    reason: format!("malformed datagram: {e}"),
+                    context: None,
+                },
+                e => e,
+            };
+            error.with_offending("datagram.malformed", Offending::Bytes(raw))
+        })?;
+
+    let alias = datagram.metadata.track_alias;
+    if tracks.resolve_alias(alias).is_some() {
+        return Ok(Delivery::Delivered(
+            tracks.deliver_from(datagram, ForwardingPreference::Datagram),
+        ));
+    }
+    match policy {
+        UnknownAliasPolicy::Drop => Ok(Delivery::Dropped),
+        UnknownAliasPolicy::Buffer(window) => {
+            Ok(tracks.deliver_or_hold(datagram, ForwardingPreference::Datagram, &window))
+        }
+        UnknownAliasPolicy::Reject => Err(Error::UnknownTrackAlias(alias)),
+    }
+}
+
+/// Send OBJECT_DATAGRAM_STATUS telling the subscribers of a datagram
+/// track that the object at `metadata` has `status` instead of a payload,
+/// e.g. [`ObjectStatus::DoesNotExist`] for a requested object that was
+/// never produced.
+///
+/// The receiver delivers it as an [`Object`] with that status and no
+/// payload. It is encoded in the layout of `version`.
+pub async fn send_object_status<T: Transport>(
+    version: &WireVersion,
+    transport: &mut T,
+    metadata: &ObjectMetadata,
+    status: ObjectStatus,
+) -> Result<(), Error> {
+    let datagram = ObjectDatagram {
+        track_alias: metadata.track_alias,
+        group_id: metadata.group_id,
+        object_id: metadata.object_id,
+        publisher_priority: metadata.publisher_priority,
+        end_of_group: false,
+        extension_headers: Bytes::new(),
+        object_status: Some(status.code()),
+        payload: Bytes::new(),
+    };
+    send_object_datagram(version, transport, &datagram).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::message::SubscribeOk;
+
+    fn datagram(track_alias: u64, object_id: u64) -> Bytes {
+        let mut buf = BytesMut::new();
+        ObjectDatagram {
+            track_alias,
+            group_id: 0,
+            object_id,
+            publisher_priority: 0,
+            end_of_group: false,
+            extension_headers: Bytes::new(),
+            object_status: None,
+            payload: Bytes::from_static(b"frame"),
+        }
+        .encode(&mut buf)
+        .unwrap();
+        buf.freeze()
+    }
+
+    fn subscribe_ok(request_id: u64, track_alias: u64) -> SubscribeOk {
+        SubscribeOk {
+            request_id,
+            track_alias,
+            expires: 0,
+            group_order: 0x1,
+            content_exists: false,
+            largest_location: None,
+            parameters: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn unknown_alias_policies() {
+        let tracks = TrackManager::default();
+        tracks.handle_max_request_id(10).unwrap();
+        let (request_id, mut objects) = tracks.subscribe_track("video".into()).unwrap();
+
+        let drop = UnknownAliasPolicy::Drop;
+        assert_eq!(
+            receive_datagram(&WireVersion::Draft12, &tracks, drop, datagram(7, 0)).unwrap(),
+            Delivery::Dropped
+        );
+        assert!(matches!(
+            receive_datagram(
+                &WireVersion::Draft12,
+                &tracks,
+                UnknownAliasPolicy::Reject,
+                datagram(7, 0)
+            ),
+            Err(Error::UnknownTrackAlias(7))
+        ));
+
+        let buffer = UnknownAliasPolicy::Buffer(AliasWindow::default().with_max_objects(2));
+        for object_id in 0..3 {
+            let expected = if object_id < 2 {
+                Delivery::Held
+            } else {
+                Delivery::Dropped
+            };
+            assert_eq!(
+                receive_datagram(
+                    &WireVersion::Draft12,
+                    &tracks,
+                    buffer,
+                    datagram(7, object_id)
+                )
+                .unwrap(),
+                expected
+            );
+        }
+        assert_eq!(tracks.held_objects(), 2);
+
+        // The SUBSCRIBE_OK establishing the alias releases the held objects.
+        tracks
+            .handle_subscribe_ok(&subscribe_ok(request_id, 7))
+            .unwrap();
+        assert_eq!(tracks.held_objects(), 0);
+        for object_id in 0..2 {
+            let object = objects.rx.try_recv().unwrap().unwrap();
+            assert_eq!(object.metadata.object_id, object_id);
+        }
+        assert_eq!(
+            receive_datagram(&WireVersion::Draft12, &tracks, drop, datagram(7, 3)).unwrap(),
+            Delivery::Delivered(1)
+        );
+    }
+
+    #[test]
+    fn expired_objects_are_not_released() {
+        let tracks = TrackManager::default();
+        tracks.handle_max_request_id(10).unwrap();
+        let (request_id, mut objects) = tracks.subscribe_track("video".into()).unwrap();
+        let buffer =
+            UnknownAliasPolicy::Buffer(AliasWindow::default().with_max_age(Duration::ZERO));
+        receive_datagram(&WireVersion::Draft12, &tracks, buffer, datagram(7, 0)).unwrap();
+        tracks
+            .handle_subscribe_ok(&subscribe_ok(request_id, 7))
+            .unwrap();
+        assert!(objects.rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn missing_object_is_sent_as_status() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let tracks = TrackManager::default();
+            tracks.handle_max_request_id(10).unwrap();
+            let (request_id, mut objects) = tracks.subscribe_track("video".into()).unwrap();
+            tracks
+                .handle_subscribe_ok(&subscribe_ok(request_id, 7))
+                .unwrap();
+
+            let group = crate::track::TrackPublisher::new(7).begin_group();
+            group.subgroup(0).object(Bytes::from_static(b"frame"));
+            // The subscriber asked for object 3, which was never produced.
+            let missing = group.does_not_exist(3);
+            assert_eq!(missing.metadata.object_id, 3);
+            assert_eq!(group.subgroup(0).object(Bytes::new()).metadata.object_id, 4);
+            let (mut publisher, mut subscriber) = crate::mock::MockTransport::pair();
+            send_object_status(
+                &WireVersion::Draft12,
+                &mut publisher,
+                &missing.metadata,
+                missing.status,
+            )
+            .await
+            .unwrap();
+
+            let received = subscriber.recv_datagram().await.unwrap();
+            // OBJECT_DATAGRAM_STATUS without extension headers.
+            assert_eq!(received[0], 0x04);
+            assert_eq!(
+                receive_datagram(
+                    &WireVersion::Draft12,
+                    &tracks,
+                    UnknownAliasPolicy::Drop,
+                    received
+                )
+                .unwrap(),
+                Delivery::Delivered(1)
+            );
+            let object = objects.recv().await.unwrap().unwrap();
+            assert_eq!(object.status, ObjectStatus::DoesNotExist);
+            assert_eq!(object.metadata.location(), missing.metadata.location());
+            assert!(object.payload.is_empty());
+        });
+    }
+
+    #[test]
+    fn malformed_datagram_is_violation() {
+        let tracks = TrackManager::default();
+        let truncated = Bytes::from_static(&[0x00, 0x07, 0x00]);
+        match receive_datagram(
+            &WireVersion::Draft12,
+            &tracks,
+            UnknownAliasPolicy::Drop,
+            truncated,
+        ) {
+            Err(Error::ProtocolViolation {
+                context: Some(v), ..
+            }) => assert_eq!(v.rule, "datagram.malformed"),
+            r => panic!("unexpected result: {r:?}"),
+        }
+    }
+}
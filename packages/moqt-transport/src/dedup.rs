@@ -0,0 +1,179 @@
+use std::collections::BTreeSet;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+
+use crate::error::Error;
+use crate::track::{Object, ObjectKey, ObjectStream};
+
+/// Optional subscriber-side filter letting each object through once, for
+/// tracks whose objects may be received twice: from a repair FETCH racing
+/// the subscription, or from a new upstream after failover.
+///
+/// Objects are identified by group, subgroup and object ID, whatever Track
+/// Alias they arrive under. Only the newest `max_objects` identities are
+/// remembered; an object ordered before all of them is let through, as it
+/// may be a late one never received before, so a duplicate that old may
+/// pass again.
+pub struct Deduplicator {
+    max_objects: usize,
+    seen: BTreeSet<ObjectKey>,
+    dropped: u64,
+}
+
+impl Deduplicator {
+    pub fn new(max_objects: usize) -> Self {
+        Self {
+            max_objects,
+            seen: BTreeSet::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Record a received object. Returns whether it is to be delivered,
+    /// `false` if it is a duplicate of a remembered object.
+    pub fn insert(&mut self, object: &Object) -> bool {
+        if !self.seen.insert(object.metadata.key().untracked()) {
+            self.dropped += 1;
+            return false;
+        }
+        while self.seen.len() > self.max_objects {
+            self.seen.pop_first();
+        }
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+
+    /// Objects dropped as duplicates.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+/// An [`ObjectStream`] consumed through a [`Deduplicator`], created by
+/// [`ObjectStream::dedup`].
+///
+/// Objects from several sources, e.g. the subscription and its repair
+/// FETCHes, are deduplicated together when they are fed into one stream
+/// made with [`ObjectStream::channel`].
+pub struct DedupStream {
+    objects: ObjectStream,
+    filter: Deduplicator,
+}
+
+impl DedupStream {
+    pub(crate) fn new(objects: ObjectStream, max_objects: usize) -> Self {
+        Self {
+            objects,
+            filter: Deduplicator::new(max_objects),
+        }
+    }
+
+    /// Receive the next object not received before, or `None` once the
+    /// stream ended.
+    pub async fn recv(&mut self) -> Option<Result<Object, Error>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Objects dropped as duplicates.
+    pub fn dropped(&self) -> u64 {
+        self.filter.dropped()
+    }
+}
+
+impl Stream for DedupStream {
+    type Item = Result<Object, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match Pin::new(&mut self.objects).poll_next(cx) {
+                Poll::Ready(Some(Ok(object))) => {
+                    if self.filter.insert(&object) {
+                        return Poll::Ready(Some(Ok(object)));
+                    }
+                }
+                other => return other,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::ObjectBuilder;
+
+    fn object(track_alias: u64, group_id: u64, subgroup_id: u64, object_id: u64) -> Object {
+        ObjectBuilder::new(group_id, object_id)
+            .with_track_alias(track_alias)
+            .with_subgroup(subgroup_id)
+            .build()
+    }
+
+    #[test]
+    fn objects_pass_once_across_aliases() {
+        let mut filter = Deduplicator::new(16);
+        assert!(filter.insert(&object(1, 0, 0, 0)));
+        assert!(filter.insert(&object(1, 0, 1, 1)));
+        // The same object from a new upstream under another alias.
+        assert!(!filter.insert(&object(2, 0, 0, 0)));
+        // A repair filling a gap, then returning an object twice.
+        assert!(filter.insert(&object(2, 0, 0, 2)));
+        assert!(!filter.insert(&object(1, 0, 0, 2)));
+        assert!(filter.insert(&object(1, 0, 1, 2)));
+        assert_eq!(filter.len(), 4);
+        assert_eq!(filter.dropped(), 2);
+    }
+
+    #[test]
+    fn objects_older_than_remembered_pass() {
+        let mut filter = Deduplicator::new(2);
+        assert!(filter.insert(&object(1, 0, 0, 1)));
+        assert!(filter.insert(&object(1, 1, 0, 0)));
+        assert!(filter.insert(&object(1, 1, 0, 1)));
+        assert_eq!(filter.len(), 2);
+        // Group 0 was forgotten: a late object of it is not lost, at the
+        // cost of letting a duplicate that old through as well.
+        assert!(filter.insert(&object(1, 0, 0, 0)));
+        assert!(filter.insert(&object(1, 0, 0, 1)));
+        assert_eq!(filter.len(), 2);
+        assert!(!filter.insert(&object(1, 1, 0, 1)));
+        assert!(filter.insert(&object(1, 1, 0, 2)));
+        assert_eq!(filter.dropped(), 1);
+    }
+
+    #[test]
+    fn stream_skips_duplicates() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (tx, objects) = ObjectStream::channel(16);
+            let mut objects = objects.dedup(64);
+            for object_id in [0, 1, 0, 2, 1] {
+                tx.send(Ok(object(1, 0, 0, object_id))).await.unwrap();
+            }
+            tx.send(Err(Error::SessionClosed)).await.unwrap();
+            drop(tx);
+
+            for expected in 0..3 {
+                let object = objects.recv().await.unwrap().unwrap();
+                assert_eq!(object.metadata.object_id, expected);
+            }
+            assert!(matches!(
+                objects.recv().await,
+                Some(Err(Error::SessionClosed))
+            ));
+            assert!(objects.recv().await.is_none());
+            assert_eq!(objects.dropped(), 2);
+        });
+    }
+}
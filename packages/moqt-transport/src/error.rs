@@ -1,3 +1,13 @@
+use bytes::Bytes;
+
+use crate::{
+    auth::AuthError,
+    message::{
+        AnnounceError, ControlMessage, FetchError, PublishError, SubscribeAnnouncesError,
+        SubscribeError,
+    },
+};
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Transport layer error: {0}")]
@@ -6,11 +16,28 @@ pub enum Error {
     #[error("Failed to decode message: {0}")]
     Codec(String),
 
-    #[error("Protocol violation: {reason}")]
-    ProtocolViolation { reason: String },
+    /// `context`, when attached, names the rule violated and the input
+    /// that violated it.
+    #[error(
+        "Protocol violation: {reason}{}",
+        .context.as_ref().map(|c| format!(" ({})", c.rule)).unwrap_or_default()
+    )]
+    ProtocolViolation {
+        reason: String,
+        context: Option<Box<Violation>>,
+    },
 
     #[error("Subscription failed: {reason}")]
-    SubscriptionFailed { code: u64, reason: String },
+    SubscriptionFailed {
+        code: RequestErrorCode,
+        reason: String,
+    },
+
+    #[error("Request failed: {reason}")]
+    RequestFailed {
+        code: RequestErrorCode,
+        reason: String,
+    },
 
     #[error("Session closed")]
     SessionClosed,
@@ -18,15 +45,605 @@ pub enum Error {
     #[error("Invalid track alias: {0}")]
     DuplicateTrackAlias(u64),
 
+    #[error("unknown track alias: {0}")]
+    UnknownTrackAlias(u64),
+
+    #[error("namespace {namespace:?} is already announced")]
+    DuplicateAnnounce { namespace: Vec<String> },
+
+    #[error("invalid New Session URI {uri:?}: {reason}")]
+    InvalidUri { uri: String, reason: String },
+
     #[error("varint out of range")]
     VarIntRange,
 
     #[error("unknown message type")]
     UnknownMessageType,
 
+    /// CLIENT_SETUP offered none of the versions this endpoint supports.
+    #[error("no supported version among {offered:x?}")]
+    VersionNegotiationFailed { offered: Vec<u32> },
+
+    /// The session was established on a path no application is hosted on,
+    /// or on none when one is required.
+    #[error("no application hosted on path {path:?}")]
+    InvalidPath { path: Option<String> },
+
+    /// The peer did not complete the session setup in time.
+    #[error("no CLIENT_SETUP within {timeout:?}")]
+    SetupTimeout { timeout: std::time::Duration },
+
     #[error("too many requests")]
     TooManyRequests,
 
-    #[error("std::io::Error")]
+    #[error("control message queue full")]
+    ControlQueueFull,
+
+    /// The subscriber's queue filled up and the publisher stopped sending
+    /// it objects, as with the 'Too Far Behind' SUBSCRIBE_DONE status.
+    #[error("subscriber fell too far behind")]
+    TooFarBehind,
+
+    #[error("payload hash mismatch for object {group_id}/{object_id}")]
+    PayloadHashMismatch { group_id: u64, object_id: u64 },
+
+    #[error("object of {size} bytes exceeds the maximum datagram size of {max}")]
+    ObjectTooLargeForDatagram { size: usize, max: usize },
+
+    /// An object payload, sent or received, exceeds the session's maximum
+    /// object payload size. The stream carrying it is reset.
+    #[error("object payload of {size} bytes exceeds the maximum of {max}")]
+    ObjectTooLarge { size: usize, max: usize },
+
+    #[error("malformed track: {reason}")]
+    MalformedTrack { reason: String },
+
+    /// A data stream ended or was reset in the middle of an object, which
+    /// was discarded. The Object ID is known if its header was received.
+    #[error("object of group {group_id} cut short by the end of its stream")]
+    TruncatedObject {
+        group_id: u64,
+        object_id: Option<u64>,
+    },
+
+    #[error("authorization failed: {0}")]
+    Auth(#[from] crate::auth::AuthError),
+
+    #[error("I/O error: {0}")]
     Io(#[from] std::io::Error),
 }
+
+/// The rule a protocol violation broke and the input that broke it, so
+/// operators can report it precisely to the peer's implementers.
+#[derive(Debug, Clone)]
+pub struct Violation {
+    /// Identifier of the violated rule, such as `"goaway.duplicate"`.
+    pub rule: &'static str,
+    pub offending: Offending,
+}
+
+/// Input a [`Violation`] was found in.
+#[derive(Debug, Clone)]
+pub enum Offending {
+    /// A control message that was decoded.
+    Message(ControlMessage),
+    /// Bytes that could not be decoded, as received.
+    Bytes(Bytes),
+}
+
+impl Error {
+    /// A copy of the error, e.g. to deliver one error to every subscriber
+    /// of a track. I/O and transport errors keep their kind and message but
+    /// not their source.
+    pub fn duplicate(&self) -> Error {
+        match self {
+            Error::Transport(e) => Error::Transport(e.to_string().into()),
+            Error::Codec(reason) => Error::Codec(reason.clone()),
+            Error::ProtocolViolation { reason, context } => Error::ProtocolViolation {
+                reason: reason.clone(),
+                context: context.clone(),
+            },
+            Error::SubscriptionFailed { code, reason } => Error::SubscriptionFailed {
+                code: *code,
+                reason: reason.clone(),
+            },
+            Error::RequestFailed { code, reason } => Error::RequestFailed {
+                code: *code,
+                reason: reason.clone(),
+            },
+            Error::SessionClosed => Error::SessionClosed,
+            Error::DuplicateTrackAlias(alias) => Error::DuplicateTrackAlias(*alias),
+            Error::UnknownTrackAlias(alias) => Error::UnknownTrackAlias(*alias),
+            Error::DuplicateAnnounce { namespace } => Error::DuplicateAnnounce {
+                namespace: namespace.clone(),
+            },
+            Error::InvalidUri { uri, reason } => Error::InvalidUri {
+                uri: uri.clone(),
+                reason: reason.clone(),
+            },
+            Error::VarIntRange => Error::VarIntRange,
+            Error::UnknownMessageType => Error::UnknownMessageType,
+            Error::VersionNegotiationFailed { offered } => Error::VersionNegotiationFailed {
+                offered: offered.clone(),
+            },
+            Error::InvalidPath { path } => Error::InvalidPath { path: path.clone() },
+            Error::SetupTimeout { timeout } => Error::SetupTimeout { timeout: *timeout },
+            Error::TooManyRequests => Error::TooManyRequests,
+            Error::ControlQueueFull => Error::ControlQueueFull,
+            Error::TooFarBehind => Error::TooFarBehind,
+            Error::PayloadHashMismatch {
+                group_id,
+                object_id,
+            } => Error::PayloadHashMismatch {
+                group_id: *group_id,
+                object_id: *object_id,
+            },
+            Error::ObjectTooLargeForDatagram { size, max } => Error::ObjectTooLargeForDatagram {
+                size: *size,
+                max: *max,
+            },
+            Error::ObjectTooLarge { size, max } => Error::ObjectTooLarge {
+                size: *size,
+                max: *max,
+            },
+            Error::MalformedTrack { reason } => Error::MalformedTrack {
+                reason: reason.clone(),
+            },
+            Error::TruncatedObject {
+                group_id,
+                object_id,
+            } => Error::TruncatedObject {
+                group_id: *group_id,
+                object_id: *object_id,
+            },
+            Error::Auth(e) => Error::Auth(e.clone()),
+            Error::Io(e) => Error::Io(std::io::Error::new(e.kind(), e.to_string())),
+        }
+    }
+
+    /// Attach the rule violated and the offending input to a decoding
+    /// failure, turning it into a protocol violation. I/O errors count when
+    /// they report malformed or truncated input; other errors, including
+    /// [`Error::UnknownMessageType`], are returned unchanged.
+    pub fn with_offending(self, rule: &'static str, offending: Offending) -> Self {
+        let reason = match self {
+            Error::ProtocolViolation { reason, .. } | Error::Codec(reason) => reason,
+            Error::VarIntRange => self.to_string(),
+            Error::Io(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::InvalidData | std::io::ErrorKind::UnexpectedEof
+                ) =>
+            {
+                e.to_string()
+            }
+            e => return e,
+        };
+        Error::ProtocolViolation {
+            reason,
+            context: Some(Box::new(Violation { rule, offending })),
+        }
+    }
+
+    /// Context of a protocol violation, if attached.
+    pub fn violation(&self) -> Option<&Violation> {
+        match self {
+            Error::ProtocolViolation { context, .. } => context.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl From<moqt_wire::error::Error> for Error {
+    fn from(error: moqt_wire::error::Error) -> Self {
+        use moqt_wire::error::Error as WireError;
+
+        match error {
+            WireError::ProtocolViolation { reason } => Error::ProtocolViolation {
+                reason,
+                context: None,
+            },
+            WireError::VarIntRange => Error::VarIntRange,
+            WireError::UnknownMessageType => Error::UnknownMessageType,
+            WireError::Io(e) => Error::Io(e),
+        }
+    }
+}
+
+/// Session termination codes.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-termination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminationCode {
+    NoError,
+    InternalError,
+    Unauthorized,
+    ProtocolViolation,
+    InvalidRequestId,
+    DuplicateTrackAlias,
+    KeyValueFormattingError,
+    TooManyRequests,
+    InvalidPath,
+    MalformedPath,
+    GoawayTimeout,
+    ControlMessageTimeout,
+    DataStreamTimeout,
+    AuthTokenCacheOverflow,
+    DuplicateAuthTokenAlias,
+    VersionNegotiationFailed,
+    MalformedAuthToken,
+    UnknownAuthTokenAlias,
+    ExpiredAuthToken,
+}
+
+impl TerminationCode {
+    pub fn code(self) -> u64 {
+        match self {
+            TerminationCode::NoError => 0x0,
+            TerminationCode::InternalError => 0x1,
+            TerminationCode::Unauthorized => 0x2,
+            TerminationCode::ProtocolViolation => 0x3,
+            TerminationCode::InvalidRequestId => 0x4,
+            TerminationCode::DuplicateTrackAlias => 0x5,
+            TerminationCode::KeyValueFormattingError => 0x6,
+            TerminationCode::TooManyRequests => 0x7,
+            TerminationCode::InvalidPath => 0x8,
+            TerminationCode::MalformedPath => 0x9,
+            TerminationCode::GoawayTimeout => 0x10,
+            TerminationCode::ControlMessageTimeout => 0x11,
+            TerminationCode::DataStreamTimeout => 0x12,
+            TerminationCode::AuthTokenCacheOverflow => 0x13,
+            TerminationCode::DuplicateAuthTokenAlias => 0x14,
+            TerminationCode::VersionNegotiationFailed => 0x15,
+            TerminationCode::MalformedAuthToken => 0x16,
+            TerminationCode::UnknownAuthTokenAlias => 0x17,
+            TerminationCode::ExpiredAuthToken => 0x18,
+        }
+    }
+}
+
+impl TryFrom<u64> for TerminationCode {
+    type Error = Error;
+
+    fn try_from(code: u64) -> Result<Self, Self::Error> {
+        Ok(match code {
+            0x0 => TerminationCode::NoError,
+            0x1 => TerminationCode::InternalError,
+            0x2 => TerminationCode::Unauthorized,
+            0x3 => TerminationCode::ProtocolViolation,
+            0x4 => TerminationCode::InvalidRequestId,
+            0x5 => TerminationCode::DuplicateTrackAlias,
+            0x6 => TerminationCode::KeyValueFormattingError,
+            0x7 => TerminationCode::TooManyRequests,
+            0x8 => TerminationCode::InvalidPath,
+            0x9 => TerminationCode::MalformedPath,
+            0x10 => TerminationCode::GoawayTimeout,
+            0x11 => TerminationCode::ControlMessageTimeout,
+            0x12 => TerminationCode::DataStreamTimeout,
+            0x13 => TerminationCode::AuthTokenCacheOverflow,
+            0x14 => TerminationCode::DuplicateAuthTokenAlias,
+            0x15 => TerminationCode::VersionNegotiationFailed,
+            0x16 => TerminationCode::MalformedAuthToken,
+            0x17 => TerminationCode::UnknownAuthTokenAlias,
+            0x18 => TerminationCode::ExpiredAuthToken,
+            _ => {
+                return Err(Error::ProtocolViolation {
+                    reason: format!("unknown termination code {code:#x}"),
+                    context: None,
+                });
+            }
+        })
+    }
+}
+
+/// The code to close the session with when `error` ends it.
+impl From<&Error> for TerminationCode {
+    fn from(error: &Error) -> Self {
+        match error {
+            Error::SessionClosed => TerminationCode::NoError,
+            Error::Codec(_)
+            | Error::ProtocolViolation { .. }
+            | Error::VarIntRange
+            | Error::UnknownMessageType
+            | Error::PayloadHashMismatch { .. }
+            | Error::MalformedTrack { .. }
+            | Error::UnknownTrackAlias(_)
+            | Error::DuplicateAnnounce { .. }
+            | Error::InvalidUri { .. } => TerminationCode::ProtocolViolation,
+            Error::DuplicateTrackAlias(_) => TerminationCode::DuplicateTrackAlias,
+            Error::VersionNegotiationFailed { .. } => TerminationCode::VersionNegotiationFailed,
+            Error::InvalidPath { .. } => TerminationCode::InvalidPath,
+            Error::TooManyRequests => TerminationCode::TooManyRequests,
+            Error::SetupTimeout { .. } => TerminationCode::ControlMessageTimeout,
+            Error::Auth(AuthError::KeyValueFormatting) => TerminationCode::KeyValueFormattingError,
+            Error::Auth(AuthError::CacheOverflow) => TerminationCode::AuthTokenCacheOverflow,
+            Error::Auth(AuthError::DuplicateAlias(_)) => TerminationCode::DuplicateAuthTokenAlias,
+            Error::Auth(AuthError::UnknownAlias(_)) => TerminationCode::UnknownAuthTokenAlias,
+            Error::Auth(AuthError::Unauthorized) => TerminationCode::Unauthorized,
+            Error::Auth(AuthError::MalformedToken) => TerminationCode::MalformedAuthToken,
+            Error::Transport(_)
+            | Error::SubscriptionFailed { .. }
+            | Error::RequestFailed { .. }
+            | Error::ControlQueueFull
+            | Error::TooFarBehind
+            | Error::ObjectTooLargeForDatagram { .. }
+            | Error::ObjectTooLarge { .. }
+            | Error::TruncatedObject { .. }
+            | Error::Io(_) => TerminationCode::InternalError,
+        }
+    }
+}
+
+/// Error codes of SUBSCRIBE_ERROR, FETCH_ERROR, PUBLISH_ERROR,
+/// ANNOUNCE_ERROR and SUBSCRIBE_ANNOUNCES_ERROR.
+///
+/// Code points 0x4 and 0x5 mean different things depending on the message,
+/// so received codes are interpreted with the constructor of the message
+/// they arrived in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestErrorCode {
+    InternalError,
+    Unauthorized,
+    Timeout,
+    NotSupported,
+    TrackDoesNotExist,
+    InvalidRange,
+    NoObjects,
+    InvalidJoiningRequestId,
+    UnknownStatusInRange,
+    MalformedTrack,
+    Uninterested,
+    NamespacePrefixUnknown,
+    NamespacePrefixOverlap,
+    MalformedAuthToken,
+    /// A request referring to an unregistered token alias. The draft only
+    /// assigns this error a code in the termination code table.
+    UnknownAuthTokenAlias,
+    ExpiredAuthToken,
+    /// An ANNOUNCE beyond the limits of a
+    /// [`PeerAnnounces`](crate::announce::PeerAnnounces). Draft-12 defines
+    /// no dedicated code, so it is sent as Internal Error and received
+    /// back as [`InternalError`](Self::InternalError).
+    AnnounceLimitExceeded,
+    /// A code not defined for the message it was received in.
+    Unknown(u64),
+}
+
+impl RequestErrorCode {
+    pub fn code(self) -> u64 {
+        match self {
+            RequestErrorCode::InternalError | RequestErrorCode::AnnounceLimitExceeded => 0x0,
+            RequestErrorCode::Unauthorized => 0x1,
+            RequestErrorCode::Timeout => 0x2,
+            RequestErrorCode::NotSupported => 0x3,
+            RequestErrorCode::TrackDoesNotExist
+            | RequestErrorCode::Uninterested
+            | RequestErrorCode::NamespacePrefixUnknown => 0x4,
+            RequestErrorCode::InvalidRange | RequestErrorCode::NamespacePrefixOverlap => 0x5,
+            RequestErrorCode::NoObjects => 0x6,
+            RequestErrorCode::InvalidJoiningRequestId => 0x7,
+            RequestErrorCode::UnknownStatusInRange => 0x8,
+            RequestErrorCode::MalformedTrack => 0x9,
+            RequestErrorCode::MalformedAuthToken => 0x10,
+            RequestErrorCode::UnknownAuthTokenAlias => {
+                TerminationCode::UnknownAuthTokenAlias.code()
+            }
+            RequestErrorCode::ExpiredAuthToken => 0x12,
+            RequestErrorCode::Unknown(code) => code,
+        }
+    }
+
+    /// Codes shared by every request error message.
+    fn common(code: u64) -> Self {
+        match code {
+            0x0 => RequestErrorCode::InternalError,
+            0x1 => RequestErrorCode::Unauthorized,
+            0x2 => RequestErrorCode::Timeout,
+            0x3 => RequestErrorCode::NotSupported,
+            0x10 => RequestErrorCode::MalformedAuthToken,
+            0x17 => RequestErrorCode::UnknownAuthTokenAlias,
+            0x12 => RequestErrorCode::ExpiredAuthToken,
+            _ => RequestErrorCode::Unknown(code),
+        }
+    }
+
+    /// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-subscribe_error
+    pub fn from_subscribe_code(code: u64) -> Self {
+        match code {
+            0x4 => RequestErrorCode::TrackDoesNotExist,
+            0x5 => RequestErrorCode::InvalidRange,
+            _ => Self::common(code),
+        }
+    }
+
+    /// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-fetch_error
+    pub fn from_fetch_code(code: u64) -> Self {
+        match code {
+            0x4 => RequestErrorCode::TrackDoesNotExist,
+            0x5 => RequestErrorCode::InvalidRange,
+            0x6 => RequestErrorCode::NoObjects,
+            0x7 => RequestErrorCode::InvalidJoiningRequestId,
+            0x8 => RequestErrorCode::UnknownStatusInRange,
+            0x9 => RequestErrorCode::MalformedTrack,
+            _ => Self::common(code),
+        }
+    }
+
+    /// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-publish_error
+    pub fn from_publish_code(code: u64) -> Self {
+        match code {
+            0x4 => RequestErrorCode::Uninterested,
+            // PUBLISH_ERROR does not define the auth token codes.
+            0x10 | 0x12 => RequestErrorCode::Unknown(code),
+            _ => Self::common(code),
+        }
+    }
+
+    /// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-announce_error
+    pub fn from_announce_code(code: u64) -> Self {
+        match code {
+            0x4 => RequestErrorCode::Uninterested,
+            _ => Self::common(code),
+        }
+    }
+
+    /// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-subscribe_announces_error
+    pub fn from_subscribe_announces_code(code: u64) -> Self {
+        match code {
+            0x4 => RequestErrorCode::NamespacePrefixUnknown,
+            0x5 => RequestErrorCode::NamespacePrefixOverlap,
+            _ => Self::common(code),
+        }
+    }
+}
+
+/// The code to reject a request with when handling it failed with `error`.
+impl From<&Error> for RequestErrorCode {
+    fn from(error: &Error) -> Self {
+        match error {
+            Error::SubscriptionFailed { code, .. } | Error::RequestFailed { code, .. } => *code,
+            Error::Auth(AuthError::Unauthorized) => RequestErrorCode::Unauthorized,
+            Error::Auth(AuthError::MalformedToken) => RequestErrorCode::MalformedAuthToken,
+            Error::Auth(AuthError::UnknownAlias(_)) => RequestErrorCode::UnknownAuthTokenAlias,
+            Error::MalformedTrack { .. } => RequestErrorCode::MalformedTrack,
+            _ => RequestErrorCode::InternalError,
+        }
+    }
+}
+
+/// The code as carried on the wire, as `Error::SubscriptionFailed` and
+/// `Error::RequestFailed` codes were before they were typed.
+impl From<RequestErrorCode> for u64 {
+    fn from(code: RequestErrorCode) -> Self {
+        code.code()
+    }
+}
+
+impl From<&SubscribeError> for Error {
+    fn from(msg: &SubscribeError) -> Self {
+        Error::SubscriptionFailed {
+            code: RequestErrorCode::from_subscribe_code(msg.error_code),
+            reason: msg.error_reason.clone(),
+        }
+    }
+}
+
+impl From<&FetchError> for Error {
+    fn from(msg: &FetchError) -> Self {
+        Error::RequestFailed {
+            code: RequestErrorCode::from_fetch_code(msg.error_code),
+            reason: msg.error_reason.clone(),
+        }
+    }
+}
+
+impl From<&PublishError> for Error {
+    fn from(msg: &PublishError) -> Self {
+        Error::RequestFailed {
+            code: RequestErrorCode::from_publish_code(msg.error_code),
+            reason: msg.error_reason.clone(),
+        }
+    }
+}
+
+impl From<&AnnounceError> for Error {
+    fn from(msg: &AnnounceError) -> Self {
+        Error::RequestFailed {
+            code: RequestErrorCode::from_announce_code(msg.error_code),
+            reason: msg.error_reason.clone(),
+        }
+    }
+}
+
+impl From<&SubscribeAnnouncesError> for Error {
+    fn from(msg: &SubscribeAnnouncesError) -> Self {
+        Error::RequestFailed {
+            code: RequestErrorCode::from_subscribe_announces_code(msg.error_code),
+            reason: msg.error_reason.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn termination_codes_round_trip() {
+        for code in (0x0..=0x9).chain(0x10..=0x18) {
+            assert_eq!(TerminationCode::try_from(code).unwrap().code(), code);
+        }
+        assert!(TerminationCode::try_from(0xa).is_err());
+
+        assert_eq!(
+            TerminationCode::from(&Error::TooManyRequests),
+            TerminationCode::TooManyRequests
+        );
+        assert_eq!(
+            TerminationCode::from(&Error::Auth(AuthError::DuplicateAlias(3))).code(),
+            0x14
+        );
+        assert_eq!(
+            TerminationCode::from(&Error::ProtocolViolation {
+                reason: String::new(),
+                context: None
+            }),
+            TerminationCode::ProtocolViolation
+        );
+    }
+
+    #[test]
+    fn auth_error_codes_follow_the_tables() {
+        for (error, code) in [
+            (AuthError::KeyValueFormatting, 0x6),
+            (AuthError::CacheOverflow, 0x13),
+            (AuthError::DuplicateAlias(1), 0x14),
+            (AuthError::UnknownAlias(1), 0x17),
+            (AuthError::MalformedToken, 0x10),
+            (AuthError::Unauthorized, 0x1),
+        ] {
+            assert_eq!(error.code(), code, "{error:?}");
+        }
+        let code = RequestErrorCode::from(&Error::Auth(AuthError::Unauthorized));
+        assert_eq!(u64::from(code), 0x1);
+    }
+
+    #[test]
+    fn request_error_codes_depend_on_message() {
+        assert_eq!(
+            RequestErrorCode::from_subscribe_code(0x4),
+            RequestErrorCode::TrackDoesNotExist
+        );
+        assert_eq!(
+            RequestErrorCode::from_announce_code(0x4),
+            RequestErrorCode::Uninterested
+        );
+        assert_eq!(
+            RequestErrorCode::from_subscribe_announces_code(0x5),
+            RequestErrorCode::NamespacePrefixOverlap
+        );
+        assert_eq!(
+            RequestErrorCode::from_subscribe_code(0x6),
+            RequestErrorCode::Unknown(0x6)
+        );
+        assert_eq!(RequestErrorCode::from_fetch_code(0x6).code(), 0x6);
+        assert_eq!(
+            RequestErrorCode::from_announce_code(RequestErrorCode::AnnounceLimitExceeded.code()),
+            RequestErrorCode::InternalError
+        );
+
+        let err = Error::from(&SubscribeError {
+            request_id: 1,
+            error_code: 0x1,
+            error_reason: "denied".to_string(),
+        });
+        assert!(matches!(
+            err,
+            Error::SubscriptionFailed {
+                code: RequestErrorCode::Unauthorized,
+                ..
+            }
+        ));
+        assert_eq!(RequestErrorCode::from(&err), RequestErrorCode::Unauthorized);
+    }
+}
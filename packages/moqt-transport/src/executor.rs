@@ -0,0 +1,156 @@
+use std::fmt;
+use std::sync::Arc;
+
+use tokio::sync::oneshot;
+
+/// A unit of work handed to an [`Executor`].
+pub type Job = Box<dyn FnOnce() + Send>;
+
+/// Runs handler callbacks on behalf of a session, e.g. a thread pool of the
+/// application.
+pub trait Executor: Send + Sync {
+    /// Run `job`, now or later, on any thread. Dropping it without running
+    /// it is treated as the callback failing.
+    fn execute(&self, job: Job);
+}
+
+impl<F> Executor for F
+where
+    F: Fn(Job) + Send + Sync,
+{
+    fn execute(&self, job: Job) {
+        self(job)
+    }
+}
+
+/// Where a session runs handler callbacks such as its
+/// [`Authorizer`](crate::auth::Authorizer), so that slow callbacks need not
+/// stall the processing of control messages.
+#[derive(Clone, Default)]
+pub enum CallbackExecutor {
+    /// On the task processing the control message.
+    #[default]
+    Inline,
+    /// On a dedicated task of the Tokio blocking pool.
+    Blocking,
+    /// On an executor provided by the application.
+    Custom(Arc<dyn Executor>),
+}
+
+impl fmt::Debug for CallbackExecutor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallbackExecutor::Inline => f.write_str("Inline"),
+            CallbackExecutor::Blocking => f.write_str("Blocking"),
+            CallbackExecutor::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+impl CallbackExecutor {
+    pub fn custom(executor: impl Executor + 'static) -> Self {
+        CallbackExecutor::Custom(Arc::new(executor))
+    }
+
+    pub fn is_inline(&self) -> bool {
+        matches!(self, CallbackExecutor::Inline)
+    }
+
+    /// Run `callback` and wait for its result. Returns `None` if a custom
+    /// executor dropped the callback without running it.
+    ///
+    /// A panic of the callback is propagated to the caller.
+    pub async fn run<R, F>(&self, callback: F) -> Option<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        match self {
+            CallbackExecutor::Inline => Some(callback()),
+            CallbackExecutor::Blocking => match tokio::task::spawn_blocking(callback).await {
+                Ok(result) => Some(result),
+                Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+                Err(_) => None,
+            },
+            CallbackExecutor::Custom(executor) => {
+                let (tx, rx) = oneshot::channel();
+                executor.execute(Box::new(move || {
+                    let _ = tx.send(callback());
+                }));
+                rx.await.ok()
+            }
+        }
+    }
+
+    /// Run `callback` without waiting for it to complete. A
+    /// [`Blocking`](CallbackExecutor::Blocking) executor must be used from
+    /// within a Tokio runtime.
+    pub fn dispatch<F>(&self, callback: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        match self {
+            CallbackExecutor::Inline => callback(),
+            CallbackExecutor::Blocking => {
+                tokio::task::spawn_blocking(callback);
+            }
+            CallbackExecutor::Custom(executor) => executor.execute(Box::new(callback)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn callbacks_run_where_configured() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let caller = thread::current().id();
+            let inline = CallbackExecutor::Inline;
+            assert_eq!(inline.run(|| thread::current().id()).await, Some(caller));
+
+            let blocking = CallbackExecutor::Blocking;
+            assert_ne!(blocking.run(|| thread::current().id()).await, Some(caller));
+
+            let custom = CallbackExecutor::custom(|job: Job| {
+                thread::spawn(job);
+            });
+            assert_ne!(custom.run(|| thread::current().id()).await, Some(caller));
+        });
+    }
+
+    #[test]
+    fn dropped_callback_has_no_result() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let dropped = CallbackExecutor::custom(|_: Job| {});
+            assert_eq!(dropped.run(|| 1).await, None);
+        });
+    }
+
+    #[test]
+    fn dispatch_queues_on_custom_executor() {
+        let queue = Arc::new(Mutex::new(Vec::<Job>::new()));
+        let executor = {
+            let queue = queue.clone();
+            CallbackExecutor::custom(move |job| queue.lock().unwrap().push(job))
+        };
+        let ran = Arc::new(Mutex::new(false));
+        let flag = ran.clone();
+        executor.dispatch(move || *flag.lock().unwrap() = true);
+        assert!(!*ran.lock().unwrap());
+        for job in queue.lock().unwrap().drain(..) {
+            job();
+        }
+        assert!(*ran.lock().unwrap());
+    }
+}
@@ -0,0 +1,392 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use std::io::{Error as IoError, ErrorKind};
+
+use bytes::BytesMut;
+use tokio::io::{AsyncRead, AsyncWriteExt};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    data::{FetchHeader, FetchObject, StreamResetCode, check_payload_size},
+    error::Error,
+    message::FetchCancel,
+    stream_reader::StreamReader,
+    transport::UniStream,
+};
+
+/// How serving a FETCH ended.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FetchOutcome {
+    /// Every object from the source was written and the stream finished.
+    Completed,
+    /// FETCH_CANCEL was received and the stream was reset.
+    Cancelled,
+}
+
+/// Publisher side of FETCH: writes objects from a source onto the FETCH
+/// data stream and honors FETCH_CANCEL.
+pub struct FetchResponder {
+    active: Mutex<HashMap<u64, CancellationToken>>,
+    max_object_payload_size: usize,
+}
+
+impl Default for FetchResponder {
+    fn default() -> Self {
+        Self {
+            active: Mutex::default(),
+            max_object_payload_size: usize::MAX,
+        }
+    }
+}
+
+impl FetchResponder {
+    /// Bound the payload size of the objects served, typically to
+    /// [`Session::max_object_payload_size`](crate::session::Session::max_object_payload_size).
+    /// Serving a larger object fails with [`Error::ObjectTooLarge`] and
+    /// resets the stream.
+    pub fn with_max_object_payload_size(mut self, max_object_payload_size: usize) -> Self {
+        self.max_object_payload_size = max_object_payload_size;
+        self
+    }
+
+    /// Serve the FETCH identified by `request_id` on `stream`, pulling
+    /// objects from `source` (typically a cache iterator).
+    ///
+    /// When the fetch is cancelled the source is dropped without being
+    /// polled again, so any cache resources it holds are released, and the
+    /// stream is reset with [`StreamResetCode::Cancelled`] even if an object
+    /// was only partially written.
+    pub async fn serve<S, I>(
+        &self,
+        request_id: u64,
+        stream: &mut S,
+        source: I,
+    ) -> Result<FetchOutcome, Error>
+    where
+        S: UniStream,
+        I: IntoIterator<Item = FetchObject>,
+    {
+        let token = CancellationToken::new();
+        {
+            let mut active = self.active.lock().unwrap();
+            if active.contains_key(&request_id) {
+                return Err(Error::ProtocolViolation {
+                    reason: "duplicate FETCH request id".into(),
+                    context: None,
+                });
+            }
+            active.insert(request_id, token.clone());
+        }
+
+        let result = self.write_all(request_id, stream, source, &token).await;
+        self.active.lock().unwrap().remove(&request_id);
+
+        match result {
+            Ok(FetchOutcome::Completed) => {
+                stream.shutdown().await?;
+                Ok(FetchOutcome::Completed)
+            }
+            Ok(FetchOutcome::Cancelled) => {
+                stream.reset(StreamResetCode::Cancelled as u64);
+                Ok(FetchOutcome::Cancelled)
+            }
+            Err(e) => {
+                stream.reset(StreamResetCode::InternalError as u64);
+                Err(e)
+            }
+        }
+    }
+
+    async fn write_all<S, I>(
+        &self,
+        request_id: u64,
+        stream: &mut S,
+        source: I,
+        token: &CancellationToken,
+    ) -> Result<FetchOutcome, Error>
+    where
+        S: UniStream,
+        I: IntoIterator<Item = FetchObject>,
+    {
+        let mut buf = BytesMut::new();
+        FetchHeader { request_id }.encode(&mut buf)?;
+        if token
+            .run_until_cancelled(stream.write_all(&buf))
+            .await
+            .transpose()?
+            .is_none()
+        {
+            return Ok(FetchOutcome::Cancelled);
+        }
+
+        for object in source {
+            if token.is_cancelled() {
+                return Ok(FetchOutcome::Cancelled);
+            }
+            check_payload_size(object.payload.len(), self.max_object_payload_size)?;
+            buf.clear();
+            object.encode(&mut buf)?;
+            if token
+                .run_until_cancelled(stream.write_all(&buf))
+                .await
+                .transpose()?
+                .is_none()
+            {
+                return Ok(FetchOutcome::Cancelled);
+            }
+        }
+        Ok(FetchOutcome::Completed)
+    }
+
+    /// Handle FETCH_CANCEL. Returns `false` when no FETCH with that request
+    /// id is being served, which is not an error since the fetch may have
+    /// completed already.
+    pub fn cancel(&self, msg: &FetchCancel) -> bool {
+        match self.active.lock().unwrap().remove(&msg.request_id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Number of fetches currently being served.
+    pub fn active(&self) -> usize {
+        self.active.lock().unwrap().len()
+    }
+}
+
+/// Subscriber side of FETCH: reads the objects of one FETCH data stream
+/// in the order they were sent.
+pub struct FetchReader<R> {
+    reader: StreamReader<R>,
+    header: Option<FetchHeader>,
+    max_object_payload_size: usize,
+}
+
+impl<R: AsyncRead + Unpin> FetchReader<R> {
+    pub fn new(stream: R) -> Self {
+        Self::from_reader(StreamReader::new(stream))
+    }
+
+    pub(crate) fn from_reader(reader: StreamReader<R>) -> Self {
+        Self {
+            reader,
+            header: None,
+            max_object_payload_size: usize::MAX,
+        }
+    }
+
+    /// Bound the bytes buffered while waiting for a complete object.
+    pub fn with_max_buffer_size(mut self, max_buffer_size: usize) -> Self {
+        self.reader.set_max_buffer_size(max_buffer_size);
+        self
+    }
+
+    /// Bound the payload size of the objects read. A larger object fails
+    /// with [`Error::ObjectTooLarge`] as soon as its length is read, leaving
+    /// the stream to be reset through [`get_mut`](Self::get_mut).
+    pub fn with_max_object_payload_size(mut self, max_object_payload_size: usize) -> Self {
+        self.max_object_payload_size = max_object_payload_size;
+        self
+    }
+
+    /// The stream being read.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.reader.get_mut()
+    }
+
+    /// The FETCH_HEADER opening the stream, read if not done yet.
+    pub async fn header(&mut self) -> Result<&FetchHeader, Error> {
+        if self.header.is_none() {
+            let header = self
+                .reader
+                .read("fetch header", FetchHeader::decode)
+                .await?
+                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "fetch header"))?;
+            self.header = Some(header);
+        }
+        Ok(self.header.as_ref().expect("header read above"))
+    }
+
+    /// Request ID of the FETCH this stream answers.
+    pub async fn request_id(&mut self) -> Result<u64, Error> {
+        Ok(self.header().await?.request_id)
+    }
+
+    /// The next object of the fetch, or `None` once the stream ended.
+    pub async fn next(&mut self) -> Result<Option<FetchObject>, Error> {
+        self.header().await?;
+        let max_payload_size = self.max_object_payload_size;
+        self.reader
+            .read("fetch object", |buf| {
+                FetchObject::decode_bounded(buf, max_payload_size)
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockTransport;
+    use crate::transport::Transport;
+    use bytes::Bytes;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use tokio::io::AsyncReadExt;
+
+    fn object(object_id: u64) -> FetchObject {
+        FetchObject {
+            group_id: 0,
+            subgroup_id: 0,
+            object_id,
+            publisher_priority: 0,
+            extension_headers: Bytes::new(),
+            object_status: None,
+            payload: Bytes::from_static(b"payload"),
+        }
+    }
+
+    /// Cache iterator stand-in that cancels the fetch after yielding a few
+    /// objects and records whether it was released.
+    struct CancellingSource {
+        responder: Arc<FetchResponder>,
+        next: u64,
+        pulled: Arc<AtomicU64>,
+        dropped: Arc<AtomicBool>,
+    }
+
+    impl Iterator for CancellingSource {
+        type Item = FetchObject;
+
+        fn next(&mut self) -> Option<FetchObject> {
+            if self.next == 3 {
+                self.responder.cancel(&FetchCancel { request_id: 1 });
+            }
+            self.pulled.fetch_add(1, Ordering::SeqCst);
+            self.next += 1;
+            Some(object(self.next - 1))
+        }
+    }
+
+    impl Drop for CancellingSource {
+        fn drop(&mut self) {
+            self.dropped.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn cancel_mid_range_resets_stream() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (mut a, mut b) = MockTransport::pair();
+            let mut send = a.open_uni_stream().await.unwrap();
+            let mut recv = b.accept_uni_stream().await.unwrap();
+
+            let responder = Arc::new(FetchResponder::default());
+            let pulled = Arc::new(AtomicU64::new(0));
+            let dropped = Arc::new(AtomicBool::new(false));
+            let source = CancellingSource {
+                responder: responder.clone(),
+                next: 0,
+                pulled: pulled.clone(),
+                dropped: dropped.clone(),
+            };
+
+            let outcome = responder.serve(1, &mut send, source).await.unwrap();
+            assert_eq!(outcome, FetchOutcome::Cancelled);
+            assert_eq!(send.reset_code(), Some(StreamResetCode::Cancelled as u64));
+            assert_eq!(pulled.load(Ordering::SeqCst), 4);
+            assert!(dropped.load(Ordering::SeqCst));
+            assert_eq!(responder.active(), 0);
+
+            let mut data = Vec::new();
+            recv.read_to_end(&mut data).await.unwrap();
+            let mut buf = BytesMut::from(&data[..]);
+            assert_eq!(FetchHeader::decode(&mut buf).unwrap().request_id, 1);
+            let mut received = Vec::new();
+            while !buf.is_empty() {
+                received.push(FetchObject::decode(&mut buf).unwrap().object_id);
+            }
+            assert_eq!(received, vec![0, 1, 2]);
+        });
+    }
+
+    #[test]
+    fn oversized_fetch_object_resets_stream() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (mut a, mut b) = MockTransport::pair();
+            let mut send = a.open_uni_stream().await.unwrap();
+            let recv = b.accept_uni_stream().await.unwrap();
+
+            let responder = FetchResponder::default().with_max_object_payload_size(4);
+            let served = responder.serve(3, &mut send, (0..2).map(object)).await;
+            assert!(matches!(
+                served,
+                Err(Error::ObjectTooLarge { size: 7, max: 4 })
+            ));
+            assert_eq!(
+                send.reset_code(),
+                Some(StreamResetCode::InternalError as u64)
+            );
+            assert_eq!(responder.active(), 0);
+
+            let mut reader = FetchReader::new(recv);
+            assert_eq!(reader.request_id().await.unwrap(), 3);
+            assert!(reader.next().await.unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn fetch_reader_bounds_payload_size() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let mut buf = BytesMut::new();
+            FetchHeader { request_id: 1 }.encode(&mut buf).unwrap();
+            object(0).encode(&mut buf).unwrap();
+
+            let mut reader = FetchReader::new(&buf[..]).with_max_object_payload_size(4);
+            assert!(matches!(
+                reader.next().await,
+                Err(Error::ObjectTooLarge { size: 7, max: 4 })
+            ));
+            let mut reader = FetchReader::new(&buf[..]).with_max_object_payload_size(7);
+            assert_eq!(reader.next().await.unwrap().unwrap(), object(0));
+        });
+    }
+
+    #[test]
+    fn completed_fetch_finishes_stream() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (mut a, mut b) = MockTransport::pair();
+            let mut send = a.open_uni_stream().await.unwrap();
+            let mut recv = b.accept_uni_stream().await.unwrap();
+
+            let responder = FetchResponder::default();
+            let outcome = responder
+                .serve(2, &mut send, (0..2).map(object))
+                .await
+                .unwrap();
+            assert_eq!(outcome, FetchOutcome::Completed);
+            assert_eq!(send.reset_code(), None);
+            assert!(!responder.cancel(&FetchCancel { request_id: 2 }));
+
+            let mut data = Vec::new();
+            recv.read_to_end(&mut data).await.unwrap();
+            assert!(!data.is_empty());
+        });
+    }
+}
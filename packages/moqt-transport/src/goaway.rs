@@ -0,0 +1,176 @@
+use crate::error::Error;
+
+/// Schemes a New Session URI may use by default.
+pub const DEFAULT_URI_SCHEMES: [&str; 2] = ["https", "moqt"];
+
+/// Which New Session URIs a GOAWAY may carry. Checked on GOAWAYs sent and
+/// received, so that a peer cannot make the migration dial an arbitrary
+/// target.
+///
+/// A URI is accepted if it is an absolute URI with an authority, such as
+/// `https://relay.example:4443/moq`, whose scheme is allowed, and has no
+/// fragment. By default the schemes `https` and `moqt` are allowed.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-goaway
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoawayUriPolicy {
+    schemes: Vec<String>,
+}
+
+impl Default for GoawayUriPolicy {
+    /// Allow [`DEFAULT_URI_SCHEMES`].
+    fn default() -> Self {
+        Self::new(DEFAULT_URI_SCHEMES)
+    }
+}
+
+impl GoawayUriPolicy {
+    /// Allow the schemes `schemes`, compared case-insensitively.
+    pub fn new<I, S>(schemes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            schemes: schemes
+                .into_iter()
+                .map(|s| s.into().to_ascii_lowercase())
+                .collect(),
+        }
+    }
+
+    /// Also allow `scheme`.
+    pub fn with_scheme(mut self, scheme: impl Into<String>) -> Self {
+        let scheme = scheme.into().to_ascii_lowercase();
+        if !self.schemes.contains(&scheme) {
+            self.schemes.push(scheme);
+        }
+        self
+    }
+
+    pub fn schemes(&self) -> &[String] {
+        &self.schemes
+    }
+
+    /// Check `uri` against the policy, failing with [`Error::InvalidUri`].
+    pub fn validate(&self, uri: &str) -> Result<(), Error> {
+        let invalid = |reason: &str| Error::InvalidUri {
+            uri: uri.to_string(),
+            reason: reason.to_string(),
+        };
+        if let Some(c) = uri.chars().find(|c| !c.is_ascii_graphic()) {
+            return Err(invalid(&format!("invalid character {c:?}")));
+        }
+        if uri.contains('#') {
+            return Err(invalid("fragments are not allowed"));
+        }
+        if !percent_encoding_is_valid(uri) {
+            return Err(invalid("invalid percent-encoding"));
+        }
+
+        let Some((scheme, rest)) = uri.split_once(':') else {
+            return Err(invalid("missing scheme"));
+        };
+        if !is_scheme(scheme) {
+            return Err(invalid("invalid scheme"));
+        }
+        if !self
+            .schemes
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(scheme))
+        {
+            return Err(invalid(&format!("scheme {scheme:?} is not allowed")));
+        }
+
+        let Some(rest) = rest.strip_prefix("//") else {
+            return Err(invalid("missing authority"));
+        };
+        let authority = rest.split(['/', '?']).next().unwrap_or_default();
+        let host_port = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+        let (host, port) = match host_port.strip_prefix('[') {
+            // IP literal
+            Some(literal) => match literal.split_once(']') {
+                Some((host, "")) => (host, None),
+                Some((host, port)) => match port.strip_prefix(':') {
+                    Some(port) => (host, Some(port)),
+                    None => return Err(invalid("invalid authority")),
+                },
+                None => return Err(invalid("unterminated IP literal")),
+            },
+            None => match host_port.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (host_port, None),
+            },
+        };
+        if host.is_empty() {
+            return Err(invalid("missing host"));
+        }
+        if port.is_some_and(|p| p.is_empty() || !p.bytes().all(|b| b.is_ascii_digit())) {
+            return Err(invalid("invalid port"));
+        }
+        Ok(())
+    }
+}
+
+/// scheme = ALPHA *( ALPHA / DIGIT / "+" / "-" / "." ), RFC 3986.
+fn is_scheme(scheme: &str) -> bool {
+    let mut bytes = scheme.bytes();
+    bytes.next().is_some_and(|b| b.is_ascii_alphabetic())
+        && bytes.all(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'-' | b'.'))
+}
+
+/// Whether every `%` is followed by two hex digits.
+fn percent_encoding_is_valid(uri: &str) -> bool {
+    let bytes = uri.as_bytes();
+    bytes.iter().enumerate().all(|(i, b)| {
+        *b != b'%'
+            || bytes
+                .get(i + 1..i + 3)
+                .is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_policy() {
+        let policy = GoawayUriPolicy::default();
+        for uri in [
+            "https://relay.example/moq",
+            "HTTPS://relay.example:4443/moq?session=a%2Fb",
+            "moqt://user@[2001:db8::1]:443",
+        ] {
+            policy.validate(uri).unwrap();
+        }
+        for (uri, reason) in [
+            ("relay.example/moq", "missing scheme"),
+            ("1https://relay.example", "invalid scheme"),
+            ("http://relay.example", "scheme \"http\" is not allowed"),
+            ("https:relay.example", "missing authority"),
+            ("https:///moq", "missing host"),
+            ("https://relay.example:port", "invalid port"),
+            ("https://[2001:db8::1/moq", "unterminated IP literal"),
+            (
+                "https://relay.example/moq#frag",
+                "fragments are not allowed",
+            ),
+            ("https://relay.example/m oq", "invalid character ' '"),
+            ("https://relay.example/%zz", "invalid percent-encoding"),
+        ] {
+            match policy.validate(uri) {
+                Err(Error::InvalidUri { reason: r, .. }) => assert_eq!(r, reason, "{uri}"),
+                r => panic!("unexpected result for {uri}: {r:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn custom_schemes() {
+        let policy = GoawayUriPolicy::new(["moqt"]).with_scheme("WebTransport");
+        assert_eq!(policy.schemes(), ["moqt", "webtransport"]);
+        policy.validate("webtransport://relay.example").unwrap();
+        assert!(policy.validate("https://relay.example").is_err());
+    }
+}
@@ -0,0 +1,464 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures_core::Stream;
+
+use crate::{
+    data::{SubgroupHeader, SubgroupId},
+    error::Error,
+    model::ObjectStatus,
+    retention::RetentionBuffer,
+    track::{Object, ObjectMetadata, ObjectStream, TrackAlias},
+};
+
+/// Every object of a group, ordered by object ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    pub group_id: u64,
+    pub objects: Vec<Object>,
+}
+
+/// Tracks which groups of a track have been received completely.
+///
+/// A group is complete once an End of Group or End of Track status for it
+/// arrives, or once an object of a group at least the reorder window newer
+/// arrives. The window defaults to one group, so an object of the next
+/// group completes the current one. Objects are kept per group, so with a
+/// wider window objects of several groups may interleave. Objects of groups
+/// already completed and objects that do not exist are discarded.
+pub struct GroupAssembler {
+    pending: BTreeMap<u64, BTreeMap<u64, Object>>,
+    /// Groups completed at or above `next_group`.
+    completed: BTreeSet<u64>,
+    /// Groups below this ID have been completed.
+    next_group: u64,
+    window: u64,
+}
+
+impl Default for GroupAssembler {
+    fn default() -> Self {
+        Self {
+            pending: BTreeMap::new(),
+            completed: BTreeSet::new(),
+            next_group: 0,
+            window: 1,
+        }
+    }
+}
+
+impl GroupAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep a group open until an object of a group `groups` newer arrives,
+    /// for publishers sending groups on concurrent streams. At least one.
+    pub fn with_reorder_window(mut self, groups: u64) -> Self {
+        self.window = groups.max(1);
+        self
+    }
+
+    /// Add a received object. Returns the groups it completed, oldest first.
+    pub fn push(&mut self, object: Object) -> Vec<Group> {
+        let group_id = object.metadata.group_id;
+        let mut complete = Vec::new();
+        if group_id < self.next_group || self.completed.contains(&group_id) {
+            return complete;
+        }
+        self.complete_below(group_id.saturating_sub(self.window - 1), &mut complete);
+
+        match object.status {
+            ObjectStatus::Normal => {
+                self.pending
+                    .entry(group_id)
+                    .or_default()
+                    .entry(object.metadata.object_id)
+                    .or_insert(object);
+            }
+            ObjectStatus::DoesNotExist => {}
+            ObjectStatus::EndOfGroup => complete.push(self.finish(group_id)),
+            ObjectStatus::EndOfTrack => {
+                self.complete_below(group_id, &mut complete);
+                complete.push(self.finish(group_id));
+            }
+        }
+        complete
+    }
+
+    /// ID of the newest group being received, if any.
+    pub fn pending_group(&self) -> Option<u64> {
+        self.pending.last_key_value().map(|(g, _)| *g)
+    }
+
+    /// Complete every group below `floor`.
+    fn complete_below(&mut self, floor: u64, complete: &mut Vec<Group>) {
+        while let Some(entry) = self.pending.first_entry() {
+            if *entry.key() >= floor {
+                break;
+            }
+            let (group_id, objects) = entry.remove_entry();
+            complete.push(Group {
+                group_id,
+                objects: objects.into_values().collect(),
+            });
+        }
+        if floor > self.next_group {
+            self.next_group = floor;
+            self.completed = self.completed.split_off(&floor);
+            self.advance();
+        }
+    }
+
+    fn finish(&mut self, group_id: u64) -> Group {
+        let objects = self.pending.remove(&group_id).unwrap_or_default();
+        self.completed.insert(group_id);
+        self.advance();
+        Group {
+            group_id,
+            objects: objects.into_values().collect(),
+        }
+    }
+
+    fn advance(&mut self) {
+        while self.completed.remove(&self.next_group) {
+            self.next_group += 1;
+        }
+    }
+}
+
+/// View of an [`ObjectStream`] yielding complete groups, e.g. for decoders
+/// that need whole groups of pictures. A group still pending when the
+/// object stream ends is not yielded.
+pub struct GroupStream {
+    objects: ObjectStream,
+    assembler: GroupAssembler,
+    ready: VecDeque<Group>,
+}
+
+impl GroupStream {
+    pub fn new(objects: ObjectStream) -> Self {
+        Self {
+            objects,
+            assembler: GroupAssembler::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// See [`GroupAssembler::with_reorder_window`].
+    pub fn with_reorder_window(mut self, groups: u64) -> Self {
+        self.assembler = self.assembler.with_reorder_window(groups);
+        self
+    }
+
+    /// Receive the next complete group.
+    pub async fn recv(&mut self) -> Option<Result<Group, Error>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl Stream for GroupStream {
+    type Item = Result<Group, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(group) = self.ready.pop_front() {
+                return Poll::Ready(Some(Ok(group)));
+            }
+            match Pin::new(&mut self.objects).poll_next(cx) {
+                Poll::Ready(Some(Ok(object))) => {
+                    let groups = self.assembler.push(object);
+                    self.ready.extend(groups);
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// A group opened with
+/// [`TrackPublisher::begin_group`](crate::track::TrackPublisher::begin_group).
+///
+/// Object IDs are allocated in sequence across all subgroups of the group,
+/// so handles for several subgroups can be used concurrently.
+#[derive(Debug, Clone)]
+pub struct GroupHandle {
+    track_alias: TrackAlias,
+    group_id: u64,
+    publisher_priority: u8,
+    next_object: Arc<AtomicU64>,
+    retention: RetentionBuffer,
+}
+
+impl GroupHandle {
+    pub(crate) fn new(
+        track_alias: TrackAlias,
+        group_id: u64,
+        publisher_priority: u8,
+        retention: RetentionBuffer,
+    ) -> Self {
+        Self {
+            track_alias,
+            group_id,
+            publisher_priority,
+            next_object: Arc::new(AtomicU64::new(0)),
+            retention,
+        }
+    }
+
+    pub fn group_id(&self) -> u64 {
+        self.group_id
+    }
+
+    pub fn subgroup(&self, subgroup_id: u64) -> SubgroupHandle {
+        SubgroupHandle {
+            group: self.clone(),
+            subgroup_id,
+        }
+    }
+
+    /// The End of Group status object, whose Object ID is one greater than
+    /// the largest object produced in the group.
+    pub(crate) fn end_of_group(&self) -> Object {
+        self.object(0, ObjectStatus::EndOfGroup, Bytes::new())
+    }
+
+    /// A Does Not Exist status object for Object `object_id` of the group,
+    /// e.g. one a subscriber requested that the encoder dropped. On a
+    /// datagram track it is sent as OBJECT_DATAGRAM_STATUS. Objects
+    /// produced afterwards take Object IDs greater than `object_id`.
+    pub fn does_not_exist(&self, object_id: u64) -> Object {
+        self.next_object
+            .fetch_max(object_id.saturating_add(1), Ordering::SeqCst);
+        self.object_with_id(0, object_id, ObjectStatus::DoesNotExist, Bytes::new())
+    }
+
+    fn object(&self, subgroup_id: u64, status: ObjectStatus, payload: Bytes) -> Object {
+        let object_id = self.next_object.fetch_add(1, Ordering::SeqCst);
+        self.object_with_id(subgroup_id, object_id, status, payload)
+    }
+
+    fn object_with_id(
+        &self,
+        subgroup_id: u64,
+        object_id: u64,
+        status: ObjectStatus,
+        payload: Bytes,
+    ) -> Object {
+        let object = Object {
+            metadata: ObjectMetadata {
+                track_alias: self.track_alias,
+                group_id: self.group_id,
+                subgroup_id,
+                object_id,
+                publisher_priority: self.publisher_priority,
+            },
+            status,
+            extension_headers: Bytes::new(),
+            payload,
+        };
+        self.retention.record(&object);
+        object
+    }
+}
+
+/// A subgroup of a [`GroupHandle`], typically written on its own stream.
+#[derive(Debug, Clone)]
+pub struct SubgroupHandle {
+    group: GroupHandle,
+    subgroup_id: u64,
+}
+
+impl SubgroupHandle {
+    pub fn group_id(&self) -> u64 {
+        self.group.group_id
+    }
+
+    pub fn subgroup_id(&self) -> u64 {
+        self.subgroup_id
+    }
+
+    /// SUBGROUP_HEADER opening the subgroup's stream.
+    pub fn header(&self, end_of_group: bool) -> SubgroupHeader {
+        SubgroupHeader {
+            track_alias: self.group.track_alias,
+            group_id: self.group.group_id,
+            subgroup_id: match self.subgroup_id {
+                0 => SubgroupId::Zero,
+                id => SubgroupId::Explicit(id),
+            },
+            publisher_priority: self.group.publisher_priority,
+            extensions_present: false,
+            end_of_group,
+        }
+    }
+
+    /// Next object of the subgroup, with the next Object ID of the group.
+    pub fn object(&self, payload: Bytes) -> Object {
+        self.group
+            .object(self.subgroup_id, ObjectStatus::Normal, payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::ObjectBuilder;
+    use crate::track::TrackPublisher;
+
+    fn object(group_id: u64, object_id: u64, status: ObjectStatus) -> Object {
+        ObjectBuilder::new(group_id, object_id)
+            .with_status(status)
+            .build()
+    }
+
+    fn ids(group: &Group) -> Vec<u64> {
+        group.objects.iter().map(|o| o.metadata.object_id).collect()
+    }
+
+    #[test]
+    fn end_of_group_and_next_group_complete() {
+        let mut assembler = GroupAssembler::new();
+        assert!(
+            assembler
+                .push(object(0, 1, ObjectStatus::Normal))
+                .is_empty()
+        );
+        assert!(
+            assembler
+                .push(object(0, 0, ObjectStatus::Normal))
+                .is_empty()
+        );
+        let groups = assembler.push(object(0, 2, ObjectStatus::EndOfGroup));
+        assert_eq!(groups.len(), 1);
+        assert_eq!(ids(&groups[0]), vec![0, 1]);
+        assert_eq!(assembler.pending_group(), None);
+
+        // Late object of a completed group is discarded.
+        assert!(
+            assembler
+                .push(object(0, 1, ObjectStatus::Normal))
+                .is_empty()
+        );
+
+        assembler.push(object(1, 0, ObjectStatus::Normal));
+        assembler.push(object(1, 1, ObjectStatus::DoesNotExist));
+        let groups = assembler.push(object(2, 0, ObjectStatus::Normal));
+        assert_eq!(groups[0].group_id, 1);
+        assert_eq!(ids(&groups[0]), vec![0]);
+        assert_eq!(assembler.pending_group(), Some(2));
+
+        let groups = assembler.push(object(3, 0, ObjectStatus::EndOfTrack));
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[1].group_id, 3);
+        assert!(groups[1].objects.is_empty());
+    }
+
+    #[test]
+    fn late_objects_stay_in_their_group() {
+        let mut assembler = GroupAssembler::new();
+        assembler.push(object(0, 0, ObjectStatus::Normal));
+        assembler.push(object(2, 0, ObjectStatus::Normal));
+        // Group 1 was skipped and group 0 completed by group 2, so neither
+        // a late object nor a late End of Group lands in group 2.
+        assert!(
+            assembler
+                .push(object(1, 0, ObjectStatus::Normal))
+                .is_empty()
+        );
+        assert!(
+            assembler
+                .push(object(1, 1, ObjectStatus::EndOfGroup))
+                .is_empty()
+        );
+        let groups = assembler.push(object(2, 1, ObjectStatus::EndOfGroup));
+        assert_eq!((groups[0].group_id, ids(&groups[0])), (2, vec![0]));
+
+        // With a window of two groups, groups sent on concurrent streams
+        // interleave.
+        let mut assembler = GroupAssembler::new().with_reorder_window(2);
+        for (g, o) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            assert!(
+                assembler
+                    .push(object(g, o, ObjectStatus::Normal))
+                    .is_empty()
+            );
+        }
+        let groups = assembler.push(object(0, 2, ObjectStatus::EndOfGroup));
+        assert_eq!((groups[0].group_id, ids(&groups[0])), (0, vec![0, 1]));
+        assert!(
+            assembler
+                .push(object(2, 0, ObjectStatus::Normal))
+                .is_empty()
+        );
+        let groups = assembler.push(object(3, 0, ObjectStatus::Normal));
+        assert_eq!((groups[0].group_id, ids(&groups[0])), (1, vec![0, 1]));
+        assert_eq!(assembler.pending_group(), Some(3));
+    }
+
+    #[test]
+    fn stream_yields_complete_groups_only() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (tx, objects) = ObjectStream::channel(8);
+            for (g, o) in [(0, 0), (0, 1), (1, 0), (2, 0)] {
+                tx.send(Ok(object(g, o, ObjectStatus::Normal)))
+                    .await
+                    .unwrap();
+            }
+            drop(tx);
+
+            let mut groups = GroupStream::new(objects);
+            let first = groups.recv().await.unwrap().unwrap();
+            assert_eq!((first.group_id, ids(&first)), (0, vec![0, 1]));
+            assert_eq!(groups.recv().await.unwrap().unwrap().group_id, 1);
+            assert!(groups.recv().await.is_none());
+        });
+    }
+
+    #[test]
+    fn groups_rotate_on_keyframes() {
+        let mut publisher = TrackPublisher::new(4);
+        let mut sent = Vec::new();
+        // Frames before the first keyframe cannot be decoded on their own.
+        for keyframe in [false, false, true, false, false, true, false] {
+            sent.extend(publisher.push_frame(keyframe, Bytes::from_static(b"frame")));
+        }
+        let locations: Vec<_> = sent
+            .iter()
+            .map(|o| (o.metadata.group_id, o.metadata.object_id, o.status))
+            .collect();
+        assert_eq!(
+            locations,
+            vec![
+                (0, 0, ObjectStatus::Normal),
+                (0, 1, ObjectStatus::Normal),
+                (0, 2, ObjectStatus::Normal),
+                (0, 3, ObjectStatus::EndOfGroup),
+                (1, 0, ObjectStatus::Normal),
+                (1, 1, ObjectStatus::Normal),
+            ]
+        );
+        assert_eq!(publisher.current_group(), Some(1));
+
+        // Group IDs keep increasing and object IDs are shared by subgroups.
+        let group = publisher.begin_group();
+        assert_eq!(group.group_id(), 2);
+        let (base, enhancement) = (group.subgroup(0), group.subgroup(1));
+        assert_eq!(base.object(Bytes::new()).metadata.object_id, 0);
+        assert_eq!(enhancement.object(Bytes::new()).metadata.object_id, 1);
+        assert_eq!(
+            enhancement.header(false).subgroup_id,
+            SubgroupId::Explicit(1)
+        );
+        assert_eq!(publisher.end_group().unwrap().metadata.object_id, 2);
+        assert!(publisher.end_group().is_none());
+    }
+}
@@ -0,0 +1,135 @@
+use std::io::{Error as IoError, ErrorKind};
+
+use bytes::Bytes;
+use tokio::io::AsyncRead;
+
+use crate::{
+    data::{StreamType, SubgroupHeader},
+    error::{Error, Offending},
+    fetch::FetchReader,
+    stream_reader::StreamReader,
+    subgroup::SubgroupReader,
+};
+
+/// An incoming unidirectional stream, told apart by the stream type it
+/// starts with.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-data-streams-and-datagrams
+pub enum IncomingStream<R> {
+    Subgroup(SubgroupReader<R>),
+    Fetch(FetchReader<R>),
+}
+
+impl<R: AsyncRead + Unpin> IncomingStream<R> {
+    /// Read the stream type of `stream` and hand it to the matching reader,
+    /// which reads the header that follows. At most `max_buffer_size` bytes
+    /// of the stream are buffered at a time.
+    ///
+    /// An unknown stream type is a protocol violation, as the session must
+    /// be closed on receiving one.
+    pub async fn accept(stream: R, max_buffer_size: usize) -> Result<Self, Error> {
+        let mut reader = StreamReader::new(stream);
+        reader.set_max_buffer_size(max_buffer_size);
+        let (stream_type, raw) = reader
+            .peek("stream type", |buf: &mut Bytes| {
+                let (stream_type, len) = crate::codec::VarInt
+                    .peek(buf)
+                    .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "stream type"))?;
+                Ok((stream_type, buf.slice(..len)))
+            })
+            .await?
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "stream type"))?;
+
+        if stream_type == StreamType::FetchHeader as u64 {
+            Ok(Self::Fetch(FetchReader::from_reader(reader)))
+        } else if SubgroupHeader::is_subgroup_type(stream_type) {
+            Ok(Self::Subgroup(SubgroupReader::from_reader(reader)))
+        } else {
+            let error = Error::ProtocolViolation {
+                reason: format!("unknown stream type {stream_type:#x}"),
+                context: None,
+            };
+            Err(error.with_offending("stream.unknown_type", Offending::Bytes(raw)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+
+    use super::*;
+    use crate::data::{FetchHeader, FetchObject};
+    use crate::track::TrackPublisher;
+
+    #[test]
+    fn streams_are_routed_by_type() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let mut publisher = TrackPublisher::new(1);
+            let subgroup = publisher.begin_group().subgroup(0);
+            let mut buf = BytesMut::new();
+            subgroup.header(false).encode(&mut buf).unwrap();
+            subgroup
+                .object(Bytes::from_static(b"frame"))
+                .to_subgroup_object()
+                .encode(&mut buf, false)
+                .unwrap();
+            match IncomingStream::accept(&buf[..], usize::MAX).await.unwrap() {
+                IncomingStream::Subgroup(mut reader) => {
+                    assert_eq!(reader.header().await.unwrap().track_alias, 1);
+                    assert_eq!(&reader.next().await.unwrap().unwrap().payload[..], b"frame");
+                }
+                IncomingStream::Fetch(_) => panic!("expected a subgroup stream"),
+            }
+
+            let mut buf = BytesMut::new();
+            FetchHeader { request_id: 4 }.encode(&mut buf).unwrap();
+            FetchObject {
+                group_id: 0,
+                subgroup_id: 0,
+                object_id: 0,
+                publisher_priority: 0,
+                extension_headers: Bytes::new(),
+                object_status: None,
+                payload: Bytes::from_static(b"frame"),
+            }
+            .encode(&mut buf)
+            .unwrap();
+            match IncomingStream::accept(&buf[..], usize::MAX).await.unwrap() {
+                IncomingStream::Fetch(mut reader) => {
+                    assert_eq!(reader.request_id().await.unwrap(), 4);
+                    assert_eq!(&reader.next().await.unwrap().unwrap().payload[..], b"frame");
+                    assert!(reader.next().await.unwrap().is_none());
+                }
+                IncomingStream::Subgroup(_) => panic!("expected a fetch stream"),
+            }
+        });
+    }
+
+    #[test]
+    fn unknown_stream_type_is_protocol_violation() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let stream: &[u8] = &[0x40, 0x30, 0x00];
+            match IncomingStream::accept(stream, usize::MAX).await {
+                Err(Error::ProtocolViolation {
+                    context: Some(v), ..
+                }) => {
+                    assert_eq!(v.rule, "stream.unknown_type");
+                    assert!(matches!(v.offending, Offending::Bytes(b) if b[..] == [0x40, 0x30]));
+                }
+                _ => panic!("expected a protocol violation"),
+            }
+            let empty: &[u8] = &[];
+            match IncomingStream::accept(empty, usize::MAX).await {
+                Err(Error::Io(e)) => assert_eq!(e.kind(), ErrorKind::UnexpectedEof),
+                _ => panic!("expected truncation"),
+            }
+        });
+    }
+}
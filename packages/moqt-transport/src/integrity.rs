@@ -0,0 +1,100 @@
+use bytes::{Bytes, BytesMut};
+
+use crate::{error::Error, model::Parameter, track::Object};
+
+/// Object extension header carrying the CRC-32 of the object payload.
+///
+/// This is an experimental, unregistered extension. Being an even type its
+/// value is a single varint. Relays forward it unmodified, so a mismatch at
+/// the subscriber points at corruption anywhere along the path.
+pub const PAYLOAD_HASH_EXTENSION: u64 = 0xC0DE;
+
+fn payload_hash(payload: &[u8]) -> u64 {
+    crc32fast::hash(payload) as u64
+}
+
+/// Append a payload hash extension header to the object. Called by a
+/// publisher that opted in to integrity checking before the object is
+/// sent.
+pub fn add_payload_hash(object: &mut Object) -> Result<(), Error> {
+    let mut buf = BytesMut::from(&object.extension_headers[..]);
+    Parameter::varint(PAYLOAD_HASH_EXTENSION, payload_hash(&object.payload))?.encode(&mut buf)?;
+    object.extension_headers = buf.freeze();
+    Ok(())
+}
+
+/// Check the payload hash extension of a received object. Objects without
+/// the extension pass unchecked.
+pub fn verify_payload_hash(object: &Object) -> Result<(), Error> {
+    let Some(expected) = find_payload_hash(&object.extension_headers)? else {
+        return Ok(());
+    };
+    if expected != payload_hash(&object.payload) {
+        return Err(Error::PayloadHashMismatch {
+            group_id: object.metadata.group_id,
+            object_id: object.metadata.object_id,
+        });
+    }
+    Ok(())
+}
+
+fn find_payload_hash(extension_headers: &Bytes) -> Result<Option<u64>, Error> {
+    let mut buf = BytesMut::from(&extension_headers[..]);
+    while !buf.is_empty() {
+        let header = Parameter::decode(&mut buf)?;
+        if header.parameter_type == PAYLOAD_HASH_EXTENSION {
+            return Ok(Some(header.as_varint()?));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::ObjectBuilder;
+
+    fn object(payload: &'static [u8]) -> Object {
+        ObjectBuilder::new(2, 3)
+            .with_track_alias(1)
+            .with_payload(Bytes::from_static(payload))
+            .build()
+    }
+
+    #[test]
+    fn hash_roundtrip() {
+        let mut obj = object(b"frame");
+        add_payload_hash(&mut obj).unwrap();
+        assert!(!obj.extension_headers.is_empty());
+        verify_payload_hash(&obj).unwrap();
+    }
+
+    #[test]
+    fn corrupted_payload_is_detected() {
+        let mut obj = object(b"frame");
+        add_payload_hash(&mut obj).unwrap();
+        obj.payload = Bytes::from_static(b"frane");
+        match verify_payload_hash(&obj) {
+            Err(Error::PayloadHashMismatch {
+                group_id: 2,
+                object_id: 3,
+            }) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn other_extensions_are_preserved() {
+        let mut obj = object(b"frame");
+        // An odd typed header with a two byte value.
+        obj.extension_headers = Bytes::from_static(&[0x01, 0x02, 0xAA, 0xBB]);
+        add_payload_hash(&mut obj).unwrap();
+        assert!(obj.extension_headers.starts_with(&[0x01, 0x02, 0xAA, 0xBB]));
+        verify_payload_hash(&obj).unwrap();
+    }
+
+    #[test]
+    fn objects_without_hash_pass() {
+        verify_payload_hash(&object(b"frame")).unwrap();
+    }
+}
@@ -0,0 +1,405 @@
+//! Scripted interoperability scenario against another MoQT implementation.
+//!
+//! [`Scenario::run`] drives a control stream step by step: setup,
+//! ANNOUNCE, SUBSCRIBE, PUBLISH and FETCH, and records for each step
+//! whether the peer answered as the draft requires. The resulting
+//! [`Report`] prints as a pass/fail matrix.
+//!
+//! The `interop_client` example of `moqt-native` runs the scenario over
+//! QUIC against the endpoint named by [`INTEROP_URL_VAR`], e.g. from a CI
+//! machine at an interop event.
+
+use std::fmt;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::BytesMut;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{
+    codec::{ControlMessageCodec, DRAFT_11, DRAFT_12, WireVersion},
+    error::Error,
+    message::{Announce, ClientSetup, ControlMessage, Fetch, Publish},
+    model::Location,
+    request::SubscribeRequest,
+    transport::{BiStream, BoxError, Transport},
+};
+
+/// Environment variable naming the endpoint to run the scenario against.
+pub const INTEROP_URL_VAR: &str = "MOQT_INTEROP_URL";
+
+/// The endpoint named by [`INTEROP_URL_VAR`], if set.
+pub fn endpoint_from_env() -> Option<String> {
+    std::env::var(INTEROP_URL_VAR)
+        .ok()
+        .filter(|url| !url.is_empty())
+}
+
+/// Establishes connections to interop endpoints, over whichever QUIC or
+/// WebTransport stack the application uses.
+#[async_trait]
+pub trait Dialer: Send + Sync {
+    type Transport: Transport;
+
+    async fn dial(&self, url: &str) -> Result<Self::Transport, BoxError>;
+}
+
+/// A step of the scenario, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Setup,
+    Announce,
+    Subscribe,
+    Publish,
+    Fetch,
+}
+
+impl Step {
+    pub const ALL: [Step; 5] = [
+        Step::Setup,
+        Step::Announce,
+        Step::Subscribe,
+        Step::Publish,
+        Step::Fetch,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Step::Setup => "setup",
+            Step::Announce => "announce",
+            Step::Subscribe => "subscribe",
+            Step::Publish => "publish",
+            Step::Fetch => "fetch",
+        }
+    }
+}
+
+/// Result of a step. A request the peer rejected with an error message
+/// fails the step, as the scenario could not exercise it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Fail(String),
+    Skipped(String),
+}
+
+/// Results of a scenario run, one per [`Step`].
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// The version selected by the peer, once set up.
+    pub version: Option<u32>,
+    pub results: Vec<(Step, Outcome)>,
+}
+
+impl Report {
+    pub fn outcome(&self, step: Step) -> Option<&Outcome> {
+        self.results
+            .iter()
+            .find(|(s, _)| *s == step)
+            .map(|(_, o)| o)
+    }
+
+    /// Whether no step failed. Skipped steps do not count as failures.
+    pub fn passed(&self) -> bool {
+        !self
+            .results
+            .iter()
+            .any(|(_, o)| matches!(o, Outcome::Fail(_)))
+    }
+
+    fn skip_remaining(&mut self, reason: &str) {
+        for step in Step::ALL {
+            if self.outcome(step).is_none() {
+                self.results.push((step, Outcome::Skipped(reason.into())));
+            }
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.version {
+            Some(v) => writeln!(f, "version {v:#x}")?,
+            None => writeln!(f, "version -")?,
+        }
+        for (step, outcome) in &self.results {
+            match outcome {
+                Outcome::Pass => writeln!(f, "{:<10} pass", step.name())?,
+                Outcome::Fail(reason) => writeln!(f, "{:<10} FAIL  {reason}", step.name())?,
+                Outcome::Skipped(reason) => writeln!(f, "{:<10} skip  {reason}", step.name())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The scripted scenario, see the [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    namespace: Vec<String>,
+    track_name: String,
+    path: Option<String>,
+    timeout: Duration,
+}
+
+impl Default for Scenario {
+    /// Namespace `moqt-interop`, track `clock`, no PATH and 5 seconds for
+    /// each answer.
+    fn default() -> Self {
+        Self {
+            namespace: vec!["moqt-interop".into()],
+            track_name: "clock".into(),
+            path: None,
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl Scenario {
+    pub fn with_namespace(mut self, namespace: Vec<String>) -> Self {
+        self.namespace = namespace;
+        self
+    }
+
+    pub fn with_track_name(mut self, track_name: impl Into<String>) -> Self {
+        self.track_name = track_name.into();
+        self
+    }
+
+    /// PATH sent in CLIENT_SETUP, for endpoints reached over raw QUIC.
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// How long each step waits for the peer's answer.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Dial `url` and run the scenario. A failure to connect fails the
+    /// setup step.
+    pub async fn run_against<D: Dialer>(&self, dialer: &D, url: &str) -> Report {
+        match dialer.dial(url).await {
+            Ok(mut transport) => self.run(&mut transport).await,
+            Err(e) => {
+                let mut report = Report::default();
+                report
+                    .results
+                    .push((Step::Setup, Outcome::Fail(format!("connect: {e}"))));
+                report.skip_remaining("not set up");
+                report
+            }
+        }
+    }
+
+    /// Run the scenario over a control stream opened on `transport`.
+    pub async fn run<T: Transport>(&self, transport: &mut T) -> Report {
+        let mut report = Report::default();
+        let (reader, writer) = match transport.open_bi_stream().await {
+            Ok(stream) => stream.split(),
+            Err(e) => {
+                report
+                    .results
+                    .push((Step::Setup, Outcome::Fail(format!("control stream: {e}"))));
+                report.skip_remaining("not set up");
+                return report;
+            }
+        };
+        let mut control = Control {
+            reader,
+            writer,
+            codec: ControlMessageCodec::new(),
+            buf: BytesMut::new(),
+            timeout: self.timeout,
+        };
+
+        let max_request_id = match self.setup(&mut control).await {
+            Ok((version, max_request_id)) => {
+                report.version = Some(version);
+                report.results.push((Step::Setup, Outcome::Pass));
+                max_request_id
+            }
+            Err(e) => {
+                report
+                    .results
+                    .push((Step::Setup, Outcome::Fail(e.to_string())));
+                report.skip_remaining("not set up");
+                return report;
+            }
+        };
+
+        // Client requests use even IDs.
+        for (step, request_id) in Step::ALL[1..].iter().zip((0..).step_by(2)) {
+            let outcome = if request_id >= max_request_id {
+                Outcome::Skipped(format!("peer allows request IDs below {max_request_id}"))
+            } else if *step == Step::Publish && report.version == Some(DRAFT_11) {
+                Outcome::Skipped("PUBLISH does not exist in draft-11".into())
+            } else {
+                match self.request(&mut control, *step, request_id).await {
+                    Ok(outcome) => outcome,
+                    Err(e) => Outcome::Fail(e.to_string()),
+                }
+            };
+            report.results.push((*step, outcome));
+        }
+        report
+    }
+
+    async fn setup<R, W>(&self, control: &mut Control<R, W>) -> Result<(u32, u64), Error>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut setup = ClientSetup::new([DRAFT_12, DRAFT_11]);
+        if let Some(path) = &self.path {
+            setup = setup.with_path(path.clone());
+        }
+        control
+            .send(ControlMessage::ClientSetup(setup.clone()))
+            .await?;
+        let ControlMessage::ServerSetup(server) = control.recv().await? else {
+            return Err(Error::ProtocolViolation {
+                reason: "expected SERVER_SETUP".into(),
+                context: None,
+            });
+        };
+        server.validate(&setup)?;
+        control
+            .codec
+            .set_version(WireVersion::negotiated(server.selected_version)?);
+        Ok((server.selected_version, server.max_request_id()?))
+    }
+
+    async fn request<R, W>(
+        &self,
+        control: &mut Control<R, W>,
+        step: Step,
+        request_id: u64,
+    ) -> Result<Outcome, Error>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let msg = match step {
+            Step::Setup => unreachable!("setup is not a request"),
+            Step::Announce => ControlMessage::Announce(Announce {
+                request_id,
+                track_namespace: self.namespace.clone(),
+                parameters: Vec::new(),
+            }),
+            Step::Subscribe => ControlMessage::Subscribe(
+                SubscribeRequest::new(0, self.track_name.clone()).into_subscribe(request_id)?,
+            ),
+            Step::Publish => ControlMessage::Publish(Publish {
+                request_id,
+                track_namespace: 0,
+                track_name: self.track_name.clone(),
+                track_alias: request_id,
+                group_order: 1,
+                content_exists: 0,
+                largest: None,
+                forward: 1,
+                parameters: Vec::new(),
+            }),
+            Step::Fetch => ControlMessage::Fetch(Fetch {
+                request_id,
+                subscriber_priority: 128,
+                group_order: 1,
+                fetch_type: 0x1,
+                track_namespace: Some(0),
+                track_name: Some(self.track_name.clone()),
+                start_location: Some(Location {
+                    group: 0,
+                    object: 0,
+                }),
+                end_location: Some(Location {
+                    group: 0,
+                    object: 0,
+                }),
+                joining_request_id: None,
+                joining_start: None,
+                parameters: Vec::new(),
+            }),
+        };
+        control.send(msg).await?;
+
+        // Anything not answering this request, such as MAX_REQUEST_ID or a
+        // data-less SUBSCRIBE_DONE, is skipped.
+        loop {
+            let answer = control.recv().await?;
+            if answer.request_id() != Some(request_id) {
+                continue;
+            }
+            return Ok(match (step, answer) {
+                (Step::Announce, ControlMessage::AnnounceOk(_))
+                | (Step::Subscribe, ControlMessage::SubscribeOk(_))
+                | (Step::Publish, ControlMessage::PublishOk(_))
+                | (Step::Fetch, ControlMessage::FetchOk(_)) => Outcome::Pass,
+                (Step::Announce, ControlMessage::AnnounceError(e)) => {
+                    rejected("ANNOUNCE_ERROR", e.error_code, &e.error_reason)
+                }
+                (Step::Subscribe, ControlMessage::SubscribeError(e)) => {
+                    rejected("SUBSCRIBE_ERROR", e.error_code, &e.error_reason)
+                }
+                (Step::Publish, ControlMessage::PublishError(e)) => {
+                    rejected("PUBLISH_ERROR", e.error_code, &e.error_reason)
+                }
+                (Step::Fetch, ControlMessage::FetchError(e)) => {
+                    rejected("FETCH_ERROR", e.error_code, &e.error_reason)
+                }
+                (_, other) => Outcome::Fail(format!(
+                    "unexpected {} for request {request_id}",
+                    other.message_type().map_or("unknown message", |t| t.name())
+                )),
+            });
+        }
+    }
+}
+
+fn rejected(message: &str, code: u64, reason: &str) -> Outcome {
+    Outcome::Fail(format!("{message} {code:#x}: {reason}"))
+}
+
+/// The client side of a control stream.
+struct Control<R, W> {
+    reader: R,
+    writer: W,
+    codec: ControlMessageCodec,
+    buf: BytesMut,
+    timeout: Duration,
+}
+
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> Control<R, W> {
+    async fn send(&mut self, msg: ControlMessage) -> Result<(), Error> {
+        let mut buf = BytesMut::new();
+        self.codec.encode(msg, &mut buf)?;
+        self.writer.write_all(&buf).await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    async fn recv(&mut self) -> Result<ControlMessage, Error> {
+        let timeout = self.timeout;
+        tokio::time::timeout(timeout, async {
+            loop {
+                if let Some(msg) = self.codec.decode(&mut self.buf)? {
+                    return Ok(msg);
+                }
+                if self.reader.read_buf(&mut self.buf).await? == 0 {
+                    return Err(Error::SessionClosed);
+                }
+            }
+        })
+        .await
+        .unwrap_or_else(|_| {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("no answer within {timeout:?}"),
+            )
+            .into())
+        })
+    }
+}
@@ -1,8 +1,42 @@
+pub mod announce;
+pub mod auth;
+pub mod bandwidth;
+pub mod blocking;
+pub mod broadcast;
 pub mod codec;
+pub mod control;
+pub mod data;
+pub mod datagram;
+pub mod dedup;
 pub mod error;
-pub mod message;
+pub mod executor;
+pub mod fetch;
+pub mod goaway;
+pub mod group;
+pub mod incoming;
+pub mod integrity;
+#[cfg(feature = "interop")]
+pub mod interop;
+pub mod listener;
+pub mod live;
 pub mod mock;
-pub mod model;
+pub mod observe;
+pub mod prelude;
+pub mod publish;
+pub mod rendition;
+pub mod reorder;
+pub mod repair;
+pub mod request;
+pub mod retention;
+pub mod scheduler;
 pub mod session;
+pub mod source;
+mod stream_reader;
+pub mod subgroup;
+pub mod subscription;
+mod sync;
+pub mod task;
 pub mod track;
 pub mod transport;
+
+pub use moqt_wire::{message, model};
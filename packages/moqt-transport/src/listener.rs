@@ -0,0 +1,933 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_core::Stream;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc};
+use tokio::task::JoinHandle;
+use tokio_util::codec::FramedRead;
+
+use crate::{
+    codec::{ControlMessageCodec, DRAFT_12, WireVersion},
+    error::{Error, TerminationCode},
+    message::{ClientSetup, ControlMessage, ServerSetup},
+    session::{Session, SessionConfig},
+    source::TrackSource,
+    track::FullTrackName,
+    transport::{BiStream, Transport},
+};
+
+/// Accepts the connections of a QUIC or WebTransport endpoint, over
+/// whichever stack the application uses.
+#[async_trait]
+pub trait Acceptor: Send {
+    type Transport: Transport + 'static;
+
+    /// The next connection, or `None` once the endpoint is closed.
+    /// Connections failing to be established are skipped.
+    async fn accept(&mut self) -> Option<Self::Transport>;
+}
+
+/// Control stream of an accepted session, decoding the peer's messages
+/// with the negotiated version.
+pub type ControlStream<T> =
+    FramedRead<<<T as Transport>::Bi as BiStream>::Reader, ControlMessageCodec>;
+
+/// A session whose setup completed, yielded by [`Incoming`].
+///
+/// The session is the only owner of its transport, so data streams can be
+/// opened and accepted through `Arc::get_mut(&mut session.transport)`. Its
+/// outgoing control messages are written by a task of the session.
+pub struct Accepted<T: Transport> {
+    pub session: Session<T>,
+    /// The peer's control messages following CLIENT_SETUP.
+    pub control: ControlStream<T>,
+    /// CLIENT_SETUP received from the peer, e.g. to read its
+    /// authorization tokens.
+    pub setup: ClientSetup,
+    /// Path the session was established on, from the WebTransport URL or
+    /// the PATH parameter of CLIENT_SETUP, including any query. Its
+    /// application is picked by this path when the listener
+    /// [hosts](Listener::with_host) several.
+    pub path: Option<String>,
+    /// The hosted application the session was routed to by its path,
+    /// `None` unless the listener hosts applications.
+    pub host: Option<Arc<Host>>,
+}
+
+/// An application a [`Listener`] hosts on one path: the configuration of
+/// its sessions and the tracks it serves.
+#[derive(Clone, Default)]
+pub struct Host {
+    config: SessionConfig,
+    sources: HashMap<FullTrackName, Arc<TrackSource>>,
+}
+
+impl Host {
+    pub fn new(config: SessionConfig) -> Self {
+        Self {
+            config,
+            sources: HashMap::new(),
+        }
+    }
+
+    /// Serve the track `name` from `source` to the sessions of the
+    /// application.
+    pub fn with_source(mut self, name: impl Into<FullTrackName>, source: Arc<TrackSource>) -> Self {
+        self.sources.insert(name.into(), source);
+        self
+    }
+
+    pub fn config(&self) -> &SessionConfig {
+        &self.config
+    }
+
+    /// The source of the track `name` in this application, to answer a
+    /// SUBSCRIBE or FETCH of a session routed here.
+    pub fn source(&self, name: &str) -> Option<&Arc<TrackSource>> {
+        self.sources.get(name)
+    }
+}
+
+impl From<SessionConfig> for Host {
+    fn from(config: SessionConfig) -> Self {
+        Self::new(config)
+    }
+}
+
+/// What a [`Listener`] does with a connection beyond its
+/// [limits](ListenerLimits).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverloadPolicy {
+    /// Stop accepting until a session ends or a setup completes. Further
+    /// connections wait in the endpoint, whose own backlog refuses them
+    /// at the transport. The default.
+    #[default]
+    Refuse,
+    /// Accept the connection and close it right away with
+    /// [`TerminationCode::TooManyRequests`], telling the peer to retry
+    /// later or elsewhere.
+    Close,
+}
+
+/// Bounds on the connections a [`Listener`] handles at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListenerLimits {
+    max_handshakes: usize,
+    backlog: usize,
+    max_sessions: usize,
+    overload: OverloadPolicy,
+}
+
+impl Default for ListenerLimits {
+    /// 64 concurrent setups with 256 more connections waiting for one, no
+    /// bound on the sessions, refusing connections beyond.
+    fn default() -> Self {
+        Self {
+            max_handshakes: 64,
+            backlog: 256,
+            max_sessions: Semaphore::MAX_PERMITS,
+            overload: OverloadPolicy::Refuse,
+        }
+    }
+}
+
+impl ListenerLimits {
+    /// Connections exchanging CLIENT_SETUP and SERVER_SETUP at the same
+    /// time.
+    pub fn with_max_handshakes(mut self, max_handshakes: usize) -> Self {
+        self.max_handshakes = max_handshakes;
+        self
+    }
+
+    /// Accepted connections waiting for their setup to start.
+    pub fn with_backlog(mut self, backlog: usize) -> Self {
+        self.backlog = backlog;
+        self
+    }
+
+    /// Sessions alive at the same time, counting those being set up. A
+    /// session counts until it is shut down or dropped.
+    pub fn with_max_sessions(mut self, max_sessions: usize) -> Self {
+        self.max_sessions = max_sessions.min(Semaphore::MAX_PERMITS);
+        self
+    }
+
+    /// What to do with connections beyond the limits.
+    pub fn with_overload_policy(mut self, overload: OverloadPolicy) -> Self {
+        self.overload = overload;
+        self
+    }
+
+    pub fn max_handshakes(&self) -> usize {
+        self.max_handshakes
+    }
+
+    pub fn backlog(&self) -> usize {
+        self.backlog
+    }
+
+    pub fn max_sessions(&self) -> usize {
+        self.max_sessions
+    }
+
+    pub fn overload_policy(&self) -> OverloadPolicy {
+        self.overload
+    }
+}
+
+/// Server endpoint yielding ready [`Session`]s: each accepted connection
+/// has its control stream accepted, CLIENT_SETUP read and SERVER_SETUP
+/// sent before it is passed on.
+///
+/// One listener can host several independent applications, each on its
+/// own path with its own configuration and tracks. Sessions are then
+/// served from the [`Host`] they were routed to:
+///
+/// ```ignore
+/// let mut incoming = Listener::new(endpoint)
+///     .with_host("/chat", Host::default().with_source("messages", messages))
+///     .with_host(
+///         "/media",
+///         Host::new(SessionConfig::default().with_max_object_payload_size(8 << 20))
+///             .with_source("video", video),
+///     )
+///     .incoming();
+/// while let Some(Ok(accepted)) = incoming.recv().await {
+///     let host = accepted.host.clone().expect("routed to a host");
+///     serve(accepted, move |subscribe| host.source(&subscribe.track_name).cloned());
+/// }
+/// ```
+///
+/// ```ignore
+/// let mut incoming = Listener::new(endpoint)
+///     .with_config(SessionConfig::default().with_max_object_payload_size(1 << 20))
+///     .with_max_request_id(100)
+///     .incoming();
+/// while let Some(accepted) = incoming.recv().await {
+///     match accepted {
+///         Ok(accepted) => serve(accepted),
+///         Err(e) => eprintln!("setup failed: {e}"),
+///     }
+/// }
+/// ```
+pub struct Listener<A> {
+    acceptor: A,
+    config: SessionConfig,
+    versions: Vec<u32>,
+    max_request_id: u64,
+    setup_timeout: Duration,
+    limits: ListenerLimits,
+    hosts: Vec<(String, Arc<Host>)>,
+}
+
+impl<A: Acceptor + 'static> Listener<A> {
+    /// Listener accepting draft-12 sessions, advertising no Maximum Request
+    /// ID and waiting up to 10 seconds for CLIENT_SETUP.
+    pub fn new(acceptor: A) -> Self {
+        Self {
+            acceptor,
+            config: SessionConfig::default(),
+            versions: vec![DRAFT_12],
+            max_request_id: 0,
+            setup_timeout: Duration::from_secs(10),
+            limits: ListenerLimits::default(),
+            hosts: Vec::new(),
+        }
+    }
+
+    /// Configuration of every session accepted, unless the listener
+    /// [hosts](Self::with_host) applications on their own paths.
+    pub fn with_config(mut self, config: SessionConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Versions accepted, in order of preference. The first one offered by
+    /// the client is selected.
+    pub fn with_versions(mut self, versions: impl Into<Vec<u32>>) -> Self {
+        self.versions = versions.into();
+        self
+    }
+
+    /// Initial Maximum Request ID advertised in SERVER_SETUP. The peer may
+    /// send no request until it is raised from the default of 0.
+    pub fn with_max_request_id(mut self, max: u64) -> Self {
+        self.max_request_id = max;
+        self
+    }
+
+    /// Host an application on `path`, e.g. `/chat`: sessions established
+    /// on it get the configuration of `host` and are yielded with it as
+    /// their [`Accepted::host`]. The path is matched against the
+    /// WebTransport URL path or, on raw QUIC, the PATH parameter of
+    /// CLIENT_SETUP, ignoring any query. Hosting `path` again replaces its
+    /// application.
+    ///
+    /// Once any application is hosted, sessions on another path, or on
+    /// none, fail their setup with [`Error::InvalidPath`] and their
+    /// connection is closed with [`TerminationCode::InvalidPath`].
+    pub fn with_host(mut self, path: impl Into<String>, host: impl Into<Host>) -> Self {
+        let path = path.into();
+        self.hosts.retain(|(p, _)| *p != path);
+        self.hosts.push((path, Arc::new(host.into())));
+        self
+    }
+
+    /// Bounds on the setups and sessions handled at once.
+    pub fn with_limits(mut self, limits: ListenerLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// How long a connection may take to open its control stream and send
+    /// CLIENT_SETUP before it is closed with
+    /// [`TerminationCode::ControlMessageTimeout`], counted from the start of
+    /// its setup.
+    pub fn with_setup_timeout(mut self, timeout: Duration) -> Self {
+        self.setup_timeout = timeout;
+        self
+    }
+
+    /// Start accepting connections on a background task. The setup of each
+    /// connection runs on its own task, so a slow peer holds up no other.
+    ///
+    /// Sessions are yielded as their setup completes, and connections whose
+    /// setup failed as the error, e.g. [`Error::VersionNegotiationFailed`],
+    /// after closing the connection with the error's [`TerminationCode`]. The stream ends once the
+    /// acceptor is closed and the pending setups are done. Dropping it stops
+    /// accepting.
+    ///
+    /// Connections beyond the [limits](Self::with_limits) are handled by
+    /// their [`OverloadPolicy`] and never yielded.
+    pub fn incoming(self) -> Incoming<A::Transport> {
+        let Listener {
+            mut acceptor,
+            config,
+            versions,
+            max_request_id,
+            setup_timeout,
+            limits,
+            hosts,
+        } = self;
+        let setup = Arc::new(Setup {
+            config,
+            hosts,
+            versions,
+            max_request_id,
+        });
+        let admission = Admission::new(limits);
+        let accepting = admission.clone();
+        let (tx, rx) = mpsc::channel(16);
+        let task = tokio::spawn(async move {
+            loop {
+                let admitted = match limits.overload {
+                    OverloadPolicy::Refuse => Some(accepting.wait().await),
+                    OverloadPolicy::Close => None,
+                };
+                let Some(transport) = acceptor.accept().await else {
+                    return;
+                };
+                let Some(admitted) = admitted.or_else(|| accepting.try_admit()) else {
+                    accepting.rejected.fetch_add(1, Ordering::Relaxed);
+                    let code = TerminationCode::TooManyRequests.code();
+                    transport.close(code, "too many connections");
+                    continue;
+                };
+                let setup = setup.clone();
+                let handshakes = accepting.handshakes.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let Admitted { session, pending } = admitted;
+                    let handshake = handshakes.acquire_owned().await.expect("never closed");
+                    let accepted = setup.run(transport, setup_timeout).await;
+                    drop((handshake, pending));
+                    // The session counts against the limit for as long as
+                    // its tasks run.
+                    if let Ok(accepted) = &accepted {
+                        accepted.session.spawn(async move {
+                            let _session = session;
+                            std::future::pending::<()>().await;
+                        });
+                    }
+                    let _ = tx.send(accepted).await;
+                });
+            }
+        });
+        Incoming {
+            rx,
+            task,
+            admission,
+        }
+    }
+}
+
+/// Sessions accepted by a [`Listener`], as they become ready.
+pub struct Incoming<T: Transport> {
+    rx: mpsc::Receiver<Result<Accepted<T>, Error>>,
+    task: JoinHandle<()>,
+    admission: Admission,
+}
+
+impl<T: Transport> Incoming<T> {
+    /// The next session ready or failed setup, or `None` once the acceptor
+    /// is closed.
+    pub async fn recv(&mut self) -> Option<Result<Accepted<T>, Error>> {
+        self.rx.recv().await
+    }
+
+    /// Sessions alive, including those being set up.
+    pub fn active_sessions(&self) -> usize {
+        self.admission.max_sessions - self.admission.sessions.available_permits()
+    }
+
+    /// Connections closed by [`OverloadPolicy::Close`] so far.
+    pub fn rejected(&self) -> u64 {
+        self.admission.rejected.load(Ordering::Relaxed)
+    }
+}
+
+impl<T: Transport> Stream for Incoming<T> {
+    type Item = Result<Accepted<T>, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+impl<T: Transport> Drop for Incoming<T> {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl<T: Transport + 'static> Session<T> {
+    /// Accept the sessions of `acceptor` with the default [`Listener`]
+    /// settings and `config`.
+    pub fn accept_many<A>(acceptor: A, config: SessionConfig) -> Incoming<T>
+    where
+        A: Acceptor<Transport = T> + 'static,
+    {
+        Listener::new(acceptor).with_config(config).incoming()
+    }
+}
+
+/// Permits of a connection admitted by a listener.
+struct Admitted {
+    /// Held for the lifetime of the session.
+    session: OwnedSemaphorePermit,
+    /// Held until the setup completes.
+    pending: OwnedSemaphorePermit,
+}
+
+/// Counts of the connections a listener handles against its limits.
+#[derive(Clone)]
+struct Admission {
+    max_sessions: usize,
+    sessions: Arc<Semaphore>,
+    /// Connections being set up or waiting in the backlog.
+    pending: Arc<Semaphore>,
+    handshakes: Arc<Semaphore>,
+    rejected: Arc<AtomicU64>,
+}
+
+impl Admission {
+    fn new(limits: ListenerLimits) -> Self {
+        let pending = limits.max_handshakes.saturating_add(limits.backlog);
+        Self {
+            max_sessions: limits.max_sessions,
+            sessions: Arc::new(Semaphore::new(limits.max_sessions)),
+            pending: Arc::new(Semaphore::new(pending.min(Semaphore::MAX_PERMITS))),
+            handshakes: Arc::new(Semaphore::new(limits.max_handshakes)),
+            rejected: Arc::default(),
+        }
+    }
+
+    /// Wait for room for one more connection.
+    async fn wait(&self) -> Admitted {
+        let session = self.sessions.clone().acquire_owned().await;
+        let pending = self.pending.clone().acquire_owned().await;
+        Admitted {
+            session: session.expect("never closed"),
+            pending: pending.expect("never closed"),
+        }
+    }
+
+    /// Admit one more connection if there is room.
+    fn try_admit(&self) -> Option<Admitted> {
+        Some(Admitted {
+            session: self.sessions.clone().try_acquire_owned().ok()?,
+            pending: self.pending.clone().try_acquire_owned().ok()?,
+        })
+    }
+}
+
+impl<T: Transport> Accepted<T> {
+    /// [`path`](Self::path) without its query, as matched against the
+    /// paths of [hosted](Listener::with_host) applications.
+    pub fn route(&self) -> Option<&str> {
+        self.path.as_deref().map(route)
+    }
+}
+
+/// `path` without its query.
+fn route(path: &str) -> &str {
+    path.split_once('?').map_or(path, |(route, _)| route)
+}
+
+/// What every connection of a listener is set up with.
+struct Setup {
+    config: SessionConfig,
+    /// Application hosted on each path, if any.
+    hosts: Vec<(String, Arc<Host>)>,
+    versions: Vec<u32>,
+    max_request_id: u64,
+}
+
+impl Setup {
+    /// The application hosted on `path`, `None` if the listener hosts
+    /// none.
+    fn host(&self, path: Option<&str>) -> Result<Option<Arc<Host>>, Error> {
+        if self.hosts.is_empty() {
+            return Ok(None);
+        }
+        path.and_then(|path| self.hosts.iter().find(|(p, _)| p == route(path)))
+            .map(|(_, host)| Some(host.clone()))
+            .ok_or_else(|| Error::InvalidPath {
+                path: path.map(Into::into),
+            })
+    }
+
+    /// Path the session is established on. PATH is only sent on raw QUIC,
+    /// a WebTransport session carrying it is invalid.
+    fn path(transport: &impl Transport, setup: &ClientSetup) -> Result<Option<String>, Error> {
+        match (transport.url_path(), setup.path()?) {
+            (Some(_), Some(path)) => Err(Error::InvalidPath {
+                path: Some(path.into()),
+            }),
+            (Some(path), None) | (None, Some(path)) => Ok(Some(path.into())),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Set up the session of `transport`, giving the peer `timeout` to
+    /// open its control stream and send CLIENT_SETUP. The connection is
+    /// closed with the termination code of the error if the setup fails.
+    async fn run<T: Transport + 'static>(
+        &self,
+        mut transport: T,
+        timeout: Duration,
+    ) -> Result<Accepted<T>, Error> {
+        let handshake = tokio::time::timeout(timeout, self.handshake(&mut transport))
+            .await
+            .unwrap_or(Err(Error::SetupTimeout { timeout }));
+        let handshake = match handshake {
+            Ok(handshake) => handshake,
+            Err(e) => {
+                transport.close(TerminationCode::from(&e).code(), &e.to_string());
+                return Err(e);
+            }
+        };
+        let config = match &handshake.host {
+            Some(host) => host.config.clone(),
+            None => self.config.clone(),
+        };
+        let (session, rx) = Session::with_config(Arc::new(transport), config);
+        match self.establish(&session, rx, handshake).await {
+            Ok((control, setup, path, host)) => Ok(Accepted {
+                session,
+                control,
+                setup,
+                path,
+                host,
+            }),
+            Err(e) => {
+                let code = TerminationCode::from(&e).code();
+                session.transport.close(code, &e.to_string());
+                Err(e)
+            }
+        }
+    }
+
+    /// Read CLIENT_SETUP and pick the version and application of the
+    /// session.
+    async fn handshake<T: Transport>(&self, transport: &mut T) -> Result<Handshake<T>, Error> {
+        let (reader, writer) = transport
+            .accept_bi_stream()
+            .await
+            .map_err(Error::Transport)?
+            .split();
+        // CLIENT_SETUP is decoded with the listener's configuration, as the
+        // application it is for is not known yet.
+        let mut control = FramedRead::new(reader, self.config.control_codec());
+
+        let setup = match std::future::poll_fn(|cx| Pin::new(&mut control).poll_next(cx)).await {
+            Some(Ok(ControlMessage::ClientSetup(setup))) => setup,
+            Some(Err(e)) => return Err(e),
+            Some(Ok(_)) | None => {
+                return Err(Error::ProtocolViolation {
+                    reason: "expected CLIENT_SETUP".into(),
+                    context: None,
+                });
+            }
+        };
+        let Some(&version) = self
+            .versions
+            .iter()
+            .find(|v| setup.supported_versions.contains(v))
+        else {
+            return Err(Error::VersionNegotiationFailed {
+                offered: setup.supported_versions,
+            });
+        };
+        let path = Self::path(transport, &setup)?;
+        let host = self.host(path.as_deref())?;
+        Ok(Handshake {
+            control,
+            writer,
+            setup,
+            version,
+            path,
+            host,
+        })
+    }
+
+    /// Start the control stream of `session` and answer with SERVER_SETUP.
+    async fn establish<T: Transport + 'static>(
+        &self,
+        session: &Session<T>,
+        rx: mpsc::Receiver<ControlMessage>,
+        handshake: Handshake<T>,
+    ) -> Result<Established<T>, Error> {
+        let Handshake {
+            mut control,
+            writer,
+            setup,
+            version,
+            path,
+            host,
+        } = handshake;
+        session.set_wire_version(WireVersion::negotiated(version)?);
+        *control.decoder_mut() = session.control_codec();
+        let max = setup.max_request_id()?;
+        if max > 0 {
+            session.track_manager.handle_max_request_id(max)?;
+        }
+
+        session.spawn_control_writer(writer, rx);
+        let mut server = ServerSetup::accept(version);
+        if self.max_request_id > 0 {
+            session.advertise_max_request_id(self.max_request_id)?;
+            server = server.with_max_request_id(self.max_request_id);
+        }
+        session
+            .send_control(ControlMessage::ServerSetup(server))
+            .await?;
+        session.activate();
+        Ok((control, setup, path, host))
+    }
+}
+
+/// What CLIENT_SETUP established about a connection.
+struct Handshake<T: Transport> {
+    control: ControlStream<T>,
+    writer: <<T as Transport>::Bi as BiStream>::Writer,
+    setup: ClientSetup,
+    version: u32,
+    path: Option<String>,
+    host: Option<Arc<Host>>,
+}
+
+/// The parts of an [`Accepted`] session besides the session itself.
+type Established<T> = (
+    ControlStream<T>,
+    ClientSetup,
+    Option<String>,
+    Option<Arc<Host>>,
+);
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_util::codec::{Decoder, Encoder};
+
+    use super::*;
+    use crate::codec::DRAFT_11;
+    use crate::message::MaxRequestId;
+    use crate::mock::MockTransport;
+    use crate::session::State;
+
+    struct MockAcceptor(mpsc::Receiver<MockTransport>);
+
+    #[async_trait]
+    impl Acceptor for MockAcceptor {
+        type Transport = MockTransport;
+
+        async fn accept(&mut self) -> Option<MockTransport> {
+            self.0.recv().await
+        }
+    }
+
+    /// Connect a client offering `versions`, returning its answer to
+    /// CLIENT_SETUP and its transport.
+    async fn connect(
+        connections: &mpsc::Sender<MockTransport>,
+        versions: &[u32],
+    ) -> (Option<ControlMessage>, MockTransport) {
+        let setup = ClientSetup::new(versions).with_max_request_id(5);
+        connect_with(connections, setup, None).await
+    }
+
+    /// Connect a client sending `setup`, over WebTransport on `url_path`
+    /// if given.
+    async fn connect_with(
+        connections: &mpsc::Sender<MockTransport>,
+        setup: ClientSetup,
+        url_path: Option<&str>,
+    ) -> (Option<ControlMessage>, MockTransport) {
+        let (mut client, mut server) = MockTransport::pair();
+        if let Some(path) = url_path {
+            server.set_url_path(path);
+        }
+        connections.send(server).await.unwrap();
+        let (mut reader, mut writer) = client.open_bi_stream().await.unwrap().split();
+        let mut codec = ControlMessageCodec::new();
+        let mut buf = BytesMut::new();
+        codec
+            .encode(ControlMessage::ClientSetup(setup), &mut buf)
+            .unwrap();
+        let request = ControlMessage::MaxRequestId(MaxRequestId { request_id: 8 });
+        codec.encode(request, &mut buf).unwrap();
+        writer.write_all(&buf).await.unwrap();
+
+        let mut buf = BytesMut::new();
+        let answer = loop {
+            if let Some(msg) = codec.decode(&mut buf).unwrap() {
+                break Some(msg);
+            }
+            // Refused connections end the stream or close the connection.
+            if reader.read_buf(&mut buf).await.unwrap_or(0) == 0 {
+                break None;
+            }
+        };
+        (answer, client)
+    }
+
+    #[test]
+    fn listener_yields_sessions_after_setup() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (connections, rx) = mpsc::channel(4);
+            let mut incoming = Listener::new(MockAcceptor(rx))
+                .with_versions([DRAFT_12, DRAFT_11])
+                .with_max_request_id(10)
+                .incoming();
+
+            let (answer, _client) = connect(&connections, &[DRAFT_11]).await;
+            let Some(ControlMessage::ServerSetup(server)) = answer else {
+                panic!("expected SERVER_SETUP, got {answer:?}");
+            };
+            assert_eq!(server.selected_version, DRAFT_11);
+            assert_eq!(server.max_request_id().unwrap(), 10);
+
+            let mut accepted = incoming.recv().await.unwrap().unwrap();
+            assert_eq!(accepted.session.state(), State::Active);
+            assert_eq!(accepted.session.max_request_id(), 10);
+            assert_eq!(accepted.setup.max_request_id().unwrap(), 5);
+            assert!(Arc::get_mut(&mut accepted.session.transport).is_some());
+            let next = std::future::poll_fn(|cx| Pin::new(&mut accepted.control).poll_next(cx));
+            assert!(matches!(
+                next.await,
+                Some(Ok(ControlMessage::MaxRequestId(MaxRequestId {
+                    request_id: 8
+                })))
+            ));
+
+            // A client without a common version gets no SERVER_SETUP.
+            let (answer, client) = connect(&connections, &[0xff000001]).await;
+            assert!(answer.is_none());
+            let closed = client.close_reason().unwrap();
+            assert_eq!(
+                closed.code,
+                TerminationCode::VersionNegotiationFailed.code()
+            );
+            assert!(matches!(
+                incoming.recv().await,
+                Some(Err(Error::VersionNegotiationFailed { offered })) if offered == [0xff000001]
+            ));
+
+            drop(connections);
+            assert!(incoming.recv().await.is_none());
+        });
+    }
+
+    #[test]
+    fn silent_clients_time_out() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .start_paused(true)
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (connections, rx) = mpsc::channel(4);
+            let mut incoming = Listener::new(MockAcceptor(rx))
+                .with_setup_timeout(Duration::from_secs(1))
+                .incoming();
+            let (client, server) = MockTransport::pair();
+            connections.send(server).await.unwrap();
+
+            assert!(matches!(
+                incoming.recv().await,
+                Some(Err(Error::SetupTimeout { timeout })) if timeout == Duration::from_secs(1)
+            ));
+            let closed = client.close_reason().unwrap();
+            assert_eq!(closed.code, TerminationCode::ControlMessageTimeout.code());
+        });
+    }
+
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    #[test]
+    fn sessions_beyond_limit_are_closed() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (connections, rx) = mpsc::channel(4);
+            let limits = ListenerLimits::default()
+                .with_max_sessions(1)
+                .with_overload_policy(OverloadPolicy::Close);
+            let mut incoming = Listener::new(MockAcceptor(rx))
+                .with_limits(limits)
+                .incoming();
+
+            let (answer, _first) = connect(&connections, &[DRAFT_12]).await;
+            assert!(matches!(answer, Some(ControlMessage::ServerSetup(_))));
+            let accepted = incoming.recv().await.unwrap().unwrap();
+            assert_eq!(incoming.active_sessions(), 1);
+
+            let (client, server) = MockTransport::pair();
+            connections.send(server).await.unwrap();
+            settle().await;
+            let closed = client.close_reason().unwrap();
+            assert_eq!(closed.code, TerminationCode::TooManyRequests.code());
+            assert_eq!(incoming.rejected(), 1);
+
+            // Room is made once the session ends.
+            drop(accepted);
+            settle().await;
+            assert_eq!(incoming.active_sessions(), 0);
+            let (answer, _third) = connect(&connections, &[DRAFT_12]).await;
+            assert!(matches!(answer, Some(ControlMessage::ServerSetup(_))));
+            assert!(incoming.recv().await.unwrap().is_ok());
+        });
+    }
+
+    #[test]
+    fn connections_beyond_limit_wait_in_endpoint() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (connections, rx) = mpsc::channel(4);
+            let limits = ListenerLimits::default().with_max_sessions(1);
+            let mut incoming = Listener::new(MockAcceptor(rx))
+                .with_limits(limits)
+                .incoming();
+
+            let (_answer, _first) = connect(&connections, &[DRAFT_12]).await;
+            let accepted = incoming.recv().await.unwrap().unwrap();
+
+            // The second connection is left to the endpoint.
+            let (_client, server) = MockTransport::pair();
+            connections.send(server).await.unwrap();
+            settle().await;
+            assert_eq!(connections.capacity(), 3);
+
+            drop(accepted);
+            settle().await;
+            assert_eq!(connections.capacity(), 4);
+            assert_eq!(incoming.active_sessions(), 1);
+            assert_eq!(incoming.rejected(), 0);
+        });
+    }
+
+    #[test]
+    fn sessions_are_routed_by_path() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (connections, rx) = mpsc::channel(4);
+            let video = Arc::new(TrackSource::default());
+            let mut incoming = Listener::new(MockAcceptor(rx))
+                .with_host("/chat", SessionConfig::default())
+                .with_host(
+                    "/media",
+                    Host::new(SessionConfig::default().with_max_object_payload_size(1024))
+                        .with_source("video", video.clone()),
+                )
+                .incoming();
+
+            // Raw QUIC, by the PATH parameter.
+            let setup = ClientSetup::new([DRAFT_12]).with_path("/media?room=1");
+            let (answer, _media) = connect_with(&connections, setup, None).await;
+            assert!(matches!(answer, Some(ControlMessage::ServerSetup(_))));
+            let accepted = incoming.recv().await.unwrap().unwrap();
+            assert_eq!(accepted.path.as_deref(), Some("/media?room=1"));
+            assert_eq!(accepted.route(), Some("/media"));
+            assert_eq!(accepted.session.max_object_payload_size(), 1024);
+            let host = accepted.host.unwrap();
+            assert!(Arc::ptr_eq(host.source("video").unwrap(), &video));
+
+            // WebTransport, by the URL path.
+            let setup = ClientSetup::new([DRAFT_12]);
+            let (answer, _chat) = connect_with(&connections, setup, Some("/chat")).await;
+            assert!(matches!(answer, Some(ControlMessage::ServerSetup(_))));
+            let accepted = incoming.recv().await.unwrap().unwrap();
+            assert_eq!(accepted.route(), Some("/chat"));
+            assert!(accepted.host.unwrap().source("video").is_none());
+            assert_eq!(
+                accepted.session.max_object_payload_size(),
+                crate::session::DEFAULT_MAX_OBJECT_PAYLOAD_SIZE
+            );
+
+            // Sessions on no hosted path are closed.
+            let setup = ClientSetup::new([DRAFT_12]).with_path("/other");
+            let (answer, client) = connect_with(&connections, setup, None).await;
+            assert!(answer.is_none());
+            let closed = client.close_reason().unwrap();
+            assert_eq!(closed.code, TerminationCode::InvalidPath.code());
+            assert!(matches!(
+                incoming.recv().await,
+                Some(Err(Error::InvalidPath { path: Some(path) })) if path == "/other"
+            ));
+
+            // PATH must not be sent over WebTransport.
+            let setup = ClientSetup::new([DRAFT_12]).with_path("/chat");
+            let (answer, client) = connect_with(&connections, setup, Some("/chat")).await;
+            assert!(answer.is_none());
+            let closed = client.close_reason().unwrap();
+            assert_eq!(closed.code, TerminationCode::InvalidPath.code());
+            assert!(matches!(
+                incoming.recv().await,
+                Some(Err(Error::InvalidPath { .. }))
+            ));
+        });
+    }
+}
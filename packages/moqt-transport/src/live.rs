@@ -0,0 +1,288 @@
+use std::collections::VecDeque;
+
+use crate::error::Error;
+use crate::track::{Object, ObjectStream};
+
+/// Item of a [`LiveStream`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LiveEvent {
+    Object(Object),
+    /// Objects of groups `first_group..=last_group` were dropped to catch
+    /// up with the live edge. Objects of those groups delivered before the
+    /// skip may have been emitted already.
+    Skipped {
+        first_group: u64,
+        last_group: u64,
+        objects: u64,
+    },
+}
+
+/// Subscriber-side queue keeping a consumer close to the live edge.
+///
+/// Whenever the oldest queued object is more than `max_lag` groups behind
+/// the newest group received, every queued object of the groups before
+/// `newest - max_lag` is dropped, so whole groups are skipped rather than
+/// single objects, and a [`LiveEvent::Skipped`] is emitted in their place.
+/// Objects of skipped groups arriving later are dropped as well.
+pub struct LiveEdge {
+    max_lag: u64,
+    queue: VecDeque<Object>,
+    newest: Option<u64>,
+    floor: u64,
+    skip: Option<(u64, u64, u64)>,
+    dropped: u64,
+}
+
+impl LiveEdge {
+    pub fn new(max_lag: u64) -> Self {
+        Self {
+            max_lag,
+            queue: VecDeque::new(),
+            newest: None,
+            floor: 0,
+            skip: None,
+            dropped: 0,
+        }
+    }
+
+    /// Queue a received object, dropping older groups if the queue lags too
+    /// far behind.
+    pub fn push(&mut self, object: Object) {
+        let group = object.metadata.group_id;
+        if group < self.floor {
+            self.dropped += 1;
+            return;
+        }
+        self.newest = Some(self.newest.map_or(group, |newest| newest.max(group)));
+        self.queue.push_back(object);
+
+        let newest = self.newest.unwrap_or(group);
+        let lagging = self
+            .queue
+            .front()
+            .is_some_and(|o| newest - o.metadata.group_id > self.max_lag);
+        if lagging {
+            self.floor = newest - self.max_lag;
+            let floor = self.floor;
+            let before = self.queue.len();
+            let mut skipped = None::<(u64, u64)>;
+            self.queue.retain(|o| {
+                let group = o.metadata.group_id;
+                if group >= floor {
+                    return true;
+                }
+                skipped = Some(skipped.map_or((group, group), |(first, last)| {
+                    (first.min(group), last.max(group))
+                }));
+                false
+            });
+            if let Some((first, last)) = skipped {
+                let objects = (before - self.queue.len()) as u64;
+                self.dropped += objects;
+                self.skip = Some(match self.skip {
+                    Some((f, l, n)) => (f.min(first), l.max(last), n + objects),
+                    None => (first, last, objects),
+                });
+            }
+        }
+    }
+
+    /// Next event for the consumer. A pending skip is reported before the
+    /// objects following it.
+    pub fn pop(&mut self) -> Option<LiveEvent> {
+        if let Some((first_group, last_group, objects)) = self.skip.take() {
+            return Some(LiveEvent::Skipped {
+                first_group,
+                last_group,
+                objects,
+            });
+        }
+        self.queue.pop_front().map(LiveEvent::Object)
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty() && self.skip.is_none()
+    }
+
+    /// Objects dropped to keep up with the live edge.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+/// An [`ObjectStream`] consumed through a [`LiveEdge`], created by
+/// [`ObjectStream::keep_live`].
+///
+/// Objects already delivered by the session but not yet consumed count
+/// towards the lag, so a slow consumer skips ahead instead of falling
+/// further behind. At most as many objects as the stream's channel holds
+/// are taken from it ahead of the consumer, so a consumer lagging less than
+/// `max_lag` groups still leaves the channel full and the publisher sees it
+/// fall behind.
+pub struct LiveStream {
+    objects: ObjectStream,
+    edge: LiveEdge,
+    capacity: usize,
+    /// Error received after the objects still queued in `edge`.
+    error: Option<Error>,
+}
+
+impl LiveStream {
+    pub(crate) fn new(objects: ObjectStream, max_lag: u64) -> Self {
+        Self {
+            capacity: objects.rx.max_capacity(),
+            objects,
+            edge: LiveEdge::new(max_lag),
+            error: None,
+        }
+    }
+
+    /// Receive the next event, or `None` once the stream ended and every
+    /// queued object was consumed. An error is returned after the objects
+    /// received before it.
+    pub async fn recv(&mut self) -> Option<Result<LiveEvent, Error>> {
+        loop {
+            while self.error.is_none() && self.edge.len() < self.capacity {
+                match self.objects.rx.try_recv() {
+                    Ok(Ok(object)) => self.edge.push(object),
+                    Ok(Err(e)) => self.error = Some(e),
+                    Err(_) => break,
+                }
+            }
+            if let Some(event) = self.edge.pop() {
+                return Some(Ok(event));
+            }
+            if let Some(e) = self.error.take() {
+                return Some(Err(e));
+            }
+            match self.objects.recv().await? {
+                Ok(object) => self.edge.push(object),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.edge.dropped()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::ObjectBuilder;
+
+    fn object(group_id: u64, object_id: u64) -> Object {
+        ObjectBuilder::new(group_id, object_id).build()
+    }
+
+    fn location(event: LiveEvent) -> (u64, u64) {
+        match event {
+            LiveEvent::Object(o) => (o.metadata.group_id, o.metadata.object_id),
+            e => panic!("unexpected event: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn lagging_groups_are_skipped_whole() {
+        let mut edge = LiveEdge::new(1);
+        edge.push(object(0, 0));
+        edge.push(object(0, 1));
+        edge.push(object(1, 0));
+        assert_eq!(edge.len(), 3);
+
+        // Group 2 puts the queue two groups behind.
+        edge.push(object(2, 0));
+        assert_eq!(
+            edge.pop(),
+            Some(LiveEvent::Skipped {
+                first_group: 0,
+                last_group: 0,
+                objects: 2
+            })
+        );
+        assert_eq!(location(edge.pop().unwrap()), (1, 0));
+
+        // Late objects of a skipped group are dropped.
+        edge.push(object(0, 2));
+        assert_eq!(location(edge.pop().unwrap()), (2, 0));
+        assert!(edge.pop().is_none());
+        assert_eq!(edge.dropped(), 3);
+    }
+
+    #[test]
+    fn stream_skips_unconsumed_objects() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (tx, objects) = ObjectStream::channel(16);
+            let mut live = objects.keep_live(0);
+            for group in 0..3 {
+                tx.send(Ok(object(group, 0))).await.unwrap();
+                tx.send(Ok(object(group, 1))).await.unwrap();
+            }
+            drop(tx);
+
+            assert_eq!(
+                live.recv().await.unwrap().unwrap(),
+                LiveEvent::Skipped {
+                    first_group: 0,
+                    last_group: 1,
+                    objects: 4
+                }
+            );
+            assert_eq!(location(live.recv().await.unwrap().unwrap()), (2, 0));
+            assert_eq!(location(live.recv().await.unwrap().unwrap()), (2, 1));
+            assert!(live.recv().await.is_none());
+        });
+    }
+
+    #[test]
+    fn error_follows_queued_objects() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (tx, objects) = ObjectStream::channel(16);
+            let mut live = objects.keep_live(4);
+            tx.send(Ok(object(0, 0))).await.unwrap();
+            tx.send(Ok(object(0, 1))).await.unwrap();
+            tx.send(Err(Error::SessionClosed)).await.unwrap();
+            tx.send(Ok(object(1, 0))).await.unwrap();
+
+            assert_eq!(location(live.recv().await.unwrap().unwrap()), (0, 0));
+            assert_eq!(location(live.recv().await.unwrap().unwrap()), (0, 1));
+            assert!(matches!(live.recv().await, Some(Err(Error::SessionClosed))));
+            assert_eq!(location(live.recv().await.unwrap().unwrap()), (1, 0));
+        });
+    }
+
+    #[test]
+    fn stream_takes_at_most_its_capacity() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (tx, objects) = ObjectStream::channel(2);
+            let mut live = objects.keep_live(4);
+            tx.send(Ok(object(0, 0))).await.unwrap();
+            tx.send(Ok(object(0, 1))).await.unwrap();
+
+            assert_eq!(location(live.recv().await.unwrap().unwrap()), (0, 0));
+            tx.try_send(Ok(object(0, 2))).unwrap();
+            tx.try_send(Ok(object(0, 3))).unwrap();
+
+            // Only (0, 2) is taken from the full channel to refill the
+            // stream, so the channel keeps a single free slot.
+            assert_eq!(location(live.recv().await.unwrap().unwrap()), (0, 1));
+            assert_eq!(live.edge.len(), 1);
+            tx.try_send(Ok(object(0, 4))).unwrap();
+            assert!(tx.try_send(Ok(object(0, 5))).is_err());
+        });
+    }
+}
@@ -1,13 +1,159 @@
 use bytes::Bytes;
+use std::future::Future;
 use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
+use std::time::Duration;
 use tokio::io::duplex;
 use tokio::io::{self, AsyncRead, AsyncWrite, DuplexStream};
 use tokio::sync::mpsc;
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
 
-use crate::transport::{BiStream, BoxError, Transport};
+use crate::model::ObjectStatus;
+use crate::track::{Object, ObjectMetadata};
+use crate::transport::{BiStream, BoxError, Capabilities, Transport, UniStream};
 
-pub struct MockUniStream(DuplexStream);
+/// Error returned by a [`MockTransport`] pair and its streams once either
+/// end was closed with [`MockTransport::close`]. Stream reads and writes
+/// fail with an [`io::Error`] of kind
+/// [`ConnectionAborted`](io::ErrorKind::ConnectionAborted) wrapping it.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("connection closed with code {code:#x}: {reason}")]
+pub struct ConnectionClosed {
+    pub code: u64,
+    pub reason: String,
+}
+
+/// Close state shared by both ends of a pair and all of their streams.
+#[derive(Clone, Default)]
+struct Connection {
+    closed: CancellationToken,
+    error: Arc<Mutex<Option<ConnectionClosed>>>,
+}
+
+impl Connection {
+    fn close(&self, code: u64, reason: &str) {
+        self.error
+            .lock()
+            .unwrap()
+            .get_or_insert_with(|| ConnectionClosed {
+                code,
+                reason: reason.to_string(),
+            });
+        self.closed.cancel();
+    }
+
+    fn error(&self) -> ConnectionClosed {
+        self.error
+            .lock()
+            .unwrap()
+            .clone()
+            .expect("error set before cancelling")
+    }
+
+    fn check(&self) -> Result<(), BoxError> {
+        if self.closed.is_cancelled() {
+            return Err(Box::new(self.error()));
+        }
+        Ok(())
+    }
+
+    fn stream(&self, pipe: DuplexStream) -> MockStream {
+        MockStream {
+            pipe,
+            connection: self.clone(),
+            closed: Box::pin(self.closed.clone().cancelled_owned()),
+        }
+    }
+}
+
+/// One direction of a mock stream. Pending and later reads and writes fail
+/// once the connection is closed.
+pub struct MockStream {
+    pipe: DuplexStream,
+    connection: Connection,
+    closed: Pin<Box<WaitForCancellationFutureOwned>>,
+}
+
+impl MockStream {
+    fn poll_closed(&mut self, cx: &mut Context<'_>) -> Poll<io::Error> {
+        self.closed
+            .as_mut()
+            .poll(cx)
+            .map(|()| io::Error::new(io::ErrorKind::ConnectionAborted, self.connection.error()))
+    }
+}
+
+impl AsyncRead for MockStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if let Poll::Ready(e) = this.poll_closed(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Pin::new(&mut this.pipe).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for MockStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if let Poll::Ready(e) = this.poll_closed(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Pin::new(&mut this.pipe).poll_write(cx, data)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if let Poll::Ready(e) = this.poll_closed(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Pin::new(&mut this.pipe).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if let Poll::Ready(e) = this.poll_closed(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Pin::new(&mut this.pipe).poll_shutdown(cx)
+    }
+}
+
+pub struct MockUniStream {
+    stream: MockStream,
+    reset: Option<u64>,
+}
+
+impl MockUniStream {
+    fn new(stream: MockStream) -> Self {
+        Self {
+            stream,
+            reset: None,
+        }
+    }
+
+    /// Error code passed to [`UniStream::reset`], if the stream was reset.
+    pub fn reset_code(&self) -> Option<u64> {
+        self.reset
+    }
+}
+
+impl UniStream for MockUniStream {
+    fn reset(&mut self, code: u64) {
+        // Dropping our end of the pipe ends the peer's reads.
+        self.stream.pipe = duplex(1).0;
+        self.reset = Some(code);
+    }
+}
 
 impl AsyncRead for MockUniStream {
     fn poll_read(
@@ -15,7 +161,7 @@ impl AsyncRead for MockUniStream {
         cx: &mut Context<'_>,
         buf: &mut io::ReadBuf<'_>,
     ) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.get_mut().0).poll_read(cx, buf)
+        Pin::new(&mut self.get_mut().stream).poll_read(cx, buf)
     }
 }
 
@@ -25,28 +171,81 @@ impl AsyncWrite for MockUniStream {
         cx: &mut Context<'_>,
         data: &[u8],
     ) -> Poll<io::Result<usize>> {
-        Pin::new(&mut self.get_mut().0).poll_write(cx, data)
+        if self.reset.is_some() {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, data)
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.get_mut().0).poll_flush(cx)
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
     }
 
     fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        Pin::new(&mut self.get_mut().0).poll_shutdown(cx)
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+/// Writer that limits throughput to a fixed number of bytes per second,
+/// simulating a bandwidth constrained path. Each write is charged against
+/// the budget and delays the next one accordingly.
+pub struct ThrottledWriter<W> {
+    inner: W,
+    bytes_per_second: u64,
+    sleep: Pin<Box<tokio::time::Sleep>>,
+}
+
+impl<W> ThrottledWriter<W> {
+    pub fn new(inner: W, bytes_per_second: u64) -> Self {
+        Self {
+            inner,
+            bytes_per_second,
+            sleep: Box::pin(tokio::time::sleep(Duration::ZERO)),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
     }
 }
 
-impl Unpin for MockUniStream {}
+impl<W: AsyncWrite + Unpin> AsyncWrite for ThrottledWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.sleep.as_mut().poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let n = match Pin::new(&mut this.inner).poll_write(cx, data) {
+            Poll::Ready(Ok(n)) => n,
+            other => return other,
+        };
+        let delay = Duration::from_secs_f64(n as f64 / this.bytes_per_second as f64);
+        let deadline = tokio::time::Instant::now() + delay;
+        this.sleep.as_mut().reset(deadline);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
 
 pub struct MockBiStream {
-    read: DuplexStream,
-    write: DuplexStream,
+    read: MockStream,
+    write: MockStream,
 }
 
 impl BiStream for MockBiStream {
-    type Reader = DuplexStream;
-    type Writer = DuplexStream;
+    type Reader = MockStream;
+    type Writer = MockStream;
 
     fn split(self) -> (Self::Reader, Self::Writer) {
         (self.read, self.write)
@@ -61,6 +260,9 @@ pub struct MockTransport {
     uni_tx: mpsc::Sender<DuplexStream>,
     bi_tx: mpsc::Sender<(DuplexStream, DuplexStream)>,
     datagram_tx: mpsc::Sender<Bytes>,
+    capabilities: Capabilities,
+    url_path: Option<String>,
+    connection: Connection,
 }
 
 impl MockTransport {
@@ -73,6 +275,7 @@ impl MockTransport {
 
         let (dg_tx_a, dg_rx_a) = mpsc::channel(8);
         let (dg_tx_b, dg_rx_b) = mpsc::channel(8);
+        let connection = Connection::default();
 
         let a = MockTransport {
             incoming_unis: uni_rx_a,
@@ -81,6 +284,9 @@ impl MockTransport {
             uni_tx: uni_tx_b,
             bi_tx: bi_tx_b,
             datagram_tx: dg_tx_b,
+            capabilities: Capabilities::default(),
+            url_path: None,
+            connection: connection.clone(),
         };
 
         let b = MockTransport {
@@ -90,13 +296,36 @@ impl MockTransport {
             uni_tx: uni_tx_a,
             bi_tx: bi_tx_a,
             datagram_tx: dg_tx_a,
+            capabilities: Capabilities::default(),
+            url_path: None,
+            connection,
         };
 
         (a, b)
     }
 
-    pub async fn recv_datagram(&mut self) -> Option<Bytes> {
-        self.incoming_datagrams.recv().await
+    /// Close the connection as with a QUIC CONNECTION_CLOSE. From then on
+    /// every operation of both ends, including pending accepts and reads on
+    /// their streams, fails with [`ConnectionClosed`] carrying `code` and
+    /// `reason`. Closing again keeps the first code and reason.
+    pub fn close(&self, code: u64, reason: &str) {
+        self.connection.close(code, reason);
+    }
+
+    /// Code and reason the connection was closed with, if it was.
+    pub fn close_reason(&self) -> Option<ConnectionClosed> {
+        self.connection.error.lock().unwrap().clone()
+    }
+
+    /// Capabilities reported by this end of the pair.
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+    }
+
+    /// Make this end report a WebTransport URL path, as if the session was
+    /// established over WebTransport.
+    pub fn set_url_path(&mut self, path: impl Into<String>) {
+        self.url_path = Some(path.into());
     }
 }
 
@@ -106,45 +335,144 @@ impl Transport for MockTransport {
     type Bi = MockBiStream;
 
     async fn open_uni_stream(&mut self) -> Result<Self::Uni, BoxError> {
+        self.connection.check()?;
         let (local, remote) = duplex(1024);
         self.uni_tx
             .send(remote)
             .await
             .map_err(|e| Box::new(e) as BoxError)?;
-        Ok(MockUniStream(local))
+        Ok(MockUniStream::new(self.connection.stream(local)))
     }
 
     async fn accept_uni_stream(&mut self) -> Result<Self::Uni, BoxError> {
-        match self.incoming_unis.recv().await {
-            Some(s) => Ok(MockUniStream(s)),
-            None => Err("channel closed".into()),
+        let connection = self.connection.clone();
+        match connection
+            .closed
+            .run_until_cancelled(self.incoming_unis.recv())
+            .await
+        {
+            Some(Some(s)) => Ok(MockUniStream::new(connection.stream(s))),
+            Some(None) => Err("channel closed".into()),
+            None => Err(Box::new(connection.error())),
         }
     }
 
     async fn open_bi_stream(&mut self) -> Result<Self::Bi, BoxError> {
+        self.connection.check()?;
         let (r1, r2) = duplex(1024);
         let (w1, w2) = duplex(1024);
         self.bi_tx
-            .send((r2, w2))
+            .send((w2, r2))
             .await
             .map_err(|e| Box::new(e) as BoxError)?;
         Ok(MockBiStream {
-            read: r1,
-            write: w1,
+            read: self.connection.stream(r1),
+            write: self.connection.stream(w1),
         })
     }
 
     async fn accept_bi_stream(&mut self) -> Result<Self::Bi, BoxError> {
-        match self.incoming_bis.recv().await {
-            Some((r, w)) => Ok(MockBiStream { read: r, write: w }),
-            None => Err("channel closed".into()),
+        let connection = self.connection.clone();
+        match connection
+            .closed
+            .run_until_cancelled(self.incoming_bis.recv())
+            .await
+        {
+            Some(Some((r, w))) => Ok(MockBiStream {
+                read: connection.stream(r),
+                write: connection.stream(w),
+            }),
+            Some(None) => Err("channel closed".into()),
+            None => Err(Box::new(connection.error())),
         }
     }
 
     async fn send_datagram(&mut self, data: Bytes) -> Result<(), BoxError> {
+        self.connection.check()?;
         self.datagram_tx
             .send(data)
             .await
             .map_err(|e| Box::new(e) as BoxError)
     }
+
+    /// The next datagram, or `None` once the connection is closed.
+    async fn recv_datagram(&mut self) -> Option<Bytes> {
+        self.connection
+            .closed
+            .run_until_cancelled(self.incoming_datagrams.recv())
+            .await
+            .flatten()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities.clone()
+    }
+
+    fn url_path(&self) -> Option<&str> {
+        self.url_path.as_deref()
+    }
+
+    fn close(&self, code: u64, reason: &str) {
+        self.connection.close(code, reason);
+    }
+}
+
+/// Builds the objects of tests: a normal object of track alias 0 and
+/// subgroup 0 with publisher priority 0 and a `frame` payload, unless set
+/// otherwise.
+#[derive(Debug, Clone)]
+pub struct ObjectBuilder {
+    object: Object,
+}
+
+impl ObjectBuilder {
+    pub fn new(group_id: u64, object_id: u64) -> Self {
+        Self {
+            object: Object {
+                metadata: ObjectMetadata {
+                    track_alias: 0,
+                    group_id,
+                    subgroup_id: 0,
+                    object_id,
+                    publisher_priority: 0,
+                },
+                status: ObjectStatus::Normal,
+                extension_headers: Bytes::new(),
+                payload: Bytes::from_static(b"frame"),
+            },
+        }
+    }
+
+    pub fn with_track_alias(mut self, track_alias: u64) -> Self {
+        self.object.metadata.track_alias = track_alias;
+        self
+    }
+
+    pub fn with_subgroup(mut self, subgroup_id: u64) -> Self {
+        self.object.metadata.subgroup_id = subgroup_id;
+        self
+    }
+
+    pub fn with_publisher_priority(mut self, priority: u8) -> Self {
+        self.object.metadata.publisher_priority = priority;
+        self
+    }
+
+    /// Any status but `Normal` also empties the payload.
+    pub fn with_status(mut self, status: ObjectStatus) -> Self {
+        if status != ObjectStatus::Normal {
+            self.object.payload = Bytes::new();
+        }
+        self.object.status = status;
+        self
+    }
+
+    pub fn with_payload(mut self, payload: impl Into<Bytes>) -> Self {
+        self.object.payload = payload.into();
+        self
+    }
+
+    pub fn build(self) -> Object {
+        self.object
+    }
 }
@@ -0,0 +1,198 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::{message::ControlMessage, track::Object};
+
+/// Hooks on the control messages and objects passing through a session,
+/// on which logging, metrics, qlog or capture can be built.
+///
+/// Hooks are called inline by the [`ControlMessageCodec`] and the
+/// [`TrackManager`] they are installed on, so they should be cheap and must
+/// not block. Every hook does nothing by default.
+///
+/// [`ControlMessageCodec`]: crate::codec::ControlMessageCodec
+/// [`TrackManager`]: crate::track::TrackManager
+pub trait Observer: Send + Sync {
+    /// `msg` is about to be encoded on the control stream.
+    fn on_message_sent(&self, _msg: &ControlMessage) {}
+
+    /// `msg` was decoded from the control stream.
+    fn on_message_received(&self, _msg: &ControlMessage) {}
+
+    /// `object` was queued on `subscriptions` subscriptions of its track.
+    fn on_object_forwarded(&self, _object: &Object, _subscriptions: usize) {}
+}
+
+/// Observers composed into one, called in the order they were added.
+///
+/// ```ignore
+/// let observers = Observers::new().with(Logger).with(metrics.clone());
+/// let config = SessionConfig::default().with_observers(observers);
+/// ```
+#[derive(Clone, Default)]
+pub struct Observers {
+    observers: Vec<Arc<dyn Observer>>,
+}
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Observers")
+            .field("len", &self.observers.len())
+            .finish()
+    }
+}
+
+impl Observers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, observer: impl Observer + 'static) -> Self {
+        self.observers.push(Arc::new(observer));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.observers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observers.is_empty()
+    }
+}
+
+impl Observer for Observers {
+    fn on_message_sent(&self, msg: &ControlMessage) {
+        for observer in &self.observers {
+            observer.on_message_sent(msg);
+        }
+    }
+
+    fn on_message_received(&self, msg: &ControlMessage) {
+        for observer in &self.observers {
+            observer.on_message_received(msg);
+        }
+    }
+
+    fn on_object_forwarded(&self, object: &Object, subscriptions: usize) {
+        for observer in &self.observers {
+            observer.on_object_forwarded(object, subscriptions);
+        }
+    }
+}
+
+impl<O: Observer + ?Sized> Observer for Arc<O> {
+    fn on_message_sent(&self, msg: &ControlMessage) {
+        (**self).on_message_sent(msg)
+    }
+
+    fn on_message_received(&self, msg: &ControlMessage) {
+        (**self).on_message_received(msg)
+    }
+
+    fn on_object_forwarded(&self, object: &Object, subscriptions: usize) {
+        (**self).on_object_forwarded(object, subscriptions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use bytes::{Bytes, BytesMut};
+    use tokio_util::codec::{Decoder, Encoder};
+
+    use super::*;
+    use crate::{
+        message::{MaxRequestId, SubscribeOk},
+        mock::MockTransport,
+        request::SubscribeRequest,
+        session::{Session, SessionConfig},
+        track::TrackPublisher,
+    };
+
+    struct Recorder {
+        name: &'static str,
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Observer for Recorder {
+        fn on_message_sent(&self, msg: &ControlMessage) {
+            let event = format!("{} sent {:?}", self.name, msg.message_type().unwrap());
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn on_message_received(&self, msg: &ControlMessage) {
+            let event = format!("{} received {:?}", self.name, msg.message_type().unwrap());
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn on_object_forwarded(&self, object: &Object, subscriptions: usize) {
+            let event = format!(
+                "{} forwarded {} to {subscriptions}",
+                self.name, object.metadata.object_id
+            );
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    #[test]
+    fn observers_see_messages_and_objects() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorder = |name| Recorder {
+            name,
+            events: events.clone(),
+        };
+        let observers = Observers::new()
+            .with(recorder("log"))
+            .with(recorder("metrics"));
+        assert_eq!(observers.len(), 2);
+        let (transport, _peer) = MockTransport::pair();
+        let (session, _control) = Session::with_config(
+            Arc::new(transport),
+            SessionConfig::default().with_observers(observers),
+        );
+
+        let mut codec = session.control_codec();
+        let mut buf = BytesMut::new();
+        let max = ControlMessage::MaxRequestId(MaxRequestId { request_id: 1 });
+        codec.encode(max, &mut buf).unwrap();
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        session.track_manager.handle_max_request_id(1).unwrap();
+        let handle = rt
+            .block_on(session.subscribe(SubscribeRequest::new(0, "video")))
+            .unwrap();
+        session
+            .track_manager
+            .handle_subscribe_ok(&SubscribeOk {
+                request_id: handle.request_id(),
+                track_alias: 1,
+                expires: 0,
+                group_order: 1,
+                content_exists: false,
+                largest_location: None,
+                parameters: Vec::new(),
+            })
+            .unwrap();
+        let mut publisher = TrackPublisher::new(1);
+        for object in publisher.push_frame(true, Bytes::from_static(b"key")) {
+            session.track_manager.deliver(object);
+        }
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "log sent MAX_REQUEST_ID(0x15)",
+                "metrics sent MAX_REQUEST_ID(0x15)",
+                "log received MAX_REQUEST_ID(0x15)",
+                "metrics received MAX_REQUEST_ID(0x15)",
+                "log forwarded 0 to 1",
+                "metrics forwarded 0 to 1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,15 @@
+//! The types most applications need, for a single glob import:
+//!
+//! ```
+//! use moqt_transport::prelude::*;
+//! ```
+
+pub use crate::{
+    error::{Error, RequestErrorCode, TerminationCode},
+    listener::{Acceptor, Host, Listener},
+    model::{Filter, Location},
+    request::{AnnounceRequest, SubscribeRequest},
+    session::{Session, SessionConfig},
+    track::{FullTrackName, Object, ObjectStream, TrackNamespace, TrackPublisher},
+    transport::Transport,
+};
@@ -0,0 +1,299 @@
+use std::time::Duration;
+
+use crate::{
+    codec::MAX_CACHE_DURATION,
+    error::Error,
+    message::{Publish, PublishOk},
+    model::{Filter, Location, Parameter},
+    scheduler::{Priority, check_group_order},
+    track::ObjectMetadata,
+};
+
+/// DELIVERY TIMEOUT version specific parameter.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-delivery-timeout-parameter
+pub const DELIVERY_TIMEOUT: u64 = 0x02;
+
+/// Subscriber side answer to an incoming PUBLISH.
+///
+/// ```ignore
+/// let ok = PublishResponse::accept()
+///     .with_filter(Filter::next_group())
+///     .with_subscriber_priority(10)
+///     .into_publish_ok(publish.request_id);
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PublishResponse {
+    forward: bool,
+    subscriber_priority: u8,
+    group_order: u8,
+    filter: Filter,
+    delivery_timeout: Option<Duration>,
+}
+
+impl PublishResponse {
+    /// Accept the publication with default delivery parameters: forwarding
+    /// enabled, subscriber priority 128, ascending group order and a
+    /// Largest Object filter.
+    pub fn accept() -> Self {
+        Self {
+            forward: true,
+            subscriber_priority: 128,
+            group_order: 0x1,
+            filter: Filter::LargestObject,
+            delivery_timeout: None,
+        }
+    }
+
+    pub fn with_forward(mut self, forward: bool) -> Self {
+        self.forward = forward;
+        self
+    }
+
+    pub fn with_subscriber_priority(mut self, priority: u8) -> Self {
+        self.subscriber_priority = priority;
+        self
+    }
+
+    pub fn with_group_order(mut self, group_order: u8) -> Self {
+        self.group_order = group_order;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_delivery_timeout(mut self, timeout: Duration) -> Self {
+        self.delivery_timeout = Some(timeout);
+        self
+    }
+
+    pub fn into_publish_ok(self, request_id: u64) -> Result<PublishOk, Error> {
+        let mut parameters = Vec::new();
+        if let Some(timeout) = self.delivery_timeout {
+            parameters.push(duration_parameter(DELIVERY_TIMEOUT, timeout)?);
+        }
+        let (filter_type, start, end_group) = self.filter.to_parts();
+        Ok(PublishOk {
+            request_id,
+            forward: self.forward as u8,
+            subscriber_priority: self.subscriber_priority,
+            group_order: self.group_order,
+            filter_type,
+            start,
+            end_group,
+            parameters,
+        })
+    }
+}
+
+/// Delivery parameters negotiated for a subscription created by PUBLISH.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DeliveryParams {
+    pub forward: bool,
+    pub subscriber_priority: u8,
+    pub group_order: u8,
+    pub filter: Filter,
+    /// Largest location announced in PUBLISH, used to resolve relative
+    /// filters.
+    pub largest: Option<Location>,
+    /// Minimum of the publisher's and subscriber's DELIVERY TIMEOUT.
+    pub delivery_timeout: Option<Duration>,
+}
+
+impl DeliveryParams {
+    /// Combine the PUBLISH sent by this endpoint with the PUBLISH_OK
+    /// received from the subscriber.
+    pub fn negotiate(publish: &Publish, ok: &PublishOk) -> Result<Self, Error> {
+        if publish.request_id != ok.request_id {
+            return Err(Error::ProtocolViolation {
+                reason: "PUBLISH_OK request id mismatch".into(),
+                context: None,
+            });
+        }
+        let filter = Filter::from_parts(ok.filter_type, ok.start.clone(), ok.end_group)?;
+        let delivery_timeout = match (
+            delivery_timeout(&publish.parameters)?,
+            delivery_timeout(&ok.parameters)?,
+        ) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        Ok(Self {
+            forward: ok.forward == 1,
+            subscriber_priority: ok.subscriber_priority,
+            group_order: check_group_order(ok.group_order)?,
+            filter,
+            largest: publish.largest.clone(),
+            delivery_timeout,
+        })
+    }
+
+    /// Scheduling priority of an object sent on this subscription.
+    pub fn priority(&self, object: &ObjectMetadata) -> Priority {
+        Priority::for_object(self.subscriber_priority, self.group_order, object)
+    }
+
+    /// Whether an object at `loc` should be sent on this subscription.
+    pub fn should_forward(&self, loc: &Location) -> bool {
+        self.forward && self.filter.matches(loc, self.largest.as_ref())
+    }
+}
+
+fn duration_parameter(parameter_type: u64, value: Duration) -> Result<Parameter, Error> {
+    Ok(Parameter::varint(parameter_type, value.as_millis() as u64)?)
+}
+
+fn duration(parameters: &[Parameter], parameter_type: u64) -> Result<Option<Duration>, Error> {
+    parameters
+        .iter()
+        .find(|p| p.parameter_type == parameter_type)
+        .map(|p| Ok(Duration::from_millis(p.as_varint()?)))
+        .transpose()
+}
+
+fn delivery_timeout(parameters: &[Parameter]) -> Result<Option<Duration>, Error> {
+    duration(parameters, DELIVERY_TIMEOUT)
+}
+
+/// MAX_CACHE_DURATION parameter announcing how long objects of a track may
+/// be cached, for SUBSCRIBE_OK, PUBLISH or FETCH_OK.
+pub fn max_cache_duration_parameter(max: Duration) -> Result<Parameter, Error> {
+    duration_parameter(MAX_CACHE_DURATION, max)
+}
+
+/// How long objects may be cached according to the MAX_CACHE_DURATION in
+/// `parameters`, `None` if there is no limit.
+pub fn max_cache_duration(parameters: &[Parameter]) -> Result<Option<Duration>, Error> {
+    duration(parameters, MAX_CACHE_DURATION)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+
+    use super::*;
+
+    fn publish() -> Publish {
+        Publish {
+            request_id: 3,
+            track_namespace: 1,
+            track_name: "video".into(),
+            track_alias: 9,
+            group_order: 1,
+            content_exists: 1,
+            largest: Some(Location {
+                group: 4,
+                object: 2,
+            }),
+            forward: 1,
+            parameters: vec![
+                duration_parameter(DELIVERY_TIMEOUT, Duration::from_millis(500)).unwrap(),
+            ],
+        }
+    }
+
+    #[test]
+    fn builder_produces_publish_ok() {
+        let ok = PublishResponse::accept()
+            .with_filter(
+                Filter::absolute_range(
+                    Location {
+                        group: 5,
+                        object: 0,
+                    },
+                    8,
+                )
+                .unwrap(),
+            )
+            .with_subscriber_priority(7)
+            .with_delivery_timeout(Duration::from_millis(200))
+            .into_publish_ok(3)
+            .unwrap();
+
+        assert_eq!(ok.filter_type, 0x4);
+        assert_eq!(
+            ok.start,
+            Some(Location {
+                group: 5,
+                object: 0
+            })
+        );
+        assert_eq!(ok.end_group, Some(8));
+        assert_eq!(ok.subscriber_priority, 7);
+
+        let mut buf = BytesMut::new();
+        ok.encode(&mut buf).unwrap();
+        assert_eq!(PublishOk::decode(&mut buf).unwrap(), ok);
+    }
+
+    #[test]
+    fn negotiation_honors_subscriber_choices() {
+        let ok = PublishResponse::accept()
+            .with_filter(Filter::NextGroupStart)
+            .with_delivery_timeout(Duration::from_millis(200))
+            .into_publish_ok(3)
+            .unwrap();
+        let params = DeliveryParams::negotiate(&publish(), &ok).unwrap();
+
+        assert_eq!(params.delivery_timeout, Some(Duration::from_millis(200)));
+        assert!(!params.should_forward(&Location {
+            group: 4,
+            object: 3
+        }));
+        assert!(params.should_forward(&Location {
+            group: 5,
+            object: 0
+        }));
+    }
+
+    #[test]
+    fn negotiated_group_order_drives_priority() {
+        let ok = PublishResponse::accept()
+            .with_group_order(0x2)
+            .into_publish_ok(3)
+            .unwrap();
+        let params = DeliveryParams::negotiate(&publish(), &ok).unwrap();
+        let metadata = |group_id| ObjectMetadata {
+            track_alias: 9,
+            group_id,
+            subgroup_id: 0,
+            object_id: 0,
+            publisher_priority: 0,
+        };
+        let mut scheduler = crate::scheduler::Scheduler::default();
+        for group in 0..3 {
+            scheduler.push(params.priority(&metadata(group)), group);
+        }
+        assert_eq!(scheduler.pop(), Some(2));
+
+        let unstated = PublishOk {
+            group_order: 0x0,
+            ..ok
+        };
+        assert!(DeliveryParams::negotiate(&publish(), &unstated).is_err());
+    }
+
+    #[test]
+    fn negotiation_respects_forward_flag() {
+        let ok = PublishResponse::accept()
+            .with_forward(false)
+            .into_publish_ok(3)
+            .unwrap();
+        let params = DeliveryParams::negotiate(&publish(), &ok).unwrap();
+
+        assert_eq!(params.delivery_timeout, Some(Duration::from_millis(500)));
+        assert!(!params.should_forward(&Location {
+            group: 9,
+            object: 0
+        }));
+    }
+
+    #[test]
+    fn negotiation_rejects_mismatched_request() {
+        let ok = PublishResponse::accept().into_publish_ok(4).unwrap();
+        assert!(DeliveryParams::negotiate(&publish(), &ok).is_err());
+    }
+}
@@ -0,0 +1,332 @@
+use std::future::{Future, poll_fn};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+
+use tokio::time::Sleep;
+
+use crate::{
+    error::Error, model::Filter, request::SubscribeRequest, session::Session,
+    subscription::SubscriptionHandle, track::Object, transport::Transport,
+};
+
+/// Default of [`RenditionSwitcher::with_drain_timeout`].
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The subscription being switched away from, forwarded until the group
+/// at which the new rendition starts.
+struct Draining {
+    subscription: SubscriptionHandle,
+    boundary: u64,
+    /// When the subscription is abandoned if it did not reach `boundary`.
+    deadline: Pin<Box<Sleep>>,
+}
+
+/// Subscriber-side view of several renditions of the same content, e.g.
+/// the quality variants listed by a catalog, as a single stream of
+/// objects.
+///
+/// [`switch_to`](Self::switch_to) subscribes to the new rendition from
+/// its next group on. Objects of the current rendition keep flowing until
+/// the new one delivers its first group, after which the current one is
+/// forwarded up to that group and then unsubscribed, so every group comes
+/// from exactly one rendition. A current rendition that does not get to
+/// that group within the [drain timeout](Self::with_drain_timeout) is
+/// unsubscribed anyway, and the rest of its groups before the boundary
+/// are lost.
+pub struct RenditionSwitcher<T: Transport> {
+    session: Arc<Session<T>>,
+    current: SubscriptionHandle,
+    track: String,
+    next: Option<(SubscriptionHandle, String)>,
+    draining: Option<Draining>,
+    drain_timeout: Duration,
+    /// First object of the current rendition, held back while the previous
+    /// one drains.
+    first: Option<Object>,
+}
+
+enum Ready {
+    Current(Option<Result<Object, Error>>),
+    Next(Option<Result<Object, Error>>),
+}
+
+impl<T: Transport> RenditionSwitcher<T> {
+    /// Subscribe to the initial rendition.
+    pub async fn new(session: Arc<Session<T>>, request: SubscribeRequest) -> Result<Self, Error> {
+        let track = request.track_name().to_string();
+        Ok(Self {
+            current: session.subscribe(request).await?,
+            session,
+            track,
+            next: None,
+            draining: None,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            first: None,
+        })
+    }
+
+    /// How long the previous rendition may take to reach the first group of
+    /// the new one, 5 seconds by default.
+    pub fn with_drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    /// Track name of the rendition objects are currently taken from.
+    pub fn current_track(&self) -> &str {
+        &self.track
+    }
+
+    /// Track name of the rendition being switched to, until it delivers its
+    /// first object.
+    pub fn pending_track(&self) -> Option<&str> {
+        self.next.as_ref().map(|(_, track)| track.as_str())
+    }
+
+    pub fn current(&self) -> &SubscriptionHandle {
+        &self.current
+    }
+
+    /// Switch to another rendition at its next group boundary. The filter
+    /// of `request` is replaced with [`Filter::next_group`]. A switch still
+    /// pending is abandoned and its subscription ended.
+    pub async fn switch_to(&mut self, request: SubscribeRequest) -> Result<(), Error> {
+        let track = request.track_name().to_string();
+        let subscription = self
+            .session
+            .subscribe(request.with_filter(Filter::next_group()))
+            .await?;
+        if let Some((abandoned, _)) = self.next.replace((subscription, track)) {
+            self.session.unsubscribe(abandoned).await?;
+        }
+        Ok(())
+    }
+
+    /// Receive the next object, or `None` once the current rendition ended.
+    pub async fn recv(&mut self) -> Option<Result<Object, Error>> {
+        loop {
+            if let Some(draining) = &mut self.draining {
+                let item = poll_fn(|cx| {
+                    if let Poll::Ready(item) = draining.subscription.poll_recv(cx) {
+                        return Poll::Ready(item);
+                    }
+                    draining.deadline.as_mut().poll(cx).map(|()| None)
+                })
+                .await;
+                match item {
+                    Some(Ok(object)) if object.metadata.group_id < draining.boundary => {
+                        return Some(Ok(object));
+                    }
+                    // Errors of the old rendition no longer matter.
+                    _ => {
+                        let draining = self.draining.take().expect("draining above");
+                        if let Err(e) = self.session.unsubscribe(draining.subscription).await {
+                            return Some(Err(e));
+                        }
+                    }
+                }
+            }
+            if let Some(object) = self.first.take() {
+                return Some(Ok(object));
+            }
+            let Some((next, _)) = &mut self.next else {
+                return self.current.recv().await;
+            };
+
+            let current = &mut self.current;
+            let ready = poll_fn(|cx| {
+                if let Poll::Ready(item) = next.poll_recv(cx) {
+                    return Poll::Ready(Ready::Next(item));
+                }
+                current.poll_recv(cx).map(Ready::Current)
+            })
+            .await;
+            match ready {
+                Ready::Current(item) => return item,
+                Ready::Next(Some(Ok(object))) => {
+                    let (next, track) = self.next.take().expect("switching above");
+                    self.draining = Some(Draining {
+                        subscription: std::mem::replace(&mut self.current, next),
+                        boundary: object.metadata.group_id,
+                        deadline: Box::pin(tokio::time::sleep(self.drain_timeout)),
+                    });
+                    self.track = track;
+                    self.first = Some(object);
+                }
+                // The switch failed; stay on the current rendition.
+                Ready::Next(item) => {
+                    let (failed, _) = self.next.take().expect("switching above");
+                    self.session
+                        .track_manager
+                        .end_subscription(failed.request_id(), None);
+                    if let Some(Err(e)) = item {
+                        return Some(Err(e));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::message::{ControlMessage, SubscribeOk};
+    use crate::mock::{MockTransport, ObjectBuilder};
+
+    fn object(track_alias: u64, group_id: u64, object_id: u64) -> Object {
+        ObjectBuilder::new(group_id, object_id)
+            .with_track_alias(track_alias)
+            .build()
+    }
+
+    fn subscribe_ok(request_id: u64, track_alias: u64) -> SubscribeOk {
+        SubscribeOk {
+            request_id,
+            track_alias,
+            expires: 0,
+            group_order: 1,
+            content_exists: false,
+            largest_location: None,
+            parameters: Vec::new(),
+        }
+    }
+
+    async fn next(switcher: &mut RenditionSwitcher<MockTransport>) -> (u64, u64, u64) {
+        let o = switcher.recv().await.unwrap().unwrap().metadata;
+        (o.track_alias, o.group_id, o.object_id)
+    }
+
+    #[test]
+    fn switch_happens_at_group_boundary() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (transport, _peer) = MockTransport::pair();
+            let (session, mut rx) = Session::new(Arc::new(transport));
+            let session = Arc::new(session);
+            session.track_manager.handle_max_request_id(10).unwrap();
+            let tracks = &session.track_manager;
+
+            let mut switcher =
+                RenditionSwitcher::new(session.clone(), SubscribeRequest::new(1, "720p"))
+                    .await
+                    .unwrap();
+            tracks.handle_subscribe_ok(&subscribe_ok(0, 1)).unwrap();
+            tracks.deliver(object(1, 4, 0));
+            assert_eq!(next(&mut switcher).await, (1, 4, 0));
+
+            switcher
+                .switch_to(SubscribeRequest::new(1, "1080p"))
+                .await
+                .unwrap();
+            assert_eq!(switcher.pending_track(), Some("1080p"));
+            tracks.handle_subscribe_ok(&subscribe_ok(1, 2)).unwrap();
+            tracks.deliver(object(1, 5, 0));
+            assert_eq!(next(&mut switcher).await, (1, 5, 0));
+
+            // The new rendition starts at group 6 while the old one is
+            // still sending group 5.
+            tracks.deliver(object(2, 6, 0));
+            tracks.deliver(object(1, 5, 1));
+            tracks.deliver(object(1, 6, 0));
+            tracks.deliver(object(2, 6, 1));
+            assert_eq!(next(&mut switcher).await, (1, 5, 1));
+            assert_eq!(next(&mut switcher).await, (2, 6, 0));
+            assert_eq!(next(&mut switcher).await, (2, 6, 1));
+            assert_eq!(switcher.current_track(), "1080p");
+            assert_eq!(switcher.pending_track(), None);
+            let subscriptions = tracks.subscriptions();
+            assert_eq!(subscriptions.len(), 1);
+            assert_eq!(subscriptions[0].request_id, 1);
+
+            assert!(matches!(rx.recv().await, Some(ControlMessage::Subscribe(s)) if s.track_name == "720p"));
+            match rx.recv().await {
+                Some(ControlMessage::Subscribe(s)) => {
+                    assert_eq!(s.track_name, "1080p");
+                    assert_eq!(s.filter(), &Filter::NextGroupStart);
+                }
+                _ => panic!("expected SUBSCRIBE"),
+            }
+            match rx.recv().await {
+                Some(ControlMessage::Unsubscribe(u)) => assert_eq!(u.request_id, 0),
+                _ => panic!("expected UNSUBSCRIBE"),
+            }
+        });
+    }
+
+    #[test]
+    fn stalled_rendition_is_abandoned_after_drain_timeout() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .start_paused(true)
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (transport, _peer) = MockTransport::pair();
+            let (session, _rx) = Session::new(Arc::new(transport));
+            let session = Arc::new(session);
+            session.track_manager.handle_max_request_id(10).unwrap();
+            let tracks = &session.track_manager;
+
+            let mut switcher =
+                RenditionSwitcher::new(session.clone(), SubscribeRequest::new(1, "720p"))
+                    .await
+                    .unwrap()
+                    .with_drain_timeout(Duration::from_secs(1));
+            tracks.handle_subscribe_ok(&subscribe_ok(0, 1)).unwrap();
+            switcher
+                .switch_to(SubscribeRequest::new(1, "1080p"))
+                .await
+                .unwrap();
+            tracks.handle_subscribe_ok(&subscribe_ok(1, 2)).unwrap();
+
+            // The old rendition never gets to group 6.
+            let start = tokio::time::Instant::now();
+            tracks.deliver(object(2, 6, 0));
+            tracks.deliver(object(2, 6, 1));
+            assert_eq!(next(&mut switcher).await, (2, 6, 0));
+            assert_eq!(next(&mut switcher).await, (2, 6, 1));
+            assert_eq!(start.elapsed(), Duration::from_secs(1));
+            assert_eq!(tracks.subscriptions().len(), 1);
+        });
+    }
+
+    #[test]
+    fn abandoned_switch_is_ended() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (transport, _peer) = MockTransport::pair();
+            let (session, _rx) = Session::new(Arc::new(transport));
+            let session = Arc::new(session);
+            session.track_manager.handle_max_request_id(10).unwrap();
+
+            let mut switcher =
+                RenditionSwitcher::new(session.clone(), SubscribeRequest::new(1, "720p"))
+                    .await
+                    .unwrap();
+            for track in ["1080p", "480p"] {
+                switcher
+                    .switch_to(SubscribeRequest::new(1, track))
+                    .await
+                    .unwrap();
+            }
+            assert_eq!(switcher.pending_track(), Some("480p"));
+            let requests: Vec<_> = session
+                .track_manager
+                .subscriptions()
+                .iter()
+                .map(|s| s.request_id)
+                .collect();
+            assert_eq!(requests, [0, 2]);
+        });
+    }
+}
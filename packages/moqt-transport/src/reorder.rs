@@ -0,0 +1,364 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_core::Stream;
+use tokio::time::{Instant, Sleep};
+
+use crate::error::Error;
+use crate::model::ObjectStatus;
+use crate::track::{Object, ObjectKey, ObjectStream};
+
+/// Bounds of a [`ReorderBuffer`]. Once any bound is exceeded the oldest
+/// buffered objects are released even if earlier objects are missing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReorderLimits {
+    max_objects: usize,
+    max_bytes: usize,
+    max_delay: Duration,
+}
+
+impl Default for ReorderLimits {
+    /// 64 objects, 1 MiB of payload and 100 ms of delay.
+    fn default() -> Self {
+        Self {
+            max_objects: 64,
+            max_bytes: 1 << 20,
+            max_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+impl ReorderLimits {
+    pub fn with_max_objects(mut self, max_objects: usize) -> Self {
+        self.max_objects = max_objects;
+        self
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+}
+
+/// Optional subscriber-side buffer emitting objects that arrive out of
+/// order on several subgroup streams in [`ObjectKey`] order.
+///
+/// An object is released as soon as nothing can be missing before it: it
+/// directly follows the last released object of its group, whichever
+/// subgroup carried either, or it is the first object of a group and the
+/// previous group ended or nothing was released yet. Otherwise it is held
+/// until holding it back would exceed the [`ReorderLimits`]. Objects
+/// ordered before an already released object are dropped since emitting
+/// them would break the ordering.
+pub struct ReorderBuffer {
+    limits: ReorderLimits,
+    pending: BTreeMap<ObjectKey, (Object, Instant)>,
+    bytes: usize,
+    last: Option<ObjectKey>,
+    /// Whether the last released object ended its group.
+    group_ended: bool,
+    dropped: u64,
+}
+
+impl ReorderBuffer {
+    pub fn new(limits: ReorderLimits) -> Self {
+        Self {
+            limits,
+            pending: BTreeMap::new(),
+            bytes: 0,
+            last: None,
+            group_ended: false,
+            dropped: 0,
+        }
+    }
+
+    /// Buffer an object received at `now` and return the objects that can
+    /// be released, in order.
+    pub fn push(&mut self, object: Object, now: Instant) -> Vec<Object> {
+        let key = object.metadata.key().untracked();
+        if self.last.is_some_and(|last| key <= last) || self.pending.contains_key(&key) {
+            self.dropped += 1;
+            return Vec::new();
+        }
+        self.bytes += object.payload.len();
+        self.pending.insert(key, (object, now));
+
+        let mut released = Vec::new();
+        while self.pending.len() > self.limits.max_objects || self.bytes > self.limits.max_bytes {
+            released.extend(self.pop());
+        }
+        released.extend(self.expire(now));
+        released
+    }
+
+    /// Release the objects whose delay bound expired at `now`, along with
+    /// every object ordered before them.
+    pub fn expire(&mut self, now: Instant) -> Vec<Object> {
+        let mut released = Vec::new();
+        while let Some(deadline) = self.next_deadline() {
+            if deadline > now {
+                break;
+            }
+            let oldest = self
+                .pending
+                .iter()
+                .min_by_key(|(_, (_, at))| *at)
+                .map(|(key, _)| *key);
+            while let Some(key) = self.pending.keys().next().copied() {
+                released.extend(self.pop());
+                if Some(key) == oldest {
+                    break;
+                }
+            }
+        }
+        while self.pending.keys().next().is_some_and(|&k| self.follows(k)) {
+            released.extend(self.pop());
+        }
+        released
+    }
+
+    /// When the oldest buffered object must be released.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending
+            .values()
+            .map(|(_, at)| *at + self.limits.max_delay)
+            .min()
+    }
+
+    /// Release every buffered object, e.g. when the subscription ends.
+    pub fn flush(&mut self) -> Vec<Object> {
+        let mut released = Vec::with_capacity(self.pending.len());
+        while let Some(object) = self.pop() {
+            released.push(object);
+        }
+        released
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Objects dropped because they arrived after later objects had been
+    /// released, or were duplicates.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Whether no object can be missing between the last released object
+    /// and `key`. Object IDs are unique within a group, so the subgroups
+    /// carrying them do not matter.
+    fn follows(&self, key: ObjectKey) -> bool {
+        match self.last {
+            Some(last) if last.group == key.group => last.object + 1 == key.object,
+            Some(_) => key.object == 0 && self.group_ended,
+            None => key.object == 0,
+        }
+    }
+
+    fn pop(&mut self) -> Option<Object> {
+        let (key, (object, _)) = self.pending.pop_first()?;
+        self.bytes -= object.payload.len();
+        self.last = Some(key);
+        self.group_ended = object.status == ObjectStatus::EndOfGroup;
+        Some(object)
+    }
+}
+
+/// An [`ObjectStream`] consumed through a [`ReorderBuffer`], created by
+/// [`ObjectStream::reorder`]. Held objects are released when their delay
+/// bound expires even if no further object arrives, and every held object
+/// is released once the stream ended.
+pub struct ReorderStream {
+    objects: Option<ObjectStream>,
+    buffer: ReorderBuffer,
+    ready: VecDeque<Object>,
+    timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl ReorderStream {
+    pub(crate) fn new(objects: ObjectStream, limits: ReorderLimits) -> Self {
+        Self {
+            objects: Some(objects),
+            buffer: ReorderBuffer::new(limits),
+            ready: VecDeque::new(),
+            timer: None,
+        }
+    }
+
+    /// Receive the next object in order, or `None` once the stream ended
+    /// and every held object was received.
+    pub async fn recv(&mut self) -> Option<Result<Object, Error>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Objects dropped because they arrived too late or twice.
+    pub fn dropped(&self) -> u64 {
+        self.buffer.dropped()
+    }
+}
+
+impl Stream for ReorderStream {
+    type Item = Result<Object, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if let Some(object) = this.ready.pop_front() {
+                return Poll::Ready(Some(Ok(object)));
+            }
+            let Some(objects) = this.objects.as_mut() else {
+                return Poll::Ready(None);
+            };
+            match Pin::new(objects).poll_next(cx) {
+                Poll::Ready(Some(Ok(object))) => {
+                    let released = this.buffer.push(object, Instant::now());
+                    this.ready.extend(released);
+                    continue;
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => {
+                    this.objects = None;
+                    this.timer = None;
+                    this.ready.extend(this.buffer.flush());
+                    continue;
+                }
+                Poll::Pending => {}
+            }
+            let Some(deadline) = this.buffer.next_deadline() else {
+                this.timer = None;
+                return Poll::Pending;
+            };
+            let timer = match &mut this.timer {
+                Some(timer) => {
+                    timer.as_mut().reset(deadline);
+                    timer
+                }
+                timer => timer.insert(Box::pin(tokio::time::sleep_until(deadline))),
+            };
+            if timer.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            let released = this.buffer.expire(Instant::now().max(deadline));
+            this.ready.extend(released);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::ObjectBuilder;
+    use bytes::Bytes;
+
+    fn object(group_id: u64, subgroup_id: u64, object_id: u64) -> Object {
+        ObjectBuilder::new(group_id, object_id)
+            .with_subgroup(subgroup_id)
+            .build()
+    }
+
+    fn ids(objects: &[Object]) -> Vec<(u64, u64)> {
+        objects
+            .iter()
+            .map(|o| (o.metadata.group_id, o.metadata.object_id))
+            .collect()
+    }
+
+    #[test]
+    fn contiguous_objects_release_immediately() {
+        let now = Instant::now();
+        let mut buffer = ReorderBuffer::new(ReorderLimits::default());
+        assert_eq!(ids(&buffer.push(object(0, 0, 0), now)), vec![(0, 0)]);
+
+        // Object 1 is carried by another subgroup than objects 0 and 2.
+        assert!(buffer.push(object(0, 0, 2), now).is_empty());
+        assert_eq!(
+            ids(&buffer.push(object(0, 1, 1), now)),
+            vec![(0, 1), (0, 2)]
+        );
+        assert!(buffer.is_empty());
+
+        // Late object after its successors were released is dropped.
+        assert!(buffer.push(object(0, 0, 0), now).is_empty());
+        assert_eq!(buffer.dropped(), 1);
+    }
+
+    #[test]
+    fn next_group_waits_for_end_of_group() {
+        let now = Instant::now();
+        let mut buffer = ReorderBuffer::new(ReorderLimits::default());
+        assert_eq!(ids(&buffer.push(object(0, 0, 0), now)), vec![(0, 0)]);
+        assert!(buffer.push(object(1, 0, 0), now).is_empty());
+
+        let mut end = object(0, 0, 1);
+        end.status = ObjectStatus::EndOfGroup;
+        end.payload = Bytes::new();
+        assert_eq!(ids(&buffer.push(end, now)), vec![(0, 1), (1, 0)]);
+    }
+
+    #[test]
+    fn limits_force_release_in_order() {
+        let now = Instant::now();
+        let limits = ReorderLimits::default()
+            .with_max_objects(2)
+            .with_max_delay(Duration::from_millis(50));
+        let mut buffer = ReorderBuffer::new(limits);
+
+        // Subgroup 1 arrives before subgroup 0 of the same group.
+        assert!(buffer.push(object(0, 1, 5), now).is_empty());
+        assert!(buffer.push(object(0, 0, 1), now).is_empty());
+        let released = buffer.push(object(0, 0, 0), now);
+        assert_eq!(ids(&released), vec![(0, 0), (0, 1)]);
+        assert_eq!(buffer.len(), 1);
+
+        let later = now + Duration::from_millis(10);
+        buffer.push(object(1, 2, 0), later);
+        assert_eq!(
+            buffer.next_deadline(),
+            Some(now + Duration::from_millis(50))
+        );
+        let released = buffer.expire(now + Duration::from_millis(50));
+        assert_eq!(ids(&released), vec![(0, 5)]);
+        assert_eq!(ids(&buffer.flush()), vec![(1, 0)]);
+    }
+
+    #[test]
+    fn stream_releases_held_objects_when_delay_expires() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .start_paused(true)
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (tx, objects) = ObjectStream::channel(8);
+            let limits = ReorderLimits::default().with_max_delay(Duration::from_millis(50));
+            let mut stream = objects.reorder(limits);
+
+            tx.send(Ok(object(0, 0, 1))).await.unwrap();
+            tx.send(Ok(object(0, 0, 2))).await.unwrap();
+            let start = Instant::now();
+            let first = stream.recv().await.unwrap().unwrap();
+            assert_eq!(first.metadata.object_id, 1);
+            assert_eq!(start.elapsed(), Duration::from_millis(50));
+            let second = stream.recv().await.unwrap().unwrap();
+            assert_eq!(second.metadata.object_id, 2);
+
+            tx.send(Ok(object(0, 0, 4))).await.unwrap();
+            drop(tx);
+            let last = stream.recv().await.unwrap().unwrap();
+            assert_eq!(last.metadata.object_id, 4);
+            assert!(stream.recv().await.is_none());
+        });
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::{
+    message::{Fetch, Subscribe},
+    model::{Location, ObjectStatus},
+    track::Object,
+};
+
+/// Whether and how far back a subscription delivered over datagrams repairs
+/// lost objects with a FETCH.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RepairPolicy {
+    /// Never repair.
+    #[default]
+    Off,
+    /// Repair at most the last N objects before the object revealing a gap.
+    LastObjects(u64),
+    /// Repair gaps in groups whose first object arrived within the window.
+    TimeWindow(Duration),
+}
+
+/// Objects `first..=last` of a group detected missing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepairRange {
+    pub group_id: u64,
+    pub first_object: u64,
+    pub last_object: u64,
+}
+
+impl RepairRange {
+    /// Standalone FETCH for the missing objects.
+    pub fn into_fetch(
+        self,
+        request_id: u64,
+        track_namespace: u64,
+        track_name: String,
+        subscriber_priority: u8,
+    ) -> Fetch {
+        Fetch {
+            request_id,
+            subscriber_priority,
+            group_order: 0x1,
+            fetch_type: 0x1,
+            track_namespace: Some(track_namespace),
+            track_name: Some(track_name),
+            start_location: Some(Location {
+                group: self.group_id,
+                object: self.first_object,
+            }),
+            // The end location of a FETCH is exclusive in the object ID.
+            end_location: Some(Location {
+                group: self.group_id,
+                object: self.last_object + 1,
+            }),
+            joining_request_id: None,
+            joining_start: None,
+            parameters: Vec::new(),
+        }
+    }
+}
+
+/// Detects object ID gaps in a track received over datagrams, where object
+/// IDs within a group are consecutive, and decides which gaps to repair.
+///
+/// Objects arriving late only shift the expected object ID if they are
+/// ahead of it, so a datagram overtaken by a later one is reported as a gap
+/// once; objects the repair FETCH returns twice can be dropped with a
+/// [`Deduplicator`].
+///
+/// [`Deduplicator`]: crate::dedup::Deduplicator
+pub struct GapDetector {
+    policy: RepairPolicy,
+    /// Current group, its first arrival time and the next expected object.
+    current: Option<(u64, Instant, u64)>,
+}
+
+impl GapDetector {
+    pub fn new(policy: RepairPolicy) -> Self {
+        Self {
+            policy,
+            current: None,
+        }
+    }
+
+    pub fn policy(&self) -> RepairPolicy {
+        self.policy
+    }
+
+    /// Record a received object. Returns the range to repair if the object
+    /// reveals a gap the policy covers.
+    pub fn observe(&mut self, object: &Object, now: Instant) -> Option<RepairRange> {
+        let group_id = object.metadata.group_id;
+        let object_id = object.metadata.object_id;
+        let next = object_id.saturating_add(1);
+        let next = match object.status {
+            ObjectStatus::EndOfGroup | ObjectStatus::EndOfTrack => object_id,
+            _ => next,
+        };
+
+        let (first, started) = match self.current {
+            Some((g, started, expected)) if g == group_id => {
+                if object_id < expected {
+                    return None;
+                }
+                self.current = Some((g, started, next));
+                (expected, started)
+            }
+            Some((g, _, _)) if g > group_id => return None,
+            _ => {
+                self.current = Some((group_id, now, next));
+                (0, now)
+            }
+        };
+        if object_id <= first {
+            return None;
+        }
+
+        let last = object_id - 1;
+        let first = match self.policy {
+            RepairPolicy::Off => return None,
+            RepairPolicy::LastObjects(0) => return None,
+            RepairPolicy::LastObjects(n) => first.max(object_id.saturating_sub(n)),
+            RepairPolicy::TimeWindow(window) => {
+                if now.duration_since(started) > window {
+                    return None;
+                }
+                first
+            }
+        };
+        Some(RepairRange {
+            group_id,
+            first_object: first,
+            last_object: last,
+        })
+    }
+}
+
+/// Track of a repaired subscription, as its FETCHes name it.
+struct RepairedTrack {
+    track_namespace: u64,
+    track_name: String,
+    subscriber_priority: u8,
+}
+
+/// Subscriptions of a session repairing their gaps, and the repair FETCHes
+/// in flight.
+#[derive(Default)]
+pub(crate) struct Repairs {
+    tracks: HashMap<u64, RepairedTrack>,
+    /// Subscription repaired by each FETCH, by FETCH Request ID.
+    fetches: HashMap<u64, u64>,
+}
+
+impl Repairs {
+    pub(crate) fn add_subscription(&mut self, subscribe: &Subscribe) {
+        let track = RepairedTrack {
+            track_namespace: subscribe.track_namespace,
+            track_name: subscribe.track_name.clone(),
+            subscriber_priority: subscribe.subscriber_priority,
+        };
+        self.tracks.insert(subscribe.request_id, track);
+    }
+
+    pub(crate) fn remove_subscription(&mut self, request_id: u64) {
+        self.tracks.remove(&request_id);
+        self.fetches
+            .retain(|_, subscription| *subscription != request_id);
+    }
+
+    /// FETCH `request_id` repairing `range` of `subscription`, recorded
+    /// until its stream arrives. `None` if the subscription is not
+    /// repaired.
+    pub(crate) fn fetch(
+        &mut self,
+        request_id: u64,
+        subscription: u64,
+        range: RepairRange,
+    ) -> Option<Fetch> {
+        let track = self.tracks.get(&subscription)?;
+        self.fetches.insert(request_id, subscription);
+        Some(range.into_fetch(
+            request_id,
+            track.track_namespace,
+            track.track_name.clone(),
+            track.subscriber_priority,
+        ))
+    }
+
+    /// Subscription repaired by FETCH `request_id`, forgetting the FETCH.
+    pub(crate) fn take_fetch(&mut self, request_id: u64) -> Option<u64> {
+        self.fetches.remove(&request_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::ObjectBuilder;
+    use bytes::Bytes;
+
+    fn object(group_id: u64, object_id: u64) -> Object {
+        ObjectBuilder::new(group_id, object_id)
+            .with_payload(Bytes::from_static(b"sample"))
+            .build()
+    }
+
+    fn range(group_id: u64, first_object: u64, last_object: u64) -> Option<RepairRange> {
+        Some(RepairRange {
+            group_id,
+            first_object,
+            last_object,
+        })
+    }
+
+    #[test]
+    fn last_objects_policy_limits_repair() {
+        let now = Instant::now();
+        let mut detector = GapDetector::new(RepairPolicy::LastObjects(2));
+        assert_eq!(detector.observe(&object(0, 0), now), None);
+        assert_eq!(detector.observe(&object(0, 2), now), range(0, 1, 1));
+        assert_eq!(detector.observe(&object(0, 8), now), range(0, 6, 7));
+        // Late arrival of a lost object is not a gap.
+        assert_eq!(detector.observe(&object(0, 4), now), None);
+        // New group missing its first objects.
+        assert_eq!(detector.observe(&object(1, 1), now), range(1, 0, 0));
+        assert_eq!(detector.observe(&object(0, 9), now), None);
+    }
+
+    #[test]
+    fn time_window_policy_skips_stale_groups() {
+        let now = Instant::now();
+        let mut detector = GapDetector::new(RepairPolicy::TimeWindow(Duration::from_secs(1)));
+        detector.observe(&object(0, 0), now);
+        assert_eq!(
+            detector.observe(&object(0, 3), now + Duration::from_millis(500)),
+            range(0, 1, 2)
+        );
+        assert_eq!(
+            detector.observe(&object(0, 5), now + Duration::from_secs(2)),
+            None
+        );
+
+        let mut off = GapDetector::new(RepairPolicy::Off);
+        off.observe(&object(0, 0), now);
+        assert_eq!(off.observe(&object(0, 5), now), None);
+    }
+
+    #[test]
+    fn repair_fetch_end_is_exclusive() {
+        let fetch = RepairRange {
+            group_id: 4,
+            first_object: 1,
+            last_object: 2,
+        }
+        .into_fetch(6, 1, "video".into(), 128);
+        assert_eq!(
+            fetch.start_location,
+            Some(Location {
+                group: 4,
+                object: 1
+            })
+        );
+        assert_eq!(
+            fetch.end_location,
+            Some(Location {
+                group: 4,
+                object: 3
+            })
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{
+    auth::AuthToken,
+    error::Error,
+    message::{Announce, Subscribe},
+    model::{Filter, Parameter},
+    repair::RepairPolicy,
+    scheduler::DEFAULT_SUBSCRIBER_PRIORITY,
+};
+
+/// Builder for an outgoing SUBSCRIBE.
+///
+/// ```ignore
+/// let subscribe = SubscribeRequest::new(namespace, "video")
+///     .with_filter(Filter::next_group())
+///     .with_auth_token(session.token_aliases.token(token))
+///     .into_subscribe(request_id)?;
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SubscribeRequest {
+    track_namespace: u64,
+    track_name: String,
+    subscriber_priority: Option<u8>,
+    group_order: u8,
+    forward: bool,
+    filter: Filter,
+    auth_tokens: Vec<AuthToken>,
+    repair: RepairPolicy,
+}
+
+impl SubscribeRequest {
+    /// Subscribe with the session's default subscriber priority, 128 unless
+    /// configured, the publisher's group order, forwarding enabled and a
+    /// Largest Object filter.
+    pub fn new(track_namespace: u64, track_name: impl Into<String>) -> Self {
+        Self {
+            track_namespace,
+            track_name: track_name.into(),
+            subscriber_priority: None,
+            group_order: 0x0,
+            forward: true,
+            filter: Filter::LargestObject,
+            auth_tokens: Vec::new(),
+            repair: RepairPolicy::Off,
+        }
+    }
+
+    pub fn with_subscriber_priority(mut self, priority: u8) -> Self {
+        self.subscriber_priority = Some(priority);
+        self
+    }
+
+    /// Use `priority` unless a subscriber priority was set.
+    pub(crate) fn or_subscriber_priority(mut self, priority: u8) -> Self {
+        self.subscriber_priority.get_or_insert(priority);
+        self
+    }
+
+    pub fn with_group_order(mut self, group_order: u8) -> Self {
+        self.group_order = group_order;
+        self
+    }
+
+    pub fn with_forward(mut self, forward: bool) -> Self {
+        self.forward = forward;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Attach an AUTHORIZATION TOKEN parameter. May be called repeatedly.
+    pub fn with_auth_token(mut self, token: AuthToken) -> Self {
+        self.auth_tokens.push(token);
+        self
+    }
+
+    /// Repair objects lost from the track's datagrams with FETCHes, see
+    /// [`Session::subscribe`](crate::session::Session::subscribe).
+    pub fn with_repair(mut self, policy: RepairPolicy) -> Self {
+        self.repair = policy;
+        self
+    }
+
+    pub fn track_name(&self) -> &str {
+        &self.track_name
+    }
+
+    pub fn repair(&self) -> RepairPolicy {
+        self.repair
+    }
+
+    pub fn filter(&self) -> &Filter {
+        &self.filter
+    }
+
+    pub fn group_order(&self) -> u8 {
+        self.group_order
+    }
+
+    pub fn into_subscribe(self, request_id: u64) -> Result<Subscribe, Error> {
+        let mut subscribe = Subscribe::new(
+            request_id,
+            self.track_namespace,
+            self.track_name,
+            self.filter,
+        );
+        subscribe.subscriber_priority = self
+            .subscriber_priority
+            .unwrap_or(DEFAULT_SUBSCRIBER_PRIORITY);
+        subscribe.group_order = self.group_order;
+        subscribe.forward = self.forward as u8;
+        subscribe.parameters = auth_parameters(self.auth_tokens)?;
+        Ok(subscribe)
+    }
+}
+
+/// Builder for an outgoing ANNOUNCE.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AnnounceRequest {
+    track_namespace: Vec<String>,
+    auth_tokens: Vec<AuthToken>,
+}
+
+impl AnnounceRequest {
+    pub fn new(track_namespace: Vec<String>) -> Self {
+        Self {
+            track_namespace,
+            auth_tokens: Vec::new(),
+        }
+    }
+
+    /// Attach an AUTHORIZATION TOKEN parameter. May be called repeatedly.
+    pub fn with_auth_token(mut self, token: AuthToken) -> Self {
+        self.auth_tokens.push(token);
+        self
+    }
+
+    pub fn track_namespace(&self) -> &[String] {
+        &self.track_namespace
+    }
+
+    pub fn auth_tokens(&self) -> &[AuthToken] {
+        &self.auth_tokens
+    }
+
+    pub fn into_announce(self, request_id: u64) -> Result<Announce, Error> {
+        Ok(Announce {
+            request_id,
+            track_namespace: self.track_namespace,
+            parameters: auth_parameters(self.auth_tokens)?,
+        })
+    }
+}
+
+fn auth_parameters(tokens: Vec<AuthToken>) -> Result<Vec<Parameter>, Error> {
+    tokens.into_iter().map(AuthToken::into_parameter).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{AUTHORIZATION_TOKEN, Token};
+    use bytes::{Bytes, BytesMut};
+
+    #[test]
+    fn subscribe_carries_auth_tokens() {
+        let token = Token {
+            token_type: 0,
+            value: Bytes::from_static(b"secret"),
+        };
+        let msg = SubscribeRequest::new(1, "video")
+            .with_filter(Filter::next_group())
+            .with_auth_token(AuthToken::UseValue { token })
+            .with_auth_token(AuthToken::UseAlias { alias: 2 })
+            .into_subscribe(4)
+            .unwrap();
+
+        assert_eq!(msg.filter(), &Filter::NextGroupStart);
+        assert_eq!(msg.parameters.len(), 2);
+        assert!(
+            msg.parameters
+                .iter()
+                .all(|p| p.parameter_type == AUTHORIZATION_TOKEN)
+        );
+
+        let mut buf = BytesMut::new();
+        msg.encode(&mut buf).unwrap();
+        assert_eq!(Subscribe::decode(&mut buf).unwrap(), msg);
+    }
+
+    #[test]
+    fn filter_constructors_produce_consistent_subscribes() {
+        use crate::model::Location;
+
+        let start = Location {
+            group: 3,
+            object: 1,
+        };
+        for filter in [
+            Filter::largest_object(),
+            Filter::next_group(),
+            Filter::absolute_start(start.clone()),
+            Filter::absolute_range(start.clone(), 3).unwrap(),
+        ] {
+            let msg = SubscribeRequest::new(1, "video")
+                .with_filter(filter.clone())
+                .into_subscribe(0)
+                .unwrap();
+            assert_eq!(msg.filter(), &filter);
+        }
+
+        assert!(Filter::absolute_range(start, 2).is_err());
+    }
+}
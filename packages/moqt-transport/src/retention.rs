@@ -0,0 +1,432 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::{
+    data::FetchObject,
+    model::Location,
+    sync::{Arc, AtomicUsize, Mutex, Ordering},
+    track::Object,
+};
+
+/// What a track keeps of the groups it published.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RetentionPolicy {
+    /// Nothing is kept. The default.
+    #[default]
+    None,
+    /// The last `n` groups are kept.
+    Groups(usize),
+    /// Groups are kept until no object was added to them for the duration.
+    Age(Duration),
+}
+
+#[derive(Debug)]
+struct BudgetState {
+    limit: usize,
+    used: AtomicUsize,
+}
+
+/// Bytes of object data a session may hold for its tracks, shared by the
+/// retention buffers charged against it.
+///
+/// A buffer that would exceed the budget drops its own oldest groups to
+/// make room, and skips the object if that is not enough. Clones share the
+/// same budget.
+#[derive(Debug, Clone)]
+pub struct MemoryBudget {
+    state: Arc<BudgetState>,
+}
+
+impl Default for MemoryBudget {
+    /// A budget without limit.
+    fn default() -> Self {
+        Self::new(usize::MAX)
+    }
+}
+
+impl MemoryBudget {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            state: Arc::new(BudgetState {
+                limit,
+                used: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.state.limit
+    }
+
+    /// Bytes charged against the budget.
+    pub fn used(&self) -> usize {
+        self.state.used.load(Ordering::Relaxed)
+    }
+
+    /// Charge `bytes` if they fit in what is left of the budget.
+    fn try_reserve(&self, bytes: usize) -> bool {
+        self.state
+            .used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(bytes).filter(|&u| u <= self.state.limit)
+            })
+            .is_ok()
+    }
+
+    fn release(&self, bytes: usize) {
+        self.state.used.fetch_sub(bytes, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug)]
+struct RetainedGroup {
+    objects: BTreeMap<u64, Object>,
+    bytes: usize,
+    updated: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Retained {
+    policy: RetentionPolicy,
+    budget: MemoryBudget,
+    bytes: usize,
+    largest: Option<Location>,
+    groups: BTreeMap<u64, RetainedGroup>,
+}
+
+impl Retained {
+    fn evict(&mut self) {
+        let now = Instant::now();
+        while let Some((_, oldest)) = self.groups.first_key_value() {
+            let expired = match self.policy {
+                RetentionPolicy::None => true,
+                RetentionPolicy::Groups(n) => self.groups.len() > n,
+                RetentionPolicy::Age(age) => now.duration_since(oldest.updated) > age,
+            };
+            if !expired {
+                break;
+            }
+            self.pop_oldest();
+        }
+    }
+
+    fn pop_oldest(&mut self) {
+        if let Some((_, group)) = self.groups.pop_first() {
+            self.bytes -= group.bytes;
+            self.budget.release(group.bytes);
+        }
+    }
+
+    /// Make room for `bytes` in the budget by dropping groups older than
+    /// `group_id`.
+    fn reserve(&mut self, group_id: u64, bytes: usize) -> bool {
+        loop {
+            if self.budget.try_reserve(bytes) {
+                self.bytes += bytes;
+                return true;
+            }
+            match self.groups.first_key_value() {
+                Some((&oldest, _)) if oldest < group_id => self.pop_oldest(),
+                _ => return false,
+            }
+        }
+    }
+}
+
+impl Drop for Retained {
+    fn drop(&mut self) {
+        self.budget.release(self.bytes);
+    }
+}
+
+fn object_size(object: &Object) -> usize {
+    object.payload.len() + object.extension_headers.len()
+}
+
+/// Objects a [`TrackPublisher`](crate::track::TrackPublisher) keeps of its
+/// recent groups, so an origin without a separate cache can answer
+/// FETCHes, e.g. joining fetches, from what it published.
+///
+/// Every object produced by the publisher's group handles is recorded and
+/// kept as the [`RetentionPolicy`] allows, charged against a
+/// [`MemoryBudget`]. The largest recorded location is tracked whatever the
+/// policy, to answer Content Exists and Largest Location. Clones share the
+/// same buffer.
+#[derive(Debug, Clone, Default)]
+pub struct RetentionBuffer {
+    inner: Arc<Mutex<Retained>>,
+}
+
+impl RetentionBuffer {
+    /// Buffer keeping the last `max_groups` groups. With 0 nothing is kept.
+    pub fn new(max_groups: usize) -> Self {
+        let buffer = Self::default();
+        buffer.set_max_groups(max_groups);
+        buffer
+    }
+
+    pub fn set_max_groups(&self, max_groups: usize) {
+        self.set_policy(match max_groups {
+            0 => RetentionPolicy::None,
+            n => RetentionPolicy::Groups(n),
+        });
+    }
+
+    /// Change the policy, dropping the groups it no longer allows.
+    pub fn set_policy(&self, policy: RetentionPolicy) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.policy = policy;
+        inner.evict();
+    }
+
+    pub fn policy(&self) -> RetentionPolicy {
+        self.inner.lock().unwrap().policy
+    }
+
+    /// Charge retained objects against `budget` instead of the unlimited
+    /// default, e.g. the [`Session::memory_budget`](crate::session::Session::memory_budget)
+    /// of the session publishing the track. Objects already retained move
+    /// to the new budget.
+    pub fn set_memory_budget(&self, budget: MemoryBudget) {
+        let mut inner = self.inner.lock().unwrap();
+        let bytes = inner.bytes;
+        inner.budget.release(bytes);
+        budget.state.used.fetch_add(bytes, Ordering::Relaxed);
+        inner.budget = budget;
+        while inner.budget.used() > inner.budget.limit() && !inner.groups.is_empty() {
+            inner.pop_oldest();
+        }
+    }
+
+    /// Bytes of object data retained.
+    pub fn retained_bytes(&self) -> usize {
+        self.inner.lock().unwrap().bytes
+    }
+
+    pub(crate) fn record(&self, object: &Object) {
+        let mut inner = self.inner.lock().unwrap();
+        let loc = object.metadata.location();
+        if inner.largest.as_ref().is_none_or(|l| l.is_before(&loc)) {
+            inner.largest = Some(loc);
+        }
+        inner.evict();
+        if inner.policy == RetentionPolicy::None {
+            return;
+        }
+
+        let group_id = object.metadata.group_id;
+        let replaced = inner
+            .groups
+            .get_mut(&group_id)
+            .and_then(|g| g.objects.remove(&object.metadata.object_id));
+        if let Some(old) = replaced {
+            let size = object_size(&old);
+            inner.bytes -= size;
+            inner.budget.release(size);
+            inner.groups.get_mut(&group_id).unwrap().bytes -= size;
+        }
+
+        let size = object_size(object);
+        if !inner.reserve(group_id, size) {
+            if inner
+                .groups
+                .get(&group_id)
+                .is_some_and(|g| g.objects.is_empty())
+            {
+                inner.groups.remove(&group_id);
+            }
+            return;
+        }
+        let group = inner.groups.entry(group_id).or_insert(RetainedGroup {
+            objects: BTreeMap::new(),
+            bytes: 0,
+            updated: Instant::now(),
+        });
+        group
+            .objects
+            .insert(object.metadata.object_id, object.clone());
+        group.bytes += size;
+        group.updated = Instant::now();
+        inner.evict();
+    }
+
+    /// Largest location recorded, retained or not. `None` while nothing
+    /// was published, i.e. Content Exists is 0.
+    pub fn largest(&self) -> Option<Location> {
+        self.inner.lock().unwrap().largest.clone()
+    }
+
+    /// Locations of the first and last retained objects.
+    pub fn range(&self) -> Option<(Location, Location)> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.evict();
+        let (first_group, first) = inner.groups.first_key_value()?;
+        let (last_group, last) = inner.groups.last_key_value()?;
+        Some((
+            Location::new(*first_group, *first.objects.first_key_value()?.0),
+            Location::new(*last_group, *last.objects.last_key_value()?.0),
+        ))
+    }
+
+    /// Retained objects from `start` to `end` inclusive, in ascending group
+    /// and object order, as written on a FETCH stream. Objects no longer or
+    /// not yet retained are skipped.
+    pub fn fetch(&self, start: &Location, end: &Location) -> Vec<FetchObject> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.evict();
+        inner
+            .groups
+            .range(start.group..=end.group)
+            .flat_map(|(_, group)| group.objects.values())
+            .filter(|o| o.metadata.key().is_within(start, end))
+            .map(Object::to_fetch_object)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::track::TrackPublisher;
+
+    #[test]
+    fn keeps_last_groups() {
+        let mut publisher = TrackPublisher::new(1);
+        publisher.set_retained_groups(2);
+        assert_eq!(publisher.retained_range(), None);
+        for _ in 0..3 {
+            let subgroup = publisher.begin_group().subgroup(0);
+            for _ in 0..3 {
+                subgroup.object(Bytes::from_static(b"frame"));
+            }
+        }
+        let end = publisher.end_group().unwrap();
+        assert_eq!(end.metadata.location(), Location::new(2, 3));
+
+        assert_eq!(
+            publisher.retained_range(),
+            Some((Location::new(1, 0), Location::new(2, 3)))
+        );
+        let fetched = publisher
+            .retention()
+            .fetch(&Location::new(0, 0), &Location::new(2, 1));
+        let locations: Vec<_> = fetched
+            .iter()
+            .map(|o| Location::new(o.group_id, o.object_id))
+            .collect();
+        assert_eq!(
+            locations,
+            [(1, 0), (1, 1), (1, 2), (2, 0), (2, 1)].map(|(g, o)| Location::new(g, o))
+        );
+    }
+
+    #[test]
+    fn age_policy_drops_idle_groups() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .start_paused(true)
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let mut publisher = TrackPublisher::new(1);
+            publisher.set_retention_policy(RetentionPolicy::Age(Duration::from_millis(20)));
+            publisher.push_frame(true, Bytes::from_static(b"old"));
+            publisher.end_group();
+            tokio::time::advance(Duration::from_millis(20)).await;
+            publisher.push_frame(true, Bytes::from_static(b"new"));
+            assert_eq!(
+                publisher.retained_range(),
+                Some((Location::new(0, 0), Location::new(1, 0)))
+            );
+            tokio::time::advance(Duration::from_millis(1)).await;
+            assert_eq!(
+                publisher.retained_range(),
+                Some((Location::new(1, 0), Location::new(1, 0)))
+            );
+        });
+    }
+
+    #[test]
+    fn memory_budget_is_shared_by_tracks() {
+        let budget = MemoryBudget::new(10);
+        let mut audio = TrackPublisher::new(1);
+        let mut video = TrackPublisher::new(2);
+        for publisher in [&mut audio, &mut video] {
+            publisher.set_retention_policy(RetentionPolicy::Groups(8));
+            publisher.set_memory_budget(budget.clone());
+        }
+
+        audio.push_frame(true, Bytes::from_static(b"aaaa"));
+        video.push_frame(true, Bytes::from_static(b"vvvv"));
+        assert_eq!(budget.used(), 8);
+
+        // Video makes room by dropping its own older group only.
+        video.push_frame(true, Bytes::from_static(b"vvvv"));
+        assert_eq!(budget.used(), 8);
+        assert_eq!(audio.retention().retained_bytes(), 4);
+        assert_eq!(
+            video.retained_range(),
+            Some((Location::new(1, 0), Location::new(1, 0)))
+        );
+
+        // An object that cannot fit is not retained, but still published.
+        video.push_frame(false, Bytes::from_static(b"vvvvvvvv"));
+        assert_eq!(video.retention().retained_bytes(), 4);
+        assert_eq!(video.largest_location(), Some(Location::new(1, 1)));
+
+        drop(audio);
+        assert_eq!(budget.used(), 4);
+    }
+
+    #[test]
+    fn largest_is_tracked_without_retention() {
+        let mut publisher = TrackPublisher::new(1);
+        assert_eq!(publisher.largest_location(), None);
+        publisher.push_frame(true, Bytes::from_static(b"frame"));
+        publisher.push_frame(false, Bytes::from_static(b"frame"));
+        assert_eq!(publisher.retained_range(), None);
+        assert_eq!(publisher.largest_location(), Some(Location::new(0, 1)));
+    }
+
+    #[test]
+    fn fetch_responder_serves_retained_objects() {
+        use crate::fetch::{FetchReader, FetchResponder};
+        use crate::mock::MockTransport;
+        use crate::transport::Transport;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let mut publisher = TrackPublisher::new(1);
+            publisher.set_retained_groups(4);
+            for frame in 0..4 {
+                publisher.push_frame(frame % 2 == 0, Bytes::from_static(b"frame"));
+            }
+
+            let (mut a, mut b) = MockTransport::pair();
+            let mut send = a.open_uni_stream().await.unwrap();
+            let recv = b.accept_uni_stream().await.unwrap();
+            let (start, end) = publisher.retained_range().unwrap();
+            FetchResponder::default()
+                .serve(5, &mut send, publisher.retention().fetch(&start, &end))
+                .await
+                .unwrap();
+
+            let mut reader = FetchReader::new(recv);
+            let mut received = Vec::new();
+            while let Some(object) = reader.next().await.unwrap() {
+                received.push(Location::new(object.group_id, object.object_id));
+            }
+            // The keyframe of group 1 ended group 0 with a status object.
+            assert_eq!(
+                received,
+                [(0, 0), (0, 1), (0, 2), (1, 0), (1, 1)].map(|(g, o)| Location::new(g, o))
+            );
+        });
+    }
+}
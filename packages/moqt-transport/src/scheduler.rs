@@ -0,0 +1,300 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use crate::{error::Error, track::ObjectMetadata};
+
+/// Group Order of a SUBSCRIBE or PUBLISH_OK deferring to the publisher.
+pub const GROUP_ORDER_PUBLISHER: u8 = 0x0;
+/// Groups with lower Group IDs are sent first.
+pub const GROUP_ORDER_ASCENDING: u8 = 0x1;
+/// Groups with higher Group IDs are sent first.
+pub const GROUP_ORDER_DESCENDING: u8 = 0x2;
+
+/// Group order a subscription is delivered in: the one `requested` by the
+/// subscriber, or the `publisher`'s if the subscriber left it to the
+/// publisher.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-priorities
+pub fn resolve_group_order(requested: u8, publisher: u8) -> Result<u8, Error> {
+    match requested {
+        GROUP_ORDER_PUBLISHER => check_group_order(publisher),
+        _ => check_group_order(requested),
+    }
+}
+
+/// Check a Group Order stating the order a subscription is delivered in,
+/// as in SUBSCRIBE_OK or PUBLISH_OK, where 0x0 is not allowed.
+pub fn check_group_order(order: u8) -> Result<u8, Error> {
+    match order {
+        GROUP_ORDER_ASCENDING | GROUP_ORDER_DESCENDING => Ok(order),
+        _ => Err(Error::ProtocolViolation {
+            reason: format!("invalid group order {order:#x}"),
+            context: None,
+        }),
+    }
+}
+
+/// Subscriber priority of subscriptions that do not state one.
+pub const DEFAULT_SUBSCRIBER_PRIORITY: u8 = 128;
+
+/// Session-wide subscriber priority rules: the priority of outgoing
+/// subscriptions that do not state one, and the range the priorities of
+/// the peer's SUBSCRIBE, SUBSCRIBE_UPDATE and PUBLISH_OK messages are
+/// clamped to.
+///
+/// Lower values are more urgent, so raising the minimum keeps a peer from
+/// starving other subscriptions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubscriberPriorityPolicy {
+    default: u8,
+    min: u8,
+    max: u8,
+}
+
+impl Default for SubscriberPriorityPolicy {
+    /// Default priority 128, every priority allowed.
+    fn default() -> Self {
+        Self {
+            default: DEFAULT_SUBSCRIBER_PRIORITY,
+            min: u8::MIN,
+            max: u8::MAX,
+        }
+    }
+}
+
+impl SubscriberPriorityPolicy {
+    pub fn with_default(mut self, priority: u8) -> Self {
+        self.default = priority;
+        self
+    }
+
+    /// Allow the peer priorities from `min` to `max`, inclusive. Bounds
+    /// given in the wrong order are swapped.
+    pub fn with_range(mut self, min: u8, max: u8) -> Self {
+        self.min = min.min(max);
+        self.max = min.max(max);
+        self
+    }
+
+    pub fn default_priority(&self) -> u8 {
+        self.default
+    }
+
+    pub fn min(&self) -> u8 {
+        self.min
+    }
+
+    pub fn max(&self) -> u8 {
+        self.max
+    }
+
+    /// `priority` clamped to the allowed range.
+    pub fn clamp(&self, priority: u8) -> u8 {
+        priority.clamp(self.min, self.max)
+    }
+}
+
+/// Scheduling attributes of a schedulable object.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-priorities
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Priority {
+    pub subscriber_priority: u8,
+    pub publisher_priority: u8,
+    /// Group order of the subscription: 0x1 ascending, 0x2 descending.
+    pub group_order: u8,
+    pub group_id: u64,
+    /// Subgroup ID for subgroup delivery, Object ID for datagrams.
+    pub subgroup_id: u64,
+}
+
+impl Priority {
+    /// Priority of an object sent on a subscription with the given
+    /// subscriber priority and resolved group order.
+    pub fn for_object(subscriber_priority: u8, group_order: u8, object: &ObjectMetadata) -> Self {
+        Self {
+            subscriber_priority,
+            publisher_priority: object.publisher_priority,
+            group_order,
+            group_id: object.group_id,
+            subgroup_id: object.subgroup_id,
+        }
+    }
+
+    pub(crate) fn key(&self) -> (u8, u8, u64, u64) {
+        let group = if self.group_order == 0x2 {
+            u64::MAX - self.group_id
+        } else {
+            self.group_id
+        };
+        (
+            self.subscriber_priority,
+            self.publisher_priority,
+            group,
+            self.subgroup_id,
+        )
+    }
+}
+
+struct Entry<T> {
+    key: (u8, u8, u64, u64),
+    seq: u64,
+    item: T,
+}
+
+impl<T> PartialEq for Entry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key && self.seq == other.seq
+    }
+}
+
+impl<T> Eq for Entry<T> {}
+
+impl<T> PartialOrd for Entry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Entry<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.key, self.seq).cmp(&(other.key, other.seq))
+    }
+}
+
+/// Queue of schedulable objects implementing the draft's scheduling
+/// algorithm: lower subscriber priority first, then lower publisher
+/// priority, then group order, then lowest subgroup (or object) ID.
+///
+/// The draft leaves the order of objects from different requests with
+/// equal priorities undefined; this queue applies the same rules across
+/// requests and falls back to insertion order.
+pub struct Scheduler<T> {
+    heap: BinaryHeap<Reverse<Entry<T>>>,
+    seq: u64,
+}
+
+impl<T> Default for Scheduler<T> {
+    fn default() -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            seq: 0,
+        }
+    }
+}
+
+impl<T> Scheduler<T> {
+    pub fn push(&mut self, priority: Priority, item: T) {
+        let seq = self.seq;
+        self.seq += 1;
+        self.heap.push(Reverse(Entry {
+            key: priority.key(),
+            seq,
+            item,
+        }));
+    }
+
+    /// Remove the object that should be sent next.
+    pub fn pop(&mut self) -> Option<T> {
+        self.heap.pop().map(|Reverse(e)| e.item)
+    }
+
+    /// The object [`pop`](Self::pop) would remove next.
+    pub fn peek(&self) -> Option<&T> {
+        self.heap.peek().map(|Reverse(e)| &e.item)
+    }
+
+    /// Keep only the objects for which `f` returns `true`.
+    pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        self.heap.retain(|Reverse(e)| f(&e.item));
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn priority(subscriber: u8, publisher: u8, order: u8, group: u64, subgroup: u64) -> Priority {
+        Priority {
+            subscriber_priority: subscriber,
+            publisher_priority: publisher,
+            group_order: order,
+            group_id: group,
+            subgroup_id: subgroup,
+        }
+    }
+
+    #[test]
+    fn group_order_resolution() {
+        assert_eq!(
+            resolve_group_order(GROUP_ORDER_PUBLISHER, 0x2).unwrap(),
+            0x2
+        );
+        assert_eq!(resolve_group_order(0x1, 0x2).unwrap(), 0x1);
+        assert!(resolve_group_order(GROUP_ORDER_PUBLISHER, 0x0).is_err());
+        assert!(resolve_group_order(0x3, 0x1).is_err());
+        assert!(check_group_order(0x0).is_err());
+    }
+
+    #[test]
+    fn priority_policy_clamps_to_range() {
+        let policy = SubscriberPriorityPolicy::default().with_range(16, 200);
+        assert_eq!(policy.clamp(0), 16);
+        assert_eq!(policy.clamp(100), 100);
+        assert_eq!(policy.clamp(255), 200);
+        assert_eq!(SubscriberPriorityPolicy::default().clamp(0), 0);
+
+        let inverted = SubscriberPriorityPolicy::default().with_range(200, 16);
+        assert_eq!(inverted, policy);
+    }
+
+    #[test]
+    fn subscriber_priority_wins() {
+        let mut s = Scheduler::default();
+        s.push(priority(5, 0, 1, 0, 0), "low");
+        s.push(priority(1, 9, 1, 0, 0), "high");
+        assert_eq!(s.pop(), Some("high"));
+        assert_eq!(s.pop(), Some("low"));
+    }
+
+    #[test]
+    fn publisher_priority_breaks_ties() {
+        let mut s = Scheduler::default();
+        s.push(priority(1, 7, 1, 0, 0), "b");
+        s.push(priority(1, 2, 1, 0, 0), "a");
+        assert_eq!(s.pop(), Some("a"));
+    }
+
+    #[test]
+    fn group_order_is_respected() {
+        let mut asc = Scheduler::default();
+        asc.push(priority(1, 1, 1, 4, 0), 4);
+        asc.push(priority(1, 1, 1, 3, 0), 3);
+        assert_eq!(asc.pop(), Some(3));
+
+        let mut desc = Scheduler::default();
+        desc.push(priority(1, 1, 2, 3, 0), 3);
+        desc.push(priority(1, 1, 2, 4, 0), 4);
+        assert_eq!(desc.pop(), Some(4));
+    }
+
+    #[test]
+    fn lowest_subgroup_then_fifo() {
+        let mut s = Scheduler::default();
+        s.push(priority(1, 1, 1, 0, 2), "sg2");
+        s.push(priority(1, 1, 1, 0, 1), "sg1-a");
+        s.push(priority(1, 1, 1, 0, 1), "sg1-b");
+        assert_eq!(s.pop(), Some("sg1-a"));
+        assert_eq!(s.pop(), Some("sg1-b"));
+        assert_eq!(s.pop(), Some("sg2"));
+        assert!(s.is_empty());
+    }
+}
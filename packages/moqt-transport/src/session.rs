@@ -1,77 +1,1033 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
-use tokio::sync::mpsc;
+use tokio::io::AsyncWrite;
+use tokio::sync::{Semaphore, mpsc, watch};
 
 use crate::{
-    error::Error,
-    message::{ControlMessage, Goaway},
-    track::TrackManager,
-    transport::Transport,
+    announce::{AnnounceLimits, AnnounceSubscriptions, DiscoveryState, PeerAnnounces},
+    auth::{AuthError, AuthRequest, Authorizer, OwnedAuthRequest, TokenAliases, TokenCache},
+    codec::{ControlMessageCodec, MessageSizeLimits, WireVersion},
+    control::{ControlQueue, ControlQueueConfig, ControlQueueStats, ControlWriter},
+    data::{
+        ObjectDatagram, StreamResetCode, SubgroupHeader, SubgroupObject, check_payload_size,
+        send_object_datagram, write_object_vectored_for,
+    },
+    datagram::{UnknownAliasPolicy, receive_datagram},
+    error::{Error, Offending, RequestErrorCode},
+    executor::CallbackExecutor,
+    fetch::FetchReader,
+    goaway::GoawayUriPolicy,
+    incoming::IncomingStream,
+    message::{
+        AnnounceCancel, AnnounceError, ControlMessage, Goaway, MaxRequestId, RequestsBlocked,
+        SubscribeAnnounces, Unannounce, UnsubscribeAnnounces,
+    },
+    model::ForwardingPreference,
+    observe::Observers,
+    repair::{RepairPolicy, Repairs},
+    request::{AnnounceRequest, SubscribeRequest},
+    retention::MemoryBudget,
+    scheduler::SubscriberPriorityPolicy,
+    subgroup::{DataStreamLimits, DataStreamStats},
+    subscription::SubscriptionHandle,
+    task::SessionTasks,
+    track::{Object, TrackManager},
+    transport::{Capabilities, Transport, UniStream},
 };
 
+/// Default bound on the payload of a single object sent or received by a
+/// session.
+pub const DEFAULT_MAX_OBJECT_PAYLOAD_SIZE: usize = 16 << 20;
+
+/// Lifecycle of a [`Session`], observable through
+/// [`Session::state_changes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum State {
+    /// The setup messages have not been exchanged yet.
     Initializing,
+    /// Requests may be sent.
     Active,
+    /// GOAWAY was received or the session is shutting down. No new
+    /// requests should be sent.
     Closing,
 }
 
+/// Configuration of a [`Session`].
+#[derive(Debug, Clone, Default)]
+pub struct SessionConfig {
+    control_queue: ControlQueueConfig,
+    message_size_limits: MessageSizeLimits,
+    announce_limits: AnnounceLimits,
+    data_stream_limits: DataStreamLimits,
+    max_object_payload_size: Option<usize>,
+    memory_budget: Option<usize>,
+    unknown_alias_policy: UnknownAliasPolicy,
+    callback_executor: CallbackExecutor,
+    subscriber_priority: SubscriberPriorityPolicy,
+    goaway_uri_policy: GoawayUriPolicy,
+    observers: Observers,
+}
+
+impl SessionConfig {
+    pub fn with_control_queue(mut self, control_queue: ControlQueueConfig) -> Self {
+        self.control_queue = control_queue;
+        self
+    }
+
+    /// Per message type limits applied when decoding incoming control
+    /// messages.
+    pub fn with_message_size_limits(mut self, limits: MessageSizeLimits) -> Self {
+        self.message_size_limits = limits;
+        self
+    }
+
+    /// Limits on the namespaces the peer may announce.
+    pub fn with_announce_limits(mut self, limits: AnnounceLimits) -> Self {
+        self.announce_limits = limits;
+        self
+    }
+
+    /// Limits on the data streams read from the peer at once.
+    pub fn with_data_stream_limits(mut self, limits: DataStreamLimits) -> Self {
+        self.data_stream_limits = limits;
+        self
+    }
+
+    /// Largest object payload sent or received, see
+    /// [`Session::max_object_payload_size`]. 16 MiB by default.
+    pub fn with_max_object_payload_size(mut self, bytes: usize) -> Self {
+        self.max_object_payload_size = Some(bytes);
+        self
+    }
+
+    /// Bytes of object data the session may retain for the tracks it
+    /// publishes, see [`Session::memory_budget`]. Unlimited by default.
+    pub fn with_memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// How datagrams for track aliases not registered yet are handled.
+    /// Dropped by default.
+    pub fn with_unknown_alias_policy(mut self, policy: UnknownAliasPolicy) -> Self {
+        self.unknown_alias_policy = policy;
+        self
+    }
+
+    /// Where the authorizer and other handler callbacks run. Inline on the
+    /// task processing the control message by default.
+    pub fn with_callback_executor(mut self, executor: CallbackExecutor) -> Self {
+        self.callback_executor = executor;
+        self
+    }
+
+    /// Default subscriber priority of outgoing subscriptions and the range
+    /// the peer's subscriber priorities are clamped to.
+    pub fn with_subscriber_priority(mut self, policy: SubscriberPriorityPolicy) -> Self {
+        self.subscriber_priority = policy;
+        self
+    }
+
+    /// New Session URIs accepted in GOAWAY, sent or received. `https` and
+    /// `moqt` URIs by default.
+    pub fn with_goaway_uri_policy(mut self, policy: GoawayUriPolicy) -> Self {
+        self.goaway_uri_policy = policy;
+        self
+    }
+
+    /// Observers of the control messages going through
+    /// [`Session::control_codec`] and of the objects the session forwards
+    /// to its subscriptions. None by default.
+    pub fn with_observers(mut self, observers: Observers) -> Self {
+        self.observers = observers;
+        self
+    }
+
+    /// Codec for the control stream of a session with this configuration,
+    /// as [`Session::control_codec`].
+    pub(crate) fn control_codec(&self) -> ControlMessageCodec {
+        ControlMessageCodec::new()
+            .with_size_limits(self.message_size_limits.clone())
+            .with_observers(self.observers.clone())
+    }
+}
+
 pub struct Session<T: Transport> {
-    state: Arc<Mutex<State>>,
+    state: watch::Sender<State>,
     received_goaway: Arc<Mutex<bool>>,
-    pub(crate) control_tx: mpsc::Sender<ControlMessage>,
+    control: Arc<ControlQueue>,
     pub track_manager: TrackManager,
+    /// Namespace subscriptions the peer created with SUBSCRIBE_ANNOUNCES.
+    pub announce_subscriptions: AnnounceSubscriptions,
+    /// Namespaces this endpoint announced and prefixes it subscribed to.
+    pub discovery: DiscoveryState,
+    /// Namespaces the peer announced.
+    pub peer_announces: PeerAnnounces,
     pub transport: Arc<T>,
+    /// Aliases of the tokens this endpoint registered with the peer.
+    pub token_aliases: TokenAliases,
+    token_cache: Mutex<TokenCache>,
+    authorizer: Option<Arc<dyn Authorizer>>,
+    tasks: SessionTasks,
+    /// Maximum Request ID advertised to the peer, exclusive.
+    max_request_id: AtomicU64,
+    message_size_limits: MessageSizeLimits,
+    data_stream_limits: DataStreamLimits,
+    /// One permit per data stream that may be read concurrently.
+    data_stream_permits: Arc<Semaphore>,
+    data_stream_stats: Mutex<DataStreamStats>,
+    max_object_payload_size: usize,
+    memory_budget: MemoryBudget,
+    /// Wire format negotiated through CLIENT_SETUP and SERVER_SETUP.
+    wire_version: Mutex<WireVersion>,
+    unknown_alias_policy: UnknownAliasPolicy,
+    callback_executor: CallbackExecutor,
+    subscriber_priority: SubscriberPriorityPolicy,
+    goaway_uri_policy: GoawayUriPolicy,
+    observers: Observers,
+    /// Peer maximum for which REQUESTS_BLOCKED was last sent.
+    blocked_sent: Mutex<Option<u64>>,
+    /// Maximum for which the peer's REQUESTS_BLOCKED was last reported.
+    blocked_reported: Mutex<Option<u64>>,
+    on_requests_blocked: Option<Arc<dyn Fn(u64) + Send + Sync>>,
+    /// Subscriptions repairing lost datagrams and their FETCHes in flight.
+    repairs: Mutex<Repairs>,
 }
 
 impl<T: Transport> Session<T> {
     pub fn new(transport: Arc<T>) -> (Self, mpsc::Receiver<ControlMessage>) {
-        let (tx, rx) = mpsc::channel(16);
+        Self::with_config(transport, SessionConfig::default())
+    }
+
+    /// Create a session whose outgoing control messages are queued as
+    /// configured by `control_queue`.
+    pub fn with_control_queue(
+        transport: Arc<T>,
+        control_queue: ControlQueueConfig,
+    ) -> (Self, mpsc::Receiver<ControlMessage>) {
+        Self::with_config(
+            transport,
+            SessionConfig::default().with_control_queue(control_queue),
+        )
+    }
+
+    pub fn with_config(
+        transport: Arc<T>,
+        config: SessionConfig,
+    ) -> (Self, mpsc::Receiver<ControlMessage>) {
+        let SessionConfig {
+            control_queue,
+            message_size_limits,
+            announce_limits,
+            data_stream_limits,
+            max_object_payload_size,
+            memory_budget,
+            unknown_alias_policy,
+            callback_executor,
+            subscriber_priority,
+            goaway_uri_policy,
+            observers,
+        } = config;
+        let (control, rx) = ControlQueue::new(control_queue);
         let session = Session {
-            state: Arc::new(Mutex::new(State::Initializing)),
+            state: watch::Sender::new(State::Initializing),
             received_goaway: Arc::new(Mutex::new(false)),
-            control_tx: tx,
-            track_manager: TrackManager::default(),
+            control: Arc::new(control),
+            track_manager: TrackManager::default().with_observers(observers.clone()),
+            announce_subscriptions: AnnounceSubscriptions::default(),
+            discovery: DiscoveryState::default(),
+            peer_announces: PeerAnnounces::new(announce_limits),
             transport,
+            token_aliases: TokenAliases::default(),
+            token_cache: Mutex::new(TokenCache::default()),
+            authorizer: None,
+            tasks: SessionTasks::default(),
+            max_request_id: AtomicU64::new(0),
+            message_size_limits,
+            data_stream_limits,
+            data_stream_permits: Arc::new(Semaphore::new(
+                data_stream_limits.max_concurrent_streams(),
+            )),
+            data_stream_stats: Mutex::default(),
+            max_object_payload_size: max_object_payload_size
+                .unwrap_or(DEFAULT_MAX_OBJECT_PAYLOAD_SIZE),
+            memory_budget: memory_budget.map_or_else(MemoryBudget::default, MemoryBudget::new),
+            wire_version: Mutex::default(),
+            unknown_alias_policy,
+            callback_executor,
+            subscriber_priority,
+            goaway_uri_policy,
+            observers,
+            blocked_sent: Mutex::new(None),
+            blocked_reported: Mutex::new(None),
+            on_requests_blocked: None,
+            repairs: Mutex::default(),
         };
         (session, rx)
     }
 
+    /// Codec for the control stream, enforcing the configured message size
+    /// limits on reading and notifying the configured observers, in the
+    /// negotiated [wire version](Self::wire_version).
+    pub fn control_codec(&self) -> ControlMessageCodec {
+        ControlMessageCodec::new()
+            .with_size_limits(self.message_size_limits.clone())
+            .with_observers(self.observers.clone())
+            .with_version(self.wire_version())
+    }
+
+    /// Wire format negotiated with the peer, draft-12 until
+    /// [`set_wire_version`](Self::set_wire_version) is called.
+    pub fn wire_version(&self) -> WireVersion {
+        self.wire_version.lock().unwrap().clone()
+    }
+
+    /// Speak the wire format of the version selected by SERVER_SETUP, on
+    /// the control stream codecs created afterwards, on the subgroup
+    /// streams written by [`write_object`](Self::write_object) and on the
+    /// datagrams sent by [`send_object_datagram`](Self::send_object_datagram)
+    /// and received by [`accept_datagrams`](Self::accept_datagrams).
+    pub fn set_wire_version(&self, version: WireVersion) {
+        *self.wire_version.lock().unwrap() = version;
+    }
+
+    /// Queue a control message, applying the configured
+    /// [`OverflowPolicy`](crate::control::OverflowPolicy) when the queue
+    /// is full.
     pub async fn send_control(&self, msg: ControlMessage) -> Result<(), crate::error::Error> {
-        self.control_tx
-            .send(msg)
-            .await
-            .map_err(|e| crate::error::Error::Transport(Box::new(e)))
+        self.control.send(msg).await
+    }
+
+    /// Write the `outgoing` control messages returned with the session to
+    /// `writer`, the sending half of the control stream, from a background
+    /// task. Messages queued together are coalesced into batched writes as
+    /// configured by the session's [`ControlQueueConfig`], encoded in the
+    /// [wire version](Self::wire_version) negotiated at this point. The
+    /// task ends when the session is shut down or every sender is gone.
+    /// Returns `false` if the session has already been shut down.
+    pub fn spawn_control_writer<W>(
+        &self,
+        writer: W,
+        outgoing: mpsc::Receiver<ControlMessage>,
+    ) -> bool
+    where
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let config = self.control.config();
+        let mut writer = ControlWriter::new(writer)
+            .with_codec(self.control_codec())
+            .with_max_batch_bytes(config.max_batch_bytes);
+        if let Some(linger) = config.linger {
+            writer = writer.with_linger(linger);
+        }
+        self.spawn(async move {
+            let _ = writer.run(outgoing).await;
+        })
+    }
+
+    /// Current occupancy of the outgoing control message queue.
+    pub fn control_queue_stats(&self) -> ControlQueueStats {
+        self.control.stats()
+    }
+
+    /// Subscribe to a track: allocate a Request ID, send the SUBSCRIBE and
+    /// return the handle receiving its objects and modifying it later.
+    ///
+    /// With a [`RepairPolicy`] other than `Off`, gaps the policy covers in
+    /// the objects received by [`accept_datagrams`](Self::accept_datagrams)
+    /// are fetched, and the objects of those FETCHes are delivered to the
+    /// subscription by [`accept_data_streams`](Self::accept_data_streams),
+    /// after the objects that revealed the gaps. Their FETCH_OK and
+    /// FETCH_ERROR need no handling.
+    pub async fn subscribe(&self, request: SubscribeRequest) -> Result<SubscriptionHandle, Error> {
+        let request = request.or_subscriber_priority(self.subscriber_priority.default_priority());
+        let repair = request.repair();
+        let subscribed = self.track_manager.subscribe_request(&request);
+        let (request_id, objects) = self.check_blocked(subscribed).await?;
+        let sent = async {
+            let subscribe = request.into_subscribe(request_id)?;
+            if repair != RepairPolicy::Off {
+                self.repairs.lock().unwrap().add_subscription(&subscribe);
+            }
+            let established = self
+                .track_manager
+                .watch_established(request_id)
+                .expect("subscription just added");
+            let handle =
+                SubscriptionHandle::new(&subscribe, self.control.clone(), established, objects)?;
+            self.send_control(ControlMessage::Subscribe(subscribe))
+                .await?;
+            Ok(handle)
+        }
+        .await;
+        if sent.is_err() {
+            self.track_manager.end_subscription(request_id, None);
+            self.repairs.lock().unwrap().remove_subscription(request_id);
+        }
+        sent
+    }
+
+    /// End `subscription` by sending UNSUBSCRIBE. Unlike
+    /// [`SubscriptionHandle::unsubscribe`], the subscription is removed
+    /// from the [`TrackManager`] as well, so no objects are routed to it
+    /// any longer.
+    pub async fn unsubscribe(&self, subscription: SubscriptionHandle) -> Result<(), Error> {
+        self.track_manager
+            .end_subscription(subscription.request_id(), None);
+        self.repairs
+            .lock()
+            .unwrap()
+            .remove_subscription(subscription.request_id());
+        subscription.unsubscribe().await
+    }
+
+    /// Announce a namespace. Returns the Request ID of the ANNOUNCE.
+    /// Announcing a namespace again before unannouncing it, or before the
+    /// peer rejected or cancelled it, fails with [`Error::DuplicateAnnounce`],
+    /// as the peer may treat the duplicate as a protocol violation.
+    pub async fn announce(&self, request: AnnounceRequest) -> Result<u64, Error> {
+        let namespace = request.track_namespace().to_vec();
+        if !self.discovery.add_announced(&request) {
+            return Err(Error::DuplicateAnnounce { namespace });
+        }
+        let sent = async {
+            let request_id = self
+                .check_blocked(self.track_manager.new_request_id())
+                .await?;
+            let announce = request.into_announce(request_id)?;
+            self.discovery.announce_sent(request_id, &namespace);
+            self.send_control(ControlMessage::Announce(announce))
+                .await?;
+            Ok(request_id)
+        }
+        .await;
+        if sent.is_err() {
+            self.discovery.remove_announced(&namespace);
+        }
+        sent
+    }
+
+    /// Process an incoming ANNOUNCE_ERROR: the namespace is no longer
+    /// announced and may be announced again. Returns the namespace, or
+    /// `None` if the Request ID is not that of an active ANNOUNCE.
+    pub fn handle_announce_error(&self, msg: &AnnounceError) -> Option<Vec<String>> {
+        self.discovery.remove_request(msg.request_id)
+    }
+
+    /// Process an incoming ANNOUNCE_CANCEL: the peer no longer accepts
+    /// subscriptions for the namespace. Returns whether it was announced.
+    pub fn handle_announce_cancel(&self, msg: &AnnounceCancel) -> bool {
+        self.discovery.remove_announced(&msg.track_namespace)
+    }
+
+    /// Pass through the result of allocating a Request ID, sending
+    /// REQUESTS_BLOCKED if the peer's maximum was reached. Only one is sent
+    /// per maximum.
+    async fn check_blocked<R>(&self, result: Result<R, Error>) -> Result<R, Error> {
+        if !matches!(result, Err(Error::TooManyRequests)) {
+            return result;
+        }
+        let maximum_request_id = self.track_manager.max_request_id();
+        {
+            let mut sent = self.blocked_sent.lock().unwrap();
+            if sent.is_some_and(|max| max >= maximum_request_id) {
+                return result;
+            }
+            *sent = Some(maximum_request_id);
+        }
+        self.send_control(ControlMessage::RequestsBlocked(RequestsBlocked {
+            maximum_request_id,
+        }))
+        .await?;
+        result
+    }
+
+    pub async fn unannounce(&self, namespace: &[String]) -> Result<(), Error> {
+        self.discovery.remove_announced(namespace);
+        self.send_control(ControlMessage::Unannounce(Unannounce {
+            track_namespace: namespace.to_vec(),
+        }))
+        .await
+    }
+
+    /// Subscribe to the namespaces matching `prefix`. Returns the Request ID
+    /// of the SUBSCRIBE_ANNOUNCES.
+    pub async fn subscribe_announces(&self, prefix: Vec<String>) -> Result<u64, Error> {
+        let request_id = self
+            .check_blocked(self.track_manager.new_request_id())
+            .await?;
+        self.send_control(ControlMessage::SubscribeAnnounces(SubscribeAnnounces {
+            request_id,
+            track_namespace_prefix: prefix.clone(),
+            parameters: Vec::new(),
+        }))
+        .await?;
+        self.discovery.add_prefix(prefix);
+        Ok(request_id)
+    }
+
+    pub async fn unsubscribe_announces(&self, prefix: &[String]) -> Result<(), Error> {
+        self.discovery.remove_prefix(prefix);
+        self.send_control(ControlMessage::UnsubscribeAnnounces(UnsubscribeAnnounces {
+            track_namespace_prefix: prefix.to_vec(),
+        }))
+        .await
+    }
+
+    /// Re-establish this session's discovery state on the session replacing
+    /// it after GOAWAY: every active namespace is announced again and every
+    /// namespace subscription is sent again, before the caller drains this
+    /// session.
+    ///
+    /// The ANNOUNCEs carry the authorization tokens of the original ones,
+    /// by value since token aliases are scoped to a session.
+    pub async fn migrate_discovery<U: Transport>(&self, new: &Session<U>) -> Result<(), Error> {
+        for request in self.discovery.announce_requests() {
+            new.announce(request).await?;
+        }
+        for prefix in self.discovery.prefixes() {
+            new.subscribe_announces(prefix).await?;
+        }
+        Ok(())
+    }
+
+    /// Spawn a background task tied to the session's lifetime. Returns
+    /// `false` if the session has already been shut down.
+    pub fn spawn<F>(&self, task: F) -> bool
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.tasks.spawn(task)
+    }
+
+    /// Read the data streams the peer opens on `transport` until the
+    /// transport stops accepting streams or the session is shut down. This
+    /// is the single entry point of the data plane: each stream is told
+    /// apart by its type with [`IncomingStream::accept`]. Objects of
+    /// subgroup streams are delivered to
+    /// [`track_manager`](Self::track_manager), while FETCH streams are
+    /// passed on `fetch_streams` for the requester to read.
+    ///
+    /// At most [`DataStreamLimits::max_concurrent_streams`] streams are read
+    /// at once, a FETCH stream counting until it was passed on. Further
+    /// streams are not accepted until one of them ends, so the transport's
+    /// stream limit pushes back on the peer instead of the session spawning
+    /// a reader per stream. A stream that fails to be read, including one
+    /// carrying an object larger than [`DataStreamLimits::max_buffer_size`],
+    /// is dropped without affecting the others. A subgroup stream whose
+    /// object payload exceeds
+    /// [`max_object_payload_size`](Self::max_object_payload_size) is reset
+    /// as soon as the payload length is read; FETCH streams are passed on
+    /// with the same bound, failing with [`Error::ObjectTooLarge`] when
+    /// read. Any other error, such as a protocol violation like an unknown
+    /// stream type, ends the loop with that error so the session can be
+    /// closed. How subgroup streams ended, including those cut short
+    /// in the middle of an object, is counted in
+    /// [`data_stream_stats`](Self::data_stream_stats).
+    pub async fn accept_data_streams<U>(
+        self: &Arc<Self>,
+        transport: &mut U,
+        fetch_streams: mpsc::Sender<FetchReader<U::Uni>>,
+    ) -> Result<(), Error>
+    where
+        T: 'static,
+        U: Transport + 'static,
+    {
+        let failure = Arc::new(Mutex::new(None));
+        let stop = self.tasks.cancellation_token().child_token();
+        loop {
+            let permits = self.data_stream_permits.clone();
+            let accepted = stop
+                .run_until_cancelled(async {
+                    let permit = permits
+                        .acquire_owned()
+                        .await
+                        .expect("data stream permits are never closed");
+                    let stream = transport.accept_uni_stream().await;
+                    stream.map(|stream| (permit, stream))
+                })
+                .await;
+            let Some(accepted) = accepted else {
+                return match failure.lock().unwrap().take() {
+                    Some(e) => Err(e),
+                    None => Ok(()),
+                };
+            };
+            let (permit, stream) = accepted.map_err(Error::Transport)?;
+
+            let session = self.clone();
+            let fetch_streams = fetch_streams.clone();
+            let failure = failure.clone();
+            let stop = stop.clone();
+            let max_buffer_size = self.data_stream_limits.max_buffer_size();
+            let alias_window = self.data_stream_limits.alias_window();
+            let max_payload_size = self.max_object_payload_size;
+            let spawned = self.tasks.spawn(async move {
+                let _permit = permit;
+                let result = match IncomingStream::accept(stream, max_buffer_size).await {
+                    Ok(IncomingStream::Subgroup(reader)) => {
+                        let mut reader = reader
+                            .with_alias_window(alias_window)
+                            .with_max_object_payload_size(max_payload_size);
+                        let delivered = reader.deliver(&session.track_manager).await;
+                        if let Err(Error::ObjectTooLarge { .. }) = delivered {
+                            reader
+                                .get_mut()
+                                .reset(StreamResetCode::InternalError as u64);
+                        }
+                        session.data_stream_stats.lock().unwrap().record(&delivered);
+                        delivered.map(|_| ())
+                    }
+                    // The requester is gone if the channel closed.
+                    Ok(IncomingStream::Fetch(reader)) => {
+                        let mut reader = reader.with_max_object_payload_size(max_payload_size);
+                        match reader.request_id().await {
+                            Ok(request_id) => {
+                                let repairs = &session.repairs;
+                                let repaired = repairs.lock().unwrap().take_fetch(request_id);
+                                match repaired {
+                                    Some(subscription) => {
+                                        session.deliver_repair(subscription, reader).await
+                                    }
+                                    None => {
+                                        let _ = fetch_streams.send(reader).await;
+                                        Ok(())
+                                    }
+                                }
+                            }
+                            Err(e) => Err(e),
+                        }
+                    }
+                    Err(e) => Err(e),
+                };
+                match result {
+                    // Failures of this stream alone.
+                    Ok(())
+                    | Err(
+                        Error::Io(_)
+                        | Error::Transport(_)
+                        | Error::ObjectTooLarge { .. }
+                        | Error::TruncatedObject { .. },
+                    ) => {}
+                    Err(e) => {
+                        failure.lock().unwrap().get_or_insert(e);
+                        stop.cancel();
+                    }
+                }
+            });
+            if !spawned {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Receive the datagrams of `transport` until it yields no more or the
+    /// session is shut down, delivering their objects to
+    /// [`track_manager`](Self::track_manager). Datagrams for aliases not
+    /// registered yet are handled according to the configured
+    /// [`UnknownAliasPolicy`].
+    ///
+    /// A malformed datagram, or an unknown alias with
+    /// [`UnknownAliasPolicy::Reject`], ends the loop with the error so the
+    /// session can be closed.
+    pub async fn accept_datagrams<U: Transport>(&self, transport: &mut U) -> Result<(), Error> {
+        let stop = self.tasks.cancellation_token();
+        let version = self.wire_version();
+        while let Some(Some(datagram)) = stop.run_until_cancelled(transport.recv_datagram()).await {
+            receive_datagram(
+                &version,
+                &self.track_manager,
+                self.unknown_alias_policy,
+                datagram,
+            )?;
+            self.send_repairs().await?;
+        }
+        Ok(())
+    }
+
+    /// Send a FETCH for each gap found in the subscriptions repairing their
+    /// datagrams. Gaps found while the peer's maximum Request ID is reached
+    /// are not repaired.
+    async fn send_repairs(&self) -> Result<(), Error> {
+        for (subscription, range) in self.track_manager.take_repairs() {
+            let request_id = match self
+                .check_blocked(self.track_manager.new_request_id())
+                .await
+            {
+                Ok(request_id) => request_id,
+                Err(Error::TooManyRequests) => continue,
+                Err(e) => return Err(e),
+            };
+            let fetch = self
+                .repairs
+                .lock()
+                .unwrap()
+                .fetch(request_id, subscription, range);
+            if let Some(fetch) = fetch {
+                self.send_control(ControlMessage::Fetch(fetch)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deliver the objects of a repair FETCH to the subscription it
+    /// repairs, waiting for room in its queue.
+    async fn deliver_repair<R>(
+        &self,
+        subscription: u64,
+        mut reader: FetchReader<R>,
+    ) -> Result<(), Error>
+    where
+        R: tokio::io::AsyncRead + Unpin,
+    {
+        let Some((track_alias, objects)) = self.track_manager.subscription_sender(subscription)
+        else {
+            return Ok(());
+        };
+        while let Some(object) = reader.next().await? {
+            let object = Object::from_fetch(track_alias, object)?;
+            if objects.send(Ok(object)).await.is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Data streams being read by [`accept_data_streams`](Self::accept_data_streams).
+    pub fn active_data_streams(&self) -> usize {
+        self.data_stream_limits.max_concurrent_streams()
+            - self.data_stream_permits.available_permits()
+    }
+
+    /// How the subgroup streams read by
+    /// [`accept_data_streams`](Self::accept_data_streams) ended so far.
+    pub fn data_stream_stats(&self) -> DataStreamStats {
+        *self.data_stream_stats.lock().unwrap()
+    }
+
+    /// Largest object payload the session sends or receives.
+    ///
+    /// Larger objects read by
+    /// [`accept_data_streams`](Self::accept_data_streams) fail with
+    /// [`Error::ObjectTooLarge`] and their stream is reset, as are those
+    /// written with [`write_object`](Self::write_object). Pass it to
+    /// [`FetchResponder::with_max_object_payload_size`](crate::fetch::FetchResponder::with_max_object_payload_size)
+    /// to bound the FETCH streams served too.
+    pub fn max_object_payload_size(&self) -> usize {
+        self.max_object_payload_size
+    }
+
+    /// Write `object` on a subgroup stream as
+    /// [`write_object_vectored`](crate::data::write_object_vectored) does,
+    /// in the session's [wire version](Self::wire_version), after checking
+    /// its payload against
+    /// [`max_object_payload_size`](Self::max_object_payload_size). An object
+    /// too large is not written: the stream is reset and the write fails
+    /// with [`Error::ObjectTooLarge`].
+    pub async fn write_object<S: UniStream>(
+        &self,
+        stream: &mut S,
+        header: Option<&SubgroupHeader>,
+        extensions_present: bool,
+        object: &SubgroupObject,
+    ) -> Result<(), Error> {
+        if let Err(e) = check_payload_size(object.payload.len(), self.max_object_payload_size) {
+            stream.reset(StreamResetCode::InternalError as u64);
+            return Err(e);
+        }
+        let version = self.wire_version();
+        write_object_vectored_for(&version, stream, header, extensions_present, object).await
+    }
+
+    /// Send `datagram` over `transport` in the session's
+    /// [wire version](Self::wire_version), see
+    /// [`data::send_object_datagram`](crate::data::send_object_datagram).
+    pub async fn send_object_datagram<U: Transport>(
+        &self,
+        transport: &mut U,
+        datagram: &ObjectDatagram,
+    ) -> Result<(), Error> {
+        send_object_datagram(&self.wire_version(), transport, datagram).await
+    }
+
+    /// Budget the objects retained for this session's tracks are charged
+    /// against, to pass to [`TrackPublisher::set_memory_budget`](crate::track::TrackPublisher::set_memory_budget).
+    pub fn memory_budget(&self) -> MemoryBudget {
+        self.memory_budget.clone()
+    }
+
+    /// Stop every background task of the session and wait for them to
+    /// return.
+    pub async fn shutdown(&self) {
+        self.set_state(State::Closing);
+        self.tasks.shutdown().await;
+    }
+
+    /// Set the MAX_AUTH_TOKEN_CACHE_SIZE advertised to the peer. Must be
+    /// called before the peer registers any token.
+    pub fn set_max_auth_token_cache_size(&self, size: u64) {
+        *self.token_cache.lock().unwrap() = TokenCache::new(size);
+    }
+
+    /// Capabilities of the underlying transport.
+    pub fn capabilities(&self) -> Capabilities {
+        self.transport.capabilities()
+    }
+
+    /// Check that objects can be sent with the given forwarding preference
+    /// before a track commits to it. Datagram forwarding is refused with
+    /// [`RequestErrorCode::NotSupported`] when the transport has no
+    /// datagram support.
+    pub fn check_forwarding_preference(
+        &self,
+        preference: ForwardingPreference,
+    ) -> Result<(), Error> {
+        match preference {
+            ForwardingPreference::Datagram if !self.capabilities().datagrams => {
+                Err(Error::RequestFailed {
+                    code: RequestErrorCode::NotSupported,
+                    reason: "datagrams not supported by the transport".into(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Install the callback deciding whether incoming requests are allowed.
+    /// Without one every request is accepted.
+    pub fn set_authorizer(&mut self, authorizer: impl Authorizer + 'static) {
+        self.authorizer = Some(Arc::new(authorizer));
+    }
+
+    /// Resolve the AUTHORIZATION TOKEN parameters of an incoming request
+    /// against the peer's registered tokens and run the authorizer on the
+    /// configured [`CallbackExecutor`], so a slow authorizer does not stall
+    /// the task processing control messages. The request is denied if the
+    /// executor drops the check without running it.
+    ///
+    /// Errors for which [`AuthError::is_session_error`] holds must close
+    /// the session; others reject only this request.
+    pub async fn authorize(&self, request: AuthRequest<'_>) -> Result<(), Error> {
+        let tokens = self
+            .token_cache
+            .lock()
+            .unwrap()
+            .resolve(request.parameters())?;
+        let Some(authorizer) = self.authorizer.clone() else {
+            return Ok(());
+        };
+        if !tokens.iter().all(|t| authorizer.check_token(t)) {
+            return Err(AuthError::MalformedToken.into());
+        }
+        let allowed = if self.callback_executor.is_inline() {
+            Some(authorizer.authorize(&request, &tokens))
+        } else {
+            let request = OwnedAuthRequest::from(request);
+            self.callback_executor
+                .run(move || authorizer.authorize(&request.as_request(), &tokens))
+                .await
+        };
+        match allowed {
+            Some(true) => Ok(()),
+            _ => Err(AuthError::Unauthorized.into()),
+        }
+    }
+
+    /// Raise the Maximum Request ID advertised to the peer and return the
+    /// MAX_REQUEST_ID message to send. The initial value sent in the setup
+    /// parameters is recorded the same way.
+    pub fn advertise_max_request_id(&self, max: u64) -> Result<MaxRequestId, Error> {
+        // A single read-modify-write, so concurrent callers cannot lower
+        // the maximum after another raised it.
+        let current = self.max_request_id.fetch_max(max, Ordering::SeqCst);
+        if max <= current {
+            return Err(Error::ProtocolViolation {
+                reason: "MAX_REQUEST_ID must increase".into(),
+                context: None,
+            });
+        }
+        Ok(MaxRequestId { request_id: max })
+    }
+
+    /// Maximum Request ID advertised to the peer.
+    pub fn max_request_id(&self) -> u64 {
+        self.max_request_id.load(Ordering::SeqCst)
+    }
+
+    /// Install the callback told when the peer is blocked by the Maximum
+    /// Request ID advertised to it, e.g. to raise it with
+    /// [`advertise_max_request_id`](Self::advertise_max_request_id).
+    pub fn on_requests_blocked(&mut self, callback: impl Fn(u64) + Send + Sync + 'static) {
+        self.on_requests_blocked = Some(Arc::new(callback));
+    }
+
+    /// Process an incoming REQUESTS_BLOCKED. The callback runs at most once
+    /// per maximum, on the configured [`CallbackExecutor`]: repeats, and
+    /// reports for a maximum that was raised since, are ignored. Returns
+    /// whether the callback was run or handed to the executor.
+    pub fn handle_requests_blocked(&self, msg: &RequestsBlocked) -> bool {
+        let maximum = msg.maximum_request_id;
+        if maximum < self.max_request_id() {
+            return false;
+        }
+        {
+            let mut reported = self.blocked_reported.lock().unwrap();
+            if reported.is_some_and(|max| max >= maximum) {
+                return false;
+            }
+            *reported = Some(maximum);
+        }
+        match &self.on_requests_blocked {
+            Some(callback) => {
+                let callback = callback.clone();
+                self.callback_executor.dispatch(move || callback(maximum));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Session-level checks run on every incoming control message before
+    /// it is dispatched. An error must close the session.
+    ///
+    /// A request whose Request ID is not below the advertised Maximum
+    /// Request ID fails with [`Error::TooManyRequests`], an ANNOUNCE for a
+    /// namespace the peer already announced with
+    /// [`Error::DuplicateAnnounce`] unless configured as idempotent. The
+    /// subscriber priority of the message is then
+    /// [policed](Self::police_priority).
+    pub fn check_incoming(&self, msg: &mut ControlMessage) -> Result<(), Error> {
+        self.check_request(msg)?;
+        self.police_priority(msg);
+        Ok(())
+    }
+
+    fn check_request(&self, msg: &ControlMessage) -> Result<(), Error> {
+        if !msg.message_type().is_some_and(|t| t.is_request()) {
+            return Ok(());
+        }
+        if let Some(id) = msg.request_id()
+            && id >= self.max_request_id.load(Ordering::SeqCst)
+        {
+            return Err(Error::TooManyRequests);
+        }
+        if let ControlMessage::Announce(announce) = msg {
+            self.peer_announces.check_duplicate(announce).map_err(|e| {
+                Error::ProtocolViolation {
+                    reason: e.to_string(),
+                    context: None,
+                }
+                .with_offending("announce.duplicate", Offending::Message(msg.clone()))
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Clamp the subscriber priority of an incoming SUBSCRIBE,
+    /// SUBSCRIBE_UPDATE or PUBLISH_OK to the range allowed by the
+    /// configured [`SubscriberPriorityPolicy`]. Returns whether the
+    /// priority changed.
+    pub fn police_priority(&self, msg: &mut ControlMessage) -> bool {
+        let priority = match msg {
+            ControlMessage::Subscribe(m) => &mut m.subscriber_priority,
+            ControlMessage::SubscribeUpdate(m) => &mut m.subscriber_priority,
+            ControlMessage::PublishOk(m) => &mut m.subscriber_priority,
+            _ => return false,
+        };
+        let clamped = self.subscriber_priority.clamp(*priority);
+        let changed = clamped != *priority;
+        *priority = clamped;
+        changed
     }
 
     /// Process an incoming GOAWAY message. `is_server` indicates whether this
     /// endpoint is acting as a server when receiving the message.
     pub fn handle_goaway(&self, msg: &Goaway, is_server: bool) -> Result<(), Error> {
+        let violation = |rule, reason: &str| {
+            Error::ProtocolViolation {
+                reason: reason.into(),
+                context: None,
+            }
+            .with_offending(
+                rule,
+                Offending::Message(ControlMessage::Goaway(msg.clone())),
+            )
+        };
         {
             let mut received = self.received_goaway.lock().unwrap();
             if *received {
-                return Err(Error::ProtocolViolation {
-                    reason: "multiple GOAWAY messages".into(),
-                });
+                return Err(violation("goaway.duplicate", "multiple GOAWAY messages"));
             }
             *received = true;
         }
 
         if is_server && msg.new_session_uri.is_some() {
-            return Err(Error::ProtocolViolation {
-                reason: "GOAWAY from client contained URI".into(),
-            });
+            return Err(violation(
+                "goaway.uri_from_client",
+                "GOAWAY from client contained URI",
+            ));
+        }
+        if let Some(uri) = &msg.new_session_uri
+            && let Err(e) = self.goaway_uri_policy.validate(uri)
+        {
+            return Err(violation("goaway.invalid_uri", &e.to_string()));
         }
 
-        let mut state = self.state.lock().unwrap();
-        *state = State::Closing;
+        self.set_state(State::Closing);
 
         Ok(())
     }
+
+    /// Send GOAWAY and stop accepting new requests. `new_session_uri` is
+    /// where the peer should reconnect to, and may only be set by a
+    /// server; it must be allowed by the configured [`GoawayUriPolicy`],
+    /// otherwise nothing is sent and [`Error::InvalidUri`] is returned.
+    pub async fn goaway(&self, new_session_uri: Option<String>) -> Result<(), Error> {
+        if let Some(uri) = &new_session_uri {
+            self.goaway_uri_policy.validate(uri)?;
+        }
+        self.send_control(ControlMessage::Goaway(Goaway { new_session_uri }))
+            .await?;
+        self.set_state(State::Closing);
+        Ok(())
+    }
+
+    /// Current state of the session.
+    pub fn state(&self) -> State {
+        *self.state.borrow()
+    }
+
+    /// Receiver notified whenever the session changes state, e.g. to stop
+    /// submitting requests as soon as GOAWAY arrives.
+    pub fn state_changes(&self) -> watch::Receiver<State> {
+        self.state.subscribe()
+    }
+
+    /// Mark the session active once CLIENT_SETUP and SERVER_SETUP were
+    /// exchanged. Has no effect on a closing session.
+    pub fn activate(&self) {
+        self.state.send_if_modified(|state| {
+            let initializing = *state == State::Initializing;
+            if initializing {
+                *state = State::Active;
+            }
+            initializing
+        });
+    }
+
+    fn set_state(&self, new: State) {
+        self.state.send_if_modified(|state| {
+            let changed = *state != new;
+            *state = new;
+            changed
+        });
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::transport::{BiStream, BoxError};
+    use crate::transport::{BiStream, BoxError, UniStream};
     use std::pin::Pin;
     use std::task::{Context, Poll};
     use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
@@ -107,6 +1063,10 @@ mod tests {
         }
     }
 
+    impl UniStream for DummyStream {
+        fn reset(&mut self, _code: u64) {}
+    }
+
     struct DummyBi;
 
     impl BiStream for DummyBi {
@@ -147,6 +1107,36 @@ mod tests {
         }
     }
 
+    #[test]
+    fn datagram_preference_requires_transport_support() {
+        let (mut transport, _peer) = crate::mock::MockTransport::pair();
+        let (session, _rx) = Session::new(Arc::new(DummyTransport));
+        assert!(session.capabilities().datagrams);
+        assert!(
+            session
+                .check_forwarding_preference(ForwardingPreference::Datagram)
+                .is_ok()
+        );
+
+        transport.set_capabilities(Capabilities {
+            datagrams: false,
+            ..Capabilities::default()
+        });
+        let (session, _rx) = Session::new(Arc::new(transport));
+        assert!(
+            session
+                .check_forwarding_preference(ForwardingPreference::Subgroup)
+                .is_ok()
+        );
+        match session.check_forwarding_preference(ForwardingPreference::Datagram) {
+            Err(Error::RequestFailed {
+                code: RequestErrorCode::NotSupported,
+                ..
+            }) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
     #[test]
     fn multiple_goaway_is_violation() {
         let (session, _rx) = Session::new(Arc::new(DummyTransport));
@@ -168,7 +1158,15 @@ mod tests {
             )
             .unwrap_err();
         match err {
-            Error::ProtocolViolation { .. } => {}
+            Error::ProtocolViolation {
+                context: Some(v), ..
+            } => {
+                assert_eq!(v.rule, "goaway.duplicate");
+                assert!(matches!(
+                    v.offending,
+                    Offending::Message(ControlMessage::Goaway(_))
+                ));
+            }
             e => panic!("unexpected error: {:?}", e),
         }
     }
@@ -187,7 +1185,9 @@ mod tests {
             .unwrap_err();
 
         match err {
-            Error::ProtocolViolation { .. } => {}
+            Error::ProtocolViolation {
+                context: Some(v), ..
+            } => assert_eq!(v.rule, "goaway.uri_from_client"),
             e => panic!("unexpected error: {:?}", e),
         }
     }
@@ -204,11 +1204,7 @@ mod tests {
             )
             .unwrap();
 
-        let state = session.state.lock().unwrap();
-        match *state {
-            State::Closing => {}
-            _ => panic!("unexpected state"),
-        }
+        assert_eq!(session.state(), State::Closing);
     }
 
     #[test]
@@ -224,10 +1220,1372 @@ mod tests {
             )
             .unwrap();
 
-        let state = session.state.lock().unwrap();
-        match *state {
-            State::Closing => {}
-            _ => panic!("unexpected state"),
+        assert_eq!(session.state(), State::Closing);
+    }
+
+    #[test]
+    fn goaway_uri_is_checked_against_policy() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let config = SessionConfig::default()
+                .with_goaway_uri_policy(GoawayUriPolicy::default().with_scheme("moq-test"));
+            let (session, mut rx) = Session::with_config(Arc::new(DummyTransport), config);
+
+            assert!(matches!(
+                session.goaway(Some("http://relay.example".into())).await,
+                Err(Error::InvalidUri { .. })
+            ));
+            assert!(rx.try_recv().is_err());
+            assert_eq!(session.state(), State::Initializing);
+
+            session
+                .goaway(Some("moq-test://relay.example".into()))
+                .await
+                .unwrap();
+            assert!(matches!(rx.try_recv(), Ok(ControlMessage::Goaway(_))));
+            assert_eq!(session.state(), State::Closing);
+
+            let (session, _rx) = Session::new(Arc::new(DummyTransport));
+            let error = session
+                .handle_goaway(
+                    &Goaway {
+                        new_session_uri: Some("https://relay.example/#frag".into()),
+                    },
+                    false,
+                )
+                .unwrap_err();
+            assert_eq!(error.violation().unwrap().rule, "goaway.invalid_uri");
+            assert_eq!(
+                crate::error::TerminationCode::from(&error),
+                crate::error::TerminationCode::ProtocolViolation
+            );
+        });
+    }
+
+    #[test]
+    fn state_changes_are_observed() {
+        let (session, _rx) = Session::new(Arc::new(DummyTransport));
+        let mut changes = session.state_changes();
+        assert_eq!(*changes.borrow_and_update(), State::Initializing);
+
+        session.activate();
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(*changes.borrow_and_update(), State::Active);
+
+        session
+            .handle_goaway(
+                &Goaway {
+                    new_session_uri: None,
+                },
+                false,
+            )
+            .unwrap();
+        assert_eq!(*changes.borrow_and_update(), State::Closing);
+
+        // A closing session does not become active again.
+        session.activate();
+        assert!(!changes.has_changed().unwrap());
+        assert_eq!(session.state(), State::Closing);
+    }
+
+    #[test]
+    fn authorizer_sees_tokens_registered_earlier() {
+        use crate::auth::Token;
+        use crate::request::SubscribeRequest;
+
+        let (client, _rx) = Session::new(Arc::new(DummyTransport));
+        let (mut server, _rx) = Session::new(Arc::new(DummyTransport));
+        client.token_aliases.set_peer_cache_size(64);
+        server.set_max_auth_token_cache_size(64);
+        server.set_authorizer(|_: &AuthRequest<'_>, tokens: &[Token]| {
+            tokens.iter().any(|t| t.value.as_ref() == b"secret")
+        });
+
+        let token = Token {
+            token_type: 0,
+            value: bytes::Bytes::from_static(b"secret"),
+        };
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            for request_id in [0, 2] {
+                let subscribe = SubscribeRequest::new(1, "video")
+                    .with_auth_token(client.token_aliases.token(token.clone()))
+                    .into_subscribe(request_id)
+                    .unwrap();
+                server
+                    .authorize(AuthRequest::Subscribe(&subscribe))
+                    .await
+                    .unwrap();
+            }
+
+            let denied = SubscribeRequest::new(1, "video").into_subscribe(4).unwrap();
+            match server.authorize(AuthRequest::Subscribe(&denied)).await {
+                Err(Error::Auth(AuthError::Unauthorized)) => {}
+                r => panic!("unexpected result: {:?}", r),
+            }
+        });
+    }
+
+    #[test]
+    fn tokens_invalid_for_their_type_are_malformed() {
+        use crate::auth::{AuthToken, Token};
+        use crate::request::SubscribeRequest;
+
+        struct Jwt;
+        impl Authorizer for Jwt {
+            fn authorize(&self, _: &AuthRequest<'_>, _: &[Token]) -> bool {
+                true
+            }
+
+            fn check_token(&self, token: &Token) -> bool {
+                token.value.split(|b| *b == b'.').count() == 3
+            }
+        }
+
+        let (mut server, _rx) = Session::new(Arc::new(DummyTransport));
+        server.set_authorizer(Jwt);
+        let subscribe = |value: &'static [u8]| {
+            let token = Token {
+                token_type: 0,
+                value: bytes::Bytes::from_static(value),
+            };
+            SubscribeRequest::new(1, "video")
+                .with_auth_token(AuthToken::UseValue { token })
+                .into_subscribe(0)
+                .unwrap()
+        };
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            server
+                .authorize(AuthRequest::Subscribe(&subscribe(b"a.b.c")))
+                .await
+                .unwrap();
+            match server
+                .authorize(AuthRequest::Subscribe(&subscribe(b"abc")))
+                .await
+            {
+                Err(Error::Auth(e @ AuthError::MalformedToken)) => {
+                    assert!(!e.is_session_error());
+                    assert_eq!(e.code(), 0x10);
+                }
+                r => panic!("unexpected result: {:?}", r),
+            }
+        });
+    }
+
+    #[test]
+    fn authorizer_on_executor_does_not_stall_caller() {
+        use std::sync::mpsc as std_mpsc;
+        use std::time::Duration;
+
+        use crate::auth::Token;
+        use crate::request::SubscribeRequest;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let config =
+                SessionConfig::default().with_callback_executor(CallbackExecutor::Blocking);
+            let (mut server, _rx) = Session::with_config(Arc::new(DummyTransport), config);
+            // The authorizer waits for a task of the caller's runtime, which
+            // only runs if the caller is not blocked by the authorizer.
+            let (tx, rx) = std_mpsc::sync_channel(1);
+            let rx = Mutex::new(rx);
+            server.set_authorizer(move |_: &AuthRequest<'_>, _: &[Token]| {
+                rx.lock()
+                    .unwrap()
+                    .recv_timeout(Duration::from_secs(5))
+                    .is_ok()
+            });
+            tokio::spawn(async move { tx.send(()).unwrap() });
+
+            let subscribe = SubscribeRequest::new(1, "video").into_subscribe(0).unwrap();
+            server
+                .authorize(AuthRequest::Subscribe(&subscribe))
+                .await
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn control_writer_follows_queue_config() {
+        use std::time::Duration;
+        use tokio::io::AsyncReadExt;
+        use tokio_util::codec::Decoder;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .start_paused(true)
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let queue = ControlQueueConfig::default().with_linger(Duration::from_millis(50));
+            let config = SessionConfig::default().with_control_queue(queue);
+            let (session, rx) = Session::with_config(Arc::new(DummyTransport), config);
+            let (writer, mut reader) = tokio::io::duplex(1024);
+            assert!(session.spawn_control_writer(writer, rx));
+
+            let max = |request_id| ControlMessage::MaxRequestId(MaxRequestId { request_id });
+            session.send_control(max(1)).await.unwrap();
+            let mut buf = bytes::BytesMut::new();
+            // The writer lingers for more messages before writing.
+            let early = tokio::time::timeout(Duration::from_millis(10), reader.read_buf(&mut buf));
+            assert!(early.await.is_err());
+            session.send_control(max(2)).await.unwrap();
+
+            let mut codec = session.control_codec();
+            let mut received = Vec::new();
+            while received.len() < 2 {
+                reader.read_buf(&mut buf).await.unwrap();
+                while let Some(msg) = codec.decode(&mut buf).unwrap() {
+                    received.push(msg);
+                }
+            }
+            let ids: Vec<_> = received
+                .iter()
+                .map(|msg| match msg {
+                    ControlMessage::MaxRequestId(m) => m.request_id,
+                    other => panic!("unexpected message {other:?}"),
+                })
+                .collect();
+            assert_eq!(ids, [1, 2]);
+
+            session.shutdown().await;
+            assert!(!session.spawn_control_writer(tokio::io::sink(), mpsc::channel(1).1));
+        });
+    }
+
+    #[test]
+    fn shutdown_stops_background_tasks() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (session, _rx) = Session::new(Arc::new(DummyTransport));
+            let (tx, mut rx) = mpsc::channel::<()>(1);
+            session.spawn(async move {
+                let _tx = tx;
+                std::future::pending::<()>().await;
+            });
+
+            session.shutdown().await;
+            assert!(rx.recv().await.is_none());
+            assert!(!session.spawn(async {}));
+        });
+    }
+
+    #[test]
+    fn failed_subscribe_is_rolled_back() {
+        use crate::repair::RepairRange;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (session, rx) = Session::new(Arc::new(DummyTransport));
+            session.track_manager.handle_max_request_id(1).unwrap();
+            drop(rx);
+
+            let request =
+                SubscribeRequest::new(1, "video").with_repair(RepairPolicy::LastObjects(4));
+            assert!(matches!(
+                session.subscribe(request).await,
+                Err(Error::Transport(_))
+            ));
+            assert!(session.track_manager.subscriptions().is_empty());
+            assert!(session.track_manager.watch_established(0).is_none());
+            assert!(!session.track_manager.end_subscription(0, None));
+            let range = RepairRange {
+                group_id: 0,
+                first_object: 0,
+                last_object: 0,
+            };
+            assert!(session.repairs.lock().unwrap().fetch(1, 0, range).is_none());
+        });
+    }
+
+    #[test]
+    fn request_id_at_advertised_max_is_too_many_requests() {
+        use crate::request::SubscribeRequest;
+
+        let (session, _rx) = Session::new(Arc::new(DummyTransport));
+        let subscribe = |request_id| {
+            ControlMessage::Subscribe(
+                SubscribeRequest::new(1, "video")
+                    .into_subscribe(request_id)
+                    .unwrap(),
+            )
+        };
+        assert!(matches!(
+            session.check_incoming(&mut subscribe(0)),
+            Err(Error::TooManyRequests)
+        ));
+
+        assert_eq!(session.advertise_max_request_id(4).unwrap().request_id, 4);
+        assert!(session.advertise_max_request_id(4).is_err());
+        assert!(session.check_incoming(&mut subscribe(2)).is_ok());
+        assert!(matches!(
+            session.check_incoming(&mut subscribe(4)),
+            Err(Error::TooManyRequests)
+        ));
+    }
+
+    #[test]
+    fn requests_blocked_sent_once_per_maximum() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let ns = |s: &str| vec!["example.com".to_string(), s.to_string()];
+            let (session, mut rx) = Session::new(Arc::new(DummyTransport));
+            session.track_manager.handle_max_request_id(1).unwrap();
+            session
+                .announce(AnnounceRequest::new(ns("a")))
+                .await
+                .unwrap();
+            assert!(matches!(rx.recv().await, Some(ControlMessage::Announce(_))));
+
+            for _ in 0..2 {
+                assert!(matches!(
+                    session.subscribe_announces(ns("b")).await,
+                    Err(Error::TooManyRequests)
+                ));
+            }
+            match rx.try_recv() {
+                Ok(ControlMessage::RequestsBlocked(b)) => assert_eq!(b.maximum_request_id, 1),
+                _ => panic!("expected REQUESTS_BLOCKED"),
+            }
+            assert!(rx.try_recv().is_err());
+
+            session.track_manager.handle_max_request_id(2).unwrap();
+            session
+                .announce(AnnounceRequest::new(ns("b")))
+                .await
+                .unwrap();
+            assert!(
+                session
+                    .announce(AnnounceRequest::new(ns("c")))
+                    .await
+                    .is_err()
+            );
+            assert!(matches!(rx.try_recv(), Ok(ControlMessage::Announce(_))));
+            match rx.try_recv() {
+                Ok(ControlMessage::RequestsBlocked(b)) => assert_eq!(b.maximum_request_id, 2),
+                _ => panic!("expected REQUESTS_BLOCKED"),
+            }
+        });
+    }
+
+    #[test]
+    fn requests_blocked_reported_once_per_maximum() {
+        let (mut session, _rx) = Session::new(Arc::new(DummyTransport));
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let log = reported.clone();
+        session.on_requests_blocked(move |max| log.lock().unwrap().push(max));
+        let blocked = |maximum_request_id| RequestsBlocked { maximum_request_id };
+
+        session.advertise_max_request_id(4).unwrap();
+        assert!(session.handle_requests_blocked(&blocked(4)));
+        assert!(!session.handle_requests_blocked(&blocked(4)));
+        session.advertise_max_request_id(8).unwrap();
+        // Sent before the peer saw the new maximum.
+        assert!(!session.handle_requests_blocked(&blocked(4)));
+        assert!(session.handle_requests_blocked(&blocked(8)));
+        assert_eq!(*reported.lock().unwrap(), vec![4, 8]);
+    }
+
+    async fn next_update(
+        rx: &mut mpsc::Receiver<ControlMessage>,
+    ) -> crate::message::SubscribeUpdate {
+        match rx.recv().await {
+            Some(ControlMessage::SubscribeUpdate(u)) => u,
+            _ => panic!("expected SUBSCRIBE_UPDATE"),
+        }
+    }
+
+    #[test]
+    fn subscriber_priority_policy() {
+        use crate::request::SubscribeRequest;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let policy = SubscriberPriorityPolicy::default()
+                .with_default(64)
+                .with_range(32, 96);
+            let config = SessionConfig::default().with_subscriber_priority(policy);
+            let (session, mut rx) = Session::with_config(Arc::new(DummyTransport), config);
+            session.track_manager.handle_max_request_id(10).unwrap();
+
+            let _default = session
+                .subscribe(SubscribeRequest::new(1, "video"))
+                .await
+                .unwrap();
+            let _explicit = session
+                .subscribe(SubscribeRequest::new(1, "audio").with_subscriber_priority(10))
+                .await
+                .unwrap();
+            let mut priorities = Vec::new();
+            for _ in 0..2 {
+                match rx.recv().await {
+                    Some(ControlMessage::Subscribe(s)) => priorities.push(s.subscriber_priority),
+                    _ => panic!("expected SUBSCRIBE"),
+                }
+            }
+            assert_eq!(priorities, [64, 10]);
+
+            let mut incoming = ControlMessage::Subscribe(
+                SubscribeRequest::new(1, "video")
+                    .with_subscriber_priority(0)
+                    .into_subscribe(1)
+                    .unwrap(),
+            );
+            assert!(session.police_priority(&mut incoming));
+            match &incoming {
+                ControlMessage::Subscribe(s) => assert_eq!(s.subscriber_priority, 32),
+                _ => unreachable!(),
+            }
+            assert!(!session.police_priority(&mut incoming));
+
+            // Every incoming message is policed by the session checks.
+            let mut publish_ok = ControlMessage::PublishOk(
+                crate::publish::PublishResponse::accept()
+                    .with_subscriber_priority(200)
+                    .into_publish_ok(3)
+                    .unwrap(),
+            );
+            session.check_incoming(&mut publish_ok).unwrap();
+            match &publish_ok {
+                ControlMessage::PublishOk(ok) => assert_eq!(ok.subscriber_priority, 96),
+                _ => unreachable!(),
+            }
+        });
+    }
+
+    fn subscribe_ok(request_id: u64) -> crate::message::SubscribeOk {
+        crate::message::SubscribeOk {
+            request_id,
+            track_alias: request_id,
+            expires: 0,
+            group_order: 0x1,
+            content_exists: false,
+            largest_location: None,
+            parameters: Vec::new(),
         }
     }
+
+    #[test]
+    fn subscription_handle_sends_updates() {
+        use crate::model::{Filter, Location};
+        use crate::request::SubscribeRequest;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (session, mut rx) = Session::new(Arc::new(DummyTransport));
+            session.track_manager.handle_max_request_id(10).unwrap();
+            let start = Location {
+                group: 2,
+                object: 0,
+            };
+            let handle = session
+                .subscribe(
+                    SubscribeRequest::new(1, "video")
+                        .with_filter(Filter::absolute_range(start.clone(), 9).unwrap()),
+                )
+                .await
+                .unwrap();
+            match rx.recv().await {
+                Some(ControlMessage::Subscribe(s)) => assert_eq!(s.request_id, handle.request_id()),
+                _ => panic!("expected SUBSCRIBE"),
+            }
+
+            // Nothing can be updated before SUBSCRIBE_OK.
+            assert!(handle.pause().await.is_err());
+            assert!(rx.try_recv().is_err());
+            assert_eq!(handle.group_order(), 0x0);
+            session
+                .track_manager
+                .handle_subscribe_ok(&subscribe_ok(handle.request_id()))
+                .unwrap();
+            assert!(handle.is_established());
+            assert_eq!(handle.group_order(), 0x1);
+
+            handle.pause().await.unwrap();
+            let paused = next_update(&mut rx).await;
+            assert_eq!((paused.forward, paused.end_group), (0, 10));
+            assert_eq!(paused.start_location, start);
+
+            handle.set_priority(7).await.unwrap();
+            handle.narrow(5).await.unwrap();
+            let narrowed = {
+                next_update(&mut rx).await;
+                next_update(&mut rx).await
+            };
+            assert_eq!(narrowed.subscriber_priority, 7);
+            assert_eq!(narrowed.end_group, 6);
+
+            assert!(handle.narrow(6).await.is_err());
+            assert!(handle.narrow(1).await.is_err());
+            handle.resume().await.unwrap();
+            assert_eq!(next_update(&mut rx).await.forward, 1);
+        });
+    }
+
+    #[test]
+    fn discovery_state_moves_to_new_session() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let ns = |s: &str| vec!["example.com".to_string(), s.to_string()];
+            use crate::auth::{AuthToken, Token};
+
+            let token = Token {
+                token_type: 0,
+                value: bytes::Bytes::from_static(b"secret"),
+            };
+            let (old, _old_rx) = Session::new(Arc::new(DummyTransport));
+            old.track_manager.handle_max_request_id(10).unwrap();
+            old.announce(
+                AnnounceRequest::new(ns("live")).with_auth_token(AuthToken::Register {
+                    alias: 1,
+                    token: token.clone(),
+                }),
+            )
+            .await
+            .unwrap();
+            old.announce(AnnounceRequest::new(ns("vod"))).await.unwrap();
+            old.announce(
+                AnnounceRequest::new(ns("clips")).with_auth_token(AuthToken::UseAlias { alias: 1 }),
+            )
+            .await
+            .unwrap();
+            old.unannounce(&ns("vod")).await.unwrap();
+            old.subscribe_announces(ns("sports")).await.unwrap();
+
+            let (new, mut rx) = Session::new(Arc::new(DummyTransport));
+            new.track_manager.handle_max_request_id(10).unwrap();
+            old.migrate_discovery(&new).await.unwrap();
+
+            // The token registered on the old session is sent by value,
+            // also where it was used by its alias.
+            let by_value = AuthToken::UseValue { token }.into_parameter().unwrap();
+            match rx.recv().await {
+                Some(ControlMessage::Announce(a)) => {
+                    assert_eq!(a.track_namespace, ns("live"));
+                    assert_eq!(a.parameters, vec![by_value.clone()]);
+                }
+                _ => panic!("expected ANNOUNCE"),
+            }
+            match rx.recv().await {
+                Some(ControlMessage::Announce(a)) => {
+                    assert_eq!(a.track_namespace, ns("clips"));
+                    assert_eq!(a.parameters, vec![by_value]);
+                }
+                _ => panic!("expected ANNOUNCE"),
+            }
+            match rx.recv().await {
+                Some(ControlMessage::SubscribeAnnounces(s)) => {
+                    assert_eq!(s.track_namespace_prefix, ns("sports"))
+                }
+                _ => panic!("expected SUBSCRIBE_ANNOUNCES"),
+            }
+            assert!(rx.try_recv().is_err());
+            assert_eq!(new.discovery.announced(), vec![ns("live"), ns("clips")]);
+            assert_eq!(new.discovery.prefixes(), vec![ns("sports")]);
+        });
+    }
+
+    #[test]
+    fn rejected_and_cancelled_announces_are_forgotten() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let ns = |s: &str| vec!["example.com".to_string(), s.to_string()];
+            let (session, _rx) = Session::new(Arc::new(DummyTransport));
+            session.track_manager.handle_max_request_id(10).unwrap();
+            let live = session
+                .announce(AnnounceRequest::new(ns("live")))
+                .await
+                .unwrap();
+            session
+                .announce(AnnounceRequest::new(ns("vod")))
+                .await
+                .unwrap();
+
+            let error = AnnounceError {
+                request_id: live,
+                error_code: 0x1,
+                error_reason: "unauthorized".into(),
+            };
+            assert_eq!(session.handle_announce_error(&error), Some(ns("live")));
+            assert_eq!(session.handle_announce_error(&error), None);
+            assert!(session.handle_announce_cancel(&AnnounceCancel {
+                track_namespace: ns("vod"),
+                error_code: 0x0,
+                error_reason: String::new(),
+            }));
+            assert!(session.discovery.announced().is_empty());
+
+            // Both may be announced again.
+            session
+                .announce(AnnounceRequest::new(ns("live")))
+                .await
+                .unwrap();
+            session
+                .announce(AnnounceRequest::new(ns("vod")))
+                .await
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn duplicate_announce_is_refused() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let ns = vec!["example.com".to_string(), "live".to_string()];
+            let (session, mut rx) = Session::new(Arc::new(DummyTransport));
+            session.track_manager.handle_max_request_id(10).unwrap();
+            session
+                .announce(AnnounceRequest::new(ns.clone()))
+                .await
+                .unwrap();
+            assert!(matches!(
+                session.announce(AnnounceRequest::new(ns.clone())).await,
+                Err(Error::DuplicateAnnounce { .. })
+            ));
+            session.unannounce(&ns).await.unwrap();
+            session
+                .announce(AnnounceRequest::new(ns.clone()))
+                .await
+                .unwrap();
+
+            let mut request_ids = Vec::new();
+            while let Ok(msg) = rx.try_recv() {
+                if let ControlMessage::Announce(a) = msg {
+                    request_ids.push(a.request_id);
+                }
+            }
+            // No Request ID was spent on the refused ANNOUNCE.
+            assert_eq!(request_ids, [0, 1]);
+
+            session.advertise_max_request_id(10).unwrap();
+            let mut incoming = ControlMessage::Announce(
+                AnnounceRequest::new(ns.clone()).into_announce(1).unwrap(),
+            );
+            session.check_incoming(&mut incoming).unwrap();
+            if let ControlMessage::Announce(a) = &incoming {
+                session.peer_announces.handle_announce(a).unwrap();
+            }
+            let mut duplicate =
+                ControlMessage::Announce(AnnounceRequest::new(ns).into_announce(3).unwrap());
+            let error = session.check_incoming(&mut duplicate).unwrap_err();
+            assert_eq!(error.violation().unwrap().rule, "announce.duplicate");
+            assert_eq!(
+                crate::error::TerminationCode::from(&error),
+                crate::error::TerminationCode::ProtocolViolation
+            );
+        });
+    }
+
+    #[test]
+    fn control_queue_overflow_policies() {
+        use crate::control::{ControlQueueConfig, OverflowPolicy};
+        use crate::message::{MaxRequestId, TrackStatusRequest};
+        use std::future::Future;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let max_request_id = || ControlMessage::MaxRequestId(MaxRequestId { request_id: 1 });
+            let config = ControlQueueConfig::default().with_capacity(1);
+
+            let (session, _rx) = Session::with_control_queue(
+                Arc::new(DummyTransport),
+                config.clone().with_overflow_policy(OverflowPolicy::Error),
+            );
+            session.send_control(max_request_id()).await.unwrap();
+            assert!(matches!(
+                session.send_control(max_request_id()).await,
+                Err(Error::ControlQueueFull)
+            ));
+            let stats = session.control_queue_stats();
+            assert_eq!((stats.depth, stats.high_water, stats.rejected), (1, 1, 1));
+
+            let (session, mut rx) = Session::with_control_queue(
+                Arc::new(DummyTransport),
+                config.with_overflow_policy(OverflowPolicy::DropNonCritical),
+            );
+            session.send_control(max_request_id()).await.unwrap();
+            let track_status = ControlMessage::TrackStatusRequest(TrackStatusRequest {
+                request_id: 0,
+                track_namespace: 1,
+                track_name: "video".into(),
+                parameters: Vec::new(),
+            });
+            session.send_control(track_status).await.unwrap();
+            assert_eq!(session.control_queue_stats().dropped, 1);
+
+            // Critical messages wait for room.
+            let mut send = std::pin::pin!(session.send_control(max_request_id()));
+            std::future::poll_fn(|cx| {
+                assert!(send.as_mut().poll(cx).is_pending());
+                Poll::Ready(())
+            })
+            .await;
+            assert!(matches!(
+                rx.recv().await,
+                Some(ControlMessage::MaxRequestId(_))
+            ));
+            send.await.unwrap();
+            assert!(matches!(
+                rx.recv().await,
+                Some(ControlMessage::MaxRequestId(_))
+            ));
+        });
+    }
+
+    #[test]
+    fn subscription_handles_share_the_control_queue() {
+        use crate::control::{ControlQueueConfig, OverflowPolicy};
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let config = ControlQueueConfig::default()
+                .with_capacity(0)
+                .with_overflow_policy(OverflowPolicy::Error);
+            let (session, mut rx) = Session::with_control_queue(Arc::new(DummyTransport), config);
+            assert_eq!(session.control_queue_stats().capacity, 1);
+            session.track_manager.handle_max_request_id(1).unwrap();
+            let handle = session
+                .subscribe(SubscribeRequest::new(0, "video"))
+                .await
+                .unwrap();
+
+            session
+                .track_manager
+                .handle_subscribe_ok(&subscribe_ok(handle.request_id()))
+                .unwrap();
+            // The SUBSCRIBE fills the queue, so the update is refused as
+            // any other message would be.
+            assert!(matches!(handle.pause().await, Err(Error::ControlQueueFull)));
+            assert_eq!(session.control_queue_stats().rejected, 1);
+            assert!(matches!(
+                rx.recv().await,
+                Some(ControlMessage::Subscribe(_))
+            ));
+            handle.unsubscribe().await.unwrap();
+            assert!(matches!(
+                rx.recv().await,
+                Some(ControlMessage::Unsubscribe(_))
+            ));
+            assert_eq!(session.control_queue_stats().high_water, 1);
+        });
+    }
+
+    #[test]
+    fn data_streams_beyond_limit_wait() {
+        use crate::message::SubscribeOk;
+        use crate::mock::MockTransport;
+        use crate::track::TrackPublisher;
+        use bytes::{Bytes, BytesMut};
+        use tokio::io::AsyncWriteExt;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let config = SessionConfig::default().with_data_stream_limits(
+                DataStreamLimits::default().with_max_concurrent_streams(2),
+            );
+            let (session, _rx) = Session::with_config(Arc::new(DummyTransport), config);
+            let session = Arc::new(session);
+            session.track_manager.handle_max_request_id(1).unwrap();
+            let (request_id, mut objects) = session
+                .track_manager
+                .subscribe_track("video".into())
+                .unwrap();
+            session
+                .track_manager
+                .handle_subscribe_ok(&SubscribeOk {
+                    request_id,
+                    track_alias: 1,
+                    expires: 0,
+                    group_order: 1,
+                    content_exists: false,
+                    largest_location: None,
+                    parameters: Vec::new(),
+                })
+                .unwrap();
+
+            let (mut publisher_end, mut subscriber_end) = MockTransport::pair();
+            let accepting = session.clone();
+            tokio::spawn(async move {
+                let (fetch_tx, _fetch_rx) = mpsc::channel(1);
+                let _ = accepting
+                    .accept_data_streams(&mut subscriber_end, fetch_tx)
+                    .await;
+            });
+
+            // Two streams stay open, holding every permit.
+            let mut publisher = TrackPublisher::new(1);
+            let mut open = Vec::new();
+            for _ in 0..2 {
+                let subgroup = publisher.begin_group().subgroup(0);
+                let mut stream = publisher_end.open_uni_stream().await.unwrap();
+                let mut buf = BytesMut::new();
+                subgroup.header(false).encode(&mut buf).unwrap();
+                stream.write_all(&buf).await.unwrap();
+                open.push(stream);
+            }
+            let subgroup = publisher.begin_group().subgroup(0);
+            let mut buf = BytesMut::new();
+            subgroup.header(false).encode(&mut buf).unwrap();
+            subgroup
+                .object(Bytes::from_static(b"frame"))
+                .to_subgroup_object()
+                .encode(&mut buf, false)
+                .unwrap();
+            let mut third = publisher_end.open_uni_stream().await.unwrap();
+            third.write_all(&buf).await.unwrap();
+            third.shutdown().await.unwrap();
+
+            for _ in 0..10 {
+                tokio::task::yield_now().await;
+            }
+            assert_eq!(session.active_data_streams(), 2);
+            assert!(objects.rx.try_recv().is_err());
+
+            // Ending one stream lets the third be read.
+            open.pop().unwrap().shutdown().await.unwrap();
+            let object = objects.recv().await.unwrap().unwrap();
+            assert_eq!(object.metadata.group_id, 2);
+            session.shutdown().await;
+        });
+    }
+
+    #[test]
+    fn oversized_objects_are_rejected_both_ways() {
+        use crate::message::SubscribeOk;
+        use crate::mock::MockTransport;
+        use crate::track::TrackPublisher;
+        use bytes::{Bytes, BytesMut};
+        use tokio::io::AsyncWriteExt;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let config = SessionConfig::default().with_max_object_payload_size(8);
+            let (session, _rx) = Session::with_config(Arc::new(DummyTransport), config);
+            let session = Arc::new(session);
+            assert_eq!(session.max_object_payload_size(), 8);
+            session.track_manager.handle_max_request_id(1).unwrap();
+            let (request_id, mut objects) = session
+                .track_manager
+                .subscribe_track("video".into())
+                .unwrap();
+            session
+                .track_manager
+                .handle_subscribe_ok(&SubscribeOk {
+                    request_id,
+                    track_alias: 1,
+                    expires: 0,
+                    group_order: 1,
+                    content_exists: false,
+                    largest_location: None,
+                    parameters: Vec::new(),
+                })
+                .unwrap();
+
+            let (mut publisher_end, mut subscriber_end) = MockTransport::pair();
+            let accepting = session.clone();
+            tokio::spawn(async move {
+                let (fetch_tx, _fetch_rx) = mpsc::channel(1);
+                let _ = accepting
+                    .accept_data_streams(&mut subscriber_end, fetch_tx)
+                    .await;
+            });
+
+            // The oversized object is rejected on its length alone, without
+            // waiting for the payload.
+            let mut publisher = TrackPublisher::new(1);
+            let subgroup = publisher.begin_group().subgroup(0);
+            let small = subgroup.object(Bytes::from_static(b"frame"));
+            let large = subgroup.object(Bytes::from(vec![0; 64]));
+            let mut buf = BytesMut::new();
+            subgroup.header(false).encode(&mut buf).unwrap();
+            small.to_subgroup_object().encode(&mut buf, false).unwrap();
+            large.to_subgroup_object().encode(&mut buf, false).unwrap();
+            buf.truncate(buf.len() - 60);
+            let mut stream = publisher_end.open_uni_stream().await.unwrap();
+            stream.write_all(&buf).await.unwrap();
+
+            assert_eq!(objects.recv().await.unwrap().unwrap(), small);
+            assert!(matches!(
+                objects.recv().await.unwrap(),
+                Err(Error::ObjectTooLarge { size: 64, max: 8 })
+            ));
+            assert_eq!(session.data_stream_stats().failed, 1);
+
+            // Sending is bounded the same way.
+            let mut stream = publisher_end.open_uni_stream().await.unwrap();
+            let header = subgroup.header(false);
+            let sent = session
+                .write_object(
+                    &mut stream,
+                    Some(&header),
+                    false,
+                    &small.to_subgroup_object(),
+                )
+                .await;
+            assert!(sent.is_ok());
+            let sent = session
+                .write_object(&mut stream, None, false, &large.to_subgroup_object())
+                .await;
+            assert!(matches!(
+                sent,
+                Err(Error::ObjectTooLarge { size: 64, max: 8 })
+            ));
+            assert_eq!(
+                stream.reset_code(),
+                Some(StreamResetCode::InternalError as u64)
+            );
+            session.shutdown().await;
+        });
+    }
+
+    #[test]
+    fn draft11_sessions_exchange_subgroup_streams() {
+        use crate::codec::DRAFT_11;
+        use crate::message::SubscribeOk;
+        use crate::mock::MockTransport;
+        use crate::track::TrackPublisher;
+        use bytes::Bytes;
+        use tokio::io::AsyncReadExt;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let draft11 = WireVersion::negotiated(DRAFT_11).unwrap();
+            let (sender, _rx) = Session::new(Arc::new(DummyTransport));
+            sender.set_wire_version(draft11.clone());
+            assert_eq!(sender.control_codec().version().version(), DRAFT_11);
+            let (receiver, _rx) = Session::new(Arc::new(DummyTransport));
+            receiver.set_wire_version(draft11);
+            let receiver = Arc::new(receiver);
+            receiver.track_manager.handle_max_request_id(1).unwrap();
+            let (request_id, mut objects) = receiver
+                .track_manager
+                .subscribe_track("video".into())
+                .unwrap();
+            receiver
+                .track_manager
+                .handle_subscribe_ok(&SubscribeOk {
+                    request_id,
+                    track_alias: 1,
+                    expires: 0,
+                    group_order: 1,
+                    content_exists: false,
+                    largest_location: None,
+                    parameters: Vec::new(),
+                })
+                .unwrap();
+
+            let mut publisher = TrackPublisher::new(1);
+            let subgroup = publisher.begin_group().subgroup(0);
+            let object = subgroup.object(Bytes::from_static(b"frame"));
+            let header = subgroup.header(true);
+            let (mut a, mut b) = MockTransport::pair();
+            for _ in 0..2 {
+                let mut stream = a.open_uni_stream().await.unwrap();
+                sender
+                    .write_object(
+                        &mut stream,
+                        Some(&header),
+                        false,
+                        &object.to_subgroup_object(),
+                    )
+                    .await
+                    .unwrap();
+            }
+
+            // Draft-11 types have no end-of-group bit.
+            let mut raw = b.accept_uni_stream().await.unwrap();
+            assert_eq!(raw.read_u8().await.unwrap(), 0x08);
+
+            let accepting = receiver.clone();
+            tokio::spawn(async move {
+                let (fetch_tx, _fetch_rx) = mpsc::channel(1);
+                let _ = accepting.accept_data_streams(&mut b, fetch_tx).await;
+            });
+            assert_eq!(objects.recv().await.unwrap().unwrap(), object);
+            receiver.shutdown().await;
+        });
+    }
+
+    #[test]
+    fn data_streams_are_dispatched_by_type() {
+        use crate::data::FetchHeader;
+        use crate::mock::MockTransport;
+        use bytes::BytesMut;
+        use tokio::io::AsyncWriteExt;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (session, _rx) = Session::new(Arc::new(DummyTransport));
+            let session = Arc::new(session);
+            let (mut publisher_end, mut subscriber_end) = MockTransport::pair();
+            let (fetch_tx, mut fetch_rx) = mpsc::channel(1);
+            let accepting = session.clone();
+            let accept = tokio::spawn(async move {
+                accepting
+                    .accept_data_streams(&mut subscriber_end, fetch_tx)
+                    .await
+            });
+
+            let mut buf = BytesMut::new();
+            FetchHeader { request_id: 3 }.encode(&mut buf).unwrap();
+            let mut fetch = publisher_end.open_uni_stream().await.unwrap();
+            fetch.write_all(&buf).await.unwrap();
+            let mut reader = fetch_rx.recv().await.unwrap();
+            assert_eq!(reader.request_id().await.unwrap(), 3);
+
+            let mut unknown = publisher_end.open_uni_stream().await.unwrap();
+            unknown.write_all(&[0x3f]).await.unwrap();
+            match accept.await.unwrap() {
+                Err(Error::ProtocolViolation {
+                    context: Some(v), ..
+                }) => assert_eq!(v.rule, "stream.unknown_type"),
+                r => panic!("unexpected result: {r:?}"),
+            }
+            session.shutdown().await;
+        });
+    }
+
+    #[test]
+    fn truncated_data_streams_are_counted() {
+        use crate::mock::MockTransport;
+        use crate::track::TrackPublisher;
+        use bytes::{Bytes, BytesMut};
+        use tokio::io::AsyncWriteExt;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (session, _rx) = Session::new(Arc::new(DummyTransport));
+            let session = Arc::new(session);
+            let (mut publisher_end, mut subscriber_end) = MockTransport::pair();
+            let (fetch_tx, _fetch_rx) = mpsc::channel(1);
+            let accepting = session.clone();
+            tokio::spawn(async move {
+                accepting
+                    .accept_data_streams(&mut subscriber_end, fetch_tx)
+                    .await
+            });
+
+            let mut publisher = TrackPublisher::new(1);
+            let subgroup = publisher.begin_group().subgroup(0);
+            let mut buf = BytesMut::new();
+            subgroup.header(false).encode(&mut buf).unwrap();
+            subgroup
+                .object(Bytes::from_static(b"0123456789"))
+                .to_subgroup_object()
+                .encode(&mut buf, false)
+                .unwrap();
+            for len in [buf.len(), buf.len() - 3] {
+                let mut stream = publisher_end.open_uni_stream().await.unwrap();
+                stream.write_all(&buf[..len]).await.unwrap();
+            }
+            while session.data_stream_stats().completed + session.data_stream_stats().truncated < 2
+            {
+                tokio::task::yield_now().await;
+            }
+            assert_eq!(
+                session.data_stream_stats(),
+                DataStreamStats {
+                    completed: 1,
+                    truncated: 1,
+                    failed: 0,
+                }
+            );
+            session.shutdown().await;
+        });
+    }
+
+    #[test]
+    fn datagrams_wait_for_their_alias() {
+        use crate::data::ObjectDatagram;
+        use crate::message::SubscribeOk;
+        use crate::mock::MockTransport;
+        use crate::track::AliasWindow;
+        use bytes::{Bytes, BytesMut};
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let config = SessionConfig::default()
+                .with_unknown_alias_policy(UnknownAliasPolicy::Buffer(AliasWindow::default()));
+            let (session, _rx) = Session::with_config(Arc::new(DummyTransport), config);
+            let session = Arc::new(session);
+            session.track_manager.handle_max_request_id(1).unwrap();
+            let (request_id, mut objects) = session
+                .track_manager
+                .subscribe_track("video".into())
+                .unwrap();
+
+            let (mut publisher_end, mut subscriber_end) = MockTransport::pair();
+            let accepting = session.clone();
+            let accept =
+                tokio::spawn(async move { accepting.accept_datagrams(&mut subscriber_end).await });
+
+            let mut buf = BytesMut::new();
+            ObjectDatagram {
+                track_alias: 4,
+                group_id: 0,
+                object_id: 0,
+                publisher_priority: 0,
+                end_of_group: false,
+                extension_headers: Bytes::new(),
+                object_status: None,
+                payload: Bytes::from_static(b"frame"),
+            }
+            .encode(&mut buf)
+            .unwrap();
+            publisher_end.send_datagram(buf.freeze()).await.unwrap();
+            while session.track_manager.held_objects() == 0 {
+                tokio::task::yield_now().await;
+            }
+
+            session
+                .track_manager
+                .handle_subscribe_ok(&SubscribeOk {
+                    request_id,
+                    track_alias: 4,
+                    expires: 0,
+                    group_order: 0x1,
+                    content_exists: false,
+                    largest_location: None,
+                    parameters: Vec::new(),
+                })
+                .unwrap();
+            let object = objects.recv().await.unwrap().unwrap();
+            assert_eq!(&object.payload[..], b"frame");
+
+            publisher_end
+                .send_datagram(Bytes::from_static(&[0x3f]))
+                .await
+                .unwrap();
+            match accept.await.unwrap() {
+                Err(Error::ProtocolViolation {
+                    context: Some(v), ..
+                }) => assert_eq!(v.rule, "datagram.malformed"),
+                r => panic!("unexpected result: {r:?}"),
+            }
+            session.shutdown().await;
+        });
+    }
+
+    #[test]
+    fn draft11_datagrams_roundtrip() {
+        use crate::codec::DRAFT_11;
+        use crate::data::ObjectDatagram;
+        use crate::message::SubscribeOk;
+        use crate::mock::MockTransport;
+        use crate::model::ObjectStatus;
+        use bytes::Bytes;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (publisher, _rx) = Session::new(Arc::new(DummyTransport));
+            let (subscriber, _rx) = Session::new(Arc::new(DummyTransport));
+            let subscriber = Arc::new(subscriber);
+            for session in [&publisher, &*subscriber] {
+                session.set_wire_version(WireVersion::negotiated(DRAFT_11).unwrap());
+            }
+            subscriber.track_manager.handle_max_request_id(1).unwrap();
+            let (request_id, mut objects) = subscriber
+                .track_manager
+                .subscribe_track("video".into())
+                .unwrap();
+            subscriber
+                .track_manager
+                .handle_subscribe_ok(&SubscribeOk {
+                    request_id,
+                    track_alias: 4,
+                    expires: 0,
+                    group_order: 0x1,
+                    content_exists: false,
+                    largest_location: None,
+                    parameters: Vec::new(),
+                })
+                .unwrap();
+
+            let (mut publisher_end, mut subscriber_end) = MockTransport::pair();
+            let accepting = subscriber.clone();
+            let accept =
+                tokio::spawn(async move { accepting.accept_datagrams(&mut subscriber_end).await });
+
+            let object = ObjectDatagram {
+                track_alias: 4,
+                group_id: 0,
+                object_id: 0,
+                publisher_priority: 0,
+                end_of_group: false,
+                extension_headers: Bytes::new(),
+                object_status: None,
+                payload: Bytes::from_static(b"frame"),
+            };
+            let missing = ObjectDatagram {
+                object_id: 1,
+                object_status: Some(ObjectStatus::DoesNotExist.code()),
+                payload: Bytes::new(),
+                ..object.clone()
+            };
+            for datagram in [&object, &missing] {
+                publisher
+                    .send_object_datagram(&mut publisher_end, datagram)
+                    .await
+                    .unwrap();
+            }
+
+            let received = objects.recv().await.unwrap().unwrap();
+            assert_eq!(&received.payload[..], b"frame");
+            let received = objects.recv().await.unwrap().unwrap();
+            assert_eq!(received.metadata.object_id, 1);
+            assert_eq!(received.status, ObjectStatus::DoesNotExist);
+            assert!(received.payload.is_empty());
+
+            subscriber.shutdown().await;
+            accept.await.unwrap().unwrap();
+        });
+    }
+
+    #[test]
+    fn datagram_gaps_are_repaired_with_fetch() {
+        use crate::data::{FetchHeader, FetchObject, ObjectDatagram};
+        use crate::message::SubscribeOk;
+        use crate::mock::MockTransport;
+        use crate::model::Location;
+        use bytes::{Bytes, BytesMut};
+        use tokio::io::AsyncWriteExt;
+
+        let datagram = |object_id| {
+            let mut buf = BytesMut::new();
+            ObjectDatagram {
+                track_alias: 4,
+                group_id: 0,
+                object_id,
+                publisher_priority: 0,
+                end_of_group: false,
+                extension_headers: Bytes::new(),
+                object_status: None,
+                payload: Bytes::from_static(b"frame"),
+            }
+            .encode(&mut buf)
+            .unwrap();
+            buf.freeze()
+        };
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let (session, mut control) = Session::new(Arc::new(DummyTransport));
+            let session = Arc::new(session);
+            session.track_manager.handle_max_request_id(4).unwrap();
+            let request =
+                SubscribeRequest::new(0, "video").with_repair(RepairPolicy::LastObjects(4));
+            let mut subscription = session.subscribe(request).await.unwrap();
+            let Some(ControlMessage::Subscribe(subscribe)) = control.recv().await else {
+                panic!("expected SUBSCRIBE");
+            };
+            session
+                .track_manager
+                .handle_subscribe_ok(&SubscribeOk {
+                    request_id: subscribe.request_id,
+                    track_alias: 4,
+                    expires: 0,
+                    group_order: 0x1,
+                    content_exists: false,
+                    largest_location: None,
+                    parameters: Vec::new(),
+                })
+                .unwrap();
+
+            let (mut datagrams, mut datagrams_end) = MockTransport::pair();
+            let (mut streams, mut streams_end) = MockTransport::pair();
+            let accepting = session.clone();
+            tokio::spawn(async move { accepting.accept_datagrams(&mut datagrams_end).await });
+            let accepting = session.clone();
+            tokio::spawn(async move {
+                let (fetch_tx, _fetch_rx) = mpsc::channel(1);
+                let _ = accepting
+                    .accept_data_streams(&mut streams_end, fetch_tx)
+                    .await;
+            });
+
+            datagrams.send_datagram(datagram(0)).await.unwrap();
+            datagrams.send_datagram(datagram(2)).await.unwrap();
+            for object_id in [0, 2] {
+                let object = subscription.recv().await.unwrap().unwrap();
+                assert_eq!(object.metadata.object_id, object_id);
+            }
+            let Some(ControlMessage::Fetch(fetch)) = control.recv().await else {
+                panic!("expected FETCH");
+            };
+            assert_eq!(fetch.track_name.as_deref(), Some("video"));
+            assert_eq!(fetch.start_location, Some(Location::new(0, 1)));
+            assert_eq!(fetch.end_location, Some(Location::new(0, 2)));
+
+            let mut buf = BytesMut::new();
+            FetchHeader {
+                request_id: fetch.request_id,
+            }
+            .encode(&mut buf)
+            .unwrap();
+            FetchObject {
+                group_id: 0,
+                subgroup_id: 0,
+                object_id: 1,
+                publisher_priority: 0,
+                extension_headers: Bytes::new(),
+                object_status: None,
+                payload: Bytes::from_static(b"frame"),
+            }
+            .encode(&mut buf)
+            .unwrap();
+            let mut stream = streams.open_uni_stream().await.unwrap();
+            stream.write_all(&buf).await.unwrap();
+            stream.shutdown().await.unwrap();
+
+            let repaired = subscription.recv().await.unwrap().unwrap();
+            assert_eq!(repaired.metadata.object_id, 1);
+            assert_eq!(repaired.metadata.track_alias, 4);
+            session.shutdown().await;
+        });
+    }
 }
@@ -0,0 +1,623 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_sink::Sink;
+use tokio::sync::mpsc;
+
+use crate::{
+    error::Error,
+    message::{Publish, Subscribe, SubscribeOk},
+    model::{Filter, Location, Parameter},
+    publish::max_cache_duration_parameter,
+    retention::RetentionBuffer,
+    scheduler::resolve_group_order,
+    track::{Object, ObjectStream},
+};
+
+/// Capacity of each subscriber's object queue. A subscriber that falls this
+/// far behind is dropped by [`TrackSource::publish`], as with the 'Too Far
+/// Behind' SUBSCRIBE_DONE status: the last slot is kept for the
+/// [`Error::TooFarBehind`] ending its stream.
+pub const SUBSCRIBER_QUEUE_CAPACITY: usize = 1024;
+
+struct SourceSubscriber {
+    filter: Filter,
+    largest: Option<Location>,
+    tx: mpsc::Sender<Result<Object, Error>>,
+}
+
+struct SourceState {
+    largest: Option<Location>,
+    subscribers: Vec<SourceSubscriber>,
+    /// Subscribers dropped for falling too far behind.
+    dropped: u64,
+}
+
+/// Publisher side state of a track: the largest published location and the
+/// subscriptions receiving new objects.
+///
+/// Both are kept under one lock so that a SUBSCRIBE racing with newly
+/// published objects sees a consistent cutoff: the largest location
+/// returned by [`TrackSource::subscribe`] is exactly the last object the
+/// subscriber will not receive, and every object published afterwards
+/// that passes the filter is delivered once.
+pub struct TrackSource {
+    group_order: u8,
+    expires: u64,
+    max_cache_duration: Option<Duration>,
+    retention: Option<RetentionBuffer>,
+    state: Mutex<SourceState>,
+}
+
+impl Default for TrackSource {
+    /// Ascending group order and subscriptions that never expire.
+    fn default() -> Self {
+        Self {
+            group_order: 0x1,
+            expires: 0,
+            max_cache_duration: None,
+            retention: None,
+            state: Mutex::new(SourceState {
+                largest: None,
+                subscribers: Vec::new(),
+                dropped: 0,
+            }),
+        }
+    }
+}
+
+impl TrackSource {
+    /// Group order used for subscribers deferring to the publisher's.
+    pub fn with_group_order(mut self, group_order: u8) -> Self {
+        self.group_order = group_order;
+        self
+    }
+
+    /// Milliseconds after which subscriptions expire, zero meaning never.
+    pub fn with_expires(mut self, expires: u64) -> Self {
+        self.expires = expires;
+        self
+    }
+
+    /// How long caches downstream may keep the track's objects, announced
+    /// as MAX_CACHE_DURATION in SUBSCRIBE_OK and PUBLISH. Unlimited by
+    /// default.
+    pub fn with_max_cache_duration(mut self, max: Duration) -> Self {
+        self.max_cache_duration = Some(max);
+        self
+    }
+
+    /// Record every object published on the source in `retention`, so
+    /// FETCHes can be answered from what subscribers were sent. Content
+    /// Exists and Largest Location keep following the objects published on
+    /// the source only, so a new subscription never starts after objects it
+    /// has yet to receive.
+    pub fn with_retention(mut self, retention: RetentionBuffer) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    pub fn group_order(&self) -> u8 {
+        self.group_order
+    }
+
+    pub fn expires(&self) -> u64 {
+        self.expires
+    }
+
+    pub fn max_cache_duration(&self) -> Option<Duration> {
+        self.max_cache_duration
+    }
+
+    /// Parameters describing the track in SUBSCRIBE_OK and PUBLISH.
+    fn parameters(&self) -> Result<Vec<Parameter>, Error> {
+        Ok(match self.max_cache_duration {
+            Some(max) => vec![max_cache_duration_parameter(max)?],
+            None => Vec::new(),
+        })
+    }
+
+    /// Largest location published so far.
+    pub fn largest(&self) -> Option<Location> {
+        self.state.lock().unwrap().largest.clone()
+    }
+
+    /// Register a subscription. Returns the largest location at the time
+    /// of registration, to be sent in SUBSCRIBE_OK, and the stream of
+    /// matching objects.
+    pub fn subscribe(&self, filter: Filter) -> (Option<Location>, ObjectStream) {
+        let (tx, rx) = mpsc::channel(SUBSCRIBER_QUEUE_CAPACITY);
+        let mut state = self.state.lock().unwrap();
+        let largest = state.largest.clone();
+        state.subscribers.push(SourceSubscriber {
+            filter,
+            largest: largest.clone(),
+            tx,
+        });
+        (largest, ObjectStream { rx })
+    }
+
+    /// Publish an object to every subscription whose filter it passes.
+    /// Returns the number of subscriptions the object was queued on.
+    ///
+    /// A subscriber whose queue is full is dropped: its stream ends with
+    /// [`Error::TooFarBehind`] and it is counted in
+    /// [`dropped_subscribers`](Self::dropped_subscribers). Use
+    /// [`publish_async`](Self::publish_async) to wait for room instead.
+    pub fn publish(&self, object: Object) -> usize {
+        let loc = object.metadata.location();
+        let mut state = self.record(&object);
+        let mut delivered = 0;
+        let mut dropped = 0;
+        state.subscribers.retain(|s| {
+            if s.tx.is_closed() {
+                return false;
+            }
+            if !s.filter.matches(&loc, s.largest.as_ref()) {
+                return true;
+            }
+            // The last slot of the queue is kept for the error.
+            if s.tx.capacity() <= 1 {
+                let _ = s.tx.try_send(Err(Error::TooFarBehind));
+                dropped += 1;
+                return false;
+            }
+            let sent = s.tx.try_send(Ok(object.clone())).is_ok();
+            delivered += sent as usize;
+            sent
+        });
+        state.dropped += dropped;
+        delivered
+    }
+
+    /// Like [`publish`](Self::publish), but waits for room in the queue of
+    /// each subscription in turn instead of dropping full ones, so a slow
+    /// subscriber slows the publisher down rather than losing objects.
+    pub async fn publish_async(&self, object: Object) -> usize {
+        let loc = object.metadata.location();
+        let targets: Vec<_> = {
+            let mut state = self.record(&object);
+            state.subscribers.retain(|s| !s.tx.is_closed());
+            state
+                .subscribers
+                .iter()
+                .filter(|s| s.filter.matches(&loc, s.largest.as_ref()))
+                .map(|s| s.tx.clone())
+                .collect()
+        };
+        let mut delivered = 0;
+        for tx in targets {
+            if tx.send(Ok(object.clone())).await.is_ok() {
+                delivered += 1;
+            }
+        }
+        delivered
+    }
+
+    /// Record a published object in the retention buffer and as the
+    /// largest location, returning the locked state to deliver it.
+    fn record(&self, object: &Object) -> std::sync::MutexGuard<'_, SourceState> {
+        let loc = object.metadata.location();
+        if let Some(retention) = &self.retention {
+            retention.record(object);
+        }
+        let mut state = self.state.lock().unwrap();
+        let is_larger = match &state.largest {
+            Some(l) => l.is_before(&loc),
+            None => true,
+        };
+        if is_larger {
+            state.largest = Some(loc);
+        }
+        state
+    }
+
+    /// Subscribers dropped by [`publish`](Self::publish) for falling too
+    /// far behind.
+    pub fn dropped_subscribers(&self) -> u64 {
+        self.state.lock().unwrap().dropped
+    }
+
+    /// Number of live subscriptions.
+    pub fn subscriber_count(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.subscribers.retain(|s| !s.tx.is_closed());
+        state.subscribers.len()
+    }
+
+    /// [`Sink`] publishing the objects sent into it on this source.
+    pub fn sink(self: &Arc<Self>) -> SourceSink {
+        SourceSink {
+            source: self.clone(),
+            sending: None,
+            closed: false,
+        }
+    }
+}
+
+/// [`Sink`] of the objects to publish on a [`TrackSource`], created by
+/// [`TrackSource::sink`], so that a pipeline producing a stream of objects
+/// can be forwarded into a track with the usual stream combinators.
+///
+/// Objects are published with [`TrackSource::publish_async`]: the sink is
+/// ready for the next object once the previous one was queued for every
+/// subscriber, so slow subscribers apply backpressure to the pipeline
+/// instead of being dropped. Sending after the sink was closed fails.
+pub struct SourceSink {
+    source: Arc<TrackSource>,
+    /// Publication of the last object sent, until queued for every
+    /// subscriber.
+    sending: Option<Pin<Box<dyn Future<Output = usize> + Send>>>,
+    closed: bool,
+}
+
+impl SourceSink {
+    pub fn source(&self) -> &Arc<TrackSource> {
+        &self.source
+    }
+
+    /// Drive the publication of the last object sent to completion.
+    fn poll_sent(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        if let Some(sending) = &mut self.sending {
+            std::task::ready!(sending.as_mut().poll(cx));
+            self.sending = None;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Sink<Object> for SourceSink {
+    type Error = Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.poll_sent(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, object: Object) -> Result<(), Error> {
+        if self.closed {
+            return Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "sink closed").into());
+        }
+        let source = self.source.clone();
+        self.sending = Some(Box::pin(async move { source.publish_async(object).await }));
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.poll_sent(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        std::task::ready!(self.poll_sent(cx))?;
+        self.closed = true;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Construction of a SUBSCRIBE_OK from the state of a [`TrackSource`].
+pub trait SubscribeOkExt: Sized {
+    /// Accept `subscribe` for the track published through `source` under
+    /// `track_alias`.
+    ///
+    /// The subscription is registered on the source while building the
+    /// reply, so the largest location and Content Exists describe exactly
+    /// the cutoff of the returned stream. The group order is the one
+    /// requested, or the source's if the subscriber deferred to it.
+    fn for_track(
+        source: &TrackSource,
+        subscribe: &Subscribe,
+        track_alias: u64,
+    ) -> Result<(Self, ObjectStream), Error>;
+}
+
+impl SubscribeOkExt for SubscribeOk {
+    fn for_track(
+        source: &TrackSource,
+        subscribe: &Subscribe,
+        track_alias: u64,
+    ) -> Result<(Self, ObjectStream), Error> {
+        let group_order = resolve_group_order(subscribe.group_order, source.group_order)?;
+        let filter = subscribe.filter().clone();
+        let parameters = source.parameters()?;
+        let (largest, objects) = source.subscribe(filter);
+        let ok = SubscribeOk {
+            request_id: subscribe.request_id,
+            track_alias,
+            expires: source.expires,
+            group_order,
+            content_exists: largest.is_some(),
+            largest_location: largest,
+            parameters,
+        };
+        Ok((ok, objects))
+    }
+}
+
+/// Construction of a PUBLISH from the state of a [`TrackSource`].
+pub trait PublishExt: Sized {
+    /// Publish the track `track_name` of `track_namespace`, published
+    /// through `source`, under `track_alias`.
+    ///
+    /// As with [`SubscribeOkExt::for_track`], the subscription is
+    /// registered on the source while building the message, starting after
+    /// the largest location it announces.
+    fn for_track(
+        source: &TrackSource,
+        request_id: u64,
+        track_namespace: u64,
+        track_name: impl Into<String>,
+        track_alias: u64,
+    ) -> Result<(Self, ObjectStream), Error>;
+}
+
+impl PublishExt for Publish {
+    fn for_track(
+        source: &TrackSource,
+        request_id: u64,
+        track_namespace: u64,
+        track_name: impl Into<String>,
+        track_alias: u64,
+    ) -> Result<(Self, ObjectStream), Error> {
+        let parameters = source.parameters()?;
+        let (largest, objects) = source.subscribe(Filter::LargestObject);
+        let publish = Publish {
+            request_id,
+            track_namespace,
+            track_name: track_name.into(),
+            track_alias,
+            group_order: source.group_order,
+            content_exists: largest.is_some() as u8,
+            largest,
+            forward: 1,
+            parameters,
+        };
+        Ok((publish, objects))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::ObjectBuilder;
+    use bytes::Bytes;
+
+    fn object(group_id: u64, object_id: u64) -> Object {
+        ObjectBuilder::new(group_id, object_id)
+            .with_payload(Bytes::new())
+            .build()
+    }
+
+    fn drain(stream: &mut ObjectStream) -> Vec<(u64, u64)> {
+        let mut out = Vec::new();
+        while let Ok(Ok(o)) = stream.rx.try_recv() {
+            out.push((o.metadata.group_id, o.metadata.object_id));
+        }
+        out
+    }
+
+    #[test]
+    fn largest_object_filter_starts_after_largest() {
+        let source = TrackSource::default();
+        source.publish(object(0, 0));
+        source.publish(object(0, 1));
+
+        let (largest, mut stream) = source.subscribe(Filter::LargestObject);
+        assert_eq!(
+            largest,
+            Some(Location {
+                group: 0,
+                object: 1
+            })
+        );
+
+        // Reordered object below the cutoff is not delivered.
+        source.publish(object(0, 0));
+        source.publish(object(0, 2));
+        source.publish(object(1, 0));
+        assert_eq!(drain(&mut stream), vec![(0, 2), (1, 0)]);
+    }
+
+    #[test]
+    fn subscribe_ok_reflects_source() {
+        let subscribe = Subscribe::new(4, 0, "video", Filter::LargestObject);
+        let source = TrackSource::default()
+            .with_group_order(0x2)
+            .with_expires(30_000)
+            .with_max_cache_duration(Duration::from_secs(10));
+
+        let (ok, _) = SubscribeOk::for_track(&source, &subscribe, 9).unwrap();
+        assert_eq!((ok.request_id, ok.track_alias), (4, 9));
+        assert_eq!((ok.group_order, ok.expires), (0x2, 30_000));
+        assert!(!ok.content_exists);
+        assert_eq!(ok.largest_location, None);
+        assert_eq!(
+            crate::publish::max_cache_duration(&ok.parameters).unwrap(),
+            Some(Duration::from_secs(10))
+        );
+
+        source.publish(object(3, 1));
+        let mut ascending = subscribe.clone();
+        ascending.group_order = 0x1;
+        let (ok, mut stream) = SubscribeOk::for_track(&source, &ascending, 9).unwrap();
+        assert_eq!(ok.group_order, 0x1);
+        assert!(ok.content_exists);
+        assert_eq!(ok.largest_location, Some(Location::new(3, 1)));
+        source.publish(object(3, 2));
+        assert_eq!(drain(&mut stream), vec![(3, 2)]);
+
+        let mut invalid = subscribe;
+        invalid.group_order = 0x3;
+        assert!(SubscribeOk::for_track(&source, &invalid, 9).is_err());
+    }
+
+    #[test]
+    fn publish_reflects_source() {
+        let source = TrackSource::default()
+            .with_group_order(0x2)
+            .with_max_cache_duration(Duration::from_secs(10));
+        source.publish(object(3, 1));
+
+        let (publish, mut stream) = Publish::for_track(&source, 2, 0, "video", 9).unwrap();
+        assert_eq!((publish.request_id, publish.track_alias), (2, 9));
+        assert_eq!((publish.group_order, publish.forward), (0x2, 1));
+        assert_eq!(publish.content_exists, 1);
+        assert_eq!(publish.largest, Some(Location::new(3, 1)));
+        assert_eq!(
+            crate::publish::max_cache_duration(&publish.parameters).unwrap(),
+            Some(Duration::from_secs(10))
+        );
+        source.publish(object(3, 2));
+        assert_eq!(drain(&mut stream), vec![(3, 2)]);
+    }
+
+    #[test]
+    fn retention_records_published_objects() {
+        let mut publisher = crate::track::TrackPublisher::new(9);
+        let retention = RetentionBuffer::new(4);
+        let source = TrackSource::default().with_retention(retention.clone());
+        assert_eq!(source.largest(), None);
+
+        // Created by the publisher but not yet published on the source.
+        let first = publisher.push_frame(true, bytes::Bytes::from_static(b"key"));
+        let (largest, mut stream) = source.subscribe(Filter::LargestObject);
+        assert_eq!(largest, None);
+        assert_eq!(retention.largest(), None);
+        for object in first {
+            source.publish(object);
+        }
+        for object in publisher.push_frame(false, bytes::Bytes::from_static(b"delta")) {
+            source.publish(object);
+        }
+        assert_eq!(drain(&mut stream), vec![(0, 0), (0, 1)]);
+        assert_eq!(source.largest(), Some(Location::new(0, 1)));
+        assert_eq!(
+            retention.range(),
+            Some((Location::new(0, 0), Location::new(0, 1)))
+        );
+    }
+
+    #[test]
+    fn concurrent_publish_and_subscribe() {
+        const GROUPS: u64 = 50;
+        const OBJECTS: u64 = 20;
+
+        let source = Arc::new(TrackSource::default());
+        let publisher = {
+            let source = source.clone();
+            std::thread::spawn(move || {
+                for g in 0..GROUPS {
+                    for o in 0..OBJECTS {
+                        source.publish(object(g, o));
+                    }
+                }
+            })
+        };
+        let subscribers: Vec<_> = (0..8)
+            .map(|i| {
+                let source = source.clone();
+                std::thread::spawn(move || {
+                    let mut subs = Vec::new();
+                    for _ in 0..25 {
+                        let filter = if i % 2 == 0 {
+                            Filter::LargestObject
+                        } else {
+                            Filter::NextGroupStart
+                        };
+                        subs.push((filter.clone(), source.subscribe(filter)));
+                        std::thread::yield_now();
+                    }
+                    subs
+                })
+            })
+            .collect();
+
+        publisher.join().unwrap();
+        for handle in subscribers {
+            for (filter, (largest, mut stream)) in handle.join().unwrap() {
+                let start = filter.start(largest.as_ref());
+                let expected: Vec<_> = (0..GROUPS)
+                    .flat_map(|g| (0..OBJECTS).map(move |o| (g, o)))
+                    .filter(|&(g, o)| !Location::new(g, o).is_before(&start))
+                    .collect();
+                assert_eq!(drain(&mut stream), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn objects_are_forwarded_through_sink() {
+        let source = Arc::new(TrackSource::default());
+        let (_, mut stream) = source.subscribe(Filter::LargestObject);
+        let mut sink = source.sink();
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(std::future::poll_fn(|cx| {
+            let mut sink = Pin::new(&mut sink);
+            for o in 0..3 {
+                assert!(sink.as_mut().poll_ready(cx).is_ready());
+                sink.as_mut().start_send(object(0, o)).unwrap();
+            }
+            sink.as_mut().poll_close(cx)
+        }))
+        .unwrap();
+
+        assert_eq!(drain(&mut stream), vec![(0, 0), (0, 1), (0, 2)]);
+        assert_eq!(source.largest(), Some(Location::new(0, 2)));
+        assert!(Pin::new(&mut sink).start_send(object(0, 3)).is_err());
+    }
+
+    #[test]
+    fn full_subscriber_is_told_it_fell_behind() {
+        let source = TrackSource::default();
+        let (_, mut slow) = source.subscribe(Filter::LargestObject);
+        let (_, mut fast) = source.subscribe(Filter::LargestObject);
+        let capacity = SUBSCRIBER_QUEUE_CAPACITY as u64;
+        for o in 0..capacity - 1 {
+            assert_eq!(source.publish(object(0, o)), 2);
+            fast.rx.try_recv().unwrap().unwrap();
+        }
+        assert_eq!(source.publish(object(0, capacity - 1)), 1);
+        assert_eq!(source.dropped_subscribers(), 1);
+        assert_eq!(source.subscriber_count(), 1);
+
+        for o in 0..capacity - 1 {
+            assert_eq!(slow.rx.try_recv().unwrap().unwrap().metadata.object_id, o);
+        }
+        assert!(matches!(slow.rx.try_recv(), Ok(Err(Error::TooFarBehind))));
+    }
+
+    #[test]
+    fn sink_waits_for_room() {
+        let source = Arc::new(TrackSource::default());
+        let (_, mut stream) = source.subscribe(Filter::LargestObject);
+        let mut sink = source.sink();
+        let capacity = SUBSCRIBER_QUEUE_CAPACITY as u64;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let mut sink = Pin::new(&mut sink);
+            for o in 0..=capacity {
+                std::future::poll_fn(|cx| sink.as_mut().poll_ready(cx))
+                    .await
+                    .unwrap();
+                sink.as_mut().start_send(object(0, o)).unwrap();
+            }
+            let mut flushed = std::future::poll_fn(|cx| sink.as_mut().poll_flush(cx));
+            let pending = std::future::poll_fn(|cx| {
+                Poll::Ready(Pin::new(&mut flushed).poll(cx).is_pending())
+            });
+            assert!(pending.await);
+
+            stream.recv().await.unwrap().unwrap();
+            flushed.await.unwrap();
+        });
+        assert_eq!(source.dropped_subscribers(), 0);
+        assert_eq!(drain(&mut stream).len(), SUBSCRIBER_QUEUE_CAPACITY);
+    }
+}
@@ -0,0 +1,130 @@
+use std::io::{Error as IoError, ErrorKind};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::error::Error;
+
+/// Buffered reader of a data stream, decoding one item at a time.
+///
+/// Only as much of the stream is read as the next item needs, and at most
+/// `max_buffer_size` bytes are buffered while waiting for it to complete.
+pub(crate) struct StreamReader<R> {
+    stream: R,
+    buf: BytesMut,
+    max_buffer_size: usize,
+    /// Whether the stream ended or failed with part of an item buffered.
+    truncated: bool,
+}
+
+impl<R: AsyncRead + Unpin> StreamReader<R> {
+    pub(crate) fn new(stream: R) -> Self {
+        Self {
+            stream,
+            buf: BytesMut::new(),
+            max_buffer_size: usize::MAX,
+            truncated: false,
+        }
+    }
+
+    pub(crate) fn set_max_buffer_size(&mut self, max_buffer_size: usize) {
+        self.max_buffer_size = max_buffer_size;
+    }
+
+    /// Decode the next item, or `None` if the stream ended cleanly before
+    /// it. An item cut short by the end of the stream fails with
+    /// [`UnexpectedEof`](ErrorKind::UnexpectedEof) naming `what`.
+    pub(crate) async fn read<T>(
+        &mut self,
+        what: &'static str,
+        mut decode: impl FnMut(&mut Bytes) -> Result<T, Error>,
+    ) -> Result<Option<T>, Error> {
+        loop {
+            if let Some(item) = try_decode(&mut self.buf, &mut decode)? {
+                return Ok(Some(item));
+            }
+            if !self.fill().await? {
+                if self.buf.is_empty() {
+                    return Ok(None);
+                }
+                return Err(IoError::new(ErrorKind::UnexpectedEof, what).into());
+            }
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut R {
+        &mut self.stream
+    }
+
+    /// Whether the last read failed because the stream ended or failed, e.g.
+    /// on a reset, in the middle of an item.
+    pub(crate) fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Bytes of the next item received so far.
+    pub(crate) fn buffered(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Like [`read`](Self::read), but leaves the item in the buffer for the
+    /// next read.
+    pub(crate) async fn peek<T>(
+        &mut self,
+        what: &'static str,
+        mut decode: impl FnMut(&mut Bytes) -> Result<T, Error>,
+    ) -> Result<Option<T>, Error> {
+        self.read(what, |buf| decode(&mut buf.clone())).await
+    }
+
+    /// Read more of the stream. Returns `false` at its end.
+    async fn fill(&mut self) -> Result<bool, Error> {
+        let room = self.max_buffer_size.saturating_sub(self.buf.len());
+        if room == 0 {
+            return Err(IoError::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "object exceeds the read buffer of {} bytes",
+                    self.max_buffer_size
+                ),
+            )
+            .into());
+        }
+        let mut buf = (&mut self.buf).limit(room);
+        let read = self.stream.read_buf(&mut buf).await;
+        self.truncated = matches!(read, Ok(0) | Err(_)) && !self.buf.is_empty();
+        Ok(read? > 0)
+    }
+}
+
+/// Decode from `buf` if it holds a complete item, leaving it untouched
+/// otherwise.
+///
+/// The attempt reads a shared view of the buffer, so nothing is copied to
+/// retry an item that is still arriving.
+fn try_decode<T>(
+    buf: &mut BytesMut,
+    decode: impl FnOnce(&mut Bytes) -> Result<T, Error>,
+) -> Result<Option<T>, Error> {
+    let received = std::mem::take(buf).freeze();
+    let mut attempt = received.clone();
+    let result = decode(&mut attempt);
+    // The view kept is the only one left, so it converts back without
+    // copying unless a decoded item still shares it; then only the
+    // undecoded rest is copied.
+    *buf = match result {
+        Ok(_) => {
+            drop(received);
+            attempt.into()
+        }
+        Err(_) => {
+            drop(attempt);
+            received.into()
+        }
+    };
+    match result {
+        Ok(item) => Ok(Some(item)),
+        Err(Error::Io(e)) if e.kind() == ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,538 @@
+use std::io::{Error as IoError, ErrorKind};
+
+use bytes::BytesMut;
+use tokio::io::AsyncRead;
+use tokio_util::codec::Decoder;
+
+use crate::{
+    codec::VarInt,
+    data::{SubgroupHeader, SubgroupObject},
+    error::Error,
+    model::ForwardingPreference,
+    stream_reader::StreamReader,
+    track::{AliasWindow, Object, TrackManager},
+};
+
+/// Limits on the data streams a session reads from its peer at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataStreamLimits {
+    max_concurrent_streams: usize,
+    max_buffer_size: usize,
+    alias_window: AliasWindow,
+}
+
+impl Default for DataStreamLimits {
+    /// 256 streams, each buffering up to 4 MiB, and the default
+    /// [`AliasWindow`] for streams overtaking their SUBSCRIBE_OK.
+    fn default() -> Self {
+        Self {
+            max_concurrent_streams: 256,
+            max_buffer_size: 4 << 20,
+            alias_window: AliasWindow::default(),
+        }
+    }
+}
+
+impl DataStreamLimits {
+    /// Incoming streams read at the same time, at least one. Further
+    /// streams are left unaccepted until one of them ends.
+    pub fn with_max_concurrent_streams(mut self, max_concurrent_streams: usize) -> Self {
+        self.max_concurrent_streams = max_concurrent_streams.max(1);
+        self
+    }
+
+    /// Bytes buffered per stream, which bounds the size of an object.
+    pub fn with_max_buffer_size(mut self, max_buffer_size: usize) -> Self {
+        self.max_buffer_size = max_buffer_size;
+        self
+    }
+
+    /// Bounds on the objects held for subgroup streams whose track alias
+    /// is not registered yet.
+    pub fn with_alias_window(mut self, alias_window: AliasWindow) -> Self {
+        self.alias_window = alias_window;
+        self
+    }
+
+    pub fn max_concurrent_streams(&self) -> usize {
+        self.max_concurrent_streams
+    }
+
+    pub fn max_buffer_size(&self) -> usize {
+        self.max_buffer_size
+    }
+
+    pub fn alias_window(&self) -> AliasWindow {
+        self.alias_window
+    }
+}
+
+/// How the subgroup streams read by a session ended, returned by
+/// [`Session::data_stream_stats`](crate::session::Session::data_stream_stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DataStreamStats {
+    /// Streams read to their end.
+    pub completed: u64,
+    /// Streams that ended or were reset in the middle of an object.
+    pub truncated: u64,
+    /// Streams that failed otherwise, e.g. on an object exceeding the read
+    /// buffer or the maximum object payload size, or a protocol violation.
+    pub failed: u64,
+}
+
+impl DataStreamStats {
+    pub(crate) fn record<T>(&mut self, result: &Result<T, Error>) {
+        match result {
+            Ok(_) => self.completed += 1,
+            Err(Error::TruncatedObject { .. }) => self.truncated += 1,
+            Err(_) => self.failed += 1,
+        }
+    }
+}
+
+/// Reads the objects of one subgroup stream.
+///
+/// Objects are returned in the order they were sent, which within a
+/// subgroup is ascending Object ID order; an object whose ID does not
+/// exceed the previous one is a protocol violation. Each stream is meant
+/// to be read by a single task: objects of different subgroups may then be
+/// delivered interleaved, but never out of order within a subgroup.
+pub struct SubgroupReader<R> {
+    reader: StreamReader<R>,
+    header: Option<SubgroupHeader>,
+    first_object_id: Option<u64>,
+    last_object_id: Option<u64>,
+    alias_window: AliasWindow,
+    max_object_payload_size: usize,
+}
+
+impl<R: AsyncRead + Unpin> SubgroupReader<R> {
+    pub fn new(stream: R) -> Self {
+        Self::from_reader(StreamReader::new(stream))
+    }
+
+    pub(crate) fn from_reader(reader: StreamReader<R>) -> Self {
+        Self {
+            reader,
+            header: None,
+            first_object_id: None,
+            last_object_id: None,
+            alias_window: AliasWindow::default(),
+            max_object_payload_size: usize::MAX,
+        }
+    }
+
+    /// Bound the bytes buffered while waiting for a complete object. An
+    /// object that does not fit fails with
+    /// [`InvalidData`](ErrorKind::InvalidData) instead of growing the
+    /// buffer.
+    pub fn with_max_buffer_size(mut self, max_buffer_size: usize) -> Self {
+        self.reader.set_max_buffer_size(max_buffer_size);
+        self
+    }
+
+    /// Bound the payload size of the objects read. A larger object fails
+    /// with [`Error::ObjectTooLarge`] as soon as its length is read, leaving
+    /// the stream to be reset through [`get_mut`](Self::get_mut).
+    pub fn with_max_object_payload_size(mut self, max_object_payload_size: usize) -> Self {
+        self.max_object_payload_size = max_object_payload_size;
+        self
+    }
+
+    /// Bound the objects [`deliver`](Self::deliver) holds while the track
+    /// alias of the stream is not registered.
+    pub fn with_alias_window(mut self, alias_window: AliasWindow) -> Self {
+        self.alias_window = alias_window;
+        self
+    }
+
+    /// The stream being read.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.reader.get_mut()
+    }
+
+    /// The SUBGROUP_HEADER opening the stream, read if not done yet.
+    pub async fn header(&mut self) -> Result<&SubgroupHeader, Error> {
+        if self.header.is_none() {
+            let header = self
+                .reader
+                .read("subgroup header", SubgroupHeader::decode)
+                .await?
+                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "subgroup header"))?;
+            self.header = Some(header);
+        }
+        Ok(self.header.as_ref().expect("header read above"))
+    }
+
+    /// The next object of the subgroup, or `None` once the stream ended.
+    ///
+    /// An object cut short by the end of the stream, or by a transport
+    /// error such as a reset, is discarded and fails with
+    /// [`Error::TruncatedObject`]: a partial payload is never returned.
+    pub async fn next(&mut self) -> Result<Option<Object>, Error> {
+        let extensions_present = self.header().await?.extensions_present;
+        let max_payload_size = self.max_object_payload_size;
+        let read = self
+            .reader
+            .read("subgroup object", |buf| {
+                SubgroupObject::decode_bounded(buf, extensions_present, max_payload_size)
+            })
+            .await;
+        if read.is_err() && self.reader.is_truncated() {
+            return Err(self.truncated());
+        }
+        let Some(object) = read? else {
+            return Ok(None);
+        };
+
+        if let Some(last) = self.last_object_id.filter(|last| object.object_id <= *last) {
+            return Err(Error::ProtocolViolation {
+                reason: format!(
+                    "object {} after object {} in a subgroup",
+                    object.object_id, last
+                ),
+                context: None,
+            });
+        }
+        self.last_object_id = Some(object.object_id);
+        let first_object_id = *self.first_object_id.get_or_insert(object.object_id);
+        let header = self.header.as_ref().expect("header read above");
+        Object::from_subgroup(header, first_object_id, object).map(Some)
+    }
+
+    /// [`Error::TruncatedObject`] for the object being read, whose Object ID
+    /// leads its encoding.
+    fn truncated(&self) -> Error {
+        let header = self.header.as_ref().expect("header read before objects");
+        let mut partial = BytesMut::from(self.reader.buffered());
+        Error::TruncatedObject {
+            group_id: header.group_id,
+            object_id: VarInt.decode(&mut partial).ok().flatten(),
+        }
+    }
+
+    /// Read the stream to its end, delivering each object to the
+    /// subscribers of its track before reading the next one. Returns the
+    /// number of objects read.
+    ///
+    /// If the stream is cut short in the middle of an object, or an object
+    /// exceeds the [maximum payload size](Self::with_max_object_payload_size),
+    /// the subscribers are sent the [`Error::TruncatedObject`] or
+    /// [`Error::ObjectTooLarge`] it fails with.
+    ///
+    /// The stream may arrive before the SUBSCRIBE_OK establishing its track
+    /// alias. Its objects are then held within the bounds of the
+    /// [alias window](Self::with_alias_window) and delivered once the alias
+    /// is registered.
+    pub async fn deliver(&mut self, tracks: &TrackManager) -> Result<u64, Error> {
+        let mut count = 0;
+        while self.deliver_one(tracks, false).await? {
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Like [`deliver`](Self::deliver), but waits for room in the queues of
+    /// the subscribers as [`TrackManager::deliver_async`] does, so that a
+    /// slow subscriber slows the stream down rather than missing objects.
+    pub async fn deliver_async(&mut self, tracks: &TrackManager) -> Result<u64, Error> {
+        let mut count = 0;
+        while self.deliver_one(tracks, true).await? {
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Like [`deliver`](Self::deliver) for the next object only, e.g. to
+    /// drain the subscribers' queues in between. Returns `false` once the
+    /// stream ended.
+    pub async fn deliver_next(&mut self, tracks: &TrackManager) -> Result<bool, Error> {
+        self.deliver_one(tracks, false).await
+    }
+
+    async fn deliver_one(&mut self, tracks: &TrackManager, wait: bool) -> Result<bool, Error> {
+        match self.next().await {
+            Ok(Some(object)) => {
+                let preference = ForwardingPreference::Subgroup;
+                if wait {
+                    tracks
+                        .deliver_or_hold_async(object, preference, &self.alias_window)
+                        .await;
+                } else {
+                    tracks.deliver_or_hold(object, preference, &self.alias_window);
+                }
+                Ok(true)
+            }
+            Ok(None) => Ok(false),
+            Err(Error::TruncatedObject {
+                group_id,
+                object_id,
+            }) => {
+                let alias = self.header.as_ref().expect("header read above").track_alias;
+                let truncated = || Error::TruncatedObject {
+                    group_id,
+                    object_id,
+                };
+                tracks.deliver_error(alias, truncated());
+                Err(truncated())
+            }
+            Err(Error::ObjectTooLarge { size, max }) => {
+                let alias = self.header.as_ref().expect("header read above").track_alias;
+                tracks.deliver_error(alias, Error::ObjectTooLarge { size, max });
+                Err(Error::ObjectTooLarge { size, max })
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::{Bytes, BytesMut};
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+    use crate::message::SubscribeOk;
+    use crate::track::TrackPublisher;
+
+    fn encode(header: &SubgroupHeader, objects: &[Object]) -> BytesMut {
+        let mut buf = BytesMut::new();
+        header.encode(&mut buf).unwrap();
+        for object in objects {
+            object
+                .to_subgroup_object()
+                .encode(&mut buf, header.extensions_present)
+                .unwrap();
+        }
+        buf
+    }
+
+    #[test]
+    fn at_least_one_stream_is_read_at_once() {
+        let limits = DataStreamLimits::default().with_max_concurrent_streams(0);
+        assert_eq!(limits.max_concurrent_streams(), 1);
+    }
+
+    #[test]
+    fn concurrent_subgroups_deliver_in_object_order() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let tracks = Arc::new(TrackManager::default());
+            tracks.handle_max_request_id(1).unwrap();
+            let (request_id, mut objects) = tracks.subscribe_track("video".into()).unwrap();
+            tracks
+                .handle_subscribe_ok(&SubscribeOk {
+                    request_id,
+                    track_alias: 1,
+                    expires: 0,
+                    group_order: 1,
+                    content_exists: false,
+                    largest_location: None,
+                    parameters: Vec::new(),
+                })
+                .unwrap();
+
+            // Object IDs are shared by the group, so each subgroup gets
+            // every third one.
+            let mut publisher = TrackPublisher::new(1);
+            let group = publisher.begin_group();
+            let subgroups: Vec<_> = (0..3).map(|id| group.subgroup(id)).collect();
+            let mut sent = vec![Vec::new(); 3];
+            for _ in 0..5 {
+                for (subgroup, sent) in subgroups.iter().zip(&mut sent) {
+                    sent.push(subgroup.object(Bytes::from_static(b"0123456789")));
+                }
+            }
+
+            let mut readers = Vec::new();
+            for (subgroup, sent) in subgroups.iter().zip(&sent) {
+                // A small pipe and small writes interleave the readers.
+                let (mut tx, rx) = tokio::io::duplex(8);
+                let bytes = encode(&subgroup.header(false), sent);
+                tokio::spawn(async move {
+                    for chunk in bytes.chunks(5) {
+                        tx.write_all(chunk).await.unwrap();
+                    }
+                });
+                let tracks = tracks.clone();
+                readers.push(tokio::spawn(async move {
+                    SubgroupReader::new(rx).deliver(&tracks).await.unwrap()
+                }));
+            }
+            for reader in readers {
+                assert_eq!(reader.await.unwrap(), 5);
+            }
+
+            let mut received = vec![Vec::new(); 3];
+            for _ in 0..15 {
+                let object = objects.recv().await.unwrap().unwrap();
+                received[object.metadata.subgroup_id as usize].push(object);
+            }
+            assert_eq!(received, sent);
+        });
+    }
+
+    #[test]
+    fn stream_ahead_of_subscribe_ok_is_held() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let tracks = TrackManager::default();
+            tracks.handle_max_request_id(1).unwrap();
+            let (request_id, mut objects) = tracks.subscribe_track("video".into()).unwrap();
+
+            let mut publisher = TrackPublisher::new(1);
+            let subgroup = publisher.begin_group().subgroup(0);
+            let sent: Vec<_> = (0..3)
+                .map(|_| subgroup.object(Bytes::from_static(b"0123456789")))
+                .collect();
+            let bytes = encode(&subgroup.header(false), &sent);
+
+            // Room for two of the three objects.
+            let window = AliasWindow::default().with_max_bytes(25);
+            let mut reader = SubgroupReader::new(&bytes[..]).with_alias_window(window);
+            assert_eq!(reader.deliver(&tracks).await.unwrap(), 3);
+            assert_eq!(tracks.held_objects(), 2);
+
+            tracks
+                .handle_subscribe_ok(&SubscribeOk {
+                    request_id,
+                    track_alias: 1,
+                    expires: 0,
+                    group_order: 1,
+                    content_exists: false,
+                    largest_location: None,
+                    parameters: Vec::new(),
+                })
+                .unwrap();
+            assert_eq!(tracks.held_objects(), 0);
+            assert_eq!(objects.recv().await.unwrap().unwrap(), sent[0]);
+            assert_eq!(objects.recv().await.unwrap().unwrap(), sent[1]);
+        });
+    }
+
+    /// Stream reset by the peer once its data was read.
+    struct Reset;
+
+    impl AsyncRead for Reset {
+        fn poll_read(
+            self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            _buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Err(ErrorKind::ConnectionReset.into()))
+        }
+    }
+
+    #[test]
+    fn object_cut_short_is_reported_not_delivered() {
+        use tokio::io::AsyncReadExt;
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let tracks = TrackManager::default();
+            tracks.handle_max_request_id(1).unwrap();
+            let (request_id, mut objects) = tracks.subscribe_track("video".into()).unwrap();
+            tracks
+                .handle_subscribe_ok(&SubscribeOk {
+                    request_id,
+                    track_alias: 1,
+                    expires: 0,
+                    group_order: 1,
+                    content_exists: false,
+                    largest_location: None,
+                    parameters: Vec::new(),
+                })
+                .unwrap();
+
+            let mut publisher = TrackPublisher::new(1);
+            let subgroup = publisher.begin_group().subgroup(0);
+            let sent: Vec<_> = (0..2)
+                .map(|_| subgroup.object(Bytes::from_static(b"0123456789")))
+                .collect();
+            let bytes = encode(&subgroup.header(false), &sent);
+            let cut = &bytes[..bytes.len() - 3];
+
+            let truncated = SubgroupReader::new(cut).deliver(&tracks).await;
+            assert!(matches!(
+                truncated,
+                Err(Error::TruncatedObject {
+                    group_id: 0,
+                    object_id: Some(1)
+                })
+            ));
+            assert_eq!(objects.recv().await.unwrap().unwrap(), sent[0]);
+            assert!(matches!(
+                objects.recv().await.unwrap(),
+                Err(Error::TruncatedObject { .. })
+            ));
+            assert!(objects.rx.try_recv().is_err());
+
+            // A reset in the middle of an object truncates it as well.
+            let mut reader = SubgroupReader::new(cut.chain(Reset));
+            assert_eq!(reader.next().await.unwrap().unwrap(), sent[0]);
+            assert!(matches!(
+                reader.next().await,
+                Err(Error::TruncatedObject { .. })
+            ));
+
+            // Between objects, nothing is lost but the rest of the stream.
+            let first = encode(&subgroup.header(false), &sent[..1]);
+            let mut reader = SubgroupReader::new((&first[..]).chain(Reset));
+            assert_eq!(reader.next().await.unwrap().unwrap(), sent[0]);
+            match reader.next().await {
+                Err(Error::Io(e)) => assert_eq!(e.kind(), ErrorKind::ConnectionReset),
+                r => panic!("unexpected result: {r:?}"),
+            }
+        });
+    }
+
+    #[test]
+    fn object_larger_than_buffer_is_rejected() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let mut publisher = TrackPublisher::new(1);
+            let subgroup = publisher.begin_group().subgroup(0);
+            let small = subgroup.object(Bytes::from_static(b"a"));
+            let large = subgroup.object(Bytes::from(vec![0; 64]));
+            let bytes = encode(&subgroup.header(false), &[small, large]);
+
+            let mut reader = SubgroupReader::new(&bytes[..]).with_max_buffer_size(32);
+            assert_eq!(reader.next().await.unwrap().unwrap().metadata.object_id, 0);
+            match reader.next().await {
+                Err(Error::Io(e)) => assert_eq!(e.kind(), ErrorKind::InvalidData),
+                r => panic!("unexpected result: {r:?}"),
+            }
+        });
+    }
+
+    #[test]
+    fn descending_object_id_is_protocol_violation() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let mut publisher = TrackPublisher::new(1);
+            let subgroup = publisher.begin_group().subgroup(0);
+            let first = subgroup.object(Bytes::from_static(b"a"));
+            let second = subgroup.object(Bytes::from_static(b"b"));
+            let bytes = encode(&subgroup.header(false), &[second, first]);
+
+            let mut reader = SubgroupReader::new(&bytes[..]);
+            assert_eq!(reader.next().await.unwrap().unwrap().metadata.object_id, 1);
+            assert!(matches!(
+                reader.next().await,
+                Err(Error::ProtocolViolation { .. })
+            ));
+        });
+    }
+}
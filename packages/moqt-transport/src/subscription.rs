@@ -0,0 +1,190 @@
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use tokio::sync::watch;
+
+use crate::{
+    control::ControlQueue,
+    error::Error,
+    message::{ControlMessage, Subscribe, SubscribeOk, SubscribeUpdate, Unsubscribe},
+    model::{Filter, Location},
+    track::{Object, ObjectStream},
+};
+
+struct UpdateState {
+    filter: Filter,
+    end_group: Option<u64>,
+    subscriber_priority: u8,
+    group_order: u8,
+    forward: bool,
+}
+
+/// An active subscription returned by
+/// [`Session::subscribe`](crate::session::Session::subscribe).
+///
+/// Every modification is sent to the publisher as a SUBSCRIBE_UPDATE
+/// carrying the full current state of the subscription. Modifications are
+/// refused until the SUBSCRIBE_OK is received and applied through
+/// [`TrackManager::handle_subscribe_ok`](crate::track::TrackManager::handle_subscribe_ok),
+/// as the start of relative filters is not known before.
+pub struct SubscriptionHandle {
+    request_id: u64,
+    control: Arc<ControlQueue>,
+    state: Mutex<UpdateState>,
+    established: watch::Receiver<Option<SubscribeOk>>,
+    objects: ObjectStream,
+}
+
+impl SubscriptionHandle {
+    pub(crate) fn new(
+        subscribe: &Subscribe,
+        control: Arc<ControlQueue>,
+        established: watch::Receiver<Option<SubscribeOk>>,
+        objects: ObjectStream,
+    ) -> Result<Self, Error> {
+        let filter = subscribe.filter().clone();
+        Ok(Self {
+            request_id: subscribe.request_id,
+            control,
+            state: Mutex::new(UpdateState {
+                end_group: filter.end_group(),
+                filter,
+                subscriber_priority: subscribe.subscriber_priority,
+                group_order: subscribe.group_order,
+                forward: subscribe.forward != 0,
+            }),
+            established,
+            objects,
+        })
+    }
+
+    pub fn request_id(&self) -> u64 {
+        self.request_id
+    }
+
+    /// Whether the SUBSCRIBE_OK of this subscription was received. The
+    /// largest location it carries resolves the start of relative filters,
+    /// which updates must not move backwards.
+    pub fn is_established(&self) -> bool {
+        self.established.borrow().is_some()
+    }
+
+    /// Group order requested in the SUBSCRIBE until the subscription is
+    /// [established](Self::is_established), then the one the publisher
+    /// delivers in.
+    pub fn group_order(&self) -> u8 {
+        match &*self.established.borrow() {
+            Some(ok) => ok.group_order,
+            None => self.state.lock().unwrap().group_order,
+        }
+    }
+
+    /// Receive the next object of the subscription. Objects the publisher
+    /// reports a status for, such as one that does not exist, are received
+    /// with that [`status`](Object::status) and no payload.
+    pub async fn recv(&mut self) -> Option<Result<Object, Error>> {
+        self.objects.recv().await
+    }
+
+    /// Receive the next object if one is queued, without waiting.
+    pub fn try_recv(&mut self) -> Option<Result<Object, Error>> {
+        self.objects.rx.try_recv().ok()
+    }
+
+    pub(crate) fn poll_recv(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Object, Error>>> {
+        self.objects.rx.poll_recv(cx)
+    }
+
+    /// End the subscription by sending UNSUBSCRIBE. It stays registered
+    /// with the session's track manager, which
+    /// [`Session::unsubscribe`](crate::session::Session::unsubscribe) ends
+    /// as well.
+    pub async fn unsubscribe(self) -> Result<(), Error> {
+        self.control
+            .send(ControlMessage::Unsubscribe(Unsubscribe {
+                request_id: self.request_id,
+            }))
+            .await
+    }
+
+    /// Stop forwarding of objects without ending the subscription.
+    pub async fn pause(&self) -> Result<(), Error> {
+        self.update(|s, _| {
+            s.forward = false;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Resume forwarding after [`pause`](Self::pause).
+    pub async fn resume(&self) -> Result<(), Error> {
+        self.update(|s, _| {
+            s.forward = true;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn set_priority(&self, subscriber_priority: u8) -> Result<(), Error> {
+        self.update(|s, _| {
+            s.subscriber_priority = subscriber_priority;
+            Ok(())
+        })
+        .await
+    }
+
+    /// End the subscription after `end_group`, inclusive. Subscriptions can
+    /// only be narrowed, so the end group must not increase and must not be
+    /// before the start group.
+    pub async fn narrow(&self, end_group: u64) -> Result<(), Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        self.update(|s, largest| {
+            if s.end_group.is_some_and(|end| end_group > end) {
+                return Err(IoError::new(ErrorKind::InvalidInput, "end group increased").into());
+            }
+            if end_group < s.filter.start(largest).group {
+                return Err(IoError::new(ErrorKind::InvalidInput, "end group before start").into());
+            }
+            s.end_group = Some(end_group);
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update(
+        &self,
+        f: impl FnOnce(&mut UpdateState, Option<&Location>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        let Some(largest) = self
+            .established
+            .borrow()
+            .as_ref()
+            .map(|ok| ok.largest_location.clone())
+        else {
+            return Err(IoError::new(ErrorKind::InvalidInput, "SUBSCRIBE_OK not received").into());
+        };
+        let update = {
+            let mut state = self.state.lock().unwrap();
+            f(&mut state, largest.as_ref())?;
+            SubscribeUpdate {
+                request_id: self.request_id,
+                start_location: state.filter.start(largest.as_ref()),
+                // SUBSCRIBE_UPDATE carries the end group plus one, zero
+                // meaning open-ended.
+                end_group: state.end_group.map_or(0, |end| end + 1),
+                subscriber_priority: state.subscriber_priority,
+                forward: state.forward as u8,
+                parameters: Vec::new(),
+            }
+        };
+        self.control
+            .send(ControlMessage::SubscribeUpdate(update))
+            .await
+    }
+}
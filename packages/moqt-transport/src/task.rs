@@ -0,0 +1,154 @@
+use std::future::Future;
+use std::sync::Mutex;
+
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+/// Background tasks owned by a session: the control loop, datagram loop and
+/// per-stream readers.
+///
+/// [`SessionTasks::shutdown`] cancels the tasks and waits for them to
+/// return. Dropping the set aborts any task still running, so no task
+/// outlives its session.
+#[derive(Default)]
+pub struct SessionTasks {
+    cancel: CancellationToken,
+    tasks: Mutex<Option<JoinSet<()>>>,
+}
+
+impl SessionTasks {
+    /// Spawn `task` on the current runtime. The task is dropped at its next
+    /// await point once the set is shut down. Returns `false` without
+    /// spawning if the set has already been shut down.
+    pub fn spawn<F>(&self, task: F) -> bool
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        if self.cancel.is_cancelled() {
+            return false;
+        }
+        let cancel = self.cancel.clone();
+        let mut tasks = self.tasks.lock().unwrap();
+        let tasks = tasks.get_or_insert_with(JoinSet::new);
+        // Reap tasks that already finished so the set does not grow with
+        // every short-lived stream reader.
+        while tasks.try_join_next().is_some() {}
+        tasks.spawn(async move {
+            cancel.run_until_cancelled(task).await;
+        });
+        true
+    }
+
+    /// Number of tasks that have not been reaped yet.
+    pub fn len(&self) -> usize {
+        self.tasks.lock().unwrap().as_ref().map_or(0, JoinSet::len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Token cancelled on shutdown, for tasks that need to clean up before
+    /// returning instead of being dropped.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// Cancel every task and wait until all of them have returned.
+    pub async fn shutdown(&self) {
+        self.cancel.cancel();
+        let tasks = self.tasks.lock().unwrap().take();
+        if let Some(mut tasks) = tasks {
+            while let Some(result) = tasks.join_next().await {
+                if let Err(e) = result
+                    && e.is_panic()
+                {
+                    std::panic::resume_unwind(e.into_panic());
+                }
+            }
+        }
+    }
+}
+
+impl Drop for SessionTasks {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Increments a counter when dropped, i.e. when the owning task ends.
+    struct Guard(Arc<AtomicUsize>);
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn shutdown_waits_for_all_tasks() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let tasks = SessionTasks::default();
+            let ended = Arc::new(AtomicUsize::new(0));
+            for _ in 0..3 {
+                let guard = Guard(ended.clone());
+                assert!(tasks.spawn(async move {
+                    let _guard = guard;
+                    std::future::pending::<()>().await;
+                }));
+            }
+            tokio::task::yield_now().await;
+            assert_eq!(tasks.len(), 3);
+
+            tasks.shutdown().await;
+            assert_eq!(ended.load(Ordering::SeqCst), 3);
+            assert!(tasks.is_empty());
+            assert!(!tasks.spawn(async {}));
+        });
+    }
+
+    #[test]
+    fn drop_aborts_tasks() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let ended = Arc::new(AtomicUsize::new(0));
+        rt.block_on(async {
+            let tasks = SessionTasks::default();
+            let guard = Guard(ended.clone());
+            tasks.spawn(async move {
+                let _guard = guard;
+                std::future::pending::<()>().await;
+            });
+            tokio::task::yield_now().await;
+            drop(tasks);
+            tokio::task::yield_now().await;
+        });
+        assert_eq!(ended.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn finished_tasks_are_reaped() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let tasks = SessionTasks::default();
+            for _ in 0..10 {
+                tasks.spawn(async {});
+                tokio::task::yield_now().await;
+            }
+            assert!(tasks.len() <= 1);
+            tasks.shutdown().await;
+        });
+    }
+}
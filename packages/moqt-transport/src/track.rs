@@ -1,25 +1,80 @@
 use bytes::Bytes;
 use futures_core::Stream;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::ops::RangeInclusive;
 use std::pin::Pin;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{Arc, RwLock};
 use std::task::{Context, Poll};
-use tokio::sync::mpsc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
 
+use crate::data::{FetchObject, ObjectDatagram, SubgroupHeader, SubgroupId, SubgroupObject};
+use crate::dedup::DedupStream;
 use crate::error::Error;
+use crate::group::GroupHandle;
+use crate::live::LiveStream;
 use crate::message::SubscribeOk;
+use crate::model::{Filter, ForwardingPreference, Location, ObjectStatus};
+use crate::observe::{Observer, Observers};
+use crate::publish::DeliveryParams;
+use crate::reorder::{ReorderLimits, ReorderStream};
+use crate::repair::{GapDetector, RepairPolicy, RepairRange};
+use crate::request::SubscribeRequest;
+use crate::retention::{MemoryBudget, RetentionBuffer, RetentionPolicy};
+use crate::scheduler::{
+    DEFAULT_SUBSCRIBER_PRIORITY, GROUP_ORDER_ASCENDING, GROUP_ORDER_PUBLISHER, Priority,
+    check_group_order,
+};
+use crate::sync::{Arc, AtomicU64, AtomicUsize, Mutex, Ordering, RwLock};
 
 pub type FullTrackName = String;
+/// A track namespace, one string per namespace field.
+pub type TrackNamespace = Vec<String>;
 pub type TrackAlias = u64;
 
+/// Full track name of track `name` in `namespace`: the namespace fields
+/// and the track name joined with `/`.
+pub fn full_track_name(namespace: &[String], name: &str) -> FullTrackName {
+    let mut full = namespace.join("/");
+    if !full.is_empty() {
+        full.push('/');
+    }
+    full.push_str(name);
+    full
+}
+
+/// Tracks, aliases and subscriptions of a session, and delivery of the
+/// objects received to the subscribers.
+///
+/// # Locking
+///
+/// State is kept behind `std::sync` locks that are only taken within
+/// synchronous methods, so no guard can be held across an `.await`;
+/// `clippy::await_holding_lock` flags any attempt. The lookup maps are
+/// locked just long enough to clone the shared [`TrackEntry`]. The state of
+/// a track is locked to check an object against it and to take the senders
+/// of its subscribers, and objects are queued on those senders once the
+/// lock is released. Waiting for room in a subscriber's queue, as
+/// [`deliver_async`](Self::deliver_async) does, thus never blocks other
+/// users of the track.
+///
+/// Locks are taken in this order: the held objects, then the lookup maps,
+/// then the state of a track. No two lookup maps or track states are held
+/// at once.
 pub struct TrackManager {
-    #[allow(dead_code)]
-    tracks: RwLock<HashMap<FullTrackName, Arc<std::sync::Mutex<TrackState>>>>,
-    aliases: RwLock<HashMap<TrackAlias, FullTrackName>>,
-    requests: RwLock<HashMap<u64, FullTrackName>>,
+    tracks: RwLock<HashMap<FullTrackName, Arc<TrackEntry>>>,
+    aliases: RwLock<HashMap<TrackAlias, Arc<TrackEntry>>>,
+    requests: RwLock<HashMap<u64, Arc<TrackEntry>>>,
     request_counter: AtomicU64,
     max_request_id: AtomicU64,
+    /// Objects received for aliases not registered yet.
+    held: Mutex<Held>,
+    /// Number of objects in `held`, read without its lock so that objects
+    /// of registered aliases skip it while nothing is held.
+    held_objects: AtomicUsize,
+    /// Gaps found in subscriptions repairing their datagrams, with the
+    /// Request ID of the subscription.
+    repairs: Mutex<Vec<(u64, RepairRange)>>,
+    observers: Observers,
 }
 
 impl Default for TrackManager {
@@ -30,109 +85,782 @@ impl Default for TrackManager {
             requests: RwLock::new(HashMap::new()),
             request_counter: AtomicU64::new(0),
             max_request_id: AtomicU64::new(0),
+            held: Mutex::new(Held::default()),
+            held_objects: AtomicUsize::new(0),
+            repairs: Mutex::new(Vec::new()),
+            observers: Observers::default(),
         }
     }
 }
 
-#[allow(dead_code)]
-struct TrackState {
+struct HeldObject {
+    expires: Instant,
+    object: Object,
+    preference: ForwardingPreference,
+}
+
+/// Held objects of each alias, oldest first, with their totals.
+#[derive(Default)]
+struct Held {
+    by_alias: HashMap<TrackAlias, VecDeque<HeldObject>>,
+    objects: usize,
+    bytes: usize,
+}
+
+impl Held {
+    fn expire(&mut self, now: Instant) {
+        let (objects, bytes) = (&mut self.objects, &mut self.bytes);
+        self.by_alias.retain(|_, queue| {
+            queue.retain(|h| {
+                let live = h.expires > now;
+                if !live {
+                    *objects -= 1;
+                    *bytes -= h.object.payload.len();
+                }
+                live
+            });
+            !queue.is_empty()
+        });
+    }
+
+    fn take(&mut self, alias: TrackAlias) -> VecDeque<HeldObject> {
+        let queue = self.by_alias.remove(&alias).unwrap_or_default();
+        self.objects -= queue.len();
+        self.bytes -= queue.iter().map(|h| h.object.payload.len()).sum::<usize>();
+        queue
+    }
+}
+
+/// Bounds on the objects a [`TrackManager`] holds for track aliases not
+/// registered yet.
+///
+/// Data can overtake the SUBSCRIBE_OK establishing its alias, as QUIC does
+/// not order streams. The draft lets the receiver buffer such data briefly
+/// rather than abandon it.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-subgroup-header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AliasWindow {
+    max_age: Duration,
+    max_objects: usize,
+    max_bytes: usize,
+}
+
+impl Default for AliasWindow {
+    /// Objects are held for up to 500 ms, at most 1024 objects and 1 MiB
+    /// of payload at once.
+    fn default() -> Self {
+        Self {
+            max_age: Duration::from_millis(500),
+            max_objects: 1024,
+            max_bytes: 1 << 20,
+        }
+    }
+}
+
+impl AliasWindow {
+    /// How long an object is held before it is discarded.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Objects held at once across all aliases of the [`TrackManager`],
+    /// whichever window they were held with.
+    pub fn with_max_objects(mut self, max_objects: usize) -> Self {
+        self.max_objects = max_objects;
+        self
+    }
+
+    /// Payload bytes held at once across all aliases of the
+    /// [`TrackManager`], whichever window they were held with.
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn max_age(&self) -> Duration {
+        self.max_age
+    }
+
+    pub fn max_objects(&self) -> usize {
+        self.max_objects
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+}
+
+/// What became of an object passed to [`TrackManager::deliver_or_hold`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Queued on this many subscriptions.
+    Delivered(usize),
+    /// Held until its alias is registered.
+    Held,
+    /// Dropped since its alias is unknown and no more objects can be held.
+    Dropped,
+}
+
+/// A track known to the [`TrackManager`], shared between the name, alias
+/// and request lookups so resolving an alias per object does not allocate.
+pub struct TrackEntry {
     name: FullTrackName,
+    state: Mutex<TrackState>,
+}
+
+struct TrackState {
     alias: Option<TrackAlias>,
-    subscribers: Vec<mpsc::Sender<Result<Object, Error>>>,
+    forwarding: Option<ForwardingPreference>,
+    subscribers: Vec<Subscriber>,
+}
+
+struct Subscriber {
+    request_id: u64,
+    filter: Option<Filter>,
+    group_order: u8,
+    state: SubscriptionState,
+    delivered: Arc<AtomicU64>,
+    tx: mpsc::Sender<Result<Object, Error>>,
+    /// The SUBSCRIBE_OK once received, for the subscription's handle.
+    established: watch::Sender<Option<SubscribeOk>>,
+    /// Gap detection of a subscription repairing its datagrams.
+    gaps: Option<GapDetector>,
+}
+
+/// An object checked against its track, or the error to deliver instead,
+/// with the subscribers to queue it on. Taken from the state of the track
+/// so that queueing happens without holding its lock.
+struct Route {
+    entry: Arc<TrackEntry>,
+    item: Result<Object, Error>,
+    targets: Vec<Target>,
+}
+
+struct Target {
+    request_id: u64,
+    tx: mpsc::Sender<Result<Object, Error>>,
+    delivered: Arc<AtomicU64>,
+}
+
+impl Route {
+    /// A copy of the item for one subscriber.
+    fn item(&self) -> Result<Object, Error> {
+        match &self.item {
+            Ok(object) => Ok(object.clone()),
+            Err(e) => Err(e.duplicate()),
+        }
+    }
+}
+
+/// Whether the publisher accepted a subscription yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionState {
+    /// Waiting for SUBSCRIBE_OK.
+    Pending,
+    Established,
+}
+
+/// Snapshot of a subscription, returned by
+/// [`TrackManager::subscriptions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriptionInfo {
+    pub request_id: u64,
+    pub track: FullTrackName,
+    pub alias: Option<TrackAlias>,
+    /// `None` if the subscription was not created with a filter, see
+    /// [`TrackManager::subscribe_track_with_filter`].
+    pub filter: Option<Filter>,
+    /// Group order requested in the SUBSCRIBE, 0x0 leaving it to the
+    /// publisher, until established; the order objects are delivered in
+    /// afterwards.
+    pub group_order: u8,
+    pub state: SubscriptionState,
+    /// Objects queued on the subscription's stream so far.
+    pub delivered: u64,
+}
+
+/// Snapshot of a track, returned by [`TrackManager::tracks`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackInfo {
+    pub name: FullTrackName,
+    pub alias: Option<TrackAlias>,
+    /// Forwarding preference of the first object received, if any.
+    pub forwarding: Option<ForwardingPreference>,
+    pub subscribers: usize,
+}
+
+impl TrackEntry {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn alias(&self) -> Option<TrackAlias> {
+        self.state.lock().unwrap().alias
+    }
 }
 
 impl TrackManager {
-    /// Insert a track if it does not already exist and return a handle to its
-    /// state. Existing tracks are returned as-is.
-    pub(crate) fn add_track(&self, name: FullTrackName) {
+    /// Notify `observers` of every object queued on subscriptions.
+    pub fn with_observers(mut self, observers: Observers) -> Self {
+        self.observers = observers;
+        self
+    }
+
+    /// Insert a track if it does not already exist and return its entry.
+    /// Existing tracks are returned as-is.
+    pub(crate) fn add_track(&self, name: FullTrackName) -> Arc<TrackEntry> {
         let mut tracks = self.tracks.write().unwrap();
-        tracks.entry(name.clone()).or_insert_with(|| {
-            Arc::new(std::sync::Mutex::new(TrackState {
-                name,
-                alias: None,
-                subscribers: Vec::new(),
-            }))
-        });
+        tracks
+            .entry(name.clone())
+            .or_insert_with(|| {
+                Arc::new(TrackEntry {
+                    name,
+                    state: Mutex::new(TrackState {
+                        alias: None,
+                        forwarding: None,
+                        subscribers: Vec::new(),
+                    }),
+                })
+            })
+            .clone()
     }
 
     pub fn assign_alias(&self, alias: TrackAlias, name: FullTrackName) -> Result<(), Error> {
-        let mut aliases = self.aliases.write().unwrap();
-        if aliases.contains_key(&alias) {
-            return Err(Error::DuplicateTrackAlias(alias));
-        }
-        aliases.insert(alias, name);
-        Ok(())
+        let entry = self.add_track(name);
+        self.set_track_alias(entry, alias)
     }
 
     /// Generate a new unique request identifier. Returns an error if the peer
     /// has not allowed opening additional requests.
     pub fn new_request_id(&self) -> Result<u64, Error> {
-        let next = self.request_counter.load(Ordering::SeqCst);
-        let max = self.max_request_id.load(Ordering::SeqCst);
-        if next >= max {
-            return Err(Error::TooManyRequests);
-        }
-        Ok(self.request_counter.fetch_add(1, Ordering::SeqCst))
+        self.request_counter
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |next| {
+                (next < self.max_request_id.load(Ordering::SeqCst)).then_some(next + 1)
+            })
+            .map_err(|_| Error::TooManyRequests)
     }
 
     /// Associate an alias with an existing track. Returns an error on
     /// duplication.
-    pub(crate) fn set_track_alias(
-        &self,
-        name: &FullTrackName,
-        alias: TrackAlias,
-    ) -> Result<(), Error> {
-        self.assign_alias(alias, name.clone())?;
-        if let Some(entry) = self.tracks.write().unwrap().get_mut(name) {
-            let mut state = entry.lock().unwrap();
-            state.alias = Some(alias);
+    fn set_track_alias(&self, entry: Arc<TrackEntry>, alias: TrackAlias) -> Result<(), Error> {
+        {
+            let mut aliases = self.aliases.write().unwrap();
+            if aliases.contains_key(&alias) {
+                return Err(Error::DuplicateTrackAlias(alias));
+            }
+            entry.state.lock().unwrap().alias = Some(alias);
+            aliases.insert(alias, entry);
         }
+        self.release_held(alias);
         Ok(())
     }
 
-    pub fn resolve_alias(&self, alias: TrackAlias) -> Option<FullTrackName> {
-        let aliases = self.aliases.read().unwrap();
-        aliases.get(&alias).cloned()
+    /// Deliver the objects held for `alias` that did not expire yet. They
+    /// are delivered under the lock of the held objects, and counted as
+    /// held until then, so that objects of the alias passed to
+    /// [`deliver_or_hold`](Self::deliver_or_hold) meanwhile wait for them.
+    fn release_held(&self, alias: TrackAlias) {
+        let now = Instant::now();
+        let mut held = self.held.lock().unwrap();
+        held.expire(now);
+        let released = held.take(alias);
+        for h in released {
+            self.deliver_checked(h.object, Some(h.preference));
+        }
+        self.held_objects.store(held.objects, Ordering::SeqCst);
+    }
+
+    /// The track an alias refers to. Only the reference count of the shared
+    /// entry is touched, so this is cheap enough to call for every object.
+    pub fn resolve_alias(&self, alias: TrackAlias) -> Option<Arc<TrackEntry>> {
+        self.aliases.read().unwrap().get(&alias).cloned()
+    }
+
+    /// Maximum Request ID permitted by the peer, exclusive.
+    pub fn max_request_id(&self) -> u64 {
+        self.max_request_id.load(Ordering::SeqCst)
     }
 
     /// Update the maximum request ID permitted by the peer. The provided value
     /// MUST be strictly greater than any previously received value.
     pub fn handle_max_request_id(&self, new_max: u64) -> Result<(), Error> {
-        let current = self.max_request_id.load(Ordering::SeqCst);
+        let current = self.max_request_id.fetch_max(new_max, Ordering::SeqCst);
         if new_max <= current {
             return Err(Error::ProtocolViolation {
                 reason: "MAX_REQUEST_ID decreased".into(),
+                context: None,
             });
         }
-        self.max_request_id.store(new_max, Ordering::SeqCst);
         Ok(())
     }
 
     /// Start a new subscription to the given track name. Returns the request id and a stream of objects.
     pub fn subscribe_track(&self, name: FullTrackName) -> Result<(u64, ObjectStream), Error> {
-        self.add_track(name.clone());
+        self.add_subscriber(name, None, GROUP_ORDER_PUBLISHER, RepairPolicy::Off)
+    }
+
+    /// Like [`subscribe_track`](Self::subscribe_track), recording the
+    /// filter of the SUBSCRIBE for [`subscriptions`](Self::subscriptions).
+    pub fn subscribe_track_with_filter(
+        &self,
+        name: FullTrackName,
+        filter: Filter,
+    ) -> Result<(u64, ObjectStream), Error> {
+        self.add_subscriber(name, Some(filter), GROUP_ORDER_PUBLISHER, RepairPolicy::Off)
+    }
+
+    /// Like [`subscribe_track_with_filter`](Self::subscribe_track_with_filter)
+    /// for the track of `request`, also recording its group order so the
+    /// one stated in SUBSCRIBE_OK can be checked against it.
+    pub fn subscribe_request(
+        &self,
+        request: &SubscribeRequest,
+    ) -> Result<(u64, ObjectStream), Error> {
+        self.add_subscriber(
+            request.track_name().to_string(),
+            Some(request.filter().clone()),
+            request.group_order(),
+            request.repair(),
+        )
+    }
+
+    fn add_subscriber(
+        &self,
+        name: FullTrackName,
+        filter: Option<Filter>,
+        group_order: u8,
+        repair: RepairPolicy,
+    ) -> Result<(u64, ObjectStream), Error> {
         let request_id = self.new_request_id()?;
+        let entry = self.add_track(name);
         let (tx, rx) = mpsc::channel(16);
+        entry.state.lock().unwrap().subscribers.push(Subscriber {
+            request_id,
+            filter,
+            group_order,
+            state: SubscriptionState::Pending,
+            delivered: Arc::new(AtomicU64::new(0)),
+            tx,
+            established: watch::Sender::new(None),
+            gaps: (repair != RepairPolicy::Off).then(|| GapDetector::new(repair)),
+        });
 
-        if let Some(entry) = self.tracks.read().unwrap().get(&name) {
-            let mut state = entry.lock().unwrap();
-            state.subscribers.push(tx);
+        self.requests.write().unwrap().insert(request_id, entry);
+        Ok((request_id, ObjectStream { rx }))
+    }
+
+    /// End the subscription `request_id`, e.g. on SUBSCRIBE_ERROR or
+    /// SUBSCRIBE_DONE: its object stream ends once the objects already
+    /// queued are received, after `error` if one is given and there is room
+    /// for it. Returns whether the subscription was known.
+    pub fn end_subscription(&self, request_id: u64, error: Option<Error>) -> bool {
+        self.requests.write().unwrap().remove(&request_id);
+        let entries: Vec<_> = self.tracks.read().unwrap().values().cloned().collect();
+        for entry in entries {
+            let mut state = entry.state.lock().unwrap();
+            let Some(i) = state
+                .subscribers
+                .iter()
+                .position(|s| s.request_id == request_id)
+            else {
+                continue;
+            };
+            let subscriber = state.subscribers.remove(i);
+            drop(state);
+            if let Some(error) = error {
+                let _ = subscriber.tx.try_send(Err(error));
+            }
+            return true;
         }
+        false
+    }
 
-        self.requests.write().unwrap().insert(request_id, name);
-        Ok((request_id, ObjectStream { rx }))
+    /// Follow the SUBSCRIBE_OK of the pending subscription `request_id`,
+    /// `None` once the request is no longer pending.
+    pub(crate) fn watch_established(
+        &self,
+        request_id: u64,
+    ) -> Option<watch::Receiver<Option<SubscribeOk>>> {
+        let entry = self.requests.read().unwrap().get(&request_id).cloned()?;
+        let state = entry.state.lock().unwrap();
+        state
+            .subscribers
+            .iter()
+            .find(|s| s.request_id == request_id)
+            .map(|s| s.established.subscribe())
+    }
+
+    /// The alias of the track `name`, once registered.
+    pub fn alias_of(&self, name: &str) -> Option<TrackAlias> {
+        let entry = self.tracks.read().unwrap().get(name).cloned()?;
+        let state = entry.state.lock().unwrap();
+        state.alias
+    }
+
+    /// Every subscription whose stream is still open, by Request ID.
+    pub fn subscriptions(&self) -> Vec<SubscriptionInfo> {
+        let mut subscriptions: Vec<_> = self
+            .tracks
+            .read()
+            .unwrap()
+            .values()
+            .flat_map(|entry| {
+                let state = entry.state.lock().unwrap();
+                state
+                    .subscribers
+                    .iter()
+                    .filter(|s| !s.tx.is_closed())
+                    .map(|s| SubscriptionInfo {
+                        request_id: s.request_id,
+                        track: entry.name.clone(),
+                        alias: state.alias,
+                        filter: s.filter.clone(),
+                        group_order: s.group_order,
+                        state: s.state,
+                        delivered: s.delivered.load(Ordering::Relaxed),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        subscriptions.sort_by_key(|s| s.request_id);
+        subscriptions
+    }
+
+    /// Every known track, by name.
+    pub fn tracks(&self) -> Vec<TrackInfo> {
+        let mut tracks: Vec<_> = self
+            .tracks
+            .read()
+            .unwrap()
+            .values()
+            .map(|entry| {
+                let state = entry.state.lock().unwrap();
+                TrackInfo {
+                    name: entry.name.clone(),
+                    alias: state.alias,
+                    forwarding: state.forwarding,
+                    subscribers: state
+                        .subscribers
+                        .iter()
+                        .filter(|s| !s.tx.is_closed())
+                        .count(),
+                }
+            })
+            .collect();
+        tracks.sort_by(|a, b| a.name.cmp(&b.name));
+        tracks
     }
 
     /// Process SUBSCRIBE_OK by registering the alias and clearing pending state.
     pub fn handle_subscribe_ok(&self, ok: &SubscribeOk) -> Result<(), Error> {
-        let name = {
+        let entry = {
             let mut reqs = self.requests.write().unwrap();
             reqs.remove(&ok.request_id)
         };
-        let name = name.ok_or_else(|| Error::ProtocolViolation {
+        let entry = entry.ok_or_else(|| Error::ProtocolViolation {
             reason: "unknown request".into(),
+            context: None,
         })?;
-        self.set_track_alias(&name, ok.track_alias)
+        for subscriber in &mut entry.state.lock().unwrap().subscribers {
+            if subscriber.request_id == ok.request_id {
+                // The requested order is a preference; objects are
+                // delivered in the order the publisher states.
+                subscriber.group_order = check_group_order(ok.group_order)?;
+                subscriber.state = SubscriptionState::Established;
+                subscriber.established.send_replace(Some(ok.clone()));
+            }
+        }
+        self.set_track_alias(entry, ok.track_alias)
+    }
+
+    /// Deliver a received object to the subscribers of its track. An object
+    /// whose payload hash extension does not match is delivered as
+    /// [`Error::PayloadHashMismatch`] instead. Returns the number of
+    /// subscribers the object was queued on.
+    ///
+    /// Objects are queued before this returns, so objects delivered one
+    /// after another by the same task reach every subscriber in that order.
+    /// A subscriber whose queue is full has fallen too far behind: its
+    /// stream ends with [`Error::TooFarBehind`] and it is unsubscribed.
+    /// [`SubgroupReader::deliver`](crate::subgroup::SubgroupReader::deliver)
+    /// relies on this to keep each subgroup in Object ID order.
+    ///
+    /// The forwarding preference is not checked, as for objects received
+    /// in response to a FETCH.
+    pub fn deliver(&self, object: Object) -> usize {
+        self.deliver_checked(object, None)
+    }
+
+    /// Like [`deliver`](Self::deliver) for an object received on a subgroup
+    /// stream or in a datagram. A track must keep the forwarding preference
+    /// of its first object; an object received otherwise marks the track
+    /// malformed and is delivered as [`Error::MalformedTrack`].
+    pub fn deliver_from(&self, object: Object, preference: ForwardingPreference) -> usize {
+        self.deliver_checked(object, Some(preference))
+    }
+
+    /// Like [`deliver`](Self::deliver), but waits for room in the queue of
+    /// each subscriber in turn instead of skipping full ones, so a slow
+    /// subscriber slows the caller down rather than losing objects.
+    pub async fn deliver_async(&self, object: Object) -> usize {
+        self.deliver_checked_async(object, None).await
+    }
+
+    /// Like [`deliver_from`](Self::deliver_from), waiting for room in the
+    /// subscribers' queues as [`deliver_async`](Self::deliver_async) does.
+    pub async fn deliver_from_async(
+        &self,
+        object: Object,
+        preference: ForwardingPreference,
+    ) -> usize {
+        self.deliver_checked_async(object, Some(preference)).await
+    }
+
+    /// Like [`deliver_from`](Self::deliver_from), but an object whose alias
+    /// is not registered yet is held within the bounds of `window`, and
+    /// delivered once the alias is registered, e.g. by a SUBSCRIBE_OK that
+    /// was overtaken by the object. Objects that do not fit are dropped.
+    ///
+    /// Objects of an alias are released in the order they were held, and
+    /// before any object of the alias passed afterwards, so a subgroup
+    /// stream read by one task stays in order.
+    pub fn deliver_or_hold(
+        &self,
+        object: Object,
+        preference: ForwardingPreference,
+        window: &AliasWindow,
+    ) -> Delivery {
+        match self.hold(object, preference, window) {
+            Ok(delivery) => delivery,
+            Err(object) => Delivery::Delivered(self.deliver_from(object, preference)),
+        }
+    }
+
+    /// Like [`deliver_or_hold`](Self::deliver_or_hold), waiting for room in
+    /// the subscribers' queues as [`deliver_async`](Self::deliver_async)
+    /// does once the alias is registered.
+    pub async fn deliver_or_hold_async(
+        &self,
+        object: Object,
+        preference: ForwardingPreference,
+        window: &AliasWindow,
+    ) -> Delivery {
+        match self.hold(object, preference, window) {
+            Ok(delivery) => delivery,
+            Err(object) => Delivery::Delivered(self.deliver_from_async(object, preference).await),
+        }
+    }
+
+    /// Hold `object` if its alias is not registered, or give it back to be
+    /// delivered right away.
+    fn hold(
+        &self,
+        object: Object,
+        preference: ForwardingPreference,
+        window: &AliasWindow,
+    ) -> Result<Delivery, Object> {
+        let alias = object.metadata.track_alias;
+        // Objects are only held while their alias is not registered, and
+        // released before they stop being counted, so nothing held can be
+        // overtaken once the alias resolves and the count is zero.
+        if self.held_objects.load(Ordering::SeqCst) == 0 && self.resolve_alias(alias).is_some() {
+            return Err(object);
+        }
+        let now = Instant::now();
+        let mut held = self.held.lock().unwrap();
+        held.expire(now);
+        self.held_objects.store(held.objects, Ordering::SeqCst);
+        // Checked under the lock, so the alias cannot be registered between
+        // the check and holding the object without releasing it. Objects
+        // still held for a registered alias are about to be released, and
+        // this one must follow them.
+        if !held.by_alias.contains_key(&alias) && self.resolve_alias(alias).is_some() {
+            return Err(object);
+        }
+        let len = object.payload.len();
+        if held.objects >= window.max_objects || held.bytes + len > window.max_bytes {
+            return Ok(Delivery::Dropped);
+        }
+        held.by_alias
+            .entry(alias)
+            .or_default()
+            .push_back(HeldObject {
+                expires: now + window.max_age,
+                object,
+                preference,
+            });
+        held.objects += 1;
+        held.bytes += len;
+        self.held_objects.store(held.objects, Ordering::SeqCst);
+        Ok(Delivery::Held)
+    }
+
+    /// Number of objects held for aliases not registered yet, including
+    /// expired ones not discarded yet.
+    pub fn held_objects(&self) -> usize {
+        self.held.lock().unwrap().objects
+    }
+
+    fn deliver_checked(&self, object: Object, preference: Option<ForwardingPreference>) -> usize {
+        let Some(route) = self.route(object, preference) else {
+            return 0;
+        };
+        let mut delivered = 0;
+        let mut behind = Vec::new();
+        for target in &route.targets {
+            // The last slot is kept to tell a subscriber it fell behind.
+            if target.tx.capacity() > 1 && target.tx.try_send(route.item()).is_ok() {
+                target.delivered.fetch_add(1, Ordering::Relaxed);
+                delivered += 1;
+            } else if !target.tx.is_closed() {
+                behind.push(target.request_id);
+            }
+        }
+        if !behind.is_empty() {
+            Self::drop_behind(&route.entry, &behind);
+        }
+        self.observe(&route, delivered);
+        delivered
+    }
+
+    /// End the subscriptions in `behind`, whose queue is full, with
+    /// [`Error::TooFarBehind`].
+    fn drop_behind(entry: &TrackEntry, behind: &[u64]) {
+        let mut state = entry.state.lock().unwrap();
+        state.subscribers.retain(|s| {
+            if !behind.contains(&s.request_id) {
+                return true;
+            }
+            let _ = s.tx.try_send(Err(Error::TooFarBehind));
+            false
+        });
+    }
+
+    async fn deliver_checked_async(
+        &self,
+        object: Object,
+        preference: Option<ForwardingPreference>,
+    ) -> usize {
+        let Some(route) = self.route(object, preference) else {
+            return 0;
+        };
+        let mut delivered = 0;
+        for target in &route.targets {
+            if target.tx.send(route.item()).await.is_ok() {
+                target.delivered.fetch_add(1, Ordering::Relaxed);
+                delivered += 1;
+            }
+        }
+        self.observe(&route, delivered);
+        delivered
+    }
+
+    fn observe(&self, route: &Route, delivered: usize) {
+        if let Ok(object) = &route.item {
+            self.observers.on_object_forwarded(object, delivered);
+        }
+    }
+
+    /// Queue `error` on every subscription of the track with `track_alias`,
+    /// e.g. to report an object lost with its stream. Returns the number of
+    /// subscriptions it was queued on, 0 if the alias is not registered.
+    pub fn deliver_error(&self, track_alias: TrackAlias, error: Error) -> usize {
+        let Some(entry) = self.resolve_alias(track_alias) else {
+            return 0;
+        };
+        let mut state = entry.state.lock().unwrap();
+        state.subscribers.retain(|s| !s.tx.is_closed());
+        let route = Route {
+            entry: entry.clone(),
+            item: Err(error),
+            targets: state
+                .subscribers
+                .iter()
+                .map(|s| Target {
+                    request_id: s.request_id,
+                    tx: s.tx.clone(),
+                    delivered: s.delivered.clone(),
+                })
+                .collect(),
+        };
+        drop(state);
+        // Not counted as delivered objects.
+        route
+            .targets
+            .iter()
+            .filter(|target| target.tx.try_send(route.item()).is_ok())
+            .count()
+    }
+
+    /// Check `object` against the forwarding preference of its track and
+    /// take the subscribers to queue it on, dropping those that went away.
+    /// `None` if its alias is not registered.
+    fn route(&self, object: Object, preference: Option<ForwardingPreference>) -> Option<Route> {
+        let entry = self.resolve_alias(object.metadata.track_alias)?;
+        let mut state = entry.state.lock().unwrap();
+        let item = match (preference, state.forwarding) {
+            (Some(received), Some(track)) if received != track => Err(Error::MalformedTrack {
+                reason: format!(
+                    "object {}/{} received as {:?} on a {:?} track",
+                    object.metadata.group_id, object.metadata.object_id, received, track
+                ),
+            }),
+            (received, _) => {
+                state.forwarding = state.forwarding.or(received);
+                crate::integrity::verify_payload_hash(&object).map(|()| object)
+            }
+        };
+        state.subscribers.retain(|s| !s.tx.is_closed());
+        if let (Some(ForwardingPreference::Datagram), Ok(object)) = (preference, &item) {
+            let now = tokio::time::Instant::now();
+            let mut repairs = self.repairs.lock().unwrap();
+            for s in &mut state.subscribers {
+                if let Some(range) = s.gaps.as_mut().and_then(|g| g.observe(object, now)) {
+                    repairs.push((s.request_id, range));
+                }
+            }
+        }
+        let targets = state
+            .subscribers
+            .iter()
+            .map(|s| Target {
+                request_id: s.request_id,
+                tx: s.tx.clone(),
+                delivered: s.delivered.clone(),
+            })
+            .collect();
+        drop(state);
+        Some(Route {
+            entry,
+            item,
+            targets,
+        })
+    }
+
+    /// Take the gaps found in subscriptions repairing their datagrams, see
+    /// [`SubscribeRequest::with_repair`].
+    pub fn take_repairs(&self) -> Vec<(u64, RepairRange)> {
+        std::mem::take(&mut *self.repairs.lock().unwrap())
+    }
+
+    /// Alias of the track of subscription `request_id` and the sender of
+    /// its object stream, for objects reaching it other than through the
+    /// track, such as those of a repair FETCH.
+    pub(crate) fn subscription_sender(
+        &self,
+        request_id: u64,
+    ) -> Option<(TrackAlias, mpsc::Sender<Result<Object, Error>>)> {
+        let entries: Vec<_> = self.tracks.read().unwrap().values().cloned().collect();
+        entries.iter().find_map(|entry| {
+            let state = entry.state.lock().unwrap();
+            let subscriber = state
+                .subscribers
+                .iter()
+                .find(|s| s.request_id == request_id)?;
+            Some((state.alias?, subscriber.tx.clone()))
+        })
     }
 }
 
@@ -142,29 +870,415 @@ pub struct Track {
 
 pub struct TrackPublisher {
     track_alias: TrackAlias,
+    delivery: Option<DeliveryParams>,
+    publisher_priority: u8,
+    next_group: u64,
+    open_group: Option<GroupHandle>,
+    retention: RetentionBuffer,
 }
 
 impl TrackPublisher {
+    /// Publisher with publisher priority 128 whose first group is group 0.
+    pub fn new(track_alias: TrackAlias) -> Self {
+        Self {
+            track_alias,
+            delivery: None,
+            publisher_priority: 128,
+            next_group: 0,
+            open_group: None,
+            retention: RetentionBuffer::default(),
+        }
+    }
+
+    pub fn set_publisher_priority(&mut self, priority: u8) {
+        self.publisher_priority = priority;
+    }
+
+    /// Set the ID of the next group, e.g. to continue a track after a
+    /// restart. Group IDs must keep increasing.
+    pub fn set_next_group(&mut self, group_id: u64) {
+        self.next_group = group_id;
+    }
+
+    /// Start the next group. A group still open is ended implicitly: the
+    /// start of the next group marks it complete for subscribers.
+    pub fn begin_group(&mut self) -> GroupHandle {
+        let group = GroupHandle::new(
+            self.track_alias,
+            self.next_group,
+            self.publisher_priority,
+            self.retention.clone(),
+        );
+        self.next_group += 1;
+        self.open_group = Some(group.clone());
+        group
+    }
+
+    /// End the open group. Returns the End of Group status object to send,
+    /// or `None` if no group is open.
+    pub fn end_group(&mut self) -> Option<Object> {
+        self.open_group.take().map(|g| g.end_of_group())
+    }
+
+    /// ID of the open group.
+    pub fn current_group(&self) -> Option<u64> {
+        self.open_group.as_ref().map(|g| g.group_id())
+    }
+
+    /// Map an encoded frame to objects: a keyframe ends the open group and
+    /// starts a new one, so each group begins with an independently
+    /// decodable frame. Frames go to subgroup 0. Returns the objects to
+    /// send, in order.
+    ///
+    /// While no group is open, e.g. before the first keyframe or after
+    /// [`end_group`](Self::end_group), frames that are not keyframes cannot
+    /// start a group and are dropped: nothing is returned for them.
+    pub fn push_frame(&mut self, keyframe: bool, payload: Bytes) -> Vec<Object> {
+        let mut objects = Vec::with_capacity(2);
+        if keyframe {
+            objects.extend(self.end_group());
+            self.begin_group();
+        }
+        let Some(group) = self.open_group.as_ref() else {
+            return objects;
+        };
+        objects.push(group.subgroup(0).object(payload));
+        objects
+    }
+
     pub fn alias(&self) -> TrackAlias {
         self.track_alias
     }
+
+    /// Keep the objects of the last `groups` groups for FETCH repair. No
+    /// objects are kept by default.
+    pub fn set_retained_groups(&mut self, groups: usize) {
+        self.retention.set_max_groups(groups);
+    }
+
+    /// Set what is kept of published groups, see [`RetentionPolicy`].
+    pub fn set_retention_policy(&mut self, policy: RetentionPolicy) {
+        self.retention.set_policy(policy);
+    }
+
+    /// Charge retained objects against `budget`, typically the memory
+    /// budget of the session the track is published on.
+    pub fn set_memory_budget(&mut self, budget: MemoryBudget) {
+        self.retention.set_memory_budget(budget);
+    }
+
+    /// Largest location published, for Largest Location in SUBSCRIBE_OK
+    /// and PUBLISH. `None` means Content Exists is 0.
+    pub fn largest_location(&self) -> Option<Location> {
+        self.retention.largest()
+    }
+
+    /// Locations of the first and last retained objects.
+    pub fn retained_range(&self) -> Option<(Location, Location)> {
+        self.retention.range()
+    }
+
+    /// Buffer of the retained objects, for a FETCH responder to serve
+    /// retained ranges from, including from another task.
+    pub fn retention(&self) -> RetentionBuffer {
+        self.retention.clone()
+    }
+
+    /// Apply the delivery parameters negotiated through PUBLISH_OK.
+    pub fn set_delivery(&mut self, params: DeliveryParams) {
+        self.delivery = Some(params);
+    }
+
+    pub fn delivery(&self) -> Option<&DeliveryParams> {
+        self.delivery.as_ref()
+    }
+
+    /// Scheduling priority of `object` on this track: the negotiated
+    /// subscriber priority and group order, or the default subscriber
+    /// priority in ascending group order until PUBLISH_OK has been
+    /// processed.
+    pub fn priority(&self, object: &ObjectMetadata) -> Priority {
+        match &self.delivery {
+            Some(delivery) => delivery.priority(object),
+            None => {
+                Priority::for_object(DEFAULT_SUBSCRIBER_PRIORITY, GROUP_ORDER_ASCENDING, object)
+            }
+        }
+    }
+
+    /// How long an object may wait to be sent before it is dropped, as
+    /// negotiated through PUBLISH_OK.
+    pub fn delivery_timeout(&self) -> Option<Duration> {
+        self.delivery.as_ref().and_then(|d| d.delivery_timeout)
+    }
+
+    /// Whether an object at `loc` should be sent. Until PUBLISH_OK has been
+    /// processed every object is eligible.
+    pub fn should_send(&self, loc: &Location) -> bool {
+        self.delivery
+            .as_ref()
+            .map(|d| d.should_forward(loc))
+            .unwrap_or(true)
+    }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Object {
     pub metadata: ObjectMetadata,
+    /// Status of the object; anything but `Normal` has an empty payload.
+    pub status: ObjectStatus,
+    /// Object extension headers, serialized as key-value pairs.
+    pub extension_headers: Bytes,
     pub payload: Bytes,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ObjectMetadata {
     pub track_alias: u64,
     pub group_id: u64,
+    pub subgroup_id: u64,
     pub object_id: u64,
-    pub priority: u8,
+    pub publisher_priority: u8,
+}
+
+impl ObjectMetadata {
+    pub fn location(&self) -> Location {
+        Location::new(self.group_id, self.object_id)
+    }
+
+    pub fn key(&self) -> ObjectKey {
+        ObjectKey {
+            track: self.track_alias,
+            group: self.group_id,
+            subgroup: self.subgroup_id,
+            object: self.object_id,
+        }
+    }
+}
+
+/// Identity of an object, shared by every index of objects: the relay
+/// cache and its duplicate detection, the [`ReorderBuffer`] and FETCH range
+/// iteration.
+///
+/// Keys are ordered by track, then as on a FETCH stream: by group, then by
+/// object. Object IDs are unique within a group, so the subgroup only
+/// orders otherwise equal keys, and the keys of a [`Location`] range are
+/// those of [`ObjectKey::range`].
+///
+/// [`ReorderBuffer`]: crate::reorder::ReorderBuffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjectKey {
+    /// Track Alias, or 0 in indexes of a single track whose objects may
+    /// arrive under several aliases.
+    pub track: u64,
+    pub group: u64,
+    pub subgroup: u64,
+    pub object: u64,
+}
+
+impl ObjectKey {
+    /// Keys of `track` from `start` up to and including `end`, in every
+    /// subgroup.
+    pub fn range(track: u64, start: &Location, end: &Location) -> RangeInclusive<ObjectKey> {
+        let first = ObjectKey {
+            track,
+            group: start.group,
+            subgroup: 0,
+            object: start.object,
+        };
+        let last = ObjectKey {
+            track,
+            group: end.group,
+            subgroup: u64::MAX,
+            object: end.object,
+        };
+        first..=last
+    }
+
+    pub fn location(&self) -> Location {
+        Location::new(self.group, self.object)
+    }
+
+    /// Whether the object lies from `start` up to and including `end`.
+    pub fn is_within(&self, start: &Location, end: &Location) -> bool {
+        self.location().is_within(start, end)
+    }
+
+    /// The same object in an index of a single track.
+    pub fn untracked(self) -> Self {
+        Self { track: 0, ..self }
+    }
+}
+
+impl Ord for ObjectKey {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.track, self.group, self.object, self.subgroup).cmp(&(
+            other.track,
+            other.group,
+            other.object,
+            other.subgroup,
+        ))
+    }
+}
+
+impl PartialOrd for ObjectKey {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Object {
+    /// Object read from the subgroup stream opened by `header`.
+    /// `first_object_id` is the Object ID of the first object on the
+    /// stream, which some header types use as the Subgroup ID.
+    pub fn from_subgroup(
+        header: &SubgroupHeader,
+        first_object_id: u64,
+        object: SubgroupObject,
+    ) -> Result<Self, Error> {
+        let subgroup_id = match header.subgroup_id {
+            SubgroupId::Zero => 0,
+            SubgroupId::FirstObjectId => first_object_id,
+            SubgroupId::Explicit(id) => id,
+        };
+        Ok(Self {
+            metadata: ObjectMetadata {
+                track_alias: header.track_alias,
+                group_id: header.group_id,
+                subgroup_id,
+                object_id: object.object_id,
+                publisher_priority: header.publisher_priority,
+            },
+            status: status(object.object_status)?,
+            extension_headers: object.extension_headers,
+            payload: object.payload,
+        })
+    }
+
+    /// Object read from a fetch stream of the track aliased `track_alias`.
+    pub fn from_fetch(track_alias: TrackAlias, object: FetchObject) -> Result<Self, Error> {
+        Ok(Self {
+            metadata: ObjectMetadata {
+                track_alias,
+                group_id: object.group_id,
+                subgroup_id: object.subgroup_id,
+                object_id: object.object_id,
+                publisher_priority: object.publisher_priority,
+            },
+            status: status(object.object_status)?,
+            extension_headers: object.extension_headers,
+            payload: object.payload,
+        })
+    }
+
+    /// Object received in a datagram. Datagrams carry no Subgroup ID, so
+    /// the object is placed in subgroup 0.
+    pub fn from_datagram(datagram: ObjectDatagram) -> Result<Self, Error> {
+        Ok(Self {
+            metadata: ObjectMetadata {
+                track_alias: datagram.track_alias,
+                group_id: datagram.group_id,
+                subgroup_id: 0,
+                object_id: datagram.object_id,
+                publisher_priority: datagram.publisher_priority,
+            },
+            status: status(datagram.object_status)?,
+            extension_headers: datagram.extension_headers,
+            payload: datagram.payload,
+        })
+    }
+
+    /// The object as written on a subgroup stream. Metadata other than the
+    /// Object ID is carried by the stream's SUBGROUP_HEADER.
+    pub fn to_subgroup_object(&self) -> SubgroupObject {
+        SubgroupObject {
+            object_id: self.metadata.object_id,
+            extension_headers: self.extension_headers.clone(),
+            object_status: self.wire_status(),
+            payload: self.payload.clone(),
+        }
+    }
+
+    /// The object as sent in a datagram.
+    pub fn to_datagram(&self, end_of_group: bool) -> ObjectDatagram {
+        ObjectDatagram {
+            track_alias: self.metadata.track_alias,
+            group_id: self.metadata.group_id,
+            object_id: self.metadata.object_id,
+            publisher_priority: self.metadata.publisher_priority,
+            end_of_group,
+            extension_headers: self.extension_headers.clone(),
+            object_status: self.wire_status(),
+            payload: self.payload.clone(),
+        }
+    }
+
+    /// The object as written on a fetch stream.
+    pub fn to_fetch_object(&self) -> FetchObject {
+        FetchObject {
+            group_id: self.metadata.group_id,
+            subgroup_id: self.metadata.subgroup_id,
+            object_id: self.metadata.object_id,
+            publisher_priority: self.metadata.publisher_priority,
+            extension_headers: self.extension_headers.clone(),
+            object_status: self.wire_status(),
+            payload: self.payload.clone(),
+        }
+    }
+
+    /// Object Status is only present on the wire for empty payloads.
+    fn wire_status(&self) -> Option<u64> {
+        self.payload.is_empty().then(|| self.status.code())
+    }
+}
+
+fn status(object_status: Option<u64>) -> Result<ObjectStatus, Error> {
+    Ok(object_status.map_or(Ok(ObjectStatus::Normal), ObjectStatus::try_from)?)
 }
 
 /// Stream of objects for a subscription.
 pub struct ObjectStream {
-    rx: mpsc::Receiver<Result<Object, Error>>,
+    pub(crate) rx: mpsc::Receiver<Result<Object, Error>>,
+}
+
+impl ObjectStream {
+    /// Create a stream together with the sender feeding it, for components
+    /// producing objects outside the track manager such as relays.
+    pub fn channel(capacity: usize) -> (mpsc::Sender<Result<Object, Error>>, Self) {
+        let (tx, rx) = mpsc::channel(capacity);
+        (tx, Self { rx })
+    }
+
+    /// Receive the next object, or `None` once every sender is gone.
+    pub async fn recv(&mut self) -> Option<Result<Object, Error>> {
+        self.rx.recv().await
+    }
+
+    /// Receive the next object if one is queued, without waiting.
+    pub fn try_recv(&mut self) -> Option<Result<Object, Error>> {
+        self.rx.try_recv().ok()
+    }
+
+    /// Consume the stream in "keep up with live" mode, skipping whole
+    /// groups once the consumer lags more than `max_lag` groups behind the
+    /// newest group received.
+    pub fn keep_live(self, max_lag: u64) -> LiveStream {
+        LiveStream::new(self, max_lag)
+    }
+
+    /// Let each object through once, dropping those received again, e.g.
+    /// from a repair FETCH or after failover to another upstream. The
+    /// newest `max_objects` objects are remembered.
+    pub fn dedup(self, max_objects: usize) -> DedupStream {
+        DedupStream::new(self, max_objects)
+    }
+
+    /// Emit the objects in group and object order although they arrive on
+    /// several subgroup streams, holding early objects back within
+    /// `limits`.
+    pub fn reorder(self, limits: ReorderLimits) -> ReorderStream {
+        ReorderStream::new(self, limits)
+    }
 }
 
 impl Stream for ObjectStream {
@@ -178,15 +1292,14 @@ impl Stream for ObjectStream {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mock::ObjectBuilder;
 
     #[test]
     fn duplicate_alias_is_error() {
         let manager = TrackManager::default();
-        manager.add_track("video".to_string());
-        assert!(manager.set_track_alias(&"video".to_string(), 1).is_ok());
-        let err = manager
-            .set_track_alias(&"video".to_string(), 1)
-            .unwrap_err();
+        let entry = manager.add_track("video".to_string());
+        assert!(manager.set_track_alias(entry.clone(), 1).is_ok());
+        let err = manager.set_track_alias(entry, 1).unwrap_err();
         match err {
             Error::DuplicateTrackAlias(1) => {}
             e => panic!("unexpected error: {:?}", e),
@@ -196,9 +1309,9 @@ mod tests {
     #[test]
     fn resolve_returns_name() {
         let manager = TrackManager::default();
-        manager.add_track("audio".to_string());
-        manager.set_track_alias(&"audio".to_string(), 2).unwrap();
-        assert_eq!(manager.resolve_alias(2).as_deref(), Some("audio"));
+        let entry = manager.add_track("audio".to_string());
+        manager.set_track_alias(entry, 2).unwrap();
+        assert_eq!(manager.resolve_alias(2).unwrap().name(), "audio");
     }
 
     #[test]
@@ -216,8 +1329,8 @@ mod tests {
         manager.handle_max_request_id(10).unwrap();
         let (id, stream) = manager.subscribe_track("video".to_string()).unwrap();
         assert_eq!(
-            manager.requests.read().unwrap().get(&id),
-            Some(&"video".to_string())
+            manager.requests.read().unwrap().get(&id).map(|t| t.name()),
+            Some("video")
         );
         drop(stream);
     }
@@ -237,7 +1350,7 @@ mod tests {
             parameters: Vec::new(),
         };
         manager.handle_subscribe_ok(&ok).unwrap();
-        assert_eq!(manager.resolve_alias(7).as_deref(), Some("audio"));
+        assert_eq!(manager.resolve_alias(7).unwrap().name(), "audio");
     }
 
     #[test]
@@ -249,6 +1362,7 @@ mod tests {
             Error::ProtocolViolation { .. } => {}
             e => panic!("unexpected error: {:?}", e),
         }
+        assert_eq!(manager.max_request_id(), 10);
     }
 
     #[test]
@@ -262,4 +1376,392 @@ mod tests {
             e => panic!("unexpected error: {:?}", e),
         }
     }
+
+    #[test]
+    fn deliver_surfaces_hash_mismatch() {
+        let manager = TrackManager::default();
+        manager.handle_max_request_id(10).unwrap();
+        let (id, mut stream) = manager.subscribe_track("video".to_string()).unwrap();
+        manager
+            .handle_subscribe_ok(&SubscribeOk {
+                request_id: id,
+                track_alias: 3,
+                expires: 0,
+                group_order: 1,
+                content_exists: false,
+                largest_location: None,
+                parameters: Vec::new(),
+            })
+            .unwrap();
+
+        let mut object = ObjectBuilder::new(0, 0).with_track_alias(3).build();
+        crate::integrity::add_payload_hash(&mut object).unwrap();
+        assert_eq!(manager.deliver(object.clone()), 1);
+
+        object.metadata.object_id = 1;
+        object.payload = Bytes::from_static(b"corrupt");
+        assert_eq!(manager.deliver(object), 1);
+
+        assert!(stream.rx.try_recv().unwrap().is_ok());
+        match stream.rx.try_recv().unwrap() {
+            Err(Error::PayloadHashMismatch {
+                group_id: 0,
+                object_id: 1,
+            }) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn subscribe_ok_states_effective_group_order() {
+        let manager = TrackManager::default();
+        manager.handle_max_request_id(10).unwrap();
+        let ok = |request_id, group_order| SubscribeOk {
+            request_id,
+            track_alias: request_id + 1,
+            expires: 0,
+            group_order,
+            content_exists: false,
+            largest_location: None,
+            parameters: Vec::new(),
+        };
+
+        // Deferring to the publisher takes the order it chose.
+        let (deferred, _a) = manager
+            .subscribe_request(&SubscribeRequest::new(0, "a"))
+            .unwrap();
+        manager.handle_subscribe_ok(&ok(deferred, 0x2)).unwrap();
+        assert_eq!(manager.subscriptions()[0].group_order, 0x2);
+
+        // An explicit preference is shown until SUBSCRIBE_OK, which may
+        // state another order.
+        let (explicit, _b) = manager
+            .subscribe_request(&SubscribeRequest::new(0, "b").with_group_order(0x1))
+            .unwrap();
+        assert_eq!(manager.subscriptions()[1].group_order, 0x1);
+        manager.handle_subscribe_ok(&ok(explicit, 0x2)).unwrap();
+        assert_eq!(manager.subscriptions()[1].group_order, 0x2);
+
+        // SUBSCRIBE_OK must state the order.
+        let (unstated, _c) = manager
+            .subscribe_request(&SubscribeRequest::new(0, "c"))
+            .unwrap();
+        assert!(manager.handle_subscribe_ok(&ok(unstated, 0x0)).is_err());
+    }
+
+    #[test]
+    fn async_delivery_waits_for_room() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let manager = Arc::new(TrackManager::default());
+            manager.handle_max_request_id(10).unwrap();
+            let (id, mut stream) = manager.subscribe_track("video".to_string()).unwrap();
+            manager
+                .handle_subscribe_ok(&SubscribeOk {
+                    request_id: id,
+                    track_alias: 4,
+                    expires: 0,
+                    group_order: 1,
+                    content_exists: false,
+                    largest_location: None,
+                    parameters: Vec::new(),
+                })
+                .unwrap();
+            let object = |object_id| ObjectBuilder::new(0, object_id).with_track_alias(4).build();
+            // Fill the queue of the subscription.
+            let object_id = 16;
+            for object_id in 0..object_id {
+                assert_eq!(manager.deliver_async(object(object_id)).await, 1);
+            }
+
+            let delivering = manager.clone();
+            let pending =
+                tokio::spawn(async move { delivering.deliver_async(object(object_id)).await });
+            tokio::task::yield_now().await;
+            assert!(!pending.is_finished());
+            // The track is not locked while the delivery waits.
+            assert_eq!(manager.subscriptions()[0].delivered, object_id);
+
+            stream.recv().await.unwrap().unwrap();
+            assert_eq!(pending.await.unwrap(), 1);
+            assert_eq!(manager.subscriptions()[0].delivered, object_id + 1);
+        });
+    }
+
+    #[test]
+    fn subscriber_with_full_queue_is_dropped() {
+        let manager = TrackManager::default();
+        manager.handle_max_request_id(10).unwrap();
+        let (id, mut stream) = manager.subscribe_track("video".to_string()).unwrap();
+        manager
+            .handle_subscribe_ok(&SubscribeOk {
+                request_id: id,
+                track_alias: 5,
+                expires: 0,
+                group_order: 1,
+                content_exists: false,
+                largest_location: None,
+                parameters: Vec::new(),
+            })
+            .unwrap();
+        let object = |object_id| ObjectBuilder::new(0, object_id).with_track_alias(5).build();
+        let mut object_id = 0;
+        while manager.deliver(object(object_id)) == 1 {
+            object_id += 1;
+        }
+        assert!(manager.subscriptions().is_empty());
+
+        // Objects queued before it fell behind are kept, then the stream
+        // ends with the reason.
+        for expected in 0..object_id {
+            let received = stream.rx.try_recv().unwrap().unwrap();
+            assert_eq!(received.metadata.object_id, expected);
+        }
+        match stream.rx.try_recv().unwrap() {
+            Err(Error::TooFarBehind) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert!(stream.rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn delivered_errors_keep_their_variant() {
+        let manager = TrackManager::default();
+        manager.handle_max_request_id(10).unwrap();
+        let (id, mut stream) = manager.subscribe_track("video".to_string()).unwrap();
+        manager
+            .handle_subscribe_ok(&SubscribeOk {
+                request_id: id,
+                track_alias: 6,
+                expires: 0,
+                group_order: 1,
+                content_exists: false,
+                largest_location: None,
+                parameters: Vec::new(),
+            })
+            .unwrap();
+        let error = Error::TruncatedObject {
+            group_id: 2,
+            object_id: Some(7),
+        };
+        assert_eq!(manager.deliver_error(6, error), 1);
+        match stream.rx.try_recv().unwrap() {
+            Err(Error::TruncatedObject {
+                group_id: 2,
+                object_id: Some(7),
+            }) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn subscriptions_and_tracks_are_listed() {
+        let manager = TrackManager::default();
+        manager.handle_max_request_id(10).unwrap();
+        let (video, _video) = manager
+            .subscribe_track_with_filter("video".to_string(), Filter::next_group())
+            .unwrap();
+        let (audio, audio_stream) = manager.subscribe_track("audio".to_string()).unwrap();
+        manager
+            .handle_subscribe_ok(&SubscribeOk {
+                request_id: video,
+                track_alias: 4,
+                expires: 0,
+                group_order: 1,
+                content_exists: false,
+                largest_location: None,
+                parameters: Vec::new(),
+            })
+            .unwrap();
+        manager.deliver(ObjectBuilder::new(0, 0).with_track_alias(4).build());
+
+        let subscriptions = manager.subscriptions();
+        assert_eq!(
+            subscriptions,
+            vec![
+                SubscriptionInfo {
+                    request_id: video,
+                    track: "video".to_string(),
+                    alias: Some(4),
+                    filter: Some(Filter::NextGroupStart),
+                    group_order: 1,
+                    state: SubscriptionState::Established,
+                    delivered: 1,
+                },
+                SubscriptionInfo {
+                    request_id: audio,
+                    track: "audio".to_string(),
+                    alias: None,
+                    filter: None,
+                    group_order: 0,
+                    state: SubscriptionState::Pending,
+                    delivered: 0,
+                },
+            ]
+        );
+
+        drop(audio_stream);
+        assert_eq!(manager.subscriptions().len(), 1);
+        let tracks = manager.tracks();
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(
+            tracks[1],
+            TrackInfo {
+                name: "video".to_string(),
+                alias: Some(4),
+                forwarding: None,
+                subscribers: 1,
+            }
+        );
+        assert_eq!(tracks[0].subscribers, 0);
+    }
+
+    #[test]
+    fn mixed_forwarding_preference_is_malformed() {
+        let manager = TrackManager::default();
+        manager.handle_max_request_id(10).unwrap();
+        let (id, mut stream) = manager.subscribe_track("video".to_string()).unwrap();
+        manager
+            .handle_subscribe_ok(&SubscribeOk {
+                request_id: id,
+                track_alias: 3,
+                expires: 0,
+                group_order: 1,
+                content_exists: false,
+                largest_location: None,
+                parameters: Vec::new(),
+            })
+            .unwrap();
+        let object = |object_id| ObjectBuilder::new(0, object_id).with_track_alias(3).build();
+
+        manager.deliver_from(object(0), ForwardingPreference::Datagram);
+        manager.deliver_from(object(1), ForwardingPreference::Subgroup);
+        // Fetched objects do not count.
+        manager.deliver(object(2));
+        manager.deliver_from(object(3), ForwardingPreference::Datagram);
+
+        assert!(stream.rx.try_recv().unwrap().is_ok());
+        match stream.rx.try_recv().unwrap() {
+            Err(Error::MalformedTrack { .. }) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert!(stream.rx.try_recv().unwrap().is_ok());
+        assert!(stream.rx.try_recv().unwrap().is_ok());
+    }
+
+    #[test]
+    fn held_objects_are_released_per_alias() {
+        let manager = TrackManager::default();
+        manager.handle_max_request_id(10).unwrap();
+        let (id, mut stream) = manager.subscribe_track("video".to_string()).unwrap();
+        let object = |track_alias, object_id| {
+            ObjectBuilder::new(0, object_id)
+                .with_track_alias(track_alias)
+                .build()
+        };
+        let window = AliasWindow::default().with_max_bytes(15);
+        let hold = |o| manager.deliver_or_hold(o, ForwardingPreference::Subgroup, &window);
+
+        assert_eq!(hold(object(3, 0)), Delivery::Held);
+        assert_eq!(hold(object(4, 0)), Delivery::Held);
+        assert_eq!(hold(object(3, 1)), Delivery::Held);
+        // The payload bytes of all aliases count against the window.
+        assert_eq!(hold(object(4, 1)), Delivery::Dropped);
+
+        manager
+            .handle_subscribe_ok(&SubscribeOk {
+                request_id: id,
+                track_alias: 3,
+                expires: 0,
+                group_order: 1,
+                content_exists: false,
+                largest_location: None,
+                parameters: Vec::new(),
+            })
+            .unwrap();
+        assert_eq!(manager.held_objects(), 1);
+        for object_id in 0..2 {
+            let released = stream.rx.try_recv().unwrap().unwrap();
+            assert_eq!(released.metadata.object_id, object_id);
+        }
+        assert_eq!(hold(object(3, 2)), Delivery::Delivered(1));
+        // Releasing freed room for the alias still unknown.
+        assert_eq!(hold(object(4, 1)), Delivery::Held);
+        assert_eq!(manager.held_objects(), 2);
+    }
+
+    #[test]
+    fn objects_convert_to_and_from_the_wire() {
+        let header = SubgroupHeader {
+            track_alias: 7,
+            group_id: 2,
+            subgroup_id: SubgroupId::FirstObjectId,
+            publisher_priority: 40,
+            extensions_present: true,
+            end_of_group: false,
+        };
+        let wire = SubgroupObject {
+            object_id: 5,
+            extension_headers: Bytes::from_static(&[0x02, 0x01]),
+            object_status: None,
+            payload: Bytes::from_static(b"frame"),
+        };
+        let object = Object::from_subgroup(&header, 4, wire.clone()).unwrap();
+        assert_eq!(
+            object.metadata,
+            ObjectMetadata {
+                track_alias: 7,
+                group_id: 2,
+                subgroup_id: 4,
+                object_id: 5,
+                publisher_priority: 40,
+            }
+        );
+        assert_eq!(object.to_subgroup_object(), wire);
+
+        // Re-forwarding on a fetch stream keeps every field.
+        let fetched = Object::from_fetch(7, object.to_fetch_object()).unwrap();
+        assert_eq!(fetched, object);
+
+        let end = SubgroupObject {
+            object_id: 6,
+            extension_headers: Bytes::new(),
+            object_status: Some(0x3),
+            payload: Bytes::new(),
+        };
+        let end = Object::from_subgroup(&header, 4, end).unwrap();
+        assert_eq!(end.status, ObjectStatus::EndOfGroup);
+        assert_eq!(end.to_fetch_object().object_status, Some(0x3));
+    }
+
+    #[test]
+    fn object_keys_order_as_fetch_streams() {
+        let key = |group, subgroup, object| ObjectKey {
+            track: 1,
+            group,
+            subgroup,
+            object,
+        };
+        let mut keys = vec![key(1, 0, 0), key(0, 1, 1), key(0, 0, 2), key(0, 0, 0)];
+        keys.sort();
+        assert_eq!(
+            keys,
+            [key(0, 0, 0), key(0, 1, 1), key(0, 0, 2), key(1, 0, 0)]
+        );
+
+        let range = ObjectKey::range(1, &Location::new(0, 1), &Location::new(0, 2));
+        let within: Vec<_> = keys.iter().filter(|k| range.contains(k)).collect();
+        assert_eq!(within, [&key(0, 1, 1), &key(0, 0, 2)]);
+        assert!(!range.contains(&ObjectKey {
+            track: 2,
+            ..key(0, 1, 1)
+        }));
+        assert!(key(0, 1, 1).is_within(&Location::new(0, 1), &Location::new(0, 2)));
+
+        let unique: std::collections::HashSet<_> =
+            [key(0, 0, 0), key(0, 0, 0).untracked(), key(0, 0, 0)].into();
+        assert_eq!(unique.len(), 2);
+    }
 }
@@ -1,11 +1,24 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
 use async_trait::async_trait;
 use bytes::Bytes;
-use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 
 pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
 
-pub trait UniStream: AsyncRead + AsyncWrite + Unpin + Send {}
-impl<T> UniStream for T where T: AsyncRead + AsyncWrite + Unpin + Send {}
+pub trait UniStream: AsyncRead + AsyncWrite + Unpin + Send {
+    /// Abruptly terminate the sending part of the stream (RESET_STREAM) with
+    /// the given application error code.
+    fn reset(&mut self, code: u64);
+
+    /// Whether the stream writes several buffers in one call to
+    /// `poll_write_vectored`. Writers use this to choose between vectored
+    /// writes and writing buffers one after another.
+    fn supports_vectored_writes(&self) -> bool {
+        self.is_write_vectored()
+    }
+}
 
 pub trait BiStream: Send {
     type Reader: AsyncRead + Unpin + Send;
@@ -14,6 +27,40 @@ pub trait BiStream: Send {
     fn split(self) -> (Self::Reader, Self::Writer);
 }
 
+/// What the underlying connection supports, letting the session pick
+/// defaults up front instead of failing at send time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Whether datagrams can be sent and received.
+    pub datagrams: bool,
+    /// Maximum number of streams the peer allows to be opened, if known.
+    pub max_streams: Option<u64>,
+    /// Largest datagram payload that can be sent, if known.
+    pub max_datagram_size: Option<usize>,
+    /// Whether the connection was established with 0-RTT.
+    pub zero_rtt: bool,
+}
+
+impl Default for Capabilities {
+    /// Datagram support without known limits, as assumed by transports
+    /// that do not report capabilities.
+    fn default() -> Self {
+        Self {
+            datagrams: true,
+            max_streams: None,
+            max_datagram_size: None,
+            zero_rtt: false,
+        }
+    }
+}
+
+impl Capabilities {
+    /// Whether a datagram carrying `len` bytes can be sent.
+    pub fn fits_datagram(&self, len: usize) -> bool {
+        self.datagrams && self.max_datagram_size.is_none_or(|max| len <= max)
+    }
+}
+
 #[async_trait]
 pub trait Transport: Send + Sync {
     type Uni: UniStream;
@@ -26,4 +73,128 @@ pub trait Transport: Send + Sync {
     async fn accept_bi_stream(&mut self) -> Result<Self::Bi, BoxError>;
 
     async fn send_datagram(&mut self, data: Bytes) -> Result<(), BoxError>;
+
+    /// The next datagram received, or `None` once no more can arrive. The
+    /// default never yields any, for transports without datagrams.
+    async fn recv_datagram(&mut self) -> Option<Bytes> {
+        None
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    /// Path of the WebTransport URL the session was established on, e.g.
+    /// `/chat`. `None` on raw QUIC, where CLIENT_SETUP carries the path in
+    /// its PATH parameter instead.
+    fn url_path(&self) -> Option<&str> {
+        None
+    }
+
+    /// Close the connection with a session termination `code`, as with a
+    /// QUIC CONNECTION_CLOSE. The default does nothing, leaving the
+    /// connection to be closed once dropped.
+    fn close(&self, _code: u64, _reason: &str) {}
+}
+
+/// Transport of a session whose streams are carried by the application,
+/// e.g. across a language boundary. Opening or accepting streams and
+/// sending datagrams fail: the application passes what it reads to the
+/// session and writes what the session queues itself.
+pub struct AppTransport;
+
+/// The streams of an [`AppTransport`], which cannot exist.
+pub enum NoStream {}
+
+impl AsyncRead for NoStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match *self {}
+    }
+}
+
+impl AsyncWrite for NoStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match *self {}
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match *self {}
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match *self {}
+    }
+}
+
+impl UniStream for NoStream {
+    fn reset(&mut self, _code: u64) {
+        match *self {}
+    }
+}
+
+impl BiStream for NoStream {
+    type Reader = NoStream;
+    type Writer = NoStream;
+
+    fn split(self) -> (NoStream, NoStream) {
+        match self {}
+    }
+}
+
+fn handled_by_application() -> BoxError {
+    "streams are handled by the application".into()
+}
+
+#[async_trait]
+impl Transport for AppTransport {
+    type Uni = NoStream;
+    type Bi = NoStream;
+
+    async fn open_uni_stream(&mut self) -> Result<NoStream, BoxError> {
+        Err(handled_by_application())
+    }
+
+    async fn accept_uni_stream(&mut self) -> Result<NoStream, BoxError> {
+        Err(handled_by_application())
+    }
+
+    async fn open_bi_stream(&mut self) -> Result<NoStream, BoxError> {
+        Err(handled_by_application())
+    }
+
+    async fn accept_bi_stream(&mut self) -> Result<NoStream, BoxError> {
+        Err(handled_by_application())
+    }
+
+    async fn send_datagram(&mut self, _data: Bytes) -> Result<(), BoxError> {
+        Err(handled_by_application())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn datagram_size_limit() {
+        let capabilities = Capabilities {
+            max_datagram_size: Some(1200),
+            ..Capabilities::default()
+        };
+        assert!(capabilities.fits_datagram(1200));
+        assert!(!capabilities.fits_datagram(1201));
+        let none = Capabilities {
+            datagrams: false,
+            ..Capabilities::default()
+        };
+        assert!(!none.fits_datagram(1));
+    }
 }
@@ -0,0 +1,158 @@
+//! Interoperability scenario against scripted peers. External endpoints are
+//! reached over QUIC by the `interop_client` example of `moqt-native`.
+
+use bytes::BytesMut;
+use moqt_transport::codec::{ControlMessageCodec, DRAFT_11, DRAFT_12, WireVersion};
+use moqt_transport::interop::{Outcome, Scenario, Step};
+use moqt_transport::message::{
+    AnnounceOk, ControlMessage, FetchError, FetchOk, PublishOk, ServerSetup, SubscribeOk,
+};
+use moqt_transport::mock::MockTransport;
+use moqt_transport::model::Location;
+use moqt_transport::transport::{BiStream, Transport};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Answers every request of the scenario as `answer` decides, after
+/// selecting `version`.
+async fn scripted_peer(
+    mut transport: MockTransport,
+    version: u32,
+    answer: fn(ControlMessage) -> ControlMessage,
+) {
+    let (mut reader, mut writer) = transport.accept_bi_stream().await.unwrap().split();
+    let mut codec = ControlMessageCodec::new();
+    let mut buf = BytesMut::new();
+    loop {
+        let msg = loop {
+            if let Some(msg) = codec.decode(&mut buf).unwrap() {
+                break msg;
+            }
+            if reader.read_buf(&mut buf).await.unwrap() == 0 {
+                return;
+            }
+        };
+        let mut out = BytesMut::new();
+        match msg {
+            ControlMessage::ClientSetup(_) => {
+                let setup = ServerSetup::accept(version).with_max_request_id(64);
+                codec
+                    .encode(ControlMessage::ServerSetup(setup), &mut out)
+                    .unwrap();
+                codec.set_version(WireVersion::negotiated(version).unwrap());
+            }
+            request => codec.encode(answer(request), &mut out).unwrap(),
+        }
+        writer.write_all(&out).await.unwrap();
+    }
+}
+
+fn accept(request: ControlMessage) -> ControlMessage {
+    let request_id = request.request_id().unwrap();
+    match request {
+        ControlMessage::Announce(_) => ControlMessage::AnnounceOk(AnnounceOk { request_id }),
+        ControlMessage::Subscribe(_) => ControlMessage::SubscribeOk(SubscribeOk {
+            request_id,
+            track_alias: request_id,
+            expires: 0,
+            group_order: 1,
+            content_exists: false,
+            largest_location: None,
+            parameters: Vec::new(),
+        }),
+        ControlMessage::Publish(_) => ControlMessage::PublishOk(PublishOk {
+            request_id,
+            forward: 1,
+            subscriber_priority: 128,
+            group_order: 1,
+            filter_type: 0x2,
+            start: None,
+            end_group: None,
+            parameters: Vec::new(),
+        }),
+        ControlMessage::Fetch(_) => ControlMessage::FetchOk(FetchOk {
+            request_id,
+            group_order: 1,
+            end_of_track: false,
+            end_location: Location {
+                group: 0,
+                object: 0,
+            },
+            parameters: Vec::new(),
+        }),
+        other => panic!("unexpected request: {other:?}"),
+    }
+}
+
+fn reject_fetch(request: ControlMessage) -> ControlMessage {
+    match request {
+        ControlMessage::Fetch(fetch) => ControlMessage::FetchError(FetchError {
+            request_id: fetch.request_id,
+            error_code: 0x4,
+            error_reason: "no objects".into(),
+        }),
+        other => accept(other),
+    }
+}
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn scripted_peer_passes_every_step() {
+    runtime().block_on(async {
+        let (mut client, server) = MockTransport::pair();
+        tokio::spawn(scripted_peer(server, DRAFT_12, accept));
+        let report = Scenario::default().run(&mut client).await;
+        assert!(report.passed());
+        assert_eq!(report.version, Some(DRAFT_12));
+        for step in Step::ALL {
+            assert_eq!(report.outcome(step), Some(&Outcome::Pass));
+        }
+    });
+}
+
+#[test]
+fn draft11_peer_skips_publish_and_reports_rejection() {
+    runtime().block_on(async {
+        let (mut client, server) = MockTransport::pair();
+        tokio::spawn(scripted_peer(server, DRAFT_11, reject_fetch));
+        let report = Scenario::default().run(&mut client).await;
+        assert!(!report.passed());
+        assert_eq!(report.version, Some(DRAFT_11));
+        assert_eq!(report.outcome(Step::Subscribe), Some(&Outcome::Pass));
+        assert!(matches!(
+            report.outcome(Step::Publish),
+            Some(Outcome::Skipped(_))
+        ));
+        assert_eq!(
+            report.outcome(Step::Fetch),
+            Some(&Outcome::Fail("FETCH_ERROR 0x4: no objects".into()))
+        );
+    });
+}
+
+#[test]
+fn silent_peer_times_out() {
+    runtime().block_on(async {
+        let (mut client, mut server) = MockTransport::pair();
+        tokio::spawn(async move {
+            let _stream = server.accept_bi_stream().await.unwrap();
+            std::future::pending::<()>().await;
+        });
+        let scenario = Scenario::default().with_timeout(std::time::Duration::from_millis(10));
+        let report = scenario.run(&mut client).await;
+        assert!(matches!(
+            report.outcome(Step::Setup),
+            Some(Outcome::Fail(_))
+        ));
+        assert!(matches!(
+            report.outcome(Step::Fetch),
+            Some(Outcome::Skipped(_))
+        ));
+    });
+}
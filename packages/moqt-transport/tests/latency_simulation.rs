@@ -0,0 +1,487 @@
+//! Glass-to-glass latency of an audio and a video track between two
+//! sessions over [`MockTransport`], on a paused clock.
+//!
+//! Objects take the send path a publisher assembles from the library: each
+//! track is published on a [`Broadcast`], attached with the delivery
+//! parameters of its subscription (the policed subscriber priority of the
+//! SUBSCRIBE and the publisher's delivery timeout), and the session's
+//! objects leave through a [`Shaper`] pacing them at the path's rate in
+//! priority order. Each group is sent on its own subgroup stream over a
+//! simulated path adding propagation delay, jitter and loss; a lost packet
+//! is sent again, taking its share of the path's rate, and holds back the
+//! rest of its stream by a retransmission round trip.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use moqt_transport::bandwidth::{BandwidthLimits, Rate, Release, Shaper};
+use moqt_transport::broadcast::Broadcast;
+use moqt_transport::data::{SubgroupHeader, SubgroupId};
+use moqt_transport::message::{ControlMessage, SubscribeOk};
+use moqt_transport::mock::{MockTransport, MockUniStream};
+use moqt_transport::model::ObjectStatus;
+use moqt_transport::publish::DeliveryParams;
+use moqt_transport::request::SubscribeRequest;
+use moqt_transport::scheduler::{GROUP_ORDER_ASCENDING, Priority, resolve_group_order};
+use moqt_transport::session::Session;
+use moqt_transport::subgroup::SubgroupReader;
+use moqt_transport::track::{Object, ObjectStream, TrackPublisher};
+use moqt_transport::transport::Transport;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Notify, mpsc};
+use tokio::time::Instant;
+
+const PACKET_SIZE: usize = 1_200;
+/// Objects each session queues per track before the publisher waits.
+const QUEUE: usize = 2;
+/// Objects of a track the sender keeps ready ahead of the path.
+const WINDOW: usize = 2;
+
+#[derive(Clone, Copy)]
+struct Media {
+    name: &'static str,
+    alias: u64,
+    frames: u64,
+    interval: Duration,
+    group_frames: u64,
+    keyframe_size: usize,
+    frame_size: usize,
+}
+
+/// 30 fps with a keyframe every second, about 50 KB/s.
+const VIDEO: Media = Media {
+    name: "video",
+    alias: 1,
+    frames: 150,
+    interval: Duration::from_millis(33),
+    group_frames: 30,
+    keyframe_size: 6_000,
+    frame_size: 1_500,
+};
+
+/// 20 ms frames, 8 KB/s.
+const AUDIO: Media = Media {
+    name: "audio",
+    alias: 2,
+    frames: 250,
+    interval: Duration::from_millis(20),
+    group_frames: 10,
+    keyframe_size: 160,
+    frame_size: 160,
+};
+
+#[derive(Clone, Copy)]
+struct Profile {
+    /// Bytes per second.
+    bandwidth: u64,
+    one_way: Duration,
+    jitter: Duration,
+    /// Probability for each packet to be lost and retransmitted.
+    loss: f64,
+}
+
+impl Profile {
+    fn transmission(&self, size: usize) -> Duration {
+        Duration::from_secs_f64(size as f64 / self.bandwidth as f64)
+    }
+}
+
+/// Deterministic xorshift generator, so every run sees the same path.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// What the sender releases: objects, and the retransmission of packets
+/// the path lost, which take rate from the objects queued after them.
+enum Item {
+    Object(Object),
+    Retransmission,
+}
+
+/// The current group's stream of a track on the simulated path.
+struct PathStream {
+    group_id: u64,
+    tx: mpsc::UnboundedSender<(Instant, Bytes)>,
+    /// Arrival of the last byte sent, which later bytes cannot overtake.
+    last: Instant,
+}
+
+/// Write every buffer to `stream` when it arrives, then finish it.
+fn deliver(mut stream: MockUniStream) -> mpsc::UnboundedSender<(Instant, Bytes)> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<(Instant, Bytes)>();
+    tokio::spawn(async move {
+        while let Some((at, buf)) = rx.recv().await {
+            tokio::time::sleep_until(at).await;
+            stream.write_all(&buf).await.unwrap();
+        }
+        stream.shutdown().await.unwrap();
+    });
+    tx
+}
+
+struct Track {
+    request_id: u64,
+    delivery: DeliveryParams,
+    objects: ObjectStream,
+    /// Objects pushed to the shaper and not released yet.
+    ready: usize,
+}
+
+/// Latencies of the frames a track delivered, and how many objects its
+/// delivery timeout dropped.
+struct Outcome {
+    latencies: Vec<Duration>,
+    expired: u64,
+}
+
+/// Publish `tracks` with the given subscriber priorities to a subscriber
+/// `profile` away, dropping objects that wait longer than
+/// `delivery_timeout` for room in their queue.
+fn run(
+    profile: Profile,
+    delivery_timeout: Option<Duration>,
+    tracks: &[(Media, u8)],
+) -> Vec<Outcome> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .start_paused(true)
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let (a, b) = MockTransport::pair();
+        let (client, mut client_rx) = Session::new(Arc::new(a));
+        let (server, _server_rx) = Session::new(Arc::new(b));
+        let client = Arc::new(client);
+        let count = tracks.len() as u64;
+        client.track_manager.handle_max_request_id(count).unwrap();
+        server.advertise_max_request_id(count).unwrap();
+
+        let start = Instant::now();
+        let ready = Arc::new(Notify::new());
+        let publishing = Arc::new(AtomicUsize::new(tracks.len()));
+        let mut senders = Vec::new();
+        let mut collectors = Vec::new();
+        let mut attachments = Vec::new();
+        for &(media, subscriber_priority) in tracks {
+            let mut subscription = client
+                .subscribe(
+                    SubscribeRequest::new(0, media.name)
+                        .with_subscriber_priority(subscriber_priority),
+                )
+                .await
+                .unwrap();
+            let mut msg = client_rx.recv().await.unwrap();
+            server.check_incoming(&mut msg).unwrap();
+            let ControlMessage::Subscribe(subscribe) = msg else {
+                panic!("expected SUBSCRIBE");
+            };
+            let ok = SubscribeOk {
+                request_id: subscribe.request_id,
+                track_alias: media.alias,
+                expires: 0,
+                group_order: GROUP_ORDER_ASCENDING,
+                content_exists: false,
+                largest_location: None,
+                parameters: Vec::new(),
+            };
+            client.track_manager.handle_subscribe_ok(&ok).unwrap();
+            assert!(subscription.is_established());
+
+            // What the publisher agreed to for this subscription.
+            let delivery = DeliveryParams {
+                forward: subscribe.forward == 1,
+                subscriber_priority: subscribe.subscriber_priority,
+                group_order: resolve_group_order(subscribe.group_order, GROUP_ORDER_ASCENDING)
+                    .unwrap(),
+                filter: subscribe.filter().clone(),
+                largest: None,
+                delivery_timeout,
+            };
+            let mut attached = TrackPublisher::new(media.alias);
+            attached.set_delivery(delivery.clone());
+            let broadcast = Arc::new(Broadcast::new(QUEUE));
+            let (id, objects) = broadcast.attach(attached);
+            attachments.push((broadcast.clone(), id));
+            senders.push(Track {
+                request_id: subscribe.request_id,
+                delivery,
+                objects,
+                ready: 0,
+            });
+
+            let (ready, publishing) = (ready.clone(), publishing.clone());
+            tokio::spawn(async move {
+                let mut source = TrackPublisher::new(media.alias);
+                for frame in 0..media.frames {
+                    // The payload carries its capture time.
+                    let captured = start + media.interval * frame as u32;
+                    tokio::time::sleep_until(captured).await;
+                    let keyframe = frame % media.group_frames == 0;
+                    let size = if keyframe {
+                        media.keyframe_size
+                    } else {
+                        media.frame_size
+                    };
+                    let mut payload = BytesMut::with_capacity(size);
+                    payload.put_u64((captured - start).as_micros() as u64);
+                    payload.resize(size, 0);
+                    for object in source.push_frame(keyframe, payload.freeze()) {
+                        broadcast.publish(&object).await;
+                        ready.notify_one();
+                    }
+                }
+                publishing.fetch_sub(1, Ordering::SeqCst);
+                ready.notify_one();
+            });
+
+            collectors.push(tokio::spawn(async move {
+                let mut latencies = Vec::new();
+                while let Some(object) = subscription.recv().await {
+                    let object = object.unwrap();
+                    if object.status != ObjectStatus::Normal {
+                        continue;
+                    }
+                    let micros = u64::from_be_bytes(object.payload[..8].try_into().unwrap());
+                    latencies.push(Instant::now() - (start + Duration::from_micros(micros)));
+                }
+                latencies
+            }));
+        }
+
+        // Data streams travel over a separate pair so the sessions keep
+        // sole ownership of theirs.
+        let (mut publisher_end, mut subscriber_end) = MockTransport::pair();
+        let receiver = client.clone();
+        tokio::spawn(async move {
+            while let Ok(stream) = subscriber_end.accept_uni_stream().await {
+                let receiver = receiver.clone();
+                tokio::spawn(async move {
+                    SubgroupReader::new(stream)
+                        .deliver(&receiver.track_manager)
+                        .await
+                        .unwrap();
+                });
+            }
+        });
+
+        let limits = BandwidthLimits::default()
+            .with_session_rate(Rate::new(profile.bandwidth).with_burst(PACKET_SIZE as u64));
+        let mut shaper = Shaper::new(limits, Instant::now());
+        let mut streams: HashMap<u64, PathStream> = HashMap::new();
+        let mut rng = Rng(0x9e37_79b9);
+        loop {
+            for track in &mut senders {
+                while track.ready < WINDOW {
+                    let Some(Ok(object)) = track.objects.try_recv() else {
+                        break;
+                    };
+                    let priority = track.delivery.priority(&object.metadata);
+                    let size = object.payload.len();
+                    shaper.push(
+                        track.request_id,
+                        priority,
+                        Item::Object(object),
+                        size,
+                        Instant::now(),
+                    );
+                    track.ready += 1;
+                }
+            }
+            let (request_id, object) = match shaper.pop(Instant::now()) {
+                Release::Send {
+                    request_id,
+                    item: Item::Object(object),
+                } => (request_id, object),
+                Release::Send { .. } => continue,
+                Release::Wait(at) => {
+                    tokio::time::sleep_until(at).await;
+                    continue;
+                }
+                Release::Idle => {
+                    if publishing.load(Ordering::SeqCst) == 0 {
+                        break;
+                    }
+                    ready.notified().await;
+                    continue;
+                }
+                Release::Dropped { .. } => unreachable!("no subscription cap"),
+            };
+            let track = senders
+                .iter_mut()
+                .find(|t| t.request_id == request_id)
+                .unwrap();
+            track.ready -= 1;
+
+            let meta = &object.metadata;
+            let mut buf = BytesMut::new();
+            let current = streams.get(&meta.track_alias);
+            if current.is_none_or(|s| s.group_id != meta.group_id) {
+                let stream = publisher_end.open_uni_stream().await.unwrap();
+                SubgroupHeader {
+                    track_alias: meta.track_alias,
+                    group_id: meta.group_id,
+                    subgroup_id: SubgroupId::Explicit(meta.subgroup_id),
+                    publisher_priority: meta.publisher_priority,
+                    extensions_present: false,
+                    end_of_group: false,
+                }
+                .encode(&mut buf)
+                .unwrap();
+                // Replacing the previous group's stream finishes it.
+                streams.insert(
+                    meta.track_alias,
+                    PathStream {
+                        group_id: meta.group_id,
+                        tx: deliver(stream),
+                        last: Instant::now(),
+                    },
+                );
+            }
+            object.to_subgroup_object().encode(&mut buf, false).unwrap();
+
+            let now = Instant::now();
+            let packets = buf.len().div_ceil(PACKET_SIZE);
+            let lost = (0..packets).filter(|_| rng.next() < profile.loss).count();
+            let mut at = now
+                + profile.transmission(buf.len())
+                + profile.one_way
+                + profile.jitter.mul_f64(rng.next());
+            if lost > 0 {
+                at += profile.one_way * 3;
+                let priority = Priority {
+                    subscriber_priority: 0,
+                    publisher_priority: 0,
+                    group_id: 0,
+                    group_order: GROUP_ORDER_ASCENDING,
+                    subgroup_id: 0,
+                };
+                shaper.push(
+                    request_id,
+                    priority,
+                    Item::Retransmission,
+                    lost * PACKET_SIZE,
+                    now,
+                );
+            }
+            let stream = streams.get_mut(&object.metadata.track_alias).unwrap();
+            stream.last = stream.last.max(at);
+            stream.tx.send((stream.last, buf.freeze())).unwrap();
+        }
+        drop(streams);
+
+        // Let the path drain before ending the subscriptions.
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        let mut outcomes = Vec::new();
+        for ((track, collector), (broadcast, id)) in
+            senders.iter().zip(collectors).zip(&attachments)
+        {
+            client
+                .track_manager
+                .end_subscription(track.request_id, None);
+            outcomes.push(Outcome {
+                latencies: collector.await.unwrap(),
+                expired: broadcast.stats(*id).unwrap().expired,
+            });
+        }
+        outcomes
+    })
+}
+
+fn p95(latencies: &[Duration]) -> Duration {
+    let mut latencies = latencies.to_vec();
+    latencies.sort();
+    latencies[(latencies.len() * 95).div_ceil(100) - 1]
+}
+
+/// 500 kbit/s.
+const CLEAN: Profile = Profile {
+    bandwidth: 62_500,
+    one_way: Duration::from_millis(20),
+    jitter: Duration::ZERO,
+    loss: 0.0,
+};
+
+#[test]
+fn clean_path_latency() {
+    let [video] = &run(CLEAN, None, &[(VIDEO, 128)])[..] else {
+        unreachable!()
+    };
+    assert_eq!(video.latencies.len(), VIDEO.frames as usize);
+    assert!(video.latencies.iter().all(|l| *l >= CLEAN.one_way));
+    // Propagation plus serialization of a keyframe.
+    let p95 = p95(&video.latencies);
+    let bound = CLEAN.one_way + CLEAN.transmission(VIDEO.keyframe_size + PACKET_SIZE);
+    assert!(p95 <= bound, "p95 {p95:?}");
+}
+
+#[test]
+fn priority_keeps_audio_ahead_of_video() {
+    let profile = Profile {
+        jitter: Duration::from_millis(10),
+        ..CLEAN
+    };
+    let outcome = |audio, video| {
+        let [audio, video] = run(profile, None, &[(AUDIO, audio), (VIDEO, video)])
+            .try_into()
+            .ok()
+            .unwrap();
+        (audio, video)
+    };
+    let (shared_audio, _) = outcome(128, 128);
+    let (audio, video) = outcome(0, 200);
+    assert_eq!(audio.latencies.len(), AUDIO.frames as usize);
+    assert_eq!(video.latencies.len(), VIDEO.frames as usize);
+
+    // Audio only waits for the object being sent when it is captured.
+    let (first, shared) = (p95(&audio.latencies), p95(&shared_audio.latencies));
+    assert!(
+        first < shared,
+        "audio p95 {first:?} with priority, {shared:?} without"
+    );
+    let bound = profile.one_way + profile.jitter + profile.transmission(2 * PACKET_SIZE);
+    assert!(first <= bound, "audio p95 {first:?}");
+}
+
+#[test]
+fn delivery_timeout_bounds_latency_under_loss() {
+    const TIMEOUT: Duration = Duration::from_millis(60);
+    let lossy = Profile {
+        jitter: Duration::from_millis(10),
+        loss: 0.25,
+        ..CLEAN
+    };
+
+    // Retransmissions leave the path short of rate: without a timeout
+    // video queues up, with one the objects that wait too long are dropped.
+    let [unbounded] = &run(lossy, None, &[(VIDEO, 128)])[..] else {
+        unreachable!()
+    };
+    let [bounded] = &run(lossy, Some(TIMEOUT), &[(VIDEO, 128)])[..] else {
+        unreachable!()
+    };
+    assert_eq!(unbounded.expired, 0);
+    assert_eq!(unbounded.latencies.len(), VIDEO.frames as usize);
+    assert!(bounded.expired > 0);
+    let (late, timely) = (p95(&unbounded.latencies), p95(&bounded.latencies));
+    assert!(
+        timely < late,
+        "p95 {timely:?} with timeout, {late:?} without"
+    );
+
+    // On a path with rate to spare the timeout drops nothing.
+    let [clean] = &run(CLEAN, Some(TIMEOUT), &[(VIDEO, 128)])[..] else {
+        unreachable!()
+    };
+    assert_eq!(clean.expired, 0);
+    assert_eq!(clean.latencies.len(), VIDEO.frames as usize);
+}
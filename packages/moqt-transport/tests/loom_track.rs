@@ -0,0 +1,122 @@
+//! Model checked concurrency tests of the track manager.
+//!
+//! Run with `RUSTFLAGS="--cfg loom" cargo test -p moqt-transport --release
+//! --test loom_track`.
+#![cfg(loom)]
+
+use loom::sync::Arc;
+use loom::thread;
+use moqt_transport::error::Error;
+use moqt_transport::message::SubscribeOk;
+use moqt_transport::mock::ObjectBuilder;
+use moqt_transport::track::{Object, TrackManager};
+
+fn subscribe_ok(request_id: u64, track_alias: u64) -> SubscribeOk {
+    SubscribeOk {
+        request_id,
+        track_alias,
+        expires: 0,
+        group_order: 1,
+        content_exists: false,
+        largest_location: None,
+        parameters: Vec::new(),
+    }
+}
+
+fn object(track_alias: u64) -> Object {
+    ObjectBuilder::new(0, 0)
+        .with_track_alias(track_alias)
+        .build()
+}
+
+#[test]
+fn request_ids_are_unique_and_bounded() {
+    loom::model(|| {
+        let manager = Arc::new(TrackManager::default());
+        manager.handle_max_request_id(1).unwrap();
+
+        let other = manager.clone();
+        let t = thread::spawn(move || other.new_request_id());
+        let a = manager.new_request_id();
+        let b = t.join().unwrap();
+
+        // Exactly one of the two requests fits under MAX_REQUEST_ID.
+        match (a, b) {
+            (Ok(0), Err(Error::TooManyRequests)) | (Err(Error::TooManyRequests), Ok(0)) => {}
+            other => panic!("unexpected request ids: {other:?}"),
+        }
+    });
+}
+
+#[test]
+fn concurrent_subscribes_register_requests() {
+    loom::model(|| {
+        let manager = Arc::new(TrackManager::default());
+        manager.handle_max_request_id(2).unwrap();
+
+        let other = manager.clone();
+        let t = thread::spawn(move || {
+            let (id, stream) = other.subscribe_track("video".to_string()).unwrap();
+            other.handle_subscribe_ok(&subscribe_ok(id, 1)).unwrap();
+            stream
+        });
+        let (id, _audio) = manager.subscribe_track("audio".to_string()).unwrap();
+        manager.handle_subscribe_ok(&subscribe_ok(id, 2)).unwrap();
+        let _video = t.join().unwrap();
+
+        assert_eq!(manager.resolve_alias(1).unwrap().name(), "video");
+        assert_eq!(manager.resolve_alias(2).unwrap().name(), "audio");
+    });
+}
+
+#[test]
+fn duplicate_alias_races_fail_once() {
+    loom::model(|| {
+        let manager = Arc::new(TrackManager::default());
+        manager.handle_max_request_id(2).unwrap();
+        let (a, _audio) = manager.subscribe_track("audio".to_string()).unwrap();
+        let (b, _video) = manager.subscribe_track("video".to_string()).unwrap();
+
+        let other = manager.clone();
+        let t = thread::spawn(move || other.handle_subscribe_ok(&subscribe_ok(b, 1)));
+        let first = manager.handle_subscribe_ok(&subscribe_ok(a, 1));
+        let second = t.join().unwrap();
+
+        assert_eq!(first.is_ok() as u8 + second.is_ok() as u8, 1);
+        let name = manager.resolve_alias(1).unwrap().name().to_string();
+        assert_eq!(name == "audio", first.is_ok());
+    });
+}
+
+#[test]
+fn delivery_races_alias_assignment() {
+    loom::model(|| {
+        let manager = Arc::new(TrackManager::default());
+        manager.handle_max_request_id(1).unwrap();
+        let (id, _stream) = manager.subscribe_track("video".to_string()).unwrap();
+
+        let other = manager.clone();
+        let t = thread::spawn(move || other.deliver(object(3)));
+        manager.handle_subscribe_ok(&subscribe_ok(id, 3)).unwrap();
+
+        // An object racing SUBSCRIBE_OK is either dropped as unknown or
+        // delivered, never lost after the alias is visible.
+        let delivered = t.join().unwrap();
+        assert!(delivered <= 1);
+        assert_eq!(manager.deliver(object(3)), 1);
+    });
+}
+
+#[test]
+fn concurrent_max_request_ids_keep_the_largest() {
+    loom::model(|| {
+        let manager = Arc::new(TrackManager::default());
+
+        let other = manager.clone();
+        let t = thread::spawn(move || other.handle_max_request_id(7));
+        // Fails if it arrives last, but never lowers the larger maximum.
+        let _ = manager.handle_max_request_id(5);
+        assert!(t.join().unwrap().is_ok());
+        assert_eq!(manager.max_request_id(), 7);
+    });
+}
@@ -1,5 +1,5 @@
 use bytes::Bytes;
-use moqt_transport::mock::MockTransport;
+use moqt_transport::mock::{ConnectionClosed, MockTransport};
 use moqt_transport::transport::{BiStream, Transport};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
@@ -33,8 +33,8 @@ fn bidirectional_stream_roundtrip() {
     rt.block_on(async {
         let (mut a, mut b) = MockTransport::pair();
 
-        let mut client = a.open_bi_stream().await.unwrap();
-        let mut server = b.accept_bi_stream().await.unwrap();
+        let client = a.open_bi_stream().await.unwrap();
+        let server = b.accept_bi_stream().await.unwrap();
 
         let (mut cr, mut cw) = client.split();
         let (mut sr, mut sw) = server.split();
@@ -66,3 +66,43 @@ fn datagram_send_recv() {
         assert_eq!(d, Bytes::from_static(b"data"));
     });
 }
+
+#[test]
+fn close_fails_pending_accepts_and_reads() {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let (mut a, mut b) = MockTransport::pair();
+        let _send = a.open_uni_stream().await.unwrap();
+        let mut recv = b.accept_uni_stream().await.unwrap();
+
+        let read = tokio::spawn(async move {
+            let mut buf = Vec::new();
+            recv.read_to_end(&mut buf).await
+        });
+        let accept = tokio::spawn(async move { b.accept_uni_stream().await.map(|_| ()) });
+        tokio::task::yield_now().await;
+        a.close(0x3, "protocol violation");
+
+        let expected = ConnectionClosed {
+            code: 0x3,
+            reason: "protocol violation".into(),
+        };
+        let e = read.await.unwrap().unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::ConnectionAborted);
+        assert_eq!(
+            e.into_inner().unwrap().downcast_ref::<ConnectionClosed>(),
+            Some(&expected)
+        );
+        let e = accept.await.unwrap().unwrap_err();
+        assert_eq!(e.downcast_ref::<ConnectionClosed>(), Some(&expected));
+
+        // Later operations fail the same way, keeping the first reason.
+        a.close(0x0, "again");
+        assert_eq!(a.close_reason(), Some(expected.clone()));
+        let e = a.open_uni_stream().await.err().unwrap();
+        assert_eq!(e.downcast_ref::<ConnectionClosed>(), Some(&expected));
+    });
+}
@@ -0,0 +1,185 @@
+//! Audio and video tracks competing for a link that cannot carry both, on
+//! a paused clock. Objects are published through [`TrackPublisher`]s,
+//! ordered by the [`Scheduler`] on the priority the publisher derives for
+//! them and sent as datagrams through a [`ThrottledWriter`]. Latencies are
+//! measured on the receiving side from the capture time carried in each
+//! payload.
+
+use std::time::Duration;
+
+use bytes::{BufMut, BytesMut};
+use moqt_transport::data::ObjectDatagram;
+use moqt_transport::mock::{MockTransport, ThrottledWriter};
+use moqt_transport::model::ObjectStatus;
+use moqt_transport::scheduler::{Priority, Scheduler};
+use moqt_transport::track::{Object, TrackPublisher};
+use moqt_transport::transport::Transport;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+const BANDWIDTH: u64 = 20_000;
+const AUDIO_ALIAS: u64 = 1;
+const AUDIO_SIZE: usize = 160;
+const AUDIO_FRAMES: u64 = 50;
+const AUDIO_INTERVAL: Duration = Duration::from_millis(20);
+const VIDEO_ALIAS: u64 = 2;
+const VIDEO_SIZE: usize = 1_500;
+const VIDEO_FRAMES: u64 = 30;
+const VIDEO_INTERVAL: Duration = Duration::from_millis(33);
+/// Bytes of a datagram besides the payload, an upper bound.
+const DATAGRAM_OVERHEAD: usize = 32;
+
+/// Publish `frames` frames of `size` bytes every `interval`, sending each
+/// object with the priority `publisher` gives it.
+async fn publish(
+    mut publisher: TrackPublisher,
+    frames: u64,
+    size: usize,
+    interval: Duration,
+    start: Instant,
+    tx: mpsc::Sender<(Priority, Object)>,
+) {
+    for frame in 0..frames {
+        tokio::time::sleep_until(start + interval * frame as u32).await;
+        // The payload carries its capture time.
+        let mut payload = BytesMut::with_capacity(size);
+        payload.put_u64((Instant::now() - start).as_micros() as u64);
+        payload.resize(size, 0);
+        for object in publisher.push_frame(frame % 10 == 0, payload.freeze()) {
+            let priority = publisher.priority(&object.metadata);
+            tx.send((priority, object)).await.unwrap();
+        }
+    }
+}
+
+/// Latencies of the audio and video objects received when both tracks
+/// share the link with the given publisher priorities.
+fn run(audio_priority: u8, video_priority: u8) -> (Vec<Duration>, Vec<Duration>) {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .start_paused(true)
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let (mut a, mut b) = MockTransport::pair();
+        let send = a.open_uni_stream().await.unwrap();
+        let mut recv = b.accept_uni_stream().await.unwrap();
+        let mut send = ThrottledWriter::new(send, BANDWIDTH);
+
+        let start = Instant::now();
+        let reader = tokio::spawn(async move {
+            let mut received = Vec::new();
+            while let Ok(len) = recv.read_u16().await {
+                let mut buf = BytesMut::zeroed(len as usize);
+                recv.read_exact(&mut buf).await.unwrap();
+                let object =
+                    Object::from_datagram(ObjectDatagram::decode(&mut buf).unwrap()).unwrap();
+                if object.status != ObjectStatus::Normal {
+                    continue;
+                }
+                let micros = u64::from_be_bytes(object.payload[..8].try_into().unwrap());
+                let captured = start + Duration::from_micros(micros);
+                received.push((object.metadata, Instant::now() - captured));
+            }
+            received
+        });
+
+        let (tx, mut rx) = mpsc::channel(1024);
+        let mut audio = TrackPublisher::new(AUDIO_ALIAS);
+        audio.set_publisher_priority(audio_priority);
+        let mut video = TrackPublisher::new(VIDEO_ALIAS);
+        video.set_publisher_priority(video_priority);
+        let audio = tokio::spawn(publish(
+            audio,
+            AUDIO_FRAMES,
+            AUDIO_SIZE,
+            AUDIO_INTERVAL,
+            start,
+            tx.clone(),
+        ));
+        let video = tokio::spawn(publish(
+            video,
+            VIDEO_FRAMES,
+            VIDEO_SIZE,
+            VIDEO_INTERVAL,
+            start,
+            tx,
+        ));
+
+        let mut scheduler = Scheduler::default();
+        loop {
+            while let Ok((priority, object)) = rx.try_recv() {
+                scheduler.push(priority, object);
+            }
+            let object: Object = match scheduler.pop() {
+                Some(object) => object,
+                None => match rx.recv().await {
+                    Some((priority, object)) => {
+                        scheduler.push(priority, object);
+                        continue;
+                    }
+                    None => break,
+                },
+            };
+            let mut datagram = BytesMut::new();
+            object.to_datagram(false).encode(&mut datagram).unwrap();
+            let mut buf = BytesMut::new();
+            buf.put_u16(datagram.len() as u16);
+            buf.extend_from_slice(&datagram);
+            send.write_all(&buf).await.unwrap();
+        }
+
+        audio.await.unwrap();
+        video.await.unwrap();
+        send.shutdown().await.unwrap();
+        drop(send);
+        let received = reader.await.unwrap();
+
+        let latencies = |alias| {
+            received
+                .iter()
+                .filter(|(metadata, _)| metadata.track_alias == alias)
+                .map(|(_, latency)| *latency)
+                .collect::<Vec<_>>()
+        };
+        (latencies(AUDIO_ALIAS), latencies(VIDEO_ALIAS))
+    })
+}
+
+fn transmission(size: usize) -> Duration {
+    Duration::from_secs_f64((size + DATAGRAM_OVERHEAD) as f64 / BANDWIDTH as f64)
+}
+
+/// Audio (publisher priority 0) and video (publisher priority 200) share
+/// a link. Audio must only ever wait for the video object already being
+/// written and an audio object queued before it, never for queued video,
+/// while video falls behind.
+#[test]
+fn audio_is_never_delayed_behind_video() {
+    let (audio, video) = run(0, 200);
+    assert_eq!(audio.len() as u64, AUDIO_FRAMES);
+    assert_eq!(video.len() as u64, VIDEO_FRAMES);
+
+    let bound = transmission(VIDEO_SIZE) + transmission(AUDIO_SIZE);
+    let audio_max = audio.iter().max().unwrap();
+    assert!(
+        *audio_max <= bound,
+        "audio waited {audio_max:?}, more than a video and an audio object"
+    );
+    // The link was congested: video queued behind audio and itself.
+    let video_max = video.iter().max().unwrap();
+    assert!(
+        *video_max > transmission(VIDEO_SIZE) * 4,
+        "video was never held back ({video_max:?})"
+    );
+}
+
+/// With equal priorities the same link queues audio behind video, so the
+/// bound above only holds because of the priorities.
+#[test]
+fn equal_priorities_delay_audio_behind_video() {
+    let (audio, _) = run(128, 128);
+    let bound = transmission(VIDEO_SIZE) + transmission(AUDIO_SIZE);
+    assert!(audio.iter().max().unwrap() > &bound);
+}
@@ -0,0 +1,230 @@
+//! End-to-end flow between two sessions over [`MockTransport`]: setup,
+//! announce, subscribe, objects on a subgroup stream and SUBSCRIBE_DONE.
+
+use std::sync::Arc;
+
+use bytes::{Bytes, BytesMut};
+use moqt_transport::auth::AuthRequest;
+use moqt_transport::codec::{ControlMessageCodec, MAX_REQUEST_ID};
+use moqt_transport::data::{SubgroupHeader, SubgroupObject};
+use moqt_transport::message::{
+    AnnounceOk, ClientSetup, ControlMessage, ServerSetup, SubscribeDone, SubscribeOk,
+};
+use moqt_transport::mock::{MockBiStream, MockStream, MockTransport};
+use moqt_transport::model::{ForwardingPreference, Parameter};
+use moqt_transport::prelude::*;
+use moqt_transport::transport::BiStream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio_util::codec::Decoder;
+
+const VERSION: u32 = 0xff00000c;
+
+/// Reads control messages off the control stream of one endpoint.
+struct ControlReader<R> {
+    reader: R,
+    codec: ControlMessageCodec,
+    buf: BytesMut,
+}
+
+impl<R: AsyncRead + Unpin> ControlReader<R> {
+    fn new(reader: R, codec: ControlMessageCodec) -> Self {
+        Self {
+            reader,
+            codec,
+            buf: BytesMut::new(),
+        }
+    }
+
+    async fn recv(&mut self) -> ControlMessage {
+        loop {
+            if let Some(msg) = self.codec.decode(&mut self.buf).unwrap() {
+                return msg;
+            }
+            let n = self.reader.read_buf(&mut self.buf).await.unwrap();
+            assert!(n > 0, "control stream closed");
+        }
+    }
+}
+
+/// A session whose outgoing control messages are written to the control
+/// stream by its control writer.
+fn start(
+    transport: MockTransport,
+    control: MockBiStream,
+) -> (Session<MockTransport>, ControlReader<MockStream>) {
+    let (reader, writer) = control.split();
+    let (session, rx) = Session::new(Arc::new(transport));
+    assert!(session.spawn_control_writer(writer, rx));
+    let reader = ControlReader::new(reader, session.control_codec());
+    (session, reader)
+}
+
+fn max_request_id_parameter(max: u64) -> Parameter {
+    Parameter::varint(MAX_REQUEST_ID, max).unwrap()
+}
+
+/// The session is the only owner of its transport, so data streams can be
+/// opened through it.
+fn transport(session: &mut Session<MockTransport>) -> &mut MockTransport {
+    Arc::get_mut(&mut session.transport).unwrap()
+}
+
+#[test]
+fn subscribe_publish_object_flow() {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let (mut a, mut b) = MockTransport::pair();
+        let control = a.open_bi_stream().await.unwrap();
+        let accepted = b.accept_bi_stream().await.unwrap();
+        let (mut client, mut client_rx) = start(a, control);
+        let (mut server, mut server_rx) = start(b, accepted);
+
+        // SETUP: each endpoint advertises how many requests it accepts.
+        let client_max = client.advertise_max_request_id(10).unwrap();
+        client
+            .send_control(ControlMessage::ClientSetup(ClientSetup {
+                supported_versions: vec![VERSION],
+                setup_parameters: vec![max_request_id_parameter(client_max.request_id)],
+            }))
+            .await
+            .unwrap();
+        let ControlMessage::ClientSetup(setup) = server_rx.recv().await else {
+            panic!("expected CLIENT_SETUP");
+        };
+        assert_eq!(setup.supported_versions, vec![VERSION]);
+        assert_eq!(setup.setup_parameters, vec![max_request_id_parameter(10)]);
+        server.track_manager.handle_max_request_id(10).unwrap();
+
+        let server_max = server.advertise_max_request_id(10).unwrap();
+        server
+            .send_control(ControlMessage::ServerSetup(ServerSetup {
+                selected_version: VERSION,
+                setup_parameters: vec![max_request_id_parameter(server_max.request_id)],
+            }))
+            .await
+            .unwrap();
+        let ControlMessage::ServerSetup(setup) = client_rx.recv().await else {
+            panic!("expected SERVER_SETUP");
+        };
+        assert_eq!(setup.selected_version, VERSION);
+        client.track_manager.handle_max_request_id(10).unwrap();
+
+        // ANNOUNCE from the publishing server.
+        let namespace = vec!["live".to_string()];
+        let announce_id = server
+            .announce(AnnounceRequest::new(namespace.clone()))
+            .await
+            .unwrap();
+        let mut msg = client_rx.recv().await;
+        client.check_incoming(&mut msg).unwrap();
+        let ControlMessage::Announce(announce) = msg else {
+            panic!("expected ANNOUNCE");
+        };
+        assert_eq!(announce.request_id, announce_id);
+        assert_eq!(announce.track_namespace, namespace);
+        client
+            .send_control(ControlMessage::AnnounceOk(AnnounceOk {
+                request_id: announce.request_id,
+            }))
+            .await
+            .unwrap();
+        let ControlMessage::AnnounceOk(ok) = server_rx.recv().await else {
+            panic!("expected ANNOUNCE_OK");
+        };
+        assert_eq!(ok.request_id, announce_id);
+        assert_eq!(server.discovery.announced(), vec![namespace]);
+
+        // SUBSCRIBE from the client, accepted under track alias 1.
+        let mut subscription = client
+            .subscribe(SubscribeRequest::new(0, "video"))
+            .await
+            .unwrap();
+        let mut msg = server_rx.recv().await;
+        server.check_incoming(&mut msg).unwrap();
+        let ControlMessage::Subscribe(subscribe) = msg else {
+            panic!("expected SUBSCRIBE");
+        };
+        server
+            .authorize(AuthRequest::Subscribe(&subscribe))
+            .await
+            .unwrap();
+        assert_eq!(subscribe.request_id, subscription.request_id());
+        assert_eq!(subscribe.track_name, "video");
+        let ok = SubscribeOk {
+            request_id: subscribe.request_id,
+            track_alias: 1,
+            expires: 0,
+            group_order: 1,
+            content_exists: false,
+            largest_location: None,
+            parameters: Vec::new(),
+        };
+        server
+            .send_control(ControlMessage::SubscribeOk(ok.clone()))
+            .await
+            .unwrap();
+        let ControlMessage::SubscribeOk(received) = client_rx.recv().await else {
+            panic!("expected SUBSCRIBE_OK");
+        };
+        assert_eq!(received, ok);
+        client.track_manager.handle_subscribe_ok(&received).unwrap();
+        assert!(subscription.is_established());
+
+        // Objects of group 0 on one subgroup stream.
+        let mut publisher = TrackPublisher::new(ok.track_alias);
+        let subgroup = publisher.begin_group().subgroup(0);
+        let sent: Vec<Object> = ["key", "delta 1", "delta 2"]
+            .into_iter()
+            .map(|payload| subgroup.object(Bytes::from(payload)))
+            .collect();
+        let header = subgroup.header(true);
+        let mut buf = BytesMut::new();
+        header.encode(&mut buf).unwrap();
+        for object in &sent {
+            object.to_subgroup_object().encode(&mut buf, false).unwrap();
+        }
+        let mut stream = transport(&mut server).open_uni_stream().await.unwrap();
+        stream.write_all(&buf).await.unwrap();
+        stream.shutdown().await.unwrap();
+
+        let mut stream = transport(&mut client).accept_uni_stream().await.unwrap();
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        let mut buf = BytesMut::from(&buf[..]);
+        let received_header = SubgroupHeader::decode(&mut buf).unwrap();
+        assert_eq!(received_header, header);
+        while !buf.is_empty() {
+            let object =
+                SubgroupObject::decode(&mut buf, received_header.extensions_present).unwrap();
+            let object = Object::from_subgroup(&received_header, object.object_id, object).unwrap();
+            assert_eq!(
+                client
+                    .track_manager
+                    .deliver_from(object, ForwardingPreference::Subgroup),
+                1
+            );
+        }
+        for expected in &sent {
+            assert_eq!(&subscription.recv().await.unwrap().unwrap(), expected);
+        }
+
+        // SUBSCRIBE_DONE once the track ended.
+        let done = SubscribeDone {
+            request_id: subscribe.request_id,
+            status_code: 0x2,
+            stream_count: 1,
+            reason: "track ended".to_string(),
+        };
+        server
+            .send_control(ControlMessage::SubscribeDone(done.clone()))
+            .await
+            .unwrap();
+        let ControlMessage::SubscribeDone(received) = client_rx.recv().await else {
+            panic!("expected SUBSCRIBE_DONE");
+        };
+        assert_eq!(received, done);
+    });
+}
@@ -0,0 +1,272 @@
+//! Decode the wire format vectors in `tests/vectors` and re-encode them
+//! byte-exactly, so any change to the wire format shows up as a failing
+//! vector. Most were generated by this codebase; those captured from other
+//! implementations name their source, and the ones this codebase is known
+//! not to read yet are marked `xfail`.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::BytesMut;
+use moqt_transport::codec::{ControlMessageCodec, VarInt};
+use moqt_transport::data::{FetchHeader, FetchObject, SubgroupHeader, SubgroupObject};
+use moqt_transport::incoming::IncomingStream;
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio_util::codec::{Decoder, Encoder};
+
+const CONTROL: &str = include_str!("vectors/control.txt");
+const DATA: &str = include_str!("vectors/data.txt");
+
+/// Named byte strings.
+type Vectors<'a> = Vec<(&'a str, Vec<u8>)>;
+
+/// Parse `name: hex` lines, skipping blank lines and `#` comments, into
+/// the vectors expected to round-trip and those marked `xfail`.
+fn parse(corpus: &str) -> (Vectors<'_>, Vectors<'_>) {
+    let mut vectors = Vec::new();
+    let mut xfail = Vec::new();
+    for line in corpus.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, hex) = line.split_once(':').expect("missing `:`");
+        let bytes = hex
+            .split_whitespace()
+            .map(|b| u8::from_str_radix(b, 16).expect("invalid hex"))
+            .collect();
+        match name.trim().strip_prefix("xfail ") {
+            Some(name) => xfail.push((name.trim(), bytes)),
+            None => vectors.push((name.trim(), bytes)),
+        }
+    }
+    (vectors, xfail)
+}
+
+fn vectors(corpus: &str) -> Vectors<'_> {
+    parse(corpus).0
+}
+
+/// Whether `bytes` decodes as a single control message re-encoding to the
+/// same bytes.
+fn control_round_trips(bytes: &[u8]) -> bool {
+    let mut codec = ControlMessageCodec::new();
+    let mut buf = BytesMut::from(bytes);
+    let Ok(Some(msg)) = codec.decode(&mut buf) else {
+        return false;
+    };
+    let mut encoded = BytesMut::new();
+    buf.is_empty() && codec.encode(msg, &mut encoded).is_ok() && encoded[..] == *bytes
+}
+
+#[test]
+fn control_vectors_round_trip() {
+    for (name, bytes) in vectors(CONTROL) {
+        let mut codec = ControlMessageCodec::new();
+        let mut buf = BytesMut::from(&bytes[..]);
+        let msg = codec
+            .decode(&mut buf)
+            .unwrap_or_else(|e| panic!("{name}: {e}"))
+            .unwrap_or_else(|| panic!("{name}: incomplete"));
+        assert!(buf.is_empty(), "{name}: trailing bytes");
+
+        let mut encoded = BytesMut::new();
+        codec.encode(msg, &mut encoded).unwrap();
+        assert_eq!(&encoded[..], &bytes[..], "{name}: re-encoding differs");
+    }
+}
+
+/// The `xfail` control captures write the draft-12 16-bit Message Length,
+/// which this codec reads as a varint. Each must fail as captured and
+/// round-trip once its length is rewritten as a varint, so a capture
+/// differing in anything else is caught, and one the codec starts reading
+/// has to lose its `xfail`.
+#[test]
+fn control_xfail_vectors_differ_only_in_length() {
+    for (name, bytes) in parse(CONTROL).1 {
+        assert!(!control_round_trips(&bytes), "{name}: passes, drop `xfail`");
+
+        let mut buf = BytesMut::from(&bytes[..]);
+        let msg_type = VarInt.decode(&mut buf).unwrap().unwrap();
+        assert!(buf.len() >= 2, "{name}: truncated length");
+        let length = buf.split_to(2);
+        let length = u16::from_be_bytes([length[0], length[1]]);
+        assert_eq!(usize::from(length), buf.len(), "{name}: length mismatch");
+        let mut rewritten = BytesMut::new();
+        VarInt.encode(msg_type, &mut rewritten).unwrap();
+        VarInt.encode(u64::from(length), &mut rewritten).unwrap();
+        rewritten.extend_from_slice(&buf);
+        assert!(
+            control_round_trips(&rewritten),
+            "{name}: differs in more than the length"
+        );
+    }
+}
+
+#[test]
+fn data_stream_vectors_round_trip() {
+    for (name, bytes) in vectors(DATA) {
+        let mut buf = BytesMut::from(&bytes[..]);
+        let stream_type = VarInt
+            .decode(&mut buf.clone())
+            .unwrap()
+            .unwrap_or_else(|| panic!("{name}: empty"));
+
+        let mut encoded = BytesMut::new();
+        if SubgroupHeader::is_subgroup_type(stream_type) {
+            let header = SubgroupHeader::decode(&mut buf).unwrap_or_else(|e| panic!("{name}: {e}"));
+            header.encode(&mut encoded).unwrap();
+            while !buf.is_empty() {
+                let object = SubgroupObject::decode(&mut buf, header.extensions_present)
+                    .unwrap_or_else(|e| panic!("{name}: {e}"));
+                object
+                    .encode(&mut encoded, header.extensions_present)
+                    .unwrap();
+            }
+        } else {
+            let header = FetchHeader::decode(&mut buf).unwrap_or_else(|e| panic!("{name}: {e}"));
+            header.encode(&mut encoded).unwrap();
+            while !buf.is_empty() {
+                let object =
+                    FetchObject::decode(&mut buf).unwrap_or_else(|e| panic!("{name}: {e}"));
+                object.encode(&mut encoded).unwrap();
+            }
+        }
+        assert_eq!(&encoded[..], &bytes[..], "{name}: re-encoding differs");
+    }
+}
+
+/// Split points of a `len` byte buffer: every byte on its own for `None`,
+/// otherwise pseudo-random chunks of 1 to 7 bytes seeded by `seed`.
+fn chunks(len: usize, seed: Option<u64>) -> Vec<usize> {
+    let Some(mut state) = seed else {
+        return vec![1; len];
+    };
+    let mut sizes = Vec::new();
+    let mut left = len;
+    while left > 0 {
+        // Numerical Recipes LCG, enough to vary the split points.
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        let size = ((state >> 33) % 7 + 1) as usize;
+        sizes.push(size.min(left));
+        left -= size.min(left);
+    }
+    sizes
+}
+
+/// Decode every control message of `bytes` as they arrive in `chunks`,
+/// returning each message re-encoded.
+fn decode_control(bytes: &[u8], chunks: &[usize]) -> Vec<BytesMut> {
+    let mut codec = ControlMessageCodec::new();
+    let mut buf = BytesMut::new();
+    let mut messages = Vec::new();
+    let mut rest = bytes;
+    for &size in chunks {
+        let (chunk, tail) = rest.split_at(size);
+        rest = tail;
+        buf.extend_from_slice(chunk);
+        while let Some(msg) = codec.decode(&mut buf).unwrap() {
+            let mut encoded = BytesMut::new();
+            codec.encode(msg, &mut encoded).unwrap();
+            messages.push(encoded);
+        }
+    }
+    assert!(buf.is_empty(), "trailing bytes");
+    messages
+}
+
+#[test]
+fn control_vectors_decode_from_fragments() {
+    let vectors = vectors(CONTROL);
+    for (name, bytes) in &vectors {
+        let whole = decode_control(bytes, &[bytes.len()]);
+        assert_eq!(whole.len(), 1, "{name}");
+        assert_eq!(
+            decode_control(bytes, &chunks(bytes.len(), None)),
+            whole,
+            "{name}: byte by byte"
+        );
+    }
+
+    // All messages back to back, as on a control stream.
+    let stream: Vec<u8> = vectors.iter().flat_map(|(_, b)| b.clone()).collect();
+    let whole = decode_control(&stream, &[stream.len()]);
+    assert_eq!(whole.len(), vectors.len());
+    for seed in 0..32 {
+        assert_eq!(
+            decode_control(&stream, &chunks(stream.len(), Some(seed))),
+            whole,
+            "seed {seed}"
+        );
+    }
+}
+
+/// A stream returning `data` in the given chunks, one per read.
+struct Fragmented {
+    data: Vec<u8>,
+    chunks: std::vec::IntoIter<usize>,
+}
+
+impl AsyncRead for Fragmented {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if let Some(size) = self.chunks.next() {
+            let size = size.min(buf.remaining());
+            let chunk: Vec<u8> = self.data.drain(..size).collect();
+            buf.put_slice(&chunk);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Read the stream `bytes` arriving in `chunks` as an incoming data stream.
+async fn read_data_stream(bytes: &[u8], chunks: Vec<usize>) -> Vec<String> {
+    let stream = Fragmented {
+        data: bytes.to_vec(),
+        chunks: chunks.into_iter(),
+    };
+    let mut items = Vec::new();
+    match IncomingStream::accept(stream, usize::MAX).await.unwrap() {
+        IncomingStream::Subgroup(mut reader) => {
+            items.push(format!("{:?}", reader.header().await.unwrap()));
+            while let Some(object) = reader.next().await.unwrap() {
+                items.push(format!("{object:?}"));
+            }
+        }
+        IncomingStream::Fetch(mut reader) => {
+            items.push(format!("{:?}", reader.header().await.unwrap()));
+            while let Some(object) = reader.next().await.unwrap() {
+                items.push(format!("{object:?}"));
+            }
+        }
+    }
+    items
+}
+
+#[test]
+fn data_stream_vectors_decode_from_fragments() {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        for (name, bytes) in vectors(DATA) {
+            let whole = read_data_stream(&bytes, vec![bytes.len()]).await;
+            assert_eq!(
+                read_data_stream(&bytes, chunks(bytes.len(), None)).await,
+                whole,
+                "{name}: byte by byte"
+            );
+            for seed in 0..32 {
+                assert_eq!(
+                    read_data_stream(&bytes, chunks(bytes.len(), Some(seed))).await,
+                    whole,
+                    "{name}: seed {seed}"
+                );
+            }
+        }
+    });
+}
@@ -0,0 +1,21 @@
+mod collection;
+mod context;
+mod field;
+mod namespace;
+mod varint;
+
+pub use collection::*;
+pub use context::*;
+pub use field::*;
+pub use namespace::*;
+pub use varint::*;
+
+use bytes::BytesMut;
+
+pub trait Encode {
+    fn encode(&self, buf: &mut BytesMut) -> Result<(), crate::error::Error>;
+}
+
+pub trait Decode: Sized {
+    fn decode(buf: &mut BytesMut) -> Result<Self, crate::error::Error>;
+}
@@ -0,0 +1,58 @@
+use bytes::BytesMut;
+
+/// Maximum number of setup/version/message parameters accepted in a single
+/// message.
+pub const MAX_PARAMETERS: usize = 256;
+
+/// Maximum number of supported versions accepted in CLIENT_SETUP.
+pub const MAX_VERSIONS: usize = 64;
+
+/// Maximum number of Track Namespace tuple fields.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-track-naming
+pub const MAX_NAMESPACE_FIELDS: usize = 32;
+
+/// Upper bound on the number of elements reserved up front. Collections
+/// larger than this grow incrementally as elements are actually decoded.
+const MAX_PREALLOCATED: usize = 16;
+
+/// Create a vector for `len` elements announced on the wire.
+///
+/// Returns a protocol violation when `len` exceeds `limit`. The initial
+/// capacity never exceeds the number of bytes left in `buf` (each element
+/// occupies at least one byte) nor a small fixed bound, so a tiny message
+/// cannot force a large allocation.
+pub fn bounded_vec<T>(
+    len: usize,
+    limit: usize,
+    buf: &BytesMut,
+    what: &str,
+) -> Result<Vec<T>, crate::error::Error> {
+    if len > limit {
+        return Err(crate::error::Error::ProtocolViolation {
+            reason: format!("too many {what}: {len} > {limit}"),
+        });
+    }
+    Ok(Vec::with_capacity(len.min(buf.len()).min(MAX_PREALLOCATED)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capacity_is_capped() {
+        let buf = BytesMut::from(&[0u8; 4][..]);
+        let v: Vec<u8> = bounded_vec(200, MAX_PARAMETERS, &buf, "parameters").unwrap();
+        assert!(v.capacity() < 200);
+    }
+
+    #[test]
+    fn len_over_limit_is_error() {
+        let buf = BytesMut::new();
+        match bounded_vec::<u8>(33, MAX_NAMESPACE_FIELDS, &buf, "namespace fields") {
+            Err(crate::error::Error::ProtocolViolation { .. }) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+}
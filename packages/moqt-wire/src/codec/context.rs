@@ -0,0 +1,114 @@
+use bytes::BytesMut;
+
+use crate::error::Error;
+
+/// Position reached while decoding a message body, used to tell where a
+/// malformed message went wrong.
+///
+/// Decoders consume their buffer as they go, so the offset of a failure is
+/// how much of the body was consumed when it happened. The field being read
+/// is named by the error itself.
+#[derive(Debug, Clone, Copy)]
+pub struct DecodeCtx {
+    message: &'static str,
+    len: usize,
+}
+
+impl DecodeCtx {
+    /// Start decoding the body of `message` held in `buf`.
+    pub fn new(message: &'static str, buf: &BytesMut) -> Self {
+        Self {
+            message,
+            len: buf.len(),
+        }
+    }
+
+    pub fn message(&self) -> &'static str {
+        self.message
+    }
+
+    /// Bytes of the body consumed so far.
+    pub fn offset(&self, buf: &BytesMut) -> usize {
+        self.len.saturating_sub(buf.len())
+    }
+
+    /// Add the message name and offset to an error raised while decoding
+    /// from `buf`, keeping its kind so truncation can still be told apart.
+    pub fn wrap(&self, buf: &BytesMut, error: Error) -> Error {
+        use std::io::{Error as IoError, ErrorKind};
+
+        let offset = self.offset(buf);
+        match error {
+            Error::Io(e) if e.kind() == ErrorKind::UnexpectedEof => IoError::new(
+                ErrorKind::UnexpectedEof,
+                format!(
+                    "{}: truncated at offset {offset} while reading {e}",
+                    self.message
+                ),
+            )
+            .into(),
+            Error::Io(e) => IoError::new(
+                e.kind(),
+                format!("{}: {e} at offset {offset}", self.message),
+            )
+            .into(),
+            Error::ProtocolViolation { reason } => Error::ProtocolViolation {
+                reason: format!("{}: {reason} at offset {offset}", self.message),
+            },
+            e => e,
+        }
+    }
+
+    /// Fail unless the whole body was consumed.
+    pub fn finish(&self, buf: &BytesMut) -> Result<(), Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        if buf.is_empty() {
+            return Ok(());
+        }
+        Err(IoError::new(
+            ErrorKind::InvalidData,
+            format!(
+                "{}: excess payload of {} bytes at offset {}",
+                self.message,
+                buf.len(),
+                self.offset(buf)
+            ),
+        )
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Subscribe;
+
+    #[test]
+    fn truncation_reports_message_field_and_offset() {
+        let mut buf = BytesMut::from(&[0x01, 0x00, 0x05, b'v', b'i'][..]);
+        let ctx = DecodeCtx::new("SUBSCRIBE", &buf);
+        let error = Subscribe::decode(&mut buf).unwrap_err();
+        match ctx.wrap(&buf, error) {
+            Error::Io(e) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+                assert_eq!(
+                    e.to_string(),
+                    "SUBSCRIBE: truncated at offset 3 while reading track name"
+                );
+            }
+            e => panic!("unexpected error: {e:?}"),
+        }
+    }
+
+    #[test]
+    fn excess_payload() {
+        let buf = BytesMut::from(&[0x00, 0x00][..]);
+        let ctx = DecodeCtx::new("GOAWAY", &BytesMut::from(&[0x00, 0x00, 0x00][..]));
+        assert!(ctx.finish(&BytesMut::new()).is_ok());
+        assert_eq!(
+            ctx.finish(&buf).unwrap_err().to_string(),
+            "I/O error: GOAWAY: excess payload of 2 bytes at offset 1"
+        );
+    }
+}
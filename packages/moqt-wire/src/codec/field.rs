@@ -0,0 +1,160 @@
+use bytes::{BufMut, BytesMut};
+
+use crate::codec::VarInt;
+
+/// Maximum length of a Reason Phrase.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-reason-phrase-structure
+pub const MAX_REASON_PHRASE_LENGTH: usize = 1024;
+
+/// Maximum length of a Full Track Name, which bounds its track name and
+/// each namespace field.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-track-naming
+pub const MAX_FULL_TRACK_NAME_LENGTH: usize = 4096;
+
+/// Maximum length of the New Session URI of a GOAWAY.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-goaway
+pub const MAX_URI_LENGTH: usize = 8192;
+
+/// Maximum length of a parameter value.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-key-value-pair-structure
+pub const MAX_PARAMETER_VALUE_LENGTH: usize = 0xFFFF;
+
+/// A byte string prefixed with its varint length.
+///
+/// Encoding a value longer than `max_len` fails with
+/// [`InvalidData`](std::io::ErrorKind::InvalidData); receiving one is a
+/// protocol violation. `what` names the field in errors.
+#[derive(Debug, Clone, Copy)]
+pub struct BytesField {
+    pub what: &'static str,
+    pub max_len: usize,
+}
+
+impl BytesField {
+    pub const fn new(what: &'static str, max_len: usize) -> Self {
+        Self { what, max_len }
+    }
+
+    pub fn encode(&self, value: &[u8], buf: &mut BytesMut) -> Result<(), crate::error::Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        if value.len() > self.max_len {
+            return Err(IoError::new(
+                ErrorKind::InvalidData,
+                format!("{} too long: {} > {}", self.what, value.len(), self.max_len),
+            )
+            .into());
+        }
+        VarInt.encode(value.len() as u64, buf)?;
+        buf.put_slice(value);
+        Ok(())
+    }
+
+    pub fn decode(&self, buf: &mut BytesMut) -> Result<BytesMut, crate::error::Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        let len = VarInt
+            .decode(buf)?
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, self.what))?;
+        if len > self.max_len as u64 {
+            return Err(crate::error::Error::ProtocolViolation {
+                reason: format!("{} too long: {} > {}", self.what, len, self.max_len),
+            });
+        }
+        let len = len as usize;
+        if buf.len() < len {
+            return Err(IoError::new(ErrorKind::UnexpectedEof, self.what).into());
+        }
+        Ok(buf.split_to(len))
+    }
+}
+
+/// A UTF-8 string prefixed with its varint length. Invalid UTF-8 fails to
+/// decode with [`InvalidData`](std::io::ErrorKind::InvalidData).
+#[derive(Debug, Clone, Copy)]
+pub struct StringField(pub BytesField);
+
+impl StringField {
+    pub const fn new(what: &'static str, max_len: usize) -> Self {
+        Self(BytesField::new(what, max_len))
+    }
+
+    pub fn encode(&self, value: &str, buf: &mut BytesMut) -> Result<(), crate::error::Error> {
+        self.0.encode(value.as_bytes(), buf)
+    }
+
+    pub fn decode(&self, buf: &mut BytesMut) -> Result<String, crate::error::Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        let bytes = self.0.decode(buf)?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|e| IoError::new(ErrorKind::InvalidData, e).into())
+    }
+}
+
+/// Track Name of a request.
+pub const TRACK_NAME: StringField = StringField::new("track name", MAX_FULL_TRACK_NAME_LENGTH);
+
+/// Field of a Track Namespace tuple.
+pub const NAMESPACE_FIELD: StringField =
+    StringField::new("namespace field", MAX_FULL_TRACK_NAME_LENGTH);
+
+/// Reason Phrase of an error or termination.
+pub const REASON_PHRASE: StringField = StringField::new("reason", MAX_REASON_PHRASE_LENGTH);
+
+/// New Session URI of a GOAWAY.
+pub const URI: StringField = StringField::new("uri", MAX_URI_LENGTH);
+
+/// Length-prefixed value of an odd type parameter. Even types carry a bare
+/// varint instead, so parameters go through [`crate::model::Parameter`].
+pub(crate) const PARAMETER_VALUE: BytesField =
+    BytesField::new("parameter value", MAX_PARAMETER_VALUE_LENGTH);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn string_roundtrip() {
+        let mut buf = BytesMut::new();
+        TRACK_NAME.encode("video", &mut buf).unwrap();
+        assert_eq!(&buf[..], b"\x05video");
+        assert_eq!(TRACK_NAME.decode(&mut buf).unwrap(), "video");
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn over_max_len() {
+        let field = StringField::new("reason", 4);
+        assert!(field.encode("too long", &mut BytesMut::new()).is_err());
+
+        let mut buf = BytesMut::from(&b"\x08too long"[..]);
+        match field.decode(&mut buf) {
+            Err(crate::error::Error::ProtocolViolation { .. }) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn truncated_and_invalid_utf8() {
+        let mut buf = BytesMut::from(&b"\x05vid"[..]);
+        match TRACK_NAME.decode(&mut buf) {
+            Err(crate::error::Error::Io(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof)
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let mut buf = BytesMut::from(&b"\x01\xff"[..]);
+        match TRACK_NAME.decode(&mut buf) {
+            Err(crate::error::Error::Io(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::InvalidData)
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use bytes::BytesMut;
+
+use crate::codec::{
+    MAX_FULL_TRACK_NAME_LENGTH, MAX_NAMESPACE_FIELDS, NAMESPACE_FIELD, VarInt, bounded_vec,
+};
+
+/// Whether two Track Namespaces are the same. Tuple fields are compared as
+/// bytes: no percent-decoding, case folding or other canonicalization is
+/// applied, so ("Example.com") and ("example.com") differ.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-track-naming
+pub fn namespace_eq(a: &[String], b: &[String]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.as_bytes() == b.as_bytes())
+}
+
+/// Whether `prefix` is a Track Namespace Prefix of `namespace`. Whole tuple
+/// fields are compared as by [`namespace_eq`], so ("example.com", "meet")
+/// is not a prefix of ("example.com", "meeting=123").
+pub fn is_namespace_prefix(prefix: &[String], namespace: &[String]) -> bool {
+    prefix.len() <= namespace.len() && namespace_eq(prefix, &namespace[..prefix.len()])
+}
+
+/// Check that a Track Namespace has between 1 and [`MAX_NAMESPACE_FIELDS`]
+/// fields. Empty fields are valid: a field is any sequence of bytes.
+pub fn validate_namespace(namespace: &[String]) -> Result<(), crate::error::Error> {
+    if namespace.is_empty() || namespace.len() > MAX_NAMESPACE_FIELDS {
+        return Err(crate::error::Error::ProtocolViolation {
+            reason: format!("track namespace of {} fields", namespace.len()),
+        });
+    }
+    Ok(())
+}
+
+/// Check a Full Track Name: a valid namespace, and at most
+/// [`MAX_FULL_TRACK_NAME_LENGTH`] bytes in the namespace fields and track
+/// name together.
+pub fn validate_full_track_name(
+    namespace: &[String],
+    track_name: &str,
+) -> Result<(), crate::error::Error> {
+    validate_namespace(namespace)?;
+    let len = namespace.iter().map(String::len).sum::<usize>() + track_name.len();
+    if len > MAX_FULL_TRACK_NAME_LENGTH {
+        return Err(crate::error::Error::ProtocolViolation {
+            reason: format!("full track name of {len} bytes"),
+        });
+    }
+    Ok(())
+}
+
+/// Encode a Track Namespace tuple.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-track-naming
+pub fn encode_namespace(
+    namespace: &[String],
+    buf: &mut BytesMut,
+) -> Result<(), crate::error::Error> {
+    encode_tuple(namespace, buf, "track namespace")
+}
+
+/// Decode a Track Namespace tuple. A tuple with no fields or more than
+/// [`MAX_NAMESPACE_FIELDS`] fields is a protocol violation.
+pub fn decode_namespace(buf: &mut BytesMut) -> Result<Vec<String>, crate::error::Error> {
+    decode_tuple(buf, "track namespace")
+}
+
+/// Encode the Track Namespace Prefix of SUBSCRIBE_ANNOUNCES or
+/// UNSUBSCRIBE_ANNOUNCES, bounded like a Track Namespace.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-subscribe_announces
+pub fn encode_namespace_prefix(
+    prefix: &[String],
+    buf: &mut BytesMut,
+) -> Result<(), crate::error::Error> {
+    encode_tuple(prefix, buf, "track namespace prefix")
+}
+
+/// Decode a Track Namespace Prefix tuple. A prefix with no fields or more
+/// than [`MAX_NAMESPACE_FIELDS`] fields is a protocol violation.
+pub fn decode_namespace_prefix(buf: &mut BytesMut) -> Result<Vec<String>, crate::error::Error> {
+    decode_tuple(buf, "track namespace prefix")
+}
+
+fn encode_tuple(
+    fields: &[String],
+    buf: &mut BytesMut,
+    what: &str,
+) -> Result<(), crate::error::Error> {
+    use std::io::{Error as IoError, ErrorKind};
+
+    if validate_namespace(fields).is_err() {
+        return Err(IoError::new(
+            ErrorKind::InvalidData,
+            format!("{what} of {} fields", fields.len()),
+        )
+        .into());
+    }
+
+    VarInt.encode(fields.len() as u64, buf)?;
+    for field in fields {
+        NAMESPACE_FIELD.encode(field, buf)?;
+    }
+
+    Ok(())
+}
+
+fn decode_tuple(buf: &mut BytesMut, what: &str) -> Result<Vec<String>, crate::error::Error> {
+    use std::io::{Error as IoError, ErrorKind};
+
+    let len = VarInt
+        .decode(buf)?
+        .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, format!("{what} len")))?
+        as usize;
+    if len == 0 {
+        return Err(crate::error::Error::ProtocolViolation {
+            reason: format!("empty {what}"),
+        });
+    }
+
+    let mut fields = bounded_vec(len, MAX_NAMESPACE_FIELDS, buf, "namespace fields")?;
+    for _ in 0..len {
+        fields.push(NAMESPACE_FIELD.decode(buf)?);
+    }
+
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_roundtrip() {
+        let namespace = vec!["example.com".to_string(), "meeting=123".to_string()];
+        let mut buf = BytesMut::new();
+        encode_namespace(&namespace, &mut buf).unwrap();
+        assert_eq!(decode_namespace(&mut buf).unwrap(), namespace);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn empty_namespace_is_violation() {
+        let mut buf = BytesMut::from(&[0x00][..]);
+        match decode_namespace(&mut buf) {
+            Err(crate::error::Error::ProtocolViolation { .. }) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert!(encode_namespace(&[], &mut BytesMut::new()).is_err());
+    }
+
+    fn ns(fields: &[&str]) -> Vec<String> {
+        fields.iter().map(|f| f.to_string()).collect()
+    }
+
+    #[test]
+    fn comparison_is_byte_exact() {
+        let namespace = ns(&["example.com", "meeting=123"]);
+        assert!(namespace_eq(
+            &namespace,
+            &ns(&["example.com", "meeting=123"])
+        ));
+        assert!(!namespace_eq(
+            &namespace,
+            &ns(&["Example.com", "meeting=123"])
+        ));
+        assert!(!namespace_eq(
+            &namespace,
+            &ns(&["example.com", "meeting%3D123"])
+        ));
+        assert!(!namespace_eq(&namespace, &ns(&["example.commeeting=123"])));
+
+        assert!(is_namespace_prefix(&ns(&["example.com"]), &namespace));
+        assert!(is_namespace_prefix(&namespace, &namespace));
+        assert!(!is_namespace_prefix(
+            &ns(&["example.com", "meet"]),
+            &namespace
+        ));
+        assert!(!is_namespace_prefix(
+            &ns(&["example.com", "meeting=123", "x"]),
+            &namespace
+        ));
+    }
+
+    #[test]
+    fn validation_follows_track_naming() {
+        assert!(validate_namespace(&[]).is_err());
+        assert!(validate_namespace(&ns(&[""])).is_ok());
+        assert!(validate_namespace(&vec![String::new(); MAX_NAMESPACE_FIELDS]).is_ok());
+        assert!(validate_namespace(&vec![String::new(); MAX_NAMESPACE_FIELDS + 1]).is_err());
+
+        let half = "a".repeat(MAX_FULL_TRACK_NAME_LENGTH / 2);
+        let namespace = vec![half.clone()];
+        assert!(validate_full_track_name(&namespace, &half).is_ok());
+        assert!(validate_full_track_name(&namespace, &format!("{half}a")).is_err());
+    }
+}
@@ -0,0 +1,15 @@
+/// Errors encoding or decoding the wire format.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Protocol violation: {reason}")]
+    ProtocolViolation { reason: String },
+
+    #[error("varint out of range")]
+    VarIntRange,
+
+    #[error("unknown message type")]
+    UnknownMessageType,
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
@@ -0,0 +1,8 @@
+//! Wire format of MoQT control messages and the types they carry, free of
+//! any async runtime. The session and transport layers live in
+//! `moqt-transport`.
+
+pub mod codec;
+pub mod error;
+pub mod message;
+pub mod model;
@@ -0,0 +1,115 @@
+use bytes::BytesMut;
+
+use crate::model::Parameter;
+
+/// ANNOUNCE
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-announce
+///
+/// ```text
+/// ANNOUNCE Message {
+///   Type (i) = 0x6,
+///   Length (16),
+///   Request ID (i),
+///   Track Namespace (tuple),
+///   Number of Parameters (i),
+///   Parameters (..) ...,
+/// }
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Announce {
+    pub request_id: u64,
+    pub track_namespace: Vec<String>,
+    pub parameters: Vec<Parameter>,
+}
+
+impl Announce {
+    pub fn encode(&self, buf: &mut BytesMut) -> Result<(), crate::error::Error> {
+        let mut vi = crate::codec::VarInt;
+
+        vi.encode(self.request_id, buf)?;
+        crate::codec::encode_namespace(&self.track_namespace, buf)?;
+
+        vi.encode(self.parameters.len() as u64, buf)?;
+        for p in &self.parameters {
+            p.encode(buf)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn decode(buf: &mut BytesMut) -> Result<Self, crate::error::Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        let mut vi = crate::codec::VarInt;
+
+        let request_id = vi
+            .decode(buf)?
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "request id"))?;
+
+        let track_namespace = crate::codec::decode_namespace(buf)?;
+
+        let params_len = vi
+            .decode(buf)?
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameters len"))?
+            as usize;
+
+        let mut parameters =
+            crate::codec::bounded_vec(params_len, crate::codec::MAX_PARAMETERS, buf, "parameters")?;
+        for _ in 0..params_len {
+            parameters.push(Parameter::decode(buf)?);
+        }
+
+        Ok(Announce {
+            request_id,
+            track_namespace,
+            parameters,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_roundtrip() {
+        let msg = Announce {
+            request_id: 1,
+            track_namespace: vec!["example.com".into(), "meeting=123".into()],
+            parameters: vec![Parameter {
+                parameter_type: 5,
+                value: vec![7, 8],
+            }],
+        };
+
+        let mut buf = BytesMut::new();
+        msg.encode(&mut buf).unwrap();
+
+        let mut decode_buf = buf.clone();
+        let decoded = Announce::decode(&mut decode_buf).unwrap();
+        assert!(decode_buf.is_empty());
+        assert_eq!(decoded, msg);
+    }
+
+    #[test]
+    fn decode_incomplete() {
+        let mut buf = BytesMut::new();
+        match Announce::decode(&mut buf) {
+            Err(crate::error::Error::Io(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn decode_rejects_empty_namespace() {
+        let mut buf = BytesMut::from(&[0x01, 0x00, 0x00][..]);
+        match Announce::decode(&mut buf) {
+            Err(crate::error::Error::ProtocolViolation { .. }) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+}
@@ -1,11 +1,11 @@
-use bytes::{BufMut, BytesMut};
-use tokio_util::codec::{Decoder, Encoder};
+use bytes::BytesMut;
 
 /// Representation of an ANNOUNCE_CANCEL message body.
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AnnounceCancel {
     /// Track namespace for which announcements are cancelled.
-    pub track_namespace: u64,
+    pub track_namespace: Vec<String>,
     /// Error code describing the reason for cancellation.
     pub error_code: u64,
     /// Human readable reason for the cancellation.
@@ -16,12 +16,10 @@ impl AnnounceCancel {
     pub fn encode(&self, buf: &mut BytesMut) -> Result<(), crate::error::Error> {
         let mut vi = crate::codec::VarInt;
 
-        vi.encode(self.track_namespace, buf)?;
+        crate::codec::encode_namespace(&self.track_namespace, buf)?;
         vi.encode(self.error_code, buf)?;
 
-        let reason_bytes = self.error_reason.as_bytes();
-        vi.encode(reason_bytes.len() as u64, buf)?;
-        buf.put_slice(reason_bytes);
+        crate::codec::REASON_PHRASE.encode(&self.error_reason, buf)?;
 
         Ok(())
     }
@@ -31,23 +29,12 @@ impl AnnounceCancel {
 
         let mut vi = crate::codec::VarInt;
 
-        let track_namespace = vi
-            .decode(buf)?
-            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "track namespace"))?;
+        let track_namespace = crate::codec::decode_namespace(buf)?;
         let error_code = vi
             .decode(buf)?
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "error code"))?;
 
-        let reason_len = vi
-            .decode(buf)?
-            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "reason length"))?
-            as usize;
-        if buf.len() < reason_len {
-            return Err(IoError::new(ErrorKind::UnexpectedEof, "reason").into());
-        }
-        let value = buf.split_to(reason_len);
-        let error_reason = String::from_utf8(value.to_vec())
-            .map_err(|e| IoError::new(ErrorKind::InvalidData, e))?;
+        let error_reason = crate::codec::REASON_PHRASE.decode(buf)?;
 
         Ok(AnnounceCancel {
             track_namespace,
@@ -64,7 +51,7 @@ mod tests {
     #[test]
     fn encode_decode_roundtrip_with_reason() {
         let msg = AnnounceCancel {
-            track_namespace: 42,
+            track_namespace: vec!["example.com".into()],
             error_code: 3,
             error_reason: "going away".into(),
         };
@@ -81,7 +68,7 @@ mod tests {
     #[test]
     fn encode_decode_roundtrip_empty_reason() {
         let msg = AnnounceCancel {
-            track_namespace: 1,
+            track_namespace: vec!["example.com".into()],
             error_code: 0x1,
             error_reason: String::new(),
         };
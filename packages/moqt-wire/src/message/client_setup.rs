@@ -1,11 +1,21 @@
 use bytes::BytesMut;
-use tokio_util::codec::{Decoder, Encoder};
 
 use crate::{
     codec::{Decode, Encode},
+    error::Error,
     model::Parameter,
 };
 
+/// PATH setup parameter.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-path
+pub const PATH: u64 = 0x01;
+
+/// MAX_REQUEST_ID setup parameter.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-max_request_id
+pub const MAX_REQUEST_ID: u64 = 0x02;
+
 /// CLIENT_SETUP
 ///
 /// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-client_setup-and-server_set
@@ -30,11 +40,96 @@ use crate::{
 /// }
 /// ```
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ClientSetup {
     pub supported_versions: Vec<u32>,
     pub setup_parameters: Vec<Parameter>,
 }
 
+impl ClientSetup {
+    /// CLIENT_SETUP offering `versions` in order of preference, without
+    /// parameters.
+    pub fn new(versions: impl Into<Vec<u32>>) -> Self {
+        Self {
+            supported_versions: versions.into(),
+            setup_parameters: Vec::new(),
+        }
+    }
+
+    /// Set the PATH of the `moqt` URI. Only sent on raw QUIC sessions;
+    /// over WebTransport the path is part of the HTTP request.
+    ///
+    /// Panics if `path` is longer than 65535 bytes.
+    pub fn with_path(mut self, path: impl Into<String>) -> Self {
+        self.setup_parameters.retain(|p| p.parameter_type != PATH);
+        self.setup_parameters
+            .push(Parameter::bytes(PATH, path.into()).expect("PATH longer than 65535 bytes"));
+        self
+    }
+
+    /// Set the initial Maximum Request ID the server may use.
+    pub fn with_max_request_id(mut self, max: u64) -> Self {
+        set_max_request_id(&mut self.setup_parameters, max);
+        self
+    }
+
+    /// The PATH parameter, if present.
+    pub fn path(&self) -> Result<Option<&str>, Error> {
+        self.setup_parameters
+            .iter()
+            .find(|p| p.parameter_type == PATH)
+            .map(Parameter::as_string)
+            .transpose()
+    }
+
+    /// The initial Maximum Request ID, 0 when omitted.
+    pub fn max_request_id(&self) -> Result<u64, Error> {
+        max_request_id(&self.setup_parameters)
+    }
+
+    /// Check the message can be sent: at least one version is offered,
+    /// none twice, and PATH is present exactly when the session runs on
+    /// raw QUIC rather than WebTransport.
+    pub fn validate(&self, webtransport: bool) -> Result<(), Error> {
+        let violation = |reason: &str| {
+            Err(Error::ProtocolViolation {
+                reason: reason.into(),
+            })
+        };
+        if self.supported_versions.is_empty() {
+            return violation("no supported versions");
+        }
+        let mut versions = self.supported_versions.clone();
+        versions.sort_unstable();
+        versions.dedup();
+        if versions.len() != self.supported_versions.len() {
+            return violation("duplicate supported version");
+        }
+        match (self.path()?, webtransport) {
+            (Some(_), true) => violation("PATH used over WebTransport"),
+            (None, false) => violation("PATH missing on a QUIC session"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Replace the MAX_REQUEST_ID parameter of `parameters`. Values beyond the
+/// varint range are clamped.
+pub(crate) fn set_max_request_id(parameters: &mut Vec<Parameter>, max: u64) {
+    parameters.retain(|p| p.parameter_type != MAX_REQUEST_ID);
+    parameters.push(
+        Parameter::varint(MAX_REQUEST_ID, max.min((1 << 62) - 1))
+            .expect("clamped to the varint range"),
+    );
+}
+
+pub(crate) fn max_request_id(parameters: &[Parameter]) -> Result<u64, Error> {
+    parameters
+        .iter()
+        .find(|p| p.parameter_type == MAX_REQUEST_ID)
+        .map_or(Ok(0), Parameter::as_varint)
+}
+
 impl Encode for ClientSetup {
     fn encode(&self, buf: &mut BytesMut) -> Result<(), crate::error::Error> {
         let mut vi = crate::codec::VarInt;
@@ -66,7 +161,8 @@ impl Decode for ClientSetup {
             .decode(buf)?
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "versions"))?
             as usize;
-        let mut versions = Vec::with_capacity(versions_len);
+        let mut versions =
+            crate::codec::bounded_vec(versions_len, crate::codec::MAX_VERSIONS, buf, "versions")?;
         for _ in 0..versions_len {
             let v = vi
                 .decode(buf)?
@@ -82,7 +178,8 @@ impl Decode for ClientSetup {
             .decode(buf)?
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameters"))?
             as usize;
-        let mut parameters = Vec::with_capacity(params_len);
+        let mut parameters =
+            crate::codec::bounded_vec(params_len, crate::codec::MAX_PARAMETERS, buf, "parameters")?;
         for _ in 0..params_len {
             parameters.push(Parameter::decode(buf)?);
         }
@@ -179,6 +276,26 @@ mod tests {
         assert_eq!(decoded, msg);
     }
 
+    #[test]
+    fn builder_sets_parameters() {
+        let setup = ClientSetup::new([0xff00000c])
+            .with_path("/live")
+            .with_max_request_id(100)
+            .with_max_request_id(200);
+        assert_eq!(setup.setup_parameters.len(), 2);
+        assert_eq!(setup.path().unwrap(), Some("/live"));
+        assert_eq!(setup.max_request_id().unwrap(), 200);
+        assert!(setup.validate(false).is_ok());
+        assert!(setup.validate(true).is_err());
+
+        let setup = ClientSetup::new([0xff00000c]);
+        assert_eq!(setup.max_request_id().unwrap(), 0);
+        assert!(setup.validate(true).is_ok());
+        assert!(setup.validate(false).is_err());
+        assert!(ClientSetup::new([]).validate(true).is_err());
+        assert!(ClientSetup::new([1, 1]).validate(true).is_err());
+    }
+
     #[test]
     fn decode_truncated_versions() {
         let mut buf = BytesMut::new();
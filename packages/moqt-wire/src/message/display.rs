@@ -0,0 +1,338 @@
+use std::fmt;
+
+use crate::{
+    message::{ControlMessage, ControlMessageType},
+    model::Location,
+};
+
+impl ControlMessageType {
+    /// Name of the message type as written in the specification.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ControlMessageType::ClientSetup => "CLIENT_SETUP",
+            ControlMessageType::ServerSetup => "SERVER_SETUP",
+            ControlMessageType::Goaway => "GOAWAY",
+            ControlMessageType::MaxRequestId => "MAX_REQUEST_ID",
+            ControlMessageType::RequestsBlocked => "REQUESTS_BLOCKED",
+            ControlMessageType::Subscribe => "SUBSCRIBE",
+            ControlMessageType::SubscribeOk => "SUBSCRIBE_OK",
+            ControlMessageType::SubscribeError => "SUBSCRIBE_ERROR",
+            ControlMessageType::SubscribeUpdate => "SUBSCRIBE_UPDATE",
+            ControlMessageType::Unsubscribe => "UNSUBSCRIBE",
+            ControlMessageType::SubscribeDone => "SUBSCRIBE_DONE",
+            ControlMessageType::Publish => "PUBLISH",
+            ControlMessageType::PublishOk => "PUBLISH_OK",
+            ControlMessageType::PublishError => "PUBLISH_ERROR",
+            ControlMessageType::Fetch => "FETCH",
+            ControlMessageType::FetchOk => "FETCH_OK",
+            ControlMessageType::FetchError => "FETCH_ERROR",
+            ControlMessageType::FetchCancel => "FETCH_CANCEL",
+            ControlMessageType::TrackStatusRequest => "TRACK_STATUS_REQUEST",
+            ControlMessageType::TrackStatus => "TRACK_STATUS",
+            ControlMessageType::Announce => "ANNOUNCE",
+            ControlMessageType::AnnounceOk => "ANNOUNCE_OK",
+            ControlMessageType::AnnounceError => "ANNOUNCE_ERROR",
+            ControlMessageType::Unannounce => "UNANNOUNCE",
+            ControlMessageType::AnnounceCancel => "ANNOUNCE_CANCEL",
+            ControlMessageType::SubscribeAnnounces => "SUBSCRIBE_ANNOUNCES",
+            ControlMessageType::SubscribeAnnouncesOk => "SUBSCRIBE_ANNOUNCES_OK",
+            ControlMessageType::SubscribeAnnouncesError => "SUBSCRIBE_ANNOUNCES_ERROR",
+            ControlMessageType::UnsubscribeAnnounces => "UNSUBSCRIBE_ANNOUNCES",
+        }
+    }
+}
+
+impl fmt::Display for ControlMessageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl fmt::Debug for ControlMessageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({:#x})", self.name(), *self as u64)
+    }
+}
+
+/// A namespace tuple joined with `/`.
+struct Namespace<'a>(&'a [String]);
+
+impl fmt::Display for Namespace<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.join("/"))
+    }
+}
+
+/// An optional location, `-` when absent.
+struct Maybe<'a>(Option<&'a Location>);
+
+impl fmt::Display for Maybe<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(loc) => loc.fmt(f),
+            None => f.write_str("-"),
+        }
+    }
+}
+
+/// One line naming the message type followed by its request ID and key
+/// fields, e.g. `SUBSCRIBE request_id=4 track=0/video filter=0x2`.
+/// Parameters are only counted and payloads only measured.
+impl fmt::Display for ControlMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlMessage::ClientSetup(m) => write!(
+                f,
+                "CLIENT_SETUP versions={:x?} params={}",
+                m.supported_versions,
+                m.setup_parameters.len()
+            ),
+            ControlMessage::ServerSetup(m) => write!(
+                f,
+                "SERVER_SETUP version={:#x} params={}",
+                m.selected_version,
+                m.setup_parameters.len()
+            ),
+            ControlMessage::Goaway(m) => match &m.new_session_uri {
+                Some(uri) => write!(f, "GOAWAY uri={}", uri),
+                None => f.write_str("GOAWAY"),
+            },
+            ControlMessage::MaxRequestId(m) => {
+                write!(f, "MAX_REQUEST_ID request_id={}", m.request_id)
+            }
+            ControlMessage::RequestsBlocked(m) => write!(
+                f,
+                "REQUESTS_BLOCKED maximum_request_id={}",
+                m.maximum_request_id
+            ),
+            ControlMessage::Subscribe(m) => {
+                write!(
+                    f,
+                    "SUBSCRIBE request_id={} track={}/{} priority={} group_order={} forward={} filter={:#x}",
+                    m.request_id,
+                    m.track_namespace,
+                    m.track_name,
+                    m.subscriber_priority,
+                    m.group_order,
+                    m.forward,
+                    m.filter().filter_type()
+                )?;
+                if let Some(start) = m.filter().start_location() {
+                    write!(f, " start={}", start)?;
+                }
+                if let Some(end_group) = m.filter().end_group() {
+                    write!(f, " end_group={}", end_group)?;
+                }
+                Ok(())
+            }
+            ControlMessage::SubscribeOk(m) => write!(
+                f,
+                "SUBSCRIBE_OK request_id={} alias={} expires={} group_order={} largest={}",
+                m.request_id,
+                m.track_alias,
+                m.expires,
+                m.group_order,
+                Maybe(m.largest_location.as_ref())
+            ),
+            ControlMessage::SubscribeError(m) => write!(
+                f,
+                "SUBSCRIBE_ERROR request_id={} code={:#x} reason={:?}",
+                m.request_id, m.error_code, m.error_reason
+            ),
+            ControlMessage::SubscribeUpdate(m) => write!(
+                f,
+                "SUBSCRIBE_UPDATE request_id={} start={} end_group={} priority={} forward={}",
+                m.request_id, m.start_location, m.end_group, m.subscriber_priority, m.forward
+            ),
+            ControlMessage::Unsubscribe(m) => {
+                write!(f, "UNSUBSCRIBE request_id={}", m.request_id)
+            }
+            ControlMessage::SubscribeDone(m) => write!(
+                f,
+                "SUBSCRIBE_DONE request_id={} status={:#x} streams={} reason={:?}",
+                m.request_id, m.status_code, m.stream_count, m.reason
+            ),
+            ControlMessage::Publish(m) => write!(
+                f,
+                "PUBLISH request_id={} track={}/{} alias={} group_order={} largest={} forward={}",
+                m.request_id,
+                m.track_namespace,
+                m.track_name,
+                m.track_alias,
+                m.group_order,
+                Maybe(m.largest.as_ref()),
+                m.forward
+            ),
+            ControlMessage::PublishOk(m) => write!(
+                f,
+                "PUBLISH_OK request_id={} forward={} priority={} group_order={} filter={:#x} start={}",
+                m.request_id,
+                m.forward,
+                m.subscriber_priority,
+                m.group_order,
+                m.filter_type,
+                Maybe(m.start.as_ref())
+            ),
+            ControlMessage::PublishError(m) => write!(
+                f,
+                "PUBLISH_ERROR request_id={} code={:#x} reason={:?}",
+                m.request_id, m.error_code, m.error_reason
+            ),
+            ControlMessage::Fetch(m) => {
+                write!(
+                    f,
+                    "FETCH request_id={} type={:#x} priority={} group_order={}",
+                    m.request_id, m.fetch_type, m.subscriber_priority, m.group_order
+                )?;
+                if let (Some(namespace), Some(name)) = (&m.track_namespace, &m.track_name) {
+                    write!(
+                        f,
+                        " track={}/{} start={} end={}",
+                        namespace,
+                        name,
+                        Maybe(m.start_location.as_ref()),
+                        Maybe(m.end_location.as_ref())
+                    )?;
+                }
+                if let Some(joining) = m.joining_request_id {
+                    write!(f, " joining_request_id={}", joining)?;
+                }
+                Ok(())
+            }
+            ControlMessage::FetchOk(m) => write!(
+                f,
+                "FETCH_OK request_id={} group_order={} end_of_track={} end={}",
+                m.request_id, m.group_order, m.end_of_track, m.end_location
+            ),
+            ControlMessage::FetchError(m) => write!(
+                f,
+                "FETCH_ERROR request_id={} code={:#x} reason={:?}",
+                m.request_id, m.error_code, m.error_reason
+            ),
+            ControlMessage::FetchCancel(m) => {
+                write!(f, "FETCH_CANCEL request_id={}", m.request_id)
+            }
+            ControlMessage::TrackStatusRequest(m) => write!(
+                f,
+                "TRACK_STATUS_REQUEST request_id={} track={}/{}",
+                m.request_id, m.track_namespace, m.track_name
+            ),
+            ControlMessage::TrackStatus(m) => write!(
+                f,
+                "TRACK_STATUS request_id={} status={:#x} largest={}",
+                m.request_id, m.status_code, m.largest_location
+            ),
+            ControlMessage::Announce(m) => write!(
+                f,
+                "ANNOUNCE request_id={} namespace={}",
+                m.request_id,
+                Namespace(&m.track_namespace)
+            ),
+            ControlMessage::AnnounceOk(m) => {
+                write!(f, "ANNOUNCE_OK request_id={}", m.request_id)
+            }
+            ControlMessage::AnnounceError(m) => write!(
+                f,
+                "ANNOUNCE_ERROR request_id={} code={:#x} reason={:?}",
+                m.request_id, m.error_code, m.error_reason
+            ),
+            ControlMessage::Unannounce(m) => {
+                write!(f, "UNANNOUNCE namespace={}", Namespace(&m.track_namespace))
+            }
+            ControlMessage::AnnounceCancel(m) => write!(
+                f,
+                "ANNOUNCE_CANCEL namespace={} code={:#x} reason={:?}",
+                Namespace(&m.track_namespace),
+                m.error_code,
+                m.error_reason
+            ),
+            ControlMessage::SubscribeAnnounces(m) => write!(
+                f,
+                "SUBSCRIBE_ANNOUNCES request_id={} prefix={}",
+                m.request_id,
+                Namespace(&m.track_namespace_prefix)
+            ),
+            ControlMessage::SubscribeAnnouncesOk(m) => {
+                write!(f, "SUBSCRIBE_ANNOUNCES_OK request_id={}", m.request_id)
+            }
+            ControlMessage::SubscribeAnnouncesError(m) => write!(
+                f,
+                "SUBSCRIBE_ANNOUNCES_ERROR request_id={} code={:#x} reason={:?}",
+                m.request_id, m.error_code, m.error_reason
+            ),
+            ControlMessage::UnsubscribeAnnounces(m) => write!(
+                f,
+                "UNSUBSCRIBE_ANNOUNCES prefix={}",
+                Namespace(&m.track_namespace_prefix)
+            ),
+            ControlMessage::Unknown {
+                message_type,
+                payload,
+            } => write!(f, "UNKNOWN({:#x}) len={}", message_type, payload.len()),
+        }
+    }
+}
+
+/// Same single line as [`Display`](fmt::Display).
+impl fmt::Debug for ControlMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{Announce, Subscribe, SubscribeError};
+    use crate::model::Filter;
+
+    #[test]
+    fn messages_print_on_one_line() {
+        let subscribe = ControlMessage::Subscribe(Subscribe::new(
+            4,
+            0,
+            "video",
+            Filter::absolute_start(Location::new(2, 0)),
+        ));
+        assert_eq!(
+            subscribe.to_string(),
+            "SUBSCRIBE request_id=4 track=0/video priority=128 group_order=0 forward=1 filter=0x3 start=2/0"
+        );
+        assert_eq!(format!("{:?}", subscribe), subscribe.to_string());
+
+        let announce = ControlMessage::Announce(Announce {
+            request_id: 2,
+            track_namespace: vec!["live".into(), "cam".into()],
+            parameters: Vec::new(),
+        });
+        assert_eq!(
+            announce.to_string(),
+            "ANNOUNCE request_id=2 namespace=live/cam"
+        );
+
+        let error = ControlMessage::SubscribeError(SubscribeError {
+            request_id: 4,
+            error_code: 0x4,
+            error_reason: "no such\ntrack".into(),
+        });
+        assert_eq!(
+            error.to_string(),
+            r#"SUBSCRIBE_ERROR request_id=4 code=0x4 reason="no such\ntrack""#
+        );
+
+        assert_eq!(
+            subscribe.message_type(),
+            Some(ControlMessageType::Subscribe)
+        );
+        assert_eq!(subscribe.request_id(), Some(4));
+        assert_eq!(announce.request_id(), Some(2));
+        assert!(ControlMessageType::Publish.is_request());
+        assert!(!ControlMessageType::PublishOk.is_request());
+        assert!(!ControlMessageType::SubscribeUpdate.is_request());
+        assert_eq!(ControlMessageType::SubscribeOk.to_string(), "SUBSCRIBE_OK");
+        assert_eq!(
+            format!("{:?}", ControlMessageType::SubscribeOk),
+            "SUBSCRIBE_OK(0x4)"
+        );
+    }
+}
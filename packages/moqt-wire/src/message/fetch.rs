@@ -1,9 +1,9 @@
 use bytes::{BufMut, BytesMut};
-use tokio_util::codec::{Decoder, Encoder};
 
 use crate::model::{Location, Parameter};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Fetch {
     pub request_id: u64,
     pub subscriber_priority: u8,
@@ -50,8 +50,7 @@ impl Fetch {
                     .ok_or_else(|| IoError::new(ErrorKind::InvalidData, "missing end location"))?;
 
                 vi.encode(ns, buf)?;
-                vi.encode(name.len() as u64, buf)?;
-                buf.put_slice(name.as_bytes());
+                crate::codec::TRACK_NAME.encode(name, buf)?;
                 start.encode(buf)?;
                 end.encode(buf)?;
             }
@@ -72,9 +71,7 @@ impl Fetch {
 
         vi.encode(self.parameters.len() as u64, buf)?;
         for p in &self.parameters {
-            vi.encode(p.parameter_type, buf)?;
-            vi.encode(p.value.len() as u64, buf)?;
-            buf.put_slice(&p.value);
+            p.encode(buf)?;
         }
 
         Ok(())
@@ -115,18 +112,7 @@ impl Fetch {
                     Some(vi.decode(buf)?.ok_or_else(|| {
                         IoError::new(ErrorKind::UnexpectedEof, "track namespace")
                     })?);
-                let name_len = vi
-                    .decode(buf)?
-                    .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "track name len"))?
-                    as usize;
-                if buf.len() < name_len {
-                    return Err(IoError::new(ErrorKind::UnexpectedEof, "track name").into());
-                }
-                let name_bytes = buf.split_to(name_len);
-                track_name = Some(
-                    String::from_utf8(name_bytes.to_vec())
-                        .map_err(|e| IoError::new(ErrorKind::InvalidData, e))?,
-                );
+                track_name = Some(crate::codec::TRACK_NAME.decode(buf)?);
                 start_location = Some(Location::decode(buf)?);
                 end_location = Some(Location::decode(buf)?);
             }
@@ -150,23 +136,10 @@ impl Fetch {
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameters len"))?
             as usize;
 
-        let mut parameters = Vec::with_capacity(params_len);
+        let mut parameters =
+            crate::codec::bounded_vec(params_len, crate::codec::MAX_PARAMETERS, buf, "parameters")?;
         for _ in 0..params_len {
-            let ty = vi
-                .decode(buf)?
-                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameter type"))?;
-            let len = vi
-                .decode(buf)?
-                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameter len"))?
-                as usize;
-            if buf.len() < len {
-                return Err(IoError::new(ErrorKind::UnexpectedEof, "parameter value").into());
-            }
-            let value = buf.split_to(len).to_vec();
-            parameters.push(Parameter {
-                parameter_type: ty,
-                value,
-            });
+            parameters.push(Parameter::decode(buf)?);
         }
 
         Ok(Fetch {
@@ -209,7 +182,7 @@ mod tests {
             joining_request_id: None,
             joining_start: None,
             parameters: vec![Parameter {
-                parameter_type: 4,
+                parameter_type: 5,
                 value: vec![7, 8],
             }],
         };
@@ -1,7 +1,7 @@
 use bytes::BytesMut;
-use tokio_util::codec::{Decoder, Encoder};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FetchCancel {
     pub request_id: u64,
 }
@@ -1,8 +1,8 @@
-use bytes::{BufMut, BytesMut};
-use tokio_util::codec::{Decoder, Encoder};
+use bytes::BytesMut;
 
 /// Representation of a FETCH_ERROR message body.
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FetchError {
     /// The request ID of the FETCH message this is replying to.
     pub request_id: u64,
@@ -20,9 +20,7 @@ impl FetchError {
         vi.encode(self.request_id, buf)?;
         vi.encode(self.error_code, buf)?;
 
-        let reason_bytes = self.error_reason.as_bytes();
-        vi.encode(reason_bytes.len() as u64, buf)?;
-        buf.put_slice(reason_bytes);
+        crate::codec::REASON_PHRASE.encode(&self.error_reason, buf)?;
 
         Ok(())
     }
@@ -40,16 +38,7 @@ impl FetchError {
             .decode(buf)?
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "error code"))?;
 
-        let reason_len = vi
-            .decode(buf)?
-            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "reason length"))?
-            as usize;
-        if buf.len() < reason_len {
-            return Err(IoError::new(ErrorKind::UnexpectedEof, "reason").into());
-        }
-        let value = buf.split_to(reason_len);
-        let error_reason = String::from_utf8(value.to_vec())
-            .map_err(|e| IoError::new(ErrorKind::InvalidData, e))?;
+        let error_reason = crate::codec::REASON_PHRASE.decode(buf)?;
 
         Ok(FetchError {
             request_id,
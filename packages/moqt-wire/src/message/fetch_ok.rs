@@ -1,9 +1,9 @@
 use bytes::{BufMut, BytesMut};
-use tokio_util::codec::{Decoder, Encoder};
 
 use crate::model::{Location, Parameter};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FetchOk {
     pub request_id: u64,
     pub group_order: u8,
@@ -24,9 +24,7 @@ impl FetchOk {
 
         vi.encode(self.parameters.len() as u64, buf)?;
         for p in &self.parameters {
-            vi.encode(p.parameter_type, buf)?;
-            vi.encode(p.value.len() as u64, buf)?;
-            buf.put_slice(&p.value);
+            p.encode(buf)?;
         }
 
         Ok(())
@@ -68,23 +66,10 @@ impl FetchOk {
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameters len"))?
             as usize;
 
-        let mut parameters = Vec::with_capacity(params_len);
+        let mut parameters =
+            crate::codec::bounded_vec(params_len, crate::codec::MAX_PARAMETERS, buf, "parameters")?;
         for _ in 0..params_len {
-            let ty = vi
-                .decode(buf)?
-                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameter type"))?;
-            let len = vi
-                .decode(buf)?
-                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameter len"))?
-                as usize;
-            if buf.len() < len {
-                return Err(IoError::new(ErrorKind::UnexpectedEof, "parameter value").into());
-            }
-            let value = buf.split_to(len).to_vec();
-            parameters.push(Parameter {
-                parameter_type: ty,
-                value,
-            });
+            parameters.push(Parameter::decode(buf)?);
         }
 
         Ok(FetchOk {
@@ -100,7 +85,6 @@ impl FetchOk {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tokio_util::codec::Encoder;
 
     #[test]
     fn encode_decode_roundtrip() {
@@ -113,7 +97,7 @@ mod tests {
                 object: 5,
             },
             parameters: vec![Parameter {
-                parameter_type: 2,
+                parameter_type: 3,
                 value: vec![7, 8],
             }],
         };
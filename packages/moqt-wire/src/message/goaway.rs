@@ -1,12 +1,7 @@
-use bytes::{BufMut, BytesMut};
-use tokio_util::codec::{Decoder, Encoder};
+use bytes::BytesMut;
 
 use crate::codec::{Decode, Encode};
 
-/// The maximum size in bytes of the optional URI contained in a GOAWAY
-/// message as defined by the specification.
-const MAX_URI_LENGTH: usize = 8_192;
-
 /// GOAWAY
 ///
 /// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-goaway
@@ -32,26 +27,16 @@ const MAX_URI_LENGTH: usize = 8_192;
 /// }
 /// ```
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Goaway {
     pub new_session_uri: Option<String>,
 }
 
 impl Encode for Goaway {
     fn encode(&self, buf: &mut BytesMut) -> Result<(), crate::error::Error> {
-        let mut vi = crate::codec::VarInt;
-
         // New Session URI
-        if let Some(uri) = &self.new_session_uri {
-            let bytes = uri.as_bytes();
-            if bytes.len() > MAX_URI_LENGTH {
-                use std::io::{Error as IoError, ErrorKind};
-                return Err(IoError::new(ErrorKind::InvalidData, "uri too long").into());
-            }
-            vi.encode(bytes.len() as u64, buf)?;
-            buf.put_slice(bytes);
-        } else {
-            vi.encode(0, buf)?;
-        }
+        let uri = self.new_session_uri.as_deref().unwrap_or_default();
+        crate::codec::URI.encode(uri, buf)?;
 
         Ok(())
     }
@@ -59,32 +44,9 @@ impl Encode for Goaway {
 
 impl Decode for Goaway {
     fn decode(buf: &mut BytesMut) -> Result<Self, crate::error::Error> {
-        use std::io::{Error as IoError, ErrorKind};
-
-        let mut vi = crate::codec::VarInt;
-
         // New Session URI
-        let len = vi
-            .decode(buf)?
-            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "uri length"))?
-            as usize;
-        if len > MAX_URI_LENGTH {
-            return Err(crate::error::Error::ProtocolViolation {
-                reason: "GOAWAY URI length exceeded maximum".into(),
-            });
-        }
-        if buf.len() < len {
-            return Err(IoError::new(ErrorKind::UnexpectedEof, "uri").into());
-        }
-        let value = buf.split_to(len);
-        let new_session_uri = if len == 0 {
-            None
-        } else {
-            Some(
-                String::from_utf8(value.to_vec())
-                    .map_err(|e| IoError::new(ErrorKind::InvalidData, e))?,
-            )
-        };
+        let uri = crate::codec::URI.decode(buf)?;
+        let new_session_uri = (!uri.is_empty()).then_some(uri);
 
         Ok(Goaway { new_session_uri })
     }
@@ -93,7 +55,7 @@ impl Decode for Goaway {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tokio_util::codec::Encoder;
+    use crate::codec::MAX_URI_LENGTH;
 
     #[test]
     fn encode_decode_roundtrip_with_uri() {
@@ -140,7 +102,7 @@ mod tests {
         let mut buf = BytesMut::new();
         let mut vi = crate::codec::VarInt;
         vi.encode((MAX_URI_LENGTH + 1) as u64, &mut buf).unwrap();
-        buf.extend(std::iter::repeat(b'a').take(MAX_URI_LENGTH + 1));
+        buf.extend(std::iter::repeat_n(b'a', MAX_URI_LENGTH + 1));
 
         match Goaway::decode(&mut buf) {
             Err(crate::error::Error::ProtocolViolation { .. }) => {}
@@ -1,5 +1,4 @@
 use bytes::BytesMut;
-use tokio_util::codec::{Decoder, Encoder};
 
 use crate::codec::{Decode, Encode};
 
@@ -21,6 +20,7 @@ use crate::codec::{Decode, Encode};
 /// }
 /// ```
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MaxRequestId {
     pub request_id: u64,
 }
@@ -1,9 +1,9 @@
 use bytes::{BufMut, BytesMut};
-use tokio_util::codec::{Decoder, Encoder};
 
 use crate::model::{Location, Parameter};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Publish {
     pub request_id: u64,
     pub track_namespace: u64,
@@ -25,8 +25,7 @@ impl Publish {
         vi.encode(self.request_id, buf)?;
         vi.encode(self.track_namespace, buf)?;
 
-        vi.encode(self.track_name.len() as u64, buf)?;
-        buf.put_slice(self.track_name.as_bytes());
+        crate::codec::TRACK_NAME.encode(&self.track_name, buf)?;
 
         vi.encode(self.track_alias, buf)?;
 
@@ -56,9 +55,7 @@ impl Publish {
 
         vi.encode(self.parameters.len() as u64, buf)?;
         for p in &self.parameters {
-            vi.encode(p.parameter_type, buf)?;
-            vi.encode(p.value.len() as u64, buf)?;
-            buf.put_slice(&p.value);
+            p.encode(buf)?;
         }
 
         Ok(())
@@ -77,17 +74,7 @@ impl Publish {
             .decode(buf)?
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "track namespace"))?;
 
-        let name_len = vi
-            .decode(buf)?
-            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "track name len"))?
-            as usize;
-
-        if buf.len() < name_len {
-            return Err(IoError::new(ErrorKind::UnexpectedEof, "track name").into());
-        }
-        let name_bytes = buf.split_to(name_len);
-        let track_name = String::from_utf8(name_bytes.to_vec())
-            .map_err(|e| IoError::new(ErrorKind::InvalidData, e))?;
+        let track_name = crate::codec::TRACK_NAME.decode(buf)?;
 
         let track_alias = vi
             .decode(buf)?
@@ -113,7 +100,7 @@ impl Publish {
             None
         };
 
-        if buf.len() < 1 {
+        if buf.is_empty() {
             return Err(IoError::new(ErrorKind::UnexpectedEof, "forward").into());
         }
         let forward = buf.split_to(1)[0];
@@ -125,23 +112,10 @@ impl Publish {
             .decode(buf)?
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameters len"))?
             as usize;
-        let mut parameters = Vec::with_capacity(params_len);
+        let mut parameters =
+            crate::codec::bounded_vec(params_len, crate::codec::MAX_PARAMETERS, buf, "parameters")?;
         for _ in 0..params_len {
-            let ty = vi
-                .decode(buf)?
-                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameter type"))?;
-            let len = vi
-                .decode(buf)?
-                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameter len"))?
-                as usize;
-            if buf.len() < len {
-                return Err(IoError::new(ErrorKind::UnexpectedEof, "parameter value").into());
-            }
-            let value = buf.split_to(len).to_vec();
-            parameters.push(Parameter {
-                parameter_type: ty,
-                value,
-            });
+            parameters.push(Parameter::decode(buf)?);
         }
 
         Ok(Publish {
@@ -177,7 +151,7 @@ mod tests {
             }),
             forward: 1,
             parameters: vec![Parameter {
-                parameter_type: 4,
+                parameter_type: 5,
                 value: vec![7, 8],
             }],
         };
@@ -1,9 +1,9 @@
 use bytes::{BufMut, BytesMut};
-use tokio_util::codec::{Decoder, Encoder};
 
 use crate::model::{Location, Parameter};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PublishOk {
     pub request_id: u64,
     pub forward: u8,
@@ -33,7 +33,7 @@ impl PublishOk {
         }
         buf.put_u8(self.group_order);
 
-        if !matches!(self.filter_type, 0x1 | 0x2 | 0x3 | 0x4) {
+        if !matches!(self.filter_type, 0x1..=0x4) {
             return Err(IoError::new(ErrorKind::InvalidData, "invalid filter type").into());
         }
         vi.encode(self.filter_type, buf)?;
@@ -56,9 +56,7 @@ impl PublishOk {
 
         vi.encode(self.parameters.len() as u64, buf)?;
         for p in &self.parameters {
-            vi.encode(p.parameter_type, buf)?;
-            vi.encode(p.value.len() as u64, buf)?;
-            buf.put_slice(&p.value);
+            p.encode(buf)?;
         }
 
         Ok(())
@@ -89,7 +87,7 @@ impl PublishOk {
         let filter_type = vi
             .decode(buf)?
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "filter type"))?;
-        if !matches!(filter_type, 0x1 | 0x2 | 0x3 | 0x4) {
+        if !matches!(filter_type, 0x1..=0x4) {
             return Err(IoError::new(ErrorKind::InvalidData, "invalid filter type").into());
         }
 
@@ -113,23 +111,10 @@ impl PublishOk {
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameters len"))?
             as usize;
 
-        let mut parameters = Vec::with_capacity(params_len);
+        let mut parameters =
+            crate::codec::bounded_vec(params_len, crate::codec::MAX_PARAMETERS, buf, "parameters")?;
         for _ in 0..params_len {
-            let ty = vi
-                .decode(buf)?
-                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameter type"))?;
-            let len = vi
-                .decode(buf)?
-                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameter len"))?
-                as usize;
-            if buf.len() < len {
-                return Err(IoError::new(ErrorKind::UnexpectedEof, "parameter value").into());
-            }
-            let value = buf.split_to(len).to_vec();
-            parameters.push(Parameter {
-                parameter_type: ty,
-                value,
-            });
+            parameters.push(Parameter::decode(buf)?);
         }
 
         Ok(PublishOk {
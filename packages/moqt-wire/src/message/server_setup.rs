@@ -1,8 +1,9 @@
 use bytes::BytesMut;
-use tokio_util::codec::{Decoder, Encoder};
 
 use crate::{
     codec::{Decode, Encode},
+    error::Error,
+    message::{ClientSetup, PATH, client_setup},
     model::Parameter,
 };
 
@@ -29,11 +30,53 @@ use crate::{
 /// }
 /// ```
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ServerSetup {
     pub selected_version: u32,
     pub setup_parameters: Vec<Parameter>,
 }
 
+impl ServerSetup {
+    /// SERVER_SETUP selecting `version`, without parameters.
+    pub fn accept(version: u32) -> Self {
+        Self {
+            selected_version: version,
+            setup_parameters: Vec::new(),
+        }
+    }
+
+    /// Set the initial Maximum Request ID the client may use.
+    pub fn with_max_request_id(mut self, max: u64) -> Self {
+        client_setup::set_max_request_id(&mut self.setup_parameters, max);
+        self
+    }
+
+    /// The initial Maximum Request ID, 0 when omitted.
+    pub fn max_request_id(&self) -> Result<u64, Error> {
+        client_setup::max_request_id(&self.setup_parameters)
+    }
+
+    /// Check this is a valid answer to `client`: the selected version was
+    /// offered, and PATH, which only the client may send, is absent.
+    pub fn validate(&self, client: &ClientSetup) -> Result<(), Error> {
+        if !client.supported_versions.contains(&self.selected_version) {
+            return Err(Error::ProtocolViolation {
+                reason: format!("version {:#x} was not offered", self.selected_version),
+            });
+        }
+        if self
+            .setup_parameters
+            .iter()
+            .any(|p| p.parameter_type == PATH)
+        {
+            return Err(Error::ProtocolViolation {
+                reason: "PATH in SERVER_SETUP".into(),
+            });
+        }
+        Ok(())
+    }
+}
+
 impl Encode for ServerSetup {
     fn encode(&self, buf: &mut BytesMut) -> Result<(), crate::error::Error> {
         let mut vi = crate::codec::VarInt;
@@ -71,7 +114,8 @@ impl Decode for ServerSetup {
             .decode(buf)?
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameters"))?
             as usize;
-        let mut parameters = Vec::with_capacity(params_len);
+        let mut parameters =
+            crate::codec::bounded_vec(params_len, crate::codec::MAX_PARAMETERS, buf, "parameters")?;
         for _ in 0..params_len {
             parameters.push(Parameter::decode(buf)?);
         }
@@ -86,7 +130,22 @@ impl Decode for ServerSetup {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use bytes::BufMut;
+
+    #[test]
+    fn accept_validates_against_client() {
+        let client = ClientSetup::new([0xff00000b, 0xff00000c]);
+        let server = ServerSetup::accept(0xff00000c).with_max_request_id(64);
+        assert_eq!(server.max_request_id().unwrap(), 64);
+        assert!(server.validate(&client).is_ok());
+        assert!(ServerSetup::accept(1).validate(&client).is_err());
+
+        let mut with_path = server.clone();
+        with_path.setup_parameters.push(Parameter {
+            parameter_type: PATH,
+            value: b"/".to_vec(),
+        });
+        assert!(with_path.validate(&client).is_err());
+    }
 
     #[test]
     fn encode_decode_roundtrip() {
@@ -159,32 +218,6 @@ mod tests {
         }
     }
 
-    #[test]
-    fn codec_roundtrip() {
-        use crate::{codec::ControlMessageCodec, message::ControlMessage};
-
-        let msg = ServerSetup {
-            selected_version: 1,
-            setup_parameters: vec![Parameter {
-                parameter_type: 0x02,
-                value: vec![5],
-            }],
-        };
-
-        let mut codec = ControlMessageCodec;
-        let mut buf = BytesMut::new();
-        codec
-            .encode(ControlMessage::ServerSetup(msg.clone()), &mut buf)
-            .unwrap();
-
-        let decoded = codec.decode(&mut buf).unwrap().unwrap();
-        match decoded {
-            ControlMessage::ServerSetup(d) => assert_eq!(d, msg),
-            _ => panic!("unexpected message type"),
-        }
-        assert!(buf.is_empty());
-    }
-
     #[test]
     fn decode_selected_version_overflow() {
         let mut buf = BytesMut::new();
@@ -1,9 +1,9 @@
 use bytes::{BufMut, BytesMut};
-use tokio_util::codec::{Decoder, Encoder};
 
-use crate::model::{Location, Parameter};
+use crate::model::{Filter, Location, Parameter};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Subscribe {
     pub request_id: u64,
     pub track_namespace: u64,
@@ -11,21 +11,48 @@ pub struct Subscribe {
     pub subscriber_priority: u8,
     pub group_order: u8,
     pub forward: u8,
-    pub filter_type: u64,
-    pub start_location: Option<Location>,
-    pub end_group: Option<u64>,
+    /// Kept consistent by [`Filter`], unlike the wire triple it encodes to.
+    filter: Filter,
     pub parameters: Vec<Parameter>,
 }
 
 impl Subscribe {
+    /// SUBSCRIBE of `track_name` in `track_namespace` forwarding the
+    /// objects `filter` selects, with subscriber priority 128, the
+    /// publisher's group order and no parameters.
+    pub fn new(
+        request_id: u64,
+        track_namespace: u64,
+        track_name: impl Into<String>,
+        filter: Filter,
+    ) -> Self {
+        Self {
+            request_id,
+            track_namespace,
+            track_name: track_name.into(),
+            subscriber_priority: 128,
+            group_order: 0,
+            forward: 1,
+            filter,
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn filter(&self) -> &Filter {
+        &self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+    }
+
     pub fn encode(&self, buf: &mut BytesMut) -> Result<(), crate::error::Error> {
         let mut vi = crate::codec::VarInt;
 
         vi.encode(self.request_id, buf)?;
         vi.encode(self.track_namespace, buf)?;
 
-        vi.encode(self.track_name.len() as u64, buf)?;
-        buf.put_slice(self.track_name.as_bytes());
+        crate::codec::TRACK_NAME.encode(&self.track_name, buf)?;
 
         buf.put_u8(self.subscriber_priority);
 
@@ -47,44 +74,18 @@ impl Subscribe {
         }
         buf.put_u8(self.forward);
 
-        if !matches!(self.filter_type, 0x1 | 0x2 | 0x3 | 0x4) {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "invalid filter type",
-            )
-            .into());
-        }
-        vi.encode(self.filter_type, buf)?;
-
-        if matches!(self.filter_type, 0x3 | 0x4) {
-            if let Some(loc) = &self.start_location {
-                loc.encode(buf)?;
-            } else {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    "missing start location",
-                )
-                .into());
-            }
+        let (filter_type, start_location, end_group) = self.filter.to_parts();
+        vi.encode(filter_type, buf)?;
+        if let Some(loc) = start_location {
+            loc.encode(buf)?;
         }
-
-        if self.filter_type == 0x4 {
-            if let Some(end) = self.end_group {
-                vi.encode(end, buf)?;
-            } else {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    "missing end group",
-                )
-                .into());
-            }
+        if let Some(end) = end_group {
+            vi.encode(end, buf)?;
         }
 
         vi.encode(self.parameters.len() as u64, buf)?;
         for p in &self.parameters {
-            vi.encode(p.parameter_type, buf)?;
-            vi.encode(p.value.len() as u64, buf)?;
-            buf.put_slice(&p.value);
+            p.encode(buf)?;
         }
 
         Ok(())
@@ -101,16 +102,7 @@ impl Subscribe {
         let track_namespace = vi
             .decode(buf)?
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "track namespace"))?;
-        let name_len = vi
-            .decode(buf)?
-            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "track name len"))?
-            as usize;
-        if buf.len() < name_len {
-            return Err(IoError::new(ErrorKind::UnexpectedEof, "track name").into());
-        }
-        let name_bytes = buf.split_to(name_len);
-        let track_name = String::from_utf8(name_bytes.to_vec())
-            .map_err(|e| IoError::new(ErrorKind::InvalidData, e))?;
+        let track_name = crate::codec::TRACK_NAME.decode(buf)?;
 
         if buf.len() < 3 {
             return Err(IoError::new(ErrorKind::UnexpectedEof, "flags").into());
@@ -128,7 +120,7 @@ impl Subscribe {
         let filter_type = vi
             .decode(buf)?
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "filter type"))?;
-        if !matches!(filter_type, 0x1 | 0x2 | 0x3 | 0x4) {
+        if !matches!(filter_type, 0x1..=0x4) {
             return Err(IoError::new(ErrorKind::InvalidData, "invalid filter type").into());
         }
 
@@ -147,27 +139,16 @@ impl Subscribe {
             None
         };
 
+        let filter = Filter::from_parts(filter_type, start_location, end_group)?;
+
         let params_len = vi
             .decode(buf)?
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameters len"))?
             as usize;
-        let mut parameters = Vec::with_capacity(params_len);
+        let mut parameters =
+            crate::codec::bounded_vec(params_len, crate::codec::MAX_PARAMETERS, buf, "parameters")?;
         for _ in 0..params_len {
-            let ty = vi
-                .decode(buf)?
-                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameter type"))?;
-            let len = vi
-                .decode(buf)?
-                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameter len"))?
-                as usize;
-            if buf.len() < len {
-                return Err(IoError::new(ErrorKind::UnexpectedEof, "parameter value").into());
-            }
-            let value = buf.split_to(len).to_vec();
-            parameters.push(Parameter {
-                parameter_type: ty,
-                value,
-            });
+            parameters.push(Parameter::decode(buf)?);
         }
 
         Ok(Subscribe {
@@ -177,9 +158,7 @@ impl Subscribe {
             subscriber_priority,
             group_order,
             forward,
-            filter_type,
-            start_location,
-            end_group,
+            filter,
             parameters,
         })
     }
@@ -198,12 +177,7 @@ mod tests {
             subscriber_priority: 3,
             group_order: 1,
             forward: 1,
-            filter_type: 0x4,
-            start_location: Some(Location {
-                group: 10,
-                object: 5,
-            }),
-            end_group: Some(20),
+            filter: Filter::absolute_range(Location::new(10, 5), 20).unwrap(),
             parameters: vec![Parameter {
                 parameter_type: 1,
                 value: vec![42],
@@ -228,9 +202,7 @@ mod tests {
             subscriber_priority: 0,
             group_order: 0,
             forward: 1,
-            filter_type: 0x2,
-            start_location: None,
-            end_group: None,
+            filter: Filter::LargestObject,
             parameters: Vec::new(),
         };
 
@@ -242,4 +214,48 @@ mod tests {
         assert!(decode_buf.is_empty());
         assert_eq!(decoded, msg);
     }
+
+    #[test]
+    fn parameters_are_key_value_pairs() {
+        let msg = Subscribe {
+            request_id: 0,
+            track_namespace: 0,
+            track_name: String::new(),
+            subscriber_priority: 0,
+            group_order: 0,
+            forward: 1,
+            filter: Filter::LargestObject,
+            parameters: vec![
+                Parameter::varint(0x02, 500).unwrap(),
+                Parameter::bytes(0x03, *b"ab").unwrap(),
+            ],
+        };
+
+        let mut buf = BytesMut::new();
+        msg.encode(&mut buf).unwrap();
+        // An even type is followed by its varint value, an odd type by a
+        // length and the bytes.
+        assert!(buf.ends_with(&[0x02, 0x02, 0x41, 0xf4, 0x03, 0x02, b'a', b'b']));
+
+        let decoded = Subscribe::decode(&mut buf).unwrap();
+        assert!(buf.is_empty());
+        assert_eq!(decoded, msg);
+    }
+
+    #[test]
+    fn range_ending_before_start_is_rejected() {
+        let msg = Subscribe::new(
+            0,
+            0,
+            "video",
+            Filter::absolute_range(Location::new(10, 0), 20).unwrap(),
+        );
+        let mut buf = BytesMut::new();
+        msg.encode(&mut buf).unwrap();
+        // The end group is the last field before the parameters.
+        let end_group = buf.len() - 2;
+        assert_eq!(buf[end_group], 20);
+        buf[end_group] = 5;
+        assert!(Subscribe::decode(&mut buf).is_err());
+    }
 }
@@ -1,9 +1,9 @@
-use bytes::{BufMut, BytesMut};
-use tokio_util::codec::{Decoder, Encoder};
+use bytes::BytesMut;
 
 use crate::model::Parameter;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SubscribeAnnounces {
     pub request_id: u64,
     pub track_namespace_prefix: Vec<String>,
@@ -12,27 +12,14 @@ pub struct SubscribeAnnounces {
 
 impl SubscribeAnnounces {
     pub fn encode(&self, buf: &mut BytesMut) -> Result<(), crate::error::Error> {
-        use std::io::{Error as IoError, ErrorKind};
-
         let mut vi = crate::codec::VarInt;
 
-        if self.track_namespace_prefix.is_empty() || self.track_namespace_prefix.len() > 32 {
-            return Err(IoError::new(ErrorKind::InvalidData, "invalid prefix length").into());
-        }
-
         vi.encode(self.request_id, buf)?;
-
-        vi.encode(self.track_namespace_prefix.len() as u64, buf)?;
-        for part in &self.track_namespace_prefix {
-            vi.encode(part.len() as u64, buf)?;
-            buf.put_slice(part.as_bytes());
-        }
+        crate::codec::encode_namespace_prefix(&self.track_namespace_prefix, buf)?;
 
         vi.encode(self.parameters.len() as u64, buf)?;
         for p in &self.parameters {
-            vi.encode(p.parameter_type, buf)?;
-            vi.encode(p.value.len() as u64, buf)?;
-            buf.put_slice(&p.value);
+            p.encode(buf)?;
         }
 
         Ok(())
@@ -47,52 +34,17 @@ impl SubscribeAnnounces {
             .decode(buf)?
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "request id"))?;
 
-        let prefix_len = vi
-            .decode(buf)?
-            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "prefix len"))?
-            as usize;
-
-        if prefix_len == 0 || prefix_len > 32 {
-            return Err(IoError::new(ErrorKind::InvalidData, "invalid prefix length").into());
-        }
-
-        let mut track_namespace_prefix = Vec::with_capacity(prefix_len);
-        for _ in 0..prefix_len {
-            let part_len = vi
-                .decode(buf)?
-                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "part len"))?
-                as usize;
-            if buf.len() < part_len {
-                return Err(IoError::new(ErrorKind::UnexpectedEof, "part").into());
-            }
-            let bytes = buf.split_to(part_len);
-            let part = String::from_utf8(bytes.to_vec())
-                .map_err(|e| IoError::new(ErrorKind::InvalidData, e))?;
-            track_namespace_prefix.push(part);
-        }
+        let track_namespace_prefix = crate::codec::decode_namespace_prefix(buf)?;
 
         let params_len = vi
             .decode(buf)?
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameters len"))?
             as usize;
 
-        let mut parameters = Vec::with_capacity(params_len);
+        let mut parameters =
+            crate::codec::bounded_vec(params_len, crate::codec::MAX_PARAMETERS, buf, "parameters")?;
         for _ in 0..params_len {
-            let ty = vi
-                .decode(buf)?
-                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameter type"))?;
-            let len = vi
-                .decode(buf)?
-                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameter len"))?
-                as usize;
-            if buf.len() < len {
-                return Err(IoError::new(ErrorKind::UnexpectedEof, "parameter value").into());
-            }
-            let value = buf.split_to(len).to_vec();
-            parameters.push(Parameter {
-                parameter_type: ty,
-                value,
-            });
+            parameters.push(Parameter::decode(buf)?);
         }
 
         Ok(SubscribeAnnounces {
@@ -1,7 +1,7 @@
-use bytes::{BufMut, BytesMut};
-use tokio_util::codec::{Decoder, Encoder};
+use bytes::BytesMut;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SubscribeDone {
     pub request_id: u64,
     pub status_code: u64,
@@ -11,20 +11,13 @@ pub struct SubscribeDone {
 
 impl SubscribeDone {
     pub fn encode(&self, buf: &mut BytesMut) -> Result<(), crate::error::Error> {
-        use std::io::{Error as IoError, ErrorKind};
-
         let mut vi = crate::codec::VarInt;
 
         vi.encode(self.request_id, buf)?;
         vi.encode(self.status_code, buf)?;
         vi.encode(self.stream_count, buf)?;
 
-        let bytes = self.reason.as_bytes();
-        if bytes.len() > 8192 {
-            return Err(IoError::new(ErrorKind::InvalidData, "reason too long").into());
-        }
-        vi.encode(bytes.len() as u64, buf)?;
-        buf.put_slice(bytes);
+        crate::codec::REASON_PHRASE.encode(&self.reason, buf)?;
 
         Ok(())
     }
@@ -43,21 +36,7 @@ impl SubscribeDone {
         let stream_count = vi
             .decode(buf)?
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "stream count"))?;
-        let reason_len = vi
-            .decode(buf)?
-            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "reason length"))?
-            as usize;
-
-        if reason_len > 8192 {
-            return Err(IoError::new(ErrorKind::InvalidData, "reason too long").into());
-        }
-        if buf.len() < reason_len {
-            return Err(IoError::new(ErrorKind::UnexpectedEof, "reason").into());
-        }
-
-        let value = buf.split_to(reason_len);
-        let reason = String::from_utf8(value.to_vec())
-            .map_err(|e| IoError::new(ErrorKind::InvalidData, e))?;
+        let reason = crate::codec::REASON_PHRASE.decode(buf)?;
 
         Ok(SubscribeDone {
             request_id,
@@ -1,9 +1,9 @@
-use bytes::{BufMut, BytesMut};
-use tokio_util::codec::{Decoder, Encoder};
+use bytes::BytesMut;
 
 use crate::model::{Location, Parameter};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TrackStatus {
     pub request_id: u64,
     pub status_code: u64,
@@ -17,7 +17,7 @@ impl TrackStatus {
 
         let mut vi = crate::codec::VarInt;
 
-        if !matches!(self.status_code, 0x00 | 0x01 | 0x02 | 0x03 | 0x04) {
+        if !matches!(self.status_code, 0x00..=0x04) {
             return Err(IoError::new(ErrorKind::InvalidData, "invalid status code").into());
         }
 
@@ -40,9 +40,7 @@ impl TrackStatus {
 
         vi.encode(self.parameters.len() as u64, buf)?;
         for p in &self.parameters {
-            vi.encode(p.parameter_type, buf)?;
-            vi.encode(p.value.len() as u64, buf)?;
-            buf.put_slice(&p.value);
+            p.encode(buf)?;
         }
 
         Ok(())
@@ -60,7 +58,7 @@ impl TrackStatus {
             .decode(buf)?
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "status code"))?;
 
-        if !matches!(status_code, 0x00 | 0x01 | 0x02 | 0x03 | 0x04) {
+        if !matches!(status_code, 0x00..=0x04) {
             return Err(IoError::new(ErrorKind::InvalidData, "invalid status code").into());
         }
 
@@ -71,23 +69,10 @@ impl TrackStatus {
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameters len"))?
             as usize;
 
-        let mut parameters = Vec::with_capacity(params_len);
+        let mut parameters =
+            crate::codec::bounded_vec(params_len, crate::codec::MAX_PARAMETERS, buf, "parameters")?;
         for _ in 0..params_len {
-            let ty = vi
-                .decode(buf)?
-                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameter type"))?;
-            let len = vi
-                .decode(buf)?
-                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameter len"))?
-                as usize;
-            if buf.len() < len {
-                return Err(IoError::new(ErrorKind::UnexpectedEof, "parameter value").into());
-            }
-            let value = buf.split_to(len).to_vec();
-            parameters.push(Parameter {
-                parameter_type: ty,
-                value,
-            });
+            parameters.push(Parameter::decode(buf)?);
         }
 
         if matches!(status_code, 0x01 | 0x02) {
@@ -114,6 +99,8 @@ impl TrackStatus {
 
 #[cfg(test)]
 mod tests {
+    use bytes::BufMut;
+
     use super::*;
 
     #[test]
@@ -1,27 +1,28 @@
-use bytes::{BufMut, BytesMut};
-use tokio_util::codec::{Decoder, Encoder};
+use bytes::BytesMut;
 
 use crate::model::Parameter;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub struct Announce {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TrackStatusRequest {
     pub request_id: u64,
     pub track_namespace: u64,
+    pub track_name: String,
     pub parameters: Vec<Parameter>,
 }
 
-impl Announce {
+impl TrackStatusRequest {
     pub fn encode(&self, buf: &mut BytesMut) -> Result<(), crate::error::Error> {
         let mut vi = crate::codec::VarInt;
 
         vi.encode(self.request_id, buf)?;
         vi.encode(self.track_namespace, buf)?;
 
+        crate::codec::TRACK_NAME.encode(&self.track_name, buf)?;
+
         vi.encode(self.parameters.len() as u64, buf)?;
         for p in &self.parameters {
-            vi.encode(p.parameter_type, buf)?;
-            vi.encode(p.value.len() as u64, buf)?;
-            buf.put_slice(&p.value);
+            p.encode(buf)?;
         }
 
         Ok(())
@@ -40,33 +41,23 @@ impl Announce {
             .decode(buf)?
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "track namespace"))?;
 
+        let track_name = crate::codec::TRACK_NAME.decode(buf)?;
+
         let params_len = vi
             .decode(buf)?
             .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameters len"))?
             as usize;
 
-        let mut parameters = Vec::with_capacity(params_len);
+        let mut parameters =
+            crate::codec::bounded_vec(params_len, crate::codec::MAX_PARAMETERS, buf, "parameters")?;
         for _ in 0..params_len {
-            let ty = vi
-                .decode(buf)?
-                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameter type"))?;
-            let len = vi
-                .decode(buf)?
-                .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameter len"))?
-                as usize;
-            if buf.len() < len {
-                return Err(IoError::new(ErrorKind::UnexpectedEof, "parameter value").into());
-            }
-            let value = buf.split_to(len).to_vec();
-            parameters.push(Parameter {
-                parameter_type: ty,
-                value,
-            });
+            parameters.push(Parameter::decode(buf)?);
         }
 
-        Ok(Announce {
+        Ok(TrackStatusRequest {
             request_id,
             track_namespace,
+            track_name,
             parameters,
         })
     }
@@ -78,11 +69,12 @@ mod tests {
 
     #[test]
     fn encode_decode_roundtrip() {
-        let msg = Announce {
+        let msg = TrackStatusRequest {
             request_id: 1,
             track_namespace: 2,
+            track_name: "video".into(),
             parameters: vec![Parameter {
-                parameter_type: 4,
+                parameter_type: 5,
                 value: vec![7, 8],
             }],
         };
@@ -91,7 +83,7 @@ mod tests {
         msg.encode(&mut buf).unwrap();
 
         let mut decode_buf = buf.clone();
-        let decoded = Announce::decode(&mut decode_buf).unwrap();
+        let decoded = TrackStatusRequest::decode(&mut decode_buf).unwrap();
         assert!(decode_buf.is_empty());
         assert_eq!(decoded, msg);
     }
@@ -99,7 +91,7 @@ mod tests {
     #[test]
     fn decode_incomplete() {
         let mut buf = BytesMut::new();
-        match Announce::decode(&mut buf) {
+        match TrackStatusRequest::decode(&mut buf) {
             Err(crate::error::Error::Io(e)) => {
                 assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
             }
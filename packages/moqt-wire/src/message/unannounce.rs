@@ -1,25 +1,29 @@
 use bytes::BytesMut;
-use tokio_util::codec::{Decoder, Encoder};
 
+/// UNANNOUNCE
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-unannounce
+///
+/// ```text
+/// UNANNOUNCE Message {
+///   Type (i) = 0x9,
+///   Length (16),
+///   Track Namespace (tuple),
+/// }
+/// ```
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Unannounce {
-    pub track_namespace: u64,
+    pub track_namespace: Vec<String>,
 }
 
 impl Unannounce {
     pub fn encode(&self, buf: &mut BytesMut) -> Result<(), crate::error::Error> {
-        let mut vi = crate::codec::VarInt;
-        vi.encode(self.track_namespace, buf)?;
-        Ok(())
+        crate::codec::encode_namespace(&self.track_namespace, buf)
     }
 
     pub fn decode(buf: &mut BytesMut) -> Result<Self, crate::error::Error> {
-        use std::io::{Error as IoError, ErrorKind};
-
-        let mut vi = crate::codec::VarInt;
-        let track_namespace = vi
-            .decode(buf)?
-            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "track namespace"))?;
+        let track_namespace = crate::codec::decode_namespace(buf)?;
 
         Ok(Unannounce { track_namespace })
     }
@@ -32,7 +36,7 @@ mod tests {
     #[test]
     fn encode_decode_roundtrip() {
         let msg = Unannounce {
-            track_namespace: 42,
+            track_namespace: vec!["example.com".into()],
         };
 
         let mut buf = BytesMut::new();
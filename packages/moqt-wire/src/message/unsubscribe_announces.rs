@@ -0,0 +1,89 @@
+use bytes::BytesMut;
+
+/// UNSUBSCRIBE_ANNOUNCES
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-unsubscribe_announces
+///
+/// ```text
+/// UNSUBSCRIBE_ANNOUNCES Message {
+///   Type (i) = 0x14,
+///   Length (16),
+///   Track Namespace Prefix (tuple)
+/// }
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UnsubscribeAnnounces {
+    pub track_namespace_prefix: Vec<String>,
+}
+
+impl UnsubscribeAnnounces {
+    pub fn encode(&self, buf: &mut BytesMut) -> Result<(), crate::error::Error> {
+        crate::codec::encode_namespace_prefix(&self.track_namespace_prefix, buf)
+    }
+
+    pub fn decode(buf: &mut BytesMut) -> Result<Self, crate::error::Error> {
+        let track_namespace_prefix = crate::codec::decode_namespace_prefix(buf)?;
+
+        Ok(UnsubscribeAnnounces {
+            track_namespace_prefix,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_roundtrip() {
+        let msg = UnsubscribeAnnounces {
+            track_namespace_prefix: vec!["example.com".into(), "meeting=123".into()],
+        };
+
+        let mut buf = BytesMut::new();
+        msg.encode(&mut buf).unwrap();
+
+        let mut decode_buf = buf.clone();
+        let decoded = UnsubscribeAnnounces::decode(&mut decode_buf).unwrap();
+        assert!(decode_buf.is_empty());
+        assert_eq!(decoded, msg);
+    }
+
+    #[test]
+    fn decode_incomplete() {
+        let mut buf = BytesMut::new();
+        match UnsubscribeAnnounces::decode(&mut buf) {
+            Err(crate::error::Error::Io(e)) => {
+                assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn decode_fails_on_invalid_prefix_len() {
+        let mut buf = BytesMut::new();
+        crate::codec::VarInt.encode(0, &mut buf).unwrap();
+
+        match UnsubscribeAnnounces::decode(&mut buf) {
+            Err(crate::error::Error::ProtocolViolation { reason }) => {
+                assert_eq!(reason, "empty track namespace prefix");
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let mut buf = BytesMut::new();
+        let fields = crate::codec::MAX_NAMESPACE_FIELDS as u64 + 1;
+        crate::codec::VarInt.encode(fields, &mut buf).unwrap();
+        for _ in 0..fields {
+            crate::codec::VarInt.encode(0, &mut buf).unwrap();
+        }
+        assert!(UnsubscribeAnnounces::decode(&mut buf).is_err());
+
+        let oversized = UnsubscribeAnnounces {
+            track_namespace_prefix: vec![String::new(); fields as usize],
+        };
+        assert!(oversized.encode(&mut BytesMut::new()).is_err());
+    }
+}
@@ -0,0 +1,488 @@
+use bytes::{BufMut, BytesMut};
+
+/// A setup or message parameter. The value holds the bytes received, so a
+/// decoded message is encoded again with its parameters unchanged and in
+/// their original order, including unknown ones.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Parameter {
+    pub parameter_type: u64,
+    pub value: Vec<u8>,
+}
+
+impl Parameter {
+    /// Parameter of even type holding the varint `value`.
+    pub fn varint(parameter_type: u64, value: u64) -> Result<Self, crate::error::Error> {
+        if !parameter_type.is_multiple_of(2) {
+            return Err(crate::error::Error::ProtocolViolation {
+                reason: format!("parameter {parameter_type:#x} is not a varint"),
+            });
+        }
+        let mut buf = BytesMut::new();
+        crate::codec::VarInt.encode(value, &mut buf)?;
+        Ok(Self {
+            parameter_type,
+            value: buf.to_vec(),
+        })
+    }
+
+    /// Parameter of odd type holding `value` as is.
+    pub fn bytes(
+        parameter_type: u64,
+        value: impl Into<Vec<u8>>,
+    ) -> Result<Self, crate::error::Error> {
+        let value = value.into();
+        if parameter_type.is_multiple_of(2) {
+            return Err(crate::error::Error::ProtocolViolation {
+                reason: format!("parameter {parameter_type:#x} is a varint"),
+            });
+        }
+        if value.len() > crate::codec::MAX_PARAMETER_VALUE_LENGTH {
+            return Err(crate::error::Error::ProtocolViolation {
+                reason: "parameter value length exceeded".into(),
+            });
+        }
+        Ok(Self {
+            parameter_type,
+            value,
+        })
+    }
+
+    /// Value of an even type parameter, which must be exactly one varint.
+    pub fn as_varint(&self) -> Result<u64, crate::error::Error> {
+        let invalid = || crate::error::Error::ProtocolViolation {
+            reason: format!("invalid varint parameter {:#x}", self.parameter_type),
+        };
+        if !self.parameter_type.is_multiple_of(2) {
+            return Err(invalid());
+        }
+        let mut buf = BytesMut::from(&self.value[..]);
+        match crate::codec::VarInt.decode(&mut buf)? {
+            Some(value) if buf.is_empty() => Ok(value),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Value of an odd type parameter.
+    pub fn as_bytes(&self) -> Result<&[u8], crate::error::Error> {
+        if self.parameter_type.is_multiple_of(2) {
+            return Err(crate::error::Error::ProtocolViolation {
+                reason: format!("parameter {:#x} is a varint", self.parameter_type),
+            });
+        }
+        Ok(&self.value)
+    }
+
+    /// Value of an odd type parameter holding UTF-8 text.
+    pub fn as_string(&self) -> Result<&str, crate::error::Error> {
+        std::str::from_utf8(self.as_bytes()?).map_err(|_| crate::error::Error::ProtocolViolation {
+            reason: format!("parameter {:#x} is not UTF-8", self.parameter_type),
+        })
+    }
+
+    pub fn encode(&self, buf: &mut BytesMut) -> Result<(), crate::error::Error> {
+        let mut vi = crate::codec::VarInt;
+
+        vi.encode(self.parameter_type, buf)?;
+
+        if self.parameter_type.is_multiple_of(2) {
+            // even types contain a varint value directly
+            let len = self.value.first().map(|first| 1 << (first >> 6));
+            if len != Some(self.value.len()) {
+                return Err(crate::error::Error::ProtocolViolation {
+                    reason: "invalid varint parameter value".into(),
+                });
+            }
+            buf.put_slice(&self.value);
+        } else {
+            crate::codec::PARAMETER_VALUE.encode(&self.value, buf)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn decode(buf: &mut BytesMut) -> Result<Self, crate::error::Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        let mut vi = crate::codec::VarInt;
+
+        let parameter_type = vi
+            .decode(buf)?
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "parameter type"))?;
+
+        let value = if parameter_type.is_multiple_of(2) {
+            // Kept as received, even if not minimally encoded, so the
+            // parameter is encoded again byte for byte.
+            let len = buf.first().map_or(1, |first| 1 << (first >> 6));
+            if buf.len() < len {
+                return Err(IoError::new(ErrorKind::UnexpectedEof, "parameter value").into());
+            }
+            buf.split_to(len).to_vec()
+        } else {
+            crate::codec::PARAMETER_VALUE.decode(buf)?.to_vec()
+        };
+
+        Ok(Parameter {
+            parameter_type,
+            value,
+        })
+    }
+}
+
+/// Location of an object within a track. Locations order by group, then
+/// by object within the group.
+#[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Location {
+    pub group: u64,
+    pub object: u64,
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.group, self.object)
+    }
+}
+
+impl Location {
+    pub fn new(group: u64, object: u64) -> Self {
+        Self { group, object }
+    }
+
+    /// Location of the next object in the same group.
+    pub fn next_object(&self) -> Self {
+        Self::new(self.group, self.object + 1)
+    }
+
+    /// Location of the first object of the next group.
+    pub fn next_group(&self) -> Self {
+        Self::new(self.group + 1, 0)
+    }
+
+    pub fn is_before(&self, other: &Location) -> bool {
+        self < other
+    }
+
+    /// Whether the location lies in `start..=end`.
+    pub fn is_within(&self, start: &Location, end: &Location) -> bool {
+        start <= self && self <= end
+    }
+
+    pub fn encode(&self, buf: &mut bytes::BytesMut) -> Result<(), crate::error::Error> {
+        let mut vi = crate::codec::VarInt;
+        vi.encode(self.group, buf)?;
+        vi.encode(self.object, buf)?;
+        Ok(())
+    }
+
+    pub fn decode(buf: &mut bytes::BytesMut) -> Result<Self, crate::error::Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        let mut vi = crate::codec::VarInt;
+        let group = vi
+            .decode(buf)?
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "location group"))?;
+        let object = vi
+            .decode(buf)?
+            .ok_or_else(|| IoError::new(ErrorKind::UnexpectedEof, "location object"))?;
+        Ok(Location { group, object })
+    }
+}
+
+/// Subscription filter.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-subscribe
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Filter {
+    NextGroupStart,
+    LargestObject,
+    AbsoluteStart(Location),
+    AbsoluteRange(AbsoluteRange),
+}
+
+/// Start location and end group of [`Filter::AbsoluteRange`], the end group
+/// never before the start group.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "(Location, u64)", into = "(Location, u64)")
+)]
+pub struct AbsoluteRange {
+    start: Location,
+    end_group: u64,
+}
+
+impl AbsoluteRange {
+    /// Range from `start` to the end of `end_group`, inclusive. Fails if the
+    /// range ends before the start group.
+    pub fn new(start: Location, end_group: u64) -> Result<Self, crate::error::Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        if end_group < start.group {
+            return Err(IoError::new(ErrorKind::InvalidInput, "end group before start").into());
+        }
+        Ok(Self { start, end_group })
+    }
+
+    pub fn start(&self) -> &Location {
+        &self.start
+    }
+
+    pub fn end_group(&self) -> u64 {
+        self.end_group
+    }
+}
+
+impl TryFrom<(Location, u64)> for AbsoluteRange {
+    type Error = crate::error::Error;
+
+    fn try_from((start, end_group): (Location, u64)) -> Result<Self, Self::Error> {
+        Self::new(start, end_group)
+    }
+}
+
+impl From<AbsoluteRange> for (Location, u64) {
+    fn from(range: AbsoluteRange) -> Self {
+        (range.start, range.end_group)
+    }
+}
+
+impl Filter {
+    /// Start at the object after the largest one the publisher has.
+    pub fn largest_object() -> Self {
+        Filter::LargestObject
+    }
+
+    /// Start at the beginning of the group after the largest one the
+    /// publisher has.
+    pub fn next_group() -> Self {
+        Filter::NextGroupStart
+    }
+
+    /// Start at `start` with no end.
+    pub fn absolute_start(start: Location) -> Self {
+        Filter::AbsoluteStart(start)
+    }
+
+    /// Start at `start` and end after `end_group`, inclusive. Fails if the
+    /// range ends before the start group.
+    pub fn absolute_range(start: Location, end_group: u64) -> Result<Self, crate::error::Error> {
+        AbsoluteRange::new(start, end_group).map(Filter::AbsoluteRange)
+    }
+
+    /// The `(filter_type, start_location, end_group)` triple carried by
+    /// SUBSCRIBE and PUBLISH_OK.
+    pub fn to_parts(&self) -> (u64, Option<Location>, Option<u64>) {
+        (self.filter_type(), self.start_location(), self.end_group())
+    }
+
+    /// Build a filter from the wire triple carried by SUBSCRIBE and
+    /// PUBLISH_OK. Fails on a triple inconsistent with its filter type or a
+    /// range ending before its start group.
+    pub fn from_parts(
+        filter_type: u64,
+        start: Option<Location>,
+        end_group: Option<u64>,
+    ) -> Result<Self, crate::error::Error> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        match (filter_type, start, end_group) {
+            (0x1, None, None) => Ok(Filter::NextGroupStart),
+            (0x2, None, None) => Ok(Filter::LargestObject),
+            (0x3, Some(start), None) => Ok(Filter::AbsoluteStart(start)),
+            (0x4, Some(start), Some(end)) => Filter::absolute_range(start, end),
+            _ => Err(IoError::new(ErrorKind::InvalidData, "invalid filter").into()),
+        }
+    }
+
+    pub fn filter_type(&self) -> u64 {
+        match self {
+            Filter::NextGroupStart => 0x1,
+            Filter::LargestObject => 0x2,
+            Filter::AbsoluteStart(_) => 0x3,
+            Filter::AbsoluteRange(_) => 0x4,
+        }
+    }
+
+    pub fn start_location(&self) -> Option<Location> {
+        match self {
+            Filter::AbsoluteStart(start) => Some(start.clone()),
+            Filter::AbsoluteRange(range) => Some(range.start.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn end_group(&self) -> Option<u64> {
+        match self {
+            Filter::AbsoluteRange(range) => Some(range.end_group),
+            _ => None,
+        }
+    }
+
+    /// Resolve the filter's start location given the largest location known
+    /// to the publisher when the subscription was processed.
+    pub fn start(&self, largest: Option<&Location>) -> Location {
+        match (self, largest) {
+            (Filter::LargestObject, Some(l)) => l.next_object(),
+            (Filter::NextGroupStart, Some(l)) => l.next_group(),
+            (Filter::AbsoluteStart(s), _) => s.clone(),
+            (Filter::AbsoluteRange(range), _) => range.start.clone(),
+            (_, None) => Location::new(0, 0),
+        }
+    }
+
+    /// Whether an object at `loc` passes the filter.
+    pub fn matches(&self, loc: &Location, largest: Option<&Location>) -> bool {
+        if loc.is_before(&self.start(largest)) {
+            return false;
+        }
+        match self.end_group() {
+            Some(end) => loc.group <= end,
+            None => true,
+        }
+    }
+}
+
+/// How the objects of a track are sent. A track keeps a single forwarding
+/// preference; fetches are not subject to it.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-objects
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ForwardingPreference {
+    Subgroup,
+    Datagram,
+}
+
+/// Object status.
+///
+/// https://datatracker.ietf.org/doc/html/draft-ietf-moq-transport-12#name-object-status
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ObjectStatus {
+    #[default]
+    Normal,
+    DoesNotExist,
+    EndOfGroup,
+    EndOfTrack,
+}
+
+impl ObjectStatus {
+    pub fn code(&self) -> u64 {
+        match self {
+            ObjectStatus::Normal => 0x0,
+            ObjectStatus::DoesNotExist => 0x1,
+            ObjectStatus::EndOfGroup => 0x3,
+            ObjectStatus::EndOfTrack => 0x4,
+        }
+    }
+}
+
+impl TryFrom<u64> for ObjectStatus {
+    type Error = crate::error::Error;
+
+    fn try_from(code: u64) -> Result<Self, Self::Error> {
+        match code {
+            0x0 => Ok(ObjectStatus::Normal),
+            0x1 => Ok(ObjectStatus::DoesNotExist),
+            0x3 => Ok(ObjectStatus::EndOfGroup),
+            0x4 => Ok(ObjectStatus::EndOfTrack),
+            _ => Err(crate::error::Error::ProtocolViolation {
+                reason: format!("unknown object status {code:#x}"),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parameter_typed_values() {
+        let varint = Parameter::varint(0x2, 300).unwrap();
+        assert_eq!(varint.value, vec![0x41, 0x2c]);
+        assert_eq!(varint.as_varint().unwrap(), 300);
+        assert!(varint.as_bytes().is_err());
+        assert!(Parameter::varint(0x1, 300).is_err());
+
+        let path = Parameter::bytes(0x1, "/live").unwrap();
+        assert_eq!(path.as_string().unwrap(), "/live");
+        assert_eq!(path.as_bytes().unwrap(), b"/live");
+        assert!(path.as_varint().is_err());
+        assert!(Parameter::bytes(0x2, "/live").is_err());
+        assert!(
+            Parameter::bytes(0x1, vec![0xff])
+                .unwrap()
+                .as_string()
+                .is_err()
+        );
+
+        // A varint value must be a single, complete varint.
+        for value in [vec![], vec![0x41], vec![0x05, 0x05]] {
+            let p = Parameter {
+                parameter_type: 0x2,
+                value,
+            };
+            assert!(p.as_varint().is_err());
+        }
+    }
+
+    #[test]
+    fn parameters_reencode_as_received() {
+        // MAX_REQUEST_ID 5 as a two byte varint, then an unknown odd
+        // parameter.
+        let wire = [0x02, 0x40, 0x05, 0x3f, 0x02, 0xab, 0xcd];
+        let mut buf = BytesMut::from(&wire[..]);
+        let varint = Parameter::decode(&mut buf).unwrap();
+        let unknown = Parameter::decode(&mut buf).unwrap();
+        assert!(buf.is_empty());
+        assert_eq!(varint.as_varint().unwrap(), 5);
+        assert_eq!(unknown.value, [0xab, 0xcd]);
+
+        let mut reencoded = BytesMut::new();
+        varint.encode(&mut reencoded).unwrap();
+        unknown.encode(&mut reencoded).unwrap();
+        assert_eq!(&reencoded[..], &wire[..]);
+
+        let mut truncated = BytesMut::from(&[0x02, 0x40][..]);
+        assert!(Parameter::decode(&mut truncated).is_err());
+    }
+
+    #[test]
+    fn locations_order_by_group_then_object() {
+        let loc = Location::new(2, 5);
+        assert!(Location::new(1, 9).is_before(&loc));
+        assert!(loc.is_before(&loc.next_object()));
+        assert!(loc.next_object().is_before(&loc.next_group()));
+        assert_eq!(loc.next_group(), Location::new(3, 0));
+
+        assert!(loc.is_within(&Location::new(2, 0), &Location::new(2, 5)));
+        assert!(!loc.is_within(&Location::new(2, 6), &Location::new(3, 0)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        use crate::message::{ControlMessage, Subscribe};
+
+        let loc = Location::new(4, 2);
+        assert_eq!(
+            serde_json::to_string(&loc).unwrap(),
+            r#"{"group":4,"object":2}"#
+        );
+
+        let mut subscribe = Subscribe::new(1, 0, "video", Filter::absolute_start(loc));
+        subscribe.parameters = vec![Parameter {
+            parameter_type: 0x2,
+            value: vec![0x10],
+        }];
+        let json = serde_json::to_string(&ControlMessage::Subscribe(subscribe.clone())).unwrap();
+        let ControlMessage::Subscribe(decoded) = serde_json::from_str(&json).unwrap() else {
+            panic!("expected SUBSCRIBE");
+        };
+        assert_eq!(decoded, subscribe);
+    }
+}